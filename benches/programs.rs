@@ -0,0 +1,63 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use tape_device::assembler::assemble_source;
+use tape_device::device::RunOptions;
+use tape_device::run_tape;
+
+///Nested loops of arithmetic, compares and jumps
+const ARITHMETIC: &str = "Arithmetic
+1
+.ops
+cpy d2 0
+outer:
+cpy d0 0
+inner:
+add d3 d0
+cpy d3 acc
+inc d0
+cmp d0 255
+jne inner
+inc d2
+cmp d2 32
+jne outer
+halt
+";
+
+///Writes and reads every byte of a 16K block
+const MEMORY: &str = "Memory
+1
+.ops
+cpy d1 0
+outer:
+cpy d0 0
+inner:
+cpy a0 d1 d0
+cpy acc d0
+memw a0
+memr a0
+inc d0
+cmp d0 255
+jne inner
+inc d1
+cmp d1 64
+jne outer
+halt
+";
+
+fn bench_program(c: &mut Criterion, name: &str, source: &str) {
+    let bytes = assemble_source(source)
+        .unwrap_or_else(|_| panic!("{} failed to assemble", name))
+        .bytes;
+    c.bench_function(name, |b| {
+        b.iter(|| run_tape(bytes.clone(), RunOptions::default()).unwrap())
+    });
+}
+
+fn programs(c: &mut Criterion) {
+    bench_program(c, "arithmetic", ARITHMETIC);
+    bench_program(c, "memory", MEMORY);
+    bench_program(c, "calls", include_str!("../examples/multiply.basm"));
+    bench_program(c, "printing", include_str!("../examples/fizzbuzz.basm"));
+}
+
+criterion_group!(benches, programs);
+criterion_main!(benches);
@@ -0,0 +1,309 @@
+use crate::assembler::diagnostic::{Diagnostic, RECURSION, STACK_OVERFLOW, STACK_UNBOUNDED};
+use crate::assembler::program_model::ProgramModel;
+use crate::constants::code::{
+    CALL_ADDR, CALL_AREG, EXIT_REG, EXIT_VAL, HALT, INTH_VAL_ADDR, JMP_ADDR, JMP_AREG, LOCAL_VAL,
+    POP_REG, POP_VAL, PUSH_REG, PUSH_VAL, RET, RETI,
+};
+use crate::constants::hardware::{DEFAULT_STACK_SIZE, RAM_SIZE};
+use crate::constants::is_jump_op;
+use crate::language::parser::params::Param;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+///Name used for the function starting at the first op
+pub const ENTRY: &str = "(entry)";
+///Bytes pushed by CALL (FP and return address)
+const CALL_FRAME: usize = 4;
+
+///Worst case stack usage found by [`stack_usage`]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct StackReport {
+    ///Functions (labels used by CALL) and the functions they call
+    pub calls: BTreeMap<String, BTreeSet<String>>,
+    ///Bytes of stack used by each function, including the functions it calls, None if it can't be bounded
+    pub usage: BTreeMap<String, Option<usize>>,
+    ///Functions that call themselves directly or through other functions
+    pub recursive: BTreeSet<String>,
+}
+
+///Stack used by a function without the functions it calls
+struct LocalUsage {
+    ///None if the stack grows in a loop
+    max: Option<usize>,
+    ///Depth when each function is called
+    calls: Vec<(usize, String)>,
+}
+
+///Build the call graph and estimate the worst case stack usage of each function
+///
+///Only calls to labels are followed, `CALL addr_reg` counts the call frame but not the function
+pub fn stack_usage(program_model: &ProgramModel) -> StackReport {
+    let targets = program_model.label_targets();
+    let mut starts: BTreeMap<String, usize> = BTreeMap::new();
+    if !program_model.ops.is_empty() {
+        starts.insert(String::from(ENTRY), 0);
+    }
+    for op in &program_model.ops {
+        //interrupt handlers are called by the device so are treated as functions
+        if let (CALL_ADDR, [Param::Label(key)]) | (INTH_VAL_ADDR, [_, Param::Label(key)]) =
+            (op.opcode, op.params.as_slice())
+        {
+            if let Some(idx) = targets.get(key) {
+                starts.insert(key.clone(), *idx);
+            }
+        }
+    }
+
+    let locals: BTreeMap<String, LocalUsage> = starts
+        .iter()
+        .map(|(name, start)| (name.clone(), local_usage(program_model, &targets, *start)))
+        .collect();
+    let mut report = StackReport {
+        calls: locals
+            .iter()
+            .map(|(name, local)| {
+                let calls = local.calls.iter().map(|(_, callee)| callee.clone());
+                (name.clone(), calls.collect())
+            })
+            .collect(),
+        ..StackReport::default()
+    };
+    for name in locals.keys() {
+        total_usage(name, &locals, &mut report, &mut vec![]);
+    }
+    report
+}
+
+///Worst case usage of `name` and the functions it calls, marks functions in call cycles as recursive
+fn total_usage(
+    name: &str,
+    locals: &BTreeMap<String, LocalUsage>,
+    report: &mut StackReport,
+    path: &mut Vec<String>,
+) -> Option<usize> {
+    if let Some(usage) = report.usage.get(name) {
+        return *usage;
+    }
+    if let Some(idx) = path.iter().position(|func| func == name) {
+        report.recursive.extend(path[idx..].iter().cloned());
+        return None;
+    }
+    let local = &locals[name];
+    path.push(name.to_string());
+    let mut usage = local.max;
+    for (depth, callee) in &local.calls {
+        let callee_usage = total_usage(callee, locals, report, path);
+        usage = usage
+            .zip(callee_usage)
+            .map(|(usage, callee)| usage.max(depth + CALL_FRAME + callee));
+    }
+    path.pop();
+    //functions in a cycle are only known once the whole cycle has been visited
+    if !path.iter().any(|func| report.recursive.contains(func)) || usage.is_some() {
+        report.usage.insert(name.to_string(), usage);
+    }
+    usage
+}
+
+fn local_usage(
+    program_model: &ProgramModel,
+    targets: &HashMap<String, usize>,
+    start: usize,
+) -> LocalUsage {
+    let ops = &program_model.ops;
+    let label_target = |idx: usize| {
+        ops[idx].params.iter().find_map(|param| match param {
+            Param::Label(key) => targets.get(key).copied(),
+            _ => None,
+        })
+    };
+    let mut usage = LocalUsage {
+        max: Some(0),
+        calls: vec![],
+    };
+    let mut depths: HashMap<usize, usize> = HashMap::new();
+    let mut pending = vec![(start, 0)];
+    while let Some((idx, depth)) = pending.pop() {
+        if idx >= ops.len() || depths.get(&idx).is_some_and(|prev| *prev >= depth) {
+            continue;
+        }
+        if depth > RAM_SIZE {
+            usage.max = None;
+            break;
+        }
+        depths.insert(idx, depth);
+        let op = &ops[idx];
+        let pushed = match (op.opcode, op.params.as_slice()) {
+            (PUSH_VAL, _) => 1,
+            (PUSH_REG, [Param::AddrReg(_)]) => 2,
+            (PUSH_REG, _) => 1,
+            (LOCAL_VAL, [Param::Number(count)]) => *count as usize,
+            (CALL_AREG, _) => CALL_FRAME,
+            _ => 0,
+        };
+        usage.max = usage.max.map(|max| max.max(depth + pushed));
+        let next_depth = match (op.opcode, op.params.as_slice()) {
+            (POP_REG, [Param::AddrReg(_)]) => depth.saturating_sub(2),
+            (POP_REG, _) => depth.saturating_sub(1),
+            (POP_VAL, [Param::Number(count)]) => depth.saturating_sub(*count as usize),
+            (CALL_AREG, _) => depth,
+            _ => depth + pushed,
+        };
+        match op.opcode {
+            HALT | EXIT_REG | EXIT_VAL | RET | RETI | JMP_AREG => {}
+            JMP_ADDR => pending.extend(label_target(idx).map(|target| (target, next_depth))),
+            CALL_ADDR => {
+                if let [Param::Label(key)] = op.params.as_slice() {
+                    if targets.contains_key(key) {
+                        usage.calls.push((depth, key.clone()));
+                    }
+                }
+                pending.push((idx + 1, next_depth));
+            }
+            opcode => {
+                if is_jump_op(opcode) {
+                    pending.extend(label_target(idx).map(|target| (target, next_depth)));
+                }
+                pending.push((idx + 1, next_depth));
+            }
+        }
+    }
+    usage.calls.sort();
+    usage.calls.dedup();
+    usage
+}
+
+///Warn about recursion and stack that can't be estimated, error if the worst case doesn't fit in RAM
+///
+///The stack can use all RAM after the reserved memory, a warning is given if it's over the stack size
+pub fn check_stack(program_model: &ProgramModel) -> Vec<Diagnostic> {
+    let report = stack_usage(program_model);
+    let definition_line = |name: &str| {
+        program_model
+            .labels
+            .get(name)
+            .and_then(|label| label.definition.as_ref())
+            .map(|definition| definition.line_num)
+            .unwrap_or(0)
+    };
+    let mut output: Vec<Diagnostic> = report
+        .recursive
+        .iter()
+        .map(|name| {
+            Diagnostic::warning(
+                RECURSION,
+                definition_line(name),
+                0,
+                format!(
+                    "Function {} is recursive, its stack usage can't be estimated",
+                    name
+                ),
+            )
+        })
+        .collect();
+    for (name, usage) in &report.usage {
+        if usage.is_none()
+            && !report.recursive.contains(name)
+            && report.calls[name].iter().all(|callee| {
+                report
+                    .usage
+                    .get(callee)
+                    .is_some_and(|usage| usage.is_some())
+            })
+        {
+            output.push(Diagnostic::warning(
+                STACK_UNBOUNDED,
+                definition_line(name),
+                0,
+                format!(
+                    "Stack usage of {} can't be estimated, the stack grows in a loop",
+                    name
+                ),
+            ));
+        }
+    }
+
+    if let Some(Some(usage)) = report.usage.get(ENTRY) {
+        let available = RAM_SIZE - program_model.reserved_end();
+        let stack_size = program_model
+            .stack_size
+            .map(|size| size as usize)
+            .unwrap_or(DEFAULT_STACK_SIZE);
+        if *usage > available {
+            output.push(Diagnostic::error(
+                STACK_OVERFLOW,
+                0,
+                0,
+                format!(
+                    "Worst case stack usage is {} bytes but only {} bytes of RAM are free after the reserved memory",
+                    usage, available
+                ),
+            ));
+        } else if *usage > stack_size {
+            output.push(Diagnostic::warning(
+                STACK_OVERFLOW,
+                0,
+                0,
+                format!(
+                    "Worst case stack usage is {} bytes, more than the stack size of {} bytes",
+                    usage, stack_size
+                ),
+            ));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::parser::generate_program_model;
+
+    fn usage_report(program: &str) -> StackReport {
+        let program_model =
+            generate_program_model(program.lines().map(|line| line.to_string()).collect()).unwrap();
+        stack_usage(&program_model)
+    }
+
+    #[test]
+    fn test_stack_usage() {
+        let report = usage_report(
+            "Test\n1\n.ops\npush d0\ncall outer\npop d0\nhalt\nouter:\nlocal 3\ncall inner\nret\ninner:\npush a0\npop a0\nret\n",
+        );
+        assert_eq!(report.calls[ENTRY], BTreeSet::from([String::from("outer")]));
+        assert_eq!(report.usage["inner"], Some(2));
+        assert_eq!(report.usage["outer"], Some(9));
+        assert_eq!(report.usage[ENTRY], Some(14));
+        assert!(report.recursive.is_empty());
+    }
+
+    #[test]
+    fn test_recursion() {
+        let report = usage_report(
+            "Test\n1\n.ops\ncall first\nhalt\nfirst:\ncall second\nret\nsecond:\ncmp d0 0\nje end\ncall first\nend:\nret\n",
+        );
+        assert_eq!(
+            report.recursive,
+            BTreeSet::from([String::from("first"), String::from("second")])
+        );
+        assert_eq!(report.usage[ENTRY], None);
+
+        let report = usage_report("Test\n1\n.ops\nloop:\npush 1\njmp loop\n");
+        assert_eq!(report.usage[ENTRY], None);
+        assert!(report.recursive.is_empty());
+    }
+
+    #[test]
+    fn test_check_stack() {
+        let program_model = generate_program_model(
+            "Test\n1\n.reserve\nstack 2\n.ops\npush 1\npush 2\npush 3\n"
+                .lines()
+                .map(|line| line.to_string())
+                .collect(),
+        )
+        .unwrap();
+        let output = check_stack(&program_model);
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].code, STACK_OVERFLOW);
+        assert!(!output[0].is_error());
+    }
+}
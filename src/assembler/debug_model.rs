@@ -6,6 +6,8 @@ pub struct DebugModel {
     pub strings: Vec<DebugString>,
     pub data: Vec<DebugData>,
     pub labels: Vec<DebugLabel>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub functions: Vec<DebugFunction>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -46,6 +48,14 @@ pub struct DebugLabel {
     pub usage: Vec<DebugUsage>,
 }
 
+///Op bytes from `start` (inclusive) to `end` (exclusive) are in function `name`
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct DebugFunction {
+    pub(crate) name: String,
+    start: u16,
+    end: u16,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct DebugUsage {
     op_addr: u16,
@@ -65,6 +75,7 @@ impl DebugModel {
             strings,
             data,
             labels,
+            functions: vec![],
         }
     }
 }
@@ -80,6 +91,85 @@ impl DebugModel {
             .find(|op| op.line_num == line)
             .map(|op| op.byte_addr)
     }
+
+    pub fn line_for_byte(&self, byte: u16) -> Option<usize> {
+        self.op_for_byte(byte).map(|op| op.line_num)
+    }
+
+    ///Name of the function containing the op at `byte`
+    pub fn function_for_byte(&self, byte: u16) -> Option<&str> {
+        self.functions
+            .iter()
+            .find(|function| function.start <= byte && byte < function.end)
+            .map(|function| function.name.as_str())
+    }
+
+    pub fn label_addr(&self, name: &str) -> Option<u16> {
+        self.labels
+            .iter()
+            .find(|label| label.name == name)
+            .map(|label| label.byte)
+    }
+
+    ///Label addresses and names in address order
+    pub fn labels_by_addr(&self) -> Vec<(u16, &str)> {
+        let mut labels: Vec<(u16, &str)> = self
+            .labels
+            .iter()
+            .map(|label| (label.byte, label.name.as_str()))
+            .collect();
+        labels.sort();
+        labels
+    }
+
+    pub fn string_addr(&self, key: &str) -> Option<u16> {
+        self.strings
+            .iter()
+            .find(|string| string.key == key)
+            .map(|string| string.addr)
+    }
+
+    pub fn string_key(&self, addr: u16) -> Option<&str> {
+        self.strings
+            .iter()
+            .find(|string| string.addr == addr)
+            .map(|string| string.key.as_str())
+    }
+
+    pub fn data_key(&self, addr: u16) -> Option<&str> {
+        self.data
+            .iter()
+            .find(|data| data.addr == addr)
+            .map(|data| data.key.as_str())
+    }
+
+    pub fn data_addr(&self, key: &str) -> Option<u16> {
+        self.data
+            .iter()
+            .find(|data| data.key == key)
+            .map(|data| data.addr)
+    }
+
+    ///Assembler listing, one line per op with address, bytes and source
+    pub fn listing(&self) -> Vec<String> {
+        self.ops
+            .iter()
+            .map(|op| {
+                let bytes = op
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                format!(
+                    "{:04X}  {:<18} {}",
+                    op.byte_addr,
+                    bytes,
+                    op.original_line.trim()
+                )
+            })
+            .collect()
+    }
 }
 
 impl DebugOp {
@@ -150,6 +240,12 @@ impl DebugLabel {
     }
 }
 
+impl DebugFunction {
+    pub fn new(name: String, start: u16, end: u16) -> Self {
+        DebugFunction { name, start, end }
+    }
+}
+
 impl DebugUsage {
     pub fn new(op_byte: u16, offset: u8, line: usize) -> Self {
         DebugUsage {
@@ -0,0 +1,301 @@
+use crate::language::TokenError;
+use serde::Serialize;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+pub const INVALID_HEADER: &str = "invalid_header";
+pub const UNEXPECTED_DIVIDER: &str = "unexpected_divider";
+pub const INVALID_STRING: &str = "invalid_string";
+pub const INVALID_DATA: &str = "invalid_data";
+pub const INVALID_CONSTANT: &str = "invalid_constant";
+pub const INVALID_RESERVE: &str = "invalid_reserve";
+pub const INVALID_OP: &str = "invalid_op";
+pub const INVALID_FUNCTION: &str = "invalid_function";
+pub const INVALID_REPEAT: &str = "invalid_repeat";
+pub const NO_OPS: &str = "no_ops";
+pub const UNDEFINED_LABEL: &str = "undefined_label";
+pub const UNUSED_LABEL: &str = "unused_label";
+pub const UNUSED_STRING: &str = "unused_string";
+pub const UNUSED_DATA: &str = "unused_data";
+pub const UNUSED_CONSTANT: &str = "unused_constant";
+pub const UNUSED_RESERVE: &str = "unused_reserve";
+pub const WRAPPED_NUMBER: &str = "wrapped_number";
+pub const FUNCTION_FALLTHROUGH: &str = "function_fallthrough";
+pub const RESERVE_OVERLAPS_STACK: &str = "reserve_overlaps_stack";
+pub const RECURSION: &str = "recursion";
+pub const STACK_UNBOUNDED: &str = "stack_unbounded";
+pub const STACK_OVERFLOW: &str = "stack_overflow";
+pub const GENERATION: &str = "generation";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+///Assembler error or warning
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: Option<String>,
+    ///Starts at 1, 0 if not for a specific line
+    pub line: usize,
+    ///Starts at 1, 0 if not for a specific column
+    pub column: usize,
+    ///Column after the end of the span, 0 if not for a specific column
+    pub end_column: usize,
+    pub severity: Severity,
+    ///Machine readable code, see constants in this module
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, line: usize, column: usize, message: String) -> Self {
+        Diagnostic {
+            file: None,
+            line,
+            column,
+            end_column: single_column_end(column),
+            severity: Severity::Error,
+            code,
+            message,
+        }
+    }
+
+    pub fn warning(code: &'static str, line: usize, column: usize, message: String) -> Self {
+        Diagnostic {
+            file: None,
+            line,
+            column,
+            end_column: single_column_end(column),
+            severity: Severity::Warning,
+            code,
+            message,
+        }
+    }
+
+    ///Set the columns from a `(start, end)` span, see [`token_span`]
+    pub fn with_span(mut self, (column, end_column): (usize, usize)) -> Self {
+        self.column = column;
+        self.end_column = end_column;
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    ///Message with the source line and the span underlined, similar to rustc
+    ///
+    ///```text
+    ///error[invalid_op]: Unable to parse foo d0, instruction not recognised
+    /// --> <source>:7:1
+    ///  |
+    ///7 | foo d0
+    ///  | ^^^
+    ///```
+    ///
+    ///Any lines after the first line of the message are printed after the source
+    pub fn render(&self, source_line: &str) -> String {
+        let gutter = " ".repeat(self.line.to_string().len());
+        let mut message = self.message.lines();
+        let mut output = format!(
+            "{}[{}]: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+            self.severity_name(),
+            self.code,
+            message.next().unwrap_or_default(),
+            gutter,
+            self.file.as_deref().unwrap_or("<source>"),
+            self.line,
+            self.column,
+            gutter,
+            self.line,
+            source_line,
+            gutter,
+            " ".repeat(self.column.saturating_sub(1)),
+            "^".repeat(self.end_column.saturating_sub(self.column).max(1))
+        );
+        for line in message {
+            output.push_str(&format!("\n{} = {}", gutter, line));
+        }
+        output
+    }
+
+    fn severity_name(&self) -> &'static str {
+        match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+fn single_column_end(column: usize) -> usize {
+    if column > 0 {
+        column + 1
+    } else {
+        0
+    }
+}
+
+///All errors found while parsing a file
+#[derive(Debug)]
+pub struct ParseErrors {
+    pub diagnostics: Vec<Diagnostic>,
+    ///Source line for each diagnostic
+    lines: Vec<String>,
+}
+
+impl ParseErrors {
+    ///`errors` are diagnostics with the source line they're for
+    pub fn new(errors: Vec<(Diagnostic, String)>) -> Self {
+        let (diagnostics, lines) = errors.into_iter().unzip();
+        ParseErrors { diagnostics, lines }
+    }
+
+    ///Set the file name shown for every diagnostic
+    pub fn set_file(&mut self, file: &str) {
+        for diagnostic in self.diagnostics.iter_mut() {
+            diagnostic.file = Some(file.to_owned());
+        }
+    }
+}
+
+impl Display for ParseErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let rendered: Vec<String> = self
+            .diagnostics
+            .iter()
+            .zip(&self.lines)
+            .map(|(diagnostic, line)| diagnostic.render(line))
+            .collect();
+        write!(f, "{}", rendered.join("\n\n"))?;
+        if rendered.len() > 1 {
+            write!(f, "\n\n{} errors found", rendered.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseErrors {}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}[{}]: {}",
+            self.file.as_deref().unwrap_or("<source>"),
+            self.line,
+            self.column,
+            self.severity_name(),
+            self.code,
+            self.message
+        )
+    }
+}
+
+///Attached to errors while parsing so they can be converted into a [`Diagnostic`]
+///
+///Displays as the original line to match the previous error context
+#[derive(Debug)]
+pub(crate) struct LineContext {
+    pub line: String,
+    pub line_num: usize,
+    pub code: &'static str,
+}
+
+impl LineContext {
+    pub fn new(line: &str, line_num: usize, code: &'static str) -> Self {
+        LineContext {
+            line: line.to_owned(),
+            line_num,
+            code,
+        }
+    }
+}
+
+impl Display for LineContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.line)
+    }
+}
+
+///Column of the first non whitespace character in `line`, starting at 1
+pub(crate) fn first_column(line: &str) -> usize {
+    line.chars()
+        .position(|chr| !chr.is_whitespace())
+        .map(|idx| idx + 1)
+        .unwrap_or(1)
+}
+
+///Start and end columns of the line without leading or trailing whitespace and comments
+pub(crate) fn statement_span(line: &str) -> (usize, usize) {
+    let content = line.split('#').next().unwrap_or_default().trim_end();
+    let start = first_column(line);
+    (start, (content.chars().count() + 1).max(start + 1))
+}
+
+///Start and end columns of `token` in `line` where it's a whole word, starting at 1
+///
+///Falls back to [`statement_span`] if the token isn't found
+pub(crate) fn token_span(line: &str, token: &str) -> (usize, usize) {
+    let is_boundary = |chr: Option<char>| {
+        chr.map(|chr| chr.is_whitespace() || chr == ':' || chr == '#' || chr == '=')
+            .unwrap_or(true)
+    };
+    if token.is_empty() {
+        return statement_span(line);
+    }
+    line.match_indices(token)
+        .find(|(idx, _)| {
+            is_boundary(line[..*idx].chars().next_back())
+                && is_boundary(line[idx + token.len()..].chars().next())
+        })
+        .map(|(idx, _)| {
+            let start = line[..idx].chars().count() + 1;
+            (start, start + token.chars().count())
+        })
+        .unwrap_or_else(|| statement_span(line))
+}
+
+impl From<&anyhow::Error> for Diagnostic {
+    fn from(err: &anyhow::Error) -> Self {
+        let message = err.root_cause().to_string();
+        match err.downcast_ref::<LineContext>() {
+            Some(context) => Diagnostic::error(context.code, context.line_num, 0, message)
+                .with_span(match err.downcast_ref::<TokenError>() {
+                    Some(TokenError {
+                        columns: Some(columns),
+                        ..
+                    }) => *columns,
+                    Some(token) => token_span(&context.line, &token.token),
+                    None => statement_span(&context.line),
+                }),
+            None => Diagnostic::error(GENERATION, 0, 0, message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_spans() {
+        assert_eq!(token_span("  cpy d0 300", "300"), (10, 13));
+        assert_eq!(token_span("lbl: jmp lbl", "lbl"), (1, 4));
+        assert_eq!(token_span("nop", "missing"), (1, 4));
+        assert_eq!(statement_span("  halt  # end"), (3, 7));
+        assert_eq!(statement_span(""), (1, 2));
+    }
+
+    #[test]
+    fn test_render() {
+        let diagnostic =
+            Diagnostic::error(INVALID_OP, 12, 0, String::from("Bad\nhint")).with_span((7, 10));
+        assert_eq!(
+            diagnostic.render("  cpy d0 300"),
+            "error[invalid_op]: Bad\n  --> <source>:12:7\n   |\n12 |   cpy d0 300\n   |       ^^^\n   = hint"
+        );
+    }
+}
@@ -0,0 +1,299 @@
+//! Reprints BASM source in one consistent style, used by `fmt`
+//!
+//! Mnemonics, registers and dividers are lower case, ops are indented with labels on their own line,
+//! trailing comments are lined up within each block of lines and sections are ordered
+//! strings, data, reserve then ops. Comments are kept and comments directly above a divider move with it
+
+use crate::assembler::parser::generate_program_model;
+use crate::assembler::parser::syntax::{parse_syntax, LineKind, SourceLine};
+use crate::assembler::parser::ParseMode;
+use crate::common::read_lines;
+use crate::constants::code::REGISTERS;
+use anyhow::{Context, Result};
+
+const INDENT: &str = "    ";
+const SECTION_ORDER: [ParseMode; 4] = [
+    ParseMode::Strings,
+    ParseMode::Data,
+    ParseMode::Reserve,
+    ParseMode::Ops,
+];
+
+///Format the file at `path`, printing the result or replacing the file if `write` is set
+pub fn start(path: &str, write: bool) -> Result<()> {
+    let formatted = format_source(read_lines(path)?)?;
+    if write {
+        let mut text = formatted.join("\n");
+        text.push('\n');
+        std::fs::write(path, text).context(format!("Unable to write {}", path))?;
+        println!("Formatted {}", path);
+    } else {
+        for line in formatted {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+struct Section {
+    mode: ParseMode,
+    ///Comments directly above the divider
+    leading: Vec<SourceLine>,
+    lines: Vec<SourceLine>,
+}
+
+enum Row {
+    Blank,
+    Comment(String),
+    Code {
+        text: String,
+        comment: Option<String>,
+        is_label: bool,
+    },
+}
+
+///Format BASM source, fails if the source isn't valid
+pub fn format_source(input: Vec<String>) -> Result<Vec<String>> {
+    let syntax = parse_syntax(&input);
+    generate_program_model(input)?;
+
+    let mut header = vec![];
+    let mut sections: Vec<Section> = vec![];
+    for line in syntax {
+        let current = match sections.last_mut() {
+            Some(section) => &mut section.lines,
+            None => &mut header,
+        };
+        if let LineKind::Divider(mode) = line.kind {
+            let start = current
+                .iter()
+                .rposition(|line| !matches!(line.kind, LineKind::Comment(_)))
+                .map(|idx| idx + 1)
+                .unwrap_or(0);
+            let leading = current.split_off(start);
+            sections.push(Section {
+                mode,
+                leading,
+                lines: vec![],
+            });
+        } else {
+            current.push(line);
+        }
+    }
+
+    let mut output = format_rows(header.iter().flat_map(to_rows).collect(), false);
+    for mode in SECTION_ORDER {
+        let mut matching = sections.iter().filter(|section| section.mode == mode);
+        if let Some(first) = matching.next() {
+            let mut rows: Vec<Row> = first.leading.iter().flat_map(to_rows).collect();
+            output.push(String::new());
+            output.extend(format_rows(rows, false));
+            output.push(divider(mode).to_owned());
+            rows = first.lines.iter().flat_map(to_rows).collect();
+            for section in matching {
+                rows.push(Row::Blank);
+                rows.extend(section.leading.iter().flat_map(to_rows));
+                rows.extend(section.lines.iter().flat_map(to_rows));
+            }
+            output.extend(format_rows(rows, mode == ParseMode::Ops));
+        }
+    }
+    Ok(output)
+}
+
+fn divider(mode: ParseMode) -> &'static str {
+    match mode {
+        ParseMode::Header => "",
+        ParseMode::Strings => ".strings",
+        ParseMode::Data => ".data",
+        ParseMode::Reserve => ".reserve",
+        ParseMode::Ops => ".ops",
+    }
+}
+
+fn code(text: String, comment: &Option<String>) -> Row {
+    Row::Code {
+        text,
+        comment: comment.clone(),
+        is_label: false,
+    }
+}
+
+fn to_rows(line: &SourceLine) -> Vec<Row> {
+    let comment = &line.comment;
+    match &line.kind {
+        LineKind::Blank => vec![Row::Blank],
+        LineKind::Comment(text) => vec![Row::Comment(text.clone())],
+        LineKind::Header(text) => vec![code(text.clone(), comment)],
+        LineKind::Description(text) => vec![code(format!(".description {}", text), comment)],
+        LineKind::Start(label) => vec![code(format!(".start {}", label), comment)],
+        LineKind::Repeat(params) => vec![code(format!(".repeat {}", params), comment)],
+        LineKind::EndRepeat => vec![code(String::from(".endrepeat"), comment)],
+        LineKind::Divider(mode) => vec![code(divider(*mode).to_owned(), comment)],
+        LineKind::String { key, content } | LineKind::Data { key, content } => {
+            vec![code(format!("{}={}", key, content), comment)]
+        }
+        LineKind::Reserve { key, size } => vec![code(format!("{} {}", key, size), comment)],
+        LineKind::Constant { key, value } => vec![code(
+            format!("{}const {} {}", INDENT, key, format_token(value)),
+            comment,
+        )],
+        LineKind::Function(name) => vec![Row::Code {
+            text: format!("fn {}:", name),
+            comment: comment.clone(),
+            is_label: true,
+        }],
+        LineKind::EndFunction => vec![code(String::from("endfn"), comment)],
+        LineKind::Op { label, tokens } => {
+            let mut rows = vec![];
+            if let Some(label) = label {
+                rows.push(Row::Code {
+                    text: format!("{}:", label),
+                    comment: if tokens.is_empty() {
+                        comment.clone()
+                    } else {
+                        None
+                    },
+                    is_label: true,
+                });
+            }
+            if !tokens.is_empty() {
+                let mut op = tokens[0].to_ascii_lowercase();
+                for token in &tokens[1..] {
+                    op.push(' ');
+                    op.push_str(&format_token(token));
+                }
+                rows.push(code(format!("{}{}", INDENT, op), comment));
+            }
+            rows
+        }
+    }
+}
+
+fn format_token(token: &str) -> String {
+    let lower = token.to_ascii_lowercase();
+    let is_register = |text: &str| REGISTERS.contains(&text);
+    let is_indexed = lower
+        .split_once('+')
+        .map(|(areg, reg)| is_register(areg) && is_register(reg))
+        .unwrap_or(false);
+    if is_register(&lower) || is_indexed {
+        lower
+    } else {
+        token.to_owned()
+    }
+}
+
+///Print rows without repeated, leading or trailing blank lines and with trailing comments lined up
+///
+///Comments in the ops section are indented unless they're directly above a label
+fn format_rows(rows: Vec<Row>, is_ops: bool) -> Vec<String> {
+    let mut rows: Vec<Row> = rows.into_iter().fold(vec![], |mut rows, row| {
+        let after_blank = matches!(rows.last(), None | Some(Row::Blank));
+        if !(matches!(row, Row::Blank) && after_blank) {
+            rows.push(row);
+        }
+        rows
+    });
+    if matches!(rows.last(), Some(Row::Blank)) {
+        rows.pop();
+    }
+
+    let mut output = vec![];
+    let mut idx = 0;
+    while idx < rows.len() {
+        match &rows[idx] {
+            Row::Blank => output.push(String::new()),
+            Row::Comment(text) => {
+                let above_label = rows[idx..]
+                    .iter()
+                    .find(|row| !matches!(row, Row::Comment(_)))
+                    .map(|row| matches!(row, Row::Code { is_label: true, .. }))
+                    .unwrap_or(false);
+                if is_ops && !above_label {
+                    output.push(format!("{}{}", INDENT, text));
+                } else {
+                    output.push(text.clone());
+                }
+            }
+            Row::Code { .. } => {
+                let end = rows[idx..]
+                    .iter()
+                    .position(|row| !matches!(row, Row::Code { .. }))
+                    .map(|len| idx + len)
+                    .unwrap_or(rows.len());
+                let width = rows[idx..end]
+                    .iter()
+                    .filter_map(|row| match row {
+                        Row::Code {
+                            text,
+                            comment: Some(_),
+                            ..
+                        } => Some(text.len()),
+                        _ => None,
+                    })
+                    .max()
+                    .unwrap_or(0);
+                for row in &rows[idx..end] {
+                    if let Row::Code { text, comment, .. } = row {
+                        match comment {
+                            Some(comment) => {
+                                output.push(format!("{:<width$} {}", text, comment, width = width))
+                            }
+                            None => output.push(text.clone()),
+                        }
+                    }
+                }
+                idx = end;
+                continue;
+            }
+        }
+        idx += 1;
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::lines;
+
+    #[test]
+    fn test_format_source() {
+        let source = "Prog\n1\n.start   loop\n# data\n.data\nnums = [[1,2]]\n.strings\nhi=Hi # there\n.ops\n\n\n# start\nloop:  CPY D0 'a' #first\n\tprtc   d0 # print it\n   MEMR A0+D1\n   jmp loop\n";
+        let formatted = format_source(lines(source)).unwrap();
+        assert_eq!(
+            formatted,
+            lines(
+                "Prog\n1\n.start loop\n\n.strings\nhi=Hi # there\n\n# data\n.data\nnums=[[1,2]]\n\n.ops\n# start\nloop:\n    cpy d0 'a' #first\n    prtc d0    # print it\n    memr a0+d1\n    jmp loop"
+            )
+        );
+        assert_eq!(format_source(formatted.clone()).unwrap(), formatted);
+        assert!(format_source(lines("Prog\n1\n.ops\nfoo d0\n")).is_err());
+    }
+
+    #[test]
+    fn test_format_repeat() {
+        let source = "Prog\n1\n.ops\n .repeat   3  i # unrolled\nPRT i\n  .endrepeat\n";
+        let formatted = format_source(lines(source)).unwrap();
+        assert_eq!(
+            formatted,
+            lines("Prog\n1\n\n.ops\n.repeat 3 i # unrolled\n    prt i\n.endrepeat")
+        );
+        assert_eq!(format_source(formatted.clone()).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_format_function() {
+        let source =
+            "Prog\n1\n.ops\ncall print\n halt\n  FN print: # prints a\nprtc 'a'\n   RET\n ENDFN\n";
+        let formatted = format_source(lines(source)).unwrap();
+        assert_eq!(
+            formatted,
+            lines(
+                "Prog\n1\n\n.ops\n    call print\n    halt\nfn print: # prints a\n    prtc 'a'\n    ret\nendfn"
+            )
+        );
+        assert_eq!(format_source(formatted.clone()).unwrap(), formatted);
+    }
+}
@@ -1,24 +1,35 @@
 use crate::assembler::debug_model::{
-    DebugData, DebugLabel, DebugModel, DebugOp, DebugString, DebugUsage,
+    DebugData, DebugFunction, DebugLabel, DebugModel, DebugOp, DebugString, DebugUsage,
 };
 use crate::assembler::program_model::{
-    AddressReplacement, DataModel, LabelModel, OpModel, ProgramModel, StringModel,
+    AddressReplacement, DataModel, FunctionModel, LabelModel, OpModel, ProgramModel, StringModel,
 };
+use crate::common::crc32;
 use crate::constants::hardware::{MAX_DATA_BYTES, MAX_STRING_BYTES};
-use crate::constants::system::{PRG_VERSION, TAPE_HEADER_1, TAPE_HEADER_2};
+use crate::constants::system::{
+    DATA_TAPE_HEADER_2, DATA_TAPE_VERSION, PRG_VERSION, TAPE_HEADER_1, TAPE_HEADER_2,
+};
 use crate::constants::{get_addr_byte_offset, get_byte_count};
 use anyhow::{Error, Result};
 use std::collections::{BTreeMap, HashMap};
 
 pub fn generate_byte_code(program_model: ProgramModel) -> Result<(Vec<u8>, DebugModel)> {
     //Write header
-    //0xFD A0 01 <name len> <name> <ver len> <ver>
+    //0xFD A0 05 <name len> <name> <ver len> <ver> <desc len> <desc> <build time u64> <entry u16> <crc32 u32>
     let mut output = vec![TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION];
     let mut debug_model = DebugModel::default();
     output.push(program_model.name.len() as u8);
     output.extend_from_slice(program_model.name.as_bytes());
     output.push(program_model.version.len() as u8);
     output.extend_from_slice(program_model.version.as_bytes());
+    let description = program_model.description.unwrap_or_default();
+    output.push(description.len() as u8);
+    output.extend_from_slice(description.as_bytes());
+    output.extend_from_slice(&program_model.build_time.to_be_bytes());
+    let entry_start = output.len();
+    output.extend_from_slice(&[0; 2]); //written once label addresses are known
+    let checksum_start = output.len();
+    output.extend_from_slice(&[0; 4]); //written once body is complete
 
     let op_byte_start = output.len() + 2; //+2 for op byte count written once len is known
 
@@ -41,6 +52,15 @@ pub fn generate_byte_code(program_model: ProgramModel) -> Result<(Vec<u8>, Debug
     output.extend_from_slice(&(ops_output.bytes.len() as u16).to_be_bytes());
     output.extend_from_slice(&ops_output.bytes);
 
+    generate_function_extents(&program_model.functions, &mut debug_model);
+
+    if let Some(start) = &program_model.start {
+        let entry = ops_output.label_addresses.get(start).ok_or_else(|| {
+            Error::msg(format!("Start label {} must be followed by an op", start))
+        })?;
+        output[entry_start..entry_start + 2].copy_from_slice(&entry.to_be_bytes());
+    }
+
     //Now all label positions are known, update addresses
     output = update_addresses(
         output,
@@ -50,14 +70,47 @@ pub fn generate_byte_code(program_model: ProgramModel) -> Result<(Vec<u8>, Debug
         &mut debug_model,
     );
 
-    //Write string len, string bytes and data bytes
+    //Write string len, string bytes, relocations and data bytes
     output.extend_from_slice(&(string_bytes.len() as u16).to_be_bytes());
     output.extend_from_slice(&string_bytes);
+    output.extend_from_slice(&(ops_output.relocations.len() as u16).to_be_bytes());
+    for (key, offsets) in &ops_output.relocations {
+        output.push(key.len() as u8);
+        output.extend_from_slice(key.as_bytes());
+        output.extend_from_slice(&(offsets.len() as u16).to_be_bytes());
+        for offset in offsets {
+            output.extend_from_slice(&offset.to_be_bytes());
+        }
+    }
     output.extend_from_slice(&data_bytes);
 
+    let checksum = crc32(&output[checksum_start + 4..]).to_be_bytes();
+    output[checksum_start..checksum_start + 4].copy_from_slice(&checksum);
+
     Ok((output, debug_model))
 }
 
+///Record the op bytes covered by each function, must be called after the ops are generated
+fn generate_function_extents(functions: &[FunctionModel], debug: &mut DebugModel) {
+    for function in functions {
+        let start_line = function.definition.line_num;
+        let end_line = function.end_line.unwrap_or(usize::MAX);
+        let mut ops = debug
+            .ops
+            .iter()
+            .filter(|op| op.line_num > start_line && op.line_num < end_line);
+        if let Some(first) = ops.next() {
+            let last = ops.next_back().unwrap_or(first);
+            let end = last.byte_addr + last.bytes.len() as u16;
+            debug.functions.push(DebugFunction::new(
+                function.name.clone(),
+                first.byte_addr,
+                end,
+            ));
+        }
+    }
+}
+
 /// Replace placeholder address bytes with actual values
 /// * `bytes`: The list of bytes to update
 /// * `targets`: The indexes of bytes in `bytes` to update, mapped by a string key
@@ -104,6 +157,8 @@ struct OpsOutput {
     bytes: Vec<u8>,
     label_targets: HashMap<String, Vec<u16>>,
     label_addresses: HashMap<String, u16>,
+    ///Offsets of data addresses in ops for data in data tapes, mapped by data key
+    relocations: BTreeMap<String, Vec<u16>>,
 }
 
 fn generate_ops_bytes(
@@ -180,6 +235,13 @@ fn generate_ops_bytes(
                     bytes[param_offset] = addr[0];
                     bytes[param_offset + 1] = addr[1];
                 }
+                AddressReplacement::Data(key) if !data_addresses.contains_key(&key) => {
+                    output
+                        .relocations
+                        .entry(key)
+                        .or_insert_with(Vec::new)
+                        .push((output.bytes.len() + param_offset) as u16);
+                }
                 AddressReplacement::Data(key) => {
                     debug
                         .data
@@ -233,6 +295,9 @@ fn generate_data_bytes(
     let mut list: Vec<(String, DataModel)> = data.into_iter().collect();
     list.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
     for (key, data_model) in list {
+        if data_model.external {
+            continue;
+        }
         if (output.len() + data_model.content.len()) > MAX_DATA_BYTES {
             return Err(Error::msg(format!(
                 "Too much data at `{}` on line {}, max {} bytes but is at least {} bytes",
@@ -256,6 +321,30 @@ fn generate_data_bytes(
     Ok((output, addresses))
 }
 
+///Generate a data tape containing every data entry
+pub fn generate_data_tape(program_model: ProgramModel) -> Result<Vec<u8>> {
+    if !program_model.ops.is_empty() || !program_model.strings.is_empty() {
+        return Err(Error::msg("Data tapes can only contain data"));
+    }
+    let mut output = vec![TAPE_HEADER_1, DATA_TAPE_HEADER_2, DATA_TAPE_VERSION];
+    let mut list: Vec<(String, DataModel)> = program_model.data.into_iter().collect();
+    list.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+    output.extend_from_slice(&(list.len() as u16).to_be_bytes());
+    for (key, data_model) in list {
+        if data_model.external {
+            return Err(Error::msg(format!(
+                "Data tapes can't contain external data, found {} on line {}",
+                key, data_model.definition.line_num
+            )));
+        }
+        output.push(key.len() as u8);
+        output.extend_from_slice(key.as_bytes());
+        output.extend_from_slice(&(data_model.content.len() as u16).to_be_bytes());
+        output.extend_from_slice(&data_model.content);
+    }
+    Ok(output)
+}
+
 fn generate_string_bytes(
     strings: HashMap<String, StringModel>,
     debug: &mut DebugModel,
@@ -293,8 +382,8 @@ fn convert_label_map_to_linenum(
     labels: HashMap<String, LabelModel>,
 ) -> BTreeMap<usize, LabelModel> {
     labels
-        .into_iter()
-        .map(|(_, model)| (model.definition.as_ref().unwrap().line_num, model))
+        .into_values()
+        .map(|model| (model.definition.as_ref().unwrap().line_num, model))
         .collect()
 }
 
@@ -411,7 +500,7 @@ mod test {
             let output = generate_ops_bytes(
                 &[
                     OpModel::new(PRTS_STR, vec![StrKey(String::from("foo"))], String::new(), String::from("prts foo"), 0),
-                    OpModel::new(LD_AREG_DATA_REG_VAL, vec![AddrReg(REG_A0), DataKey(String::from("bar")), DataReg(REG_D2), Number(10), ], String::new(), String::from("ld a0 bar d2 10"), 0),
+                    OpModel::new(LD_AREG_DATA_REG_VAL, vec![AddrReg(Register::A0), DataKey(String::from("bar")), DataReg(Register::D2), Number(10), ], String::new(), String::from("ld a0 bar d2 10"), 0),
                 ],
                 0,
                 HashMap::new(),
@@ -431,10 +520,21 @@ mod test {
         }
     }
 
+    ///Header for a program named `a` with version `b`
+    fn with_header(body: Vec<u8>) -> Vec<u8> {
+        let mut bytes = vec![TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION, 1, 97, 1, 98, 0];
+        bytes.extend_from_slice(&[0; 10]);
+        bytes.extend_from_slice(&crc32(&body).to_be_bytes());
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
     #[test]
     #[rustfmt::skip]
     fn test_header() {
-        let model = ProgramModel::new(String::from("Test Prog"), String::from("1.0"));
+        let mut model = ProgramModel::new(String::from("Test Prog"), String::from("1.0"));
+        model.description = Some(String::from("Hi"));
+        model.build_time = 0x01020304;
         let (bytes, _) = generate_byte_code(model).unwrap();
 
         assert_eq!(
@@ -443,6 +543,11 @@ mod test {
                 TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
                 9, 84, 101, 115, 116, 32, 80, 114, 111, 103,
                 3, 49, 46, 48,
+                2, 72, 105,
+                0, 0, 0, 0, 1, 2, 3, 4,
+                0, 0,
+                0xB1, 0xC2, 0xA1, 0xA3,
+                0, 0,
                 0, 0,
                 0, 0
             ]
@@ -454,24 +559,22 @@ mod test {
     fn test_simple_prog() {
         let mut model = ProgramModel::new(String::from("a"), String::from("b"));
 
-        model.ops.push(OpModel::new(INC_REG, vec![Param::DataReg(REG_D0)], String::new(), String::from("inc d0"), 0));
-        model.ops.push(OpModel::new(CPY_REG_REG, vec![Param::DataReg(REG_D1), Param::DataReg(REG_D0)], String::new(), String::from("cpy d1 d0"), 1));
-        model.ops.push(OpModel::new(ADD_REG_REG, vec![Param::DataReg(REG_D0), Param::DataReg(REG_D1)], String::new(), String::from("add d0 d1"), 2));
+        model.ops.push(OpModel::new(INC_REG, vec![Param::DataReg(Register::D0)], String::new(), String::from("inc d0"), 0));
+        model.ops.push(OpModel::new(CPY_REG_REG, vec![Param::DataReg(Register::D1), Param::DataReg(Register::D0)], String::new(), String::from("cpy d1 d0"), 1));
+        model.ops.push(OpModel::new(ADD_REG_REG, vec![Param::DataReg(Register::D0), Param::DataReg(Register::D1)], String::new(), String::from("add d0 d1"), 2));
 
         let (bytes, _) = generate_byte_code(model).unwrap();
 
         assert_eq!(
             bytes,
-            vec![
-                TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
-                1, 97,
-                1, 98,
+            with_header(vec![
                 0, 8,
                 INC_REG, REG_D0,
                 CPY_REG_REG, REG_D1, REG_D0,
                 ADD_REG_REG, REG_D0, REG_D1,
+                0, 0,
                 0, 0
-            ]
+            ])
         )
     }
 
@@ -483,24 +586,22 @@ mod test {
         model.strings.insert(String::from("abc"), StringModel::new(String::from("abc"), String::from("foo"), String::new(), 0));
         model.strings.insert(String::from("test"), StringModel::new(String::from("test"), String::from("bar"), String::new(), 0));
 
-        model.ops.push(OpModel::new(INC_REG, vec![Param::DataReg(REG_D0)], String::new(), String::from("inc d0"), 0));
+        model.ops.push(OpModel::new(INC_REG, vec![Param::DataReg(Register::D0)], String::new(), String::from("inc d0"), 0));
         model.ops.push(OpModel::new(PRTS_STR, vec![Param::StrKey(String::from("test"))], String::new(), String::from("prts test"), 1));
 
         let (bytes, _) = generate_byte_code(model).unwrap();
 
         assert_eq!(
             bytes,
-            vec![
-                TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
-                1, 97,
-                1, 98,
+            with_header(vec![
                 0, 5,
                 INC_REG, REG_D0,
                 PRTS_STR, 0, 4,
                 0, 8,
                 3, 102, 111, 111,
-                3, 98, 97, 114
-            ]
+                3, 98, 97, 114,
+                0, 0
+            ])
         )
     }
 
@@ -512,29 +613,74 @@ mod test {
         model.data.insert(String::from("dk1"), DataModel::new(String::new(), vec![3, 2, 2, 4, 10, 11, 50, 51, 97, 98, 99, 100], vec![vec![10, 11], vec![50, 51], vec![97, 98, 99, 100]], String::new(), 0));
         model.data.insert(String::from("dk2"), DataModel::new(String::new(), vec![1, 10, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39], vec![vec![30, 31, 32, 33, 34, 35, 36, 37, 38, 39]], String::new(), 0));
 
-        model.ops.push(OpModel::new(ADD_REG_REG, vec![Param::DataReg(REG_D0), Param::DataReg(REG_D1)], String::new(), String::from("add d0 d1"), 0));
-        model.ops.push(OpModel::new(INC_REG, vec![Param::DataReg(REG_ACC)], String::new(), String::from("inc acc"), 0));
-        model.ops.push(OpModel::new(LD_AREG_DATA_VAL_REG, vec![Param::AddrReg(REG_A0), Param::DataKey(String::from("dk2")), Param::Number(2), Param::DataReg(REG_D3)], String::new(), String::from("ld a0 dk1 2 d3"), 1));
+        model.ops.push(OpModel::new(ADD_REG_REG, vec![Param::DataReg(Register::D0), Param::DataReg(Register::D1)], String::new(), String::from("add d0 d1"), 0));
+        model.ops.push(OpModel::new(INC_REG, vec![Param::DataReg(Register::Acc)], String::new(), String::from("inc acc"), 0));
+        model.ops.push(OpModel::new(LD_AREG_DATA_VAL_REG, vec![Param::AddrReg(Register::A0), Param::DataKey(String::from("dk2")), Param::Number(2), Param::DataReg(Register::D3)], String::new(), String::from("ld a0 dk1 2 d3"), 1));
 
         let (bytes, _) = generate_byte_code(model).unwrap();
 
         assert_eq!(
             bytes,
-            vec![
-                TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
-                1, 97,
-                1, 98,
+            with_header(vec![
                 0, 11,
                 ADD_REG_REG, REG_D0, REG_D1,
                 INC_REG, REG_ACC,
                 LD_AREG_DATA_VAL_REG, REG_A0, 0, 12, 2, REG_D3,
                 0, 0,
+                0, 0,
                 3, 2, 2, 4, 10, 11, 50, 51, 97, 98, 99, 100,
                 1, 10, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39
-            ]
+            ])
         )
     }
 
+    #[test]
+    #[rustfmt::skip]
+    fn test_external_data() {
+        let mut model = ProgramModel::new(String::from("a"), String::from("b"));
+        let mut external = DataModel::new(String::from("ext"), vec![], vec![], String::new(), 0);
+        external.external = true;
+
+        model.data.insert(String::from("dk1"), DataModel::new(String::new(), vec![1, 1, 5], vec![vec![5]], String::new(), 0));
+        model.data.insert(String::from("ext"), external);
+
+        model.ops.push(OpModel::new(INC_REG, vec![Param::DataReg(Register::D0)], String::new(), String::from("inc d0"), 0));
+        model.ops.push(OpModel::new(LD_AREG_DATA_VAL_REG, vec![Param::AddrReg(Register::A0), Param::DataKey(String::from("ext")), Param::Number(2), Param::DataReg(Register::D3)], String::new(), String::from("ld a0 ext 2 d3"), 1));
+        model.ops.push(OpModel::new(LD_AREG_DATA_VAL_REG, vec![Param::AddrReg(Register::A0), Param::DataKey(String::from("dk1")), Param::Number(2), Param::DataReg(Register::D3)], String::new(), String::from("ld a0 dk1 2 d3"), 2));
+
+        let (bytes, _) = generate_byte_code(model).unwrap();
+
+        assert_eq!(
+            bytes,
+            with_header(vec![
+                0, 14,
+                INC_REG, REG_D0,
+                LD_AREG_DATA_VAL_REG, REG_A0, 0, 0, 2, REG_D3,
+                LD_AREG_DATA_VAL_REG, REG_A0, 0, 0, 2, REG_D3,
+                0, 0,
+                0, 1,
+                3, 101, 120, 116, 0, 1, 0, 4,
+                1, 1, 5
+            ])
+        )
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_data_tape() {
+        let mut model = ProgramModel::new(String::from("a"), String::from("b"));
+        model.data.insert(String::from("dk1"), DataModel::new(String::new(), vec![1, 1, 5], vec![vec![5]], String::new(), 0));
+
+        assert_eq!(
+            generate_data_tape(model).unwrap(),
+            vec![
+                TAPE_HEADER_1, DATA_TAPE_HEADER_2, DATA_TAPE_VERSION,
+                0, 1,
+                3, 100, 107, 49, 0, 3, 1, 1, 5
+            ]
+        );
+    }
+
     #[test]
     #[rustfmt::skip]
     fn test_simple_prog_with_strings_and_data() {
@@ -543,19 +689,16 @@ mod test {
         model.strings.insert(String::from("abc"), StringModel::new(String::from("abc"), String::from("foo"), String::new(), 0));
         model.data.insert(String::from("dk1"), DataModel::new(String::new(), vec![3, 2, 2, 4, 10, 11, 50, 51, 97, 98, 99, 100], vec![vec![10, 11], vec![50, 51], vec![97, 98, 99, 100]], String::new(), 0));
 
-        model.ops.push(OpModel::new(ADD_REG_REG, vec![Param::DataReg(REG_D0), Param::DataReg(REG_D1)], String::new(), String::from("add d0 d1"), 0));
-        model.ops.push(OpModel::new(INC_REG, vec![Param::DataReg(REG_ACC)], String::new(), String::from("inc acc"), 0));
-        model.ops.push(OpModel::new(LD_AREG_DATA_VAL_REG, vec![Param::AddrReg(REG_A0), Param::DataKey(String::from("dk1")), Param::Number(2), Param::DataReg(REG_D3)], String::new(), String::from("ld a0 dk1 2 d3"), 1));
+        model.ops.push(OpModel::new(ADD_REG_REG, vec![Param::DataReg(Register::D0), Param::DataReg(Register::D1)], String::new(), String::from("add d0 d1"), 0));
+        model.ops.push(OpModel::new(INC_REG, vec![Param::DataReg(Register::Acc)], String::new(), String::from("inc acc"), 0));
+        model.ops.push(OpModel::new(LD_AREG_DATA_VAL_REG, vec![Param::AddrReg(Register::A0), Param::DataKey(String::from("dk1")), Param::Number(2), Param::DataReg(Register::D3)], String::new(), String::from("ld a0 dk1 2 d3"), 1));
         model.ops.push(OpModel::new(PRTS_STR, vec![Param::StrKey(String::from("abc"))], String::new(), String::from("prts abc"), 3));
 
         let (bytes, model) = generate_byte_code(model).unwrap();
 
         assert_eq!(
             bytes,
-            vec![
-                TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
-                1, 97,
-                1, 98,
+            with_header(vec![
                 0, 14,
                 ADD_REG_REG, REG_D0, REG_D1,
                 INC_REG, REG_ACC,
@@ -563,8 +706,9 @@ mod test {
                 PRTS_STR, 0, 0,
                 0, 4,
                 3, 102, 111, 111,
+                0, 0,
                 3, 2, 2, 4, 10, 11, 50, 51, 97, 98, 99, 100
-            ]
+            ])
         );
 
         let mut debug_str = DebugString::new(0, String::from("abc"), String::from("foo"), String::new(), 0);
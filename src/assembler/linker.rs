@@ -0,0 +1,329 @@
+use crate::assembler::program_model::{LabelModel, OpModel, ProgramModel, Usage};
+use crate::constants::code::CPY_AREG_ADDR;
+use crate::language::parser::params::Param;
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+///Increased whenever the object format changes
+pub const OBJECT_VERSION: u8 = 1;
+
+///Relocatable object written by `assemble --object`, labels, strings and data are kept as keys until [`link`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ObjectFile {
+    pub object_version: u8,
+    pub model: ProgramModel,
+}
+
+impl ObjectFile {
+    pub fn new(model: ProgramModel) -> Self {
+        ObjectFile {
+            object_version: OBJECT_VERSION,
+            model,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let object: ObjectFile = serde_json::from_slice(bytes)
+            .map_err(|err| Error::msg(format!("Invalid object file: {}", err)))?;
+        if object.object_version != OBJECT_VERSION {
+            return Err(Error::msg(format!(
+                "Unsupported object version {}, expected {}",
+                object.object_version, OBJECT_VERSION
+            )));
+        }
+        Ok(object)
+    }
+}
+
+///Merge objects (with their file names, for errors) into a single program
+///
+///The name, version and description come from the first object. Line numbers are offset
+///for each object so labels still point at the ops after them.
+///
+///Reserved memory is replaced with addresses when assembling so only the first object may use `.reserve`,
+///only one object may use `.start`
+pub fn link(objects: Vec<(String, ProgramModel)>) -> Result<ProgramModel> {
+    let first = objects
+        .first()
+        .map(|(_, model)| model)
+        .ok_or_else(|| Error::msg("No objects to link"))?;
+    let mut program_model = ProgramModel::new(first.name.clone(), first.version.clone());
+    program_model.description = first.description.clone();
+
+    let mut defined: HashMap<String, String> = HashMap::new();
+    let mut start: Option<(String, String, Usage)> = None;
+    let mut ops: Vec<(String, usize, OpModel)> = vec![];
+    let mut line_offset = 0;
+    for (idx, (file, object)) in objects.into_iter().enumerate() {
+        let last_line = last_line(&object);
+        if idx > 0 && (!object.reserved.is_empty() || object.stack_size.is_some()) {
+            return Err(Error::msg(format!(
+                "{} uses .reserve, only the first object may reserve memory",
+                file
+            )));
+        }
+        for (key, mut reserved) in object.reserved {
+            define(&mut defined, &key, &file)?;
+            reserved.definition.line_num += line_offset;
+            for usage in reserved.usage.iter_mut() {
+                usage.line_num += line_offset;
+            }
+            program_model.reserved.insert(key, reserved);
+        }
+        if object.stack_size.is_some() {
+            program_model.stack_size = object.stack_size;
+        }
+        if let Some(key) = object.start {
+            if let Some((other, _, _)) = &start {
+                return Err(Error::msg(format!(
+                    "{} and {} both use .start, only one object may set the start",
+                    other, file
+                )));
+            }
+            let usage = object
+                .labels
+                .get(&key)
+                .and_then(|label| {
+                    label
+                        .usage
+                        .iter()
+                        .find(|usage| usage.original_line.trim_start().starts_with(".start"))
+                })
+                .map(|usage| Usage::new(usage.original_line.clone(), usage.line_num + line_offset))
+                .unwrap_or_else(|| Usage::new(format!(".start {}", key), line_offset));
+            start = Some((file.clone(), key, usage));
+        }
+        for (key, mut string) in object.strings {
+            define(&mut defined, &key, &file)?;
+            string.definition.line_num += line_offset;
+            string.usage.clear();
+            program_model.strings.insert(key, string);
+        }
+        for (key, mut data) in object.data {
+            define(&mut defined, &key, &file)?;
+            data.definition.line_num += line_offset;
+            data.usage.clear();
+            program_model.data.insert(key, data);
+        }
+        for (key, label) in object.labels {
+            if let Some(mut definition) = label.definition {
+                define(&mut defined, &key, &file)?;
+                definition.line_num += line_offset;
+                program_model
+                    .labels
+                    .insert(key.clone(), LabelModel::new(key, Some(definition), vec![]));
+            }
+        }
+        for mut op in object.ops {
+            op.line_num += line_offset;
+            ops.push((file.clone(), line_offset, op));
+        }
+        for mut function in object.functions {
+            function.definition.line_num += line_offset;
+            function.end_line = function.end_line.map(|line| line + line_offset);
+            program_model.functions.push(function);
+        }
+        line_offset += last_line + 1;
+    }
+
+    for (file, line_offset, mut op) in ops {
+        let line_num = op.line_num;
+        for param in op.params.iter_mut() {
+            //the object didn't know the key was a string so it was parsed as a label
+            if let Param::Label(key) = param {
+                if op.opcode == CPY_AREG_ADDR && program_model.strings.contains_key(key.as_str()) {
+                    *param = Param::StrKey(key.clone());
+                }
+            }
+            let usage = Usage::new(op.original_line.clone(), line_num);
+            let undefined = |kind: &str, key: &str| {
+                Error::msg(format!(
+                    "{} {} used in {} on line {} is never defined",
+                    kind,
+                    key,
+                    file,
+                    line_num - line_offset
+                ))
+            };
+            match param {
+                Param::Label(key) => match program_model.labels.get_mut(key.as_str()) {
+                    Some(label) => label.usage.push(usage),
+                    None => return Err(undefined("Label", key)),
+                },
+                Param::StrKey(key) => match program_model.strings.get_mut(key.as_str()) {
+                    Some(string) => string.usage.push(usage),
+                    None => return Err(undefined("String", key)),
+                },
+                Param::DataKey(key) => match program_model.data.get_mut(key.as_str()) {
+                    Some(data) => data.usage.push(usage),
+                    None => return Err(undefined("Data", key)),
+                },
+                _ => {}
+            }
+        }
+        program_model.ops.push(op);
+    }
+
+    if let Some((file, key, usage)) = start {
+        match program_model.labels.get_mut(key.as_str()) {
+            Some(label) => label.usage.push(usage),
+            None => {
+                return Err(Error::msg(format!(
+                    "Label {} used in .start in {} is never defined",
+                    key, file
+                )))
+            }
+        }
+        program_model.start = Some(key);
+    }
+
+    Ok(program_model)
+}
+
+fn define(defined: &mut HashMap<String, String>, key: &str, file: &str) -> Result<()> {
+    match defined.insert(key.to_string(), file.to_string()) {
+        Some(other) => Err(Error::msg(format!(
+            "{} is defined in both {} and {}",
+            key, other, file
+        ))),
+        None => Ok(()),
+    }
+}
+
+///Highest line number used by anything in the model
+fn last_line(program_model: &ProgramModel) -> usize {
+    let ops = program_model.ops.iter().map(|op| op.line_num);
+    let strings = program_model
+        .strings
+        .values()
+        .map(|string| string.definition.line_num);
+    let data = program_model
+        .data
+        .values()
+        .map(|data| data.definition.line_num);
+    let labels = program_model
+        .labels
+        .values()
+        .filter_map(|label| label.definition.as_ref().map(|def| def.line_num));
+    let reserved = program_model
+        .reserved
+        .values()
+        .map(|reserved| reserved.definition.line_num);
+    let functions = program_model
+        .functions
+        .iter()
+        .filter_map(|function| function.end_line);
+    ops.chain(strings)
+        .chain(data)
+        .chain(labels)
+        .chain(reserved)
+        .chain(functions)
+        .max()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::generator::generate_byte_code;
+    use crate::assembler::parser::generate_object_model;
+    use crate::constants::code::{CALL_ADDR, HALT, PRTS_STR, RET};
+    use crate::constants::hardware::REG_A0;
+    use crate::tape_reader::parse_tape;
+
+    fn object(file: &str, program: &str) -> (String, ProgramModel) {
+        let model =
+            generate_object_model(program.lines().map(|line| line.to_string()).collect()).unwrap();
+        assert!(model.check().is_empty());
+        let bytes = ObjectFile::new(model).to_bytes().unwrap();
+        (
+            file.to_string(),
+            ObjectFile::from_bytes(&bytes).unwrap().model,
+        )
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_link() {
+        let main = object("main.tobj", "Main\n1\n.ops\ncall greet\ncpy a0 hello\nhalt\n");
+        let lib = object("lib.tobj", "Lib\n1\n.strings\nhello=Hi\n.ops\ngreet:\nprts hello\nret\n");
+        let program_model = link(vec![main, lib]).unwrap();
+        assert_eq!(program_model.name, "Main");
+        assert!(program_model.check().is_empty());
+
+        let (bytes, _) = generate_byte_code(program_model).unwrap();
+        let tape = parse_tape(bytes).unwrap();
+        assert_eq!(tape.ops, vec![CALL_ADDR, 0, 8, CPY_AREG_ADDR, REG_A0, 0, 0, HALT, PRTS_STR, 0, 0, RET]);
+        assert_eq!(tape.entry, 0);
+    }
+
+    #[test]
+    fn test_link_start() {
+        let main = object("main.tobj", "Main\n1\n.ops\nhalt\n");
+        let lib = object("lib.tobj", "Lib\n1\n.start init\n.ops\ninit:\nnop\nhalt\n");
+        let program_model = link(vec![main, lib]).unwrap();
+        assert!(program_model.check().is_empty());
+        let (bytes, _) = generate_byte_code(program_model).unwrap();
+        assert_eq!(parse_tape(bytes).unwrap().entry, 1);
+
+        let main = object("main.tobj", "Main\n1\n.start main\n.ops\nmain:\nhalt\n");
+        let lib = object("lib.tobj", "Lib\n1\n.start init\n.ops\ninit:\nhalt\n");
+        assert_eq!(
+            link(vec![main, lib]).unwrap_err().to_string(),
+            "main.tobj and lib.tobj both use .start, only one object may set the start"
+        );
+
+        let main = object("main.tobj", "Main\n1\n.start main\n.ops\nhalt\n");
+        assert_eq!(
+            link(vec![main]).unwrap_err().to_string(),
+            "Label main used in .start in main.tobj is never defined"
+        );
+    }
+
+    #[test]
+    fn test_link_functions() {
+        let main = object("main.tobj", "Main\n1\n.ops\ncall print\nhalt\n");
+        let lib = object(
+            "lib.tobj",
+            "Lib\n1\n.ops\nfn print:\nprtc 'a'\nret\nendfn\n",
+        );
+        let program_model = link(vec![main, lib]).unwrap();
+        assert!(program_model.check().is_empty());
+        let (_, debug) = generate_byte_code(program_model).unwrap();
+        assert_eq!(debug.function_for_byte(3), None);
+        assert_eq!(debug.function_for_byte(4), Some("print"));
+        assert_eq!(debug.function_for_byte(6), Some("print"));
+    }
+
+    #[test]
+    fn test_link_errors() {
+        let main = object("main.tobj", "Main\n1\n.ops\ncall greet\nhalt\n");
+        let other = object("other.tobj", "Other\n1\n.ops\nnop\ngreet:\nret\n");
+        let lib = object("lib.tobj", "Lib\n1\n.ops\ngreet:\nret\n");
+        assert_eq!(
+            link(vec![main, other, lib]).unwrap_err().to_string(),
+            "greet is defined in both other.tobj and lib.tobj"
+        );
+
+        let main = object("main.tobj", "Main\n1\n.ops\nnop\nprts missing\n");
+        assert_eq!(
+            link(vec![main]).unwrap_err().to_string(),
+            "String missing used in main.tobj on line 5 is never defined"
+        );
+
+        let main = object("main.tobj", "Main\n1\n.reserve\nbuf 2\n.ops\nmemr @buf\n");
+        let lib = object("lib.tobj", "Lib\n1\n.reserve\nother 2\n.ops\nmemr @other\n");
+        assert_eq!(
+            link(vec![main, lib]).unwrap_err().to_string(),
+            "lib.tobj uses .reserve, only the first object may reserve memory"
+        );
+
+        assert!(link(vec![]).is_err());
+        assert!(ObjectFile::from_bytes(br#"{"object_version":0}"#).is_err());
+    }
+}
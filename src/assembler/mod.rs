@@ -1,23 +1,63 @@
+pub mod analysis;
 pub mod debug_model;
+pub mod diagnostic;
+pub mod formatter;
 mod generator;
+pub mod linker;
+pub mod optimizer;
 pub mod parser;
 pub mod program_model;
+pub mod size_report;
+pub mod text;
 
-use crate::assembler::generator::generate_byte_code;
-use crate::assembler::parser::generate_program_model;
+use crate::assembler::debug_model::DebugModel;
+use crate::assembler::diagnostic::{Diagnostic, ParseErrors, Severity};
+use crate::assembler::generator::{generate_byte_code, generate_data_tape};
+use crate::assembler::linker::{link, ObjectFile};
+use crate::assembler::parser::{generate_object_model, generate_program_model};
+use crate::assembler::program_model::{AddressReplacement, OpModel, ProgramModel};
+use crate::assembler::size_report::{check_size, size_report};
 use crate::common::{read_lines, reset_cursor};
-use crate::constants::code::{DIVDERS, KEYWORDS, MNEMONICS, REGISTERS};
+use crate::constants::code::{CPY_AREG_ADDR, DIVDERS, KEYWORDS, MNEMONICS, REGISTERS};
+use crate::constants::get_addr_byte_offset;
+use crate::diff::check_expected;
+use crate::language::parse_line;
 use anyhow::{Error, Result};
 use lazy_static::lazy_static;
 use std::fs::File;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-pub fn start(basm: &str, build_debug: bool, debug: bool) -> Result<()> {
+#[allow(clippy::too_many_arguments)]
+pub fn start(
+    basm: &str,
+    build_debug: bool,
+    debug: bool,
+    deny_warnings: bool,
+    optimize: bool,
+    max_size: Option<usize>,
+    expected_listing: Option<&str>,
+    data_tape: bool,
+    object: bool,
+    from_json: bool,
+) -> Result<()> {
     let path = PathBuf::from(basm);
+    if data_tape {
+        return start_data_tape(path);
+    }
+    if object {
+        return start_object(path);
+    }
 
     let (output_file_name, build_file_name, debug_file_name) =
         if let Some(output_file_stem) = path.file_stem() {
+            //program.build.json should make program.tape not program.build.tape
+            let output_file_stem = match from_json {
+                true => Path::new(output_file_stem)
+                    .file_stem()
+                    .unwrap_or(output_file_stem),
+                false => output_file_stem,
+            };
             (
                 format!("{}.tape", output_file_stem.to_string_lossy()),
                 format!("{}.build.json", output_file_stem.to_string_lossy()),
@@ -44,7 +84,30 @@ pub fn start(basm: &str, build_debug: bool, debug: bool) -> Result<()> {
         false => None,
     };
 
-    let bytes = assemble(read_lines(basm)?, build_file, debug_file)?;
+    let (bytes, debug_model) = if from_json {
+        build(
+            read_program_model(basm)?,
+            debug_file,
+            deny_warnings,
+            optimize,
+        )?
+    } else {
+        assemble(
+            read_lines(basm)?,
+            build_file,
+            debug_file,
+            deny_warnings,
+            optimize,
+        )
+        .map_err(|err| with_file(err, basm))?
+    };
+
+    if let Some(max_size) = max_size {
+        for line in size_report(&bytes, &debug_model)? {
+            println!("{}", line);
+        }
+        check_size(bytes.len(), max_size)?;
+    }
 
     let path = output_file_path.to_string_lossy().to_string();
     match File::create(output_file_path) {
@@ -61,6 +124,70 @@ pub fn start(basm: &str, build_debug: bool, debug: bool) -> Result<()> {
         }
     }
 
+    if let Some(expected_path) = expected_listing {
+        check_expected("Listing", expected_path, &debug_model.listing())?;
+    }
+
+    Ok(())
+}
+
+///Assemble the data section of `path` into a data tape, see [`crate::tape_reader::DataTape`]
+fn start_data_tape(path: PathBuf) -> Result<()> {
+    let file = path.to_string_lossy();
+    let program_model =
+        generate_program_model(read_lines(&file)?).map_err(|err| with_file(err, &file))?;
+    let bytes = generate_data_tape(program_model)?;
+    let output_path = path.with_extension("dtape");
+    std::fs::write(&output_path, bytes)?;
+    println!("Compiled and written to {}", output_path.to_string_lossy());
+    Ok(())
+}
+
+///Assemble `path` into a relocatable object (.tobj) for [`start_link`]
+fn start_object(path: PathBuf) -> Result<()> {
+    let program_model = generate_object_model(read_lines(&path.to_string_lossy())?)?;
+    program_model.validate(false)?;
+    let output_path = path.with_extension("tobj");
+    std::fs::write(&output_path, ObjectFile::new(program_model).to_bytes()?)?;
+    println!("Compiled and written to {}", output_path.to_string_lossy());
+    Ok(())
+}
+
+///Read a program model saved with `--save-intermediate` (or made by another tool)
+fn read_program_model(path: &str) -> Result<ProgramModel> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|err| Error::msg(format!("Unable to read {}: {}", path, err)))?;
+    let mut program_model: ProgramModel = serde_json::from_str(&json)
+        .map_err(|err| Error::msg(format!("Invalid program model in {}: {}", path, err)))?;
+    program_model.verify()?;
+    if program_model.build_time == 0 {
+        program_model.build_time = build_time();
+    }
+    Ok(program_model)
+}
+
+///Link objects made with `assemble --object` into a tape at `output`
+pub fn start_link(
+    objects: Vec<&str>,
+    output: &str,
+    deny_warnings: bool,
+    optimize: bool,
+) -> Result<()> {
+    let objects = objects
+        .into_iter()
+        .map(|path| {
+            let bytes = std::fs::read(path)
+                .map_err(|err| Error::msg(format!("Unable to read {}: {}", path, err)))?;
+            let object = ObjectFile::from_bytes(&bytes)
+                .map_err(|err| Error::msg(format!("{}: {}", path, err)))?;
+            Ok((path.to_string(), object.model))
+        })
+        .collect::<Result<Vec<(String, ProgramModel)>>>()?;
+    let mut program_model = link(objects)?;
+    program_model.build_time = build_time();
+    let (bytes, _) = build(program_model, None, deny_warnings, optimize)?;
+    std::fs::write(output, bytes)?;
+    println!("Linked and written to {}", output);
     Ok(())
 }
 
@@ -68,19 +195,174 @@ fn assemble(
     input: Vec<String>,
     build_file: Option<String>,
     debug_file: Option<String>,
-) -> Result<Vec<u8>> {
-    let program_model = generate_program_model(input)?;
+    deny_warnings: bool,
+    optimize: bool,
+) -> Result<(Vec<u8>, DebugModel)> {
+    let mut program_model = generate_program_model(input)?;
+    program_model.build_time = build_time();
     if let Some(path) = build_file {
         println!("Writing intermediate/interpretation stage to {}", path);
         std::fs::write(path, serde_json::to_string(&program_model)?)?;
     }
-    program_model.validate()?;
+    build(program_model, debug_file, deny_warnings, optimize)
+}
+
+///Validate, optimize and generate the tape for a parsed (or linked) program
+fn build(
+    mut program_model: ProgramModel,
+    debug_file: Option<String>,
+    deny_warnings: bool,
+    optimize: bool,
+) -> Result<(Vec<u8>, DebugModel)> {
+    program_model.validate(deny_warnings)?;
+    if optimize {
+        let report = optimizer::optimize(&mut program_model);
+        println!(
+            "Optimized: removed {} unreachable ops, shortened {} jumps, folded {} additions",
+            report.removed, report.jumps, report.folded
+        );
+    }
     let (bytes, debug) = generate_byte_code(program_model)?;
     if let Some(path) = debug_file {
         println!("Writing debug data to {}", path);
         std::fs::write(path, serde_json::to_string(&debug)?)?;
     }
 
+    Ok((bytes, debug))
+}
+
+#[derive(Debug)]
+pub struct AssembledTape {
+    pub bytes: Vec<u8>,
+    pub debug: DebugModel,
+    pub warnings: Vec<Diagnostic>,
+}
+
+///Assemble BASM source into tape bytes
+///
+///On failure all errors and warnings found are returned
+pub fn assemble_source(source: &str) -> std::result::Result<AssembledTape, Vec<Diagnostic>> {
+    let lines = source.lines().map(|line| line.to_string()).collect();
+    let mut program_model = generate_program_model(lines).map_err(|err| parse_diagnostics(&err))?;
+    program_model.build_time = build_time();
+    let diagnostics = program_model.check();
+    if diagnostics.iter().any(|diagnostic| diagnostic.is_error()) {
+        return Err(diagnostics);
+    }
+    let (bytes, debug) = generate_byte_code(program_model).map_err(|err| {
+        let mut output = diagnostics.clone();
+        output.push(Diagnostic::from(&err));
+        output
+    })?;
+    Ok(AssembledTape {
+        bytes,
+        debug,
+        warnings: diagnostics,
+    })
+}
+
+///Add `file` to the diagnostics if `err` is [`ParseErrors`], as [`start_check`] does
+fn with_file(mut err: Error, file: &str) -> Error {
+    if let Some(errors) = err.downcast_mut::<ParseErrors>() {
+        errors.set_file(file);
+    }
+    err
+}
+
+fn parse_diagnostics(err: &Error) -> Vec<Diagnostic> {
+    match err.downcast_ref::<ParseErrors>() {
+        Some(errors) => errors.diagnostics.clone(),
+        None => vec![Diagnostic::from(err)],
+    }
+}
+
+///Parse and validate BASM without generating a tape, returning every error and warning
+pub fn check_source(input: Vec<String>) -> Vec<Diagnostic> {
+    match generate_program_model(input) {
+        Ok(program_model) => program_model.check(),
+        Err(err) => parse_diagnostics(&err),
+    }
+}
+
+///Check the file at `basm` and print the diagnostics, one per line as text or JSON
+///
+///Fails if there are any errors (or warnings if `deny_warnings`)
+pub fn start_check(basm: &str, json: bool, deny_warnings: bool) -> Result<()> {
+    let mut diagnostics = check_source(read_lines(basm)?);
+    let mut errors = 0;
+    for diagnostic in diagnostics.iter_mut() {
+        diagnostic.file = Some(basm.to_owned());
+        if deny_warnings {
+            diagnostic.severity = Severity::Error;
+        }
+        if diagnostic.is_error() {
+            errors += 1;
+        }
+        if json {
+            println!("{}", serde_json::to_string(diagnostic)?);
+        } else {
+            println!("{}", diagnostic);
+        }
+    }
+    if errors > 0 {
+        Err(Error::msg(format!("{} errors found in {}", errors, basm)))
+    } else {
+        Ok(())
+    }
+}
+
+///Seconds since the UNIX epoch, `SOURCE_DATE_EPOCH` is used instead if set so builds can be reproduced
+fn build_time() -> u64 {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(now)
+}
+
+#[cfg(feature = "clock")]
+fn now() -> u64 {
+    chrono::Utc::now().timestamp().max(0) as u64
+}
+
+///No host clock so builds are always at the epoch
+#[cfg(not(feature = "clock"))]
+fn now() -> u64 {
+    0
+}
+
+///Assemble a single instruction, labels, strings and data are resolved using `debug`
+///
+///Constants are not supported as they aren't included in the debug data
+pub fn assemble_line(line: &str, debug: &DebugModel) -> Result<Vec<u8>> {
+    let line = line.split('#').next().unwrap_or_default().trim();
+    if line.is_empty() {
+        return Err(Error::msg("No instruction"));
+    }
+    let (opcode, params) = parse_line(line)?;
+    let model = OpModel::new(opcode, params, line.to_owned(), line.to_owned(), 0);
+    let (mut bytes, replacement) = model.to_bytes();
+    let addr = match replacement {
+        AddressReplacement::None => return Ok(bytes),
+        AddressReplacement::Label(key) => debug
+            .label_addr(&key)
+            .or_else(|| {
+                if opcode == CPY_AREG_ADDR {
+                    debug.string_addr(&key)
+                } else {
+                    None
+                }
+            })
+            .ok_or_else(|| Error::msg(format!("Unknown label: {}", key)))?,
+        AddressReplacement::Str(key) => debug
+            .string_addr(&key)
+            .ok_or_else(|| Error::msg(format!("Unknown string: {}", key)))?,
+        AddressReplacement::Data(key) => debug
+            .data_addr(&key)
+            .ok_or_else(|| Error::msg(format!("Unknown data: {}", key)))?,
+    };
+    let offset = get_addr_byte_offset(opcode)
+        .ok_or_else(|| Error::msg(format!("Assembler error: no address offset for {}", line)))?;
+    bytes[offset..offset + 2].copy_from_slice(&addr.to_be_bytes());
     Ok(bytes)
 }
 
@@ -97,6 +379,8 @@ const FORMAT_ERROR: &str = r#"Invalid BASM file, expected format:
 <strings>]
 [.data
 <datas>]
+[.reserve
+<reserved memory>]
 .ops
 <program>
 
@@ -104,7 +388,7 @@ Program name must be between 1 and 20 ASCII characters and be the first line
 Program version must between 1 and 10 ASCII characters and be the second line
 
 Blank lines are ok from the third line onwards
-Case matters for section dividers (.strings, .data and .ops)
+Case matters for section dividers (.strings, .data, .reserve and .ops)
 
 Strings and data take this format:
 <key>=<value>
@@ -112,18 +396,384 @@ e.g.
 greeting=Hello World!
 numbers=[[10,20],[xF,x10]]
 
+Reserved memory takes this format:
+<key> <size>
+e.g.
+buffer 64
+
 See language document for ops
 "#;
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::assembler::diagnostic::*;
+    use crate::common::lines;
     use crate::constants::code::{
-        ADD_REG_REG, ARG_REG_VAL, CALL_ADDR, CMP_REG_REG, CPY_REG_AREG, CPY_REG_VAL, HALT, JE_ADDR,
-        LD_AREG_DATA_VAL_VAL, PRTC_VAL, PRTLN, PRTS_STR, PRT_REG, PUSH_REG, RET,
+        ADD_REG_REG, ARG_REG_VAL, ATOI_AREG_REG, CALL_ADDR, CMP_REG_REG, CPY_AREG_ADDR,
+        CPY_REG_AREG, CPY_REG_VAL, HALT, INC_REG, ITOA_AREG_REG, ITOA_AREG_VAL, JE_ADDR, JMP_ADDR,
+        LDI_AREG_VAL, LD_AREG_DATA_VAL_VAL, MEMW_AREG_REG, PRTC_VAL, PRTLN, PRTS_AREG, PRTS_STR,
+        PRT_REG, PUSH_REG, RET, STRLD_AREG_DATA_REG, STRLD_AREG_STR,
     };
-    use crate::constants::hardware::{REG_A0, REG_ACC, REG_D0, REG_D1, REG_D2};
+    use crate::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1, REG_D2};
     use crate::constants::system::*;
+    use crate::decompiler::decompile;
+    use crate::tape_reader::{link_data, parse_data_tape, parse_tape};
+
+    ///Tape bytes after the checksum, as the header includes the build time
+    fn body(bytes: &[u8]) -> Vec<u8> {
+        let name_end = 4 + bytes[3] as usize;
+        let version_end = name_end + 1 + bytes[name_end] as usize;
+        let description_end = version_end + 1 + bytes[version_end] as usize;
+        bytes[description_end + 14..].to_vec()
+    }
+
+    fn assert_round_trip(name: &str, program: &str) {
+        let (bytes, _) = assemble(lines(program), None, None, false, false).unwrap();
+        let basm = decompile(parse_tape(bytes.clone()).unwrap());
+        let (reassembled, _) =
+            assemble(basm.clone(), None, None, false, false).unwrap_or_else(|err| {
+                panic!(
+                    "{} failed to reassemble: {:?}\n{}",
+                    name,
+                    err,
+                    basm.join("\n")
+                )
+            });
+        assert_eq!(body(&bytes), body(&reassembled), "{}", name);
+    }
+
+    #[test]
+    fn test_check_source() {
+        assert!(check_source(lines("Test\n1\n.ops\nhalt\n")).is_empty());
+        let diagnostics = check_source(lines("Test\n1\n.ops\nfoo d0\njmp missing\n"));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            serde_json::to_string(&diagnostics[0]).unwrap(),
+            r#"{"file":null,"line":4,"column":1,"end_column":4,"severity":"error","code":"invalid_op","message":"Unable to parse foo d0, instruction not recognised"}"#
+        );
+        let diagnostics = check_source(lines("Test\n1\n.strings\nhi=Hi\n.ops\nhalt\n"));
+        assert_eq!(diagnostics[0].code, UNUSED_STRING);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        let diagnostics = check_source(lines(
+            "Test\n1\n.data\nnums=[[1,-2]]\n.ops\nld a0 nums 0 0\ncpy d0 -1\n",
+        ));
+        let wrapped: Vec<(usize, usize, &str)> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.line,
+                    diagnostic.column,
+                    diagnostic.message.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            wrapped,
+            vec![
+                (4, 1, "-2 on line 4 is stored as 254"),
+                (7, 8, "-1 on line 7 is stored as 255")
+            ]
+        );
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_assemble_line() {
+        let program = "Test\n1\n.strings\nhi=Hello\na=x\n.ops\nnop\nstart:\nprts hi\njmp start\n";
+        let (_, debug) = assemble(lines(program), None, None, false, false).unwrap();
+
+        assert_eq!(assemble_line("cpy d0 10", &debug).unwrap(), vec![CPY_REG_VAL, REG_D0, 10]);
+        assert_eq!(assemble_line(" jmp start # loop", &debug).unwrap(), vec![JMP_ADDR, 0, 1]);
+        assert_eq!(assemble_line("prts hi", &debug).unwrap(), vec![PRTS_STR, 0, 2]);
+        assert_eq!(assemble_line("cpy a0 hi", &debug).unwrap(), vec![CPY_AREG_ADDR, REG_A0, 0, 2]);
+        assert_eq!(assemble_line("prts a0", &debug).unwrap(), vec![PRTS_AREG, REG_A0]);
+        assert_eq!(assemble_line("memw A1+d2", &debug).unwrap(), vec![MEMW_AREG_REG, REG_A1, REG_D2]);
+        assert!(assemble_line("memw a1+a0", &debug).is_err());
+        assert!(assemble_line("jmp missing", &debug).is_err());
+        assert!(assemble_line("cpy d0", &debug).is_err());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_string_address() {
+        let program = "Test\n1\n.strings\nhi=Hello\na=x\n.ops\ncpy a0 hi\nprts a0\n";
+        let (bytes, _) = assemble(lines(program), None, None, false, false).unwrap();
+        let tape = parse_tape(bytes).unwrap();
+
+        assert_eq!(tape.ops, vec![CPY_AREG_ADDR, REG_A0, 0, 2, PRTS_AREG, REG_A0]);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_strld() {
+        let program = "Test\n1\n.strings\nhi=Hello\nbye=Bye\n.data\nnums=[[1,2]]\n.ops\nstrld a0 bye\nstrld a1 nums d0\n";
+        let (bytes, _) = assemble(lines(program), None, None, false, false).unwrap();
+        let tape = parse_tape(bytes).unwrap();
+
+        assert_eq!(tape.ops, vec![STRLD_AREG_STR, REG_A0, 0, 0, STRLD_AREG_DATA_REG, REG_A1, 0, 0, REG_D0]);
+        let decompiled = decompile(tape).join("\n");
+        assert!(decompiled.contains("# used at 0000 (STRLD)\nstr_0000=\"Bye\""), "{}", decompiled);
+        assert!(decompiled.contains("# used at 0004 (STRLD)\ndata_0000"), "{}", decompiled);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_decimal_conversion() {
+        let program = "Test\n1\n.ops\nitoa a0 d1\nitoa a0 a1\nitoa a1 250\natoi a0 acc\natoi a1 a0\n";
+        let (bytes, _) = assemble(lines(program), None, None, false, false).unwrap();
+        let tape = parse_tape(bytes).unwrap();
+
+        assert_eq!(tape.ops, vec![ITOA_AREG_REG, REG_A0, REG_D1, ITOA_AREG_REG, REG_A0, REG_A1, ITOA_AREG_VAL, REG_A1, 250, ATOI_AREG_REG, REG_A0, REG_ACC, ATOI_AREG_REG, REG_A1, REG_A0]);
+        let decompiled: Vec<String> = decompile(tape).iter().skip(4).map(|line| line.split('#').next().unwrap().trim().to_owned()).collect();
+        assert_eq!(decompiled, vec!["ITOA A0 D1", "ITOA A0 A1", "ITOA A1 250", "ATOI A0 ACC", "ATOI A1 A0"]);
+        assert!(assemble(lines("Test\n1\n.ops\natoi a0 5\n"), None, None, false, false).is_err());
+        assert!(assemble(lines("Test\n1\n.ops\nitoa d0 d1\n"), None, None, false, false).is_err());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_ldi() {
+        let program = "Test\n1\n.ops\nconst big 1000\nldi a0 big\nldi a1 xFFFF\nldi a0 7\n";
+        let (bytes, _) = assemble(lines(program), None, None, false, false).unwrap();
+        let tape = parse_tape(bytes).unwrap();
+
+        assert_eq!(tape.ops, vec![LDI_AREG_VAL, REG_A0, 3, 232, LDI_AREG_VAL, REG_A1, 255, 255, LDI_AREG_VAL, REG_A0, 0, 7]);
+        let decompiled: Vec<String> = decompile(tape).iter().skip(4).map(|line| line.split('#').next().unwrap().trim().to_owned()).collect();
+        assert_eq!(decompiled, vec!["LDI A0 1000", "LDI A1 65535", "LDI A0 7"]);
+        assert!(assemble(lines("Test\n1\n.ops\nldi a0 65536\n"), None, None, false, false).is_err());
+        assert!(assemble(lines("Test\n1\n.ops\nldi d0 1\n"), None, None, false, false).is_err());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_optimize() {
+        let program = "Test\n1\n.ops\ncpy acc 1\nadd acc 2\ninc d0\njmp skip\nprtln\nskip:\njmp end\nend:\nprt acc\n";
+        let (bytes, debug) = assemble(lines(program), None, None, false, true).unwrap();
+        let tape = parse_tape(bytes).unwrap();
+
+        assert_eq!(tape.ops, vec![CPY_REG_VAL, REG_ACC, 3, INC_REG, REG_D0, JMP_ADDR, 0, 11, JMP_ADDR, 0, 11, PRT_REG, REG_ACC]);
+        let ops: Vec<(u16, usize)> = debug.ops.iter().map(|op| (op.byte_addr, op.line_num)).collect();
+        assert_eq!(ops, vec![(0, 4), (3, 6), (5, 7), (8, 10), (11, 12)]);
+        assert_eq!(debug.ops[2].bytes, vec![JMP_ADDR, 0, 11]);
+    }
+
+    #[test]
+    fn test_from_json() {
+        let program = "Test\n1\n.strings\nhi=Hello\n.data\nnums=[[1]]\n.ops\nstart:\nprts hi\nld a0 nums 0 0\njmp start\n";
+        let json = serde_json::to_string(&generate_program_model(lines(program)).unwrap()).unwrap();
+        let program_model: ProgramModel = serde_json::from_str(&json).unwrap();
+        program_model.verify().unwrap();
+        let (bytes, _) = build(program_model, None, false, false).unwrap();
+        let (expected, _) = assemble(lines(program), None, None, false, false).unwrap();
+        assert_eq!(body(&bytes), body(&expected));
+    }
+
+    #[test]
+    fn test_assemble_source() {
+        let tape = assemble_source("Test\n1\n.strings\nunused=a\n.ops\ncpy d0 1\n").unwrap();
+        assert_eq!(
+            body(&tape.bytes),
+            body(
+                &assemble(
+                    lines("Test\n1\n.strings\nunused=a\n.ops\ncpy d0 1\n"),
+                    None,
+                    None,
+                    false,
+                    false
+                )
+                .unwrap()
+                .0
+            )
+        );
+        assert_eq!(
+            tape.warnings,
+            vec![Diagnostic::warning(
+                UNUSED_STRING,
+                4,
+                1,
+                String::from("String unused is never used")
+            )
+            .with_span((1, 7))]
+        );
+    }
+
+    #[test]
+    fn test_assemble_source_errors() {
+        let errors = assemble_source("Test\n1\n.ops\ncpy d0 1\n  add d0\n").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 5);
+        assert_eq!(errors[0].column, 3);
+        assert_eq!(errors[0].code, INVALID_OP);
+        assert_eq!(errors[0].severity, Severity::Error);
+
+        let errors = assemble_source("Test\n1\n.ops\njmp nowhere\n").unwrap_err();
+        assert_eq!(errors[0].line, 4);
+        assert_eq!(errors[0].code, UNDEFINED_LABEL);
+
+        let errors = assemble_source("Test\n1\n.data\nnums=[[1,2]\n.ops\nnop\n").unwrap_err();
+        assert_eq!(errors[0].line, 4);
+        assert_eq!(errors[0].code, INVALID_DATA);
+
+        let errors = assemble_source("Test\n").unwrap_err();
+        assert_eq!(errors[0].code, INVALID_HEADER);
+    }
+
+    #[test]
+    fn test_multi_byte_text() {
+        let tape = assemble_source("Tést\n1\n.strings\nhi=héllo\n.ops\nprts hi\n").unwrap();
+        let tape = parse_tape(tape.bytes).unwrap();
+        assert_eq!(tape.name, "Tést");
+        assert_eq!(tape.strings[0], 6);
+        assert_eq!(&tape.strings[1..], "héllo".as_bytes());
+
+        let errors = assemble_source("Test\n1\n.ops\ncpy d0 'é'\n").unwrap_err();
+        assert_eq!(errors[0].code, INVALID_OP);
+        let errors = assemble_source("Test\n1\n.data\nbad=[['é']]\n.ops\nnop\n").unwrap_err();
+        assert_eq!(errors[0].code, INVALID_DATA);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_multiple_errors() {
+        let errors = assemble_source("Test\n1\n.strings\nbad\n.ops\ncpy d9 1\nfoo d0\nprts nope\n.ops\nadd d0\n").unwrap_err();
+        let found: Vec<(usize, usize, usize, &str)> = errors.iter().map(|err| (err.line, err.column, err.end_column, err.code)).collect();
+        assert_eq!(found, vec![
+            (4, 1, 4, INVALID_STRING),
+            (6, 5, 7, INVALID_OP),
+            (7, 1, 4, INVALID_OP),
+            (8, 6, 10, INVALID_OP),
+            (9, 1, 5, UNEXPECTED_DIVIDER),
+            (10, 1, 4, INVALID_OP),
+        ]);
+
+        let err = generate_program_model(lines("Test\n1\n.ops\n  cpy d0 300\n")).unwrap_err();
+        assert!(err.to_string().starts_with("error[invalid_op]: parsing line 'cpy d0 300'\n --> <source>:4:10\n  |\n4 |   cpy d0 300\n  |          ^^^\n  = CPY supports:\n"));
+        let err = with_file(err, "prog.basm");
+        assert!(err.to_string().contains(" --> prog.basm:4:10\n"));
+        assert_eq!(err.downcast_ref::<ParseErrors>().unwrap().diagnostics[0].file.as_deref(), Some("prog.basm"));
+
+        let errors = assemble_source("Test\n1\n.ops\nstart: cpy d0 300 # comment\nconst max 300\ncpy d1 max\n").unwrap_err();
+        let found: Vec<(usize, usize, usize)> = errors.iter().map(|err| (err.line, err.column, err.end_column)).collect();
+        assert_eq!(found, vec![(4, 15, 18), (6, 8, 11)]);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_start() {
+        let program = "Test\n1\n.start main\n.ops\nsetup:\ncpy d0 1\nret\nmain:\ncall setup\nhalt\n";
+        assert_round_trip("start", program);
+        let (bytes, _) = assemble(lines(program), None, None, false, false).unwrap();
+        let tape = parse_tape(bytes).unwrap();
+        assert_eq!(tape.entry, 4);
+        assert!(decompile(tape).contains(&String::from(".start lbl_0004")));
+
+        let tape = parse_tape(assemble(lines("Test\n1\n.ops\nhalt\n"), None, None, false, false).unwrap().0).unwrap();
+        assert_eq!(tape.entry, 0);
+
+        let errors = assemble_source("Test\n1\n.start main\n.ops\nhalt\n").unwrap_err();
+        assert_eq!((errors[0].line, errors[0].code), (3, UNDEFINED_LABEL));
+        let errors = assemble_source("Test\n1\n.start main\n.ops\n.start main\nmain:\nhalt\n").unwrap_err();
+        assert_eq!((errors[0].line, errors[0].code), (5, INVALID_HEADER));
+        assert!(assemble_source("Test\n1\n.start\n.ops\nhalt\n").is_err());
+        assert!(assemble_source("Test\n1\n.start main\n.ops\nhalt\nmain:\n").is_err());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_repeat() {
+        let program = "Test\n1\n.ops\n.repeat 3 i\nprtc 'i'\ncpy d0 i\n.endrepeat\nhalt\n";
+        assert_round_trip("repeat", program);
+        let tape = parse_tape(assemble(lines(program), None, None, false, false).unwrap().0).unwrap();
+        assert_eq!(tape.ops, vec![
+            PRTC_VAL, b'i', CPY_REG_VAL, REG_D0, 0,
+            PRTC_VAL, b'i', CPY_REG_VAL, REG_D0, 1,
+            PRTC_VAL, b'i', CPY_REG_VAL, REG_D0, 2,
+            HALT,
+        ]);
+
+        let errors = assemble_source("Test\n1\n.ops\n.repeat 2 i\ncpy d0 i\nfoo\n.endrepeat\n").unwrap_err();
+        assert_eq!((errors[0].line, errors[0].code), (6, INVALID_OP));
+        let errors = assemble_source("Test\n1\n.ops\nnop\n.repeat 2 i\nnop\n").unwrap_err();
+        assert_eq!((errors[0].line, errors[0].code), (5, INVALID_REPEAT));
+        let errors = assemble_source("Test\n1\n.ops\n.repeat 2\nlbl: nop\n.endrepeat\n").unwrap_err();
+        assert_eq!((errors[0].line, errors[0].code), (5, INVALID_OP));
+    }
+
+    #[test]
+    fn test_functions() {
+        let tape =
+            assemble_source("Test\n1\n.ops\ncall print\nhalt\nfn print:\nprtc 'a'\nret\nendfn\n")
+                .unwrap();
+        assert!(tape.warnings.is_empty());
+        assert_eq!(tape.debug.function_for_byte(3), None);
+        assert_eq!(tape.debug.function_for_byte(4), Some("print"));
+        assert_eq!(tape.debug.function_for_byte(6), Some("print"));
+        assert_eq!(tape.debug.function_for_byte(7), None);
+
+        let tape =
+            assemble_source("Test\n1\n.ops\ncall print\nfn print:\nretval 1\nendfn\n").unwrap();
+        assert_eq!(
+            (tape.warnings[0].line, tape.warnings[0].code),
+            (5, FUNCTION_FALLTHROUGH)
+        );
+
+        let errors =
+            assemble_source("Test\n1\n.ops\nfn print:\nprtc 'a'\nendfn\nhalt\n").unwrap_err();
+        assert_eq!((errors[0].line, errors[0].code), (6, INVALID_FUNCTION));
+        let errors = assemble_source("Test\n1\n.ops\nfn print:\nret\n").unwrap_err();
+        assert_eq!((errors[0].line, errors[0].code), (4, INVALID_FUNCTION));
+        let errors = assemble_source("Test\n1\n.ops\nfn a:\nfn b:\nret\nendfn\n").unwrap_err();
+        assert_eq!((errors[0].line, errors[0].code), (5, INVALID_FUNCTION));
+        let errors = assemble_source("Test\n1\n.ops\nhalt\nendfn\n").unwrap_err();
+        assert_eq!((errors[0].line, errors[0].code), (5, INVALID_FUNCTION));
+        assert!(assemble_source("Test\n1\n.ops\nfn print\nret\nendfn\n").is_err());
+        assert!(assemble_source("Test\n1\n.ops\nfn:\nret\nendfn\n").is_err());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_external_data() {
+        let program = "Test\n1\n.data\ntable=[[1]]\nassets=extern\n.ops\nld a0 assets 0 1\nld a0 table 0 0\n";
+        assert_round_trip("external", program);
+        let (bytes, _) = assemble(lines(program), None, None, false, false).unwrap();
+        let mut tape = parse_tape(bytes).unwrap();
+        assert_eq!(tape.ops, vec![LD_AREG_DATA_VAL_VAL, REG_A0, 0, 0, 0, 1, LD_AREG_DATA_VAL_VAL, REG_A0, 0, 0, 0, 0]);
+
+        let data_model = generate_program_model(lines("Assets\n1\n.data\nassets=[[4,5]]\n")).unwrap();
+        let data_tape = parse_data_tape(generate_data_tape(data_model).unwrap()).unwrap();
+        link_data(&mut tape, vec![data_tape]).unwrap();
+        assert_eq!(tape.ops, vec![LD_AREG_DATA_VAL_VAL, REG_A0, 0, 4, 0, 1, LD_AREG_DATA_VAL_VAL, REG_A0, 0, 0, 0, 0]);
+        assert_eq!(tape.data, vec![1, 0, 1, 1, 1, 0, 2, 4, 5]);
+
+        let data_model = generate_program_model(lines("Assets\n1\n.data\nassets=extern\n")).unwrap();
+        assert!(generate_data_tape(data_model).is_err());
+    }
+
+    #[test]
+    fn test_deny_warnings() {
+        let program = "Test\n1\n.ops\nconst unused d0\nloop: nop\n";
+        assert!(assemble(lines(program), None, None, false, false).is_ok());
+        let err = assemble(lines(program), None, None, true, false).unwrap_err();
+        assert!(err.to_string().contains("Constant unused is never used"));
+        assert!(err.to_string().contains("Label loop is never used"));
+    }
+
+    #[test]
+    fn test_decompile_round_trip() {
+        assert_round_trip("fizzbuzz", include_str!("../../examples/fizzbuzz.basm"));
+        assert_round_trip("hangman", include_str!("../../examples/hangman.basm"));
+        assert_round_trip(
+            "stack_example",
+            include_str!("../../examples/stack_example.basm"),
+        );
+        assert_round_trip("tape_gen", include_str!("../../examples/tape_gen.basm"));
+        assert_round_trip("trivia", include_str!("../../examples/trivia.basm"));
+        assert_round_trip(
+            "hello_world_examples",
+            include_str!("../../examples/hello_world_examples.basm"),
+        );
+    }
 
     #[test]
     #[rustfmt::skip]
@@ -136,17 +786,22 @@ mod test {
             "CPY D2 xF",
             "ADD D0 D2",
         ].iter().map(|str| str.to_string()).collect();
-        let bytes = assemble(program, None, None).unwrap();
+        let (bytes, _) = assemble(program, None, None, false, false).unwrap();
         
-        assert_eq!(bytes,
-           vec![
+        assert_eq!(bytes[..18],
+           [
             TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
             9, 84, 101, 115, 116, 32, 80, 114, 111, 103,
             3, 49, 46, 48,
+            0,
+        ]);
+        assert_eq!(body(&bytes),
+           vec![
             0, 9,
             CPY_REG_VAL, REG_D0, 10,
             CPY_REG_VAL, REG_D2, 15,
             ADD_REG_REG, REG_D0, REG_D2,
+            0, 0,
             0, 0
         ]);
     }
@@ -159,12 +814,15 @@ mod test {
             .map(|s| s.to_owned())
             .collect::<Vec<String>>();
         
-        let bytes  = assemble(program, None, None).unwrap();
+        let (bytes, _)  = assemble(program, None, None, false, false).unwrap();
         
-        assert_eq!(bytes, vec![
+        assert_eq!(bytes[..16], [
             TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
             9, 77, 97, 116, 104, 32, 84, 101, 115, 116,
             1, 49,
+            0,
+        ]);
+        assert_eq!(body(&bytes), vec![
             0, 65,
             LD_AREG_DATA_VAL_VAL, REG_A0, 0, 0, 1, 0,
             CPY_REG_AREG, REG_D0, REG_A0,
@@ -194,7 +852,8 @@ mod test {
             4, 70, 65, 73, 76,
             4, 80, 65, 83, 83,
             1, 43,
-            1, 2, 1, 2
+            0, 0,
+            1, 0, 2, 1, 2
         ]);
     }
 }
@@ -0,0 +1,224 @@
+use crate::assembler::program_model::{OpModel, ProgramModel};
+use crate::constants::code::{
+    ADD_REG_AREG, ADD_REG_REG, ADD_REG_VAL, CPY_REG_VAL, DEC_REG, EXIT_REG, EXIT_VAL, HALT,
+    INC_REG, JMP_ADDR, JMP_AREG, SUB_REG_AREG, SUB_REG_REG, SUB_REG_VAL,
+};
+use crate::constants::hardware::Register;
+use crate::constants::is_jump_op;
+use crate::language::parser::params::Param;
+use std::collections::HashSet;
+
+///Number of changes made by [`optimize`]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct OptimizeReport {
+    ///Ops after HALT, EXIT or JMP that can't be reached
+    pub removed: usize,
+    ///Jumps to a label that immediately jumps elsewhere
+    pub jumps: usize,
+    ///CPY ACC followed by ADD ACC replaced with a single CPY
+    pub folded: usize,
+}
+
+///Rewrite ops to be smaller/faster without changing behaviour
+///
+///Ops keep their original line numbers so the debug model still points at the source
+pub fn optimize(program_model: &mut ProgramModel) -> OptimizeReport {
+    let jumps = collapse_jump_chains(program_model);
+    let removed = remove_unreachable(program_model);
+    let folded = fold_additions(program_model);
+    OptimizeReport {
+        removed,
+        jumps,
+        folded,
+    }
+}
+
+///For each op whether a label points at it, labels point at the first op after their definition
+fn label_starts(program_model: &ProgramModel) -> Vec<bool> {
+    let lines: Vec<usize> = program_model
+        .labels
+        .values()
+        .filter_map(|label| label.definition.as_ref().map(|def| def.line_num))
+        .collect();
+    let mut prev_line = 0;
+    program_model
+        .ops
+        .iter()
+        .enumerate()
+        .map(|(idx, op)| {
+            let starts = lines
+                .iter()
+                .any(|line| *line <= op.line_num && (idx == 0 || *line > prev_line));
+            prev_line = op.line_num;
+            starts
+        })
+        .collect()
+}
+
+fn collapse_jump_chains(program_model: &mut ProgramModel) -> usize {
+    let targets = program_model.label_targets();
+    let ops = &program_model.ops;
+    let final_label = |key: &String| {
+        let mut key = key.clone();
+        let mut seen = HashSet::new();
+        seen.insert(key.clone());
+        while let Some(op) = targets.get(&key).map(|idx| &ops[*idx]) {
+            match (op.opcode, op.params.as_slice()) {
+                (JMP_ADDR, [Param::Label(next)]) if seen.insert(next.clone()) => key = next.clone(),
+                _ => break,
+            }
+        }
+        key
+    };
+    let mut changes = vec![];
+    for (idx, op) in ops.iter().enumerate() {
+        if !is_jump_op(op.opcode) {
+            continue;
+        }
+        for (param_idx, param) in op.params.iter().enumerate() {
+            if let Param::Label(key) = param {
+                let target = final_label(key);
+                if &target != key {
+                    changes.push((idx, param_idx, target));
+                }
+            }
+        }
+    }
+    let count = changes.len();
+    for (idx, param_idx, target) in changes {
+        program_model.ops[idx].params[param_idx] = Param::Label(target);
+    }
+    count
+}
+
+fn remove_unreachable(program_model: &mut ProgramModel) -> usize {
+    let starts = label_starts(program_model);
+    let mut reachable = true;
+    let mut keep = Vec::with_capacity(program_model.ops.len());
+    for (idx, op) in program_model.ops.iter().enumerate() {
+        if starts[idx] {
+            reachable = true;
+        }
+        keep.push(reachable);
+        if matches!(op.opcode, HALT | EXIT_REG | EXIT_VAL | JMP_ADDR | JMP_AREG) {
+            reachable = false;
+        }
+    }
+    let before = program_model.ops.len();
+    let mut keep = keep.into_iter();
+    program_model.ops.retain(|_| keep.next().unwrap_or(true));
+    before - program_model.ops.len()
+}
+
+///Folds `CPY ACC a` + `ADD ACC b` into `CPY ACC a+b`
+///
+///ADD also sets the overflow flag, so this is only done if the sum doesn't overflow and
+///the flag is overwritten (or the program ends) before any jump
+fn fold_additions(program_model: &mut ProgramModel) -> usize {
+    let mut starts = label_starts(program_model);
+    let mut count = 0;
+    let mut idx = 0;
+    while idx + 1 < program_model.ops.len() {
+        let ops = &program_model.ops;
+        let sum = match (
+            ops[idx].opcode,
+            ops[idx].params.as_slice(),
+            ops[idx + 1].opcode,
+            ops[idx + 1].params.as_slice(),
+        ) {
+            (
+                CPY_REG_VAL,
+                [Param::DataReg(Register::Acc), Param::Number(lhs)],
+                ADD_REG_VAL,
+                [Param::DataReg(Register::Acc), Param::Number(rhs)],
+            ) if !starts[idx + 1] && overflow_unused(&ops[idx + 2..]) => lhs.checked_add(*rhs),
+            _ => None,
+        };
+        if let Some(sum) = sum {
+            let cpy = &program_model.ops[idx];
+            program_model.ops[idx] = OpModel::new(
+                CPY_REG_VAL,
+                vec![Param::DataReg(Register::Acc), Param::Number(sum)],
+                format!("cpy acc {}", sum),
+                cpy.original_line.clone(),
+                cpy.line_num,
+            );
+            program_model.ops.remove(idx + 1);
+            starts.remove(idx + 1);
+            count += 1;
+        }
+        idx += 1;
+    }
+    count
+}
+
+///True if the overflow flag is overwritten or the program ends before any jump
+fn overflow_unused(ops: &[OpModel]) -> bool {
+    for op in ops {
+        match op.opcode {
+            ADD_REG_REG | ADD_REG_VAL | ADD_REG_AREG | SUB_REG_REG | SUB_REG_VAL | SUB_REG_AREG
+            | INC_REG | DEC_REG | HALT | EXIT_REG | EXIT_VAL => return true,
+            opcode if is_jump_op(opcode) => return false,
+            _ => {}
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::parser::generate_program_model;
+
+    fn model(program: &str) -> ProgramModel {
+        generate_program_model(program.lines().map(|line| line.to_string()).collect()).unwrap()
+    }
+
+    fn lines(program_model: &ProgramModel) -> Vec<String> {
+        program_model
+            .ops
+            .iter()
+            .map(|op| op.after_processing.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_remove_unreachable() {
+        let mut program_model = model("Test\n1\n.ops\njmp end\nprtln\nnop\nend:\nhalt\nprtln\n");
+        let report = optimize(&mut program_model);
+        assert_eq!(report.removed, 3);
+        assert_eq!(lines(&program_model), vec!["jmp end", "halt"]);
+        assert_eq!(program_model.ops[1].line_num, 8);
+    }
+
+    #[test]
+    fn test_collapse_jump_chains() {
+        let mut program_model =
+            model("Test\n1\n.ops\nje first\nhalt\nfirst:\njmp second\nsecond:\njmp third\nthird:\nprtln\n");
+        let report = optimize(&mut program_model);
+        assert_eq!(report.jumps, 2);
+        assert_eq!(
+            program_model.ops[0].params,
+            vec![Param::Label(String::from("third"))]
+        );
+
+        let mut program_model = model("Test\n1\n.ops\nloop:\njmp loop\n");
+        assert_eq!(optimize(&mut program_model).jumps, 0);
+    }
+
+    #[test]
+    fn test_fold_additions() {
+        let mut program_model =
+            model("Test\n1\n.ops\ncpy acc 2\nadd acc 3\nprt acc\ncpy acc 250\nadd acc 10\nhalt\n");
+        let report = optimize(&mut program_model);
+        assert_eq!(report.folded, 1);
+        assert_eq!(
+            lines(&program_model),
+            vec!["cpy acc 5", "prt acc", "cpy acc 250", "add acc 10", "halt"]
+        );
+
+        let mut program_model =
+            model("Test\n1\n.ops\ncpy acc 2\nadd acc 3\nover end\nend:\nhalt\n");
+        assert_eq!(optimize(&mut program_model).folded, 0);
+    }
+}
@@ -1,5 +1,8 @@
+use crate::assembler::parser::parse_size;
 use crate::constants::hardware::{MAX_DATA_ARRAY_COUNT, MAX_DATA_ARRAY_LEN};
+use crate::language::parser::params::{is_wrapped_literal, parse_byte_literal};
 use anyhow::{Context, Error, Result};
+use std::collections::HashMap;
 
 #[derive(Debug)]
 pub struct DataParser {
@@ -9,6 +12,10 @@ pub struct DataParser {
     container_mode: ContainerMode,
     value_mode: ValueMode,
     escaping: bool,
+    wrapped: Vec<String>,
+    ///Constant keys and values that can be used in arrays
+    constants: HashMap<String, String>,
+    used_constants: Vec<String>,
 }
 
 impl DataParser {
@@ -20,8 +27,16 @@ impl DataParser {
             container_mode: ContainerMode::None,
             value_mode: ValueMode::None,
             escaping: false,
+            wrapped: vec![],
+            constants: HashMap::new(),
+            used_constants: vec![],
         }
     }
+
+    pub fn with_constants(mut self, constants: HashMap<String, String>) -> Self {
+        self.constants = constants;
+        self
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -38,6 +53,7 @@ enum ValueMode {
     Hex,
     Char,
     Binary,
+    Octal,
 }
 
 impl DataParser {
@@ -56,7 +72,7 @@ impl DataParser {
                     idx, MAX_DATA_ARRAY_LEN
                 )));
             }
-            bytes.push(array.len() as u8);
+            bytes.extend_from_slice(&(array.len() as u16).to_be_bytes());
         }
         for array in &self.output {
             bytes.extend_from_slice(array)
@@ -64,11 +80,146 @@ impl DataParser {
         Ok((bytes, self.output))
     }
 
+    ///Negative numbers that were wrapped into bytes, e.g. -1 as 255
+    pub fn wrapped(&self) -> &[String] {
+        &self.wrapped
+    }
+
+    ///Keys of constants used in the content, in order of use
+    pub fn used_constants(&self) -> &[String] {
+        &self.used_constants
+    }
+
     pub fn run(&mut self, content: &str) -> Result<()> {
         let array_text = validate_content(content).context(content.to_owned())?;
+        let array_text = self.expand(&array_text)?;
         self.parse(array_text)
     }
 
+    ///Replace constants, ranges (`0..16`, `'a'..='z'`) and repetitions (`[0; 32]`) in arrays
+    ///with the values they stand for, strings are left as is
+    fn expand(&mut self, content: &str) -> Result<String> {
+        let chars = content.chars().collect::<Vec<char>>();
+        let mut output = String::with_capacity(content.len());
+        let mut idx = 0;
+        while idx < chars.len() {
+            output.push(chars[idx]);
+            idx += 1;
+            match chars[idx - 1] {
+                '"' => {
+                    let mut escaping = false;
+                    while idx < chars.len() {
+                        output.push(chars[idx]);
+                        idx += 1;
+                        match chars[idx - 1] {
+                            _ if escaping => escaping = false,
+                            '\\' => escaping = true,
+                            '"' => break,
+                            _ => {}
+                        }
+                    }
+                }
+                '[' => {
+                    let start = idx;
+                    while idx < chars.len() && chars[idx] != ']' {
+                        idx += match chars[idx] {
+                            '\'' => char_literal_len(&chars[idx..]),
+                            _ => 1,
+                        };
+                    }
+                    let array = chars[start..idx.min(chars.len())]
+                        .iter()
+                        .collect::<String>();
+                    output.push_str(&self.expand_array(&array)?);
+                }
+                _ => {}
+            }
+        }
+        Ok(output)
+    }
+
+    fn expand_array(&mut self, array: &str) -> Result<String> {
+        let tokens = split_array(array);
+        //plain arrays are kept as written so errors point at the right char
+        let is_plain = tokens.iter().all(|token| match token {
+            ArrayToken::Value(value) => !self.constants.contains_key(value),
+            _ => false,
+        });
+        if is_plain {
+            return Ok(array.to_owned());
+        }
+        if tokens.contains(&ArrayToken::Repeat) {
+            return match tokens.as_slice() {
+                [ArrayToken::Value(value), ArrayToken::Repeat, ArrayToken::Value(count)] => {
+                    let value = self.resolve(value);
+                    let count = self.resolve(count);
+                    let count = parse_size(&count).ok_or_else(|| {
+                        Error::msg(format!(
+                            "Invalid repeat count {} in [{}], must be 1-65535 (e317)",
+                            count, array
+                        ))
+                    })?;
+                    Ok(vec![value; count as usize].join(","))
+                }
+                _ => Err(Error::msg(format!(
+                    "Invalid repeat [{}], must be [<value>; <count>] (e318)",
+                    array
+                ))),
+            };
+        }
+        let mut values = vec![];
+        let mut idx = 0;
+        while idx < tokens.len() {
+            match &tokens[idx..] {
+                [ArrayToken::Value(start), ArrayToken::Range(inclusive), ArrayToken::Value(end), ..] =>
+                {
+                    let start = self.range_bound(start, array)?;
+                    let end = self.range_bound(end, array)?;
+                    let end = if *inclusive { end + 1 } else { end };
+                    if start >= end {
+                        return Err(Error::msg(format!("Empty range in [{}] (e320)", array)));
+                    }
+                    values.extend((start..end).map(|value| value.to_string()));
+                    idx += 3;
+                }
+                [ArrayToken::Value(value), ..] => {
+                    values.push(self.resolve(value));
+                    idx += 1;
+                }
+                _ => {
+                    return Err(Error::msg(format!(
+                        "Invalid range in [{}], must be <start>..<end> or <start>..=<end> (e319)",
+                        array
+                    )))
+                }
+            }
+        }
+        Ok(values.join(","))
+    }
+
+    fn range_bound(&mut self, token: &str, array: &str) -> Result<u16> {
+        let value = self.resolve(token);
+        parse_byte_literal(&value)
+            .map(|value| value as u16)
+            .map_err(|_| {
+                Error::msg(format!(
+                    "Invalid range bound {} in [{}], must be 0-255 or a char (e319)",
+                    value, array
+                ))
+            })
+    }
+
+    ///Value of the constant named `token` or `token` if it's not a constant
+    fn resolve(&mut self, token: &str) -> String {
+        match self.constants.get(token) {
+            Some(value) => {
+                self.used_constants.push(token.to_owned());
+                value.clone()
+            }
+            None => token.to_owned(),
+        }
+    }
+
     fn parse(&mut self, content: String) -> Result<()> {
         for (chr_idx, chr) in content.chars().enumerate() {
             match self.container_mode {
@@ -106,8 +257,11 @@ impl DataParser {
 
     fn finish_num(&mut self, chr_idx: usize) -> Result<()> {
         self.value_mode = ValueMode::None;
-        match self.current_content.parse::<u8>() {
+        match parse_byte_literal(&self.current_content) {
             Ok(num) => {
+                if is_wrapped_literal(&self.current_content) {
+                    self.wrapped.push(self.current_content.clone());
+                }
                 self.current_array.push(num);
                 self.current_content.clear();
             }
@@ -123,7 +277,7 @@ impl DataParser {
 
     fn finish_hex(&mut self, chr_idx: usize) -> Result<()> {
         self.value_mode = ValueMode::None;
-        match u8::from_str_radix(&self.current_content, 16) {
+        match parse_byte_literal(&format!("x{}", self.current_content)) {
             Ok(num) => {
                 self.current_array.push(num);
                 self.current_content.clear();
@@ -140,13 +294,13 @@ impl DataParser {
 
     fn finish_binary(&mut self, chr_idx: usize) -> Result<()> {
         self.value_mode = ValueMode::None;
-        if self.current_content.len() != 8 {
+        if self.current_content.is_empty() || self.current_content.len() > 8 {
             return Err(Error::msg(format!(
                 "Invalid binary number at char {} (e314)",
                 chr_idx
             )));
         }
-        match u8::from_str_radix(&self.current_content, 2) {
+        match parse_byte_literal(&format!("b{}", self.current_content)) {
             Ok(num) => {
                 self.current_array.push(num);
                 self.current_content.clear();
@@ -161,19 +315,62 @@ impl DataParser {
         Ok(())
     }
 
+    fn finish_octal(&mut self, chr_idx: usize) -> Result<()> {
+        self.value_mode = ValueMode::None;
+        match parse_byte_literal(&format!("o{}", self.current_content)) {
+            Ok(num) => {
+                self.current_array.push(num);
+                self.current_content.clear();
+            }
+            Err(_) => {
+                return Err(Error::msg(format!(
+                    "Invalid octal number at char {} (e315)",
+                    chr_idx
+                )));
+            }
+        }
+        Ok(())
+    }
+
     fn finish_char(&mut self, chr_idx: usize) -> Result<()> {
-        if self.current_content.chars().count() == 3
-            && self.current_content.starts_with('\'')
-            && self.current_content.ends_with('\'')
-        {
-            self.value_mode = ValueMode::None;
-            self.current_array
-                .push(self.current_content.chars().nth(1).unwrap() as u8);
-            self.current_content.clear();
+        match parse_byte_literal(&self.current_content) {
+            Ok(num) => {
+                self.value_mode = ValueMode::None;
+                self.current_array.push(num);
+                self.current_content.clear();
+            }
+            Err(err) => {
+                return Err(Error::msg(format!(
+                    "Unable to parse char at char {} (e303): {}",
+                    chr_idx, err
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    ///Chars are either `'c'` or an escape such as `'\n'`, `'\''`
+    ///
+    ///`'\'` is also accepted as a backslash to match ops
+    fn handle_char_literal_char(&mut self, chr: char, chr_idx: usize) -> Result<()> {
+        let len = self.current_content.chars().count();
+        let closing_idx = if self.current_content.starts_with("'\\") {
+            3
+        } else {
+            2
+        };
+        if len < closing_idx {
+            self.current_content.push(chr);
+        } else if len == closing_idx && chr == '\'' {
+            self.current_content.push(chr);
+            self.finish_char(chr_idx)?;
+        } else if self.current_content == "'\\'" && matches!(chr, ',' | ' ' | ']') {
+            self.finish_char(chr_idx)?;
+            self.handle_array_char(chr, chr_idx)?;
         } else {
             return Err(Error::msg(format!(
-                "Unable to parse char at char {} (e303)",
-                chr_idx
+                "Unexpected {} at char {} (e308)",
+                chr, chr_idx
             )));
         }
         Ok(())
@@ -218,163 +415,181 @@ impl DataParser {
     }
 
     fn handle_array_char(&mut self, chr: char, chr_idx: usize) -> Result<()> {
+        if self.value_mode == ValueMode::Char {
+            return self.handle_char_literal_char(chr, chr_idx);
+        }
         match chr {
-            ']' => match self.value_mode {
-                ValueMode::None => {
-                    if self.escaping {
-                        return Err(Error::msg(format!(
-                            "Unexpected ] at char {} (e306)",
-                            chr_idx
-                        )));
-                    } else {
-                        self.finish_array(chr_idx)?;
-                    }
-                }
-                ValueMode::Binary => {
-                    self.finish_binary(chr_idx)?;
-                    self.finish_array(chr_idx)?;
-                }
-                ValueMode::Number => {
-                    self.finish_num(chr_idx)?;
-                    self.finish_array(chr_idx)?;
-                }
-                ValueMode::Hex => {
-                    self.finish_hex(chr_idx)?;
-                    self.finish_array(chr_idx)?;
-                }
-                ValueMode::Char => {
-                    if self.current_content.chars().count() == 1 {
-                        self.current_content.push('[');
-                    } else {
-                        self.finish_char(chr_idx)?;
-                    }
-                }
-            },
-            ',' => match self.value_mode {
-                ValueMode::None => {}
-                ValueMode::Binary => self.finish_binary(chr_idx)?,
-                ValueMode::Number => self.finish_num(chr_idx)?,
-                ValueMode::Hex => self.finish_hex(chr_idx)?,
-                ValueMode::Char => {
-                    if self.current_content == *"'" {
-                        self.current_content.push(',')
-                    } else {
-                        self.finish_char(chr_idx)?;
-                    }
+            ']' => {
+                if self.value_mode == ValueMode::None && self.escaping {
+                    return Err(Error::msg(format!(
+                        "Unexpected ] at char {} (e306)",
+                        chr_idx
+                    )));
                 }
-            },
+                self.finish_value(chr_idx)?;
+                self.finish_array(chr_idx)?;
+            }
+            ',' | ' ' => self.finish_value(chr_idx)?,
             '\'' => match self.value_mode {
                 ValueMode::None => {
                     self.current_content.push('\'');
                     self.value_mode = ValueMode::Char;
                 }
-                ValueMode::Hex | ValueMode::Number | ValueMode::Binary => {
+                _ => {
                     return Err(Error::msg(format!(
                         "Unexpected ' at char {} (e307)",
                         chr_idx
                     )));
                 }
-                ValueMode::Char => match self.current_content.chars().count() {
-                    1 => self.current_content.push('\''),
-                    2 => {
-                        self.current_content.push('\'');
-                        self.finish_char(chr_idx)?;
-                    }
-                    _ => {
-                        return Err(Error::msg(format!(
-                            "Unexpected ' at char {} (e308)",
-                            chr_idx
-                        )));
-                    }
-                },
             },
-            'x' => match self.value_mode {
-                ValueMode::None => {
-                    self.value_mode = ValueMode::Hex;
-                }
-                ValueMode::Number | ValueMode::Hex | ValueMode::Binary => {
-                    return Err(Error::msg(format!(
-                        "Unexpected x at char {} (e309)",
-                        chr_idx
-                    )));
+            'x' | 'o' | '-' if self.value_mode == ValueMode::None => match chr {
+                'x' => self.value_mode = ValueMode::Hex,
+                'o' => self.value_mode = ValueMode::Octal,
+                _ => {
+                    self.current_content.push(chr);
+                    self.value_mode = ValueMode::Number;
                 }
-                ValueMode::Char => self.current_content.push('x'),
             },
+            'x' => {
+                return Err(Error::msg(format!(
+                    "Unexpected x at char {} (e309)",
+                    chr_idx
+                )));
+            }
             '0'..='9' => match self.value_mode {
                 ValueMode::None => {
                     self.current_content.push(chr);
                     self.value_mode = ValueMode::Number;
                 }
-                ValueMode::Char | ValueMode::Hex | ValueMode::Number => {
-                    self.current_content.push(chr)
+                ValueMode::Binary if chr > '1' => {
+                    return Err(Error::msg(format!(
+                        "Unexpected {} at char {} (e312)",
+                        chr, chr_idx
+                    )));
                 }
-                ValueMode::Binary => {
-                    if chr == '0' || chr == '1' {
-                        self.current_content.push(chr);
-                    } else {
-                        return Err(Error::msg(format!(
-                            "Unexpected {} at char {} (e312)",
-                            chr, chr_idx
-                        )));
-                    }
+                ValueMode::Octal if chr > '7' => {
+                    return Err(Error::msg(format!(
+                        "Unexpected {} at char {} (e316)",
+                        chr, chr_idx
+                    )));
                 }
+                _ => self.current_content.push(chr),
             },
             'b' => match self.value_mode {
                 ValueMode::None => self.value_mode = ValueMode::Binary,
-                ValueMode::Binary | ValueMode::Number => {
+                ValueMode::Hex => self.current_content.push(chr),
+                _ => {
                     return Err(Error::msg(format!(
                         "Unexpected {} at char {} (e312)",
                         chr, chr_idx
                     )));
                 }
-                ValueMode::Char | ValueMode::Hex => self.current_content.push(chr),
             },
             'A'..='F' | 'a'..='f' => match self.value_mode {
-                ValueMode::Number | ValueMode::None | ValueMode::Binary => {
+                ValueMode::Hex => self.current_content.push(chr),
+                _ => {
                     return Err(Error::msg(format!(
                         "Unexpected {} at char {} (e310)",
                         chr, chr_idx
                     )));
                 }
-                ValueMode::Char | ValueMode::Hex => self.current_content.push(chr),
-            },
-            ' ' => match self.value_mode {
-                ValueMode::None => { /*ignore whitespace*/ }
-                ValueMode::Number => self.finish_num(chr_idx)?,
-                ValueMode::Hex => self.finish_hex(chr_idx)?,
-                ValueMode::Binary => self.finish_binary(chr_idx)?,
-                ValueMode::Char => {
-                    if self.current_content.chars().count() == 3 {
-                        self.finish_char(chr_idx)?;
-                    } else {
-                        self.current_content.push(chr);
-                    }
-                }
-            },
-            _ => match self.value_mode {
-                ValueMode::Number | ValueMode::Hex | ValueMode::None | ValueMode::Binary => {
-                    return Err(Error::msg(format!(
-                        "Unexpected {} at char {} (e311)",
-                        chr, chr_idx
-                    )));
-                }
-                ValueMode::Char => self.current_content.push(chr),
             },
+            _ => {
+                return Err(Error::msg(format!(
+                    "Unexpected {} at char {} (e311)",
+                    chr, chr_idx
+                )));
+            }
         }
         Ok(())
     }
+
+    ///Finish the current number, if there is one
+    fn finish_value(&mut self, chr_idx: usize) -> Result<()> {
+        match self.value_mode {
+            ValueMode::None => Ok(()),
+            ValueMode::Number => self.finish_num(chr_idx),
+            ValueMode::Hex => self.finish_hex(chr_idx),
+            ValueMode::Binary => self.finish_binary(chr_idx),
+            ValueMode::Octal => self.finish_octal(chr_idx),
+            ValueMode::Char => self.finish_char(chr_idx),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ArrayToken {
+    ///Number, char or constant
+    Value(String),
+    ///`..` or `..=` (true)
+    Range(bool),
+    ///`;`
+    Repeat,
+}
+
+///Number of chars in the char literal at the start of `chars`, e.g. 3 for `'a'` and 4 for `'\n'`
+///
+///`'\'` (a backslash) is 3 unless followed by another `'`
+fn char_literal_len(chars: &[char]) -> usize {
+    let len = match chars.get(1) {
+        Some('\\') if chars.get(3) == Some(&'\'') => 4,
+        _ => 3,
+    };
+    len.min(chars.len())
+}
+
+///Split the content of an array (without brackets) on commas and whitespace
+fn split_array(array: &str) -> Vec<ArrayToken> {
+    let chars = array.chars().collect::<Vec<char>>();
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let finish = |current: &mut String, tokens: &mut Vec<ArrayToken>| {
+        if !current.is_empty() {
+            tokens.push(ArrayToken::Value(std::mem::take(current)));
+        }
+    };
+    let mut idx = 0;
+    while idx < chars.len() {
+        match chars[idx] {
+            '\'' => {
+                //chars are kept whole as they can be any of the separators
+                let len = char_literal_len(&chars[idx..]);
+                current.extend(&chars[idx..idx + len]);
+                idx += len;
+                continue;
+            }
+            ',' | ' ' | '\t' => finish(&mut current, &mut tokens),
+            ';' => {
+                finish(&mut current, &mut tokens);
+                tokens.push(ArrayToken::Repeat);
+            }
+            '.' if chars.get(idx + 1) == Some(&'.') => {
+                finish(&mut current, &mut tokens);
+                idx += 1;
+                let inclusive = chars.get(idx + 1) == Some(&'=');
+                if inclusive {
+                    idx += 1;
+                }
+                tokens.push(ArrayToken::Range(inclusive));
+            }
+            chr => current.push(chr),
+        }
+        idx += 1;
+    }
+    finish(&mut current, &mut tokens);
+    tokens
 }
 
 fn validate_content(content: &str) -> Result<String> {
     let trimmed = content.trim();
-    return if trimmed.starts_with('[') && trimmed.ends_with(']') {
+    if trimmed.starts_with('[') && trimmed.ends_with(']') {
         let mut chars = trimmed.chars();
         chars.next();
         chars.next_back();
         Ok(chars.collect::<String>().trim().to_owned())
     } else {
         Err(Error::msg("Invalid data definition, data must be made of an array with arrays or strings inside.\ne.g. [[1,2],['a','b'],\"ex\"]  (e400)"))
-    };
+    }
 }
 
 #[cfg(test)]
@@ -430,20 +645,23 @@ mod test {
         fn basic_parsing() {
             let mut parser = DataParser::new();
             parser.run("[[1]]").unwrap();
-            assert_eq!(parser.into_bytes().unwrap(), (vec![1, 1, 1], vec![vec![1]]));
+            assert_eq!(
+                parser.into_bytes().unwrap(),
+                (vec![1, 0, 1, 1], vec![vec![1]])
+            );
 
             let mut parser = DataParser::new();
             parser.run("[[b00001110]]").unwrap();
             assert_eq!(
                 parser.into_bytes().unwrap(),
-                (vec![1, 1, 14], vec![vec![14]])
+                (vec![1, 0, 1, 14], vec![vec![14]])
             );
 
             let mut parser = DataParser::new();
             parser.run("[[40, 41]]").unwrap();
             assert_eq!(
                 parser.into_bytes().unwrap(),
-                (vec![1, 2, 40, 41], vec![vec![40, 41]])
+                (vec![1, 0, 2, 40, 41], vec![vec![40, 41]])
             );
 
             let mut parser = DataParser::new();
@@ -451,12 +669,86 @@ mod test {
             assert_eq!(
                 parser.into_bytes().unwrap(),
                 (
-                    vec![2, 2, 2, 97, 98, 99, 100],
+                    vec![2, 0, 2, 0, 2, 97, 98, 99, 100],
                     vec![vec![97, 98], vec![99, 100]]
                 )
             );
         }
 
+        #[test]
+        fn test_ranges_and_repeats() {
+            let bytes = |content: &str| {
+                let mut parser = DataParser::new();
+                parser.run(content).map(|_| parser.into_bytes().unwrap().1)
+            };
+            assert_eq!(bytes("[[0..4]]").unwrap(), vec![vec![0, 1, 2, 3]]);
+            assert_eq!(
+                bytes("[[x10 ..= x12, 1]]").unwrap(),
+                vec![vec![16, 17, 18, 1]]
+            );
+            assert_eq!(
+                bytes("[['a'..='c'], \"..\"]").unwrap(),
+                vec![vec![97, 98, 99], vec![46, 46]]
+            );
+            assert_eq!(
+                bytes("[[7; 3], [';'; 2]]").unwrap(),
+                vec![vec![7, 7, 7], vec![59, 59]]
+            );
+            assert_eq!(bytes("[[0..=253, 255]]").unwrap()[0].len(), 255);
+            assert_eq!(bytes("[[0; 255]]").unwrap()[0].len(), 255);
+            assert_eq!(bytes("[['\\', ']']]").unwrap(), vec![vec![92, 93]]);
+
+            let mut parser = DataParser::new();
+            parser.run("[[0; 300]]").unwrap();
+            assert_eq!(parser.into_bytes().unwrap().0[..5], [1, 1, 44, 0, 0]);
+            let mut parser = DataParser::new();
+            parser.output = vec![vec![0; MAX_DATA_ARRAY_LEN + 1]];
+            assert!(parser.into_bytes().is_err());
+            assert!(bytes("[[4..4]]").is_err());
+            assert!(bytes("[[4..]]").is_err());
+            assert!(bytes("[[1, 2; 3]]").is_err());
+            assert!(bytes("[[1; 0]]").is_err());
+            assert!(bytes("[[0..300]]").is_err());
+        }
+
+        #[test]
+        fn test_constants() {
+            let constants = [("size", "4"), ("fill", "-1")]
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            let mut parser = DataParser::new().with_constants(constants);
+            parser.run("[[fill; size], [0..size], [size, 1]]").unwrap();
+            assert_eq!(
+                parser.used_constants(),
+                &["fill", "size", "size", "size"].map(String::from)
+            );
+            assert_eq!(parser.wrapped().len(), 4);
+            assert_eq!(
+                parser.into_bytes().unwrap().1,
+                vec![vec![255, 255, 255, 255], vec![0, 1, 2, 3], vec![4, 1]]
+            );
+
+            let mut parser = DataParser::new();
+            assert!(parser.run("[[size]]").is_err());
+        }
+
+        #[test]
+        fn test_literals() {
+            let mut parser = DataParser::new();
+            parser
+                .run("[[b1010, o17, -1, -128, 0], ['\\n', '\\'', '\\\\', '\\', ''', ' ']]")
+                .unwrap();
+            assert_eq!(
+                parser.wrapped(),
+                &[String::from("-1"), String::from("-128")]
+            );
+            assert_eq!(
+                parser.into_bytes().unwrap().1,
+                vec![vec![10, 15, 255, 128, 0], vec![10, 39, 92, 92, 39, 32]]
+            );
+        }
+
         #[test]
         fn test_numbers() {
             let mut parser = DataParser::new();
@@ -750,8 +1042,8 @@ mod test {
                 result,
                 (
                     vec![
-                        4, 3, 3, 2, 11, 253, 160, 15, 2, 72, 87, 1, 49, 72, 101, 108, 108, 111, 32,
-                        87, 111, 114, 108, 100
+                        4, 0, 3, 0, 3, 0, 2, 0, 11, 253, 160, 15, 2, 72, 87, 1, 49, 72, 101, 108,
+                        108, 111, 32, 87, 111, 114, 108, 100
                     ],
                     vec![
                         vec![253, 160, 15],
@@ -769,6 +1061,18 @@ mod test {
             let result = parser.run("[[4, 8, 15 , 16, 23,42],[ 1, 4 ,9, 16, 25, 36 ]");
             assert!(result.is_err());
         }
+
+        #[test]
+        fn test_multi_byte() {
+            let mut parser = DataParser::new();
+            parser.run("[\"£1\", [1]]").unwrap();
+            let result = parser.into_bytes().unwrap();
+            assert_eq!(result.1, vec![vec![0xC2, 0xA3, 49], vec![1]]);
+            assert_eq!(result.0[..5], [2, 0, 3, 0, 1]);
+
+            let mut parser = DataParser::new();
+            assert!(parser.run("[[1, 'é']]").is_err());
+        }
     }
 
     mod invalid_input {
@@ -805,9 +1109,24 @@ mod test {
             for i in '2'..='9' {
                 expect_error_binary(i);
             }
-            expect_error_binary(' ');
-            expect_error_binary(']');
-            expect_error_binary(',');
+        }
+
+        #[test]
+        fn test_invalid_literals() {
+            for content in [
+                "[[b]]",
+                "[[b111100001]]",
+                "[[o8]]",
+                "[[o400]]",
+                "[[-129]]",
+                "[[1-]]",
+                "[[-x1]]",
+                "[['ab']]",
+                "[['\\q']]",
+                "[['é']]",
+            ] {
+                assert!(DataParser::new().run(content).is_err(), "{}", content);
+            }
         }
 
         #[test]
@@ -1,99 +1,309 @@
 use anyhow::{Context, Error, Result};
 use lazy_static::lazy_static;
 
+use crate::assembler::diagnostic::{
+    Diagnostic, LineContext, ParseErrors, INVALID_CONSTANT, INVALID_DATA, INVALID_FUNCTION,
+    INVALID_HEADER, INVALID_OP, INVALID_RESERVE, INVALID_STRING, UNEXPECTED_DIVIDER,
+};
 use crate::assembler::parser::data_parser::DataParser;
+use crate::assembler::parser::preprocessor::expand_repeats;
+use crate::assembler::parser::syntax::split_comment;
 use crate::assembler::program_model::{
-    ConstantModel, DataModel, Definition, LabelModel, OpModel, ProgramModel, StringModel, Usage,
+    ConstantModel, DataModel, Definition, FunctionModel, LabelModel, OpModel, ProgramModel,
+    ReservedModel, StringModel, Usage,
 };
+use crate::assembler::text::check_byte_len;
 use crate::assembler::FORMAT_ERROR;
-use crate::constants::code::{DIVDERS, KEYWORDS, MNEMONICS, REGISTERS};
-use crate::constants::hardware::MAX_STRING_LEN;
-use crate::language::parse_line;
+use crate::constants::code::{CPY_AREG_ADDR, DIVDERS, KEYWORDS, MNEMONICS, REGISTERS, RET, RETI};
+use crate::constants::hardware::{MAX_STRING_LEN, RAM_SIZE};
 use crate::language::parser::params::Param;
+use crate::language::{parse_line, split_line, split_line_spans, TokenError};
 use std::collections::HashMap;
 
 mod data_parser;
+mod preprocessor;
+pub mod syntax;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum ParseMode {
     Header,
     Strings,
     Data,
+    Reserve,
     Ops,
 }
 
 pub fn generate_program_model(input: Vec<String>) -> Result<ProgramModel> {
+    parse_program(input, false)
+}
+
+///Parse BASM for linking, strings and data used but not defined are left for the linker to resolve
+pub fn generate_object_model(input: Vec<String>) -> Result<ProgramModel> {
+    parse_program(input, true)
+}
+
+fn parse_program(input: Vec<String>, object: bool) -> Result<ProgramModel> {
     if input.len() < 4 {
-        return Err(Error::msg(FORMAT_ERROR));
-    }
-    let mut iter = input.into_iter();
-    let name = ProgramModel::validate_name(
-        iter.next()
-            .context(format!("Program name missing\n\n{}", FORMAT_ERROR))?,
-    )?;
-    let version = ProgramModel::validate_version(
-        iter.next()
-            .context(format!("Program version missing\n\n{}", FORMAT_ERROR))?,
-    )?;
+        let first_line = input.first().cloned().unwrap_or_default();
+        return Err(Error::msg(FORMAT_ERROR)).context(LineContext::new(
+            &first_line,
+            1,
+            INVALID_HEADER,
+        ));
+    }
+    let name_line = input[0].clone();
+    let name = ProgramModel::validate_name(name_line.clone()).context(LineContext::new(
+        &name_line,
+        1,
+        INVALID_HEADER,
+    ))?;
+    let version_line = input[1].clone();
+    let version = ProgramModel::validate_version(version_line.clone())
+        .context(LineContext::new(&version_line, 2, INVALID_HEADER))?;
     let mut program_model = ProgramModel::new(name, version);
+    program_model.object = object;
     let mut parse_mode = ParseMode::Header;
 
-    for (idx, line) in iter.enumerate() {
-        let line_num = idx + 3;
-        let trimmed = line.trim();
-        if !trimmed.starts_with('#') && !trimmed.is_empty() {
-            match trimmed {
-                ".strings" => {
-                    if parse_mode == ParseMode::Ops {
-                        return Err(Error::msg(format!("Unexpected .strings divider at line {}, all data and strings must be defined before .ops", line_num)));
-                    } else {
-                        parse_mode = ParseMode::Strings;
-                    }
-                }
-                ".data" => {
-                    if parse_mode == ParseMode::Ops {
-                        return Err(Error::msg(format!("Unexpected .data divider at line {}, all data and strings must be defined before .ops", line_num)));
-                    } else {
-                        parse_mode = ParseMode::Data;
-                    }
-                }
-                ".ops" => {
-                    if parse_mode == ParseMode::Ops {
-                        return Err(Error::msg(format!(
-                            "Unexpected .ops divider at line {}, already in ops section",
-                            line_num
-                        )));
-                    } else {
-                        parse_mode = ParseMode::Ops;
-                    }
-                }
-                "" => {}
-                _ => match parse_mode {
-                    ParseMode::Header => {
-                        return Err(Error::msg(format!(
-                            "Unexpected content: {}\n\n{}",
-                            line, FORMAT_ERROR
-                        )));
-                    }
-                    ParseMode::Strings => {
-                        parse_string(&mut program_model, &line, line_num).context(line)?
-                    }
-                    ParseMode::Data => {
-                        parse_data(&mut program_model, &line, line_num).context(line)?
-                    }
-                    ParseMode::Ops => {
-                        if trimmed.to_lowercase().starts_with("const") {
-                            parse_constant(&mut program_model, &line, line_num).context(line)?
-                        } else {
-                            parse_op(&mut program_model, &line, line_num).context(line)?
-                        }
-                    }
-                },
+    let numbered = input
+        .into_iter()
+        .enumerate()
+        .skip(2)
+        .map(|(idx, line)| (idx + 1, line))
+        .collect::<Vec<(usize, String)>>();
+    let lines = expand_repeats(&numbered).map_err(|err| {
+        let line = err
+            .downcast_ref::<LineContext>()
+            .map(|context| context.to_string())
+            .unwrap_or_default();
+        Error::new(ParseErrors::new(vec![(Diagnostic::from(&err), line)]))
+    })?;
+
+    //errors are collected so they can all be reported at once, lines with errors are skipped
+    let mut errors = vec![];
+    for (line_num, line) in &lines {
+        let line_num = *line_num;
+        if let Err(err) = parse_program_line(&mut program_model, &mut parse_mode, line, line_num) {
+            errors.push((Diagnostic::from(&err), line.clone()));
+        }
+    }
+    if let Some(function) = program_model
+        .functions
+        .last()
+        .filter(|function| function.end_line.is_none())
+    {
+        let definition = &function.definition;
+        let err = Error::msg(format!(
+            "Function {} on line {} is missing endfn",
+            function.name, definition.line_num
+        ))
+        .context(LineContext::new(
+            &definition.original_line,
+            definition.line_num,
+            INVALID_FUNCTION,
+        ));
+        errors.push((Diagnostic::from(&err), definition.original_line.clone()));
+    }
+
+    if errors.is_empty() {
+        Ok(program_model)
+    } else {
+        Err(Error::new(ParseErrors::new(errors)))
+    }
+}
+
+fn parse_program_line(
+    program_model: &mut ProgramModel,
+    parse_mode: &mut ParseMode,
+    line: &str,
+    line_num: usize,
+) -> Result<()> {
+    let trimmed = line.trim();
+    if trimmed.starts_with('#') || trimmed.is_empty() {
+        return Ok(());
+    }
+    //after an unexpected divider the section is still changed so the lines after it can be checked
+    match trimmed {
+        ".strings" => {
+            let in_ops = *parse_mode == ParseMode::Ops;
+            *parse_mode = ParseMode::Strings;
+            if in_ops {
+                return Err(Error::msg(format!("Unexpected .strings divider at line {}, all data and strings must be defined before .ops", line_num)))
+                    .context(LineContext::new(line, line_num, UNEXPECTED_DIVIDER));
+            }
+        }
+        ".data" => {
+            let in_ops = *parse_mode == ParseMode::Ops;
+            *parse_mode = ParseMode::Data;
+            if in_ops {
+                return Err(Error::msg(format!("Unexpected .data divider at line {}, all data and strings must be defined before .ops", line_num)))
+                    .context(LineContext::new(line, line_num, UNEXPECTED_DIVIDER));
+            }
+        }
+        ".reserve" => {
+            let in_ops = *parse_mode == ParseMode::Ops;
+            *parse_mode = ParseMode::Reserve;
+            if in_ops {
+                return Err(Error::msg(format!("Unexpected .reserve divider at line {}, all reserved memory must be defined before .ops", line_num)))
+                    .context(LineContext::new(line, line_num, UNEXPECTED_DIVIDER));
             }
         }
+        ".ops" => {
+            if *parse_mode == ParseMode::Ops {
+                return Err(Error::msg(format!(
+                    "Unexpected .ops divider at line {}, already in ops section",
+                    line_num
+                )))
+                .context(LineContext::new(line, line_num, UNEXPECTED_DIVIDER));
+            }
+            *parse_mode = ParseMode::Ops;
+        }
+        _ if trimmed == ".start" || trimmed.starts_with(".start ") => parse_start(
+            program_model,
+            line,
+            line_num,
+        )
+        .context(LineContext::new(line, line_num, INVALID_HEADER))?,
+        _ => match parse_mode {
+            ParseMode::Header if trimmed.starts_with(".description ") => parse_description(
+                program_model,
+                trimmed,
+            )
+            .context(LineContext::new(line, line_num, INVALID_HEADER))?,
+            ParseMode::Header => {
+                return Err(Error::msg(format!(
+                    "Unexpected content: {}\n\n{}",
+                    line, FORMAT_ERROR
+                )))
+                .context(LineContext::new(line, line_num, INVALID_HEADER));
+            }
+            ParseMode::Strings => parse_string(program_model, line, line_num)
+                .context(LineContext::new(line, line_num, INVALID_STRING))?,
+            ParseMode::Data if trimmed.split_whitespace().next() == Some("const") => {
+                parse_constant(program_model, line, line_num).context(LineContext::new(
+                    line,
+                    line_num,
+                    INVALID_CONSTANT,
+                ))?
+            }
+            ParseMode::Data => parse_data(program_model, line, line_num)
+                .context(LineContext::new(line, line_num, INVALID_DATA))?,
+            ParseMode::Reserve => parse_reserve(program_model, line, line_num)
+                .context(LineContext::new(line, line_num, INVALID_RESERVE))?,
+            ParseMode::Ops => {
+                let code = split_comment(trimmed).0.trim().to_lowercase();
+                if trimmed.to_lowercase().starts_with("const") {
+                    parse_constant(program_model, line, line_num).context(LineContext::new(
+                        line,
+                        line_num,
+                        INVALID_CONSTANT,
+                    ))?
+                } else if code.split_whitespace().next() == Some("fn") {
+                    parse_function(program_model, line, line_num).context(LineContext::new(
+                        line,
+                        line_num,
+                        INVALID_FUNCTION,
+                    ))?
+                } else if code == "endfn" {
+                    parse_end_function(program_model, line_num).context(LineContext::new(
+                        line,
+                        line_num,
+                        INVALID_FUNCTION,
+                    ))?
+                } else {
+                    parse_op(program_model, line, line_num)
+                        .context(LineContext::new(line, line_num, INVALID_OP))?
+                }
+            }
+        },
+    }
+    Ok(())
+}
+
+fn parse_description(program_model: &mut ProgramModel, line: &str) -> Result<()> {
+    if program_model.description.is_some() {
+        return Err(Error::msg("Program description already set"));
+    }
+    let description = line.trim_start_matches(".description");
+    program_model.description = Some(ProgramModel::validate_description(description)?);
+    Ok(())
+}
+
+///`.start <label>`, can be anywhere in the file
+fn parse_start(program_model: &mut ProgramModel, line: &str, line_num: usize) -> Result<()> {
+    if program_model.start.is_some() {
+        return Err(Error::msg("Program start already set"));
     }
+    let splits = split_comment(line)
+        .0
+        .split_whitespace()
+        .collect::<Vec<&str>>();
+    if splits.len() != 2 {
+        return Err(Error::msg(format!(
+            "Error parsing start on line {}, format must be .start <label>, e.g. .start main",
+            line_num
+        )));
+    }
+    let key = splits[1];
+    program_model
+        .labels
+        .entry(key.to_owned())
+        .or_insert_with(|| LabelModel::new(key.to_owned(), None, vec![]))
+        .usage
+        .push(Usage::new(line.to_owned(), line_num));
+    program_model.start = Some(key.to_owned());
+    Ok(())
+}
+
+///`fn <name>:`, defines label `name` and starts a function that must be closed by `endfn`
+fn parse_function(program_model: &mut ProgramModel, line: &str, line_num: usize) -> Result<()> {
+    if let Some(function) = program_model
+        .functions
+        .last()
+        .filter(|function| function.end_line.is_none())
+    {
+        return Err(Error::msg(format!(
+            "Function {} on line {} must be closed with endfn before another function starts",
+            function.name, function.definition.line_num
+        )));
+    }
+    let name = split_comment(line).0.trim()[2..]
+        .trim()
+        .strip_suffix(':')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| {
+            Error::msg(format!(
+                "Error parsing function on line {}, format must be fn <name>:, e.g. fn print_score:",
+                line_num
+            ))
+        })?;
+    define_label(program_model, name, line, line_num)?;
+    program_model.functions.push(FunctionModel::new(
+        name.to_owned(),
+        Definition::new(line.to_owned(), line_num),
+    ));
+    Ok(())
+}
 
-    Ok(program_model)
+///`endfn`, the last op in the function must be RET (or RETI)
+fn parse_end_function(program_model: &mut ProgramModel, line_num: usize) -> Result<()> {
+    let last_op = program_model.ops.last().map(|op| (op.opcode, op.line_num));
+    let function = program_model
+        .functions
+        .last_mut()
+        .filter(|function| function.end_line.is_none())
+        .ok_or_else(|| Error::msg(format!("endfn on line {} without fn", line_num)))?;
+    //closed even if invalid so the following lines aren't treated as part of it
+    function.end_line = Some(line_num);
+    match last_op {
+        Some((RET, op_line)) | Some((RETI, op_line)) if op_line > function.definition.line_num => {
+            Ok(())
+        }
+        _ => Err(Error::msg(format!(
+            "Function {} must end with RET before endfn on line {}",
+            function.name, line_num
+        ))),
+    }
 }
 
 pub fn parse_constant(program_model: &mut ProgramModel, line: &str, line_num: usize) -> Result<()> {
@@ -113,7 +323,7 @@ pub fn parse_constant(program_model: &mut ProgramModel, line: &str, line_num: us
 }
 
 pub fn parse_string(program_model: &mut ProgramModel, line: &str, line_num: usize) -> Result<()> {
-    return if let Some((key, content)) = line.split_once('=') {
+    if let Some((key, content)) = line.split_once('=') {
         let key = key.trim();
         program_model.validate_key("string key", key, line_num, false)?;
         let mut content = content.trim().to_owned();
@@ -129,12 +339,11 @@ pub fn parse_string(program_model: &mut ProgramModel, line: &str, line_num: usiz
             chars.next_back();
             content = chars.collect();
         }
-        if content.len() > MAX_STRING_LEN {
-            return Err(Error::msg(format!(
-                "String {} (parsed as {}) on line {} is too long, max {} chars",
-                line, content, line_num, MAX_STRING_LEN
-            )));
-        }
+        check_byte_len(
+            &format!("String {} on line {}", key, line_num),
+            &content,
+            MAX_STRING_LEN,
+        )?;
         program_model.strings.insert(
             key.to_owned(),
             StringModel::new(key.to_owned(), content, line.to_owned(), line_num),
@@ -145,57 +354,133 @@ pub fn parse_string(program_model: &mut ProgramModel, line: &str, line_num: usiz
             "String on line {} must be defined as <key>=<content>, e.g. greeting=Hello world",
             line_num
         )))
-    };
+    }
 }
 
 pub fn parse_data(program_model: &mut ProgramModel, line: &str, line_num: usize) -> Result<()> {
-    return if let Some((key, content)) = line.split_once('=') {
+    if let Some((key, content)) = line.split_once('=') {
         let key = key.trim();
         program_model.validate_key("data key", key, line_num, false)?;
-        let mut parser = DataParser::new();
+        if content.trim() == "extern" {
+            let mut model =
+                DataModel::new(key.to_owned(), vec![], vec![], line.to_owned(), line_num);
+            model.external = true;
+            program_model.data.insert(key.to_owned(), model);
+            return Ok(());
+        }
+        let constants = program_model
+            .constants
+            .iter()
+            .map(|(key, model)| (key.clone(), model.content.clone()))
+            .collect();
+        let mut parser = DataParser::new().with_constants(constants);
         let error_msg = format!("Data definition on line {}: \"{}\"", line_num, line);
         parser.run(content).context(error_msg.clone())?;
+        for key in parser.used_constants() {
+            if let Some(model) = program_model.constants.get_mut(key) {
+                model.usage.push(Usage::new(line.to_owned(), line_num));
+            }
+        }
+        let wrapped = parser.wrapped().to_vec();
         let (content_bytes, debug_bytes) = parser.into_bytes().context(error_msg)?;
-        program_model.data.insert(
+        let mut model = DataModel::new(
             key.to_owned(),
-            DataModel::new(
-                key.to_owned(),
-                content_bytes,
-                debug_bytes,
-                line.to_owned(),
-                line_num,
-            ),
+            content_bytes,
+            debug_bytes,
+            line.to_owned(),
+            line_num,
         );
+        model.wrapped = wrapped;
+        program_model.data.insert(key.to_owned(), model);
         Ok(())
     } else {
         Err(Error::msg(format!(
             "Data on line {} must be defined as <key>=<content>, e.g. some_data=[[1,2,3],[50,60]]",
             line_num
         )))
+    }
+}
+
+///Parse `<key> <size>`, regions are placed one after another from address 0
+///
+///`stack <size>` sets the stack size used to check the regions don't overlap the stack
+pub fn parse_reserve(program_model: &mut ProgramModel, line: &str, line_num: usize) -> Result<()> {
+    let line = split_comment(line).0;
+    let splits = line.split_whitespace().collect::<Vec<&str>>();
+    if splits.len() != 2 {
+        return Err(Error::msg(format!(
+            "Reserved memory on line {} must be defined as <key> <size>, e.g. buffer 64",
+            line_num
+        )));
+    }
+    let key = splits[0];
+    let size = parse_size(splits[1]).ok_or_else(|| {
+        TokenError::error(
+            splits[1],
+            format!(
+                "Invalid size '{}' on line {}, must be 1-65535 or x1-xFFFF",
+                splits[1], line_num
+            ),
+        )
+    })?;
+    if key == "stack" {
+        if program_model.stack_size.is_some() {
+            return Err(Error::msg(format!(
+                "Stack size on line {} already set",
+                line_num
+            )));
+        }
+        program_model.stack_size = Some(size);
+        return Ok(());
+    }
+    program_model.validate_key("reserved key", key, line_num, false)?;
+    let addr = program_model.reserved_end();
+    if addr + size as usize > RAM_SIZE {
+        return Err(Error::msg(format!(
+            "Reserved memory {} on line {} doesn't fit in RAM, only {} bytes left",
+            key,
+            line_num,
+            RAM_SIZE - addr
+        )));
+    }
+    program_model.reserved.insert(
+        key.to_owned(),
+        ReservedModel::new(
+            key.to_owned(),
+            addr as u16,
+            size,
+            line.trim().to_owned(),
+            line_num,
+        ),
+    );
+    Ok(())
+}
+
+fn parse_size(input: &str) -> Option<u16> {
+    let size = match input.strip_prefix('x') {
+        Some(hex) => u16::from_str_radix(hex, 16).ok()?,
+        None => input.parse::<u16>().ok()?,
     };
+    if size == 0 {
+        None
+    } else {
+        Some(size)
+    }
 }
 
 pub fn parse_op(program_model: &mut ProgramModel, orig_line: &str, line_num: usize) -> Result<()> {
-    let mut line = orig_line.to_owned();
-    if line.contains('#') {
-        line = line.split_once('#').unwrap().0.to_owned();
-    }
+    let mut line = split_comment(orig_line).0.to_owned();
+    //byte offset of `line` in `orig_line`
+    let mut offset = 0;
     if line.contains(':') {
         let (lbl, content) = line.split_once(':').unwrap();
-        let lbl = lbl.trim();
-        program_model.validate_key("label", lbl, line_num, true)?;
-        let def = Some(Definition::new(orig_line.to_owned(), line_num));
-        if program_model.labels.contains_key(lbl) {
-            program_model.labels.get_mut(lbl).unwrap().definition = def;
-        } else {
-            program_model
-                .labels
-                .insert(lbl.to_owned(), LabelModel::new(lbl.to_owned(), def, vec![]));
-        }
+        define_label(program_model, lbl.trim(), orig_line, line_num)?;
+        offset = line.len() - content.len();
         line = content.to_owned();
     }
 
     let trimmed = line.trim();
+    offset += line.len() - line.trim_start().len();
 
     if trimmed.is_empty() {
         //line is label only
@@ -203,10 +488,118 @@ pub fn parse_op(program_model: &mut ProgramModel, orig_line: &str, line_num: usi
     }
 
     let processed = replace_constants(&mut program_model.constants, trimmed, line_num);
+    let processed = replace_reserved(&mut program_model.reserved, &processed, line_num);
+
+    let ops = expand_pseudo_op(&processed)?;
+    let single_op = ops.len() == 1;
+    for op in ops {
+        //token positions are only known if the op still has the same words as the source
+        let spans = split_line_spans(trimmed);
+        let same_tokens = single_op && split_line(&op).len() == spans.len();
+        add_op(program_model, op, orig_line, line_num).map_err(|mut err| {
+            if let Some(token) = err.downcast_mut::<TokenError>() {
+                if let Some((start, text)) =
+                    token.index.filter(|_| same_tokens).map(|idx| spans[idx])
+                {
+                    let start = offset + start;
+                    let column = orig_line[..start].chars().count() + 1;
+                    token.columns = Some((column, column + text.chars().count()));
+                }
+            }
+            err
+        })?;
+    }
 
-    let (opcode, params) = parse_line(&processed)?;
+    Ok(())
+}
 
-    for param in &params {
+fn define_label(
+    program_model: &mut ProgramModel,
+    lbl: &str,
+    orig_line: &str,
+    line_num: usize,
+) -> Result<()> {
+    program_model.validate_key("label", lbl, line_num, true)?;
+    let def = Some(Definition::new(orig_line.to_owned(), line_num));
+    if program_model.labels.contains_key(lbl) {
+        program_model.labels.get_mut(lbl).unwrap().definition = def;
+    } else {
+        program_model
+            .labels
+            .insert(lbl.to_owned(), LabelModel::new(lbl.to_owned(), def, vec![]));
+    }
+    Ok(())
+}
+
+///Converts CALLARG and RETVAL into the ops they stand for, any other instruction is returned as is
+///
+///`CALLARG target params..` pushes the params, calls target and then discards the params
+///`RETVAL value` copies value into ACC and returns
+fn expand_pseudo_op(line: &str) -> Result<Vec<String>> {
+    let parts = split_line(line);
+    match parts[0].to_ascii_lowercase().as_str() {
+        "callarg" => {
+            if parts.len() < 2 {
+                return Err(Error::msg(format!(
+                    "parsing line '{}'\nCALLARG supports:\nCALLARG (label|address|addr_reg) (data_reg|addr_reg|byte)..",
+                    line
+                )));
+            }
+            let mut ops = vec![];
+            let mut bytes = 0;
+            for param in &parts[2..] {
+                let push = format!("PUSH {}", param);
+                let (_, params) = parse_line(&push)?;
+                bytes += match params[0] {
+                    Param::AddrReg(_) => 2,
+                    _ => 1,
+                };
+                ops.push(push);
+            }
+            ops.push(format!("CALL {}", parts[1]));
+            if bytes > 0 {
+                ops.push(format!("POP {}", bytes));
+            }
+            Ok(ops)
+        }
+        "retval" => {
+            if parts.len() != 2 {
+                return Err(Error::msg(format!(
+                    "parsing line '{}'\nRETVAL supports:\nRETVAL (data_reg|byte)",
+                    line
+                )));
+            }
+            let mut ops = vec![];
+            if !parts[1].eq_ignore_ascii_case("acc") {
+                ops.push(format!("CPY ACC {}", parts[1]));
+            }
+            ops.push(String::from("RET"));
+            Ok(ops)
+        }
+        _ => Ok(vec![line.to_owned()]),
+    }
+}
+
+fn add_op(
+    program_model: &mut ProgramModel,
+    processed: String,
+    orig_line: &str,
+    line_num: usize,
+) -> Result<()> {
+    let (opcode, mut params) = parse_line(&processed)?;
+
+    //keys are unique so a 'label' naming a string is the string address
+    if opcode == CPY_AREG_ADDR {
+        for param in params.iter_mut() {
+            if let Param::Label(key) = param {
+                if program_model.strings.contains_key(key.as_str()) {
+                    *param = Param::StrKey(key.clone());
+                }
+            }
+        }
+    }
+
+    for (idx, param) in params.iter().enumerate() {
         match param {
             Param::Label(lbl) => {
                 if !program_model.labels.contains_key(lbl) {
@@ -225,21 +618,29 @@ pub fn parse_op(program_model: &mut ProgramModel, orig_line: &str, line_num: usi
             Param::StrKey(key) => {
                 if let Some(model) = program_model.strings.get_mut(key) {
                     model.usage.push(Usage::new(orig_line.to_owned(), line_num));
-                } else {
-                    return Err(Error::msg(format!(
-                        "String key {} used on {}, line {} but was never defined",
-                        key, orig_line, line_num
-                    )));
+                } else if !program_model.object {
+                    return Err(TokenError::at(
+                        idx + 1,
+                        key,
+                        format!(
+                            "String key {} used on {}, line {} but was never defined",
+                            key, orig_line, line_num
+                        ),
+                    ));
                 }
             }
             Param::DataKey(key) => {
                 if let Some(model) = program_model.data.get_mut(key) {
                     model.usage.push(Usage::new(orig_line.to_owned(), line_num));
-                } else {
-                    return Err(Error::msg(format!(
-                        "Data key {} used on {}, line {} but was never defined",
-                        key, orig_line, line_num
-                    )));
+                } else if !program_model.object {
+                    return Err(TokenError::at(
+                        idx + 1,
+                        key,
+                        format!(
+                            "Data key {} used on {}, line {} but was never defined",
+                            key, orig_line, line_num
+                        ),
+                    ));
                 }
             }
             _ => {}
@@ -275,6 +676,26 @@ fn replace_constants(
         .join(" ")
 }
 
+///Replaces `@key` with the address of the reserved memory
+fn replace_reserved(
+    reserved: &mut HashMap<String, ReservedModel>,
+    line: &str,
+    line_num: usize,
+) -> String {
+    line.split_whitespace()
+        .map(
+            |word| match word.strip_prefix('@').and_then(|key| reserved.get_mut(key)) {
+                Some(model) => {
+                    model.usage.push(Usage::new(line.to_owned(), line_num));
+                    format!("@{}", model.addr)
+                }
+                None => word.to_owned(),
+            },
+        )
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
 lazy_static! {
     static ref KEY_NAME_ERROR: String = format!("Key names must not include any register, keyword, section divider or mnemonic.\nThese include:\n{}\n{}\n{}\n{}",
         REGISTERS.join(" "),KEYWORDS.join(" "),DIVDERS.join(" "),MNEMONICS.join(" ")
@@ -285,7 +706,7 @@ lazy_static! {
 mod test {
     use super::*;
     use crate::constants::code::{ADD_REG_VAL, CMP_REG_REG};
-    use crate::constants::hardware::{REG_ACC, REG_D1, REG_D3};
+    use crate::constants::hardware::Register;
     use crate::language::parser::params::Param;
 
     #[rustfmt::skip]
@@ -295,6 +716,27 @@ mod test {
         model
     }
 
+    #[test]
+    fn test_expand_pseudo_ops() {
+        let expanded = |line: &str| expand_pseudo_op(line).unwrap();
+
+        assert_eq!(expanded("add d0 1"), vec!["add d0 1"]);
+        assert_eq!(
+            expanded("callarg sum d0 5 a1"),
+            vec!["PUSH d0", "PUSH 5", "PUSH a1", "CALL sum", "POP 4"]
+        );
+        assert_eq!(expanded("CALLARG a0"), vec!["CALL a0"]);
+        assert_eq!(
+            expanded("callarg print ' '"),
+            vec!["PUSH ' '", "CALL print", "POP 1"]
+        );
+        assert_eq!(expanded("retval d1"), vec!["CPY ACC d1", "RET"]);
+        assert_eq!(expanded("retval acc"), vec!["RET"]);
+        assert!(expand_pseudo_op("callarg").is_err());
+        assert!(expand_pseudo_op("callarg sum d0 lbl").is_err());
+        assert!(expand_pseudo_op("retval").is_err());
+    }
+
     #[test]
     fn test_whitespace() {
         let mut program_model = ProgramModel::new(String::new(), String::new());
@@ -325,13 +767,27 @@ mod test {
             }
         }
 
+        #[test]
+        fn test_parse_multi_byte_strings() {
+            let mut program_model = ProgramModel::new(String::new(), String::new());
+            let line = format!("max={}", "£".repeat(127));
+            parse_string(&mut program_model, &line, 1).unwrap();
+            assert_eq!(program_model.strings["max"].content.len(), 254);
+
+            let line = format!("long={}", "£".repeat(128));
+            assert_eq!(
+                parse_string(&mut program_model, &line, 2).unwrap_err().to_string(),
+                "String long on line 2 is too long, 256 bytes but max 255 bytes ('£' is 2 bytes, non-ASCII characters use more than one byte)"
+            );
+        }
+
         #[test]
         fn test_parse_valid_data() {
             let mut program_model = ProgramModel::new(String::new(), String::new());
             #[rustfmt::skip]
                 let data = vec![
-                ("key", "key=[[10]]", 3, DataModel::new(String::from("key"), vec![1, 1, 10], vec![vec![10]],String::from("key=[[10]]"), 3)),
-                ("ex", "ex=[ [ x1, x2 ] , [ 3, 'a'] ]", 4, DataModel::new(String::from("ex"), vec![2, 2, 2, 1, 2, 3, 97], vec![vec![1,2],vec![3,97]],String::from("ex=[ [ x1, x2 ] , [ 3, 'a'] ]"), 4))
+                ("key", "key=[[10]]", 3, DataModel::new(String::from("key"), vec![1, 0, 1, 10], vec![vec![10]],String::from("key=[[10]]"), 3)),
+                ("ex", "ex=[ [ x1, x2 ] , [ 3, 'a'] ]", 4, DataModel::new(String::from("ex"), vec![2, 0, 2, 0, 2, 1, 2, 3, 97], vec![vec![1,2],vec![3,97]],String::from("ex=[ [ x1, x2 ] , [ 3, 'a'] ]"), 4))
             ];
 
             for entry in data {
@@ -346,10 +802,8 @@ mod test {
             let mut program_model = ProgramModel::new(String::new(), String::new());
 
             #[rustfmt::skip]
-                let ops = vec![
-                ("add reg val", "add d3 10", 30, OpModel::new(ADD_REG_VAL, vec![Param::DataReg(REG_D3), Param::Number(10)], String::from("add d3 10"), String::from("add d3 10"), 30)),
-                ("cmp reg reg", "cmp d1 acc", 31, OpModel::new(CMP_REG_REG, vec![Param::DataReg(REG_D1), Param::DataReg(REG_ACC)], String::from("cmp d1 acc"), String::from("cmp d1 acc"), 31)),
-            ];
+                let ops = [("add reg val", "add d3 10", 30, OpModel::new(ADD_REG_VAL, vec![Param::DataReg(Register::D3), Param::Number(10)], String::from("add d3 10"), String::from("add d3 10"), 30)),
+                ("cmp reg reg", "cmp d1 acc", 31, OpModel::new(CMP_REG_REG, vec![Param::DataReg(Register::D1), Param::DataReg(Register::Acc)], String::from("cmp d1 acc"), String::from("cmp d1 acc"), 31))];
 
             for (idx, entry) in ops.iter().enumerate() {
                 parse_op(&mut program_model, entry.1, entry.2).unwrap();
@@ -457,6 +911,90 @@ mod test {
         }
     }
 
+    mod description {
+        use super::*;
+
+        fn model(lines: &[&str]) -> Result<ProgramModel> {
+            generate_program_model(lines.iter().map(|line| line.to_string()).collect())
+        }
+
+        #[test]
+        fn test_description() {
+            let program = model(&["Test", "1", ".description  A test ", ".ops", "nop"]).unwrap();
+            assert_eq!(program.description, Some(String::from("A test")));
+
+            let program = model(&["Test", "1", ".ops", "nop"]).unwrap();
+            assert_eq!(program.description, None);
+        }
+
+        #[test]
+        fn test_invalid_description() {
+            assert!(model(&["Test", "1", ".description ", ".ops", "nop"]).is_err());
+            assert!(model(&["Test", "1", ".ops", ".description A", "nop"]).is_err());
+            assert!(model(&[
+                "Test",
+                "1",
+                ".description A",
+                ".description B",
+                ".ops",
+                "nop"
+            ])
+            .is_err());
+            let long = format!(".description {}", "a".repeat(256));
+            assert!(model(&["Test", "1", &long, ".ops", "nop"]).is_err());
+        }
+    }
+
+    mod reserve {
+        use super::*;
+        use crate::assembler::diagnostic::{RESERVE_OVERLAPS_STACK, UNUSED_RESERVE};
+        use crate::constants::code::{CPY_AREG_ADDR, MEMR_ADDR};
+
+        fn model(program: &str) -> Result<ProgramModel> {
+            generate_program_model(program.lines().map(|line| line.to_string()).collect())
+        }
+
+        #[test]
+        #[rustfmt::skip]
+        fn test_reserve() {
+            let program = model("Test\n1\n.reserve\nbuffer 64\nflags x10 # comment\n.ops\ncpy a0 @flags\nmemr @buffer\n").unwrap();
+            assert_eq!(program.reserved["buffer"].addr, 0);
+            assert_eq!(program.reserved["flags"].addr, 64);
+            assert_eq!(program.reserved["flags"].size, 16);
+            assert_eq!(program.ops[0].opcode, CPY_AREG_ADDR);
+            assert_eq!(program.ops[0].params[1], Param::Addr(64));
+            assert_eq!(program.ops[1].opcode, MEMR_ADDR);
+            assert_eq!(program.ops[1].params[0], Param::Addr(0));
+            assert!(program.check().is_empty());
+        }
+
+        #[test]
+        #[rustfmt::skip]
+        fn test_invalid_reserve() {
+            assert!(model("Test\n1\n.reserve\nbuffer\n.ops\nnop\n").is_err());
+            assert!(model("Test\n1\n.reserve\nbuffer 0\n.ops\nnop\n").is_err());
+            assert!(model("Test\n1\n.reserve\nbuffer 65536\n.ops\nnop\n").is_err());
+            assert!(model("Test\n1\n.reserve\nbuffer 1\nbuffer 1\n.ops\nnop\n").is_err());
+            assert!(model("Test\n1\n.reserve\nbig xFFFF\nmore 1\n.ops\nnop\n").is_err());
+            assert!(model("Test\n1\n.reserve\nstack 1\nstack 1\n.ops\nnop\n").is_err());
+            assert!(model("Test\n1\n.ops\nnop\n.reserve\n").is_err());
+        }
+
+        #[test]
+        #[rustfmt::skip]
+        fn test_reserve_stack_overlap() {
+            let program = model("Test\n1\n.reserve\nbuffer xEFFF\n.ops\nmemr @buffer\n").unwrap();
+            assert!(program.check().is_empty());
+
+            let program = model("Test\n1\n.reserve\nbuffer xEFFF\nmore 1\nstack 512\n.ops\nmemr @buffer\nmemr @more\n").unwrap();
+            assert!(program.check().is_empty());
+
+            let program = model("Test\n1\n.reserve\nbuffer xEFFF\nmore 1\nunused 1\n.ops\nmemr @buffer\nmemr @more\n").unwrap();
+            let codes: Vec<&str> = program.check().iter().map(|diagnostic| diagnostic.code).collect();
+            assert_eq!(codes, vec![RESERVE_OVERLAPS_STACK, UNUSED_RESERVE]);
+        }
+    }
+
     mod edge_cases {
         use super::*;
 
@@ -489,12 +1027,33 @@ mod test {
             assert!(model.data.contains_key("dk2"));
             assert!(model.data.contains_key("dk3"));
         }
+
+        #[test]
+        fn test_data_constants() {
+            let input = vec![
+                "test",
+                "1.0",
+                ".data",
+                "const size 3",
+                "buffer=[[0; size]]",
+                ".ops",
+                "cpy d0 size",
+            ]
+            .into_iter()
+            .map(|line| line.to_string())
+            .collect();
+
+            let model = generate_program_model(input).unwrap();
+
+            assert_eq!(model.data["buffer"].content, vec![1, 0, 3, 0, 0, 0]);
+            assert_eq!(model.constants["size"].usage.len(), 2);
+        }
     }
 
     mod class_integration {
         use super::*;
         use crate::constants::code::{CMP_REG_VAL, CPY_REG_VAL, LD_AREG_DATA_VAL_VAL, PRTS_STR};
-        use crate::constants::hardware::{REG_A0, REG_D0};
+        use crate::constants::hardware::Register;
 
         #[test]
         #[rustfmt::skip]
@@ -568,7 +1127,7 @@ mod test {
             );
             let mut model = DataModel::new(
                 String::from("dat_numbers"),
-                vec![2, 6, 6, 4, 8, 15, 16, 23, 42, 1, 4, 9, 16, 25, 36],
+                vec![2, 0, 6, 0, 6, 4, 8, 15, 16, 23, 42, 1, 4, 9, 16, 25, 36],
                 vec![vec![4, 8, 15, 16, 23, 42], vec![1, 4, 9, 16, 25, 36]],
                 String::from("dat_numbers=[[4, 8, 15 , 16, 23,42],[ 1, 4 ,9, 16, 25, 36 ] ]"),
                 7,
@@ -581,7 +1140,7 @@ mod test {
                 program_model.data.get("dat_chars"),
                 Some(&DataModel::new(
                     String::from("dat_chars"),
-                    vec![2, 3, 3, 102, 111, 111, 98, 97, 114],
+                    vec![2, 0, 3, 0, 3, 102, 111, 111, 98, 97, 114],
                     vec![vec![102, 111, 111], vec![98, 97, 114]],
                     String::from("dat_chars=[['f', 'o', 'o'] , ['b', 'a', 'r']]"),
                     8
@@ -608,10 +1167,10 @@ mod test {
                 ))
             );
             assert_eq!(
-                program_model.ops.get(0).unwrap(),
+                program_model.ops.first().unwrap(),
                 &OpModel::new(
                     CPY_REG_VAL,
-                    vec![Param::DataReg(REG_D1), Param::Number(10)],
+                    vec![Param::DataReg(Register::D1), Param::Number(10)],
                     String::from("cpy d1 10"),
                     String::from("cpy d1 10"),
                     12
@@ -621,7 +1180,7 @@ mod test {
                 program_model.ops.get(1).unwrap(),
                 &OpModel::new(
                     CMP_REG_VAL,
-                    vec![Param::DataReg(REG_D0), Param::Number(1)],
+                    vec![Param::DataReg(Register::D0), Param::Number(1)],
                     String::from("cmp d0 1"),
                     String::from("cmp d0 false"),
                     13
@@ -642,7 +1201,7 @@ mod test {
                 &OpModel::new(
                     LD_AREG_DATA_VAL_VAL,
                     vec![
-                        Param::AddrReg(REG_A0),
+                        Param::AddrReg(Register::A0),
                         Param::DataKey(String::from("dat_numbers")),
                         Param::Number(0),
                         Param::Number(0)
@@ -691,25 +1250,43 @@ mod test {
             parse_op(&mut program_model, "lb3 : inc acc", 6).unwrap();
             parse_op(&mut program_model, "  lb4 : inc acc", 7).unwrap();
 
-            program_model.validate().unwrap();
+            program_model.validate(false).unwrap();
 
             assert_eq!(
                 program_model.ops[0],
-                make_op_model_constant(INC_REG, vec![DReg(REG_ACC)], "lb1: inc acc", "inc acc", 4)
+                make_op_model_constant(
+                    INC_REG,
+                    vec![DReg(Register::Acc)],
+                    "lb1: inc acc",
+                    "inc acc",
+                    4
+                )
             );
             assert_eq!(
                 program_model.ops[1],
-                make_op_model_constant(INC_REG, vec![DReg(REG_ACC)], " lb2: inc acc", "inc acc", 5)
+                make_op_model_constant(
+                    INC_REG,
+                    vec![DReg(Register::Acc)],
+                    " lb2: inc acc",
+                    "inc acc",
+                    5
+                )
             );
             assert_eq!(
                 program_model.ops[2],
-                make_op_model_constant(INC_REG, vec![DReg(REG_ACC)], "lb3 : inc acc", "inc acc", 6)
+                make_op_model_constant(
+                    INC_REG,
+                    vec![DReg(Register::Acc)],
+                    "lb3 : inc acc",
+                    "inc acc",
+                    6
+                )
             );
             assert_eq!(
                 program_model.ops[3],
                 make_op_model_constant(
                     INC_REG,
-                    vec![DReg(REG_ACC)],
+                    vec![DReg(Register::Acc)],
                     "  lb4 : inc acc",
                     "inc acc",
                     7
@@ -765,16 +1342,16 @@ mod test {
             parse_op(&mut program_model, "ADD d2 reg", 11).unwrap();
             parse_op(&mut program_model, "add d3 num", 12).unwrap();
 
-            program_model.validate().unwrap();
+            program_model.validate(false).unwrap();
 
-            assert_eq!(program_model.ops[0], make_op_model(ADD_REG_VAL, vec![DReg(REG_D0), Num(52)], "ADD D0 x34", 5));
-            assert_eq!(program_model.ops[1], make_op_model(ADD_REG_VAL, vec![DReg(REG_D0), Num(245)], "ADD D0 245", 6));
-            assert_eq!(program_model.ops[2], make_op_model(ADD_REG_VAL, vec![DReg(REG_D0), Num(97)], "ADD d0 'a'", 7));
-            assert_eq!(program_model.ops[3], make_op_model(ADD_REG_REG, vec![DReg(REG_D0), DReg(REG_D1)], "ADD D0 D1", 8));
-            assert_eq!(program_model.ops[4], make_op_model(ADD_REG_AREG, vec![DReg(REG_D0), AReg(REG_A0)], "add D0 A0", 9));
-            assert_eq!(program_model.ops[5], make_op_model_constant(ADD_REG_REG, vec![DReg(REG_ACC), DReg(REG_D2)], "ADD reg D2", "ADD acc D2", 10));
-            assert_eq!(program_model.ops[6], make_op_model_constant(ADD_REG_REG, vec![DReg(REG_D2), DReg(REG_ACC)], "ADD d2 reg", "ADD d2 acc", 11));
-            assert_eq!(program_model.ops[7], make_op_model_constant(ADD_REG_VAL, vec![DReg(REG_D3), Num(4)], "add d3 num", "add d3 4", 12));
+            assert_eq!(program_model.ops[0], make_op_model(ADD_REG_VAL, vec![DReg(Register::D0), Num(52)], "ADD D0 x34", 5));
+            assert_eq!(program_model.ops[1], make_op_model(ADD_REG_VAL, vec![DReg(Register::D0), Num(245)], "ADD D0 245", 6));
+            assert_eq!(program_model.ops[2], make_op_model(ADD_REG_VAL, vec![DReg(Register::D0), Num(97)], "ADD d0 'a'", 7));
+            assert_eq!(program_model.ops[3], make_op_model(ADD_REG_REG, vec![DReg(Register::D0), DReg(Register::D1)], "ADD D0 D1", 8));
+            assert_eq!(program_model.ops[4], make_op_model(ADD_REG_AREG, vec![DReg(Register::D0), AReg(Register::A0)], "add D0 A0", 9));
+            assert_eq!(program_model.ops[5], make_op_model_constant(ADD_REG_REG, vec![DReg(Register::Acc), DReg(Register::D2)], "ADD reg D2", "ADD acc D2", 10));
+            assert_eq!(program_model.ops[6], make_op_model_constant(ADD_REG_REG, vec![DReg(Register::D2), DReg(Register::Acc)], "ADD d2 reg", "ADD d2 acc", 11));
+            assert_eq!(program_model.ops[7], make_op_model_constant(ADD_REG_VAL, vec![DReg(Register::D3), Num(4)], "add d3 num", "add d3 4", 12));
         }
 
         #[test]
@@ -792,16 +1369,16 @@ mod test {
             parse_op(&mut program_model, "SUB d2 reg", 11).unwrap();
             parse_op(&mut program_model, "sub d3 num", 12).unwrap();
 
-            program_model.validate().unwrap();
+            program_model.validate(false).unwrap();
 
-            assert_eq!(program_model.ops[0], make_op_model(SUB_REG_VAL, vec![DReg(REG_D0), Num(52)], "SUB D0 x34", 5));
-            assert_eq!(program_model.ops[1], make_op_model(SUB_REG_VAL, vec![DReg(REG_D0), Num(245)], "SUB D0 245", 6));
-            assert_eq!(program_model.ops[2], make_op_model(SUB_REG_VAL, vec![DReg(REG_D0), Num(97)], "SUB d0 'a'", 7));
-            assert_eq!(program_model.ops[3], make_op_model(SUB_REG_REG, vec![DReg(REG_D0), DReg(REG_D1)], "SUB D0 D1", 8));
-            assert_eq!(program_model.ops[4], make_op_model(SUB_REG_AREG, vec![DReg(REG_D0), AReg(REG_A0)], "sub D0 A0", 9));
-            assert_eq!(program_model.ops[5], make_op_model_constant(SUB_REG_REG, vec![DReg(REG_ACC), DReg(REG_D2)], "sUb reg D2", "sUb acc D2", 10));
-            assert_eq!(program_model.ops[6], make_op_model_constant(SUB_REG_REG, vec![DReg(REG_D2), DReg(REG_ACC)], "SUB d2 reg", "SUB d2 acc", 11));
-            assert_eq!(program_model.ops[7], make_op_model_constant(SUB_REG_VAL, vec![DReg(REG_D3), Num(4)], "sub d3 num", "sub d3 4", 12));
+            assert_eq!(program_model.ops[0], make_op_model(SUB_REG_VAL, vec![DReg(Register::D0), Num(52)], "SUB D0 x34", 5));
+            assert_eq!(program_model.ops[1], make_op_model(SUB_REG_VAL, vec![DReg(Register::D0), Num(245)], "SUB D0 245", 6));
+            assert_eq!(program_model.ops[2], make_op_model(SUB_REG_VAL, vec![DReg(Register::D0), Num(97)], "SUB d0 'a'", 7));
+            assert_eq!(program_model.ops[3], make_op_model(SUB_REG_REG, vec![DReg(Register::D0), DReg(Register::D1)], "SUB D0 D1", 8));
+            assert_eq!(program_model.ops[4], make_op_model(SUB_REG_AREG, vec![DReg(Register::D0), AReg(Register::A0)], "sub D0 A0", 9));
+            assert_eq!(program_model.ops[5], make_op_model_constant(SUB_REG_REG, vec![DReg(Register::Acc), DReg(Register::D2)], "sUb reg D2", "sUb acc D2", 10));
+            assert_eq!(program_model.ops[6], make_op_model_constant(SUB_REG_REG, vec![DReg(Register::D2), DReg(Register::Acc)], "SUB d2 reg", "SUB d2 acc", 11));
+            assert_eq!(program_model.ops[7], make_op_model_constant(SUB_REG_VAL, vec![DReg(Register::D3), Num(4)], "sub d3 num", "sub d3 4", 12));
         }
 
         #[test]
@@ -813,11 +1390,11 @@ mod test {
             parse_op(&mut program_model, "inc a1", 6).unwrap();
             parse_op(&mut program_model, "inc reg", 7).unwrap();
 
-            program_model.validate().unwrap();
+            program_model.validate(false).unwrap();
 
-            assert_eq!(program_model.ops[0], make_op_model(INC_REG, vec![DReg(REG_D0)], "inc d0", 5));
-            assert_eq!(program_model.ops[1], make_op_model(INC_REG, vec![AReg(REG_A1)], "inc a1", 6));
-            assert_eq!(program_model.ops[2], make_op_model_constant(INC_REG, vec![DReg(REG_ACC)], "inc reg", "inc acc", 7));
+            assert_eq!(program_model.ops[0], make_op_model(INC_REG, vec![DReg(Register::D0)], "inc d0", 5));
+            assert_eq!(program_model.ops[1], make_op_model(INC_REG, vec![AReg(Register::A1)], "inc a1", 6));
+            assert_eq!(program_model.ops[2], make_op_model_constant(INC_REG, vec![DReg(Register::Acc)], "inc reg", "inc acc", 7));
         }
 
         #[test]
@@ -829,11 +1406,11 @@ mod test {
             parse_op(&mut program_model, "dec a1", 6).unwrap();
             parse_op(&mut program_model, "dec n", 7).unwrap();
 
-            program_model.validate().unwrap();
+            program_model.validate(false).unwrap();
             
-            assert_eq!(program_model.ops[0], make_op_model(DEC_REG, vec![DReg(REG_D0)], "dec d0", 5));
-            assert_eq!(program_model.ops[1], make_op_model(DEC_REG, vec![AReg(REG_A1)], "dec a1", 6));
-            assert_eq!(program_model.ops[2], make_op_model_constant(DEC_REG, vec![AReg(REG_A1)], "dec n", "dec a1", 7));
+            assert_eq!(program_model.ops[0], make_op_model(DEC_REG, vec![DReg(Register::D0)], "dec d0", 5));
+            assert_eq!(program_model.ops[1], make_op_model(DEC_REG, vec![AReg(Register::A1)], "dec a1", 6));
+            assert_eq!(program_model.ops[2], make_op_model_constant(DEC_REG, vec![AReg(Register::A1)], "dec n", "dec a1", 7));
         }
 
         #[test]
@@ -848,13 +1425,13 @@ mod test {
             parse_op(&mut program_model, "cmp d0 d1 a0", 10).unwrap();
             parse_op(&mut program_model, "cmp d0 a0", 11).unwrap();
 
-            assert_eq!(program_model.ops[0], make_op_model(CMP_REG_REG, vec![DReg(REG_D3), DReg(REG_D2)], "cmp d3 d2", 5));
-            assert_eq!(program_model.ops[1], make_op_model(CMP_REG_VAL, vec![DReg(REG_ACC), Num(10)], "cmp acc 10", 6));
-            assert_eq!(program_model.ops[2], make_op_model(CMP_AREG_AREG, vec![AReg(REG_A0), AReg(REG_A1)], "cmp a0 a1", 7));
-            assert_eq!(program_model.ops[3], make_op_model(CMP_AREG_ADDR, vec![AReg(REG_A0), Addr(100)], "cmp a0 @100", 8));
-            assert_eq!(program_model.ops[4], make_op_model(CMP_AREG_REG_REG, vec![AReg(REG_A0), DReg(REG_D0), DReg(REG_D1)], "cmp a0 d0 d1", 9));
-            assert_eq!(program_model.ops[5], make_op_model(CMP_REG_REG_AREG, vec![DReg(REG_D0), DReg(REG_D1), AReg(REG_A0)], "cmp d0 d1 a0", 10));
-            assert_eq!(program_model.ops[6], make_op_model(CMP_REG_AREG, vec![DReg(REG_D0), AReg(REG_A0)], "cmp d0 a0", 11));
+            assert_eq!(program_model.ops[0], make_op_model(CMP_REG_REG, vec![DReg(Register::D3), DReg(Register::D2)], "cmp d3 d2", 5));
+            assert_eq!(program_model.ops[1], make_op_model(CMP_REG_VAL, vec![DReg(Register::Acc), Num(10)], "cmp acc 10", 6));
+            assert_eq!(program_model.ops[2], make_op_model(CMP_AREG_AREG, vec![AReg(Register::A0), AReg(Register::A1)], "cmp a0 a1", 7));
+            assert_eq!(program_model.ops[3], make_op_model(CMP_AREG_ADDR, vec![AReg(Register::A0), Addr(100)], "cmp a0 @100", 8));
+            assert_eq!(program_model.ops[4], make_op_model(CMP_AREG_REG_REG, vec![AReg(Register::A0), DReg(Register::D0), DReg(Register::D1)], "cmp a0 d0 d1", 9));
+            assert_eq!(program_model.ops[5], make_op_model(CMP_REG_REG_AREG, vec![DReg(Register::D0), DReg(Register::D1), AReg(Register::A0)], "cmp d0 d1 a0", 10));
+            assert_eq!(program_model.ops[6], make_op_model(CMP_REG_AREG, vec![DReg(Register::D0), AReg(Register::A0)], "cmp d0 a0", 11));
         }
 
         #[test]
@@ -869,13 +1446,13 @@ mod test {
             parse_op(&mut program_model, "cpy d0 d1 a0", 10).unwrap();
             parse_op(&mut program_model, "cpy d0 a0", 11).unwrap();
 
-            assert_eq!(program_model.ops[0], make_op_model(CPY_REG_REG, vec![DReg(REG_D3), DReg(REG_D2)], "cpy d3 d2", 5));
-            assert_eq!(program_model.ops[1], make_op_model(CPY_REG_VAL, vec![DReg(REG_ACC), Num(10)], "cpy acc 10", 6));
-            assert_eq!(program_model.ops[2], make_op_model(CPY_AREG_AREG, vec![AReg(REG_A0), AReg(REG_A1)], "cpy a0 a1", 7));
-            assert_eq!(program_model.ops[3], make_op_model(CPY_AREG_ADDR, vec![AReg(REG_A0), Addr(100)], "cpy a0 @100", 8));
-            assert_eq!(program_model.ops[4], make_op_model(CPY_AREG_REG_REG, vec![AReg(REG_A0), DReg(REG_D0), DReg(REG_D1)], "cpy a0 d0 d1", 9));
-            assert_eq!(program_model.ops[5], make_op_model(CPY_REG_REG_AREG, vec![DReg(REG_D0), DReg(REG_D1), AReg(REG_A0)], "cpy d0 d1 a0", 10));
-            assert_eq!(program_model.ops[6], make_op_model(CPY_REG_AREG, vec![DReg(REG_D0), AReg(REG_A0)], "cpy d0 a0", 11));
+            assert_eq!(program_model.ops[0], make_op_model(CPY_REG_REG, vec![DReg(Register::D3), DReg(Register::D2)], "cpy d3 d2", 5));
+            assert_eq!(program_model.ops[1], make_op_model(CPY_REG_VAL, vec![DReg(Register::Acc), Num(10)], "cpy acc 10", 6));
+            assert_eq!(program_model.ops[2], make_op_model(CPY_AREG_AREG, vec![AReg(Register::A0), AReg(Register::A1)], "cpy a0 a1", 7));
+            assert_eq!(program_model.ops[3], make_op_model(CPY_AREG_ADDR, vec![AReg(Register::A0), Addr(100)], "cpy a0 @100", 8));
+            assert_eq!(program_model.ops[4], make_op_model(CPY_AREG_REG_REG, vec![AReg(Register::A0), DReg(Register::D0), DReg(Register::D1)], "cpy a0 d0 d1", 9));
+            assert_eq!(program_model.ops[5], make_op_model(CPY_REG_REG_AREG, vec![DReg(Register::D0), DReg(Register::D1), AReg(Register::A0)], "cpy d0 d1 a0", 10));
+            assert_eq!(program_model.ops[6], make_op_model(CPY_REG_AREG, vec![DReg(Register::D0), AReg(Register::A0)], "cpy d0 a0", 11));
         }
 
         #[test]
@@ -902,11 +1479,11 @@ mod test {
                 parse_op(&mut program_model, &format!("{} @200", op), 8).unwrap();
                 parse_op(&mut program_model, &format!("{} lbl", op), 9).unwrap();
 
-                program_model.validate().unwrap();
+                program_model.validate(false).unwrap();
 
                 assert_eq!(program_model.ops[0], make_op_model_constant(op_addr, vec![Addr(256)], &format!("lbl: {} addr", op), &format!("{} @x100", op), 5), "{}", op);
-                assert_eq!(program_model.ops[1], make_op_model_constant(op_areg, vec![AReg(REG_A1)], &format!("{} areg", op), &format!("{} a1", op), 6), "{}", op);
-                assert_eq!(program_model.ops[2], make_op_model(op_areg, vec![AReg(REG_A0)], &format!("{} a0", op),7), "{}", op);
+                assert_eq!(program_model.ops[1], make_op_model_constant(op_areg, vec![AReg(Register::A1)], &format!("{} areg", op), &format!("{} a1", op), 6), "{}", op);
+                assert_eq!(program_model.ops[2], make_op_model(op_areg, vec![AReg(Register::A0)], &format!("{} a0", op),7), "{}", op);
                 assert_eq!(program_model.ops[3], make_op_model(op_addr, vec![Addr(200)], &format!("{} @200", op), 8), "{}", op);
                 assert_eq!(program_model.ops[4], make_op_model(op_addr, vec![Lbl(String::from("lbl"))], &format!("{} lbl", op), 9), "{}", op);   
             }
@@ -919,7 +1496,7 @@ mod test {
                 let mut program_model = ProgramModel::new(String::new(), String::new());
                 parse_op(&mut program_model, op, 0).unwrap();
 
-                program_model.validate().unwrap();   
+                program_model.validate(false).unwrap();   
                 
                 assert_eq!(program_model.ops[0], make_op_model(opcode, vec![], op, 0), "{}", op);
             }
@@ -0,0 +1,254 @@
+//! Expands `.repeat` blocks before the lines are parsed
+//!
+//! ```text
+//! .repeat <count> [index]
+//!     ...
+//! .endrepeat
+//! ```
+//! The lines in the block are copied `count` times, if `index` is set then it is replaced with
+//! `0` to `count - 1` in each copy. Blocks can be nested and can be used in any section.
+
+use crate::assembler::diagnostic::{LineContext, INVALID_REPEAT};
+use crate::assembler::parser::parse_size;
+use crate::assembler::KEY_NAME_ERROR;
+use crate::constants::code::{KEYWORDS, MNEMONICS, REGISTERS};
+use anyhow::{Context, Error, Result};
+
+///Maximum number of lines a file can have after expanding
+const MAX_LINES: usize = u16::MAX as usize;
+
+///Expand all `.repeat` blocks in `lines` (pairs of line number and text)
+///
+///Each output line keeps the line number it was copied from so errors point at the source
+pub fn expand_repeats(lines: &[(usize, String)]) -> Result<Vec<(usize, String)>> {
+    let mut output = vec![];
+    expand(lines, &mut output)?;
+    Ok(output)
+}
+
+fn expand(lines: &[(usize, String)], output: &mut Vec<(usize, String)>) -> Result<()> {
+    let mut idx = 0;
+    while idx < lines.len() {
+        let (line_num, line) = &lines[idx];
+        let context = || LineContext::new(line, *line_num, INVALID_REPEAT);
+        match directive(line) {
+            Some(Directive::Repeat(params)) => {
+                let (count, index) = parse_repeat(params, *line_num).with_context(context)?;
+                let end = find_end(lines, idx)
+                    .ok_or_else(|| {
+                        Error::msg(format!("Repeat on line {} is missing .endrepeat", line_num))
+                    })
+                    .with_context(context)?;
+                let mut body = vec![];
+                expand(&lines[idx + 1..end], &mut body)?;
+                if output.len() + body.len() * count as usize > MAX_LINES {
+                    return Err(Error::msg(format!(
+                        "Repeat on line {} makes the program longer than {} lines",
+                        line_num, MAX_LINES
+                    )))
+                    .with_context(context);
+                }
+                for i in 0..count {
+                    output.extend(body.iter().map(|(num, text)| match index {
+                        Some(name) => (*num, replace_index(text, name, i)),
+                        None => (*num, text.clone()),
+                    }));
+                }
+                idx = end;
+            }
+            Some(Directive::EndRepeat) => {
+                return Err(Error::msg(format!(
+                    ".endrepeat on line {} without .repeat",
+                    line_num
+                )))
+                .with_context(context);
+            }
+            None => output.push((*line_num, line.clone())),
+        }
+        idx += 1;
+    }
+    Ok(())
+}
+
+enum Directive<'a> {
+    ///Text after `.repeat`
+    Repeat(&'a str),
+    EndRepeat,
+}
+
+fn directive(line: &str) -> Option<Directive<'_>> {
+    let content = line.split('#').next().unwrap_or_default().trim();
+    if content == ".endrepeat" {
+        Some(Directive::EndRepeat)
+    } else if content == ".repeat" || content.starts_with(".repeat ") {
+        Some(Directive::Repeat(content.trim_start_matches(".repeat")))
+    } else {
+        None
+    }
+}
+
+///Index of the `.endrepeat` matching the `.repeat` at `start`
+fn find_end(lines: &[(usize, String)], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (idx, (_, line)) in lines.iter().enumerate().skip(start) {
+        match directive(line) {
+            Some(Directive::Repeat(_)) => depth += 1,
+            Some(Directive::EndRepeat) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx);
+                }
+            }
+            None => {}
+        }
+    }
+    None
+}
+
+fn parse_repeat(params: &str, line_num: usize) -> Result<(u16, Option<&str>)> {
+    let params = params.split_whitespace().collect::<Vec<&str>>();
+    let count = match params.first() {
+        Some(count) if params.len() <= 2 => parse_size(count),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        Error::msg(format!(
+            "Error parsing repeat on line {}, format must be .repeat <count> [index], e.g. .repeat 8 i\nCount must be 1-65535 or x1-xFFFF",
+            line_num
+        ))
+    })?;
+    let index = params.get(1).copied();
+    if let Some(index) = index {
+        let lowercased = index.to_lowercase();
+        let lowercased = lowercased.as_str();
+        let is_reserved = REGISTERS.contains(&lowercased)
+            || KEYWORDS.contains(&lowercased)
+            || MNEMONICS.contains(&lowercased);
+        let starts_with_letter = index.starts_with(|chr: char| chr.is_ascii_alphabetic());
+        let is_valid = index
+            .chars()
+            .all(|chr| chr.is_ascii_alphanumeric() || chr == '_');
+        if is_reserved || !starts_with_letter || !is_valid {
+            return Err(Error::msg(format!(
+                "Invalid repeat index '{}' on line {}\n\n{}",
+                index, line_num, *KEY_NAME_ERROR
+            )));
+        }
+    }
+    Ok((count, index))
+}
+
+///Replace every whole word `name` in `line` with `value`, except in chars and strings
+fn replace_index(line: &str, name: &str, value: u16) -> String {
+    let mut output = String::with_capacity(line.len());
+    let mut word = String::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let is_word_chr = |chr: char| chr.is_ascii_alphanumeric() || chr == '_';
+    for chr in line.chars() {
+        if quote.is_none() && is_word_chr(chr) {
+            word.push(chr);
+            continue;
+        }
+        if !word.is_empty() {
+            if word == name {
+                output.push_str(&value.to_string());
+            } else {
+                output.push_str(&word);
+            }
+            word.clear();
+        }
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if chr == '\\' => escaped = true,
+            Some(open) if chr == open => quote = None,
+            None if chr == '\'' || chr == '"' => quote = Some(chr),
+            _ => {}
+        }
+        output.push(chr);
+    }
+    if word == name {
+        output.push_str(&value.to_string());
+    } else {
+        output.push_str(&word);
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn numbered(lines: &[&str]) -> Vec<(usize, String)> {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_replace_index() {
+        assert_eq!(replace_index("cpy d0 i", "i", 3), "cpy d0 3");
+        assert_eq!(replace_index("add d0 i # i", "i", 12), "add d0 12 # 12");
+        assert_eq!(replace_index("prtc 'i'", "i", 1), "prtc 'i'");
+        assert_eq!(replace_index("cpy a0 i_2 idx", "i", 1), "cpy a0 i_2 idx");
+        assert_eq!(replace_index("prtc '\\'' i", "i", 5), "prtc '\\'' 5");
+    }
+
+    #[test]
+    fn test_expand_repeats() {
+        let lines = numbered(&["nop", ".repeat 2 i", "prt i", ".endrepeat", "halt"]);
+        assert_eq!(
+            expand_repeats(&lines).unwrap(),
+            vec![
+                (1, String::from("nop")),
+                (3, String::from("prt 0")),
+                (3, String::from("prt 1")),
+                (5, String::from("halt")),
+            ]
+        );
+
+        let lines = numbered(&[
+            ".repeat 2 row # rows",
+            ".repeat x2 col",
+            "cpy d0 row",
+            "cpy d1 col",
+            ".endrepeat",
+            ".endrepeat",
+        ]);
+        let expanded: Vec<String> = expand_repeats(&lines)
+            .unwrap()
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect();
+        assert_eq!(
+            expanded,
+            vec![
+                "cpy d0 0", "cpy d1 0", "cpy d0 0", "cpy d1 1", "cpy d0 1", "cpy d1 0", "cpy d0 1",
+                "cpy d1 1"
+            ]
+        );
+
+        let lines = numbered(&[".repeat 3", "nop", ".endrepeat"]);
+        assert_eq!(expand_repeats(&lines).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_invalid_repeats() {
+        assert!(expand_repeats(&numbered(&[".repeat 2 i", "nop"])).is_err());
+        assert!(expand_repeats(&numbered(&["nop", ".endrepeat"])).is_err());
+        assert!(expand_repeats(&numbered(&[".repeat", "nop", ".endrepeat"])).is_err());
+        assert!(expand_repeats(&numbered(&[".repeat 0", "nop", ".endrepeat"])).is_err());
+        assert!(expand_repeats(&numbered(&[".repeat 2 d0", "nop", ".endrepeat"])).is_err());
+        assert!(expand_repeats(&numbered(&[".repeat 2 i j", "nop", ".endrepeat"])).is_err());
+        assert!(expand_repeats(&numbered(&[
+            ".repeat 1000",
+            ".repeat 1000",
+            "nop",
+            ".endrepeat",
+            ".endrepeat"
+        ]))
+        .is_err());
+    }
+}
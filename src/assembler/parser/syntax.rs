@@ -0,0 +1,281 @@
+//! Source lines with their comments and blank lines kept, used to reprint BASM (see `fmt`)
+//!
+//! This only splits lines into their parts, [`super::generate_program_model`] should be used to check they are valid
+
+use crate::assembler::parser::ParseMode;
+use crate::language::split_line;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LineKind {
+    Blank,
+    ///Whole line comment, including the `#`
+    Comment(String),
+    ///Program name or version
+    Header(String),
+    Description(String),
+    ///Label from `.start`
+    Start(String),
+    ///Count and optional index from `.repeat`
+    Repeat(String),
+    EndRepeat,
+    Divider(ParseMode),
+    String {
+        key: String,
+        content: String,
+    },
+    Data {
+        key: String,
+        content: String,
+    },
+    Reserve {
+        key: String,
+        size: String,
+    },
+    Constant {
+        key: String,
+        value: String,
+    },
+    ///`fn name:`
+    Function(String),
+    EndFunction,
+    ///Label definition and/or instruction, `tokens` is empty for label only lines
+    Op {
+        label: Option<String>,
+        tokens: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SourceLine {
+    pub kind: LineKind,
+    ///Comment after the content, including the `#`
+    pub comment: Option<String>,
+    pub line_num: usize,
+}
+
+///Split `line` into content and comment (starting with `#`), if it has one
+pub fn split_comment(line: &str) -> (&str, Option<&str>) {
+    match line.find('#') {
+        Some(idx) => (&line[..idx], Some(&line[idx..])),
+        None => (line, None),
+    }
+}
+
+///Split every line of a BASM file into its parts
+pub fn parse_syntax(input: &[String]) -> Vec<SourceLine> {
+    let mut parse_mode = ParseMode::Header;
+    input
+        .iter()
+        .enumerate()
+        .map(|(idx, line)| {
+            let line_num = idx + 1;
+            let (kind, comment) = if idx < 2 {
+                (LineKind::Header(line.trim().to_owned()), None)
+            } else {
+                parse_syntax_line(&mut parse_mode, line)
+            };
+            SourceLine {
+                kind,
+                comment: comment.map(|comment| comment.trim_end().to_owned()),
+                line_num,
+            }
+        })
+        .collect()
+}
+
+fn parse_syntax_line<'a>(parse_mode: &mut ParseMode, line: &'a str) -> (LineKind, Option<&'a str>) {
+    let trimmed = line.trim();
+    if trimmed.is_empty() {
+        return (LineKind::Blank, None);
+    }
+    if trimmed.starts_with('#') {
+        return (LineKind::Comment(trimmed.to_owned()), None);
+    }
+    if trimmed == ".start" || trimmed.starts_with(".start ") {
+        let (content, comment) = split_comment(trimmed);
+        let label = content.trim_start_matches(".start").trim().to_owned();
+        return (LineKind::Start(label), comment);
+    }
+    if trimmed == ".repeat" || trimmed.starts_with(".repeat ") {
+        let (content, comment) = split_comment(trimmed);
+        let params = split_line(content.trim_start_matches(".repeat")).join(" ");
+        return (LineKind::Repeat(params), comment);
+    }
+    if split_comment(trimmed).0.trim() == ".endrepeat" {
+        return (LineKind::EndRepeat, split_comment(trimmed).1);
+    }
+    let divider = match trimmed {
+        ".strings" => Some(ParseMode::Strings),
+        ".data" => Some(ParseMode::Data),
+        ".reserve" => Some(ParseMode::Reserve),
+        ".ops" => Some(ParseMode::Ops),
+        _ => None,
+    };
+    if let Some(divider) = divider {
+        *parse_mode = divider;
+        return (LineKind::Divider(divider), None);
+    }
+    //comments aren't supported on strings and data so a # is part of the content
+    match parse_mode {
+        ParseMode::Header => (
+            LineKind::Description(trimmed.trim_start_matches(".description").trim().to_owned()),
+            None,
+        ),
+        ParseMode::Data if trimmed.split_whitespace().next() == Some("const") => {
+            let (content, comment) = split_comment(trimmed);
+            let parts = split_line(content);
+            (
+                LineKind::Constant {
+                    key: parts.get(1).copied().unwrap_or_default().to_owned(),
+                    value: parts.get(2).copied().unwrap_or_default().to_owned(),
+                },
+                comment,
+            )
+        }
+        ParseMode::Strings | ParseMode::Data => {
+            let (key, content) = trimmed.split_once('=').unwrap_or((trimmed, ""));
+            let key = key.trim().to_owned();
+            let content = content.trim().to_owned();
+            match parse_mode {
+                ParseMode::Strings => (LineKind::String { key, content }, None),
+                _ => (LineKind::Data { key, content }, None),
+            }
+        }
+        ParseMode::Reserve => {
+            let (content, comment) = split_comment(trimmed);
+            let parts = split_line(content);
+            (
+                LineKind::Reserve {
+                    key: parts.first().copied().unwrap_or_default().to_owned(),
+                    size: parts.get(1).copied().unwrap_or_default().to_owned(),
+                },
+                comment,
+            )
+        }
+        ParseMode::Ops => {
+            let (content, comment) = split_comment(trimmed);
+            let parts = split_line(content);
+            if parts.len() == 3 && parts[0].eq_ignore_ascii_case("const") {
+                return (
+                    LineKind::Constant {
+                        key: parts[1].to_owned(),
+                        value: parts[2].to_owned(),
+                    },
+                    comment,
+                );
+            }
+            if parts
+                .first()
+                .is_some_and(|part| part.eq_ignore_ascii_case("fn"))
+            {
+                let name = content.trim()[2..].trim().trim_end_matches(':').trim();
+                return (LineKind::Function(name.to_owned()), comment);
+            }
+            if parts.len() == 1 && parts[0].eq_ignore_ascii_case("endfn") {
+                return (LineKind::EndFunction, comment);
+            }
+            let (label, content) = match content.split_once(':') {
+                Some((label, content)) => (Some(label.trim().to_owned()), content),
+                None => (None, content),
+            };
+            let tokens = split_line(content).into_iter().map(String::from).collect();
+            (LineKind::Op { label, tokens }, comment)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_syntax() {
+        let input: Vec<String> = [
+            "Prog",
+            "1",
+            ".start loop # entry",
+            "# strings",
+            ".strings",
+            "hash=a # b",
+            ".reserve",
+            "buffer 64 #scratch",
+            ".ops",
+            "",
+            "const max 10",
+            ".repeat  4 i # table",
+            ".endrepeat",
+            "loop: prtc ' '   # space",
+            "FN print: #fn",
+            "endfn",
+            "end:",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        let kinds: Vec<(LineKind, Option<String>)> = parse_syntax(&input)
+            .into_iter()
+            .map(|line| (line.kind, line.comment))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (LineKind::Header(String::from("Prog")), None),
+                (LineKind::Header(String::from("1")), None),
+                (
+                    LineKind::Start(String::from("loop")),
+                    Some(String::from("# entry"))
+                ),
+                (LineKind::Comment(String::from("# strings")), None),
+                (LineKind::Divider(ParseMode::Strings), None),
+                (
+                    LineKind::String {
+                        key: String::from("hash"),
+                        content: String::from("a # b")
+                    },
+                    None
+                ),
+                (LineKind::Divider(ParseMode::Reserve), None),
+                (
+                    LineKind::Reserve {
+                        key: String::from("buffer"),
+                        size: String::from("64")
+                    },
+                    Some(String::from("#scratch"))
+                ),
+                (LineKind::Divider(ParseMode::Ops), None),
+                (LineKind::Blank, None),
+                (
+                    LineKind::Constant {
+                        key: String::from("max"),
+                        value: String::from("10")
+                    },
+                    None
+                ),
+                (
+                    LineKind::Repeat(String::from("4 i")),
+                    Some(String::from("# table"))
+                ),
+                (LineKind::EndRepeat, None),
+                (
+                    LineKind::Op {
+                        label: Some(String::from("loop")),
+                        tokens: vec![String::from("prtc"), String::from("' '")]
+                    },
+                    Some(String::from("# space"))
+                ),
+                (
+                    LineKind::Function(String::from("print")),
+                    Some(String::from("#fn"))
+                ),
+                (LineKind::EndFunction, None),
+                (
+                    LineKind::Op {
+                        label: Some(String::from("end")),
+                        tokens: vec![]
+                    },
+                    None
+                ),
+            ]
+        );
+    }
+}
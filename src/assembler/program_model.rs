@@ -1,29 +1,66 @@
+use crate::assembler::analysis::check_stack;
+use crate::assembler::diagnostic::{
+    token_span, Diagnostic, FUNCTION_FALLTHROUGH, NO_OPS, RESERVE_OVERLAPS_STACK, UNDEFINED_LABEL,
+    UNUSED_CONSTANT, UNUSED_DATA, UNUSED_LABEL, UNUSED_RESERVE, UNUSED_STRING, WRAPPED_NUMBER,
+};
+use crate::assembler::text::check_byte_len;
 use crate::assembler::{FORMAT_ERROR, KEY_NAME_ERROR};
+use crate::constants::code::{ALL_OPS, EXIT_REG, EXIT_VAL, HALT, JMP_ADDR, JMP_AREG, RET, RETI};
 use crate::constants::code::{DIVDERS, KEYWORDS, MNEMONICS, REGISTERS};
-use crate::language::parser::params::Param;
+use crate::constants::get_byte_count;
+use crate::constants::hardware::{DEFAULT_STACK_SIZE, MAX_STRING_LEN, RAM_SIZE};
+use crate::language::parser::params::{is_wrapped_literal, parse_byte_literal, Param};
+use crate::language::{split_line, TokenError};
 use anyhow::{Error, Result};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ProgramModel {
     pub name: String,
     pub version: String,
+    pub description: Option<String>,
+    ///Seconds since the UNIX epoch
+    #[serde(default)]
+    pub build_time: u64,
     pub strings: HashMap<String, StringModel>,
     pub data: HashMap<String, DataModel>,
     pub constants: HashMap<String, ConstantModel>,
     pub ops: Vec<OpModel>,
     pub labels: HashMap<String, LabelModel>,
+    ///Named RAM regions from `.reserve`, used as `@key`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub reserved: HashMap<String, ReservedModel>,
+    ///Stack size from `.reserve`, [`DEFAULT_STACK_SIZE`] is used if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stack_size: Option<u16>,
+    ///Label from `.start` where execution begins, the first op if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    ///Blocks from `fn name:` to `endfn`, in source order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub functions: Vec<FunctionModel>,
+    ///Assembled as an object for linking, labels, strings and data may be defined in other files
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub object: bool,
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LabelModel {
     pub key: String,
     pub definition: Option<Definition>,
     pub usage: Vec<Usage>,
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionModel {
+    pub name: String,
+    pub definition: Definition,
+    ///Line of `endfn`, `None` while the function is still being parsed
+    pub end_line: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ConstantModel {
     pub key: String,
     pub content: String,
@@ -31,19 +68,19 @@ pub struct ConstantModel {
     pub usage: Vec<Usage>,
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Usage {
     pub original_line: String,
     pub line_num: usize,
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Definition {
     pub original_line: String,
     pub line_num: usize,
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StringModel {
     pub key: String,
     pub content: String,
@@ -51,16 +88,30 @@ pub struct StringModel {
     pub usage: Vec<Usage>,
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DataModel {
     pub key: String,
     pub content: Vec<u8>,
     pub interpretation: Vec<Vec<u8>>,
     pub definition: Definition,
     pub usage: Vec<Usage>,
+    ///Content is loaded from a data tape when the program is run
+    pub external: bool,
+    ///Negative numbers in the content, they're stored wrapped (e.g. -1 as 255)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wrapped: Vec<String>,
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReservedModel {
+    pub key: String,
+    pub addr: u16,
+    pub size: u16,
+    pub definition: Definition,
+    pub usage: Vec<Usage>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct OpModel {
     pub opcode: u8,
     pub params: Vec<Param>,
@@ -74,26 +125,47 @@ impl ProgramModel {
         ProgramModel {
             name,
             version,
+            description: None,
+            build_time: 0,
             strings: HashMap::new(),
             data: HashMap::new(),
             constants: HashMap::new(),
             ops: vec![],
             labels: HashMap::new(),
+            reserved: HashMap::new(),
+            stack_size: None,
+            start: None,
+            functions: vec![],
+            object: false,
         }
     }
 
     pub fn validate_name(name: String) -> Result<String> {
         let trimmed = name.trim();
-        if trimmed.is_empty() || trimmed.chars().count() > 20 {
-            return Err(Error::msg("Invalid program name, must be between 1 and 20 ASCII characters, numbers and symbols"));
+        if trimmed.is_empty() {
+            return Err(Error::msg(
+                "Invalid program name, must be between 1 and 20 bytes",
+            ));
         }
+        check_byte_len("Program name", trimmed, 20)?;
         Ok(trimmed.to_string())
     }
 
     pub fn validate_version(name: String) -> Result<String> {
         let trimmed = name.trim();
-        if trimmed.is_empty() || trimmed.chars().count() > 10 {
-            return Err(Error::msg("Invalid program version, must be between 1 and 10 ASCII characters, numbers and symbols"));
+        if trimmed.is_empty() {
+            return Err(Error::msg(
+                "Invalid program version, must be between 1 and 10 bytes",
+            ));
+        }
+        check_byte_len("Program version", trimmed, 10)?;
+        Ok(trimmed.to_string())
+    }
+
+    pub fn validate_description(description: &str) -> Result<String> {
+        let trimmed = description.trim();
+        if trimmed.is_empty() || trimmed.len() > 255 || !trimmed.is_ascii() {
+            return Err(Error::msg("Invalid program description, must be between 1 and 255 ASCII characters, numbers and symbols"));
         }
         Ok(trimmed.to_string())
     }
@@ -107,6 +179,11 @@ impl ProgramModel {
         line_num: usize,
         is_label: bool,
     ) -> Result<()> {
+        self.check_key(key_type, key, line_num, is_label)
+            .map_err(|err| TokenError::error(key, err.to_string()))
+    }
+
+    fn check_key(&self, key_type: &str, key: &str, line_num: usize, is_label: bool) -> Result<()> {
         let lowercased = key.to_lowercase();
         let lowercased = lowercased.as_str();
         if REGISTERS.contains(&lowercased)
@@ -116,10 +193,7 @@ impl ProgramModel {
         {
             return Err(Error::msg(format!(
                 "Invalid {} '{}' on line {}\n\n{}",
-                key_type,
-                key,
-                line_num,
-                KEY_NAME_ERROR.to_string()
+                key_type, key, line_num, *KEY_NAME_ERROR
             )));
         }
 
@@ -129,7 +203,7 @@ impl ProgramModel {
         let starts_with_letter = lowercased
             .chars()
             .next()
-            .map_or(false, |chr| chr.is_ascii_alphabetic());
+            .is_some_and(|chr| chr.is_ascii_alphabetic());
         if has_invalid_chars || !starts_with_letter {
             return Err(Error::msg(format!(
                 "Invalid {} '{}' on line {}\n{}s can only include ASCII letters, numbers and '_' and must start with a letter",
@@ -148,6 +222,12 @@ impl ProgramModel {
                 key_type, key, line_num, data_model.definition.line_num
             )));
         }
+        if let Some(reserved_model) = self.reserved.get(key) {
+            return Err(Error::msg(format!(
+                "Invalid {} '{}' on line {}\nAlready defined as reserved memory on line {}",
+                key_type, key, line_num, reserved_model.definition.line_num
+            )));
+        }
         if let Some(constant_model) = self.constants.get(key) {
             return Err(Error::msg(format!(
                 "Invalid {} '{}' on line {}\nAlready defined as constant on line {}",
@@ -176,41 +256,328 @@ impl ProgramModel {
         Ok(())
     }
 
-    pub fn validate(&self) -> Result<()> {
+    ///Check a model not made by the parser (such as one loaded from JSON) can be turned into byte code
+    pub fn verify(&self) -> Result<()> {
+        ProgramModel::validate_name(self.name.clone())?;
+        ProgramModel::validate_version(self.version.clone())?;
+        if let Some(description) = &self.description {
+            ProgramModel::validate_description(description)?;
+        }
+        for (key, string) in &self.strings {
+            check_byte_len(&format!("String {}", key), &string.content, MAX_STRING_LEN)?;
+        }
+        for op in &self.ops {
+            if !ALL_OPS.contains(&op.opcode) {
+                return Err(Error::msg(format!(
+                    "Unknown opcode x{:02X} on line {}",
+                    op.opcode, op.line_num
+                )));
+            }
+            if op.to_bytes().0.len() != get_byte_count(op.opcode) {
+                return Err(Error::msg(format!(
+                    "Wrong params for opcode x{:02X} on line {}: {:?}",
+                    op.opcode, op.line_num, op.params
+                )));
+            }
+            for param in &op.params {
+                let (kind, key, defined) = match param {
+                    Param::Label(key) => (
+                        "Label",
+                        key,
+                        self.labels
+                            .get(key)
+                            .is_some_and(|label| label.definition.is_some()),
+                    ),
+                    Param::StrKey(key) => ("String", key, self.strings.contains_key(key)),
+                    Param::DataKey(key) => ("Data", key, self.data.contains_key(key)),
+                    _ => continue,
+                };
+                if !defined {
+                    return Err(Error::msg(format!(
+                        "{} {} used on line {} is never defined",
+                        kind, key, op.line_num
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    ///Print any warnings and return an error if there any errors (or warnings if `deny_warnings`)
+    pub fn validate(&self, deny_warnings: bool) -> Result<()> {
+        let diagnostics = self.check();
+        let (errors, warnings): (Vec<&Diagnostic>, Vec<&Diagnostic>) = diagnostics
+            .iter()
+            .partition(|diagnostic| diagnostic.is_error() || deny_warnings);
+        let warning = warnings
+            .iter()
+            .map(|diagnostic| format!("Warning: {}\n", diagnostic.message))
+            .collect::<String>();
+        let error = errors
+            .iter()
+            .map(|diagnostic| format!("{}\n", diagnostic.message))
+            .collect::<String>();
+
+        if !warning.is_empty() {
+            println!("{}", warning);
+        }
+        if error.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::msg(error))
+        }
+    }
+
+    ///Find undefined labels and unused labels, strings, data and constants
+    ///
+    ///For objects only constants are checked as everything else may be used or defined by other files
+    pub fn check(&self) -> Vec<Diagnostic> {
+        if self.object {
+            let mut output = self.check_constants();
+            output.sort_by(|lhs, rhs| lhs.line.cmp(&rhs.line).then(lhs.message.cmp(&rhs.message)));
+            return output;
+        }
         if self.ops.is_empty() {
-            return Err(Error::msg(format!("No ops found\n\n{}", FORMAT_ERROR)));
+            return vec![Diagnostic::error(
+                NO_OPS,
+                0,
+                0,
+                format!("No ops found\n\n{}", FORMAT_ERROR),
+            )];
         }
 
-        let mut error = String::new();
-        let mut warning = String::new();
+        let mut output = vec![];
 
         for label in &self.labels {
-            if label.1.definition.is_none() {
-                error.push_str(&format!("Label {} is never defined\n", label.0));
-            }
-            if label.1.usage.is_empty() {
-                warning.push_str(&format!("Label {} is never used\n", label.0));
+            match &label.1.definition {
+                None => {
+                    let usage = label.1.usage.first();
+                    output.push(
+                        Diagnostic::error(
+                            UNDEFINED_LABEL,
+                            usage.map(|usage| usage.line_num).unwrap_or(0),
+                            0,
+                            format!("Label {} is never defined", label.0),
+                        )
+                        .with_span(
+                            usage
+                                .map(|usage| token_span(&usage.original_line, label.0))
+                                .unwrap_or_default(),
+                        ),
+                    )
+                }
+                Some(definition) => {
+                    if label.1.usage.is_empty() {
+                        output.push(
+                            Diagnostic::warning(
+                                UNUSED_LABEL,
+                                definition.line_num,
+                                0,
+                                format!("Label {} is never used", label.0),
+                            )
+                            .with_span(token_span(&definition.original_line, label.0)),
+                        );
+                    }
+                }
             }
         }
 
         for string in &self.strings {
             if string.1.usage.is_empty() {
-                warning.push_str(&format!("String {} is never used\n", string.0));
+                output.push(
+                    Diagnostic::warning(
+                        UNUSED_STRING,
+                        string.1.definition.line_num,
+                        0,
+                        format!("String {} is never used", string.0),
+                    )
+                    .with_span(token_span(&string.1.definition.original_line, string.0)),
+                );
             }
         }
 
         for data in &self.data {
             if data.1.usage.is_empty() {
-                warning.push_str(&format!("Data {} is never used\n", data.0));
+                output.push(
+                    Diagnostic::warning(
+                        UNUSED_DATA,
+                        data.1.definition.line_num,
+                        0,
+                        format!("Data {} is never used", data.0),
+                    )
+                    .with_span(token_span(&data.1.definition.original_line, data.0)),
+                );
             }
         }
 
-        println!("{}", warning);
-        if error.is_empty() {
-            Ok(())
-        } else {
-            Err(Error::msg(error))
+        output.extend(self.check_wrapped());
+        output.extend(self.check_functions());
+        output.extend(self.check_constants());
+        output.extend(self.check_reserved());
+        output.extend(check_stack(self));
+
+        output.sort_by(|lhs, rhs| lhs.line.cmp(&rhs.line).then(lhs.message.cmp(&rhs.message)));
+        output
+    }
+
+    ///Warn about negative numbers, as they're stored wrapped (e.g. -1 as 255)
+    fn check_wrapped(&self) -> Vec<Diagnostic> {
+        let warning = |literal: &str, line_num: usize, original_line: &str| {
+            Diagnostic::warning(
+                WRAPPED_NUMBER,
+                line_num,
+                0,
+                format!(
+                    "{} on line {} is stored as {}",
+                    literal,
+                    line_num,
+                    parse_byte_literal(literal).unwrap_or_default()
+                ),
+            )
+            .with_span(token_span(original_line, literal))
+        };
+        let ops = self.ops.iter().flat_map(|op| {
+            split_line(&op.after_processing)
+                .into_iter()
+                .skip(1)
+                .filter(|token| is_wrapped_literal(token))
+                .map(|token| warning(token, op.line_num, &op.original_line))
+                .collect::<Vec<Diagnostic>>()
+        });
+        let data = self.data.values().flat_map(|data| {
+            data.wrapped.iter().map(move |literal| {
+                warning(
+                    literal,
+                    data.definition.line_num,
+                    &data.definition.original_line,
+                )
+            })
+        });
+        ops.chain(data).collect()
+    }
+
+    ///Warn about functions that can be reached by running off the end of the previous op
+    fn check_functions(&self) -> Vec<Diagnostic> {
+        self.functions
+            .iter()
+            .filter_map(|function| {
+                let line_num = function.definition.line_num;
+                let prev = self.ops.iter().rev().find(|op| op.line_num < line_num)?;
+                if [JMP_ADDR, JMP_AREG, RET, RETI, HALT, EXIT_REG, EXIT_VAL].contains(&prev.opcode)
+                {
+                    return None;
+                }
+                Some(
+                    Diagnostic::warning(
+                        FUNCTION_FALLTHROUGH,
+                        line_num,
+                        0,
+                        format!(
+                            "Function {} is reached by falling through from line {}",
+                            function.name, prev.line_num
+                        ),
+                    )
+                    .with_span(token_span(
+                        &function.definition.original_line,
+                        &function.name,
+                    )),
+                )
+            })
+            .collect()
+    }
+
+    ///Where the label, string, data, constant or reserved memory named `key` is defined
+    pub fn definition_of(&self, key: &str) -> Option<&Definition> {
+        if let Some(label) = self.labels.get(key) {
+            return label.definition.as_ref();
+        }
+        self.strings
+            .get(key)
+            .map(|model| &model.definition)
+            .or_else(|| self.data.get(key).map(|model| &model.definition))
+            .or_else(|| self.constants.get(key).map(|model| &model.definition))
+            .or_else(|| self.reserved.get(key).map(|model| &model.definition))
+    }
+
+    ///Index of the op each label points at
+    pub fn label_targets(&self) -> HashMap<String, usize> {
+        self.labels
+            .values()
+            .filter_map(|label| {
+                let line = label.definition.as_ref()?.line_num;
+                let idx = self.ops.iter().position(|op| op.line_num >= line)?;
+                Some((label.key.clone(), idx))
+            })
+            .collect()
+    }
+
+    ///First address after all reserved regions
+    pub fn reserved_end(&self) -> usize {
+        self.reserved
+            .values()
+            .map(|model| model.addr as usize + model.size as usize)
+            .max()
+            .unwrap_or_default()
+    }
+
+    fn check_reserved(&self) -> Vec<Diagnostic> {
+        let mut output: Vec<Diagnostic> = self
+            .reserved
+            .iter()
+            .filter(|(_, reserved)| reserved.usage.is_empty())
+            .map(|(key, reserved)| {
+                Diagnostic::warning(
+                    UNUSED_RESERVE,
+                    reserved.definition.line_num,
+                    0,
+                    format!("Reserved memory {} is never used", key),
+                )
+                .with_span(token_span(&reserved.definition.original_line, key))
+            })
+            .collect();
+        let stack_size = self
+            .stack_size
+            .map(|size| size as usize)
+            .unwrap_or(DEFAULT_STACK_SIZE);
+        let stack_start = RAM_SIZE.saturating_sub(stack_size);
+        if let Some(overlapping) = self
+            .reserved
+            .values()
+            .filter(|reserved| reserved.addr as usize + reserved.size as usize > stack_start)
+            .min_by_key(|reserved| reserved.addr)
+        {
+            output.push(Diagnostic::error(
+                RESERVE_OVERLAPS_STACK,
+                overlapping.definition.line_num,
+                0,
+                format!(
+                    "Reserved memory {} (@x{:04X}-@x{:04X}) overlaps the stack (@x{:04X}-@x{:04X}), reduce the reserved sizes or the stack size",
+                    overlapping.key,
+                    overlapping.addr,
+                    overlapping.addr as usize + overlapping.size as usize - 1,
+                    stack_start,
+                    RAM_SIZE - 1
+                ),
+            )
+            .with_span(token_span(&overlapping.definition.original_line, &overlapping.key)));
         }
+        output
+    }
+
+    fn check_constants(&self) -> Vec<Diagnostic> {
+        self.constants
+            .iter()
+            .filter(|(_, constant)| constant.usage.is_empty())
+            .map(|(key, constant)| {
+                Diagnostic::warning(
+                    UNUSED_CONSTANT,
+                    constant.definition.line_num,
+                    0,
+                    format!("Constant {} is never used", key),
+                )
+                .with_span(token_span(&constant.definition.original_line, key))
+            })
+            .collect()
     }
 }
 
@@ -224,6 +591,16 @@ impl LabelModel {
     }
 }
 
+impl FunctionModel {
+    pub fn new(name: String, definition: Definition) -> Self {
+        FunctionModel {
+            name,
+            definition,
+            end_line: None,
+        }
+    }
+}
+
 impl ConstantModel {
     pub fn new(key: String, content: String, original_line: String, line_num: usize) -> Self {
         ConstantModel {
@@ -278,6 +655,20 @@ impl DataModel {
             interpretation,
             definition: Definition::new(original_line, line_num),
             usage: vec![],
+            external: false,
+            wrapped: vec![],
+        }
+    }
+}
+
+impl ReservedModel {
+    pub fn new(key: String, addr: u16, size: u16, original_line: String, line_num: usize) -> Self {
+        ReservedModel {
+            key,
+            addr,
+            size,
+            definition: Definition::new(original_line, line_num),
+            usage: vec![],
         }
     }
 }
@@ -314,7 +705,13 @@ impl OpModel {
         let mut replacement = AddressReplacement::None;
         for param in &self.params {
             match param {
-                Param::DataReg(val) | Param::AddrReg(val) | Param::Number(val) => output.push(*val),
+                Param::DataReg(reg) | Param::AddrReg(reg) => output.push(u8::from(*reg)),
+                Param::Number(val) => output.push(*val),
+                Param::Word(val) => output.extend_from_slice(&val.to_be_bytes()),
+                Param::Indexed(areg, reg) => {
+                    output.push(u8::from(*areg));
+                    output.push(u8::from(*reg));
+                }
                 Param::Addr(addr) => output.extend_from_slice(&addr.to_be_bytes()),
                 Param::Label(lbl) => {
                     output.push(0);
@@ -341,7 +738,7 @@ impl OpModel {
 mod test {
     use super::*;
     use crate::constants::code::{JMP_ADDR, LD_AREG_DATA_VAL_VAL, PRTS_STR};
-    use crate::constants::hardware::REG_A1;
+    use crate::constants::hardware::Register;
 
     #[test]
     fn test_valid_keys() {
@@ -368,11 +765,14 @@ mod test {
     fn test_system_invalid_keys() {
         let invalid_keys = vec![
             "d0", "d1", "d2", "d3", "acc", "a0", "a1", ".data", ".strings", ".ops", "const", "add",
-            "sub", "inc", "dec", "jmp", "je", "jl", "jg", "jne", "cpy", "cmp", "over", "nover",
-            "ld", "memr", "memw", "memp", "halt", "nop", "fopen", "filer", "filew", "fchk",
-            "fseek", "fskip", "call", "ret", "swp", "prt", "prtc", "prtln", "prts", "prtd", "push",
-            "pop", "arg", "ipoll", "rchr", "rstr", "and", "or", "xor", "not", "rand", "seed",
-            "time", "debug",
+            "sub", "addc", "subc", "inc", "dec", "jmp", "je", "jl", "jg", "jne", "cpy", "cmp",
+            "over", "nover", "ld", "memr", "memw", "memp", "halt", "exit", "nop", "inth", "imask",
+            "itimer", "reti", "send", "recv", "fopen", "filer", "filew", "fchk", "fseek", "fskip",
+            "ftrunc", "fdel", "flist", "call", "ret", "swp", "prt", "prtc", "prtx", "prtln",
+            "prts", "prtd", "cls", "curs", "color", "vsync", "push", "pop", "arg", "local", "lvar",
+            "ipoll", "rchr", "rstr", "and", "or", "xor", "not", "rand", "seed", "time", "millis",
+            "sleep", "debug", "callarg", "retval", "ldi", "memcpy", "memset", "strld", "itoa",
+            "atoi", "args", "save", "loadkv", "fn", "endfn",
         ];
 
         let program_model = ProgramModel::new(String::from("TEST"), String::new());
@@ -644,6 +1044,7 @@ mod test {
             String::from(""),
             String::from("                                     "),
             String::from("abcdefghijklmnopqrstu"),
+            String::from("abcdefghijklmnopqrs£"),
         ];
 
         for name in invalid_names {
@@ -688,6 +1089,41 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_verify() {
+        let mut model = ProgramModel::new(String::from("prog"), String::from("1"));
+        model.ops.push(OpModel::new(
+            PRTS_STR,
+            vec![Param::StrKey(String::from("s_key"))],
+            String::from("prts s_key"),
+            String::from("prts s_key"),
+            3,
+        ));
+        assert_eq!(
+            model.verify().unwrap_err().to_string(),
+            "String s_key used on line 3 is never defined"
+        );
+        model.strings.insert(
+            String::from("s_key"),
+            StringModel::new(
+                String::from("s_key"),
+                String::from("a"),
+                String::from("s_key=a"),
+                2,
+            ),
+        );
+        assert!(model.verify().is_ok());
+
+        model.ops[0].params.push(Param::Number(1));
+        assert!(model.verify().is_err());
+        model.ops[0].params.pop();
+        model.ops[0].opcode = 0;
+        assert!(model.verify().is_err());
+        model.ops[0].opcode = PRTS_STR;
+        model.name = String::new();
+        assert!(model.verify().is_err());
+    }
+
     #[test]
     fn check_program_model_json_format() {
         let mut model = ProgramModel::new(String::from("prog name"), String::from("ver1"));
@@ -738,7 +1174,7 @@ mod test {
         model.ops.push(OpModel::new(
             LD_AREG_DATA_VAL_VAL,
             vec![
-                Param::AddrReg(REG_A1),
+                Param::AddrReg(Register::A1),
                 Param::DataKey(String::from("d_key")),
                 Param::Number(0),
                 Param::Number(0),
@@ -755,6 +1191,6 @@ mod test {
             12,
         ));
 
-        assert_eq!(serde_json::to_string(&model).unwrap(), String::from("{\"name\":\"prog name\",\"version\":\"ver1\",\"strings\":{\"s_key\":{\"key\":\"s_key\",\"content\":\"example string\",\"definition\":{\"original_line\":\"s_key=example string\",\"line_num\":3},\"usage\":[{\"original_line\":\"prts s_key\",\"line_num\":10}]}},\"data\":{\"d_key\":{\"key\":\"d_key\",\"content\":[1,1,1],\"interpretation\":[[1]],\"definition\":{\"original_line\":\"d_key=[[1]]\",\"line_num\":6},\"usage\":[{\"original_line\":\"ld foo d_key 0 0\",\"line_num\":11}]}},\"constants\":{\"foo\":{\"key\":\"foo\",\"content\":\"a1\",\"definition\":{\"original_line\":\"const foo a1\",\"line_num\":8},\"usage\":[{\"original_line\":\"ld foo d_key 0 0\",\"line_num\":11}]}},\"ops\":[{\"opcode\":147,\"params\":[{\"StrKey\":\"s_key\"}],\"after_processing\":\"prts s_key\",\"original_line\":\"prts s_key\",\"line_num\":10},{\"opcode\":71,\"params\":[{\"AddrReg\":33},{\"DataKey\":\"d_key\"},{\"Number\":0},{\"Number\":0}],\"after_processing\":\"ld a1 d_key 0 0\",\"original_line\":\"ld foo d_key 0 0\",\"line_num\":11},{\"opcode\":32,\"params\":[{\"Label\":\"lbl\"}],\"after_processing\":\"jmp lbl\",\"original_line\":\"jmp lbl\",\"line_num\":12}],\"labels\":{\"lbl\":{\"key\":\"lbl\",\"definition\":{\"original_line\":\"lbl:\",\"line_num\":7},\"usage\":[{\"original_line\":\"jmp lbl\",\"line_num\":12}]}}}"));
+        assert_eq!(serde_json::to_string(&model).unwrap(), String::from("{\"name\":\"prog name\",\"version\":\"ver1\",\"description\":null,\"build_time\":0,\"strings\":{\"s_key\":{\"key\":\"s_key\",\"content\":\"example string\",\"definition\":{\"original_line\":\"s_key=example string\",\"line_num\":3},\"usage\":[{\"original_line\":\"prts s_key\",\"line_num\":10}]}},\"data\":{\"d_key\":{\"key\":\"d_key\",\"content\":[1,1,1],\"interpretation\":[[1]],\"definition\":{\"original_line\":\"d_key=[[1]]\",\"line_num\":6},\"usage\":[{\"original_line\":\"ld foo d_key 0 0\",\"line_num\":11}],\"external\":false}},\"constants\":{\"foo\":{\"key\":\"foo\",\"content\":\"a1\",\"definition\":{\"original_line\":\"const foo a1\",\"line_num\":8},\"usage\":[{\"original_line\":\"ld foo d_key 0 0\",\"line_num\":11}]}},\"ops\":[{\"opcode\":147,\"params\":[{\"StrKey\":\"s_key\"}],\"after_processing\":\"prts s_key\",\"original_line\":\"prts s_key\",\"line_num\":10},{\"opcode\":71,\"params\":[{\"AddrReg\":33},{\"DataKey\":\"d_key\"},{\"Number\":0},{\"Number\":0}],\"after_processing\":\"ld a1 d_key 0 0\",\"original_line\":\"ld foo d_key 0 0\",\"line_num\":11},{\"opcode\":32,\"params\":[{\"Label\":\"lbl\"}],\"after_processing\":\"jmp lbl\",\"original_line\":\"jmp lbl\",\"line_num\":12}],\"labels\":{\"lbl\":{\"key\":\"lbl\",\"definition\":{\"original_line\":\"lbl:\",\"line_num\":7},\"usage\":[{\"original_line\":\"jmp lbl\",\"line_num\":12}]}}}"));
     }
 }
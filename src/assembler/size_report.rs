@@ -0,0 +1,101 @@
+use crate::assembler::debug_model::DebugModel;
+use crate::tape_reader::parse_tape;
+use anyhow::{Error, Result};
+
+///Name used for ops before the first label
+const ENTRY: &str = "(entry)";
+
+///Size of each tape section and each function (ops from one label to the next), largest functions first
+pub fn size_report(bytes: &[u8], debug: &DebugModel) -> Result<Vec<String>> {
+    let tape = parse_tape(bytes.to_vec())?;
+    let other = bytes.len() - tape.ops.len() - tape.strings.len() - tape.data.len();
+    let mut lines = vec![
+        format!("Tape size: {} bytes", bytes.len()),
+        format!("  {:<20} {:>6}", "Ops", tape.ops.len()),
+        format!("  {:<20} {:>6}", "Strings", tape.strings.len()),
+        format!("  {:<20} {:>6}", "Data", tape.data.len()),
+        format!("  {:<20} {:>6}", "Header and tables", other),
+    ];
+    let functions = function_sizes(tape.ops.len(), debug);
+    if !functions.is_empty() {
+        lines.push(String::from("Functions:"));
+        for (name, size) in functions {
+            lines.push(format!("  {:<20} {:>6}", name, size));
+        }
+    }
+    Ok(lines)
+}
+
+///Bytes from each label to the next (or the end of the ops), largest first
+pub fn function_sizes(ops_len: usize, debug: &DebugModel) -> Vec<(String, usize)> {
+    let mut labels = debug.labels_by_addr();
+    if labels
+        .first()
+        .map(|(addr, _)| *addr != 0)
+        .unwrap_or(ops_len > 0)
+    {
+        labels.insert(0, (0, ENTRY));
+    }
+    let mut sizes: Vec<(String, usize)> = labels
+        .iter()
+        .enumerate()
+        .map(|(idx, (addr, name))| {
+            let end = labels
+                .get(idx + 1)
+                .map(|(next, _)| *next as usize)
+                .unwrap_or(ops_len);
+            (name.to_string(), end - *addr as usize)
+        })
+        .collect();
+    sizes.sort_by(|lhs, rhs| rhs.1.cmp(&lhs.1).then_with(|| lhs.0.cmp(&rhs.0)));
+    sizes
+}
+
+///Fails if `size` is over `max_size`
+pub fn check_size(size: usize, max_size: usize) -> Result<()> {
+    if size > max_size {
+        Err(Error::msg(format!(
+            "Tape is {} bytes, {} bytes over the limit of {}",
+            size,
+            size - max_size,
+            max_size
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble_source;
+
+    #[test]
+    fn test_function_sizes() {
+        let tape = assemble_source(
+            "Test\n1\n.ops\ncall small\nhalt\nbig:\ncpy d0 1\ncpy d1 2\nret\nsmall:\nret\n",
+        )
+        .unwrap();
+        assert_eq!(
+            function_sizes(12, &tape.debug),
+            vec![
+                (String::from("big"), 7),
+                (String::from(ENTRY), 4),
+                (String::from("small"), 1),
+            ]
+        );
+
+        let lines = size_report(&tape.bytes, &tape.debug).unwrap();
+        assert_eq!(lines[0], format!("Tape size: {} bytes", tape.bytes.len()));
+        assert_eq!(lines[1], format!("  {:<20} {:>6}", "Ops", 12));
+    }
+
+    #[test]
+    fn test_check_size() {
+        assert!(check_size(10, 10).is_ok());
+        assert_eq!(
+            check_size(12, 10).unwrap_err().to_string(),
+            "Tape is 12 bytes, 2 bytes over the limit of 10"
+        );
+    }
+}
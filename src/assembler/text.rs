@@ -0,0 +1,70 @@
+//! Checks for text written to tapes
+//!
+//! Length prefixes store the number of UTF-8 bytes, so lengths are always measured in bytes
+//! and never in chars. Strings, names and versions are encoded as UTF-8, chars stored in a
+//! single byte (char literals in ops and data) must be ASCII.
+
+use anyhow::{Error, Result};
+
+///Fails if `text` is more than `max_bytes` when encoded as UTF-8, `kind` describes the text for the error
+pub fn check_byte_len(kind: &str, text: &str, max_bytes: usize) -> Result<()> {
+    if text.len() <= max_bytes {
+        return Ok(());
+    }
+    let note = match text.chars().find(|chr| !chr.is_ascii()) {
+        Some(chr) => format!(
+            " ('{}' is {} bytes, non-ASCII characters use more than one byte)",
+            chr,
+            chr.len_utf8()
+        ),
+        None => String::new(),
+    };
+    Err(Error::msg(format!(
+        "{} is too long, {} bytes but max {} bytes{}",
+        kind,
+        text.len(),
+        max_bytes,
+        note
+    )))
+}
+
+///Fails if `text` has any non-ASCII chars, for text stored one byte per char
+pub fn check_ascii(kind: &str, text: &str) -> Result<()> {
+    match text.chars().enumerate().find(|(_, chr)| !chr.is_ascii()) {
+        Some((idx, chr)) => Err(Error::msg(format!(
+            "{} contains '{}' at char {}, only ASCII characters can be stored in a single byte",
+            kind,
+            chr,
+            idx + 1
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_byte_len() {
+        assert!(check_byte_len("Name", "abc", 3).is_ok());
+        assert!(check_byte_len("Name", "£1", 3).is_ok());
+        assert_eq!(
+            check_byte_len("Name", "ab£", 3).unwrap_err().to_string(),
+            "Name is too long, 4 bytes but max 3 bytes ('£' is 2 bytes, non-ASCII characters use more than one byte)"
+        );
+        assert_eq!(
+            check_byte_len("Name", "abcd", 3).unwrap_err().to_string(),
+            "Name is too long, 4 bytes but max 3 bytes"
+        );
+    }
+
+    #[test]
+    fn test_check_ascii() {
+        assert!(check_ascii("Char", "a ~").is_ok());
+        assert_eq!(
+            check_ascii("Char", "'é'").unwrap_err().to_string(),
+            "Char contains 'é' at char 2, only ASCII characters can be stored in a single byte"
+        );
+    }
+}
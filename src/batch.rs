@@ -0,0 +1,257 @@
+//! `run-all` subcommand, runs every `.tape` in a directory without a terminal on multiple threads
+//!
+//! Each tape gets its own device with no keyboard input and files kept in memory (so tapes can't
+//! interfere with each other), and is stopped after `max_cycles` instructions.
+
+use crate::device::files::MemoryFileProvider;
+use crate::device::runner::{run_tape, ExitReason, RunReport};
+use crate::device::RunOptions;
+use crate::test_runner::exit_name;
+use anyhow::{Context, Error, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const TAPE_EXTENSION: &str = "tape";
+///Tapes are stopped after this many instructions unless `--max-cycles` is used
+pub const DEFAULT_MAX_CYCLES: u64 = 10_000_000;
+
+///Result of running one tape with [`run_all`]
+#[derive(Debug)]
+pub struct TapeRun {
+    pub path: PathBuf,
+    ///Error message if the tape couldn't be read or isn't a program tape
+    pub report: std::result::Result<RunReport, String>,
+    pub duration: Duration,
+}
+
+impl TapeRun {
+    ///The tape couldn't be loaded or the program errored
+    pub fn failed(&self) -> bool {
+        match &self.report {
+            Ok(report) => report.exit == ExitReason::ProgError,
+            Err(_) => true,
+        }
+    }
+
+    fn exit_text(&self) -> String {
+        match &self.report {
+            Ok(RunReport {
+                exit: ExitReason::Exit(code),
+                ..
+            }) => format!("exit {}", code),
+            Ok(report) => exit_name(report.exit),
+            Err(_) => String::from("invalid"),
+        }
+    }
+}
+
+///Tapes in `dir`, subdirectories are searched recursively
+pub fn find_tapes(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut output = vec![];
+    search_dir(dir, &mut output).context(format!("Unable to read {}", dir.display()))?;
+    output.sort();
+    Ok(output)
+}
+
+fn search_dir(dir: &Path, output: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            search_dir(&path, output)?;
+        } else if path.extension().is_some_and(|ext| ext == TAPE_EXTENSION) {
+            output.push(path);
+        }
+    }
+    Ok(())
+}
+
+///Run every tape in `paths` using `jobs` threads, the results are in the same order as `paths`
+pub fn run_all(paths: &[PathBuf], max_cycles: u64, jobs: usize) -> Vec<TapeRun> {
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.max(1).min(paths.len()) {
+            let sender = sender.clone();
+            let next = &next;
+            scope.spawn(move || loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                match paths.get(idx) {
+                    Some(path) => {
+                        let _ = sender.send((idx, run_path(path, max_cycles)));
+                    }
+                    None => break,
+                }
+            });
+        }
+    });
+    drop(sender);
+    let mut runs: Vec<(usize, TapeRun)> = receiver.into_iter().collect();
+    runs.sort_by_key(|(idx, _)| *idx);
+    runs.into_iter().map(|(_, run)| run).collect()
+}
+
+fn run_path(path: &Path, max_cycles: u64) -> TapeRun {
+    let start = Instant::now();
+    let report = std::fs::read(path)
+        .map_err(Error::from)
+        .and_then(|bytes| {
+            run_tape(
+                bytes,
+                RunOptions {
+                    max_cycles: Some(max_cycles),
+                    files: Some(MemoryFileProvider::default()),
+                    ..RunOptions::default()
+                },
+            )
+        })
+        .map_err(|err| err.to_string());
+    TapeRun {
+        path: path.to_path_buf(),
+        report,
+        duration: start.elapsed(),
+    }
+}
+
+///Table of the exit reason, output size, instruction count and duration of each run
+pub fn format_summary(runs: &[TapeRun]) -> Vec<String> {
+    let names: Vec<String> = runs
+        .iter()
+        .map(|run| run.path.display().to_string())
+        .collect();
+    let width = names
+        .iter()
+        .map(|name| name.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    let mut lines = vec![format!(
+        "{: <width$}  {: <15} {: >10} {: >12} {: >10}",
+        "Tape",
+        "Exit",
+        "Output",
+        "Cycles",
+        "Time",
+        width = width
+    )];
+    for (run, name) in runs.iter().zip(names) {
+        let (output, cycles) = match &run.report {
+            Ok(report) => (
+                (report.stdout.len() + report.binary.len()).to_string(),
+                report.cycles.to_string(),
+            ),
+            Err(_) => (String::from("-"), String::from("-")),
+        };
+        lines.push(format!(
+            "{: <width$}  {: <15} {: >10} {: >12} {: >10}",
+            name,
+            run.exit_text(),
+            output,
+            cycles,
+            format!("{:.2?}", run.duration),
+            width = width
+        ));
+    }
+    lines
+}
+
+pub fn start(dir: &str, max_cycles: Option<&str>, jobs: Option<&str>) -> Result<()> {
+    let max_cycles = match max_cycles {
+        Some(value) => value
+            .parse::<u64>()
+            .map_err(|_| Error::msg(format!("Invalid max cycles '{}', must be a number", value)))?,
+        None => DEFAULT_MAX_CYCLES,
+    };
+    let jobs = match jobs {
+        Some(value) => match value.parse::<usize>() {
+            Ok(jobs) if jobs > 0 => jobs,
+            _ => {
+                return Err(Error::msg(format!(
+                    "Invalid jobs '{}', must be a number above 0",
+                    value
+                )))
+            }
+        },
+        None => thread::available_parallelism().map_or(1, |count| count.get()),
+    };
+    let tapes = find_tapes(Path::new(dir))?;
+    if tapes.is_empty() {
+        return Err(Error::msg(format!("No tapes found in {}", dir)));
+    }
+    println!("Running {} tapes", tapes.len());
+    let runs = run_all(&tapes, max_cycles, jobs);
+    for line in format_summary(&runs) {
+        println!("{}", line);
+    }
+    for run in runs.iter().filter(|run| run.failed()) {
+        println!("\n---- {} ----", run.path.display());
+        match &run.report {
+            Ok(report) => println!("{}", report.stderr.trim()),
+            Err(err) => println!("{}", err),
+        }
+    }
+    let failed = runs.iter().filter(|run| run.failed()).count();
+    println!("\n{} ok; {} failed", runs.len() - failed, failed);
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(Error::msg(format!("{} tapes failed", failed)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble_source;
+
+    #[test]
+    fn test_run_all() {
+        let dir = tempfile::tempdir().unwrap();
+        let write_tape = |name: &str, source: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, assemble_source(source).unwrap().bytes).unwrap();
+            path
+        };
+        let hello = write_tape("hello.tape", "Test\n1\n.ops\nprt 12\nhalt\n");
+        std::fs::create_dir(dir.path().join("nested")).unwrap();
+        let error = write_tape("nested/error.tape", "Test\n1\n.ops\nret\n");
+        let looping = write_tape("loop.tape", "Test\n1\n.ops\nloop:\njmp loop\n");
+        std::fs::write(dir.path().join("bad.tape"), [1, 2, 3]).unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "not a tape").unwrap();
+
+        let tapes = find_tapes(dir.path()).unwrap();
+        assert_eq!(
+            tapes,
+            vec![
+                dir.path().join("bad.tape"),
+                hello.clone(),
+                looping.clone(),
+                error.clone()
+            ]
+        );
+
+        let runs = run_all(&tapes, 1000, 3);
+        assert_eq!(runs.len(), 4);
+        assert!(runs[0].report.is_err());
+        assert!(runs[0].failed());
+        let report = runs[1].report.as_ref().unwrap();
+        assert_eq!(runs[1].path, hello);
+        assert_eq!(report.exit, ExitReason::Halt);
+        assert_eq!(report.stdout, "12");
+        assert!(!runs[1].failed());
+        assert_eq!(
+            runs[2].report.as_ref().unwrap().exit,
+            ExitReason::CycleLimit
+        );
+        assert_eq!(runs[3].report.as_ref().unwrap().exit, ExitReason::ProgError);
+        assert!(runs[3].failed());
+
+        let summary = format_summary(&runs);
+        assert_eq!(summary.len(), 5);
+        assert!(summary[2].contains("halt"));
+        assert!(summary[3].contains("cycle_limit"));
+        assert!(summary[4].contains("error"));
+    }
+}
@@ -0,0 +1,131 @@
+//! Times headless runs of a tape, used by the `bench` subcommand
+//!
+//! Output is discarded and there is no keyboard input, so programs that wait for input stop at the first read
+
+use crate::device::runner::{run_parsed_tape, ExitReason};
+use crate::device::RunOptions;
+use crate::tape_reader::parse_tape_strict;
+use anyhow::{Context, Error, Result};
+use std::fs::read;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    ///Instructions executed by each run
+    pub cycles: u64,
+    ///Why the last run stopped
+    pub exit: ExitReason,
+    ///Wall time of each run, parsing the tape isn't included
+    pub times: Vec<Duration>,
+}
+
+impl BenchReport {
+    pub fn total(&self) -> Duration {
+        self.times.iter().sum()
+    }
+
+    pub fn mean(&self) -> Duration {
+        self.total() / self.times.len() as u32
+    }
+
+    pub fn min(&self) -> Duration {
+        self.times.iter().min().copied().unwrap_or_default()
+    }
+
+    pub fn max(&self) -> Duration {
+        self.times.iter().max().copied().unwrap_or_default()
+    }
+
+    pub fn instructions_per_sec(&self) -> f64 {
+        let secs = self.total().as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        (self.cycles * self.times.len() as u64) as f64 / secs
+    }
+}
+
+///Run tape `bytes` to completion `iterations` times, fails if the program errors
+pub fn bench_tape(bytes: &[u8], iterations: u32, options: &RunOptions) -> Result<BenchReport> {
+    if iterations == 0 {
+        return Err(Error::msg("Iterations must be at least 1"));
+    }
+    let mut report = BenchReport {
+        cycles: 0,
+        exit: ExitReason::EoF,
+        times: Vec::with_capacity(iterations as usize),
+    };
+    for _ in 0..iterations {
+        let tape = parse_tape_strict(bytes.to_vec())?;
+        let start = Instant::now();
+        let run = run_parsed_tape(tape, vec![], options.clone())?;
+        report.times.push(start.elapsed());
+        if run.exit == ExitReason::ProgError {
+            return Err(Error::msg(format!(
+                "Program error while benchmarking:{}",
+                run.stderr
+            )));
+        }
+        report.cycles = run.cycles;
+        report.exit = run.exit;
+    }
+    Ok(report)
+}
+
+pub fn start(path: &str, iterations: &str, max_cycles: Option<&str>) -> Result<()> {
+    let iterations = iterations.parse::<u32>().map_err(|_| {
+        Error::msg(format!(
+            "Invalid iterations '{}', must be a number",
+            iterations
+        ))
+    })?;
+    let max_cycles = match max_cycles {
+        Some(value) => Some(value.parse::<u64>().map_err(|_| {
+            Error::msg(format!("Invalid max cycles '{}', must be a number", value))
+        })?),
+        None => None,
+    };
+    let bytes = read(path).context(format!("Unable to read {}", path))?;
+    let options = RunOptions {
+        max_cycles,
+        ..RunOptions::default()
+    };
+    let report = bench_tape(&bytes, iterations, &options)?;
+    println!("Iterations:       {}", report.times.len());
+    println!(
+        "Instructions:     {} per run, stopped by {:?}",
+        report.cycles, report.exit
+    );
+    println!(
+        "Wall time:        {:.3?} total, {:.3?} mean, {:.3?} min, {:.3?} max",
+        report.total(),
+        report.mean(),
+        report.min(),
+        report.max()
+    );
+    println!("Instructions/sec: {:.0}", report.instructions_per_sec());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble_source;
+
+    #[test]
+    fn test_bench_tape() {
+        let tape =
+            assemble_source("Test\n1\n.ops\ncpy d0 10\nloop:\ndec d0\ncmp d0 0\njne loop\nhalt\n")
+                .unwrap();
+        let report = bench_tape(&tape.bytes, 3, &RunOptions::default()).unwrap();
+        assert_eq!(report.times.len(), 3);
+        assert_eq!(report.cycles, 32);
+        assert_eq!(report.exit, ExitReason::Halt);
+        assert!(report.min() <= report.mean() && report.mean() <= report.max());
+
+        assert!(bench_tape(&tape.bytes, 0, &RunOptions::default()).is_err());
+
+        let tape = assemble_source("Test\n1\n.ops\nret\n").unwrap();
+        assert!(bench_tape(&tape.bytes, 1, &RunOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,811 @@
+use crate::device::files::MemoryFileProvider;
+use crate::device::internals::{Capabilities, Determinism};
+use crate::device::protocol::PipedFormat;
+use crate::device::storage::save_path;
+use crate::device::{DisplaySize, RunOptions};
+use crate::{
+    assembler, batch, bench, coverage, decompiler, device, language, lsp, tape_reader, test_runner,
+};
+use anyhow::{Error, Result};
+use clap::{crate_authors, crate_name, crate_version, App, AppSettings, Arg, SubCommand, Values};
+use git_version::git_version;
+use std::path::{Path, PathBuf};
+
+pub fn run() -> Result<()> {
+    let matches = App::new(crate_name!())
+        .version(format!("{}-{}", crate_version!(), git_version!()).as_str())
+        .author(crate_authors!())
+        .settings(&[
+            AppSettings::ArgRequiredElseHelp,
+            AppSettings::SubcommandsNegateReqs,
+            AppSettings::ArgsNegateSubcommands,
+            AppSettings::VersionlessSubcommands,
+        ])
+        .subcommand(
+            SubCommand::with_name("assemble")
+                .arg(
+                    Arg::with_name("file")
+                        .help("Compile .basm into .tape")
+                        .takes_value(true)
+                        .min_values(1)
+                        .max_values(2)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("build_debug")
+                        .help("Print assembler interpretation of program")
+                        .takes_value(false)
+                        .long("--save-intermediate")
+                        .short("-i")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("debug")
+                        .help("Output data for debugger")
+                        .takes_value(false)
+                        .long("--save-debug")
+                        .short("-d")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("deny_warnings")
+                        .help("Treat warnings (such as unused labels, strings, data and constants) as errors")
+                        .takes_value(false)
+                        .long("--deny-warnings")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("optimize")
+                        .help("Remove unreachable ops, shorten jump chains and fold constant additions")
+                        .takes_value(false)
+                        .long("--optimize")
+                        .short("-O")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("max_size")
+                        .help("Print the size of each section and function, fail if the tape is over this many bytes")
+                        .takes_value(true)
+                        .long("--max-size")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("expect")
+                        .help("Compare assembler listing against file, printing differences")
+                        .takes_value(true)
+                        .long("--expect")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("data_tape")
+                        .help("Build a data tape (.dtape) from the data section instead of a program")
+                        .takes_value(false)
+                        .long("--data-tape")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("object")
+                        .help("Build a relocatable object (.tobj) for linking, labels, strings and data may be defined in other files")
+                        .takes_value(false)
+                        .long("--object")
+                        .short("-c")
+                        .required(false)
+                        .multiple(false)
+                        .conflicts_with("data_tape"),
+                )
+                .arg(
+                    Arg::with_name("from_json")
+                        .help("Build the tape from a program model saved with --save-intermediate instead of BASM")
+                        .takes_value(false)
+                        .long("--from-json")
+                        .required(false)
+                        .multiple(false)
+                        .conflicts_with_all(&["data_tape", "object", "build_debug"]),
+                )
+                .arg(
+                    Arg::with_name("check")
+                        .help("Only parse and validate, printing errors and warnings without writing a tape")
+                        .takes_value(false)
+                        .long("--check")
+                        .required(false)
+                        .multiple(false)
+                        .conflicts_with_all(&["data_tape", "object", "from_json", "build_debug", "debug", "expect", "max_size"]),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .help("Format of --check output, text or json (one diagnostic per line)")
+                        .takes_value(true)
+                        .possible_values(&["text", "json"])
+                        .long("--format")
+                        .required(false)
+                        .multiple(false)
+                        .requires("check"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("link")
+                .arg(
+                    Arg::with_name("objects")
+                        .help("Objects (.tobj) to link, the first provides the program name and version")
+                        .takes_value(true)
+                        .min_values(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Tape file to write")
+                        .takes_value(true)
+                        .long("--output")
+                        .short("-o")
+                        .required(true)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("deny_warnings")
+                        .help("Treat warnings (such as unused labels, strings, data and constants) as errors")
+                        .takes_value(false)
+                        .long("--deny-warnings")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("optimize")
+                        .help("Remove unreachable ops, shorten jump chains and fold constant additions")
+                        .takes_value(false)
+                        .long("--optimize")
+                        .short("-O")
+                        .required(false)
+                        .multiple(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("decompile")
+                .arg(
+                    Arg::with_name("file")
+                        .help("Decompile .tape")
+                        .takes_value(true)
+                        .multiple(false)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Write decompiled BASM to file instead of printing it")
+                        .takes_value(true)
+                        .long("--output")
+                        .short("-o")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("expect")
+                        .help("Compare decompiled listing against file, printing differences")
+                        .takes_value(true)
+                        .long("--expect")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("cfg")
+                        .help("Write control flow graph of the ops to file as Graphviz DOT")
+                        .takes_value(true)
+                        .long("--cfg")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("debug")
+                        .help("Debug file to name labels, strings and data and show source lines")
+                        .takes_value(true)
+                        .long("--debug")
+                        .required(false)
+                        .multiple(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("fmt")
+                .arg(
+                    Arg::with_name("file")
+                        .help("BASM file to print in the standard format")
+                        .takes_value(true)
+                        .multiple(false)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("write")
+                        .help("Replace the file instead of printing")
+                        .takes_value(false)
+                        .long("write")
+                        .required(false)
+                        .multiple(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("help-op").arg(
+                Arg::with_name("mnemonic")
+                    .help("Instruction to describe, e.g. CPY")
+                    .takes_value(true)
+                    .multiple(false)
+                    .required(true),
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("lsp")
+                .about("Language server for BASM editors, speaks LSP over stdin/stdout"),
+        )
+        .subcommand(
+            SubCommand::with_name("tape-info").arg(
+                Arg::with_name("file")
+                    .help("Print tape name, version, description, build time and checksum")
+                    .takes_value(true)
+                    .multiple(false)
+                    .required(true),
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("inspect").arg(
+                Arg::with_name("file")
+                    .help("Print tape header, section sizes and a hexdump of each section")
+                    .takes_value(true)
+                    .multiple(false)
+                    .required(true),
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("validate").arg(
+                Arg::with_name("file")
+                    .help("Check tape instructions, jump targets and string/data addresses, printing every problem")
+                    .takes_value(true)
+                    .multiple(false)
+                    .required(true),
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("test")
+                .arg(
+                    Arg::with_name("paths")
+                        .help("Test files or directories to search for *.test.basm files, defaults to the current directory")
+                        .takes_value(true)
+                        .multiple(true)
+                        .required(false),
+                )
+                .arg(coverage_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("coverage").arg(
+                Arg::with_name("file")
+                    .help("Print hits per source line and the percentage of ops covered from a file written with --coverage")
+                    .takes_value(true)
+                    .multiple(false)
+                    .required(true),
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .arg(
+                    Arg::with_name("file")
+                        .help("Run tape without a terminal and print the wall time and instructions per second")
+                        .takes_value(true)
+                        .multiple(false)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("iterations")
+                        .help("Number of times to run the tape")
+                        .takes_value(true)
+                        .long("iterations")
+                        .short("-n")
+                        .default_value("10")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("max_cycles")
+                        .help("Stop each run after executing this many instructions")
+                        .takes_value(true)
+                        .long("max-cycles")
+                        .required(false)
+                        .multiple(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("run-all")
+                .arg(
+                    Arg::with_name("dir")
+                        .help("Run every .tape in this directory (and subdirectories) in parallel without a terminal and print a summary")
+                        .takes_value(true)
+                        .multiple(false)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("max_cycles")
+                        .help("Stop each tape after executing this many instructions, defaults to 10,000,000")
+                        .takes_value(true)
+                        .long("max-cycles")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .help("Number of tapes to run at once, defaults to the number of CPUs")
+                        .takes_value(true)
+                        .long("jobs")
+                        .short("-j")
+                        .required(false)
+                        .multiple(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("debug")
+                .arg(
+                    Arg::with_name("tape")
+                        .help("Device tape to debug")
+                        .takes_value(true)
+                        .multiple(false)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("debug_file")
+                        .help("Debug info file")
+                        .takes_value(true)
+                        .multiple(false)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("input")
+                        .help("Data tape for reading/writing, add :ro to make it read only (e.g. data.bin:ro)")
+                        .takes_value(true)
+                        .multiple(true)
+                        .required(false),
+                )
+                .arg(deny_arg())
+                .arg(mmio_arg())
+                .arg(strict_arg())
+                .arg(check_stack_arg())
+                .arg(
+                    Arg::with_name("script")
+                        .help("Run debugger commands from this file instead of the interactive UI, printing results")
+                        .takes_value(true)
+                        .long("script")
+                        .required(false)
+                        .multiple(false),
+                ),
+        )
+        .arg(
+            Arg::with_name("tape")
+                .help("Device tape to execute")
+                .takes_value(true)
+                .multiple(false)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("input")
+                .help("Data tape for reading/writing, add :ro to make it read only (e.g. data.bin:ro), or more device tapes with --multi")
+                .takes_value(true)
+                .multiple(true)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("args")
+                .help("Arguments for the program after --, read with ARGS (e.g. program.tape -- one two)")
+                .takes_value(true)
+                .multiple(true)
+                .required(false)
+                .last(true),
+        )
+        .arg(
+            Arg::with_name("piped")
+                .help("Start in piped mode")
+                .takes_value(false)
+                .multiple(false)
+                .required(false)
+                .long("piped"),
+        )
+        .arg(
+            Arg::with_name("piped_format")
+                .help("Message format for piped mode: framed (length prefixed JSON, default) or json (newline delimited JSON events)")
+                .takes_value(true)
+                .long("piped-format")
+                .possible_values(&["framed", "json"])
+                .requires("piped")
+                .required(false)
+                .multiple(false),
+        )
+        .arg(deny_arg())
+        .arg(mmio_arg())
+        .arg(strict_arg())
+        .arg(check_stack_arg())
+        .arg(
+            Arg::with_name("profile")
+                .help("Count instructions executed and print a summary when the program ends")
+                .takes_value(false)
+                .long("profile")
+                .required(false)
+                .multiple(false),
+        )
+        .arg(coverage_arg().conflicts_with_all(&["piped", "display"]))
+        .arg(
+            Arg::with_name("seed")
+                .help("Seed the random number generator so RAND gives the same numbers every run")
+                .takes_value(true)
+                .long("seed")
+                .required(false)
+                .multiple(false),
+        )
+        .arg(
+            Arg::with_name("fixed_time")
+                .help("Make TIME use a virtual clock starting at HH:MM:SS that advances one second per 1000 instructions")
+                .takes_value(true)
+                .long("fixed-time")
+                .required(false)
+                .multiple(false),
+        )
+        .arg(
+            Arg::with_name("stdin_script")
+                .help("Read keyboard input from this file instead of the terminal")
+                .takes_value(true)
+                .long("stdin-script")
+                .required(false)
+                .multiple(false)
+                .conflicts_with("piped"),
+        )
+        .arg(
+            Arg::with_name("data")
+                .help("Data tape to load external data from")
+                .takes_value(true)
+                .long("data")
+                .required(false)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("display")
+                .help("Run as a fantasy console, drawing a WxH character framebuffer from memory each VSYNC")
+                .takes_value(true)
+                .long("display")
+                .required(false)
+                .multiple(false)
+                .conflicts_with("piped"),
+        )
+        .arg(
+            Arg::with_name("allow_path")
+                .help("Directory FOPEN may open files by name from, any path is allowed if not set")
+                .takes_value(true)
+                .long("allow-path")
+                .required(false)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("sandbox")
+                .help("Directory all files must be in, FOPEN and FDEL fail for any other path (including symlinks out of it)")
+                .takes_value(true)
+                .long("sandbox")
+                .required(false)
+                .multiple(false),
+        )
+        .arg(
+            Arg::with_name("memory_files")
+                .help("Keep files in memory, input files start with their content and nothing is written to disk")
+                .takes_value(false)
+                .long("memory-files")
+                .required(false)
+                .multiple(false)
+                .conflicts_with("sandbox"),
+        )
+        .arg(
+            Arg::with_name("expect_output")
+                .help("Run without a terminal and compare the printed text against file, printing differences")
+                .takes_value(true)
+                .long("expect-output")
+                .required(false)
+                .multiple(false)
+                .conflicts_with_all(&["piped", "display", "profile", "coverage"]),
+        )
+        .arg(
+            Arg::with_name("multi")
+                .help("Run every tape given together, taking turns one instruction at a time and sharing memory B000-B0FF")
+                .takes_value(false)
+                .long("multi")
+                .required(false)
+                .multiple(false)
+                .conflicts_with_all(&["piped", "display", "profile", "coverage", "expect_output", "stdin_script"]),
+        )
+        .arg(
+            Arg::with_name("max_cycles")
+                .help("Stop the program after executing this many instructions")
+                .takes_value(true)
+                .long("max-cycles")
+                .required(false)
+                .multiple(false),
+        )
+        .get_matches();
+
+    if matches.is_present("tape") {
+        let capabilities = Capabilities::from_deny_list(matches.value_of("deny").unwrap_or(""))?;
+        let max_cycles = match matches.value_of("max_cycles") {
+            Some(value) => Some(value.parse::<u64>().map_err(|_| {
+                Error::msg(format!("Invalid max cycles '{}', must be a number", value))
+            })?),
+            None => None,
+        };
+        let determinism = Determinism {
+            seed: match matches.value_of("seed") {
+                Some(value) => Some(value.parse::<u64>().map_err(|_| {
+                    Error::msg(format!("Invalid seed '{}', must be a number", value))
+                })?),
+                None => None,
+            },
+            start_time: match matches.value_of("fixed_time") {
+                Some(value) => Some(Determinism::parse_time(value)?),
+                None => None,
+            },
+        };
+        let allowed_paths = convert(matches.values_of("allow_path"))
+            .into_iter()
+            .map(|path| {
+                PathBuf::from(path)
+                    .canonicalize()
+                    .map_err(|err| Error::msg(format!("Invalid allowed path '{}': {}", path, err)))
+            })
+            .collect::<Result<Vec<PathBuf>>>()?;
+        let sandbox = match matches.value_of("sandbox") {
+            Some(path) => Some(
+                PathBuf::from(path)
+                    .canonicalize()
+                    .map_err(|err| Error::msg(format!("Invalid sandbox '{}': {}", path, err)))?,
+            ),
+            None => None,
+        };
+        if sandbox.is_some() && !cfg!(feature = "fs") {
+            return Err(Error::msg("--sandbox needs the fs feature"));
+        }
+        let (input_paths, read_only_files) = split_read_only(convert(matches.values_of("input")));
+        let input_paths = validate(input_paths);
+        let files = if matches.is_present("memory_files") {
+            let files = MemoryFileProvider::default();
+            for path in &input_paths {
+                files.insert(*path, std::fs::read(path)?);
+            }
+            Some(files)
+        } else {
+            None
+        };
+        let options = RunOptions {
+            capabilities,
+            max_cycles,
+            max_output: None,
+            determinism,
+            profile: matches.is_present("profile"),
+            coverage: matches.value_of("coverage").map(String::from),
+            input_script: matches.value_of("stdin_script").map(String::from),
+            input_lines: vec![],
+            data_tapes: convert(matches.values_of("data"))
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            allowed_paths,
+            display: match matches.value_of("display") {
+                Some(value) => Some(DisplaySize::parse(value)?),
+                None => None,
+            },
+            files,
+            read_only_files,
+            args: convert(matches.values_of("args"))
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            save_file: Some(save_path(
+                Path::new(matches.value_of("tape").unwrap()),
+                sandbox.as_deref(),
+            )),
+            sandbox,
+            mmio: matches.is_present("mmio"),
+            strict: matches.is_present("strict"),
+            check_stack: matches.is_present("check_stack"),
+        };
+        let tape = matches.value_of("tape").unwrap();
+        if matches.is_present("multi") {
+            let mut tapes = vec![tape];
+            tapes.extend(input_paths);
+            if let Some(code) = device::start_multi(tapes, options)? {
+                crossterm::terminal::disable_raw_mode()?;
+                std::process::exit(code as i32);
+            }
+        } else if let Some(expected_path) = matches.value_of("expect_output") {
+            device::start_expect_output(tape, input_paths, options, expected_path)?;
+        } else if matches.is_present("piped") {
+            device::start_piped(
+                tape,
+                input_paths,
+                options,
+                PipedFormat::parse(matches.value_of("piped_format").unwrap_or("framed"))?,
+            )?;
+        } else if let Some(code) = device::start(tape, input_paths, options)? {
+            crossterm::terminal::disable_raw_mode()?;
+            std::process::exit(code as i32);
+        }
+    } else if let Some(matches) = matches.subcommand_matches("debug") {
+        let (input_paths, read_only_files) = split_read_only(convert(matches.values_of("input")));
+        device::start_debug(
+            matches.value_of("tape").unwrap(),
+            matches.value_of("debug_file").unwrap(),
+            validate(input_paths),
+            read_only_files,
+            Capabilities::from_deny_list(matches.value_of("deny").unwrap_or(""))?,
+            matches.is_present("mmio"),
+            matches.is_present("strict"),
+            matches.is_present("check_stack"),
+            matches.value_of("script"),
+        )?;
+    } else if let Some(matches) = matches
+        .subcommand_matches("assemble")
+        .filter(|matches| matches.is_present("check"))
+    {
+        assembler::start_check(
+            matches.value_of("file").unwrap(),
+            matches.value_of("format") == Some("json"),
+            matches.is_present("deny_warnings"),
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("assemble") {
+        assembler::start(
+            matches.value_of("file").unwrap(),
+            matches.is_present("build_debug"),
+            matches.is_present("debug"),
+            matches.is_present("deny_warnings"),
+            matches.is_present("optimize"),
+            match matches.value_of("max_size") {
+                Some(value) => Some(value.parse::<usize>().map_err(|_| {
+                    Error::msg(format!("Invalid max size '{}', must be a number", value))
+                })?),
+                None => None,
+            },
+            matches.value_of("expect"),
+            matches.is_present("data_tape"),
+            matches.is_present("object"),
+            matches.is_present("from_json"),
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("link") {
+        assembler::start_link(
+            convert(matches.values_of("objects")),
+            matches.value_of("output").unwrap(),
+            matches.is_present("deny_warnings"),
+            matches.is_present("optimize"),
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("decompile") {
+        decompiler::start(
+            matches.value_of("file").unwrap(),
+            matches.value_of("output"),
+            matches.value_of("expect"),
+            matches.value_of("cfg"),
+            matches.value_of("debug"),
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("fmt") {
+        assembler::formatter::start(
+            matches.value_of("file").unwrap(),
+            matches.is_present("write"),
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("help-op") {
+        let mnemonic = matches.value_of("mnemonic").unwrap();
+        let help = language::op_help(mnemonic)
+            .ok_or_else(|| Error::msg(format!("Unknown instruction {}", mnemonic)))?;
+        println!("{}", help);
+    } else if matches.subcommand_matches("lsp").is_some() {
+        lsp::start()?;
+    } else if let Some(matches) = matches.subcommand_matches("tape-info") {
+        tape_reader::print_info(matches.value_of("file").unwrap())?;
+    } else if let Some(matches) = matches.subcommand_matches("inspect") {
+        tape_reader::inspect(matches.value_of("file").unwrap())?;
+    } else if let Some(matches) = matches.subcommand_matches("validate") {
+        tape_reader::validate(matches.value_of("file").unwrap())?;
+    } else if let Some(matches) = matches.subcommand_matches("test") {
+        test_runner::start(
+            convert(matches.values_of("paths")),
+            matches.value_of("coverage"),
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("coverage") {
+        coverage::start(matches.value_of("file").unwrap())?;
+    } else if let Some(matches) = matches.subcommand_matches("bench") {
+        bench::start(
+            matches.value_of("file").unwrap(),
+            matches.value_of("iterations").unwrap(),
+            matches.value_of("max_cycles"),
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("run-all") {
+        batch::start(
+            matches.value_of("dir").unwrap(),
+            matches.value_of("max_cycles"),
+            matches.value_of("jobs"),
+        )?;
+    }
+
+    crossterm::terminal::disable_raw_mode()?;
+
+    Ok(())
+}
+
+fn deny_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("deny")
+        .help("Comma separated instruction families to deny: file, input, time, rand, debug, exec, path, serial")
+        .takes_value(true)
+        .long("deny")
+        .required(false)
+        .multiple(false)
+}
+
+fn mmio_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("mmio")
+        .help("Map device I/O into memory from BF00 for MEMR/MEMW")
+        .takes_value(false)
+        .long("mmio")
+        .required(false)
+        .multiple(false)
+}
+
+fn strict_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("strict")
+        .help("Warn when the program reads memory it hasn't written (MEMR, MEMP, LVAR, ARG)")
+        .takes_value(false)
+        .long("strict")
+        .required(false)
+        .multiple(false)
+}
+
+fn check_stack_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("check_stack")
+        .help("Put canaries around each CALL's saved FP and return address and stop if RET finds them overwritten")
+        .takes_value(false)
+        .long("check-stack")
+        .required(false)
+        .multiple(false)
+}
+
+fn coverage_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("coverage")
+        .help("Add the instructions executed to this file, print a report with the coverage subcommand")
+        .takes_value(true)
+        .long("coverage")
+        .required(false)
+        .multiple(false)
+}
+
+fn convert(values: Option<Values<'_>>) -> Vec<&str> {
+    if let Some(values) = values {
+        values.collect()
+    } else {
+        vec![]
+    }
+}
+
+///Remove the `:ro` suffix from input files and return the numbers of the files that had it
+fn split_read_only(files: Vec<&str>) -> (Vec<&str>, Vec<usize>) {
+    let mut read_only = vec![];
+    let files = files
+        .into_iter()
+        .enumerate()
+        .map(|(num, file)| match file.strip_suffix(":ro") {
+            Some(file) => {
+                read_only.push(num);
+                file
+            }
+            None => file,
+        })
+        .collect();
+    (files, read_only)
+}
+
+fn validate(files: Vec<&str>) -> Vec<&str> {
+    for file in files.iter() {
+        if !std::path::Path::new(file).is_file() {
+            eprintln!("'{}' is not a file", file);
+            std::process::exit(1);
+        }
+    }
+    files
+}
@@ -1,8 +1,12 @@
 use anyhow::{Context, Error, Result};
+#[cfg(feature = "terminal")]
 use crossterm::cursor::{MoveToColumn, MoveUp};
+#[cfg(feature = "terminal")]
 use crossterm::execute;
+#[cfg(feature = "terminal")]
 use crossterm::terminal::{Clear, ClearType};
 use std::fs::File;
+#[cfg(feature = "terminal")]
 use std::io::stdout;
 use std::io::{BufRead, BufReader, Read};
 use std::path::PathBuf;
@@ -38,7 +42,21 @@ pub fn read_lines(path_str: &str) -> Result<Vec<String>> {
         .collect())
 }
 
+///CRC32 (IEEE) of `bytes`
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFF_u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+    !crc
+}
+
 ///Move the terminal up one line and to first column
+#[cfg(feature = "terminal")]
 #[allow(unused_must_use)]
 pub fn reset_cursor() {
     execute!(
@@ -48,3 +66,24 @@ pub fn reset_cursor() {
         Clear(ClearType::CurrentLine)
     );
 }
+
+#[cfg(not(feature = "terminal"))]
+pub fn reset_cursor() {}
+
+///Owned lines of `text`, as returned by [`read_lines`]
+#[cfg(test)]
+pub(crate) fn lines(text: &str) -> Vec<String> {
+    text.lines().map(String::from).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(&[0, 0, 0, 0]), 0x2144DF1C);
+    }
+}
@@ -1,14 +1,105 @@
 use crate::constants::code::*;
 
+///Defines the opcode byte constants along with the matching `Opcode` enum and `ALL_OPS`
+macro_rules! opcodes {
+    ($($name:ident: $variant:ident = $value:literal,)*) => {
+        $(pub const $name: u8 = $value;)*
+
+        #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+        #[repr(u8)]
+        pub enum Opcode {
+            $($variant = $value,)*
+        }
+
+        pub const ALL_OPS: &[u8] = &[$($name,)*];
+
+        impl Opcode {
+            pub const ALL: &'static [Opcode] = &[$(Opcode::$variant,)*];
+        }
+
+        impl std::convert::TryFrom<u8> for Opcode {
+            type Error = anyhow::Error;
+
+            fn try_from(value: u8) -> Result<Self, Self::Error> {
+                match value {
+                    $($name => Ok(Opcode::$variant),)*
+                    _ => Err(anyhow::Error::msg(format!("Unknown instruction: {:02X}", value))),
+                }
+            }
+        }
+    };
+}
+
 pub mod hardware {
+    use anyhow::{Error, Result};
+    use serde::{Deserialize, Serialize};
+    use std::convert::TryFrom;
+    use std::fmt::{Display, Formatter};
+
     pub const RAM_SIZE: usize = 0xFFFF;
     pub const DATA_REG_COUNT: usize = 4;
     pub const ADDR_REG_COUNT: usize = 2;
-    pub const MAX_DATA_ARRAY_LEN: usize = 255;
+    pub const MAX_DATA_ARRAY_LEN: usize = 65535;
     pub const MAX_DATA_ARRAY_COUNT: usize = 254;
     pub const MAX_STRING_LEN: usize = 255;
     pub const MAX_STRING_BYTES: usize = 65535;
+    ///Stack size assumed when checking `.reserve` regions don't overlap the stack
+    pub const DEFAULT_STACK_SIZE: usize = 0x1000;
+    ///Written either side of the saved FP and return address by CALL when checking the stack
+    pub const STACK_CANARY: u8 = 0xA5;
     pub const MAX_DATA_BYTES: usize = 65535;
+    ///Start of the character framebuffer when running with `--display`
+    pub const DISPLAY_ADDR: u16 = 0xC000;
+    ///Most cells the framebuffer can have (i.e. width * height)
+    pub const MAX_DISPLAY_CELLS: usize = 0x2000;
+    ///Start of the memory shared by every tape when running with `--multi`
+    pub const MAILBOX_ADDR: u16 = 0xB000;
+    pub const MAILBOX_SIZE: usize = 0x100;
+    ///Start of the memory mapped I/O page when running with `--mmio`, MEMR/MEMW of these addresses
+    ///use the device instead of RAM
+    pub const MMIO_ADDR: u16 = 0xBF00;
+    pub const MMIO_SIZE: usize = 0x100;
+    ///Write: print the byte as a character
+    pub const MMIO_CHAR_OUT: u16 = MMIO_ADDR;
+    ///Write: print the byte as a number
+    pub const MMIO_NUM_OUT: u16 = MMIO_ADDR + 1;
+    ///Read: number of bytes in the keyboard buffer (max 255)
+    pub const MMIO_INPUT_STATUS: u16 = MMIO_ADDR + 2;
+    ///Read: next byte from the keyboard buffer, 0 if it's empty
+    pub const MMIO_INPUT_CHAR: u16 = MMIO_ADDR + 3;
+    ///Read: pseudorandom number
+    pub const MMIO_RAND: u16 = MMIO_ADDR + 4;
+    ///Read: 4 bytes of milliseconds since the program started (high byte first), reading the
+    ///first byte latches the value so the others match it
+    pub const MMIO_MILLIS: u16 = MMIO_ADDR + 5;
+    ///Bytes added to the keyboard buffer for keys that aren't characters (read with RCHR, detected with IPOLL)
+    pub const KEY_UP: u8 = 0x80;
+    pub const KEY_DOWN: u8 = 0x81;
+    pub const KEY_LEFT: u8 = 0x82;
+    pub const KEY_RIGHT: u8 = 0x83;
+    pub const KEY_HOME: u8 = 0x84;
+    pub const KEY_END: u8 = 0x85;
+    pub const KEY_PAGE_UP: u8 = 0x86;
+    pub const KEY_PAGE_DOWN: u8 = 0x87;
+    pub const KEY_INSERT: u8 = 0x88;
+    ///F1 is `KEY_F1`, F2 is `KEY_F1 + 1`... up to F12
+    pub const KEY_F1: u8 = 0x91;
+    ///File number that reads from the host's stdin with FILER
+    pub const STDIN_FILE: u8 = 254;
+    ///File number that writes to the host's stdout with FILEW
+    pub const STDOUT_FILE: u8 = 255;
+    ///Interrupt raised every ITIMER milliseconds
+    pub const INT_TIMER: u8 = 0;
+    ///Interrupt raised while the keyboard buffer isn't empty
+    pub const INT_INPUT: u8 = 1;
+    pub const INTERRUPT_COUNT: usize = 2;
+    ///IMASK value that masks every interrupt, set while a handler is running
+    pub const INT_MASK_ALL: u8 = 0b11;
+
+    pub const FLAG_ZERO: u8 = 0b0001;
+    pub const FLAG_CARRY: u8 = 0b0010;
+    pub const FLAG_NEGATIVE: u8 = 0b0100;
+    pub const FLAG_OVERFLOW: u8 = 0b1000;
 
     pub const REG_ACC: u8 = 0x01;
 
@@ -19,6 +110,81 @@ pub mod hardware {
 
     pub const REG_A0: u8 = 0x20;
     pub const REG_A1: u8 = 0x21;
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+    #[serde(into = "u8", try_from = "u8")]
+    pub enum Register {
+        Acc,
+        D0,
+        D1,
+        D2,
+        D3,
+        A0,
+        A1,
+    }
+
+    impl Register {
+        pub fn is_addr_reg(&self) -> bool {
+            matches!(self, Register::A0 | Register::A1)
+        }
+
+        ///Index into `Device::data_reg` or `Device::addr_reg`, ACC has no index
+        pub fn index(&self) -> Option<usize> {
+            match self {
+                Register::Acc => None,
+                Register::D0 | Register::A0 => Some(0),
+                Register::D1 | Register::A1 => Some(1),
+                Register::D2 => Some(2),
+                Register::D3 => Some(3),
+            }
+        }
+    }
+
+    impl TryFrom<u8> for Register {
+        type Error = Error;
+
+        fn try_from(value: u8) -> Result<Self> {
+            match value {
+                REG_ACC => Ok(Register::Acc),
+                REG_D0 => Ok(Register::D0),
+                REG_D1 => Ok(Register::D1),
+                REG_D2 => Ok(Register::D2),
+                REG_D3 => Ok(Register::D3),
+                REG_A0 => Ok(Register::A0),
+                REG_A1 => Ok(Register::A1),
+                _ => Err(Error::msg(format!("Invalid register: {:02X}", value))),
+            }
+        }
+    }
+
+    impl From<Register> for u8 {
+        fn from(reg: Register) -> Self {
+            match reg {
+                Register::Acc => REG_ACC,
+                Register::D0 => REG_D0,
+                Register::D1 => REG_D1,
+                Register::D2 => REG_D2,
+                Register::D3 => REG_D3,
+                Register::A0 => REG_A0,
+                Register::A1 => REG_A1,
+            }
+        }
+    }
+
+    impl Display for Register {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            let name = match self {
+                Register::Acc => "ACC",
+                Register::D0 => "D0",
+                Register::D1 => "D1",
+                Register::D2 => "D2",
+                Register::D3 => "D3",
+                Register::A0 => "A0",
+                Register::A1 => "A1",
+            };
+            write!(f, "{}", name)
+        }
+    }
 }
 
 pub mod compare {
@@ -31,163 +197,259 @@ pub mod system {
     pub const TAPE_HEADER_1: u8 = 0xFD;
     pub const TAPE_HEADER_2: u8 = 0xA0;
 
-    pub const PRG_VERSION: u8 = 1;
+    pub const PRG_VERSION: u8 = 6;
+    ///First version with a checksum, build time and description in the header
+    pub const METADATA_PRG_VERSION: u8 = 3;
+    ///First version with a relocation table for data loaded from data tapes
+    pub const RELOCATION_PRG_VERSION: u8 = 4;
+    ///First version with an entry point (set by `.start`) in the header
+    pub const ENTRY_PRG_VERSION: u8 = 5;
+    ///First version with 2 byte data array lengths, before this they were 1 byte
+    pub const WIDE_DATA_PRG_VERSION: u8 = 6;
+
+    pub const DATA_TAPE_HEADER_2: u8 = 0xDA;
+    pub const DATA_TAPE_VERSION: u8 = 2;
+    ///Data tapes before this have 1 byte data array lengths
+    pub const WIDE_DATA_TAPE_VERSION: u8 = 2;
+    ///Tapes built before the flags register, CMP stores its result in ACC and JE, JNE, JL and JG read it
+    pub const ACC_COMPARE_PRG_VERSION: u8 = 1;
 }
 
 pub mod code {
-    pub const DIVDERS: [&str; 3] = [".data", ".strings", ".ops"];
-    pub const KEYWORDS: [&str; 1] = ["const"];
-    pub const MNEMONICS: [&str; 47] = [
-        "add", "sub", "inc", "dec", "cmp", "cpy", "swp", "jmp", "je", "jg", "jl", "jne", "over",
-        "nover", "memr", "memw", "memp", "ld", "call", "ret", "push", "pop", "arg", "prt", "prtc",
-        "prtln", "prtd", "prts", "and", "or", "xor", "not", "fchk", "fopen", "fseek", "fskip",
-        "filew", "filer", "ipoll", "rchr", "rstr", "time", "rand", "seed", "debug", "halt", "nop",
+    pub const DIVDERS: [&str; 4] = [".data", ".strings", ".reserve", ".ops"];
+    pub const KEYWORDS: [&str; 5] = ["const", "callarg", "retval", "fn", "endfn"];
+    pub const MNEMONICS: [&str; 77] = [
+        "add", "sub", "addc", "subc", "inc", "dec", "cmp", "cpy", "swp", "jmp", "je", "jg", "jl",
+        "jne", "over", "nover", "memr", "memw", "memp", "ld", "call", "ret", "push", "pop", "arg",
+        "local", "lvar", "prt", "prtc", "prtx", "prtln", "prtd", "prts", "cls", "curs", "color",
+        "vsync", "and", "or", "xor", "not", "fchk", "fopen", "fseek", "fskip", "filew", "filer",
+        "ftrunc", "fdel", "flist", "ipoll", "rchr", "rstr", "time", "millis", "sleep", "rand",
+        "seed", "debug", "halt", "exit", "nop", "inth", "imask", "itimer", "reti", "send", "recv",
+        "ldi", "memcpy", "memset", "strld", "itoa", "atoi", "args", "save", "loadkv",
     ];
     pub const REGISTERS: [&str; 7] = ["d0", "d1", "d2", "d3", "acc", "a0", "a1"];
 
-    pub const ADD_REG_REG: u8 = 0x01;
-    pub const ADD_REG_VAL: u8 = 0x02;
-    pub const SUB_REG_REG: u8 = 0x03;
-    pub const SUB_REG_VAL: u8 = 0x04;
-    pub const INC_REG: u8 = 0x05;
-    pub const DEC_REG: u8 = 0x06;
-    pub const ADD_REG_AREG: u8 = 0x07;
-    pub const SUB_REG_AREG: u8 = 0x08;
-
-    pub const CPY_REG_REG: u8 = 0x10;
-    pub const CPY_REG_VAL: u8 = 0x11;
-    pub const CPY_AREG_REG_REG: u8 = 0x12;
-    pub const CPY_AREG_ADDR: u8 = 0x13;
-    pub const CPY_REG_REG_AREG: u8 = 0x14;
-    pub const CPY_AREG_AREG: u8 = 0x15;
-    pub const SWP_REG_REG: u8 = 0x16;
-    pub const SWP_AREG_AREG: u8 = 0x17;
-    pub const CPY_REG_AREG: u8 = 0x18;
-
-    pub const JMP_ADDR: u8 = 0x20;
-    pub const JMP_AREG: u8 = 0x21;
-    pub const JE_ADDR: u8 = 0x22;
-    pub const JE_AREG: u8 = 0x23;
-    pub const JNE_ADDR: u8 = 0x24;
-    pub const JNE_AREG: u8 = 0x25;
-    pub const JL_ADDR: u8 = 0x26;
-    pub const JL_AREG: u8 = 0x27;
-    pub const JG_ADDR: u8 = 0x28;
-    pub const JG_AREG: u8 = 0x29;
-    pub const OVER_ADDR: u8 = 0x2A;
-    pub const OVER_AREG: u8 = 0x2B;
-    pub const NOVER_ADDR: u8 = 0x2C;
-    pub const NOVER_AREG: u8 = 0x2D;
-
-    pub const CMP_REG_REG: u8 = 0x30;
-    pub const CMP_REG_VAL: u8 = 0x31;
-    pub const CMP_AREG_AREG: u8 = 0x32;
-    pub const CMP_AREG_ADDR: u8 = 0x33;
-    pub const CMP_REG_REG_AREG: u8 = 0x34;
-    pub const CMP_AREG_REG_REG: u8 = 0x35;
-    pub const CMP_REG_AREG: u8 = 0x36;
-
-    pub const MEMR_ADDR: u8 = 0x40;
-    pub const MEMR_AREG: u8 = 0x41;
-    pub const MEMW_ADDR: u8 = 0x42;
-    pub const MEMW_AREG: u8 = 0x43;
-    pub const LD_AREG_DATA_REG_REG: u8 = 0x44;
-    pub const LD_AREG_DATA_REG_VAL: u8 = 0x45;
-    pub const LD_AREG_DATA_VAL_REG: u8 = 0x46;
-    pub const LD_AREG_DATA_VAL_VAL: u8 = 0x47;
-
-    pub const CALL_ADDR: u8 = 0x70;
-    pub const CALL_AREG: u8 = 0x71;
-    pub const RET: u8 = 0x72;
-    pub const PUSH_REG: u8 = 0x73;
-    pub const PUSH_VAL: u8 = 0x74;
-    pub const POP_REG: u8 = 0x75;
-    pub const ARG_REG_VAL: u8 = 0x76;
-    pub const ARG_REG_REG: u8 = 0x77;
-
-    pub const PRT_REG: u8 = 0x90;
-    pub const PRT_VAL: u8 = 0x91;
-    pub const PRTLN: u8 = 0x92;
-    pub const PRTS_STR: u8 = 0x93;
-    pub const PRTC_REG: u8 = 0x94;
-    pub const PRTC_VAL: u8 = 0x95;
-    pub const MEMP_ADDR: u8 = 0x96;
-    pub const MEMP_AREG: u8 = 0x97;
-    pub const PRTD_AREG: u8 = 0x98;
-    pub const PRT_AREG: u8 = 0x99;
-    pub const PRTC_AREG: u8 = 0x9A;
-
-    pub const AND_REG_REG: u8 = 0xA0;
-    pub const AND_REG_VAL: u8 = 0xA1;
-    pub const OR_REG_REG: u8 = 0xA2;
-    pub const OR_REG_VAL: u8 = 0xA3;
-    pub const XOR_REG_REG: u8 = 0xA4;
-    pub const XOR_REG_VAL: u8 = 0xA5;
-    pub const NOT_REG: u8 = 0xA6;
-    pub const AND_REG_AREG: u8 = 0xA7;
-    pub const OR_REG_AREG: u8 = 0xA8;
-    pub const XOR_REG_AREG: u8 = 0xA9;
-
-    pub const FOPEN_REG: u8 = 0xC0;
-    pub const FILER_REG_ADDR: u8 = 0xC1;
-    pub const FILER_REG_AREG: u8 = 0xC2;
-    pub const FILEW_REG_ADDR: u8 = 0xC3;
-    pub const FILEW_REG_AREG: u8 = 0xC4;
-    pub const FSEEK_REG: u8 = 0xC5;
-    pub const FSKIP_REG_REG: u8 = 0xC6;
-    pub const FSKIP_REG_VAL: u8 = 0xC7;
-    pub const FCHK_REG_ADDR: u8 = 0xC8;
-    pub const FCHK_REG_AREG: u8 = 0xC9;
-    pub const FOPEN_VAL: u8 = 0xCA;
-    pub const FILER_VAL_ADDR: u8 = 0xCB;
-    pub const FILER_VAL_AREG: u8 = 0xCC;
-    pub const FILEW_VAL_ADDR: u8 = 0xCD;
-    pub const FILEW_VAL_AREG: u8 = 0xCE;
-    pub const FSEEK_VAL: u8 = 0xCF;
-    pub const FSKIP_VAL_REG: u8 = 0xD0;
-    pub const FSKIP_VAL_VAL: u8 = 0xD1;
-    pub const FCHK_VAL_ADDR: u8 = 0xD2;
-    pub const FCHK_VAL_AREG: u8 = 0xD3;
-    pub const FILEW_REG_REG: u8 = 0xD4;
-    pub const FILEW_REG_VAL: u8 = 0xD5;
-    pub const FILEW_VAL_REG: u8 = 0xD6;
-    pub const FILEW_VAL_VAL: u8 = 0xD7;
-
-    pub const IPOLL_ADDR: u8 = 0xE0;
-    pub const IPOLL_AREG: u8 = 0xE1;
-    pub const RCHR_REG: u8 = 0xE2;
-    pub const RSTR_ADDR: u8 = 0xE3;
-    pub const RSTR_AREG: u8 = 0xE4;
-    pub const RAND_REG: u8 = 0xE5;
-    pub const TIME: u8 = 0xE6;
-    pub const SEED_REG: u8 = 0xE7;
-
-    pub const DEBUG: u8 = 0xFD;
-    pub const NOP: u8 = 0xFE;
-    pub const HALT: u8 = 0xFF;
+    opcodes! {
+        ADD_REG_REG: AddRegReg = 0x01,
+        ADD_REG_VAL: AddRegVal = 0x02,
+        SUB_REG_REG: SubRegReg = 0x03,
+        SUB_REG_VAL: SubRegVal = 0x04,
+        INC_REG: IncReg = 0x05,
+        DEC_REG: DecReg = 0x06,
+        ADD_REG_AREG: AddRegAreg = 0x07,
+        SUB_REG_AREG: SubRegAreg = 0x08,
+        ADDC_REG_REG: AddcRegReg = 0x09,
+        ADDC_REG_VAL: AddcRegVal = 0x0A,
+        ADDC_REG_AREG: AddcRegAreg = 0x0B,
+        SUBC_REG_REG: SubcRegReg = 0x0C,
+        SUBC_REG_VAL: SubcRegVal = 0x0D,
+        SUBC_REG_AREG: SubcRegAreg = 0x0E,
+
+        CPY_REG_REG: CpyRegReg = 0x10,
+        CPY_REG_VAL: CpyRegVal = 0x11,
+        CPY_AREG_REG_REG: CpyAregRegReg = 0x12,
+        CPY_AREG_ADDR: CpyAregAddr = 0x13,
+        CPY_REG_REG_AREG: CpyRegRegAreg = 0x14,
+        CPY_AREG_AREG: CpyAregAreg = 0x15,
+        SWP_REG_REG: SwpRegReg = 0x16,
+        SWP_AREG_AREG: SwpAregAreg = 0x17,
+        CPY_REG_AREG: CpyRegAreg = 0x18,
+
+        JMP_ADDR: JmpAddr = 0x20,
+        JMP_AREG: JmpAreg = 0x21,
+        JE_ADDR: JeAddr = 0x22,
+        JE_AREG: JeAreg = 0x23,
+        JNE_ADDR: JneAddr = 0x24,
+        JNE_AREG: JneAreg = 0x25,
+        JL_ADDR: JlAddr = 0x26,
+        JL_AREG: JlAreg = 0x27,
+        JG_ADDR: JgAddr = 0x28,
+        JG_AREG: JgAreg = 0x29,
+        OVER_ADDR: OverAddr = 0x2A,
+        OVER_AREG: OverAreg = 0x2B,
+        NOVER_ADDR: NoverAddr = 0x2C,
+        NOVER_AREG: NoverAreg = 0x2D,
+
+        CMP_REG_REG: CmpRegReg = 0x30,
+        CMP_REG_VAL: CmpRegVal = 0x31,
+        CMP_AREG_AREG: CmpAregAreg = 0x32,
+        CMP_AREG_ADDR: CmpAregAddr = 0x33,
+        CMP_REG_REG_AREG: CmpRegRegAreg = 0x34,
+        CMP_AREG_REG_REG: CmpAregRegReg = 0x35,
+        CMP_REG_AREG: CmpRegAreg = 0x36,
+
+        MEMR_ADDR: MemrAddr = 0x40,
+        MEMR_AREG: MemrAreg = 0x41,
+        MEMW_ADDR: MemwAddr = 0x42,
+        MEMW_AREG: MemwAreg = 0x43,
+        LD_AREG_DATA_REG_REG: LdAregDataRegReg = 0x44,
+        LD_AREG_DATA_REG_VAL: LdAregDataRegVal = 0x45,
+        LD_AREG_DATA_VAL_REG: LdAregDataValReg = 0x46,
+        LD_AREG_DATA_VAL_VAL: LdAregDataValVal = 0x47,
+
+        CALL_ADDR: CallAddr = 0x70,
+        CALL_AREG: CallAreg = 0x71,
+        RET: Ret = 0x72,
+        PUSH_REG: PushReg = 0x73,
+        PUSH_VAL: PushVal = 0x74,
+        POP_REG: PopReg = 0x75,
+        ARG_REG_VAL: ArgRegVal = 0x76,
+        ARG_REG_REG: ArgRegReg = 0x77,
+        POP_VAL: PopVal = 0x78,
+        LOCAL_VAL: LocalVal = 0x79,
+        LVAR_REG_VAL: LvarRegVal = 0x7A,
+        LVAR_VAL_REG: LvarValReg = 0x7B,
+
+        PRT_REG: PrtReg = 0x90,
+        PRT_VAL: PrtVal = 0x91,
+        PRTLN: Prtln = 0x92,
+        PRTS_STR: PrtsStr = 0x93,
+        PRTC_REG: PrtcReg = 0x94,
+        PRTC_VAL: PrtcVal = 0x95,
+        MEMP_ADDR: MempAddr = 0x96,
+        MEMP_AREG: MempAreg = 0x97,
+        PRTD_AREG: PrtdAreg = 0x98,
+        PRT_AREG: PrtAreg = 0x99,
+        PRTC_AREG: PrtcAreg = 0x9A,
+        PRTS_AREG: PrtsAreg = 0x9B,
+        PRTX_REG: PrtxReg = 0x9C,
+        PRTX_VAL: PrtxVal = 0x9D,
+        PRTX_AREG: PrtxAreg = 0x9E,
+
+        AND_REG_REG: AndRegReg = 0xA0,
+        AND_REG_VAL: AndRegVal = 0xA1,
+        OR_REG_REG: OrRegReg = 0xA2,
+        OR_REG_VAL: OrRegVal = 0xA3,
+        XOR_REG_REG: XorRegReg = 0xA4,
+        XOR_REG_VAL: XorRegVal = 0xA5,
+        NOT_REG: NotReg = 0xA6,
+        AND_REG_AREG: AndRegAreg = 0xA7,
+        OR_REG_AREG: OrRegAreg = 0xA8,
+        XOR_REG_AREG: XorRegAreg = 0xA9,
+        SEND_REG: SendReg = 0xAA,
+        SEND_VAL: SendVal = 0xAB,
+        RECV_REG: RecvReg = 0xAC,
+        LDI_AREG_VAL: LdiAregVal = 0xAD,
+        MEMR_AREG_REG: MemrAregReg = 0xAE,
+        MEMW_AREG_REG: MemwAregReg = 0xAF,
+        MEMCPY_AREG_AREG_REG: MemcpyAregAregReg = 0xB0,
+        MEMSET_AREG_REG_REG: MemsetAregRegReg = 0xB1,
+        MEMSET_AREG_VAL_REG: MemsetAregValReg = 0xB2,
+        STRLD_AREG_STR: StrldAregStr = 0xB3,
+        STRLD_AREG_AREG: StrldAregAreg = 0xB4,
+        STRLD_AREG_DATA_VAL: StrldAregDataVal = 0xB5,
+        STRLD_AREG_DATA_REG: StrldAregDataReg = 0xB6,
+        ITOA_AREG_REG: ItoaAregReg = 0xB7,
+        ITOA_AREG_VAL: ItoaAregVal = 0xB8,
+        ATOI_AREG_REG: AtoiAregReg = 0xB9,
+        FILER_REG_AREG_AREG: FilerRegAregAreg = 0xBA,
+        FILER_VAL_AREG_AREG: FilerValAregAreg = 0xBB,
+        FILEW_REG_AREG_AREG: FilewRegAregAreg = 0xBC,
+        FILEW_VAL_AREG_AREG: FilewValAregAreg = 0xBD,
+        ARGS_ADDR: ArgsAddr = 0xBE,
+        ARGS_AREG: ArgsAreg = 0xBF,
+
+        FOPEN_REG: FopenReg = 0xC0,
+        FILER_REG_ADDR: FilerRegAddr = 0xC1,
+        FILER_REG_AREG: FilerRegAreg = 0xC2,
+        FILEW_REG_ADDR: FilewRegAddr = 0xC3,
+        FILEW_REG_AREG: FilewRegAreg = 0xC4,
+        FSEEK_REG: FseekReg = 0xC5,
+        FSKIP_REG_REG: FskipRegReg = 0xC6,
+        FSKIP_REG_VAL: FskipRegVal = 0xC7,
+        FCHK_REG_ADDR: FchkRegAddr = 0xC8,
+        FCHK_REG_AREG: FchkRegAreg = 0xC9,
+        FOPEN_VAL: FopenVal = 0xCA,
+        FILER_VAL_ADDR: FilerValAddr = 0xCB,
+        FILER_VAL_AREG: FilerValAreg = 0xCC,
+        FILEW_VAL_ADDR: FilewValAddr = 0xCD,
+        FILEW_VAL_AREG: FilewValAreg = 0xCE,
+        FSEEK_VAL: FseekVal = 0xCF,
+        FSKIP_VAL_REG: FskipValReg = 0xD0,
+        FSKIP_VAL_VAL: FskipValVal = 0xD1,
+        FCHK_VAL_ADDR: FchkValAddr = 0xD2,
+        FCHK_VAL_AREG: FchkValAreg = 0xD3,
+        FILEW_REG_REG: FilewRegReg = 0xD4,
+        FILEW_REG_VAL: FilewRegVal = 0xD5,
+        FILEW_VAL_REG: FilewValReg = 0xD6,
+        FILEW_VAL_VAL: FilewValVal = 0xD7,
+        FOPEN_REG_STR: FopenRegStr = 0xD8,
+        FOPEN_VAL_STR: FopenValStr = 0xD9,
+        FOPEN_REG_AREG: FopenRegAreg = 0xDA,
+        FOPEN_VAL_AREG: FopenValAreg = 0xDB,
+        FTRUNC_REG: FtruncReg = 0xDC,
+        FTRUNC_VAL: FtruncVal = 0xDD,
+        FDEL_REG: FdelReg = 0xDE,
+        FDEL_VAL: FdelVal = 0xDF,
+
+        IPOLL_ADDR: IpollAddr = 0xE0,
+        IPOLL_AREG: IpollAreg = 0xE1,
+        RCHR_REG: RchrReg = 0xE2,
+        RSTR_ADDR: RstrAddr = 0xE3,
+        RSTR_AREG: RstrAreg = 0xE4,
+        RAND_REG: RandReg = 0xE5,
+        TIME: Time = 0xE6,
+        SEED_REG: SeedReg = 0xE7,
+        FLIST_ADDR: FlistAddr = 0xE8,
+        FLIST_AREG: FlistAreg = 0xE9,
+        SLEEP_REG: SleepReg = 0xEA,
+        SLEEP_VAL: SleepVal = 0xEB,
+        MILLIS: Millis = 0xEC,
+        CLS: Cls = 0xED,
+        CURS_REG_REG: CursRegReg = 0xEE,
+        CURS_REG_VAL: CursRegVal = 0xEF,
+        CURS_VAL_REG: CursValReg = 0xF0,
+        CURS_VAL_VAL: CursValVal = 0xF1,
+        COLOR_REG: ColorReg = 0xF2,
+        COLOR_VAL: ColorVal = 0xF3,
+        VSYNC: Vsync = 0xF4,
+        EXIT_REG: ExitReg = 0xF5,
+        EXIT_VAL: ExitVal = 0xF6,
+        INTH_VAL_ADDR: InthValAddr = 0xF7,
+        IMASK_VAL: ImaskVal = 0xF8,
+        ITIMER_VAL: ItimerVal = 0xF9,
+        RETI: Reti = 0xFA,
+        SAVE_STR_AREG: SaveStrAreg = 0xFB,
+        LOADKV_STR_AREG: LoadkvStrAreg = 0xFC,
+
+        DEBUG: Debug = 0xFD,
+        NOP: Nop = 0xFE,
+        HALT: Halt = 0xFF,
+    }
 }
 
 pub fn get_byte_count(opcode: u8) -> usize {
     match opcode {
-        PRTLN | RET | NOP | HALT | TIME | DEBUG => 1,
+        PRTLN | RET | NOP | HALT | TIME | MILLIS | CLS | VSYNC | DEBUG | RETI => 1,
         INC_REG | DEC_REG | JMP_AREG | JE_AREG | JNE_AREG | JL_AREG | JG_AREG | OVER_AREG
         | NOVER_AREG | MEMR_AREG | MEMW_AREG | CALL_AREG | PUSH_REG | PUSH_VAL | POP_REG
-        | PRT_REG | PRT_VAL | PRTC_REG | PRTC_VAL | RCHR_REG | RAND_REG | NOT_REG | SEED_REG
-        | FSEEK_REG | FSEEK_VAL | FOPEN_REG | FOPEN_VAL | PRTD_AREG | MEMP_AREG | PRT_AREG
-        | PRTC_AREG | RSTR_AREG | IPOLL_AREG => 2,
+        | POP_VAL | LOCAL_VAL | PRT_REG | PRT_VAL | PRTC_REG | PRTC_VAL | RCHR_REG | RAND_REG
+        | NOT_REG | SEED_REG | FSEEK_REG | FSEEK_VAL | FOPEN_REG | FOPEN_VAL | PRTD_AREG
+        | MEMP_AREG | PRT_AREG | PRTC_AREG | PRTS_AREG | PRTX_REG | PRTX_VAL | PRTX_AREG
+        | RSTR_AREG | IPOLL_AREG | FTRUNC_REG | FTRUNC_VAL | FDEL_REG | FDEL_VAL | FLIST_AREG
+        | SLEEP_REG | SLEEP_VAL | COLOR_REG | COLOR_VAL | EXIT_REG | EXIT_VAL | IMASK_VAL
+        | ITIMER_VAL | SEND_REG | SEND_VAL | RECV_REG | ARGS_AREG => 2,
         ADD_REG_REG | ADD_REG_VAL | SUB_REG_REG | SUB_REG_VAL | CPY_REG_REG | CPY_REG_VAL
         | SWP_AREG_AREG | SWP_REG_REG | JMP_ADDR | JE_ADDR | JNE_ADDR | JL_ADDR | JG_ADDR
         | OVER_ADDR | CMP_AREG_AREG | CPY_AREG_AREG | NOVER_ADDR | CMP_REG_REG | CMP_REG_VAL
         | MEMR_ADDR | MEMW_ADDR | CALL_ADDR | PRTS_STR | FSKIP_REG_REG | FSKIP_REG_VAL
-        | FSKIP_VAL_REG | FSKIP_VAL_VAL | ARG_REG_VAL | ARG_REG_REG | MEMP_ADDR
-        | FILER_REG_AREG | FILER_VAL_AREG | FILEW_REG_AREG | FILEW_VAL_AREG | IPOLL_ADDR
-        | RSTR_ADDR | AND_REG_VAL | AND_REG_REG | AND_REG_AREG | OR_REG_AREG | XOR_REG_AREG
-        | OR_REG_VAL | OR_REG_REG | XOR_REG_REG | XOR_REG_VAL | FCHK_REG_AREG | FCHK_VAL_AREG
-        | ADD_REG_AREG | SUB_REG_AREG | CPY_REG_AREG | CMP_REG_AREG | FILEW_REG_REG
-        | FILEW_REG_VAL | FILEW_VAL_REG | FILEW_VAL_VAL => 3,
+        | FSKIP_VAL_REG | FSKIP_VAL_VAL | ARG_REG_VAL | ARG_REG_REG | LVAR_REG_VAL
+        | LVAR_VAL_REG | MEMP_ADDR | FILER_REG_AREG | FILER_VAL_AREG | FILEW_REG_AREG
+        | FILEW_VAL_AREG | IPOLL_ADDR | RSTR_ADDR | FLIST_ADDR | AND_REG_VAL | AND_REG_REG
+        | AND_REG_AREG | OR_REG_AREG | XOR_REG_AREG | OR_REG_VAL | OR_REG_REG | XOR_REG_REG
+        | XOR_REG_VAL | FCHK_REG_AREG | FCHK_VAL_AREG | ADD_REG_AREG | SUB_REG_AREG
+        | ADDC_REG_REG | ADDC_REG_VAL | ADDC_REG_AREG | SUBC_REG_REG | SUBC_REG_VAL
+        | SUBC_REG_AREG | CPY_REG_AREG | CMP_REG_AREG | FILEW_REG_REG | FILEW_REG_VAL
+        | FILEW_VAL_REG | FILEW_VAL_VAL | FOPEN_REG_AREG | FOPEN_VAL_AREG | CURS_REG_REG
+        | CURS_REG_VAL | CURS_VAL_REG | CURS_VAL_VAL | MEMR_AREG_REG | MEMW_AREG_REG
+        | STRLD_AREG_AREG | ITOA_AREG_REG | ITOA_AREG_VAL | ATOI_AREG_REG | ARGS_ADDR => 3,
         CMP_AREG_ADDR | CPY_AREG_ADDR | CMP_AREG_REG_REG | CMP_REG_REG_AREG | CPY_REG_REG_AREG
         | FCHK_REG_ADDR | FCHK_VAL_ADDR | CPY_AREG_REG_REG | FILER_REG_ADDR | FILEW_VAL_ADDR
-        | FILER_VAL_ADDR | FILEW_REG_ADDR => 4,
+        | FILER_VAL_ADDR | FILEW_REG_ADDR | FOPEN_REG_STR | FOPEN_VAL_STR | INTH_VAL_ADDR
+        | LDI_AREG_VAL | MEMCPY_AREG_AREG_REG | MEMSET_AREG_REG_REG | MEMSET_AREG_VAL_REG
+        | STRLD_AREG_STR | FILER_REG_AREG_AREG | FILER_VAL_AREG_AREG | FILEW_REG_AREG_AREG
+        | FILEW_VAL_AREG_AREG | SAVE_STR_AREG | LOADKV_STR_AREG => 4,
+        STRLD_AREG_DATA_VAL | STRLD_AREG_DATA_REG => 5,
         LD_AREG_DATA_REG_REG | LD_AREG_DATA_REG_VAL | LD_AREG_DATA_VAL_REG
         | LD_AREG_DATA_VAL_VAL => 6,
         _ => panic!("Unknown opcode: {:02X}", opcode),
@@ -197,10 +459,12 @@ pub fn get_byte_count(opcode: u8) -> usize {
 pub fn get_addr_byte_offset(opcode: u8) -> Option<usize> {
     match opcode {
         JMP_ADDR | JE_ADDR | JL_ADDR | JNE_ADDR | RSTR_ADDR | JG_ADDR | OVER_ADDR | NOVER_ADDR
-        | CALL_ADDR | MEMR_ADDR | MEMW_ADDR | IPOLL_ADDR | PRTS_STR | MEMP_ADDR => Some(1),
+        | CALL_ADDR | MEMR_ADDR | MEMW_ADDR | IPOLL_ADDR | PRTS_STR | MEMP_ADDR | FLIST_ADDR
+        | ARGS_ADDR | SAVE_STR_AREG | LOADKV_STR_AREG => Some(1),
         FCHK_VAL_ADDR | FCHK_REG_ADDR | LD_AREG_DATA_VAL_VAL | CPY_AREG_ADDR | CMP_AREG_ADDR
         | FILEW_VAL_ADDR | FILER_VAL_ADDR | FILER_REG_ADDR | LD_AREG_DATA_VAL_REG
-        | LD_AREG_DATA_REG_REG | LD_AREG_DATA_REG_VAL => Some(2),
+        | LD_AREG_DATA_REG_REG | LD_AREG_DATA_REG_VAL | FOPEN_REG_STR | FOPEN_VAL_STR
+        | INTH_VAL_ADDR | STRLD_AREG_STR | STRLD_AREG_DATA_VAL | STRLD_AREG_DATA_REG => Some(2),
         _ => None,
     }
 }
@@ -225,6 +489,7 @@ pub fn is_jump_op(opcode: u8) -> bool {
             | CALL_ADDR
             | CALL_AREG
             | RET
+            | RETI
             | FCHK_VAL_AREG
             | FCHK_VAL_ADDR
             | FCHK_REG_AREG
@@ -234,80 +499,11 @@ pub fn is_jump_op(opcode: u8) -> bool {
     )
 }
 
-#[rustfmt::skip]
-#[allow(dead_code)]
-pub const ALL_OPS: [u8; 108] = [
-    ADD_REG_REG, ADD_REG_VAL, ADD_REG_AREG,
-    SUB_REG_REG, SUB_REG_VAL, SUB_REG_AREG,
-    AND_REG_REG, AND_REG_VAL, AND_REG_AREG,
-    OR_REG_REG, OR_REG_VAL, OR_REG_AREG,
-    XOR_REG_REG, XOR_REG_VAL, XOR_REG_AREG,
-    INC_REG, DEC_REG,
-    CPY_REG_REG,
-    CPY_REG_VAL,
-    CPY_AREG_AREG,
-    CPY_AREG_ADDR,
-    CPY_AREG_REG_REG,
-    CPY_REG_REG_AREG,
-    CPY_REG_AREG,
-    CMP_AREG_AREG,
-    CMP_AREG_ADDR,
-    CMP_REG_REG_AREG,
-    CMP_REG_REG,
-    CMP_AREG_REG_REG,
-    CMP_REG_VAL,
-    CMP_REG_AREG,
-    JMP_ADDR, JMP_AREG,
-    JE_ADDR, JE_AREG,
-    JNE_ADDR, JNE_AREG,
-    JL_ADDR, JL_AREG,
-    JG_ADDR, JG_AREG,
-    OVER_ADDR, OVER_AREG,
-    NOVER_ADDR, NOVER_AREG,
-    MEMR_ADDR, MEMR_AREG,
-    MEMW_ADDR, MEMW_AREG,
-    CALL_ADDR, CALL_AREG,
-    RET,
-    PUSH_REG, PUSH_VAL,
-    POP_REG,
-    PRT_REG, PRT_VAL, PRT_AREG,
-    PRTLN,
-    PRTC_REG, PRTC_VAL, PRTC_AREG,
-    FOPEN_VAL, FOPEN_REG,
-    FILER_REG_ADDR, FILER_REG_AREG,
-    FILEW_REG_ADDR, FILEW_REG_AREG,
-    FSEEK_REG, FSEEK_VAL,
-    FSKIP_VAL_REG, FSKIP_REG_REG,
-    FCHK_REG_ADDR, FCHK_REG_AREG,
-    FCHK_VAL_ADDR, FCHK_VAL_AREG,
-    FILER_VAL_ADDR, FILER_VAL_AREG,
-    FILEW_VAL_ADDR, FILEW_VAL_AREG,
-    NOP,
-    HALT,
-    ARG_REG_VAL, ARG_REG_REG,
-    IPOLL_ADDR, IPOLL_AREG,
-    RCHR_REG,
-    RSTR_AREG, RSTR_ADDR,
-    SWP_REG_REG, SWP_AREG_AREG,
-    TIME,
-    RAND_REG,
-    SEED_REG,
-    NOT_REG,
-    LD_AREG_DATA_REG_REG,
-    LD_AREG_DATA_REG_VAL,
-    LD_AREG_DATA_VAL_REG,
-    LD_AREG_DATA_VAL_VAL,
-    MEMP_ADDR, MEMP_AREG,
-    PRTD_AREG,
-    PRTS_STR,
-    DEBUG,
-    FILEW_REG_REG, FILEW_REG_VAL, FILEW_VAL_REG, FILEW_VAL_VAL
-];
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::collections::HashSet;
+    use std::convert::TryFrom;
 
     #[test]
     fn check_ops_are_unique() {
@@ -327,6 +523,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_opcode_conversion() {
+        assert_eq!(ALL_OPS.len(), Opcode::ALL.len());
+        for op in ALL_OPS.iter() {
+            let opcode = Opcode::try_from(*op).unwrap();
+            assert_eq!(opcode as u8, *op);
+        }
+        assert!(Opcode::try_from(0x00).is_err());
+    }
+
+    #[test]
+    fn check_register_conversion() {
+        use crate::constants::hardware::*;
+        for reg in [REG_ACC, REG_D0, REG_D1, REG_D2, REG_D3, REG_A0, REG_A1].iter() {
+            assert_eq!(u8::from(Register::try_from(*reg).unwrap()), *reg);
+        }
+        assert!(Register::try_from(0x02).is_err());
+    }
+
     #[test]
     fn check_jump_ops_have_addr_offsets() {
         for op in ALL_OPS.iter() {
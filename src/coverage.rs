@@ -0,0 +1,175 @@
+//! Which instructions were executed, recorded with `--coverage` when running or testing
+//!
+//! Counts from every run are merged into the same file, the `coverage` subcommand maps them
+//! back to source lines using the debug file next to the tape (or by assembling `.basm` tests)
+
+use crate::assembler::assemble_source;
+use crate::assembler::debug_model::DebugModel;
+use crate::device::internals::Profile;
+use crate::device::read_sibling_debug_file;
+use anyhow::{Context, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, write};
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Eq, PartialEq)]
+pub struct Coverage {
+    ///Executions per op address for each program, keyed by the tape or test source path
+    pub programs: BTreeMap<String, BTreeMap<u16, u64>>,
+}
+
+impl Coverage {
+    ///Read a coverage file, if it doesn't exist an empty coverage is returned
+    pub fn read(path: &str) -> Result<Coverage> {
+        if !Path::new(path).exists() {
+            return Ok(Coverage::default());
+        }
+        let text = read_to_string(path).context(format!("Unable to read {}", path))?;
+        serde_json::from_str(&text).context(format!("Unable to parse coverage file {}", path))
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        write(path, serde_json::to_string(self)?).context(format!("Unable to write {}", path))
+    }
+
+    ///Add the instruction counts from `profile` to `program`
+    pub fn add(&mut self, program: &str, profile: &Profile) {
+        let hits = self.programs.entry(program.to_string()).or_default();
+        for (addr, count) in &profile.addrs {
+            *hits.entry(*addr).or_insert(0) += count;
+        }
+    }
+}
+
+///Add `profile` for `program` to the coverage file at `path`
+pub fn record(path: &str, program: &str, profile: &Profile) -> Result<()> {
+    let mut coverage = Coverage::read(path)?;
+    coverage.add(program, profile);
+    coverage.save(path)
+}
+
+///Hits for each op in `debug` and the number of ops covered, uncovered ops are marked with `!`
+pub fn format_report(program: &str, hits: &BTreeMap<u16, u64>, debug: &DebugModel) -> Vec<String> {
+    let covered = debug
+        .ops
+        .iter()
+        .filter(|op| hits.contains_key(&op.byte_addr))
+        .count();
+    let mut lines = vec![
+        format!(
+            "{}: {} of {} ops covered ({:.2}%)",
+            program,
+            covered,
+            debug.ops.len(),
+            percent(covered, debug.ops.len())
+        ),
+        String::from("   Line       Hits  Source"),
+    ];
+    for op in &debug.ops {
+        let count = hits.get(&op.byte_addr).copied().unwrap_or(0);
+        lines.push(format!(
+            "{} {: >5} {: >10}  {}",
+            if count == 0 { '!' } else { ' ' },
+            op.line_num,
+            count,
+            op.original_line.trim()
+        ));
+    }
+    lines
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64 * 100.0
+    }
+}
+
+///Debug info for a program in a coverage file, tests are assembled again as they don't have a debug file
+fn debug_for(program: &str) -> Result<DebugModel> {
+    if program.ends_with(".basm") {
+        let source = read_to_string(program).context(format!("Unable to read {}", program))?;
+        assemble_source(&source)
+            .map(|tape| tape.debug)
+            .map_err(|_| Error::msg(format!("Unable to assemble {}", program)))
+    } else {
+        read_sibling_debug_file(program).ok_or_else(|| {
+            Error::msg(format!(
+                "No debug file for {}, assemble it with --save-debug",
+                program
+            ))
+        })
+    }
+}
+
+///Print the report for every program in the coverage file at `path`
+pub fn start(path: &str) -> Result<()> {
+    let coverage = Coverage::read(path)?;
+    if coverage.programs.is_empty() {
+        return Err(Error::msg(format!("No coverage recorded in {}", path)));
+    }
+    let mut covered = 0;
+    let mut total = 0;
+    for (program, hits) in &coverage.programs {
+        match debug_for(program) {
+            Ok(debug) => {
+                for line in format_report(program, hits, &debug) {
+                    println!("{}", line);
+                }
+                covered += debug
+                    .ops
+                    .iter()
+                    .filter(|op| hits.contains_key(&op.byte_addr))
+                    .count();
+                total += debug.ops.len();
+            }
+            Err(err) => eprintln!("{}", err),
+        }
+        println!();
+    }
+    println!(
+        "Total: {} of {} ops covered ({:.2}%)",
+        covered,
+        total,
+        percent(covered, total)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::device::runner::run_tape;
+    use crate::device::RunOptions;
+
+    #[test]
+    fn test_report() {
+        let tape =
+            assemble_source("Test\n1\n.ops\ncmp d0 0\nje skip\nprt d0\nskip:\nhalt\n").unwrap();
+        let report = run_tape(
+            tape.bytes,
+            RunOptions {
+                profile: true,
+                ..RunOptions::default()
+            },
+        )
+        .unwrap();
+        let mut coverage = Coverage::default();
+        coverage.add("test.basm", report.profile.as_ref().unwrap());
+        coverage.add("test.basm", report.profile.as_ref().unwrap());
+        let lines = format_report("test.basm", &coverage.programs["test.basm"], &tape.debug);
+        assert_eq!(
+            lines,
+            vec![
+                "test.basm: 3 of 4 ops covered (75.00%)",
+                "   Line       Hits  Source",
+                "      4          2  cmp d0 0",
+                "      5          2  je skip",
+                "!     6          0  prt d0",
+                "      8          2  halt",
+            ]
+        );
+    }
+}
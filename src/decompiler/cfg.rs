@@ -0,0 +1,228 @@
+use crate::constants::code::*;
+use crate::constants::{get_addr_byte_offset, get_byte_count, is_jump_op};
+use crate::decompiler::{collect_jump_targets, decode};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    ///Unconditional jump
+    Jump,
+    ///Conditional jump when the condition is met
+    Branch,
+    ///Next op, including after a conditional jump isn't taken
+    Fallthrough,
+    Call,
+    ///From a RET to the op after each CALL of the function
+    Return,
+}
+
+///Ops that are always executed together, from a jump target or the op after a jump up to the next jump
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    ///Address of the first op
+    pub start: usize,
+    ///Address after the last op
+    pub end: usize,
+    ///Decompiled ops
+    pub lines: Vec<String>,
+    ///Start address of the block and the kind of edge
+    pub edges: Vec<(usize, EdgeKind)>,
+}
+
+///Split ops into basic blocks and connect them
+///
+///Jumps through addr registers can't be followed so only have an edge to the next op (if conditional)
+pub fn build_cfg(ops: &[u8]) -> Vec<BasicBlock> {
+    let targets = collect_jump_targets(ops);
+    let mut leaders = BTreeSet::new();
+    leaders.insert(0);
+    leaders.extend(targets.iter().copied());
+    let mut pc = 0;
+    while pc < ops.len() {
+        let next = pc + get_byte_count(ops[pc]);
+        if is_jump_op(ops[pc]) || matches!(ops[pc], HALT | EXIT_REG | EXIT_VAL) {
+            leaders.insert(next);
+        }
+        pc = next;
+    }
+
+    let mut blocks: Vec<BasicBlock> = vec![];
+    let mut calls = vec![];
+    let mut pc = 0;
+    while pc < ops.len() {
+        let opcode = ops[pc];
+        let next = pc + get_byte_count(opcode);
+        if next > ops.len() {
+            break;
+        }
+        if leaders.contains(&pc) {
+            blocks.push(BasicBlock {
+                start: pc,
+                end: pc,
+                lines: vec![],
+                edges: vec![],
+            });
+        }
+        let block = blocks.last_mut().unwrap();
+        block.end = next;
+        block.lines.push(format!(
+            "{:04X} {}",
+            pc,
+            decode(&mut ops[pc..next].to_vec(), &targets, pc, false)
+                .strings
+                .join(" ")
+        ));
+        let target = get_addr_byte_offset(opcode)
+            .map(|offset| u16::from_be_bytes([ops[pc + offset], ops[pc + offset + 1]]) as usize)
+            .filter(|addr| targets.contains(addr));
+        if leaders.contains(&next) || next >= ops.len() {
+            let has_next = next < ops.len();
+            match opcode {
+                HALT | EXIT_REG | EXIT_VAL | RET | RETI | JMP_AREG => {}
+                JMP_ADDR => block
+                    .edges
+                    .extend(target.map(|addr| (addr, EdgeKind::Jump))),
+                CALL_ADDR => {
+                    if let Some(addr) = target {
+                        block.edges.push((addr, EdgeKind::Call));
+                        if has_next {
+                            calls.push((addr, next));
+                        }
+                    }
+                }
+                _ => {
+                    if is_jump_op(opcode) {
+                        block
+                            .edges
+                            .extend(target.map(|addr| (addr, EdgeKind::Branch)));
+                    }
+                    if has_next {
+                        block.edges.push((next, EdgeKind::Fallthrough));
+                    }
+                }
+            }
+        }
+        pc = next;
+    }
+
+    add_return_edges(&mut blocks, ops, calls);
+    blocks
+}
+
+///Connect each RET reachable from a call target to the op after the CALL
+fn add_return_edges(blocks: &mut [BasicBlock], ops: &[u8], calls: Vec<(usize, usize)>) {
+    let indexes: BTreeMap<usize, usize> = blocks
+        .iter()
+        .enumerate()
+        .map(|(idx, block)| (block.start, idx))
+        .collect();
+    for (target, return_addr) in calls {
+        let mut seen = BTreeSet::new();
+        let mut pending = vec![target];
+        while let Some(start) = pending.pop() {
+            let idx = match indexes.get(&start) {
+                Some(idx) if seen.insert(start) => *idx,
+                _ => continue,
+            };
+            let block = &blocks[idx];
+            let last_op = block.end - last_op_len(ops, block);
+            if ops[last_op] == RET {
+                let edge = (return_addr, EdgeKind::Return);
+                if !blocks[idx].edges.contains(&edge) {
+                    blocks[idx].edges.push(edge);
+                }
+                continue;
+            }
+            for (addr, kind) in &blocks[idx].edges {
+                match kind {
+                    EdgeKind::Jump | EdgeKind::Branch | EdgeKind::Fallthrough => {
+                        pending.push(*addr)
+                    }
+                    EdgeKind::Call | EdgeKind::Return => {}
+                }
+            }
+            //after a call returns execution continues in this function
+            if ops[last_op] == CALL_ADDR && block.end < ops.len() {
+                pending.push(block.end);
+            }
+        }
+    }
+}
+
+fn last_op_len(ops: &[u8], block: &BasicBlock) -> usize {
+    let mut pc = block.start;
+    loop {
+        let next = pc + get_byte_count(ops[pc]);
+        if next >= block.end {
+            return next - pc;
+        }
+        pc = next;
+    }
+}
+
+///Graphviz DOT for `blocks`, each node is a block with its ops
+pub fn to_dot(name: &str, blocks: &[BasicBlock]) -> String {
+    let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+    let mut output = vec![
+        format!("digraph \"{}\" {{", escape(name)),
+        String::from("    node [shape=box, fontname=\"monospace\"];"),
+    ];
+    for block in blocks {
+        let mut label = String::new();
+        for line in &block.lines {
+            label.push_str(&escape(line));
+            label.push_str("\\l");
+        }
+        output.push(format!("    b{:04X} [label=\"{}\"];", block.start, label));
+    }
+    for block in blocks {
+        for (addr, kind) in &block.edges {
+            let style = match kind {
+                EdgeKind::Jump => "",
+                EdgeKind::Branch => " [label=\"taken\"]",
+                EdgeKind::Fallthrough => " [style=dashed]",
+                EdgeKind::Call => " [label=\"call\", color=blue]",
+                EdgeKind::Return => " [label=\"ret\", style=dotted, color=blue]",
+            };
+            output.push(format!(
+                "    b{:04X} -> b{:04X}{};",
+                block.start, addr, style
+            ));
+        }
+    }
+    output.push(String::from("}"));
+    let mut output = output.join("\n");
+    output.push('\n');
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constants::hardware::REG_D0;
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_build_cfg() {
+        let ops = [
+            CALL_ADDR, 0, 4, HALT,
+            CMP_REG_VAL, REG_D0, 0, JE_ADDR, 0, 12, INC_REG, REG_D0,
+            RET,
+        ];
+        let blocks = build_cfg(&ops);
+        let edges: Vec<(usize, Vec<(usize, EdgeKind)>)> = blocks.iter().map(|block| (block.start, block.edges.clone())).collect();
+        assert_eq!(edges, vec![
+            (0, vec![(4, EdgeKind::Call)]),
+            (3, vec![]),
+            (4, vec![(12, EdgeKind::Branch), (10, EdgeKind::Fallthrough)]),
+            (10, vec![(12, EdgeKind::Fallthrough)]),
+            (12, vec![(3, EdgeKind::Return)]),
+        ]);
+        assert_eq!(blocks[2].lines, vec!["0004 CMP D0 0", "0007 JE lbl_000C"]);
+
+        let dot = to_dot("Test", &blocks);
+        assert!(dot.starts_with("digraph \"Test\" {\n"));
+        assert!(dot.contains("    b0004 [label=\"0004 CMP D0 0\\l0007 JE lbl_000C\\l\"];\n"));
+        assert!(dot.contains("    b000C -> b0003 [label=\"ret\", style=dotted, color=blue];\n"));
+    }
+}
@@ -1,9 +1,16 @@
+pub mod cfg;
+
+use crate::assembler::debug_model::DebugModel;
 use crate::constants::code::*;
-use crate::constants::hardware::*;
-use crate::constants::{get_addr_byte_offset, get_byte_count};
-use crate::tape_reader::read_tape;
-use anyhow::Result;
-use std::collections::HashSet;
+use crate::constants::hardware::{Register, REG_A0, REG_A1};
+use crate::constants::system::{ACC_COMPARE_PRG_VERSION, WIDE_DATA_PRG_VERSION};
+use crate::constants::{get_addr_byte_offset, get_byte_count, is_jump_op};
+use crate::decompiler::cfg::{build_cfg, to_dot};
+use crate::diff::check_expected;
+use crate::tape_reader::{read_tape, Tape};
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
 
 pub struct Decoded {
     pub bytes: Vec<u8>,
@@ -28,139 +35,441 @@ impl Decoded {
     }
 }
 
-pub fn start(path: &str) -> Result<()> {
+///Op that uses the address of a string or data entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub op_addr: usize,
+    pub mnemonic: String,
+}
+
+///Addresses of strings and data used by ops, found by [`collect_references`]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct References {
+    pub strings: BTreeMap<u16, Vec<Reference>>,
+    pub data: BTreeMap<u16, Vec<Reference>>,
+    ///`CPY addr_reg addr` ops where the address is later used as a string
+    pub string_loads: BTreeSet<usize>,
+}
+
+impl References {
+    fn add(map: &mut BTreeMap<u16, Vec<Reference>>, addr: u16, op_addr: usize, op: &[u8]) {
+        map.entry(addr).or_default().push(Reference {
+            op_addr,
+            mnemonic: decode(&mut op.to_vec(), &[], op_addr, false).strings[0].clone(),
+        });
+    }
+}
+
+pub fn start(
+    path: &str,
+    output_path: Option<&str>,
+    expected_listing: Option<&str>,
+    cfg_path: Option<&str>,
+    debug_path: Option<&str>,
+) -> Result<()> {
     println!("Decompiling tape at {}", path);
 
-    let mut tape = read_tape(path)?;
+    let tape = read_tape(path)?;
+    if let Some(cfg_path) = cfg_path {
+        std::fs::write(cfg_path, to_dot(&tape.name, &build_cfg(&tape.ops)))?;
+        println!("Control flow graph written to {}", cfg_path);
+    }
+    let debug: Option<DebugModel> = match debug_path {
+        Some(debug_path) => {
+            let text = std::fs::read_to_string(debug_path)
+                .context(format!("Unable to read {}", debug_path))?;
+            Some(serde_json::from_str(&text).context(format!("Unable to parse {}", debug_path))?)
+        }
+        None => None,
+    };
+    let lines = decompile_with_debug(tape, debug.as_ref());
+
+    if let Some(output_path) = output_path {
+        let mut content = lines.join("\n");
+        content.push('\n');
+        std::fs::write(output_path, content)?;
+        println!("Written to {}", output_path);
+    }
+
+    match expected_listing {
+        Some(expected_path) => check_expected("Listing", expected_path, &lines),
+        None => {
+            if output_path.is_none() {
+                for line in lines {
+                    println!("{}", line);
+                }
+            }
+            Ok(())
+        }
+    }
+}
 
-    println!(
-        "\n\nProgram\nName: {}\nVersion: {}",
-        tape.name, tape.version
-    );
-    let (strings, strings_unused) = collect_strings(&tape.ops, &tape.strings);
-    let (data, data_unused) = collect_data(&tape.ops, &tape.data);
+///Generate decompiled listing for `tape`
+///
+///The listing is valid BASM and can be assembled, strings, data and labels are named after their address
+pub fn decompile(tape: Tape) -> Vec<String> {
+    decompile_with_debug(tape, None)
+}
 
-    println!(
-        "{}b ops, {}b strings ({}b unused), {}b data  ({}b unused)",
+///Same as [`decompile`] but labels, strings and data use their names from `debug` and each op
+///is followed by the source line it was assembled from
+pub fn decompile_with_debug(mut tape: Tape, debug: Option<&DebugModel>) -> Vec<String> {
+    //generated names to replace with the names from the source
+    let mut names: HashMap<String, String> = HashMap::new();
+    if let Some(debug) = debug {
+        for (addr, name) in debug.labels_by_addr() {
+            names
+                .entry(label(addr as usize))
+                .or_insert_with(|| name.to_string());
+        }
+    }
+
+    let mut output = vec![tape.name.clone(), tape.version.clone()];
+    if let Some(description) = &tape.description {
+        output.push(format!(".description {}", description));
+    }
+    let mut jmp_target = collect_jump_targets(&tape.ops);
+    if tape.entry != 0 {
+        let generated = label(tape.entry as usize);
+        output.push(format!(
+            ".start {}",
+            names.get(&generated).cloned().unwrap_or(generated)
+        ));
+        if !jmp_target.contains(&(tape.entry as usize)) {
+            jmp_target.push(tape.entry as usize);
+            jmp_target.sort_unstable();
+        }
+    }
+
+    let references = collect_references(&tape.ops);
+    let (strings, strings_unused) = collect_strings(&references, &tape.strings);
+    let len_size = if tape.tape_version >= WIDE_DATA_PRG_VERSION {
+        2
+    } else {
+        1
+    };
+    let (data, data_unused) = collect_data(&references, &tape.data, len_size);
+
+    output.push(format!(
+        "# {}b ops, {}b strings ({}b unused), {}b data ({}b unused)",
         tape.ops.len(),
         tape.strings.len(),
         strings_unused,
         tape.data.len(),
         data_unused,
-    );
-    println!("\n\nStrings:");
-    for content in &strings {
-        println!("\"{}\"", content);
+    ));
+    if tape.tape_version == ACC_COMPARE_PRG_VERSION {
+        output.push(String::from(
+            "# Built before the flags register, CMP stored its result in ACC",
+        ));
     }
-    println!("\n\nData:");
-    for content in &data {
-        println!("{}", content);
+    if !strings.is_empty() {
+        output.push(String::from(".strings"));
+        for (addr, content) in &strings {
+            output.push(describe_references(references.strings.get(addr)));
+            let key = debug.and_then(|debug| debug.string_key(*addr));
+            if let Some(key) = key {
+                names.insert(string_key(*addr), key.to_string());
+            }
+            output.push(format!(
+                "{}=\"{}\"",
+                key.map(String::from).unwrap_or_else(|| string_key(*addr)),
+                content
+            ));
+        }
     }
-    println!("\n\nOps:");
-    let jmp_target = collect_jump_targets(&tape.ops);
+    if !data.is_empty() || !tape.relocations.is_empty() {
+        output.push(String::from(".data"));
+        for (idx, (addr, content)) in data.iter().enumerate() {
+            let end = data.get(idx + 1).map(|(next, _)| *next).unwrap_or(u16::MAX);
+            let used_by: Vec<Reference> = references
+                .data
+                .range(*addr..end)
+                .flat_map(|(_, refs)| refs.iter().cloned())
+                .collect();
+            output.push(describe_references(Some(&used_by)));
+            let key = debug.and_then(|debug| debug.data_key(*addr));
+            if let Some(key) = key {
+                names.insert(data_key(*addr), key.to_string());
+            }
+            output.push(format!(
+                "{}={}",
+                key.map(String::from).unwrap_or_else(|| data_key(*addr)),
+                content
+            ));
+        }
+        for relocation in &tape.relocations {
+            output.push(format!("{}=extern", relocation.key));
+        }
+    }
+    output.push(String::from(".ops"));
 
     let mut pc = 0;
-    println!("byte  addr op");
     while !tape.ops.is_empty() {
-        let op = decode(&mut tape.ops, &tape.strings, pc, jmp_target.contains(&pc));
-        let lbl = if op.is_jump_target {
-            format!("{:04X}", op.byte_offset)
-        } else {
-            String::from("    ")
-        };
-        println!(
-            "{: <4}  {} {:<6}  {}",
-            pc,
-            lbl,
-            op.strings[0],
-            op.strings[1..]
+        let mut op = decode(&mut tape.ops, &jmp_target, pc, jmp_target.contains(&pc));
+        let op_end = pc + op.bytes.len();
+        if references.string_loads.contains(&pc) {
+            let addr = u16::from_be_bytes([op.bytes[2], op.bytes[3]]);
+            if strings.iter().any(|(start, _)| *start == addr) {
+                op.strings[2] = string_key(addr);
+            }
+        }
+        //data from data tapes has a placeholder address
+        if let Some(relocation) = tape.relocations.iter().find(|relocation| {
+            relocation
+                .offsets
                 .iter()
-                .map(|value| format!("  {:<5}", value))
-                .collect::<Vec<String>>()
-                .join("")
-        );
+                .any(|offset| (pc..op_end).contains(&(*offset as usize)))
+        }) {
+            for param in op.strings.iter_mut() {
+                if *param == data_key(0) {
+                    *param = relocation.key.clone();
+                }
+            }
+        }
+        for param in op.strings.iter_mut().skip(1) {
+            if let Some(name) = names.get(param) {
+                *param = name.clone();
+            }
+        }
+        //labels from the source are kept even if nothing jumps to them
+        if op.is_jump_target || names.contains_key(&label(op.byte_offset)) {
+            let generated = label(op.byte_offset);
+            output.push(format!(
+                "{}:",
+                names.get(&generated).cloned().unwrap_or(generated)
+            ));
+        }
+        let source = debug
+            .and_then(|debug| debug.op_for_byte(op.byte_offset as u16))
+            .map(|source| format!("  {}: {}", source.line_num, source.original_line.trim()))
+            .unwrap_or_default();
+        output.push(format!(
+            "    {:<28} # {:04X}{}",
+            op.strings.join(" "),
+            op.byte_offset,
+            source
+        ));
         pc += get_byte_count(op.bytes[0]);
     }
 
-    Ok(())
+    output
 }
 
-pub fn collect_data(_: &[u8], data: &[u8]) -> (Vec<String>, usize) {
-    //TODO finish
-    let mut data = data.to_vec();
-    let mut output = vec![];
-    loop {
-        if data.is_empty() {
+///Comment listing the ops that use a string or data entry
+fn describe_references(references: Option<&Vec<Reference>>) -> String {
+    match references {
+        Some(references) if !references.is_empty() => format!(
+            "# used at {}",
+            references
+                .iter()
+                .map(|reference| format!("{:04X} ({})", reference.op_addr, reference.mnemonic))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        _ => String::from("# unreferenced"),
+    }
+}
+
+///Find every op that uses a string or data address
+///
+///Besides ops with a string or data param, addresses loaded with `CPY addr_reg addr` are followed
+///to `PRTS addr_reg` (string) and `PRTD addr_reg` (data). Only loads in the same block are followed,
+///the register is forgotten at jump targets, calls and when another op uses it.
+pub fn collect_references(ops: &[u8]) -> References {
+    let targets = collect_jump_targets(ops);
+    let mut references = References::default();
+    let mut loaded: [Option<(u16, usize)>; 2] = [None, None];
+    let areg_idx = |reg: u8| match reg {
+        REG_A0 => Some(0),
+        REG_A1 => Some(1),
+        _ => None,
+    };
+    let mut pc = 0;
+    while pc < ops.len() {
+        let opcode = ops[pc];
+        let end = pc + get_byte_count(opcode);
+        if end > ops.len() {
             break;
         }
-
-        let sub_array_count = data.remove(0);
-        let mut sub_array_lens = vec![];
-        let mut sub_array_content = vec![];
-        for _ in 0..sub_array_count {
-            sub_array_lens.push(data.remove(0));
+        let op = &ops[pc..end];
+        if targets.contains(&pc) {
+            loaded = [None, None];
         }
-        for len in sub_array_lens {
-            let mut content = vec![];
-            for _ in 0..len {
-                content.push(data.remove(0));
+        let addr = get_addr_byte_offset(opcode)
+            .map(|offset| u16::from_be_bytes([op[offset], op[offset + 1]]));
+        match (opcode, addr) {
+            (
+                PRTS_STR | FOPEN_REG_STR | FOPEN_VAL_STR | STRLD_AREG_STR | SAVE_STR_AREG
+                | LOADKV_STR_AREG,
+                Some(addr),
+            ) => References::add(&mut references.strings, addr, pc, op),
+            (STRLD_AREG_DATA_VAL | STRLD_AREG_DATA_REG, Some(addr)) => {
+                References::add(&mut references.data, addr, pc, op)
+            }
+            (
+                LD_AREG_DATA_REG_REG | LD_AREG_DATA_REG_VAL | LD_AREG_DATA_VAL_REG
+                | LD_AREG_DATA_VAL_VAL,
+                Some(addr),
+            ) => {
+                References::add(&mut references.data, addr, pc, op);
+                if let Some(idx) = areg_idx(op[1]) {
+                    loaded[idx] = None;
+                }
+            }
+            (CPY_AREG_ADDR, Some(addr)) => {
+                if let Some(idx) = areg_idx(op[1]) {
+                    loaded[idx] = Some((addr, pc));
+                }
+            }
+            (PRTS_AREG | PRTD_AREG | STRLD_AREG_AREG, _) => {
+                let source = if opcode == STRLD_AREG_AREG {
+                    op[2]
+                } else {
+                    op[1]
+                };
+                if let Some((addr, load_pc)) = areg_idx(source).and_then(|idx| loaded[idx]) {
+                    let map = if opcode != PRTD_AREG {
+                        references.string_loads.insert(load_pc);
+                        &mut references.strings
+                    } else {
+                        &mut references.data
+                    };
+                    References::add(map, addr, load_pc, &ops[load_pc..load_pc + 4]);
+                    References::add(map, addr, pc, op);
+                }
+            }
+            (CALL_ADDR | CALL_AREG, _) => loaded = [None, None],
+            _ => {
+                let decoded = decode(&mut op.to_vec(), &[], pc, false).strings;
+                for (idx, reg) in [REG_A0, REG_A1].iter().enumerate() {
+                    if decoded[1..].contains(&decode_reg(*reg)) {
+                        loaded[idx] = None;
+                    }
+                }
             }
-            sub_array_content.push(content);
         }
-
-        output.push(
-            sub_array_content
-                .iter()
-                .map(|arr| format!("{:?}", arr))
-                .collect::<Vec<String>>()
-                .join("  "),
-        );
+        pc = end;
     }
-    (output, 0)
+    for refs in references
+        .strings
+        .values_mut()
+        .chain(references.data.values_mut())
+    {
+        refs.sort_by_key(|reference| reference.op_addr);
+        refs.dedup();
+    }
+    references
 }
 
-pub fn collect_strings(ops: &[u8], data: &[u8]) -> (Vec<String>, usize) {
-    let mut op_idx = 0;
-    let mut addresses = HashSet::new();
-    while op_idx < ops.len() {
-        if ops[op_idx] == PRTS_STR {
-            let addr = u16::from_be_bytes([ops[op_idx + 1], ops[op_idx + 2]]);
-            addresses.insert(addr);
+///Returns all data entries as (address, BASM array) and the number of bytes not referenced by any op
+///
+///An entry is referenced if any op uses an address inside it, `len_size` is the number of bytes
+///used for each array length (1 for tapes before [`WIDE_DATA_PRG_VERSION`])
+pub fn collect_data(
+    references: &References,
+    data: &[u8],
+    len_size: usize,
+) -> (Vec<(u16, String)>, usize) {
+    let mut output = vec![];
+    let mut unused = 0;
+    let mut idx = 0;
+    while idx < data.len() {
+        let start = idx;
+        let sub_array_count = data[idx] as usize;
+        idx += 1;
+        let sub_array_lens = data[idx..(idx + sub_array_count * len_size).min(data.len())]
+            .chunks(len_size)
+            .map(|len| len.iter().fold(0, |len, byte| (len << 8) + *byte as usize))
+            .collect::<Vec<usize>>();
+        idx += sub_array_count * len_size;
+        let mut sub_array_content = vec![];
+        for len in sub_array_lens {
+            let end = (idx + len).min(data.len());
+            sub_array_content.push(
+                data[idx.min(end)..end]
+                    .iter()
+                    .map(|byte| byte.to_string())
+                    .collect::<Vec<String>>()
+                    .join(","),
+            );
+            idx += len;
         }
-        op_idx += get_byte_count(ops[op_idx]);
+        let end = idx.min(data.len());
+        if references
+            .data
+            .range(start as u16..end as u16)
+            .next()
+            .is_none()
+        {
+            unused += end - start;
+        }
+        output.push((
+            start as u16,
+            format!(
+                "[{}]",
+                sub_array_content
+                    .iter()
+                    .map(|arr| format!("[{}]", arr))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+        ));
     }
+    (output, unused)
+}
 
-    let mut bytes_accounted = 0;
-    let mut results = vec![];
-    for str_addr in addresses {
-        bytes_accounted += data[str_addr as usize] as usize + 1;
-        let content = &data
-            [(str_addr + 1) as usize..(str_addr as usize + 1 + data[str_addr as usize] as usize)];
-        results.push(String::from_utf8_lossy(content).to_string());
+///Returns all strings as (address, content) and the number of bytes not referenced by any op
+pub fn collect_strings(references: &References, data: &[u8]) -> (Vec<(u16, String)>, usize) {
+    let mut output = vec![];
+    let mut unused = 0;
+    let mut idx = 0;
+    while idx < data.len() {
+        let len = data[idx] as usize;
+        let end = (idx + 1 + len).min(data.len());
+        if !references.strings.contains_key(&(idx as u16)) {
+            unused += end - idx;
+        }
+        output.push((
+            idx as u16,
+            String::from_utf8_lossy(&data[idx + 1..end]).to_string(),
+        ));
+        idx = end;
     }
-    (results, data.len() - bytes_accounted)
+    (output, unused)
 }
 
+///Returns the start of every op that is jumped to
 pub fn collect_jump_targets(ops: &[u8]) -> Vec<usize> {
-    let mut jmp_target = vec![];
+    let mut op_starts = HashSet::new();
+    let mut addresses = vec![];
     let mut pc = 0;
     loop {
         if pc >= ops.len() {
             break;
         }
         let op = ops[pc];
-        if let Some(offset) = get_addr_byte_offset(op) {
-            let addr = u16::from_be_bytes([ops[pc + offset], ops[pc + offset + 1]]) as usize;
-            jmp_target.push(addr);
+        op_starts.insert(pc);
+        if is_jump_op(op) || op == INTH_VAL_ADDR {
+            if let Some(offset) = get_addr_byte_offset(op) {
+                let addr = u16::from_be_bytes([ops[pc + offset], ops[pc + offset + 1]]) as usize;
+                addresses.push(addr);
+            }
         }
         pc += get_byte_count(op)
     }
+    let mut jmp_target: Vec<usize> = addresses
+        .into_iter()
+        .filter(|addr| op_starts.contains(addr))
+        .collect();
+    jmp_target.sort_unstable();
+    jmp_target.dedup();
     jmp_target
 }
 
 pub fn decode(
     bytes: &mut Vec<u8>,
-    strings: &[u8],
+    labels: &[usize],
     byte_offset: usize,
     is_jump_target: bool,
 ) -> Decoded {
@@ -170,173 +479,327 @@ pub fn decode(
         op.push(bytes.remove(0));
         count -= 1;
     }
-    let (op_str, params): (&str, Vec<String>) = match op[0] {
-        ADD_REG_VAL => ("ADD", vec![decode_reg(op[1]), decode_num(op[2])]),
-        ADD_REG_REG => ("ADD", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        ADD_REG_AREG => ("ADD", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        CPY_REG_VAL => ("CPY", vec![decode_reg(op[1]), decode_num(op[2])]),
-        CPY_REG_REG | CPY_AREG_AREG | CPY_REG_AREG => {
+    let (op_str, params): (&str, Vec<String>) = match Opcode::try_from(op[0]) {
+        Ok(Opcode::AddRegVal) => ("ADD", vec![decode_reg(op[1]), decode_num(op[2])]),
+        Ok(Opcode::AddRegReg) => ("ADD", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::AddRegAreg) => ("ADD", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::CpyRegVal) => ("CPY", vec![decode_reg(op[1]), decode_num(op[2])]),
+        Ok(Opcode::CpyRegReg) | Ok(Opcode::CpyAregAreg) | Ok(Opcode::CpyRegAreg) => {
             ("CPY", vec![decode_reg(op[1]), decode_reg(op[2])])
         }
-        CPY_AREG_ADDR => ("CPY", vec![decode_reg(op[1]), decode_addr(op[2], op[3])]),
-        CPY_REG_REG_AREG | CPY_AREG_REG_REG => (
+        Ok(Opcode::CpyAregAddr) => ("CPY", vec![decode_reg(op[1]), decode_addr(op[2], op[3])]),
+        Ok(Opcode::CpyRegRegAreg) | Ok(Opcode::CpyAregRegReg) => (
             "CPY",
             vec![decode_reg(op[1]), decode_reg(op[2]), decode_reg(op[3])],
         ),
-        CMP_REG_VAL => ("CMP", vec![decode_reg(op[1]), decode_num(op[2])]),
-        CMP_REG_REG | CMP_AREG_AREG | CMP_REG_AREG => {
+        Ok(Opcode::CmpRegVal) => ("CMP", vec![decode_reg(op[1]), decode_num(op[2])]),
+        Ok(Opcode::CmpRegReg) | Ok(Opcode::CmpAregAreg) | Ok(Opcode::CmpRegAreg) => {
             ("CMP", vec![decode_reg(op[1]), decode_reg(op[2])])
         }
-        CMP_AREG_ADDR => ("CMP", vec![decode_reg(op[1]), decode_addr(op[2], op[3])]),
-        CMP_REG_REG_AREG | CMP_AREG_REG_REG => (
+        Ok(Opcode::CmpAregAddr) => ("CMP", vec![decode_reg(op[1]), decode_addr(op[2], op[3])]),
+        Ok(Opcode::CmpRegRegAreg) | Ok(Opcode::CmpAregRegReg) => (
             "CMP",
             vec![decode_reg(op[1]), decode_reg(op[2]), decode_reg(op[3])],
         ),
-        SUB_REG_VAL => ("SUB", vec![decode_reg(op[1]), decode_num(op[2])]),
-        SUB_REG_REG => ("SUB", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        SUB_REG_AREG => ("SUB", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        FOPEN_REG => ("FOPEN", vec![decode_reg(op[1])]),
-        FOPEN_VAL => ("FOPEN", vec![decode_num(op[1])]),
-        FSEEK_REG => ("FSEEK", vec![decode_reg(op[1])]),
-        FSEEK_VAL => ("FSEEK", vec![decode_num(op[1])]),
-        PRTLN => ("PRTLN", vec![]),
-        INC_REG => ("INC", vec![decode_reg(op[1])]),
-        DEC_REG => ("DEC", vec![decode_reg(op[1])]),
-        FSKIP_REG_REG => ("FSKIP", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        FSKIP_REG_VAL => ("FSKIP", vec![decode_reg(op[1]), decode_num(op[2])]),
-        FSKIP_VAL_REG => ("FSKIP", vec![decode_num(op[1]), decode_reg(op[2])]),
-        FSKIP_VAL_VAL => ("FSKIP", vec![decode_num(op[1]), decode_num(op[2])]),
-        PRT_VAL => ("PRT", vec![decode_num(op[1])]),
-        PRT_REG | PRT_AREG => ("PRT", vec![decode_reg(op[1])]),
-        PRTC_VAL | PRTC_AREG => ("PRTC", vec![decode_num(op[1])]),
-        PRTC_REG => ("PRTC", vec![decode_reg(op[1])]),
-        FILER_REG_ADDR => ("FILER", vec![decode_reg(op[1]), decode_addr(op[2], op[3])]),
-        FILER_REG_AREG => ("FILER", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        FILEW_REG_ADDR => ("FILEW", vec![decode_reg(op[1]), decode_addr(op[2], op[3])]),
-        FILEW_REG_AREG => ("FILEW", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        FILER_VAL_ADDR => ("FILER", vec![decode_num(op[1]), decode_addr(op[2], op[3])]),
-        FILER_VAL_AREG => ("FILER", vec![decode_num(op[1]), decode_reg(op[2])]),
-        FILEW_VAL_ADDR => ("FILEW", vec![decode_num(op[1]), decode_addr(op[2], op[3])]),
-        FILEW_VAL_AREG => ("FILEW", vec![decode_num(op[1]), decode_reg(op[2])]),
-        FILEW_REG_REG => ("FILEW", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        FILEW_REG_VAL => ("FILEW", vec![decode_reg(op[1]), decode_num(op[2])]),
-        FILEW_VAL_REG => ("FILEW", vec![decode_num(op[1]), decode_reg(op[2])]),
-        FILEW_VAL_VAL => ("FILEW", vec![decode_num(op[1]), decode_num(op[2])]),
-        MEMR_ADDR => ("MEMR", vec![decode_addr(op[1], op[2])]),
-        MEMR_AREG => ("MEMR", vec![decode_reg(op[1])]),
-        MEMW_ADDR => ("MEMW", vec![decode_addr(op[1], op[2])]),
-        MEMW_AREG => ("MEMW", vec![decode_reg(op[1])]),
-        PRTS_STR => ("PRTS", vec![decode_string(op[1], op[2], strings)]),
-        JMP_ADDR => ("JMP", vec![decode_addr(op[1], op[2])]),
-        JE_ADDR => ("JE", vec![decode_addr(op[1], op[2])]),
-        JNE_ADDR => ("JNE", vec![decode_addr(op[1], op[2])]),
-        JL_ADDR => ("JL", vec![decode_addr(op[1], op[2])]),
-        JG_ADDR => ("JG", vec![decode_addr(op[1], op[2])]),
-        OVER_ADDR => ("OVER", vec![decode_addr(op[1], op[2])]),
-        NOVER_ADDR => ("NOVER", vec![decode_addr(op[1], op[2])]),
-        JMP_AREG => ("JMP", vec![decode_reg(op[1])]),
-        JE_AREG => ("JE", vec![decode_reg(op[1])]),
-        JNE_AREG => ("JNE", vec![decode_reg(op[1])]),
-        JL_AREG => ("JL", vec![decode_reg(op[1])]),
-        JG_AREG => ("JG", vec![decode_reg(op[1])]),
-        OVER_AREG => ("OVER", vec![decode_reg(op[1])]),
-        NOVER_AREG => ("NOVER", vec![decode_reg(op[1])]),
-        NOP => ("NOP", vec![]),
-        HALT => ("HALT", vec![]),
-        RET => ("RET", vec![]),
-        CALL_ADDR => ("CALL", vec![decode_addr(op[1], op[2])]),
-        CALL_AREG => ("CALL", vec![decode_reg(op[1])]),
-        POP_REG => ("POP", vec![decode_reg(op[1])]),
-        PUSH_REG => ("PUSH", vec![decode_reg(op[1])]),
-        PUSH_VAL => ("PUSH", vec![decode_num(op[1])]),
-        SWP_REG_REG | SWP_AREG_AREG => ("SWP", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        ARG_REG_VAL => ("ARG", vec![decode_reg(op[1]), decode_num(op[2])]),
-        ARG_REG_REG => ("ARG", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        FCHK_REG_AREG => ("FCHK", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        FCHK_REG_ADDR => ("FCHK", vec![decode_reg(op[1]), decode_addr(op[2], op[3])]),
-        FCHK_VAL_AREG => ("FCHK", vec![decode_num(op[1]), decode_reg(op[2])]),
-        FCHK_VAL_ADDR => ("FCHK", vec![decode_num(op[1]), decode_addr(op[2], op[3])]),
-        IPOLL_AREG => ("IPOLL", vec![decode_reg(op[1])]),
-        IPOLL_ADDR => ("IPOLL", vec![decode_addr(op[1], op[2])]),
-        RSTR_AREG => ("RSTR", vec![decode_reg(op[1])]),
-        RSTR_ADDR => ("RSTR", vec![decode_addr(op[1], op[2])]),
-        MEMP_AREG => ("PSTR", vec![decode_reg(op[1])]),
-        MEMP_ADDR => ("PSTR", vec![decode_addr(op[1], op[2])]),
-        RCHR_REG => ("RCHR", vec![decode_reg(op[1])]),
-        AND_REG_REG => ("AND", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        AND_REG_VAL => ("AND", vec![decode_reg(op[1]), decode_num(op[2])]),
-        AND_REG_AREG => ("AND", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        OR_REG_REG => ("OR", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        OR_REG_VAL => ("OR", vec![decode_reg(op[1]), decode_num(op[2])]),
-        OR_REG_AREG => ("OR", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        XOR_REG_REG => ("XOR", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        XOR_REG_VAL => ("XOR", vec![decode_reg(op[1]), decode_num(op[2])]),
-        XOR_REG_AREG => ("XOR", vec![decode_reg(op[1]), decode_reg(op[2])]),
-        TIME => ("TIME", vec![]),
-        DEBUG => ("DEBUG", vec![]),
-        NOT_REG => ("NOT", vec![decode_reg(op[1])]),
-        SEED_REG => ("SEED", vec![decode_reg(op[1])]),
-        RAND_REG => ("RAND", vec![decode_reg(op[1])]),
-        PRTD_AREG => ("PRTD", vec![decode_reg(op[1])]),
-        LD_AREG_DATA_REG_REG => (
+        Ok(Opcode::SubRegVal) => ("SUB", vec![decode_reg(op[1]), decode_num(op[2])]),
+        Ok(Opcode::SubRegReg) => ("SUB", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::SubRegAreg) => ("SUB", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::AddcRegReg) | Ok(Opcode::AddcRegAreg) => {
+            ("ADDC", vec![decode_reg(op[1]), decode_reg(op[2])])
+        }
+        Ok(Opcode::AddcRegVal) => ("ADDC", vec![decode_reg(op[1]), decode_num(op[2])]),
+        Ok(Opcode::SubcRegReg) | Ok(Opcode::SubcRegAreg) => {
+            ("SUBC", vec![decode_reg(op[1]), decode_reg(op[2])])
+        }
+        Ok(Opcode::SubcRegVal) => ("SUBC", vec![decode_reg(op[1]), decode_num(op[2])]),
+        Ok(Opcode::FopenReg) => ("FOPEN", vec![decode_reg(op[1])]),
+        Ok(Opcode::FopenVal) => ("FOPEN", vec![decode_num(op[1])]),
+        Ok(Opcode::FopenRegStr) => (
+            "FOPEN",
+            vec![decode_reg(op[1]), decode_string_key(op[2], op[3])],
+        ),
+        Ok(Opcode::FopenValStr) => (
+            "FOPEN",
+            vec![decode_num(op[1]), decode_string_key(op[2], op[3])],
+        ),
+        Ok(Opcode::FopenRegAreg) => ("FOPEN", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::FopenValAreg) => ("FOPEN", vec![decode_num(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::FseekReg) => ("FSEEK", vec![decode_reg(op[1])]),
+        Ok(Opcode::FseekVal) => ("FSEEK", vec![decode_num(op[1])]),
+        Ok(Opcode::FtruncReg) => ("FTRUNC", vec![decode_reg(op[1])]),
+        Ok(Opcode::FtruncVal) => ("FTRUNC", vec![decode_num(op[1])]),
+        Ok(Opcode::FdelReg) => ("FDEL", vec![decode_reg(op[1])]),
+        Ok(Opcode::FdelVal) => ("FDEL", vec![decode_num(op[1])]),
+        Ok(Opcode::FlistAreg) => ("FLIST", vec![decode_reg(op[1])]),
+        Ok(Opcode::FlistAddr) => ("FLIST", vec![decode_addr(op[1], op[2])]),
+        Ok(Opcode::Prtln) => ("PRTLN", vec![]),
+        Ok(Opcode::IncReg) => ("INC", vec![decode_reg(op[1])]),
+        Ok(Opcode::DecReg) => ("DEC", vec![decode_reg(op[1])]),
+        Ok(Opcode::FskipRegReg) => ("FSKIP", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::FskipRegVal) => ("FSKIP", vec![decode_reg(op[1]), decode_num(op[2])]),
+        Ok(Opcode::FskipValReg) => ("FSKIP", vec![decode_num(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::FskipValVal) => ("FSKIP", vec![decode_num(op[1]), decode_num(op[2])]),
+        Ok(Opcode::PrtVal) => ("PRT", vec![decode_num(op[1])]),
+        Ok(Opcode::PrtReg) | Ok(Opcode::PrtAreg) => ("PRT", vec![decode_reg(op[1])]),
+        Ok(Opcode::PrtcVal) => ("PRTC", vec![decode_num(op[1])]),
+        Ok(Opcode::PrtcReg) | Ok(Opcode::PrtcAreg) => ("PRTC", vec![decode_reg(op[1])]),
+        Ok(Opcode::PrtxVal) => ("PRTX", vec![decode_num(op[1])]),
+        Ok(Opcode::PrtxReg) | Ok(Opcode::PrtxAreg) => ("PRTX", vec![decode_reg(op[1])]),
+        Ok(Opcode::FilerRegAddr) => ("FILER", vec![decode_reg(op[1]), decode_addr(op[2], op[3])]),
+        Ok(Opcode::FilerRegAreg) => ("FILER", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::FilewRegAddr) => ("FILEW", vec![decode_reg(op[1]), decode_addr(op[2], op[3])]),
+        Ok(Opcode::FilewRegAreg) => ("FILEW", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::FilerValAddr) => ("FILER", vec![decode_num(op[1]), decode_addr(op[2], op[3])]),
+        Ok(Opcode::FilerValAreg) => ("FILER", vec![decode_num(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::FilewValAddr) => ("FILEW", vec![decode_num(op[1]), decode_addr(op[2], op[3])]),
+        Ok(Opcode::FilewValAreg) => ("FILEW", vec![decode_num(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::FilewRegReg) => ("FILEW", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::FilewRegVal) => ("FILEW", vec![decode_reg(op[1]), decode_num(op[2])]),
+        Ok(Opcode::FilewValReg) => ("FILEW", vec![decode_num(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::FilewValVal) => ("FILEW", vec![decode_num(op[1]), decode_num(op[2])]),
+        Ok(Opcode::FilerRegAregAreg) => (
+            "FILER",
+            vec![decode_reg(op[1]), decode_reg(op[2]), decode_reg(op[3])],
+        ),
+        Ok(Opcode::FilerValAregAreg) => (
+            "FILER",
+            vec![decode_num(op[1]), decode_reg(op[2]), decode_reg(op[3])],
+        ),
+        Ok(Opcode::FilewRegAregAreg) => (
+            "FILEW",
+            vec![decode_reg(op[1]), decode_reg(op[2]), decode_reg(op[3])],
+        ),
+        Ok(Opcode::FilewValAregAreg) => (
+            "FILEW",
+            vec![decode_num(op[1]), decode_reg(op[2]), decode_reg(op[3])],
+        ),
+        Ok(Opcode::MemrAddr) => ("MEMR", vec![decode_addr(op[1], op[2])]),
+        Ok(Opcode::MemrAreg) => ("MEMR", vec![decode_reg(op[1])]),
+        Ok(Opcode::MemwAddr) => ("MEMW", vec![decode_addr(op[1], op[2])]),
+        Ok(Opcode::MemwAreg) => ("MEMW", vec![decode_reg(op[1])]),
+        Ok(Opcode::MemcpyAregAregReg) => (
+            "MEMCPY",
+            vec![decode_reg(op[1]), decode_reg(op[2]), decode_reg(op[3])],
+        ),
+        Ok(Opcode::MemsetAregRegReg) => (
+            "MEMSET",
+            vec![decode_reg(op[1]), decode_reg(op[2]), decode_reg(op[3])],
+        ),
+        Ok(Opcode::MemsetAregValReg) => (
+            "MEMSET",
+            vec![decode_reg(op[1]), decode_num(op[2]), decode_reg(op[3])],
+        ),
+        Ok(Opcode::MemrAregReg) => ("MEMR", vec![decode_indexed(op[1], op[2])]),
+        Ok(Opcode::MemwAregReg) => ("MEMW", vec![decode_indexed(op[1], op[2])]),
+        Ok(Opcode::PrtsStr) => ("PRTS", vec![decode_string_key(op[1], op[2])]),
+        Ok(Opcode::StrldAregStr) => (
+            "STRLD",
+            vec![decode_reg(op[1]), decode_string_key(op[2], op[3])],
+        ),
+        Ok(Opcode::StrldAregAreg) => ("STRLD", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::StrldAregDataVal) => (
+            "STRLD",
+            vec![
+                decode_reg(op[1]),
+                decode_data_key(op[2], op[3]),
+                decode_num(op[4]),
+            ],
+        ),
+        Ok(Opcode::StrldAregDataReg) => (
+            "STRLD",
+            vec![
+                decode_reg(op[1]),
+                decode_data_key(op[2], op[3]),
+                decode_reg(op[4]),
+            ],
+        ),
+        Ok(Opcode::ItoaAregReg) => ("ITOA", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::ItoaAregVal) => ("ITOA", vec![decode_reg(op[1]), decode_num(op[2])]),
+        Ok(Opcode::AtoiAregReg) => ("ATOI", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::JmpAddr) => ("JMP", vec![decode_label(op[1], op[2], labels)]),
+        Ok(Opcode::JeAddr) => ("JE", vec![decode_label(op[1], op[2], labels)]),
+        Ok(Opcode::JneAddr) => ("JNE", vec![decode_label(op[1], op[2], labels)]),
+        Ok(Opcode::JlAddr) => ("JL", vec![decode_label(op[1], op[2], labels)]),
+        Ok(Opcode::JgAddr) => ("JG", vec![decode_label(op[1], op[2], labels)]),
+        Ok(Opcode::OverAddr) => ("OVER", vec![decode_label(op[1], op[2], labels)]),
+        Ok(Opcode::NoverAddr) => ("NOVER", vec![decode_label(op[1], op[2], labels)]),
+        Ok(Opcode::JmpAreg) => ("JMP", vec![decode_reg(op[1])]),
+        Ok(Opcode::JeAreg) => ("JE", vec![decode_reg(op[1])]),
+        Ok(Opcode::JneAreg) => ("JNE", vec![decode_reg(op[1])]),
+        Ok(Opcode::JlAreg) => ("JL", vec![decode_reg(op[1])]),
+        Ok(Opcode::JgAreg) => ("JG", vec![decode_reg(op[1])]),
+        Ok(Opcode::OverAreg) => ("OVER", vec![decode_reg(op[1])]),
+        Ok(Opcode::NoverAreg) => ("NOVER", vec![decode_reg(op[1])]),
+        Ok(Opcode::Nop) => ("NOP", vec![]),
+        Ok(Opcode::Halt) => ("HALT", vec![]),
+        Ok(Opcode::ExitReg) => ("EXIT", vec![decode_reg(op[1])]),
+        Ok(Opcode::ExitVal) => ("EXIT", vec![decode_num(op[1])]),
+        Ok(Opcode::InthValAddr) => (
+            "INTH",
+            vec![decode_num(op[1]), decode_label(op[2], op[3], labels)],
+        ),
+        Ok(Opcode::ImaskVal) => ("IMASK", vec![decode_num(op[1])]),
+        Ok(Opcode::ItimerVal) => ("ITIMER", vec![decode_num(op[1])]),
+        Ok(Opcode::Reti) => ("RETI", vec![]),
+        Ok(Opcode::Ret) => ("RET", vec![]),
+        Ok(Opcode::CallAddr) => ("CALL", vec![decode_label(op[1], op[2], labels)]),
+        Ok(Opcode::CallAreg) => ("CALL", vec![decode_reg(op[1])]),
+        Ok(Opcode::PopReg) => ("POP", vec![decode_reg(op[1])]),
+        Ok(Opcode::PopVal) => ("POP", vec![decode_num(op[1])]),
+        Ok(Opcode::LocalVal) => ("LOCAL", vec![decode_num(op[1])]),
+        Ok(Opcode::LvarRegVal) => ("LVAR", vec![decode_reg(op[1]), decode_num(op[2])]),
+        Ok(Opcode::LvarValReg) => ("LVAR", vec![decode_num(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::PushReg) => ("PUSH", vec![decode_reg(op[1])]),
+        Ok(Opcode::PushVal) => ("PUSH", vec![decode_num(op[1])]),
+        Ok(Opcode::SwpRegReg) | Ok(Opcode::SwpAregAreg) => {
+            ("SWP", vec![decode_reg(op[1]), decode_reg(op[2])])
+        }
+        Ok(Opcode::ArgRegVal) => ("ARG", vec![decode_reg(op[1]), decode_num(op[2])]),
+        Ok(Opcode::ArgRegReg) => ("ARG", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::FchkRegAreg) => ("FCHK", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::FchkRegAddr) => (
+            "FCHK",
+            vec![decode_reg(op[1]), decode_label(op[2], op[3], labels)],
+        ),
+        Ok(Opcode::FchkValAreg) => ("FCHK", vec![decode_num(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::FchkValAddr) => (
+            "FCHK",
+            vec![decode_num(op[1]), decode_label(op[2], op[3], labels)],
+        ),
+        Ok(Opcode::IpollAreg) => ("IPOLL", vec![decode_reg(op[1])]),
+        Ok(Opcode::IpollAddr) => ("IPOLL", vec![decode_label(op[1], op[2], labels)]),
+        Ok(Opcode::RstrAreg) => ("RSTR", vec![decode_reg(op[1])]),
+        Ok(Opcode::RstrAddr) => ("RSTR", vec![decode_addr(op[1], op[2])]),
+        Ok(Opcode::ArgsAreg) => ("ARGS", vec![decode_reg(op[1])]),
+        Ok(Opcode::ArgsAddr) => ("ARGS", vec![decode_addr(op[1], op[2])]),
+        Ok(Opcode::SaveStrAreg) => (
+            "SAVE",
+            vec![decode_string_key(op[1], op[2]), decode_reg(op[3])],
+        ),
+        Ok(Opcode::LoadkvStrAreg) => (
+            "LOADKV",
+            vec![decode_string_key(op[1], op[2]), decode_reg(op[3])],
+        ),
+        Ok(Opcode::MempAreg) => ("MEMP", vec![decode_reg(op[1])]),
+        Ok(Opcode::MempAddr) => ("MEMP", vec![decode_addr(op[1], op[2])]),
+        Ok(Opcode::RchrReg) => ("RCHR", vec![decode_reg(op[1])]),
+        Ok(Opcode::SendReg) => ("SEND", vec![decode_reg(op[1])]),
+        Ok(Opcode::SendVal) => ("SEND", vec![decode_num(op[1])]),
+        Ok(Opcode::RecvReg) => ("RECV", vec![decode_reg(op[1])]),
+        Ok(Opcode::LdiAregVal) => (
+            "LDI",
+            vec![
+                decode_reg(op[1]),
+                format!("{}", u16::from_be_bytes([op[2], op[3]])),
+            ],
+        ),
+        Ok(Opcode::AndRegReg) => ("AND", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::AndRegVal) => ("AND", vec![decode_reg(op[1]), decode_num(op[2])]),
+        Ok(Opcode::AndRegAreg) => ("AND", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::OrRegReg) => ("OR", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::OrRegVal) => ("OR", vec![decode_reg(op[1]), decode_num(op[2])]),
+        Ok(Opcode::OrRegAreg) => ("OR", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::XorRegReg) => ("XOR", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::XorRegVal) => ("XOR", vec![decode_reg(op[1]), decode_num(op[2])]),
+        Ok(Opcode::XorRegAreg) => ("XOR", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::Time) => ("TIME", vec![]),
+        Ok(Opcode::Millis) => ("MILLIS", vec![]),
+        Ok(Opcode::Cls) => ("CLS", vec![]),
+        Ok(Opcode::Vsync) => ("VSYNC", vec![]),
+        Ok(Opcode::CursRegReg) => ("CURS", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::CursRegVal) => ("CURS", vec![decode_reg(op[1]), decode_num(op[2])]),
+        Ok(Opcode::CursValReg) => ("CURS", vec![decode_num(op[1]), decode_reg(op[2])]),
+        Ok(Opcode::CursValVal) => ("CURS", vec![decode_num(op[1]), decode_num(op[2])]),
+        Ok(Opcode::ColorReg) => ("COLOR", vec![decode_reg(op[1])]),
+        Ok(Opcode::ColorVal) => ("COLOR", vec![decode_num(op[1])]),
+        Ok(Opcode::SleepReg) => ("SLEEP", vec![decode_reg(op[1])]),
+        Ok(Opcode::SleepVal) => ("SLEEP", vec![decode_num(op[1])]),
+        Ok(Opcode::Debug) => ("DEBUG", vec![]),
+        Ok(Opcode::NotReg) => ("NOT", vec![decode_reg(op[1])]),
+        Ok(Opcode::SeedReg) => ("SEED", vec![decode_reg(op[1])]),
+        Ok(Opcode::RandReg) => ("RAND", vec![decode_reg(op[1])]),
+        Ok(Opcode::PrtdAreg) => ("PRTD", vec![decode_reg(op[1])]),
+        Ok(Opcode::PrtsAreg) => ("PRTS", vec![decode_reg(op[1])]),
+        Ok(Opcode::LdAregDataRegReg) => (
             "LD",
             vec![
                 decode_reg(op[1]),
-                decode_addr(op[2], op[3]),
+                decode_data_key(op[2], op[3]),
                 decode_reg(op[4]),
                 decode_reg(op[5]),
             ],
         ),
-        LD_AREG_DATA_REG_VAL => (
+        Ok(Opcode::LdAregDataRegVal) => (
             "LD",
             vec![
                 decode_reg(op[1]),
-                decode_addr(op[2], op[3]),
+                decode_data_key(op[2], op[3]),
                 decode_reg(op[4]),
                 decode_num(op[5]),
             ],
         ),
-        LD_AREG_DATA_VAL_REG => (
+        Ok(Opcode::LdAregDataValReg) => (
             "LD",
             vec![
                 decode_reg(op[1]),
-                decode_addr(op[2], op[3]),
+                decode_data_key(op[2], op[3]),
                 decode_num(op[4]),
                 decode_reg(op[5]),
             ],
         ),
-        LD_AREG_DATA_VAL_VAL => (
+        Ok(Opcode::LdAregDataValVal) => (
             "LD",
             vec![
                 decode_reg(op[1]),
-                decode_addr(op[2], op[3]),
+                decode_data_key(op[2], op[3]),
                 decode_num(op[4]),
                 decode_num(op[5]),
             ],
         ),
-        _ => ("???", vec![]),
+        Err(_) => ("???", vec![]),
     };
     let mut strings = params;
     strings.insert(0, op_str.to_string());
     Decoded::new(op, strings, byte_offset, is_jump_target)
 }
 
-fn decode_string(b1: u8, b2: u8, data: &[u8]) -> String {
-    let mut addr = u16::from_be_bytes([b1, b2]) as usize;
-    let len = data[addr] as usize;
-    addr += 1;
-    let mut output = vec![];
-    for i in 0..len {
-        output.push(data[addr + i]);
+fn string_key(addr: u16) -> String {
+    format!("str_{:04X}", addr)
+}
+
+fn data_key(addr: u16) -> String {
+    format!("data_{:04X}", addr)
+}
+
+fn label(addr: usize) -> String {
+    format!("lbl_{:04X}", addr)
+}
+
+fn decode_string_key(b1: u8, b2: u8) -> String {
+    string_key(u16::from_be_bytes([b1, b2]))
+}
+
+fn decode_data_key(b1: u8, b2: u8) -> String {
+    data_key(u16::from_be_bytes([b1, b2]))
+}
+
+///Jump targets are written as labels if they point at the start of an op, otherwise as an address
+fn decode_label(b1: u8, b2: u8, labels: &[usize]) -> String {
+    let addr = u16::from_be_bytes([b1, b2]);
+    if labels.contains(&(addr as usize)) {
+        label(addr as usize)
+    } else {
+        decode_addr(b1, b2)
     }
-    String::from_utf8(output)
-        .map(|str| format!("\"{}\"", str))
-        .unwrap_or_else(|_| format!("Unable to decode string (address was {})", addr))
 }
 
 fn decode_addr(b1: u8, b2: u8) -> String {
     let addr = u16::from_be_bytes([b1, b2]);
-    format!("{:04X}", addr)
+    format!("@x{:04X}", addr)
+}
+
+fn decode_indexed(areg: u8, reg: u8) -> String {
+    format!("{}+{}", decode_reg(areg), decode_reg(reg))
 }
 
 fn decode_num(value: u8) -> String {
@@ -344,27 +807,21 @@ fn decode_num(value: u8) -> String {
 }
 
 fn decode_reg(reg: u8) -> String {
-    match reg {
-        REG_D0 => String::from("D0"),
-        REG_D1 => String::from("D1"),
-        REG_D2 => String::from("D2"),
-        REG_D3 => String::from("D3"),
-        REG_ACC => String::from("ACC"),
-        REG_A0 => String::from("A0"),
-        REG_A1 => String::from("A1"),
-        _ => String::from("?"),
-    }
+    Register::try_from(reg)
+        .map(|reg| reg.to_string())
+        .unwrap_or_else(|_| String::from("?"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::constants::ALL_OPS;
+    use crate::constants::code::ALL_OPS;
+    use crate::constants::hardware::REG_A0;
 
     #[test]
     fn check_decoding_all() {
         for op in ALL_OPS.iter() {
-            let decoded = decode(&mut vec![*op, 0, 0, 0, 0, 0, 0], &[1, 115], 0, false);
+            let decoded = decode(&mut vec![*op, 0, 0, 0, 0, 0, 0], &[], 0, false);
             assert_ne!(
                 decoded.strings[0],
                 String::from("???"),
@@ -373,4 +830,128 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_collect_strings() {
+        let ops = [PRTS_STR, 0, 3];
+        let strings = [2, b'h', b'i', 1, b'!'];
+        let (output, unused) = collect_strings(&collect_references(&ops), &strings);
+        assert_eq!(
+            output,
+            vec![(0, String::from("hi")), (3, String::from("!"))]
+        );
+        assert_eq!(unused, 3);
+    }
+
+    #[test]
+    fn test_collect_data() {
+        let ops = [LD_AREG_DATA_VAL_VAL, REG_A0, 0, 6, 0, 0];
+        let data = [2, 1, 2, 10, 11, 12, 1, 0];
+        let (output, unused) = collect_data(&collect_references(&ops), &data, 1);
+        assert_eq!(
+            output,
+            vec![
+                (0, String::from("[[10],[11,12]]")),
+                (6, String::from("[[]]"))
+            ]
+        );
+        assert_eq!(unused, 6);
+
+        let ops = [LD_AREG_DATA_VAL_VAL, REG_A0, 0, 8, 0, 0];
+        let data = [2, 0, 1, 0, 2, 10, 11, 12, 1, 0, 0];
+        let (output, unused) = collect_data(&collect_references(&ops), &data, 2);
+        assert_eq!(
+            output,
+            vec![
+                (0, String::from("[[10],[11,12]]")),
+                (8, String::from("[[]]"))
+            ]
+        );
+        assert_eq!(unused, 8);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_collect_references() {
+        let ops = [
+            CPY_AREG_ADDR, REG_A0, 0, 3, PRTS_AREG, REG_A0,
+            CPY_AREG_ADDR, REG_A1, 0, 2, PRTD_AREG, REG_A1,
+            CPY_AREG_ADDR, REG_A0, 0, 9, CPY_AREG_AREG, REG_A0, REG_A1, PRTS_AREG, REG_A0,
+        ];
+        let references = collect_references(&ops);
+        let reference = |op_addr: usize, mnemonic: &str| Reference { op_addr, mnemonic: String::from(mnemonic) };
+        assert_eq!(references.strings, BTreeMap::from([(3, vec![reference(0, "CPY"), reference(4, "PRTS")])]));
+        assert_eq!(references.data, BTreeMap::from([(2, vec![reference(6, "CPY"), reference(10, "PRTD")])]));
+        assert_eq!(references.string_loads, BTreeSet::from([0]));
+
+        //PRTD points inside the first entry so only the second is unused
+        let (_, unused) = collect_data(&references, &[1, 1, 5, 1, 1, 6], 1);
+        assert_eq!(unused, 3);
+    }
+
+    #[test]
+    fn test_decompile_references() {
+        let tape = crate::assembler::assemble_source(
+            "Test\n1\n.strings\nhi=Hi\nbye=Bye\n.ops\ncpy a0 hi\nprts a0\n",
+        )
+        .unwrap();
+        let lines = decompile(crate::tape_reader::parse_tape(tape.bytes).unwrap());
+        let strings = lines.iter().position(|line| line == ".strings").unwrap();
+        assert_eq!(
+            lines[strings + 1..strings + 5],
+            [
+                "# unreferenced",
+                "str_0000=\"Bye\"",
+                "# used at 0000 (CPY), 0004 (PRTS)",
+                "str_0004=\"Hi\""
+            ]
+        );
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("    CPY A0 str_0004 ")));
+    }
+
+    #[test]
+    fn test_decompile_with_debug() {
+        let tape = crate::assembler::assemble_source(
+            "Test\n1\n.strings\nhi=Hi\n.ops\nstart:\ncpy a0 hi\nprts a0\nloop:\njmp loop\n",
+        )
+        .unwrap();
+        let lines = decompile_with_debug(
+            crate::tape_reader::parse_tape(tape.bytes).unwrap(),
+            Some(&tape.debug),
+        );
+        let ops = lines.iter().position(|line| line == ".ops").unwrap();
+        assert_eq!(
+            lines[ops + 1..],
+            [
+                "start:",
+                "    CPY A0 hi                    # 0000  7: cpy a0 hi",
+                "    PRTS A0                      # 0004  8: prts a0",
+                "loop:",
+                "    JMP loop                     # 0006  10: jmp loop",
+            ]
+        );
+        assert!(lines.contains(&String::from("hi=\"Hi\"")));
+    }
+
+    #[test]
+    fn test_jump_targets_are_labelled() {
+        let ops = vec![NOP, JMP_ADDR, 0, 0, MEMR_ADDR, 0, 1, JE_ADDR, 0, 2];
+        let targets = collect_jump_targets(&ops);
+        assert_eq!(targets, vec![0]);
+        let mut bytes = ops[1..].to_vec();
+        assert_eq!(
+            decode(&mut bytes, &targets, 1, false).strings,
+            vec!["JMP", "lbl_0000"]
+        );
+        assert_eq!(
+            decode(&mut bytes, &targets, 4, false).strings,
+            vec!["MEMR", "@x0001"]
+        );
+        assert_eq!(
+            decode(&mut bytes, &targets, 7, false).strings,
+            vec!["JE", "@x0002"]
+        );
+    }
 }
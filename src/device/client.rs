@@ -0,0 +1,126 @@
+//! Drive a tape running in a piped device from another Rust program
+//!
+//! ```no_run
+//! # use tape_device::device::client::PipedClient;
+//! # use tape_device::device::protocol::Response;
+//! let mut client = PipedClient::spawn("tape_device", "program.tape", &[]).unwrap();
+//! for response in client.run().unwrap() {
+//!     if let Response::Output(text) = response {
+//!         print!("{}", text);
+//!     }
+//! }
+//! ```
+
+use crate::device::protocol::{read_message, write_message, Request, Response, PROTOCOL_VERSION};
+use crate::device::Dump;
+use anyhow::{Error, Result};
+use std::io::{BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+pub struct PipedClient<R: Read, W: Write> {
+    reader: R,
+    writer: W,
+    ///Device process, killed when the client is dropped
+    child: Option<Child>,
+}
+
+impl PipedClient<BufReader<ChildStdout>, ChildStdin> {
+    ///Start `executable tape_path --piped [data_files]` and connect to it
+    pub fn spawn(executable: &str, tape_path: &str, data_files: &[&str]) -> Result<Self> {
+        let mut child = Command::new(executable)
+            .arg(tape_path)
+            .args(data_files)
+            .arg("--piped")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let reader = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let writer = child.stdin.take().expect("stdin is piped");
+        let mut client = PipedClient::new(reader, writer)?;
+        client.child = Some(child);
+        Ok(client)
+    }
+}
+
+impl<R: Read, W: Write> PipedClient<R, W> {
+    ///Connect over existing streams, reads the device's hello and checks the protocol version
+    pub fn new(reader: R, writer: W) -> Result<Self> {
+        let mut client = PipedClient {
+            reader,
+            writer,
+            child: None,
+        };
+        match client.receive()? {
+            Response::Hello { version } if version == PROTOCOL_VERSION => Ok(client),
+            Response::Hello { version } => Err(Error::msg(format!(
+                "Device uses protocol version {}, expected {}",
+                version, PROTOCOL_VERSION
+            ))),
+            other => Err(Error::msg(format!("Expected hello but got {:?}", other))),
+        }
+    }
+
+    pub fn send(&mut self, request: &Request) -> Result<()> {
+        write_message(&mut self.writer, request)
+    }
+
+    pub fn receive(&mut self) -> Result<Response> {
+        read_message(&mut self.reader)?.ok_or_else(|| Error::msg("Device closed connection"))
+    }
+
+    ///Send request and collect the responses to it
+    pub fn request(&mut self, request: &Request) -> Result<Vec<Response>> {
+        self.send(request)?;
+        let mut responses = vec![];
+        loop {
+            match self.receive()? {
+                Response::Ready => return Ok(responses),
+                response => responses.push(response),
+            }
+        }
+    }
+
+    pub fn step(&mut self) -> Result<Vec<Response>> {
+        self.request(&Request::Step)
+    }
+
+    pub fn dump(&mut self) -> Result<Dump> {
+        for response in self.request(&Request::Dump)? {
+            if let Response::Dump(dump) = response {
+                return Ok(dump);
+            }
+        }
+        Err(Error::msg("Device didn't send dump"))
+    }
+
+    ///Step until the program ends, crashes, hits a breakpoint or waits for input
+    pub fn run(&mut self) -> Result<Vec<Response>> {
+        let mut responses = vec![];
+        loop {
+            let step = self.step()?;
+            let stop = step.iter().any(|response| {
+                matches!(
+                    response,
+                    Response::End
+                        | Response::Crash
+                        | Response::CycleLimit
+                        | Response::BreakpointHit(_)
+                        | Response::InputRequest(_)
+                )
+            });
+            responses.extend(step);
+            if stop {
+                return Ok(responses);
+            }
+        }
+    }
+}
+
+impl<R: Read, W: Write> Drop for PipedClient<R, W> {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
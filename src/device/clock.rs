@@ -0,0 +1,65 @@
+#[cfg(feature = "clock")]
+use chrono::{Local, Timelike};
+use random_fast_rng::FastRng;
+#[cfg(feature = "clock")]
+use std::time::{Duration, Instant};
+
+///Host time for TIME, MILLIS and SLEEP
+///
+///Without the `clock` feature there is no host time and the device always uses the virtual clock
+pub(crate) struct HostClock {
+    #[cfg(feature = "clock")]
+    started: Instant,
+}
+
+#[cfg(feature = "clock")]
+impl HostClock {
+    pub fn new() -> Self {
+        HostClock {
+            started: Instant::now(),
+        }
+    }
+
+    pub fn seconds_since_midnight(&self) -> Option<u64> {
+        Some(Local::now().num_seconds_from_midnight() as u64)
+    }
+
+    ///Milliseconds since the clock was created
+    pub fn millis(&self) -> Option<u64> {
+        Some(self.started.elapsed().as_millis() as u64)
+    }
+
+    ///Returns false if the host can't sleep
+    pub fn sleep(&self, millis: u64) -> bool {
+        std::thread::sleep(Duration::from_millis(millis));
+        true
+    }
+
+    pub fn rng() -> FastRng {
+        FastRng::new()
+    }
+}
+
+#[cfg(not(feature = "clock"))]
+impl HostClock {
+    pub fn new() -> Self {
+        HostClock {}
+    }
+
+    pub fn seconds_since_midnight(&self) -> Option<u64> {
+        None
+    }
+
+    pub fn millis(&self) -> Option<u64> {
+        None
+    }
+
+    pub fn sleep(&self, _: u64) -> bool {
+        false
+    }
+
+    ///Fixed seed as there's no time to seed from
+    pub fn rng() -> FastRng {
+        FastRng::seed(0, u64::MAX)
+    }
+}
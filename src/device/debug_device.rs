@@ -1,23 +1,35 @@
-use crate::assembler::debug_model::DebugModel;
-use crate::constants::code::RET;
+use crate::assembler::assemble_line;
+use crate::assembler::debug_model::{DebugModel, DebugOp};
+use crate::constants::code::{NOP, RET};
+use crate::constants::hardware::RAM_SIZE;
 use crate::constants::{get_addr_byte_offset, is_jump_op};
-use crate::device::comm::Output;
-use crate::device::internals::{Device, RunResult};
-use crate::device::util::{convert_and_fit, fit_in_lines};
-use crate::device::Dump;
-use anyhow::Result;
-use crossterm::cursor::{Hide, MoveToColumn, MoveToPreviousLine, Show};
+use crate::device::comm::{stack_corrupted_text, uninitialized_read_text, Output};
+use crate::device::debug_diff::diff_states;
+use crate::device::debug_expr::{evaluate, format_value};
+use crate::device::debug_script::ScriptCommand;
+use crate::device::input::key_to_byte;
+use crate::device::internals::{mmio_register_name, Capabilities, Device, RunResult};
+use crate::device::output_log::OutputLog;
+use crate::device::util::{convert_and_fit, fit_in_lines, terminal_color, truncate_line};
+use crate::device::{read_sibling_source, DeviceState, Dump};
+use crate::tape_reader::{link_data_tapes, read_tape_strict};
+use anyhow::{Context, Error, Result};
+use crossterm::cursor::{Hide, MoveTo, MoveToColumn, MoveToPreviousLine, Show};
 use crossterm::event::{Event, KeyCode, KeyModifiers};
-use crossterm::style::Styler;
+use crossterm::style::{Colorize, ResetColor, SetForegroundColor, Styler};
 use crossterm::terminal::{Clear, ClearType};
 use crossterm::ExecutableCommand;
-use std::io::stdout;
+use std::fs::{read_to_string, write};
+use std::io::{stdout, Write};
+use std::path::{Path, PathBuf};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 pub struct DebugDevice {
     device: Device,
     debug: DebugModel,
+    ///Lines of the program's .basm file, shown in the listing around the PC if set
+    source: Vec<String>,
     last_run_result: RunResult,
     ui_memory: Option<(u16, u16)>,
     state: DebuggerState,
@@ -27,11 +39,45 @@ pub struct DebugDevice {
     hex_16bit: bool,
     dump_chars: bool,
     original_line: bool,
+    show_listing: bool,
     print_info: bool,
     print_help: bool,
     print_history: bool,
+    ///Recorded by the first diff, compared against by the next
+    diff_base: Option<DeviceState>,
     auto_run: bool,
+    ///Index into [`AUTO_RUN_SPEEDS`]
+    auto_run_speed: usize,
+    ///When the last auto-run batch was executed
+    auto_run_last: Instant,
+    ///Temporary breakpoint for run to line, auto-run stops when PC reaches it
+    run_to: Option<u16>,
+    output_log: OutputLog,
+    show_output: bool,
+    ///Lines the output pane is scrolled up from the latest output
+    output_scroll: usize,
+    ///Last output search, `n` finds the previous match
+    output_search: Option<String>,
+    ///Line of the last search match, highlighted in the output pane
+    output_match: Option<usize>,
+    last_watchpoint_hit: Option<u16>,
+    ///Bytes per row in memory panel, depends on terminal width
+    memory_row_len: usize,
+    ///Addresses of instructions modified while debugging
+    patched: Vec<u16>,
     history: Vec<Option<History>>,
+    ///Tape and debug file paths, used to reload after the program has been rebuilt
+    reload_paths: Option<(String, String)>,
+    data_files: Vec<String>,
+    capabilities: Capabilities,
+    ///Memory mapped I/O is enabled, see [`Device::set_mmio`]
+    mmio: bool,
+    read_only_files: Vec<usize>,
+    save_file: Option<PathBuf>,
+    ///Warn about reads of uninitialized memory, see [`Device::set_strict`]
+    strict: bool,
+    ///Check frames with canaries, see [`Device::set_stack_check`]
+    check_stack: bool,
 }
 
 #[derive(Debug)]
@@ -89,10 +135,14 @@ impl DebugDevice {
         data: Vec<u8>,
         debug_info: DebugModel,
         data_files: Vec<String>,
+        capabilities: Capabilities,
     ) -> Self {
+        let mut device = Device::new(ops, strings, data, data_files.clone());
+        device.set_capabilities(capabilities);
         DebugDevice {
-            device: Device::new(ops, strings, data, data_files),
+            device,
             debug: debug_info,
+            source: vec![],
             last_run_result: RunResult::Pause,
             ui_memory: None,
             state: DebuggerState::Ready,
@@ -102,13 +152,83 @@ impl DebugDevice {
             hex_16bit: true,
             dump_chars: false,
             original_line: false,
+            show_listing: true,
             print_info: false,
             print_help: false,
             print_history: false,
+            diff_base: None,
             auto_run: false,
+            auto_run_speed: DEFAULT_AUTO_RUN_SPEED,
+            auto_run_last: Instant::now(),
+            run_to: None,
+            output_log: OutputLog::default(),
+            show_output: false,
+            output_scroll: 0,
+            output_search: None,
+            output_match: None,
+            last_watchpoint_hit: None,
+            patched: vec![],
+            memory_row_len: MAX_MEMORY_ROW_LEN,
             history: vec![],
+            reload_paths: None,
+            data_files,
+            capabilities,
+            mmio: false,
+            read_only_files: vec![],
+            save_file: None,
+            strict: false,
+            check_stack: false,
         }
     }
+
+    pub fn set_mmio(&mut self, enabled: bool) {
+        self.mmio = enabled;
+        self.device.set_mmio(enabled);
+    }
+
+    pub fn set_read_only_files(&mut self, files: Vec<usize>) {
+        self.read_only_files = files.clone();
+        self.device.set_read_only_files(files);
+    }
+
+    pub fn set_strict(&mut self, enabled: bool) {
+        self.strict = enabled;
+        self.device.set_strict(enabled);
+    }
+
+    pub fn set_stack_check(&mut self, enabled: bool) {
+        self.check_stack = enabled;
+        self.device.set_stack_check(enabled);
+    }
+
+    fn uninitialized_read_text(&self, pc: u16, addr: u16) -> String {
+        uninitialized_read_text(pc, addr, self.debug.line_for_byte(pc))
+    }
+
+    fn stack_corrupted_text(&self, pc: u16, addr: u16) -> String {
+        stack_corrupted_text(pc, addr, self.debug.line_for_byte(pc))
+    }
+
+    pub fn set_save_file(&mut self, path: PathBuf) {
+        self.save_file = Some(path.clone());
+        self.device.set_save_file(path);
+    }
+
+    pub fn set_tape_version(&mut self, version: u8) {
+        self.device.set_tape_version(version);
+    }
+
+    pub fn set_entry(&mut self, addr: u16) {
+        self.device.set_entry(addr);
+    }
+
+    pub fn set_reload_paths(&mut self, tape_path: &str, debug_path: &str) {
+        self.reload_paths = Some((tape_path.to_owned(), debug_path.to_owned()));
+    }
+
+    pub fn set_source(&mut self, source: Vec<String>) {
+        self.source = source;
+    }
 }
 
 impl DebugDevice {
@@ -118,51 +238,8 @@ impl DebugDevice {
         loop {
             self.draw()?;
             if let Some(input) = self.process_input()? {
-                match input {
-                    Input::ForceStep => {
-                        if self.state == DebuggerState::Ready {
-                            let pc = self.device.pc;
-                            self.last_run_result = self.device.step(true);
-                            if should_add_history(&self.last_run_result) && pc != self.device.pc {
-                                self.add_history(pc);
-                            }
-                        }
-                    }
-                    Input::SetBreakpoint(byte) => self.device.breakpoints.push(byte),
-                    Input::ClearBreakpoint(byte) => {
-                        remove_if_present(&mut self.device.breakpoints, &byte)
-                    }
-                    Input::Char(chr) => {
-                        self.device.keyboard_buffer.push(chr as u8);
-                        self.state = DebuggerState::Ready;
-                        if self.last_run_result == RunResult::CharInputRequested {
-                            let pc = self.device.pc;
-                            self.last_run_result = self.device.step(true);
-                            if should_add_history(&self.last_run_result) && pc != self.device.pc {
-                                self.add_history(pc);
-                            }
-                        }
-                    }
-                    Input::Text(str) => {
-                        self.device
-                            .keyboard_buffer
-                            .extend_from_slice(str.as_bytes());
-                        self.state = DebuggerState::Ready;
-                        let pc = self.device.pc;
-                        self.last_run_result = self.device.step(true);
-                        if should_add_history(&self.last_run_result) && pc != self.device.pc {
-                            self.add_history(pc);
-                        }
-                    }
-                    Input::Terminate => return Ok(()),
-                    Input::Toggle16BitDisplay => self.hex_16bit = !self.hex_16bit,
-                    Input::Toggle8BitDisplay => self.hex_8bit = !self.hex_8bit,
-                    Input::ToggleDumpCharacters => self.dump_chars = !self.dump_chars,
-                    Input::ToggleOriginalLine => self.original_line = !self.original_line,
-                    Input::Info => self.print_info = true,
-                    Input::Help => self.print_help = true,
-                    Input::ExecutionHistory => self.print_history = true,
-                    Input::ToggleAutoRun => self.auto_run = !self.auto_run,
+                if self.handle_input(input) {
+                    return Ok(());
                 }
                 self.redraw = true;
             }
@@ -170,22 +247,19 @@ impl DebugDevice {
             match self.last_run_result {
                 RunResult::Pause => {
                     if self.auto_run {
-                        let pc = self.device.pc;
-                        self.last_run_result = self.device.step(false);
-                        if self.last_run_result != RunResult::Pause {
-                            self.auto_run = false;
-                        }
-                        if should_add_history(&self.last_run_result) && pc != self.device.pc {
-                            self.add_history(pc);
-                        }
-                        self.redraw = true;
-                        sleep(Duration::from_millis(1));
+                        self.auto_run_batch();
                     }
                 }
-                RunResult::Breakpoint => {
+                RunResult::Breakpoint | RunResult::Watchpoint => {
                     self.state = DebuggerState::Ready;
                 }
-                RunResult::EoF | RunResult::Halt | RunResult::ProgError => {
+                //nothing is connected to the serial port while debugging so RECV would wait forever
+                RunResult::EoF
+                | RunResult::Halt
+                | RunResult::Exit(_)
+                | RunResult::ProgError
+                | RunResult::CycleLimit
+                | RunResult::SerialRequested => {
                     self.state = DebuggerState::ProgEnd;
                     self.redraw = true;
                 }
@@ -195,6 +269,11 @@ impl DebugDevice {
                         self.redraw = true;
                     }
                 }
+                //stdin isn't available while debugging so the program sees it as empty
+                RunResult::StdinRequested => {
+                    self.device.close_stdin();
+                    self.last_run_result = RunResult::Pause;
+                }
                 RunResult::StringInputRequested => {
                     if let DebuggerState::WaitingForString(_) = self.state {
                     } else {
@@ -206,6 +285,402 @@ impl DebugDevice {
         }
     }
 
+    ///Apply a command from the keyboard or a script, returns true if the debugger should quit
+    fn handle_input(&mut self, input: Input) -> bool {
+        match input {
+            Input::ForceStep => {
+                if self.state == DebuggerState::Ready {
+                    self.step_device(true);
+                }
+            }
+            Input::SetBreakpoint(byte) => self.device.breakpoints.push(byte),
+            Input::ClearBreakpoint(byte) => remove_if_present(&mut self.device.breakpoints, &byte),
+            Input::SetWatchpoint(start, end) => self.device.watchpoints.push((start, end)),
+            Input::ClearWatchpoint(addr) => self.device.clear_watchpoints(addr, addr),
+            Input::Char(chr) => {
+                self.device.push_char(chr as u8);
+                self.state = DebuggerState::Ready;
+                if self.last_run_result == RunResult::CharInputRequested {
+                    self.step_device(true);
+                }
+            }
+            Input::Text(str) => {
+                self.device.push_string(&str);
+                self.state = DebuggerState::Ready;
+                self.step_device(true);
+            }
+            Input::Terminate => return true,
+            Input::Toggle16BitDisplay => self.hex_16bit = !self.hex_16bit,
+            Input::Toggle8BitDisplay => self.hex_8bit = !self.hex_8bit,
+            Input::ToggleDumpCharacters => self.dump_chars = !self.dump_chars,
+            Input::ToggleOriginalLine => self.original_line = !self.original_line,
+            Input::ToggleListing => self.show_listing = !self.show_listing,
+            Input::SetMemoryView(range) => self.ui_memory = range,
+            Input::PokeMemory(addr, value) => {
+                if let Err(err) = self.device.poke(addr, value) {
+                    eprintln!("Unable to set {:04X}: {}", addr, err);
+                }
+            }
+            Input::PatchInstruction(line, bytes) => self.patch_instruction(line, bytes),
+            Input::Evaluate(expr) => match self.evaluate(&expr) {
+                Ok(result) => println!("{}", result),
+                Err(err) => eprintln!("{}", err),
+            },
+            Input::SetValue(text) => match self.set_value(&text) {
+                Ok(result) => println!("{}", result),
+                Err(err) => eprintln!("Unable to set {}: {}", text, err),
+            },
+            Input::Reload => {
+                if let Err(err) = self.reload() {
+                    eprintln!("Unable to reload: {}", err);
+                }
+            }
+            Input::SaveSnapshot => {
+                if let Err(err) = self.save_snapshot() {
+                    eprintln!("Unable to save snapshot: {}", err);
+                }
+            }
+            Input::Diff => {
+                if let Err(err) = self.print_diff() {
+                    eprintln!("Unable to diff: {}", err);
+                }
+            }
+            Input::LoadSnapshot => {
+                if let Err(err) = self.load_snapshot() {
+                    eprintln!("Unable to load snapshot: {}", err);
+                }
+            }
+            Input::Info => self.print_info = true,
+            Input::Help => self.print_help = true,
+            Input::ExecutionHistory => self.print_history = true,
+            Input::ToggleAutoRun => {
+                self.auto_run = !self.auto_run;
+                self.auto_run_last = Instant::now();
+                self.run_to = None;
+            }
+            Input::AutoRunFaster => {
+                self.auto_run_speed = (self.auto_run_speed + 1).min(AUTO_RUN_SPEEDS.len() - 1)
+            }
+            Input::AutoRunSlower => self.auto_run_speed = self.auto_run_speed.saturating_sub(1),
+            Input::ToggleOutputPane => self.show_output = !self.show_output,
+            Input::ScrollOutput(up) => {
+                self.show_output = true;
+                let max_scroll = self.output_log.len().saturating_sub(OUTPUT_PANE_HEIGHT);
+                self.output_scroll = if up {
+                    (self.output_scroll + OUTPUT_PANE_HEIGHT).min(max_scroll)
+                } else {
+                    self.output_scroll.saturating_sub(OUTPUT_PANE_HEIGHT)
+                };
+            }
+            Input::SearchOutput(query) => {
+                self.output_match = None;
+                self.output_search = Some(query);
+                self.search_output();
+            }
+            Input::SearchOutputNext => self.search_output(),
+            Input::RunTo(addr) => {
+                //the first step ignores breakpoints so it can run from one, or back to the current line
+                self.step_device(true);
+                self.auto_run = self.last_run_result == RunResult::Pause && self.device.pc != addr;
+                self.run_to = self.auto_run.then_some(addr);
+            }
+        }
+        false
+    }
+
+    ///Run `commands` without the terminal UI, writing each command, its result and the program's output to `out`
+    pub fn run_script<W: Write>(
+        &mut self,
+        commands: &[(String, ScriptCommand)],
+        out: &mut W,
+    ) -> Result<()> {
+        for (line, command) in commands {
+            writeln!(out, "> {}", line)?;
+            let mut output = String::new();
+            match command {
+                ScriptCommand::Break(target) | ScriptCommand::Clear(target) => {
+                    match self.breakpoint_addr(target) {
+                        Ok(addr) if matches!(command, ScriptCommand::Break(_)) => {
+                            self.handle_input(Input::SetBreakpoint(addr));
+                        }
+                        Ok(addr) => {
+                            self.handle_input(Input::ClearBreakpoint(addr));
+                        }
+                        Err(err) => writeln!(out, "{}", err)?,
+                    }
+                }
+                ScriptCommand::Watch(start, end) => {
+                    self.handle_input(Input::SetWatchpoint(*start, *end));
+                }
+                ScriptCommand::Unwatch(addr) => {
+                    self.handle_input(Input::ClearWatchpoint(*addr));
+                }
+                ScriptCommand::Step(count) => {
+                    for _ in 0..*count {
+                        if !self.script_step(true, &mut output) {
+                            break;
+                        }
+                    }
+                }
+                ScriptCommand::Run => {
+                    let mut steps = 0;
+                    //the first step ignores breakpoints so run continues from the one it's stopped at
+                    while self.script_step(steps == 0, &mut output) {
+                        steps += 1;
+                        if steps >= SCRIPT_RUN_LIMIT {
+                            writeln!(out, "Stopped after {} instructions", steps)?;
+                            break;
+                        }
+                    }
+                }
+                ScriptCommand::Regs => {
+                    let dump = format_dump(
+                        self.device.dump(),
+                        self.hex_8bit,
+                        self.hex_16bit,
+                        self.dump_chars,
+                    );
+                    writeln!(out, "{}", dump.concat().trim_end())?;
+                }
+                ScriptCommand::Mem(start, end) => {
+                    for line in self.memory_lines(*start, *end, MAX_MEMORY_ROW_LEN, None) {
+                        writeln!(out, "{}", line)?;
+                    }
+                }
+                ScriptCommand::Eval(expr) => match self.evaluate(expr) {
+                    Ok(result) => writeln!(out, "{}", result)?,
+                    Err(err) => writeln!(out, "{}", err)?,
+                },
+                ScriptCommand::Set(text) => match self.set_value(text) {
+                    Ok(result) => writeln!(out, "{}", result)?,
+                    Err(err) => writeln!(out, "Unable to set {}: {}", text, err)?,
+                },
+                ScriptCommand::Input(bytes) => self.device.push_bytes(bytes),
+                ScriptCommand::Diff => match self.diff() {
+                    Ok(lines) => {
+                        for line in lines {
+                            writeln!(out, "{}", line)?;
+                        }
+                    }
+                    Err(err) => writeln!(out, "Unable to diff: {}", err)?,
+                },
+                ScriptCommand::Quit => return Ok(()),
+            }
+            if !output.is_empty() {
+                write!(out, "{}", output)?;
+                if !output.ends_with('\n') {
+                    writeln!(out)?;
+                }
+            }
+            if matches!(command, ScriptCommand::Step(_) | ScriptCommand::Run) {
+                writeln!(out, "{}", self.script_location())?;
+            }
+        }
+        Ok(())
+    }
+
+    ///Address of a breakpoint given as a source line number or label name
+    fn breakpoint_addr(&self, target: &str) -> Result<u16> {
+        match target.parse::<usize>() {
+            Ok(line) => self
+                .debug
+                .byte_for_line(line)
+                .ok_or_else(|| Error::msg(format!("No op on line {}", line))),
+            Err(_) => self
+                .debug
+                .label_addr(target)
+                .ok_or_else(|| Error::msg(format!("No label named {}", target))),
+        }
+    }
+
+    ///Set a register or memory byte from `text`, e.g. `d0 42`, `a1 x1F00` or `mem[a0+1] d2*2`
+    ///
+    ///The value can be any expression, see [`evaluate`]
+    fn set_value(&mut self, text: &str) -> Result<String> {
+        let (target, expr) = text
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| Error::msg("Expected <register|mem[addr]> <value>"))?;
+        let target = target.to_ascii_lowercase();
+        let mut dump = self.device.dump();
+        let value = evaluate(expr, &dump, &self.device.mem)?;
+        let fits_8bit = || {
+            if (i8::MIN as i64..=u8::MAX as i64).contains(&value) {
+                Ok(value as u8)
+            } else {
+                Err(Error::msg(format!("{} doesn't fit in 8 bits", value)))
+            }
+        };
+        let fits_16bit = || {
+            if (i16::MIN as i64..=u16::MAX as i64).contains(&value) {
+                Ok(value as u16)
+            } else {
+                Err(Error::msg(format!("{} doesn't fit in 16 bits", value)))
+            }
+        };
+        if let Some(addr) = target
+            .strip_prefix("mem[")
+            .and_then(|addr| addr.strip_suffix(']'))
+        {
+            let addr = evaluate(addr, &dump, &self.device.mem)?;
+            if addr < 0 || addr >= RAM_SIZE as i64 {
+                return Err(Error::msg(format!("Address {} is outside of memory", addr)));
+            }
+            let byte = fits_8bit()?;
+            self.device.poke(addr as u16, byte)?;
+            return Ok(format!("mem[{:04X}] = {}", addr, format_value(byte as i64)));
+        }
+        match target.as_str() {
+            "acc" => dump.acc = fits_8bit()?,
+            "d0" | "d1" | "d2" | "d3" => {
+                dump.data_reg[target[1..].parse::<usize>()?] = fits_8bit()?
+            }
+            "a0" | "a1" => dump.addr_reg[target[1..].parse::<usize>()?] = fits_16bit()?,
+            "sp" => dump.sp = fits_16bit()?,
+            "fp" => dump.fp = fits_16bit()?,
+            "pc" => dump.pc = fits_16bit()?,
+            _ => return Err(Error::msg(format!("Unknown register '{}'", target))),
+        }
+        self.device.set_registers(dump);
+        //negative values are stored as two's complement
+        let stored = match target.as_str() {
+            "acc" | "d0" | "d1" | "d2" | "d3" => value as u8 as i64,
+            _ => value as u16 as i64,
+        };
+        Ok(format!("{} = {}", target, format_value(stored)))
+    }
+
+    ///Evaluate `expr` against the current registers and memory, see [`evaluate`]
+    fn evaluate(&self, expr: &str) -> Result<String> {
+        let value = evaluate(expr, &self.device.dump(), &self.device.mem)
+            .map_err(|err| Error::msg(format!("Unable to evaluate {}: {}", expr, err)))?;
+        Ok(format!("{} = {}", expr, format_value(value)))
+    }
+
+    ///Step once for a script, adding printed text to `output`, returns false if the program can't continue
+    fn script_step(&mut self, ignore_breakpoints: bool, output: &mut String) -> bool {
+        if matches!(
+            self.last_run_result,
+            RunResult::EoF
+                | RunResult::Halt
+                | RunResult::Exit(_)
+                | RunResult::ProgError
+                | RunResult::CycleLimit
+        ) {
+            return false;
+        }
+        self.step_device(ignore_breakpoints);
+        //stdin isn't available while debugging so the program sees it as empty
+        if self.last_run_result == RunResult::StdinRequested {
+            self.device.close_stdin();
+            self.last_run_result = RunResult::Pause;
+        }
+        for msg in self.device.take_output() {
+            match msg {
+                Output::OutputStd(text) => output.push_str(&text),
+                Output::OutputErr(text) => output.push_str(&format!("Error: {}\n", text.trim())),
+                Output::UninitializedRead(pc, addr) => {
+                    output.push_str(&format!("{}\n", self.uninitialized_read_text(pc, addr)))
+                }
+                Output::StackCorrupted(pc, addr) => {
+                    output.push_str(&format!("Error: {}\n", self.stack_corrupted_text(pc, addr)))
+                }
+                Output::OutputBinary(bytes) => output.push_str(&String::from_utf8_lossy(&bytes)),
+                Output::ClearScreen
+                | Output::MoveCursor(..)
+                | Output::SetColor(_)
+                | Output::Vsync
+                | Output::BreakpointHit(_)
+                | Output::WatchpointHit(_) => {}
+            }
+        }
+        self.last_run_result == RunResult::Pause
+    }
+
+    ///Why a script step or run stopped and where
+    fn script_location(&self) -> String {
+        let location = format!(
+            "line {} ({:04X})",
+            self.debug
+                .line_for_byte(self.device.pc)
+                .map(|line| line.to_string())
+                .unwrap_or_else(|| String::from("?")),
+            self.device.pc
+        );
+        match self.last_run_result {
+            RunResult::Pause => format!("Paused at {}", location),
+            RunResult::Breakpoint => format!("Breakpoint at {}", location),
+            RunResult::Watchpoint => format!("Watchpoint hit, paused at {}", location),
+            RunResult::CharInputRequested | RunResult::StringInputRequested => {
+                format!("Waiting for input at {}", location)
+            }
+            RunResult::SerialRequested => format!("Waiting for serial input at {}", location),
+            RunResult::StdinRequested => format!("Waiting for stdin at {}", location),
+            RunResult::EoF => String::from("Program ended (end of ops)"),
+            RunResult::Halt => String::from("Program ended (HALT)"),
+            RunResult::Exit(code) => format!("Program ended (EXIT {})", code),
+            RunResult::ProgError => format!("Program crashed at {}", location),
+            RunResult::CycleLimit => String::from("Program reached the cycle limit"),
+        }
+    }
+
+    ///Execute the instructions due at the auto-run speed since the last batch, or as many as
+    ///[`RUN_TO_BATCH`] when running to a line, stopping at breakpoints, input requests, etc
+    fn auto_run_batch(&mut self) {
+        let count = if self.run_to.is_some() {
+            RUN_TO_BATCH
+        } else {
+            let speed = AUTO_RUN_SPEEDS[self.auto_run_speed];
+            let due = (self.auto_run_last.elapsed().as_secs_f64() * speed as f64) as usize;
+            if due == 0 {
+                sleep(Duration::from_millis(1));
+                return;
+            }
+            //after a slow frame don't try to catch up more than a second
+            due.min(speed)
+        };
+        self.auto_run_last = Instant::now();
+        for _ in 0..count {
+            self.step_device(false);
+            if self.last_run_result != RunResult::Pause || self.run_to == Some(self.device.pc) {
+                self.auto_run = false;
+                self.run_to = None;
+                break;
+            }
+        }
+        self.redraw = true;
+    }
+
+    ///Find the previous line of output matching [`DebugDevice::output_search`] and scroll to it
+    fn search_output(&mut self) {
+        let query = match &self.output_search {
+            Some(query) => query,
+            None => return,
+        };
+        let before = self.output_match.unwrap_or(self.output_log.len());
+        match self.output_log.search_back(query, before) {
+            Some(idx) => {
+                self.show_output = true;
+                self.output_match = Some(idx);
+                //show the match in the middle of the pane
+                self.output_scroll = self
+                    .output_log
+                    .len()
+                    .saturating_sub(idx + 1 + OUTPUT_PANE_HEIGHT / 2);
+            }
+            None => eprintln!("No output matching '{}'", query),
+        }
+    }
+
+    ///Execute one instruction and record it in the history
+    fn step_device(&mut self, ignore_breakpoints: bool) {
+        let pc = self.device.pc;
+        self.last_run_result = self.device.step(ignore_breakpoints);
+        if should_add_history(&self.last_run_result) && pc != self.device.pc {
+            self.add_history(pc);
+        }
+    }
+
     fn process_input(&mut self) -> Result<Option<Input>> {
         if crossterm::event::poll(Duration::from_millis(10)).unwrap_or(false) {
             match crossterm::event::read()? {
@@ -242,6 +717,46 @@ impl DebugDevice {
                                     DebuggerState::WaitingForBreakpointLineToClear(String::new());
                                 self.redraw = true;
                                 return Ok(None);
+                            } else if let KeyCode::Char('w') = key.code {
+                                self.state =
+                                    DebuggerState::WaitingForWatchpointToSet(String::new());
+                                self.redraw = true;
+                                return Ok(None);
+                            } else if let KeyCode::Char('x') = key.code {
+                                self.state =
+                                    DebuggerState::WaitingForWatchpointToClear(String::new());
+                                self.redraw = true;
+                                return Ok(None);
+                            } else if let KeyCode::Char('m') = key.code {
+                                self.state = DebuggerState::WaitingForMemoryRange(String::new());
+                                self.redraw = true;
+                                return Ok(None);
+                            } else if let KeyCode::Char('e') = key.code {
+                                if let Some((start, _)) = self.ui_memory {
+                                    self.state = DebuggerState::EditingMemory(start, String::new());
+                                    self.redraw = true;
+                                }
+                                return Ok(None);
+                            } else if let KeyCode::Char('p') = key.code {
+                                self.state = DebuggerState::WaitingForPatch(String::new());
+                                self.redraw = true;
+                                return Ok(None);
+                            } else if let KeyCode::Char('/') = key.code {
+                                self.state = DebuggerState::WaitingForOutputSearch(String::new());
+                                self.redraw = true;
+                                return Ok(None);
+                            } else if let KeyCode::Char('v') = key.code {
+                                self.state = DebuggerState::WaitingForSetValue(String::new());
+                                self.redraw = true;
+                                return Ok(None);
+                            } else if let KeyCode::Char('g') = key.code {
+                                self.state = DebuggerState::WaitingForRunToLine(String::new());
+                                self.redraw = true;
+                                return Ok(None);
+                            } else if let KeyCode::Char('=') = key.code {
+                                self.state = DebuggerState::WaitingForExpression(String::new());
+                                self.redraw = true;
+                                return Ok(None);
                             } else if let KeyCode::Char('t') = key.code {
                                 self.state = DebuggerState::WaitingForChar;
                                 self.redraw = true;
@@ -257,24 +772,27 @@ impl DebugDevice {
                                 KeyCode::Char('6') => Some(Input::Toggle16BitDisplay),
                                 KeyCode::Char('c') => Some(Input::ToggleDumpCharacters),
                                 KeyCode::Char('l') => Some(Input::ToggleOriginalLine),
+                                KeyCode::Char('o') => Some(Input::ToggleListing),
                                 KeyCode::Char('i') => Some(Input::Info),
                                 KeyCode::Char('h') => Some(Input::Help),
                                 KeyCode::Char('y') => Some(Input::ExecutionHistory),
                                 KeyCode::Char('a') => Some(Input::ToggleAutoRun),
+                                KeyCode::Char('+') => Some(Input::AutoRunFaster),
+                                KeyCode::Char('-') => Some(Input::AutoRunSlower),
+                                KeyCode::Char('r') => Some(Input::Reload),
+                                KeyCode::Char('S') => Some(Input::SaveSnapshot),
+                                KeyCode::Char('L') => Some(Input::LoadSnapshot),
+                                KeyCode::Char('d') => Some(Input::Diff),
+                                KeyCode::Char('O') => Some(Input::ToggleOutputPane),
+                                KeyCode::PageUp => Some(Input::ScrollOutput(true)),
+                                KeyCode::PageDown => Some(Input::ScrollOutput(false)),
+                                KeyCode::Char('n') => Some(Input::SearchOutputNext),
                                 _ => None,
                             };
                             return Ok(input);
                         }
                         DebuggerState::WaitingForChar => {
-                            return match key.code {
-                                KeyCode::Char(chr) => Ok(Some(Input::Char(chr))),
-                                KeyCode::Enter => Ok(Some(Input::Char(10_u8 as char))),
-                                KeyCode::Tab => Ok(Some(Input::Char(9_u8 as char))),
-                                KeyCode::Backspace => Ok(Some(Input::Char(8_u8 as char))),
-                                KeyCode::Esc => Ok(Some(Input::Char(27_u8 as char))),
-                                KeyCode::Delete => Ok(Some(Input::Char(127_u8 as char))),
-                                _ => Ok(None),
-                            }
+                            return Ok(key_to_byte(&key).map(|chr| Input::Char(chr as char)));
                         }
                         DebuggerState::WaitingForString(line) => {
                             match key.code {
@@ -288,104 +806,386 @@ impl DebugDevice {
                                     self.redraw = true;
                                     return Ok(Some(Input::Text(line.clone())));
                                 }
-                                KeyCode::Backspace => {
-                                    if !line.is_empty() {
-                                        let mut new_line = line.clone();
-                                        new_line.truncate(line.len() - 1);
-                                        self.state = DebuggerState::WaitingForString(new_line);
+                                KeyCode::Backspace if !line.is_empty() => {
+                                    let mut new_line = line.clone();
+                                    new_line.truncate(line.len() - 1);
+                                    self.state = DebuggerState::WaitingForString(new_line);
+                                    self.redraw = true;
+                                }
+                                _ => {}
+                            }
+                            return Ok(None);
+                        }
+                        DebuggerState::WaitingForBreakpointLineToSet(line) => {
+                            match key.code {
+                                KeyCode::Char(chr) => {
+                                    if chr.is_ascii_alphanumeric() || chr == '_' {
+                                        let mut target = line.clone();
+                                        target.push(chr);
+                                        self.state =
+                                            DebuggerState::WaitingForBreakpointLineToSet(target);
                                         self.redraw = true;
                                     }
                                 }
+                                KeyCode::Enter => {
+                                    let result = self.breakpoint_addr(line);
+                                    self.state = DebuggerState::Ready;
+                                    self.redraw = true;
+                                    match result {
+                                        Ok(addr) => return Ok(Some(Input::SetBreakpoint(addr))),
+                                        Err(err) => eprintln!("{}", err),
+                                    }
+                                }
+                                KeyCode::Backspace if !line.is_empty() => {
+                                    let mut new_line = line.clone();
+                                    new_line.truncate(line.len() - 1);
+                                    self.state =
+                                        DebuggerState::WaitingForBreakpointLineToSet(new_line);
+                                    self.redraw = true;
+                                }
                                 _ => {}
                             }
                             return Ok(None);
                         }
-                        DebuggerState::WaitingForBreakpointLineToSet(line) => {
+                        DebuggerState::WaitingForBreakpointLineToClear(line) => {
                             match key.code {
                                 KeyCode::Char(chr) => {
-                                    if ('0'..='9').contains(&chr) {
-                                        let mut num = line.clone();
-                                        num.push(chr);
+                                    if chr.is_ascii_alphanumeric() || chr == '_' {
+                                        let mut target = line.clone();
+                                        target.push(chr);
                                         self.state =
-                                            DebuggerState::WaitingForBreakpointLineToSet(num);
+                                            DebuggerState::WaitingForBreakpointLineToClear(target);
                                         self.redraw = true;
                                     }
                                 }
                                 KeyCode::Enter => {
-                                    if let Ok(num) = line.parse::<usize>() {
-                                        let byte_addr = self.debug.byte_for_line(num);
-                                        self.state = DebuggerState::Ready;
-                                        if let Some(addr) = byte_addr {
-                                            return Ok(Some(Input::SetBreakpoint(addr)));
-                                        } else {
-                                            eprintln!("No op on that line");
-                                        }
-                                    } else {
-                                        eprintln!("Invalid line num")
+                                    let result = self.breakpoint_addr(line);
+                                    self.state = DebuggerState::Ready;
+                                    self.redraw = true;
+                                    match result {
+                                        Ok(addr) => return Ok(Some(Input::ClearBreakpoint(addr))),
+                                        Err(err) => eprintln!("{}", err),
                                     }
+                                }
+                                KeyCode::Backspace if !line.is_empty() => {
+                                    let mut new_line = line.clone();
+                                    new_line.truncate(line.len() - 1);
+                                    self.state =
+                                        DebuggerState::WaitingForBreakpointLineToClear(new_line);
                                     self.redraw = true;
                                 }
-                                KeyCode::Backspace => {
-                                    if !line.is_empty() {
-                                        let mut new_line = line.clone();
-                                        new_line.truncate(line.len() - 1);
+                                _ => {}
+                            }
+                            return Ok(None);
+                        }
+                        DebuggerState::WaitingForWatchpointToSet(text) => {
+                            match key.code {
+                                KeyCode::Char(chr) => {
+                                    if is_address_char(chr) {
+                                        let mut range = text.clone();
+                                        range.push(chr);
                                         self.state =
-                                            DebuggerState::WaitingForBreakpointLineToSet(new_line);
+                                            DebuggerState::WaitingForWatchpointToSet(range);
                                         self.redraw = true;
                                     }
                                 }
+                                KeyCode::Enter => {
+                                    let range = parse_address_range(text);
+                                    self.state = DebuggerState::Ready;
+                                    self.redraw = true;
+                                    if let Some((start, end)) = range {
+                                        return Ok(Some(Input::SetWatchpoint(start, end)));
+                                    } else {
+                                        eprintln!("Invalid address or range")
+                                    }
+                                }
+                                KeyCode::Backspace if !text.is_empty() => {
+                                    let mut new_text = text.clone();
+                                    new_text.truncate(text.len() - 1);
+                                    self.state = DebuggerState::WaitingForWatchpointToSet(new_text);
+                                    self.redraw = true;
+                                }
                                 _ => {}
                             }
                             return Ok(None);
                         }
-                        DebuggerState::WaitingForBreakpointLineToClear(line) => {
+                        DebuggerState::WaitingForWatchpointToClear(text) => {
                             match key.code {
                                 KeyCode::Char(chr) => {
-                                    if ('0'..='9').contains(&chr) {
-                                        let mut num = line.clone();
-                                        num.push(chr);
+                                    if is_address_char(chr) && chr != '-' {
+                                        let mut addr = text.clone();
+                                        addr.push(chr);
                                         self.state =
-                                            DebuggerState::WaitingForBreakpointLineToClear(num);
+                                            DebuggerState::WaitingForWatchpointToClear(addr);
                                         self.redraw = true;
                                     }
                                 }
                                 KeyCode::Enter => {
-                                    if let Ok(num) = line.parse::<usize>() {
-                                        let byte_addr = self.debug.byte_for_line(num);
-                                        self.state = DebuggerState::Ready;
-                                        if let Some(addr) = byte_addr {
-                                            return Ok(Some(Input::ClearBreakpoint(addr)));
-                                        } else {
-                                            eprintln!("No op on that line");
-                                        }
+                                    let addr = parse_address(text);
+                                    self.state = DebuggerState::Ready;
+                                    self.redraw = true;
+                                    if let Some(addr) = addr {
+                                        return Ok(Some(Input::ClearWatchpoint(addr)));
                                     } else {
-                                        eprintln!("Invalid line num")
+                                        eprintln!("Invalid address")
                                     }
+                                }
+                                KeyCode::Backspace if !text.is_empty() => {
+                                    let mut new_text = text.clone();
+                                    new_text.truncate(text.len() - 1);
+                                    self.state =
+                                        DebuggerState::WaitingForWatchpointToClear(new_text);
+                                    self.redraw = true;
+                                }
+                                _ => {}
+                            }
+                            return Ok(None);
+                        }
+                        DebuggerState::WaitingForPatch(line) => {
+                            match key.code {
+                                KeyCode::Char(chr) => {
+                                    let mut text = line.clone();
+                                    text.push(chr);
+                                    self.state = DebuggerState::WaitingForPatch(text);
                                     self.redraw = true;
                                 }
-                                KeyCode::Backspace => {
-                                    if !line.is_empty() {
-                                        let mut new_line = line.clone();
-                                        new_line.truncate(line.len() - 1);
-                                        self.state = DebuggerState::WaitingForBreakpointLineToClear(
-                                            new_line,
-                                        );
+                                KeyCode::Enter => {
+                                    let result = assemble_line(line, &self.debug);
+                                    let line = line.trim().to_string();
+                                    self.state = DebuggerState::Ready;
+                                    self.redraw = true;
+                                    match result {
+                                        Ok(bytes) => {
+                                            return Ok(Some(Input::PatchInstruction(line, bytes)))
+                                        }
+                                        Err(err) => eprintln!("Unable to assemble patch: {}", err),
+                                    }
+                                }
+                                KeyCode::Backspace if !line.is_empty() => {
+                                    let mut new_line = line.clone();
+                                    new_line.truncate(line.len() - 1);
+                                    self.state = DebuggerState::WaitingForPatch(new_line);
+                                    self.redraw = true;
+                                }
+                                _ => {}
+                            }
+                            return Ok(None);
+                        }
+                        DebuggerState::WaitingForRunToLine(target) => {
+                            match key.code {
+                                KeyCode::Char(chr) => {
+                                    if chr.is_ascii_alphanumeric() || chr == '_' {
+                                        let mut new_target = target.clone();
+                                        new_target.push(chr);
+                                        self.state = DebuggerState::WaitingForRunToLine(new_target);
                                         self.redraw = true;
                                     }
                                 }
+                                KeyCode::Enter => {
+                                    let result = self.breakpoint_addr(target);
+                                    self.state = DebuggerState::Ready;
+                                    self.redraw = true;
+                                    match result {
+                                        Ok(addr) => return Ok(Some(Input::RunTo(addr))),
+                                        Err(err) => eprintln!("{}", err),
+                                    }
+                                }
+                                KeyCode::Backspace if !target.is_empty() => {
+                                    let mut new_target = target.clone();
+                                    new_target.truncate(target.len() - 1);
+                                    self.state = DebuggerState::WaitingForRunToLine(new_target);
+                                    self.redraw = true;
+                                }
+                                _ => {}
+                            }
+                            return Ok(None);
+                        }
+                        DebuggerState::WaitingForExpression(text) => {
+                            match key.code {
+                                KeyCode::Char(chr) => {
+                                    let mut expr = text.clone();
+                                    expr.push(chr);
+                                    self.state = DebuggerState::WaitingForExpression(expr);
+                                    self.redraw = true;
+                                }
+                                KeyCode::Enter => {
+                                    let expr = text.trim().to_string();
+                                    self.state = DebuggerState::Ready;
+                                    self.redraw = true;
+                                    if !expr.is_empty() {
+                                        return Ok(Some(Input::Evaluate(expr)));
+                                    }
+                                }
+                                KeyCode::Backspace if !text.is_empty() => {
+                                    let mut new_text = text.clone();
+                                    new_text.truncate(text.len() - 1);
+                                    self.state = DebuggerState::WaitingForExpression(new_text);
+                                    self.redraw = true;
+                                }
                                 _ => {}
                             }
                             return Ok(None);
                         }
+                        DebuggerState::WaitingForOutputSearch(text) => {
+                            match key.code {
+                                KeyCode::Char(chr) => {
+                                    let mut query = text.clone();
+                                    query.push(chr);
+                                    self.state = DebuggerState::WaitingForOutputSearch(query);
+                                    self.redraw = true;
+                                }
+                                KeyCode::Enter => {
+                                    let query = text.clone();
+                                    self.state = DebuggerState::Ready;
+                                    self.redraw = true;
+                                    if !query.is_empty() {
+                                        return Ok(Some(Input::SearchOutput(query)));
+                                    }
+                                }
+                                KeyCode::Backspace if !text.is_empty() => {
+                                    let mut new_text = text.clone();
+                                    new_text.truncate(text.len() - 1);
+                                    self.state = DebuggerState::WaitingForOutputSearch(new_text);
+                                    self.redraw = true;
+                                }
+                                _ => {}
+                            }
+                            return Ok(None);
+                        }
+                        DebuggerState::WaitingForSetValue(text) => {
+                            match key.code {
+                                KeyCode::Char(chr) => {
+                                    let mut new_text = text.clone();
+                                    new_text.push(chr);
+                                    self.state = DebuggerState::WaitingForSetValue(new_text);
+                                    self.redraw = true;
+                                }
+                                KeyCode::Enter => {
+                                    let text = text.trim().to_string();
+                                    self.state = DebuggerState::Ready;
+                                    self.redraw = true;
+                                    if !text.is_empty() {
+                                        return Ok(Some(Input::SetValue(text)));
+                                    }
+                                }
+                                KeyCode::Backspace if !text.is_empty() => {
+                                    let mut new_text = text.clone();
+                                    new_text.truncate(text.len() - 1);
+                                    self.state = DebuggerState::WaitingForSetValue(new_text);
+                                    self.redraw = true;
+                                }
+                                _ => {}
+                            }
+                            return Ok(None);
+                        }
+                        DebuggerState::WaitingForMemoryRange(text) => {
+                            match key.code {
+                                KeyCode::Char(chr) => {
+                                    if is_address_char(chr) {
+                                        let mut range = text.clone();
+                                        range.push(chr);
+                                        self.state = DebuggerState::WaitingForMemoryRange(range);
+                                        self.redraw = true;
+                                    }
+                                }
+                                KeyCode::Enter => {
+                                    self.redraw = true;
+                                    if text.is_empty() {
+                                        self.state = DebuggerState::Ready;
+                                        return Ok(Some(Input::SetMemoryView(None)));
+                                    }
+                                    match parse_address_range(text) {
+                                        Some((start, end)) if (start as usize) < RAM_SIZE => {
+                                            let end = end.min(RAM_SIZE as u16 - 1);
+                                            self.state =
+                                                DebuggerState::EditingMemory(start, String::new());
+                                            return Ok(Some(Input::SetMemoryView(Some((
+                                                start, end,
+                                            )))));
+                                        }
+                                        _ => {
+                                            self.state = DebuggerState::Ready;
+                                            eprintln!("Invalid address or range")
+                                        }
+                                    }
+                                }
+                                KeyCode::Backspace if !text.is_empty() => {
+                                    let mut new_text = text.clone();
+                                    new_text.truncate(text.len() - 1);
+                                    self.state = DebuggerState::WaitingForMemoryRange(new_text);
+                                    self.redraw = true;
+                                }
+                                _ => {}
+                            }
+                            return Ok(None);
+                        }
+                        DebuggerState::EditingMemory(cursor, text) => {
+                            let (start, end) = self.ui_memory.unwrap_or((0, 0));
+                            let cursor = *cursor;
+                            let move_to = |diff: isize| -> u16 {
+                                let target = cursor as isize + diff;
+                                if target < start as isize || target > end as isize {
+                                    cursor
+                                } else {
+                                    target as u16
+                                }
+                            };
+                            let mut input = None;
+                            let new_state = match key.code {
+                                KeyCode::Left => {
+                                    DebuggerState::EditingMemory(move_to(-1), String::new())
+                                }
+                                KeyCode::Right => {
+                                    DebuggerState::EditingMemory(move_to(1), String::new())
+                                }
+                                KeyCode::Up => DebuggerState::EditingMemory(
+                                    move_to(-(self.memory_row_len as isize)),
+                                    String::new(),
+                                ),
+                                KeyCode::Down => DebuggerState::EditingMemory(
+                                    move_to(self.memory_row_len as isize),
+                                    String::new(),
+                                ),
+                                KeyCode::Char(chr) if chr.is_ascii_hexdigit() && text.len() < 2 => {
+                                    let mut value = text.clone();
+                                    value.push(chr);
+                                    DebuggerState::EditingMemory(cursor, value)
+                                }
+                                KeyCode::Backspace if !text.is_empty() => {
+                                    let mut value = text.clone();
+                                    value.truncate(text.len() - 1);
+                                    DebuggerState::EditingMemory(cursor, value)
+                                }
+                                KeyCode::Enter => {
+                                    if let Ok(value) = u8::from_str_radix(text, 16) {
+                                        input = Some(Input::PokeMemory(cursor, value));
+                                    }
+                                    DebuggerState::EditingMemory(move_to(1), String::new())
+                                }
+                                _ => return Ok(None),
+                            };
+                            self.state = new_state;
+                            self.redraw = true;
+                            return Ok(input);
+                        }
                         DebuggerState::ProgEnd => {
                             let input = match key.code {
                                 KeyCode::Char('8') => Some(Input::Toggle8BitDisplay),
                                 KeyCode::Char('6') => Some(Input::Toggle16BitDisplay),
                                 KeyCode::Char('c') => Some(Input::ToggleDumpCharacters),
                                 KeyCode::Char('l') => Some(Input::ToggleOriginalLine),
+                                KeyCode::Char('o') => Some(Input::ToggleListing),
                                 KeyCode::Char('i') => Some(Input::Info),
                                 KeyCode::Char('h') => Some(Input::Help),
                                 KeyCode::Char('y') => Some(Input::ExecutionHistory),
+                                KeyCode::Char('r') => Some(Input::Reload),
+                                KeyCode::Char('S') => Some(Input::SaveSnapshot),
+                                KeyCode::Char('L') => Some(Input::LoadSnapshot),
+                                KeyCode::Char('d') => Some(Input::Diff),
+                                KeyCode::Char('O') => Some(Input::ToggleOutputPane),
+                                KeyCode::PageUp => Some(Input::ScrollOutput(true)),
+                                KeyCode::PageDown => Some(Input::ScrollOutput(false)),
+                                KeyCode::Char('n') => Some(Input::SearchOutputNext),
                                 _ => None,
                             };
                             return Ok(input);
@@ -393,7 +1193,14 @@ impl DebugDevice {
                     }
                 }
                 Event::Mouse(_) => {}
-                Event::Resize(_, _) => {}
+                Event::Resize(_, _) => {
+                    //lines already drawn may have been rewrapped by the terminal so the footer
+                    //height is no longer known, clear everything and draw from the top
+                    stdout().execute(Clear(ClearType::All))?;
+                    stdout().execute(MoveTo(0, 0))?;
+                    self.footer_height = 0;
+                    self.redraw = true;
+                }
             }
         }
         Ok(None)
@@ -416,7 +1223,176 @@ impl DebugDevice {
         }
     }
 
+    ///Replace instruction at PC and update debug info to match
+    fn patch_instruction(&mut self, line: String, bytes: Vec<u8>) {
+        let pc = self.device.pc;
+        match self.device.patch_instruction(pc, &bytes) {
+            Ok(padding) => {
+                let idx = self.debug.ops.iter().position(|op| op.byte_addr == pc);
+                let line_num = idx.map(|idx| self.debug.ops[idx].line_num).unwrap_or(0);
+                let patched_op = DebugOp::new(pc, line.clone(), line_num, line, bytes.clone());
+                let nops = (0..padding).map(|i| {
+                    let addr = pc + (bytes.len() + i) as u16;
+                    DebugOp::new(
+                        addr,
+                        String::from("NOP"),
+                        line_num,
+                        String::from("NOP"),
+                        vec![NOP],
+                    )
+                });
+                match idx {
+                    Some(idx) => {
+                        self.debug
+                            .ops
+                            .splice(idx..=idx, std::iter::once(patched_op).chain(nops));
+                    }
+                    None => {
+                        self.debug.ops.push(patched_op);
+                        self.debug.ops.extend(nops);
+                        self.debug.ops.sort_by_key(|op| op.byte_addr);
+                    }
+                }
+                let nop_start = pc + bytes.len() as u16;
+                for addr in std::iter::once(pc).chain(nop_start..nop_start + padding as u16) {
+                    if !self.patched.contains(&addr) {
+                        self.patched.push(addr);
+                    }
+                }
+            }
+            Err(err) => eprintln!("Unable to patch: {}", err),
+        }
+    }
+
+    ///Replace tape and debug info with the rebuilt versions and restart the program
+    ///
+    ///Breakpoints are moved to the same source line (and dropped if it no longer has an op),
+    ///watchpoints and display settings are kept
+    fn reload(&mut self) -> Result<()> {
+        let (tape_path, debug_path) = self
+            .reload_paths
+            .clone()
+            .ok_or_else(|| Error::msg("Tape and debug file paths unknown"))?;
+        let mut tape = read_tape_strict(&tape_path)?;
+        link_data_tapes(&mut tape, &[])?;
+        let debug_text = read_to_string(&debug_path)
+            .context(format!("Unable to read debug info file {}", debug_path))?;
+        let debug: DebugModel = serde_json::from_str(&debug_text)
+            .context(format!("Unable to parse debug info file {}", debug_path))?;
+
+        let breakpoints: Vec<u16> = self
+            .device
+            .breakpoints
+            .iter()
+            .filter_map(|addr| self.debug.line_for_byte(*addr))
+            .filter_map(|line| debug.byte_for_line(line))
+            .collect();
+        let dropped = self.device.breakpoints.len() - breakpoints.len();
+        let watchpoints = std::mem::take(&mut self.device.watchpoints);
+
+        let mut device = Device::new(tape.ops, tape.strings, tape.data, self.data_files.clone());
+        device.set_capabilities(self.capabilities);
+        device.set_mmio(self.mmio);
+        device.set_read_only_files(self.read_only_files.clone());
+        if let Some(path) = &self.save_file {
+            device.set_save_file(path.clone());
+        }
+        device.set_strict(self.strict);
+        device.set_stack_check(self.check_stack);
+        device.set_tape_version(tape.tape_version);
+        device.set_entry(tape.entry);
+        device.breakpoints = breakpoints;
+        device.watchpoints = watchpoints;
+        self.device = device;
+        self.debug = debug;
+        self.source = read_sibling_source(&tape_path);
+        self.last_run_result = RunResult::Pause;
+        self.state = DebuggerState::Ready;
+        self.auto_run = false;
+        self.last_watchpoint_hit = None;
+        self.patched.clear();
+        self.history.clear();
+        self.output_log.clear();
+        self.output_scroll = 0;
+        self.output_match = None;
+
+        stdout().execute(MoveToColumn(0))?;
+        println!("Reloaded {} ({} v{})", tape_path, tape.name, tape.version);
+        if dropped > 0 {
+            stdout().execute(MoveToColumn(0))?;
+            eprintln!(
+                "{} breakpoint(s) removed as their lines no longer have ops",
+                dropped
+            );
+        }
+        Ok(())
+    }
+
+    ///Snapshots are saved next to the tape, i.e. program.snapshot for program.tape
+    fn snapshot_path(&self) -> String {
+        match &self.reload_paths {
+            Some((tape_path, _)) => Path::new(tape_path)
+                .with_extension("snapshot")
+                .to_string_lossy()
+                .to_string(),
+            None => String::from("device.snapshot"),
+        }
+    }
+
+    fn save_snapshot(&mut self) -> Result<()> {
+        let path = self.snapshot_path();
+        let state = self.device.snapshot()?;
+        write(&path, serde_json::to_string(&state)?)
+            .context(format!("Unable to write snapshot {}", path))?;
+        stdout().execute(MoveToColumn(0))?;
+        println!("Saved snapshot to {}", path);
+        Ok(())
+    }
+
+    ///Restores the device from the snapshot, breakpoints, watchpoints and patches are kept
+    fn load_snapshot(&mut self) -> Result<()> {
+        let path = self.snapshot_path();
+        let text = read_to_string(&path).context(format!("Unable to read snapshot {}", path))?;
+        let state =
+            serde_json::from_str(&text).context(format!("Unable to parse snapshot {}", path))?;
+        self.device.restore(state)?;
+        self.last_run_result = RunResult::Pause;
+        self.state = DebuggerState::Ready;
+        self.auto_run = false;
+        self.last_watchpoint_hit = None;
+        self.history.clear();
+        stdout().execute(MoveToColumn(0))?;
+        println!("Loaded snapshot from {}", path);
+        Ok(())
+    }
+
+    ///Record the device state if there isn't one, otherwise returns what's changed since it was
+    ///recorded and clears it
+    fn diff(&mut self) -> Result<Vec<String>> {
+        let state = self.device.snapshot()?;
+        match self.diff_base.take() {
+            Some(base) => Ok(diff_states(&base, &state).render()),
+            None => {
+                self.diff_base = Some(state);
+                Ok(vec![String::from(
+                    "Recorded registers and memory, diff again to see what changed",
+                )])
+            }
+        }
+    }
+
+    fn print_diff(&mut self) -> Result<()> {
+        for line in self.diff()? {
+            stdout().execute(MoveToColumn(0))?;
+            println!("{}", line);
+        }
+        Ok(())
+    }
+
     fn add_history(&mut self, addr: u16) {
+        if self.history.len() >= MAX_HISTORY {
+            self.history.remove(0);
+        }
         let op = self.debug.op_for_byte(addr);
         if let Some(op) = op {
             let last_op_type = self.get_last_op_type();
@@ -436,27 +1412,63 @@ impl DebugDevice {
         if self.redraw {
             self.reset_cursor()?;
             let mut newline_printed = false;
-            if !self.device.output.is_empty() {
-                for output in &self.device.output {
+            let mut watchpoint_hit = None;
+            let log_len = self.output_log.len();
+            let outputs = self.device.take_output();
+            if !outputs.is_empty() {
+                for output in &outputs {
                     match output {
                         Output::OutputStd(str) => {
                             print!("{}", str);
                             newline_printed = str.ends_with('\n');
+                            self.output_log.push_std(str);
                         }
                         Output::OutputErr(err) => {
                             stdout().execute(MoveToColumn(0))?;
-                            eprintln!("Error: {}", err)
+                            eprintln!("Error: {}", err);
+                            self.output_log.push_err(err);
+                        }
+                        Output::UninitializedRead(pc, addr) => {
+                            let warning = self.uninitialized_read_text(*pc, *addr);
+                            stdout().execute(MoveToColumn(0))?;
+                            eprintln!("{}", warning);
+                            self.output_log.push_err(&warning);
+                        }
+                        Output::StackCorrupted(pc, addr) => {
+                            let err = self.stack_corrupted_text(*pc, *addr);
+                            stdout().execute(MoveToColumn(0))?;
+                            eprintln!("Error: {}", err);
+                            self.output_log.push_err(&err);
+                        }
+                        Output::OutputBinary(bytes) => {
+                            let str = String::from_utf8_lossy(bytes);
+                            print!("{}", str);
+                            newline_printed = str.ends_with('\n');
+                            self.output_log.push_std(&str);
+                        }
+                        Output::SetColor(color) => {
+                            stdout().execute(SetForegroundColor(terminal_color(*color)))?;
                         }
+                        //would draw over the debugger
+                        Output::ClearScreen | Output::MoveCursor(..) | Output::Vsync => {}
                         Output::BreakpointHit(_) => {}
+                        Output::WatchpointHit(addr) => watchpoint_hit = Some(*addr),
                     }
                 }
+                stdout().execute(ResetColor)?;
                 if !newline_printed {
                     //print a newline after the previous text otherwise the footer will draw over it
                     //but only print one if the program didn't otherwise extra blank lines will be introduced
                     println!();
                 }
             }
-            self.device.output.clear();
+            //keep the same lines in view while scrolled up
+            if self.output_scroll > 0 {
+                self.output_scroll += self.output_log.len() - log_len;
+            }
+            if watchpoint_hit.is_some() {
+                self.last_watchpoint_hit = watchpoint_hit;
+            }
             if self.print_history {
                 println!("{}", "  Line  Addr  Bytes             Src".bold());
                 for op in &self.history {
@@ -478,13 +1490,14 @@ impl DebugDevice {
                 }
             }
             let (cols, _) = crossterm::terminal::size()?;
-            let mut footer = self.gen_footer(cols as usize)?;
-            if let Some((start, end)) = self.ui_memory {
-                let bytes = self.device.mem[start as usize..end as usize]
-                    .iter()
-                    .map(|byte| format_8bit(*byte, self.hex_8bit, self.dump_chars))
-                    .collect::<Vec<String>>();
-                footer.extend_from_slice(&fit_in_lines(bytes, cols as usize));
+            let cols = (cols as usize).max(MIN_COLS);
+            self.memory_row_len = memory_row_len(cols);
+            let mut footer = self.gen_footer(cols)?;
+            if self.ui_memory.is_some() {
+                footer.extend_from_slice(&self.gen_memory_panel(cols));
+            }
+            if self.show_output {
+                footer.extend_from_slice(&self.gen_output_panel(cols));
             }
             if self.print_info {
                 footer.extend_from_slice(&convert_and_fit(
@@ -493,8 +1506,13 @@ impl DebugDevice {
                         &format!("16bit values as hex: {}", self.hex_16bit),
                         &format!("show ASCII for registers: {}", self.dump_chars),
                         &format!("show original line: {}", self.original_line),
+                        &format!("show source listing: {}", self.show_listing),
+                        &format!(
+                            "auto-run speed: {} instructions/s",
+                            AUTO_RUN_SPEEDS[self.auto_run_speed]
+                        ),
                     ],
-                    cols as usize,
+                    cols,
                     "   ",
                 ));
             }
@@ -509,14 +1527,31 @@ impl DebugDevice {
                         "i) Print debugger info",
                         "c) Toggle printing ASCII chars",
                         "l) Toggle printing original line",
+                        "o) Toggle source listing",
                         "b) Set breakpoint",
                         "u) Clear breakpoint",
+                        "w) Set watchpoint",
+                        "x) Clear watchpoint",
+                        "m) Show memory range (enter nothing to hide)",
+                        "e) Edit shown memory",
+                        "p) Patch instruction at PC",
+                        "=) Evaluate expression (e.g. mem[a0+2])",
+                        "v) Set register or memory (e.g. d0 42)",
+                        "O) Toggle output pane (PgUp/PgDn to scroll)",
+                        "/) Search output (n for previous match)",
+                        "a) Toggle auto-run",
+                        "+/-) Auto-run faster/slower",
+                        "g) Run to line or label",
+                        "r) Reload rebuilt tape and debug file",
+                        "S) Save snapshot",
+                        "L) Load snapshot",
+                        "d) Record state, press again to show changes since",
                         "h) Print help",
                         "y) Print execution history",
                         "t) Input char",
                         "s) Input string",
                     ],
-                    cols as usize,
+                    cols,
                     "   ",
                 ));
             }
@@ -536,14 +1571,31 @@ impl DebugDevice {
     fn gen_footer(&self, cols: usize) -> Result<Vec<String>> {
         let mut lines = vec![];
         lines.push(format!("{1:-<0$}", cols, "-"));
+        if self.show_listing {
+            lines.extend_from_slice(&self.gen_listing(cols));
+            lines.push(format!("{1:-<0$}", cols, "-"));
+        }
         let status_value = match (&self.last_run_result, &self.state) {
-            (RunResult::Pause, DebuggerState::Ready) => String::from("Ready"),
+            (RunResult::Pause, DebuggerState::Ready) => match self.run_to {
+                Some(addr) if self.auto_run => format!(
+                    "Running to line {}",
+                    self.debug
+                        .line_for_byte(addr)
+                        .map(|line| line.to_string())
+                        .unwrap_or_else(|| String::from("?"))
+                ),
+                _ if self.auto_run => format!(
+                    "Auto-running at {} instructions/s",
+                    AUTO_RUN_SPEEDS[self.auto_run_speed]
+                ),
+                _ => String::from("Ready"),
+            },
             (RunResult::Pause, DebuggerState::WaitingForBreakpointLineToSet(num)) => format!(
-                "Enter breakpoint line num to set (esc to cancel): {}              ",
+                "Enter breakpoint line num or label to set (esc to cancel): {}     ",
                 num
             ),
             (RunResult::Pause, DebuggerState::WaitingForBreakpointLineToClear(num)) => format!(
-                "Enter breakpoint line num to clear (esc to cancel): {}            ",
+                "Enter breakpoint line num or label to clear (esc to cancel): {}   ",
                 num
             ),
             (RunResult::StringInputRequested, DebuggerState::WaitingForString(str)) => {
@@ -561,7 +1613,49 @@ impl DebugDevice {
             (RunResult::CharInputRequested, DebuggerState::Ready) => {
                 String::from("Waiting for character input")
             }
+            (RunResult::Pause, DebuggerState::WaitingForWatchpointToSet(range)) => format!(
+                "Enter watchpoint address or range (e.g. 100 or x10-x1F) to set (esc to cancel): {}  ",
+                range
+            ),
+            (RunResult::Pause, DebuggerState::WaitingForWatchpointToClear(addr)) => format!(
+                "Enter address in watchpoint to clear (esc to cancel): {}            ",
+                addr
+            ),
+            (_, DebuggerState::WaitingForPatch(line)) => format!(
+                "Enter instruction to replace current one, must not be larger (esc to cancel): {}",
+                line
+            ),
+            (_, DebuggerState::WaitingForRunToLine(target)) => format!(
+                "Enter line num or label to run to (esc to cancel): {}",
+                target
+            ),
+            (_, DebuggerState::WaitingForOutputSearch(query)) => format!(
+                "Enter text to search output for (esc to cancel): {}",
+                query
+            ),
+            (_, DebuggerState::WaitingForSetValue(text)) => format!(
+                "Enter register or mem[addr] and value, e.g. d0 42 or a1 x1F00 (esc to cancel): {}",
+                text
+            ),
+            (_, DebuggerState::WaitingForExpression(expr)) => format!(
+                "Enter expression to evaluate, e.g. d0*2+1 or mem[a0+2] (esc to cancel): {}",
+                expr
+            ),
+            (_, DebuggerState::WaitingForMemoryRange(range)) => format!(
+                "Enter memory address or range (e.g. x100-x1FF) to show, or nothing to hide (esc to cancel): {}",
+                range
+            ),
+            (_, DebuggerState::EditingMemory(cursor, value)) => format!(
+                "Editing {:04X}, arrows to move, type hex and press enter to write (esc to finish): {}",
+                cursor, value
+            ),
             (RunResult::Breakpoint, _) => String::from("Breakpoint Hit"),
+            (RunResult::Watchpoint, _) => format!(
+                "Watchpoint Hit ({})",
+                self.last_watchpoint_hit
+                    .map(|addr| format_16bit(addr, self.hex_16bit, false))
+                    .unwrap_or_else(|| String::from("??"))
+            ),
             (RunResult::ProgError, _) => String::from("Crashed"),
             (_, _) => String::from("End of Program"),
         };
@@ -579,7 +1673,12 @@ impl DebugDevice {
         } else {
             String::from("Unknown line: ???")
         };
-        lines.push(format!("Status: {}     {}", status_value, line_text));
+        let mut status = vec![format!("Status: {}     ", status_value)];
+        if let Some(function) = self.debug.function_for_byte(self.device.pc) {
+            status.push(format!("Function: {}     ", function));
+        }
+        status.push(line_text);
+        lines.extend_from_slice(&fit_in_lines(status, cols - 1));
         let mut dump = format_dump(
             self.device.dump(),
             self.hex_8bit,
@@ -590,10 +1689,186 @@ impl DebugDevice {
             .map(|op| op.line_num.to_string())
             .unwrap_or_else(|| String::from("??"));
         dump.insert(0, format!("Line Num: {: <5}  ", line_num));
-        lines.extend_from_slice(&fit_in_lines(dump, cols as usize - 1));
+        lines.extend_from_slice(&fit_in_lines(dump, cols - 1));
         Ok(lines)
     }
 
+    ///Source lines around the PC, or the assembled ops if the .basm file wasn't found
+    fn gen_listing(&self, cols: usize) -> Vec<String> {
+        match self.debug.line_for_byte(self.device.pc) {
+            Some(line_num) if line_num <= self.source.len() => {
+                self.gen_source_listing(line_num, cols)
+            }
+            _ => self.gen_op_listing(cols),
+        }
+    }
+
+    ///Lines of the .basm file around `current` (starting at 1), with the address of each op
+    fn gen_source_listing(&self, current: usize, cols: usize) -> Vec<String> {
+        let start = (current - 1).saturating_sub(LISTING_LINES_BEFORE);
+        let end = (current + LISTING_LINES_AFTER).min(self.source.len());
+        self.source[start..end]
+            .iter()
+            .enumerate()
+            .map(|(idx, text)| {
+                let line_num = start + idx + 1;
+                let op = self.debug.ops.iter().find(|op| op.line_num == line_num);
+                self.listing_line(op, line_num, text, line_num == current, cols)
+            })
+            .collect()
+    }
+
+    fn gen_op_listing(&self, cols: usize) -> Vec<String> {
+        let current = self
+            .debug
+            .ops
+            .iter()
+            .position(|op| op.byte_addr == self.device.pc);
+        let center = current.unwrap_or(0);
+        let start = center.saturating_sub(LISTING_LINES_BEFORE);
+        let end = (center + LISTING_LINES_AFTER + 1).min(self.debug.ops.len());
+        self.debug.ops[start..end]
+            .iter()
+            .enumerate()
+            .map(|(idx, op)| {
+                let text = if self.original_line {
+                    op.original_line.trim()
+                } else {
+                    &op.processed_line
+                };
+                let is_current = current == Some(start + idx);
+                self.listing_line(Some(op), op.line_num, text, is_current, cols)
+            })
+            .collect()
+    }
+
+    ///Listing line with breakpoint, PC and patched markers, the address is blank if `op` is `None`
+    fn listing_line(
+        &self,
+        op: Option<&DebugOp>,
+        line_num: usize,
+        text: &str,
+        is_current: bool,
+        cols: usize,
+    ) -> String {
+        let has_breakpoint = op.is_some_and(|op| self.device.breakpoints.contains(&op.byte_addr));
+        let gutter = match (has_breakpoint, is_current) {
+            (true, true) => "*>",
+            (true, false) => "* ",
+            (false, true) => " >",
+            (false, false) => "  ",
+        };
+        let modified = match op {
+            Some(op) if self.patched.contains(&op.byte_addr) => "~",
+            _ => " ",
+        };
+        let addr = match op {
+            Some(op) => format_16bit(op.byte_addr, true, false),
+            None => String::from("    "),
+        };
+        let line = truncate_line(
+            format!(
+                "{}{} {: >5}  {}  {}",
+                gutter, modified, line_num, addr, text
+            ),
+            cols - 1,
+        );
+        if is_current {
+            line.bold().to_string()
+        } else {
+            line
+        }
+    }
+
+    ///Hex dump of the memory range being viewed, with the edit cursor highlighted
+    fn gen_memory_panel(&self, cols: usize) -> Vec<String> {
+        let (start, end) = match self.ui_memory {
+            Some(range) => range,
+            None => return vec![],
+        };
+        let cursor = match self.state {
+            DebuggerState::EditingMemory(cursor, _) => Some(cursor),
+            _ => None,
+        };
+        let mut lines = vec![format!("{1:-<0$}", cols, "-")];
+        lines.extend(self.memory_lines(start, end, self.memory_row_len, cursor));
+        lines
+    }
+
+    ///Lines of program output, errors are red and the last search match is highlighted
+    fn gen_output_panel(&self, cols: usize) -> Vec<String> {
+        let total = self.output_log.len();
+        let end = total - self.output_scroll.min(total);
+        let start = end.saturating_sub(OUTPUT_PANE_HEIGHT);
+        let mut lines = vec![
+            format!("{1:-<0$}", cols, "-"),
+            truncate_line(
+                format!(
+                    "Output {}-{} of {} (PgUp/PgDn scroll, / search, n previous match, O hide)",
+                    (start + 1).min(end),
+                    end,
+                    total
+                ),
+                cols,
+            ),
+        ];
+        for (idx, line) in self.output_log.lines()[start..end].iter().enumerate() {
+            let text = truncate_line(line.text.replace('\t', "    "), cols);
+            lines.push(if self.output_match == Some(start + idx) {
+                text.reverse().to_string()
+            } else if line.is_err {
+                text.red().to_string()
+            } else {
+                text
+            });
+        }
+        lines
+    }
+
+    ///Hex dump of memory from `start` to `end` (inclusive), `row_len` bytes per line
+    fn memory_lines(
+        &self,
+        start: u16,
+        end: u16,
+        row_len: usize,
+        cursor: Option<u16>,
+    ) -> Vec<String> {
+        let mut lines = vec![];
+        let mut row_start = start as usize;
+        while row_start <= end as usize {
+            let row_end = (row_start + row_len).min(end as usize + 1);
+            let bytes = (row_start..row_end)
+                .map(|addr| {
+                    let text = format!("{:02X}", self.device.mem[addr]);
+                    if cursor == Some(addr as u16) {
+                        text.reverse().to_string()
+                    } else {
+                        text
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(" ");
+            let chars = self.device.mem[row_start..row_end]
+                .iter()
+                .map(|byte| u8_char(*byte))
+                .collect::<String>();
+            let mut line = format!("{:04X}: {}  {}", row_start, bytes, chars);
+            if self.device.is_mmio(row_start as u16) {
+                let mut names: Vec<&str> = (row_start..row_end)
+                    .filter_map(|addr| mmio_register_name(addr as u16))
+                    .collect();
+                names.dedup();
+                line.push_str("  MMIO");
+                if !names.is_empty() {
+                    line.push_str(&format!(" ({})", names.join(", ")));
+                }
+            }
+            lines.push(line);
+            row_start = row_end;
+        }
+        lines
+    }
+
     fn reset_cursor(&self) -> Result<()> {
         stdout().execute(MoveToPreviousLine(self.footer_height))?;
         stdout().execute(Clear(ClearType::FromCursorDown))?;
@@ -601,6 +1876,33 @@ impl DebugDevice {
     }
 }
 
+///Oldest entries are dropped after this so long auto-runs don't grow forever
+const MAX_HISTORY: usize = 10_000;
+const MAX_MEMORY_ROW_LEN: usize = 16;
+///Lines of output shown in the output pane
+const OUTPUT_PANE_HEIGHT: usize = 10;
+///Instructions per second the auto-run speed can be set to
+const AUTO_RUN_SPEEDS: [usize; 6] = [1, 10, 100, 1_000, 10_000, 100_000];
+const DEFAULT_AUTO_RUN_SPEED: usize = 2;
+///Instructions executed per frame when running to a line
+const RUN_TO_BATCH: usize = 10_000;
+///`run` in a script stops after this many instructions so infinite loops don't hang
+const SCRIPT_RUN_LIMIT: usize = 10_000_000;
+///Narrower terminals are drawn as if they were this wide
+const MIN_COLS: usize = 30;
+const LISTING_LINES_BEFORE: usize = 3;
+const LISTING_LINES_AFTER: usize = 4;
+
+///Bytes per memory panel row, the largest power of 2 (up to 16) that fits in `cols`
+fn memory_row_len(cols: usize) -> usize {
+    let mut len = MAX_MEMORY_ROW_LEN;
+    //each row is "XXXX: " then "XX " and a char per byte, plus a separator
+    while len > 1 && 6 + len * 4 + 1 >= cols {
+        len /= 2;
+    }
+    len
+}
+
 fn format_dump(dump: Dump, hex_8bit: bool, hex_16bit: bool, chars: bool) -> Vec<String> {
     vec![
         format!("PC: {}  ", format_16bit(dump.pc, hex_16bit, false)),
@@ -613,7 +1915,7 @@ fn format_dump(dump: Dump, hex_8bit: bool, hex_16bit: bool, chars: bool) -> Vec<
         format!("D1: {}  ", format_8bit(dump.data_reg[1], hex_8bit, chars)),
         format!("D2: {}  ", format_8bit(dump.data_reg[2], hex_8bit, chars)),
         format!("D3: {}  ", format_8bit(dump.data_reg[3], hex_8bit, chars)),
-        format!("Overflow: {}  ", dump.overflow),
+        format!("Flags: {}  ", dump.flags_text()),
     ]
 }
 
@@ -687,14 +1989,48 @@ fn remove_if_present<T: PartialEq>(list: &mut Vec<T>, item: &T) {
 fn should_add_history(run_result: &RunResult) -> bool {
     matches!(
         run_result,
-        RunResult::Pause | RunResult::StringInputRequested | RunResult::CharInputRequested
+        RunResult::Pause
+            | RunResult::Watchpoint
+            | RunResult::StringInputRequested
+            | RunResult::CharInputRequested
     )
 }
 
+fn is_address_char(chr: char) -> bool {
+    chr.is_ascii_hexdigit() || chr == 'x' || chr == '-'
+}
+
+///Parses an address as either decimal or hex (prefixed with x)
+pub(super) fn parse_address(text: &str) -> Option<u16> {
+    if let Some(hex) = text.strip_prefix('x') {
+        u16::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse::<u16>().ok()
+    }
+}
+
+///Parses either a single address or an inclusive range, i.e. `start-end`
+pub(super) fn parse_address_range(text: &str) -> Option<(u16, u16)> {
+    match text.split_once('-') {
+        Some((start, end)) => {
+            let start = parse_address(start)?;
+            let end = parse_address(end)?;
+            if start <= end {
+                Some((start, end))
+            } else {
+                None
+            }
+        }
+        None => parse_address(text).map(|addr| (addr, addr)),
+    }
+}
+
 enum Input {
     ForceStep,
     SetBreakpoint(u16),
     ClearBreakpoint(u16),
+    SetWatchpoint(u16, u16),
+    ClearWatchpoint(u16),
     Char(char),
     Text(String),
     Terminate,
@@ -702,10 +2038,29 @@ enum Input {
     Toggle8BitDisplay,
     ToggleDumpCharacters,
     ToggleOriginalLine,
+    ToggleListing,
+    SetMemoryView(Option<(u16, u16)>),
+    PokeMemory(u16, u8),
+    PatchInstruction(String, Vec<u8>),
+    Evaluate(String),
+    SetValue(String),
+    Reload,
+    SaveSnapshot,
+    LoadSnapshot,
+    ///Record the state or show what's changed since it was recorded
+    Diff,
     Info,
     Help,
     ExecutionHistory,
     ToggleAutoRun,
+    AutoRunFaster,
+    AutoRunSlower,
+    RunTo(u16),
+    ToggleOutputPane,
+    ///True to scroll up (older output)
+    ScrollOutput(bool),
+    SearchOutput(String),
+    SearchOutputNext,
 }
 
 #[derive(Debug, PartialEq)]
@@ -713,7 +2068,221 @@ enum DebuggerState {
     Ready,
     WaitingForBreakpointLineToSet(String),
     WaitingForBreakpointLineToClear(String),
+    WaitingForWatchpointToSet(String),
+    WaitingForWatchpointToClear(String),
+    WaitingForMemoryRange(String),
+    WaitingForPatch(String),
+    WaitingForExpression(String),
+    WaitingForSetValue(String),
+    WaitingForOutputSearch(String),
+    WaitingForRunToLine(String),
+    EditingMemory(u16, String),
     WaitingForChar,
     WaitingForString(String),
     ProgEnd,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble_source;
+    use crate::device::debug_script::parse_script;
+    use crate::tape_reader::parse_tape_strict;
+
+    #[test]
+    fn test_run_script() {
+        let assembled =
+            assemble_source("Test\n1\n.ops\ncpy d0 5\nprt d0\nprtln\ninc d0\nhalt\n").unwrap();
+        let tape = parse_tape_strict(assembled.bytes).unwrap();
+        let mut device = DebugDevice::new(
+            tape.ops,
+            tape.strings,
+            tape.data,
+            assembled.debug,
+            vec![],
+            Capabilities::all(),
+        );
+        let commands = parse_script("break 7\nrun\nmem 0-3\nrun\nstep\n").unwrap();
+        let commands = [
+            commands,
+            parse_script("eval d0 * 2 + mem[1]\neval d9").unwrap(),
+        ]
+        .concat();
+        let mut out = vec![];
+        device.run_script(&commands, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "> break 7");
+        assert_eq!(lines[1], "> run");
+        assert_eq!(lines[2], "5");
+        assert!(lines[3].starts_with("Breakpoint at line 7"), "{}", out);
+        assert_eq!(lines[4], "> mem 0-3");
+        assert_eq!(lines[6], "> run");
+        assert_eq!(lines[7], "Program ended (HALT)");
+        assert_eq!(lines[8], "> step");
+        assert_eq!(lines[9], "Program ended (HALT)");
+        assert_eq!(lines[11], "d0 * 2 + mem[1] = 12 (x000C)");
+        assert_eq!(lines[13], "Unable to evaluate d9: Unknown register 'd9'");
+    }
+
+    #[test]
+    fn test_script_diff() {
+        let assembled = assemble_source("Test\n1\n.ops\ncpy d0 5\ninc d0\nhalt\n").unwrap();
+        let tape = parse_tape_strict(assembled.bytes).unwrap();
+        let mut device = DebugDevice::new(
+            tape.ops,
+            tape.strings,
+            tape.data,
+            assembled.debug,
+            vec![],
+            Capabilities::all(),
+        );
+        let commands = parse_script("diff\nstep 2\ndiff\ndiff\ndiff\n").unwrap();
+        let mut out = vec![];
+        device.run_script(&commands, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[1],
+            "Recorded registers and memory, diff again to see what changed"
+        );
+        assert_eq!(
+            lines[4..],
+            [
+                "> diff",
+                "Executed 2 instructions",
+                "PC    0000 -> 0005",
+                "D0    00 -> 06",
+                "> diff",
+                "Recorded registers and memory, diff again to see what changed",
+                "> diff",
+                "Executed 0 instructions",
+                "Nothing changed",
+            ],
+            "{}",
+            out
+        );
+    }
+
+    #[test]
+    fn test_label_breakpoint() {
+        let assembled =
+            assemble_source("Test\n1\n.ops\ncall double\nhalt\ndouble:\nadd d0 d0\nret\n").unwrap();
+        let tape = parse_tape_strict(assembled.bytes).unwrap();
+        let mut device = DebugDevice::new(
+            tape.ops,
+            tape.strings,
+            tape.data,
+            assembled.debug,
+            vec![],
+            Capabilities::all(),
+        );
+        let commands = parse_script("break double\nbreak triple\nrun\n").unwrap();
+        let mut out = vec![];
+        device.run_script(&commands, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[2], "No label named triple");
+        assert!(lines[4].starts_with("Breakpoint at line 7"), "{}", out);
+    }
+
+    #[test]
+    fn test_run_to() {
+        let assembled = assemble_source(
+            "Test\n1\n.ops\ncpy d0 0\nloop:\ninc d0\ncmp d0 200\njne loop\ndone:\nhalt\n",
+        )
+        .unwrap();
+        let tape = parse_tape_strict(assembled.bytes).unwrap();
+        let mut device = DebugDevice::new(
+            tape.ops,
+            tape.strings,
+            tape.data,
+            assembled.debug,
+            vec![],
+            Capabilities::all(),
+        );
+        let addr = device.breakpoint_addr("done").unwrap();
+        device.handle_input(Input::RunTo(addr));
+        while device.auto_run {
+            device.auto_run_batch();
+        }
+        assert_eq!(device.device.pc, addr);
+        assert_eq!(device.device.dump().data_reg[0], 200);
+        assert_eq!(device.last_run_result, RunResult::Pause);
+        assert_eq!(device.run_to, None);
+    }
+
+    #[test]
+    fn test_set_value() {
+        let assembled = assemble_source("Test\n1\n.ops\nadd d0 d1\nhalt\n").unwrap();
+        let tape = parse_tape_strict(assembled.bytes).unwrap();
+        let mut device = DebugDevice::new(
+            tape.ops,
+            tape.strings,
+            tape.data,
+            assembled.debug,
+            vec![],
+            Capabilities::all(),
+        );
+        let commands = parse_script(
+            "set d0 40\nset D1 d0 / 20\nset a1 x1F00\nset mem[a1+1] -1\nset d2 300\nset z1 1\nstep\n",
+        )
+        .unwrap();
+        let mut out = vec![];
+        device.run_script(&commands, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[1], "d0 = 40 (x0028)");
+        assert_eq!(lines[3], "d1 = 2 (x0002)");
+        assert_eq!(lines[5], "a1 = 7936 (x1F00)");
+        assert_eq!(lines[7], "mem[1F01] = 255 (x00FF)");
+        assert_eq!(lines[9], "Unable to set d2 300: 300 doesn't fit in 8 bits");
+        assert_eq!(lines[11], "Unable to set z1 1: Unknown register 'z1'");
+        assert_eq!(device.device.mem[0x1F01], 255);
+        assert_eq!(device.device.acc, 42);
+    }
+
+    #[test]
+    fn test_source_listing() {
+        let source = "Test\n1\n.ops\n# setup\ncpy d0 5\n\nloop:\ninc d0\nhalt\n";
+        let assembled = assemble_source(source).unwrap();
+        let tape = parse_tape_strict(assembled.bytes).unwrap();
+        let mut device = DebugDevice::new(
+            tape.ops,
+            tape.strings,
+            tape.data,
+            assembled.debug,
+            vec![],
+            Capabilities::all(),
+        );
+        device.device.breakpoints.push(3);
+        assert!(device.gen_listing(80)[0].contains("cpy d0 5"));
+
+        device.set_source(source.lines().map(String::from).collect());
+        device.device.step(true);
+        let listing = device.gen_listing(80);
+        assert_eq!(listing.len(), 5);
+        assert_eq!(listing[0], "        5  0000  cpy d0 5");
+        assert_eq!(listing[2], "        7        loop:");
+        assert!(listing[3].contains("*>      8  0003  inc d0"));
+        assert_eq!(listing[4], "        9  0005  halt");
+    }
+
+    #[test]
+    fn test_poke_memory() {
+        let assembled = assemble_source("Test\n1\n.ops\nhalt\n").unwrap();
+        let tape = parse_tape_strict(assembled.bytes).unwrap();
+        let mut device = DebugDevice::new(
+            tape.ops,
+            tape.strings,
+            tape.data,
+            assembled.debug,
+            vec![],
+            Capabilities::all(),
+        );
+        device.handle_input(Input::PokeMemory(0x10, 7));
+        assert_eq!(device.device.mem[0x10], 7);
+        device.handle_input(Input::PokeMemory(RAM_SIZE as u16, 7));
+        assert_eq!(device.device.mem.len(), RAM_SIZE);
+    }
+}
@@ -0,0 +1,214 @@
+//! Changes between two [`DeviceState`]s, shown by the debugger's `d` key and the `diff` script command
+//!
+//! The first use records the state and the next shows which registers and memory changed since then
+
+use crate::device::{DeviceState, Dump};
+
+///Changed bytes shown per memory line
+const BYTES_PER_LINE: usize = 8;
+///Most memory lines shown, the remaining changed bytes are counted
+pub const MAX_MEMORY_LINES: usize = 32;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RegisterChange {
+    pub name: &'static str,
+    pub old: u16,
+    pub new: u16,
+    ///Shown as 4 hex digits instead of 2
+    pub wide: bool,
+}
+
+///Runs of changed memory, each starts at `addr`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MemoryChange {
+    pub addr: u16,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct StateDiff {
+    ///Instructions executed between the two states
+    pub cycles: u64,
+    pub registers: Vec<RegisterChange>,
+    pub memory: Vec<MemoryChange>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.memory.is_empty()
+    }
+
+    pub fn render(&self) -> Vec<String> {
+        let mut lines = vec![format!("Executed {} instructions", self.cycles)];
+        if self.is_empty() {
+            lines.push(String::from("Nothing changed"));
+            return lines;
+        }
+        for change in &self.registers {
+            lines.push(if change.wide {
+                format!(
+                    "{: <5} {:04X} -> {:04X}",
+                    change.name, change.old, change.new
+                )
+            } else {
+                format!(
+                    "{: <5} {:02X} -> {:02X}",
+                    change.name, change.old, change.new
+                )
+            });
+        }
+        for change in self.memory.iter().take(MAX_MEMORY_LINES) {
+            lines.push(format!(
+                "{:04X}: {} -> {}",
+                change.addr,
+                hex(&change.old),
+                hex(&change.new)
+            ));
+        }
+        let hidden: usize = self
+            .memory
+            .iter()
+            .skip(MAX_MEMORY_LINES)
+            .map(|change| change.new.len())
+            .sum();
+        if hidden > 0 {
+            lines.push(format!("...and {} more changed bytes", hidden));
+        }
+        lines
+    }
+}
+
+pub fn diff_states(before: &DeviceState, after: &DeviceState) -> StateDiff {
+    StateDiff {
+        cycles: after.cycles.saturating_sub(before.cycles),
+        registers: diff_registers(&before.registers, &after.registers),
+        memory: diff_memory(&before.mem, &after.mem),
+    }
+}
+
+fn diff_registers(before: &Dump, after: &Dump) -> Vec<RegisterChange> {
+    let mut registers = vec![
+        ("PC", before.pc, after.pc, true),
+        ("ACC", before.acc as u16, after.acc as u16, false),
+    ];
+    for (idx, name) in ["D0", "D1", "D2", "D3"].iter().enumerate() {
+        let (old, new) = (before.data_reg[idx], after.data_reg[idx]);
+        registers.push((name, old as u16, new as u16, false));
+    }
+    registers.extend_from_slice(&[
+        ("A0", before.addr_reg[0], after.addr_reg[0], true),
+        ("A1", before.addr_reg[1], after.addr_reg[1], true),
+        ("SP", before.sp, after.sp, true),
+        ("FP", before.fp, after.fp, true),
+        ("LIMIT", before.stack_limit, after.stack_limit, true),
+        ("FLAGS", before.flags as u16, after.flags as u16, false),
+    ]);
+    registers
+        .iter()
+        .filter(|(_, old, new, _)| old != new)
+        .map(|(name, old, new, wide)| RegisterChange {
+            name,
+            old: *old,
+            new: *new,
+            wide: *wide,
+        })
+        .collect()
+}
+
+///Consecutive changed bytes are grouped, up to [`BYTES_PER_LINE`] per change
+fn diff_memory(before: &[u8], after: &[u8]) -> Vec<MemoryChange> {
+    let mut changes: Vec<MemoryChange> = vec![];
+    for (addr, (old, new)) in before.iter().zip(after).enumerate() {
+        if old == new {
+            continue;
+        }
+        match changes.last_mut() {
+            Some(change)
+                if change.addr as usize + change.new.len() == addr
+                    && change.new.len() < BYTES_PER_LINE =>
+            {
+                change.old.push(*old);
+                change.new.push(*new);
+            }
+            _ => changes.push(MemoryChange {
+                addr: addr as u16,
+                old: vec![*old],
+                new: vec![*new],
+            }),
+        }
+    }
+    changes
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constants::hardware::RAM_SIZE;
+
+    fn state(cycles: u64) -> DeviceState {
+        DeviceState {
+            registers: Dump::default(),
+            mem: vec![0; RAM_SIZE],
+            keyboard_buffer: vec![],
+            cycles,
+            files: vec![],
+            frames: vec![],
+        }
+    }
+
+    #[test]
+    fn test_diff_states() {
+        let before = state(10);
+        assert!(diff_states(&before, &before).is_empty());
+        assert_eq!(
+            diff_states(&before, &before).render(),
+            vec!["Executed 0 instructions", "Nothing changed"]
+        );
+
+        let mut after = state(13);
+        after.registers.pc = 9;
+        after.registers.data_reg[1] = 7;
+        after.mem[0x10] = 1;
+        after.mem[0x11] = 2;
+        after.mem[0x20] = 3;
+        for addr in 0x100..0x10A {
+            after.mem[addr] = 0xFF;
+        }
+        let diff = diff_states(&before, &after);
+        assert_eq!(diff.cycles, 3);
+        assert_eq!(diff.memory.len(), 4);
+        assert_eq!(
+            diff.render(),
+            vec![
+                "Executed 3 instructions",
+                "PC    0000 -> 0009",
+                "D1    00 -> 07",
+                "0010: 00 00 -> 01 02",
+                "0020: 00 -> 03",
+                "0100: 00 00 00 00 00 00 00 00 -> FF FF FF FF FF FF FF FF",
+                "0108: 00 00 -> FF FF",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hidden_memory_changes() {
+        let before = state(0);
+        let mut after = state(0);
+        for line in 0..MAX_MEMORY_LINES + 2 {
+            after.mem[line * 16] = 1;
+        }
+        let lines = diff_states(&before, &after).render();
+        assert_eq!(lines.len(), MAX_MEMORY_LINES + 2);
+        assert_eq!(lines.last().unwrap(), "...and 2 more changed bytes");
+    }
+}
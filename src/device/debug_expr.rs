@@ -0,0 +1,260 @@
+//! Expressions evaluated against the device state in the debugger, e.g. `mem[a0+2]`, `d0*2+1` or `sp-fp`
+//!
+//! Values are registers (`acc`, `d0`-`d3`, `a0`, `a1`, `sp`, `fp`, `pc`), numbers (decimal or hex, `x10`)
+//! and `mem[addr]` for the byte at an address. Operators, highest precedence first, are
+//! unary `-` and `~`, `* / %`, `+ -`, `<< >>`, `&`, `^` and `|`, brackets can be used for grouping
+
+use crate::constants::hardware::RAM_SIZE;
+use crate::device::Dump;
+use anyhow::{Error, Result};
+
+///Evaluate `expr` using the registers in `dump` and `mem`
+pub fn evaluate(expr: &str, dump: &Dump, mem: &[u8]) -> Result<i64> {
+    let mut parser = Parser {
+        tokens: tokenize(expr)?,
+        pos: 0,
+        dump,
+        mem,
+    };
+    let value = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(value),
+        Some(token) => Err(Error::msg(format!("Unexpected '{}'", token.text()))),
+    }
+}
+
+///Value as decimal and hex, negative numbers are shown as 16 bit two's complement in hex
+pub fn format_value(value: i64) -> String {
+    if (i16::MIN as i64..=u16::MAX as i64).contains(&value) {
+        format!("{} (x{:04X})", value, value as u16)
+    } else {
+        format!("{} (x{:X})", value, value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+}
+
+impl Token {
+    fn text(&self) -> String {
+        match self {
+            Token::Number(num) => num.to_string(),
+            Token::Name(name) => name.clone(),
+            Token::Symbol(symbol) => symbol.to_string(),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 15] = [
+    "<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "~", "(", ")", "[", "]",
+];
+
+fn tokenize(expr: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut rest = expr.trim_start();
+    while !rest.is_empty() {
+        if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else if rest.starts_with(|chr: char| chr.is_ascii_alphanumeric()) {
+            let len = rest
+                .find(|chr: char| !chr.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            let word = rest[..len].to_ascii_lowercase();
+            let number =
+                if let Some(hex) = word.strip_prefix("0x").or_else(|| word.strip_prefix('x')) {
+                    i64::from_str_radix(hex, 16).ok()
+                } else {
+                    word.parse::<i64>().ok()
+                };
+            match number {
+                Some(number) => tokens.push(Token::Number(number)),
+                None if word.starts_with(|chr: char| chr.is_ascii_digit()) => {
+                    return Err(Error::msg(format!("Invalid number '{}'", word)))
+                }
+                None => tokens.push(Token::Name(word)),
+            }
+            rest = &rest[len..];
+        } else {
+            return Err(Error::msg(format!(
+                "Unexpected '{}'",
+                rest.chars().next().unwrap_or_default()
+            )));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    dump: &'a Dump,
+    mem: &'a [u8],
+}
+
+impl Parser<'_> {
+    ///Consume the next token if it's one of `symbols`
+    fn take_symbol(&mut self, symbols: &[&'static str]) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Symbol(symbol)) if symbols.contains(symbol) => {
+                self.pos += 1;
+                Some(symbol)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &'static str) -> Result<()> {
+        self.take_symbol(&[symbol])
+            .map(|_| ())
+            .ok_or_else(|| Error::msg(format!("Expected '{}'", symbol)))
+    }
+
+    ///Parse a chain of left associative operators
+    fn binary(
+        &mut self,
+        symbols: &[&'static str],
+        next: fn(&mut Self) -> Result<i64>,
+    ) -> Result<i64> {
+        let mut value = next(self)?;
+        while let Some(symbol) = self.take_symbol(symbols) {
+            let rhs = next(self)?;
+            value = match symbol {
+                "|" => value | rhs,
+                "^" => value ^ rhs,
+                "&" => value & rhs,
+                "<<" => value.wrapping_shl(rhs as u32),
+                ">>" => value.wrapping_shr(rhs as u32),
+                "+" => value.wrapping_add(rhs),
+                "-" => value.wrapping_sub(rhs),
+                "*" => value.wrapping_mul(rhs),
+                "/" | "%" if rhs == 0 => return Err(Error::msg("Division by zero")),
+                "/" => value.wrapping_div(rhs),
+                "%" => value.wrapping_rem(rhs),
+                _ => unreachable!("{} isn't a binary operator", symbol),
+            };
+        }
+        Ok(value)
+    }
+
+    fn or(&mut self) -> Result<i64> {
+        self.binary(&["|"], Self::xor)
+    }
+
+    fn xor(&mut self) -> Result<i64> {
+        self.binary(&["^"], Self::and)
+    }
+
+    fn and(&mut self) -> Result<i64> {
+        self.binary(&["&"], Self::shift)
+    }
+
+    fn shift(&mut self) -> Result<i64> {
+        self.binary(&["<<", ">>"], Self::sum)
+    }
+
+    fn sum(&mut self) -> Result<i64> {
+        self.binary(&["+", "-"], Self::product)
+    }
+
+    fn product(&mut self) -> Result<i64> {
+        self.binary(&["*", "/", "%"], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<i64> {
+        match self.take_symbol(&["-", "~"]) {
+            Some("-") => Ok(self.unary()?.wrapping_neg()),
+            Some(_) => Ok(!self.unary()?),
+            None => self.value(),
+        }
+    }
+
+    fn value(&mut self) -> Result<i64> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| Error::msg("Unexpected end of expression"))?;
+        self.pos += 1;
+        match token {
+            Token::Number(num) => Ok(num),
+            Token::Symbol("(") => {
+                let value = self.or()?;
+                self.expect_symbol(")")?;
+                Ok(value)
+            }
+            Token::Name(name) if name == "mem" => {
+                self.expect_symbol("[")?;
+                let addr = self.or()?;
+                self.expect_symbol("]")?;
+                if addr < 0 || addr >= RAM_SIZE as i64 {
+                    return Err(Error::msg(format!("Address {} is outside of memory", addr)));
+                }
+                Ok(self.mem[addr as usize] as i64)
+            }
+            Token::Name(name) => {
+                let dump = self.dump;
+                let value = match name.as_str() {
+                    "acc" => dump.acc as i64,
+                    "d0" | "d1" | "d2" | "d3" => dump.data_reg[name[1..].parse::<usize>()?] as i64,
+                    "a0" | "a1" => dump.addr_reg[name[1..].parse::<usize>()?] as i64,
+                    "sp" => dump.sp as i64,
+                    "fp" => dump.fp as i64,
+                    "pc" => dump.pc as i64,
+                    _ => return Err(Error::msg(format!("Unknown register '{}'", name))),
+                };
+                Ok(value)
+            }
+            Token::Symbol(symbol) => Err(Error::msg(format!("Unexpected '{}'", symbol))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let dump = Dump {
+            pc: 10,
+            acc: 3,
+            sp: 0xFFF0,
+            fp: 0xFFF8,
+            stack_limit: 0,
+            data_reg: [5, 0, 0, 200],
+            addr_reg: [0x100, 0],
+            flags: 0,
+        };
+        let mut mem = vec![0; RAM_SIZE];
+        mem[0x102] = 42;
+
+        assert_eq!(evaluate("d0*2+1", &dump, &mem).unwrap(), 11);
+        assert_eq!(evaluate("mem[a0+2]", &dump, &mem).unwrap(), 42);
+        assert_eq!(evaluate("fp - sp", &dump, &mem).unwrap(), 8);
+        assert_eq!(evaluate("sp-fp", &dump, &mem).unwrap(), -8);
+        assert_eq!(evaluate("(acc + 1) * 2", &dump, &mem).unwrap(), 8);
+        assert_eq!(evaluate("D3 & xF0 | 1 << 2", &dump, &mem).unwrap(), 196);
+        assert_eq!(evaluate("-pc % 3", &dump, &mem).unwrap(), -1);
+        assert_eq!(evaluate("0x10", &dump, &mem).unwrap(), 16);
+
+        assert!(evaluate("d4", &dump, &mem).is_err());
+        assert!(evaluate("d0 +", &dump, &mem).is_err());
+        assert!(evaluate("d0 / 0", &dump, &mem).is_err());
+        assert!(evaluate("mem[x10000]", &dump, &mem).is_err());
+        assert!(evaluate("(d0", &dump, &mem).is_err());
+        assert!(evaluate("d0 d1", &dump, &mem).is_err());
+    }
+
+    #[test]
+    fn test_format_value() {
+        assert_eq!(format_value(11), "11 (x000B)");
+        assert_eq!(format_value(-8), "-8 (xFFF8)");
+        assert_eq!(format_value(0x12345), "74565 (x12345)");
+    }
+}
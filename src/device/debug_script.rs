@@ -0,0 +1,141 @@
+//! Debugger commands read from a file with `debug --script`, one per line
+//!
+//! - `break <line|label>` / `clear <line|label>` set or clear a breakpoint on a source line or label
+//! - `watch <addr|start-end>` / `unwatch <addr>` set or clear a watchpoint
+//! - `step [count]` execute instructions ignoring breakpoints
+//! - `run` execute until a breakpoint, watchpoint, input request or the program ends
+//! - `regs` print the registers
+//! - `mem <addr|start-end>` print a hex dump of memory
+//! - `set <register|mem[addr]> <value>` change a register or memory byte, the value can be an expression
+//! - `eval <expr>` print the value of an expression, see [`crate::device::debug_expr`]
+//! - `input <text>` add text to the keyboard buffer, `\n` is a newline
+//! - `diff` record the registers and memory, the next `diff` prints what changed since
+//! - `quit` stop the script
+//!
+//! Blank lines and lines starting with `#` are ignored, addresses can be decimal or hex (`x10`)
+
+use crate::constants::hardware::RAM_SIZE;
+use crate::device::debug_device::{parse_address, parse_address_range};
+use anyhow::{Error, Result};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ScriptCommand {
+    ///Source line number or label name
+    Break(String),
+    Clear(String),
+    Watch(u16, u16),
+    Unwatch(u16),
+    Step(usize),
+    Run,
+    Regs,
+    Mem(u16, u16),
+    Eval(String),
+    ///Register or address and value, e.g. `d0 42`
+    Set(String),
+    Input(Vec<u8>),
+    Diff,
+    Quit,
+}
+
+///Parse a script, returning each command with the line it came from
+pub fn parse_script(text: &str) -> Result<Vec<(String, ScriptCommand)>> {
+    let mut commands = vec![];
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, param) = match line.split_once(' ') {
+            Some((name, param)) => (name, param.trim()),
+            None => (line, ""),
+        };
+        let invalid = |expected: &str| {
+            Error::msg(format!(
+                "Invalid script command on line {}: '{}', expected {}",
+                idx + 1,
+                line,
+                expected
+            ))
+        };
+        let command = match name.to_ascii_lowercase().as_str() {
+            "break" if is_breakpoint_target(param) => ScriptCommand::Break(param.to_string()),
+            "break" => return Err(invalid("break <line number|label>")),
+            "clear" if is_breakpoint_target(param) => ScriptCommand::Clear(param.to_string()),
+            "clear" => return Err(invalid("clear <line number|label>")),
+            "watch" => {
+                let (start, end) =
+                    parse_address_range(param).ok_or_else(|| invalid("watch <addr|start-end>"))?;
+                ScriptCommand::Watch(start, end)
+            }
+            "unwatch" => ScriptCommand::Unwatch(
+                parse_address(param).ok_or_else(|| invalid("unwatch <addr>"))?,
+            ),
+            "step" if param.is_empty() => ScriptCommand::Step(1),
+            "step" => ScriptCommand::Step(param.parse().map_err(|_| invalid("step [count]"))?),
+            "run" => ScriptCommand::Run,
+            "regs" => ScriptCommand::Regs,
+            "mem" => {
+                let (start, end) =
+                    parse_address_range(param).ok_or_else(|| invalid("mem <addr|start-end>"))?;
+                if end as usize >= RAM_SIZE {
+                    return Err(invalid(&format!("an address below {:04X}", RAM_SIZE)));
+                }
+                ScriptCommand::Mem(start, end)
+            }
+            "set" if param.contains(char::is_whitespace) => ScriptCommand::Set(param.to_string()),
+            "set" => return Err(invalid("set <register|mem[addr]> <value>")),
+            "eval" if !param.is_empty() => ScriptCommand::Eval(param.to_string()),
+            "input" => ScriptCommand::Input(param.replace("\\n", "\n").into_bytes()),
+            "diff" => ScriptCommand::Diff,
+            "quit" => ScriptCommand::Quit,
+            _ => {
+                return Err(invalid(
+                    "break, clear, watch, unwatch, step, run, regs, mem, set, eval, input, diff or quit",
+                ))
+            }
+        };
+        commands.push((line.to_string(), command));
+    }
+    Ok(commands)
+}
+
+fn is_breakpoint_target(text: &str) -> bool {
+    !text.is_empty()
+        && text
+            .chars()
+            .all(|chr| chr.is_ascii_alphanumeric() || chr == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let commands = parse_script(
+            "# reproduce crash\nbreak 12\n\nrun\nstep\nstep 5\nmem x10-x1F\nwatch 100\ninput Ray\\n\nregs\nquit\n",
+        )
+        .unwrap();
+        let commands: Vec<ScriptCommand> =
+            commands.into_iter().map(|(_, command)| command).collect();
+        assert_eq!(
+            commands,
+            vec![
+                ScriptCommand::Break(String::from("12")),
+                ScriptCommand::Run,
+                ScriptCommand::Step(1),
+                ScriptCommand::Step(5),
+                ScriptCommand::Mem(16, 31),
+                ScriptCommand::Watch(100, 100),
+                ScriptCommand::Input(b"Ray\n".to_vec()),
+                ScriptCommand::Regs,
+                ScriptCommand::Quit,
+            ]
+        );
+
+        assert!(parse_script("break").is_err());
+        assert!(parse_script("clear x-1").is_err());
+        assert!(parse_script("mem 20-10").is_err());
+        assert!(parse_script("jump 5").is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use crate::constants::hardware::DISPLAY_ADDR;
+use crate::device::comm::{stack_corrupted_text, uninitialized_read_text, Output};
+use crate::device::input::{is_quit, key_to_byte};
+use crate::device::internals::{Device, RunResult};
+use crate::device::util::terminal_color;
+use crate::device::{DisplaySize, RunOptions};
+use anyhow::Result;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{poll, read, Event};
+use crossterm::style::{Color, Print, ResetColor, SetForegroundColor};
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use crossterm::{ExecutableCommand, QueueableCommand};
+use std::io::{stdout, Write};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const FRAME_TIME: Duration = Duration::from_micros(16_667);
+
+/// DisplayDevice
+/// Runs a tape as a fantasy console, `size` bytes of memory starting at [`DISPLAY_ADDR`] are drawn as characters each VSYNC
+///
+/// Key presses are added to the keyboard buffer (for IPOLL), printed text is shown below the display
+pub struct DisplayDevice {
+    device: Device,
+    size: DisplaySize,
+    ///Last line of printed text
+    status: String,
+    color: Option<u8>,
+    last_frame: Instant,
+}
+
+impl DisplayDevice {
+    pub fn new(
+        ops: Vec<u8>,
+        strings: Vec<u8>,
+        data: Vec<u8>,
+        data_files: Vec<String>,
+        options: &RunOptions,
+        size: DisplaySize,
+    ) -> Self {
+        let mut device = Device::new(ops, strings, data, data_files);
+        options.configure(&mut device);
+        //stop the stack growing into the framebuffer
+        device.set_stack_limit(DISPLAY_ADDR + size.cells() as u16);
+        DisplayDevice {
+            device,
+            size,
+            status: String::new(),
+            color: None,
+            last_frame: Instant::now(),
+        }
+    }
+
+    pub fn set_tape_version(&mut self, version: u8) {
+        self.device.set_tape_version(version);
+    }
+
+    pub fn set_entry(&mut self, addr: u16) {
+        self.device.set_entry(addr);
+    }
+}
+
+impl DisplayDevice {
+    ///Returns the exit code if the program ended with EXIT
+    pub fn run(&mut self) -> Result<Option<u8>> {
+        enable_raw_mode()?;
+        stdout().execute(EnterAlternateScreen)?.execute(Hide)?;
+        let result = self.run_loop();
+        stdout().execute(Show)?.execute(LeaveAlternateScreen)?;
+        disable_raw_mode()?;
+        if !self.status.is_empty() {
+            println!("{}", self.status);
+        }
+        result
+    }
+
+    fn run_loop(&mut self) -> Result<Option<u8>> {
+        self.draw()?;
+        loop {
+            let result = self.device.step(true);
+            let quit = self.handle_output()?;
+            if quit {
+                return Ok(None);
+            }
+            match result {
+                RunResult::Pause => {}
+                RunResult::CharInputRequested | RunResult::StringInputRequested => {
+                    if !self.wait_for_key()? {
+                        return Ok(None);
+                    }
+                }
+                //no stdin while the display is shown
+                RunResult::StdinRequested => self.device.close_stdin(),
+                RunResult::SerialRequested => {
+                    self.status
+                        .push_str(" [Waiting for serial input but nothing is connected]");
+                    self.draw()?;
+                    self.wait_for_key()?;
+                    return Ok(None);
+                }
+                RunResult::Breakpoint | RunResult::Watchpoint => {}
+                RunResult::EoF
+                | RunResult::Halt
+                | RunResult::Exit(_)
+                | RunResult::ProgError
+                | RunResult::CycleLimit => {
+                    self.status.push_str(" [Ended, press any key]");
+                    self.draw()?;
+                    self.wait_for_key()?;
+                    return Ok(match result {
+                        RunResult::Exit(code) => Some(code),
+                        _ => None,
+                    });
+                }
+            }
+        }
+    }
+
+    ///Returns true if the user quit
+    fn handle_output(&mut self) -> Result<bool> {
+        let msgs = self.device.take_output();
+        for output in msgs {
+            match output {
+                Output::OutputStd(text) => self.add_status(&text),
+                Output::OutputErr(text) => self.add_status(&text),
+                Output::UninitializedRead(pc, addr) => {
+                    self.add_status(&format!("{}\n", uninitialized_read_text(pc, addr, None)))
+                }
+                Output::StackCorrupted(pc, addr) => {
+                    self.add_status(&format!("{}\n", stack_corrupted_text(pc, addr, None)))
+                }
+                Output::OutputBinary(bytes) => self.add_status(&String::from_utf8_lossy(&bytes)),
+                Output::SetColor(color) => self.color = color,
+                Output::Vsync => {
+                    self.draw()?;
+                    let elapsed = self.last_frame.elapsed();
+                    if elapsed < FRAME_TIME {
+                        sleep(FRAME_TIME - elapsed);
+                    }
+                    self.last_frame = Instant::now();
+                    if !self.poll_keys()? {
+                        return Ok(true);
+                    }
+                }
+                //the framebuffer is the screen
+                Output::ClearScreen | Output::MoveCursor(..) => {}
+                Output::BreakpointHit(_) | Output::WatchpointHit(_) => {}
+            }
+        }
+        Ok(false)
+    }
+
+    fn add_status(&mut self, text: &str) {
+        self.status.push_str(text);
+        if let Some(idx) = self.status.rfind('\n') {
+            self.status = self.status[idx + 1..].to_string();
+        }
+    }
+
+    ///Adds any pressed keys to the keyboard buffer, returns false if the user quit
+    fn poll_keys(&mut self) -> Result<bool> {
+        while poll(Duration::from_millis(0))? {
+            if !self.handle_event(read()?) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    ///Blocks until a key is pressed, returns false if the user quit
+    fn wait_for_key(&mut self) -> Result<bool> {
+        loop {
+            let event = read()?;
+            if let Event::Key(_) = event {
+                return Ok(self.handle_event(event));
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: Event) -> bool {
+        if let Event::Key(key) = event {
+            if is_quit(&key) {
+                return false;
+            }
+            if let Some(chr) = key_to_byte(&key) {
+                self.device.push_char(chr);
+            }
+        }
+        true
+    }
+
+    fn draw(&mut self) -> Result<()> {
+        let mut out = stdout();
+        out.queue(MoveTo(0, 0))?
+            .queue(SetForegroundColor(terminal_color(self.color)))?;
+        for (row, line) in render_lines(&self.device.mem, self.size)
+            .into_iter()
+            .enumerate()
+        {
+            out.queue(MoveTo(0, row as u16))?.queue(Print(line))?;
+        }
+        out.queue(ResetColor)?
+            .queue(MoveTo(0, self.size.height as u16))?
+            .queue(Clear(ClearType::CurrentLine))?
+            .queue(SetForegroundColor(Color::DarkGrey))?
+            .queue(Print(&self.status))?
+            .queue(ResetColor)?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+///Framebuffer as text, one string per row
+///
+///Printable ASCII is drawn as is, everything else is drawn as a space
+pub fn render_lines(mem: &[u8], size: DisplaySize) -> Vec<String> {
+    let start = DISPLAY_ADDR as usize;
+    mem[start..start + size.cells()]
+        .chunks(size.width as usize)
+        .map(|row| {
+            row.iter()
+                .map(|byte| {
+                    if byte.is_ascii_graphic() {
+                        *byte as char
+                    } else {
+                        ' '
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constants::hardware::RAM_SIZE;
+
+    #[test]
+    fn test_render_lines() {
+        let mut mem = [0_u8; RAM_SIZE];
+        let start = DISPLAY_ADDR as usize;
+        mem[start..start + 6].copy_from_slice(b"ab\ncd!");
+        let lines = render_lines(
+            &mem,
+            DisplaySize {
+                width: 3,
+                height: 3,
+            },
+        );
+        assert_eq!(lines, vec!["ab ", "cd!", "   "]);
+    }
+}
@@ -0,0 +1,495 @@
+use anyhow::{Error, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+///Open file returned by a [`FileProvider`]
+pub trait DeviceFile: Read + Write + Seek {
+    ///Truncate or extend the file to `len` bytes
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+}
+
+///Bytes written to a [`BufferedFile`] are kept until there are this many
+pub const FILE_BUFFER_SIZE: usize = 8192;
+
+///Storage for files opened by FOPEN
+pub trait FileProvider {
+    ///Open `path` for reading and writing, creating it if it doesn't exist
+    fn open(&mut self, path: &Path) -> Result<Box<dyn DeviceFile>>;
+
+    ///Open `path` for reading only, it must already exist
+    fn open_read_only(&mut self, path: &Path) -> Result<Box<dyn DeviceFile>>;
+
+    fn remove(&mut self, path: &Path) -> Result<()>;
+
+    ///Absolute path used to check allowed paths, None if it can't be resolved
+    fn canonicalize(&self, path: &Path) -> Option<PathBuf>;
+}
+
+///Files on the host filesystem
+#[cfg(feature = "fs")]
+#[derive(Debug, Default)]
+pub struct StdFileProvider;
+
+#[cfg(feature = "fs")]
+impl DeviceFile for std::fs::File {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        std::fs::File::set_len(self, len)
+    }
+}
+
+#[cfg(feature = "fs")]
+impl FileProvider for StdFileProvider {
+    fn open(&mut self, path: &Path) -> Result<Box<dyn DeviceFile>> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        Ok(Box::new(file))
+    }
+
+    fn open_read_only(&mut self, path: &Path) -> Result<Box<dyn DeviceFile>> {
+        let file = std::fs::OpenOptions::new().read(true).open(path)?;
+        Ok(Box::new(file))
+    }
+
+    fn remove(&mut self, path: &Path) -> Result<()> {
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    fn canonicalize(&self, path: &Path) -> Option<PathBuf> {
+        canonicalize_host_path(path)
+    }
+}
+
+///Absolute path of `path` on the host with symlinks and `..` resolved
+#[cfg(feature = "fs")]
+fn canonicalize_host_path(path: &Path) -> Option<PathBuf> {
+    //canonicalize fails for files that don't exist yet so check the parent instead
+    match path.canonicalize() {
+        Ok(path) => Some(path),
+        //a dangling symlink, opening it would create the file wherever it points
+        Err(_)
+            if std::fs::symlink_metadata(path)
+                .map(|meta| meta.file_type().is_symlink())
+                .unwrap_or(false) =>
+        {
+            None
+        }
+        Err(_) => path
+            .file_name()
+            .zip(path.parent())
+            .and_then(|(file_name, parent)| {
+                let parent = if parent.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    parent
+                };
+                parent.canonicalize().ok().map(|dir| dir.join(file_name))
+            }),
+    }
+}
+
+///Files on the host filesystem that must be inside `root`
+///
+///Paths are resolved (including symlinks and `..`) before every open or delete so a program
+///can't reach files outside the directory
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone)]
+pub struct SandboxFileProvider {
+    root: PathBuf,
+}
+
+#[cfg(feature = "fs")]
+impl SandboxFileProvider {
+    ///`root` must already be canonical
+    pub fn new(root: PathBuf) -> Self {
+        SandboxFileProvider { root }
+    }
+
+    fn resolve(&self, path: &Path) -> Result<PathBuf> {
+        self.canonicalize(path).ok_or_else(|| {
+            Error::msg(format!(
+                "File {} is outside the sandbox {}",
+                path.display(),
+                self.root.display()
+            ))
+        })
+    }
+}
+
+#[cfg(feature = "fs")]
+impl FileProvider for SandboxFileProvider {
+    fn open(&mut self, path: &Path) -> Result<Box<dyn DeviceFile>> {
+        let path = self.resolve(path)?;
+        StdFileProvider.open(&path)
+    }
+
+    fn open_read_only(&mut self, path: &Path) -> Result<Box<dyn DeviceFile>> {
+        let path = self.resolve(path)?;
+        StdFileProvider.open_read_only(&path)
+    }
+
+    fn remove(&mut self, path: &Path) -> Result<()> {
+        let path = self.resolve(path)?;
+        StdFileProvider.remove(&path)
+    }
+
+    ///None if `path` isn't inside the sandbox
+    fn canonicalize(&self, path: &Path) -> Option<PathBuf> {
+        canonicalize_host_path(path).filter(|path| path.starts_with(&self.root))
+    }
+}
+
+///Files kept in memory, for hosts without a filesystem such as browsers
+///
+///Clones share the same files so the host can read what the program wrote
+#[derive(Debug, Default, Clone)]
+pub struct MemoryFileProvider {
+    files: Rc<RefCell<HashMap<PathBuf, Vec<u8>>>>,
+}
+
+impl MemoryFileProvider {
+    pub fn insert<P: Into<PathBuf>>(&self, path: P, content: Vec<u8>) {
+        self.files.borrow_mut().insert(path.into(), content);
+    }
+
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<Vec<u8>> {
+        self.files.borrow().get(path.as_ref()).cloned()
+    }
+}
+
+impl FileProvider for MemoryFileProvider {
+    fn open(&mut self, path: &Path) -> Result<Box<dyn DeviceFile>> {
+        self.files
+            .borrow_mut()
+            .entry(path.to_path_buf())
+            .or_default();
+        Ok(Box::new(MemoryFile {
+            files: self.files.clone(),
+            path: path.to_path_buf(),
+            cursor: 0,
+        }))
+    }
+
+    fn open_read_only(&mut self, path: &Path) -> Result<Box<dyn DeviceFile>> {
+        if !self.files.borrow().contains_key(path) {
+            return Err(Error::msg(format!(
+                "File does not exist: {}",
+                path.display()
+            )));
+        }
+        self.open(path)
+    }
+
+    fn remove(&mut self, path: &Path) -> Result<()> {
+        match self.files.borrow_mut().remove(path) {
+            Some(_) => Ok(()),
+            None => Err(Error::msg(format!(
+                "File does not exist: {}",
+                path.display()
+            ))),
+        }
+    }
+
+    ///Paths are used as given, there are no directories to resolve
+    fn canonicalize(&self, path: &Path) -> Option<PathBuf> {
+        Some(path.to_path_buf())
+    }
+}
+
+struct MemoryFile {
+    files: Rc<RefCell<HashMap<PathBuf, Vec<u8>>>>,
+    path: PathBuf,
+    cursor: u64,
+}
+
+impl MemoryFile {
+    fn with_content<T>(&self, action: impl FnOnce(&mut Vec<u8>) -> T) -> io::Result<T> {
+        match self.files.borrow_mut().get_mut(&self.path) {
+            Some(content) => Ok(action(content)),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} was deleted", self.path.display()),
+            )),
+        }
+    }
+}
+
+impl Read for MemoryFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let cursor = self.cursor as usize;
+        let count = self.with_content(|content| {
+            let start = cursor.min(content.len());
+            let count = buf.len().min(content.len() - start);
+            buf[..count].copy_from_slice(&content[start..start + count]);
+            count
+        })?;
+        self.cursor += count as u64;
+        Ok(count)
+    }
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let cursor = self.cursor as usize;
+        self.with_content(|content| {
+            if content.len() < cursor + buf.len() {
+                content.resize(cursor + buf.len(), 0);
+            }
+            content[cursor..cursor + buf.len()].copy_from_slice(buf);
+        })?;
+        self.cursor += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemoryFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let len = self.with_content(|content| content.len() as u64)?;
+        let cursor = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (len as i64).checked_add(offset).map(|pos| pos as u64),
+            SeekFrom::Current(offset) => (self.cursor as i64)
+                .checked_add(offset)
+                .map(|pos| pos as u64),
+        };
+        match cursor {
+            Some(cursor) if (cursor as i64) >= 0 => {
+                self.cursor = cursor;
+                Ok(cursor)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid seek to a negative position",
+            )),
+        }
+    }
+}
+
+impl DeviceFile for MemoryFile {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.with_content(|content| content.resize(len as usize, 0))
+    }
+}
+
+///Collects writes so FILEW doesn't reach the underlying file for every byte
+///
+///The buffer is written before any read, seek or resize and when dropped
+pub struct BufferedFile {
+    inner: Box<dyn DeviceFile>,
+    buffer: Vec<u8>,
+}
+
+impl BufferedFile {
+    pub fn new(inner: Box<dyn DeviceFile>) -> Self {
+        BufferedFile {
+            inner,
+            buffer: Vec::with_capacity(FILE_BUFFER_SIZE),
+        }
+    }
+
+    fn write_buffer(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.inner.write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+}
+
+impl Read for BufferedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.write_buffer()?;
+        self.inner.read(buf)
+    }
+}
+
+impl Write for BufferedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buffer.len() + buf.len() > FILE_BUFFER_SIZE {
+            self.write_buffer()?;
+        }
+        if buf.len() >= FILE_BUFFER_SIZE {
+            self.inner.write(buf)
+        } else {
+            self.buffer.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_buffer()?;
+        self.inner.flush()
+    }
+}
+
+impl Seek for BufferedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.write_buffer()?;
+        self.inner.seek(pos)
+    }
+}
+
+impl DeviceFile for BufferedFile {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.write_buffer()?;
+        self.inner.set_len(len)
+    }
+}
+
+impl Drop for BufferedFile {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constants::code::{FILEW_VAL_VAL, FOPEN_VAL, FTRUNC_VAL};
+    use crate::device::internals::{Device, RunResult};
+
+    #[test]
+    fn test_memory_files() {
+        let mut provider = MemoryFileProvider::default();
+        provider.insert("a.bin", vec![1, 2, 3]);
+
+        let mut file = provider.open(Path::new("a.bin")).unwrap();
+        let mut buffer = [0; 2];
+        assert_eq!(file.read(&mut buffer).unwrap(), 2);
+        assert_eq!(buffer, [1, 2]);
+        assert_eq!(file.write(&[7, 8]).unwrap(), 2);
+        assert_eq!(provider.get("a.bin"), Some(vec![1, 2, 7, 8]));
+        assert_eq!(file.seek(SeekFrom::End(0)).unwrap(), 4);
+        assert!(file.seek(SeekFrom::Current(-5)).is_err());
+        file.set_len(1).unwrap();
+        assert_eq!(provider.get("a.bin"), Some(vec![1]));
+
+        provider.open(Path::new("new.bin")).unwrap();
+        assert_eq!(provider.get("new.bin"), Some(vec![]));
+        provider.remove(Path::new("new.bin")).unwrap();
+        assert_eq!(provider.get("new.bin"), None);
+        assert!(provider.remove(Path::new("new.bin")).is_err());
+        assert!(provider.open_read_only(Path::new("new.bin")).is_err());
+    }
+
+    #[test]
+    fn test_buffered_file() {
+        let provider = MemoryFileProvider::default();
+        provider.insert("a.bin", vec![1, 2, 3, 4]);
+        let mut file = BufferedFile::new(provider.clone().open(Path::new("a.bin")).unwrap());
+
+        file.write_all(&[7, 8]).unwrap();
+        assert_eq!(provider.get("a.bin"), Some(vec![1, 2, 3, 4]));
+        let mut buffer = [0; 1];
+        file.read_exact(&mut buffer).unwrap();
+        assert_eq!(buffer, [3]);
+        assert_eq!(provider.get("a.bin"), Some(vec![7, 8, 3, 4]));
+
+        file.write_all(&[9]).unwrap();
+        assert_eq!(file.stream_position().unwrap(), 4);
+        file.write_all(&[10]).unwrap();
+        file.set_len(2).unwrap();
+        assert_eq!(provider.get("a.bin"), Some(vec![7, 8]));
+
+        file.write_all(&vec![5; FILE_BUFFER_SIZE + 1]).unwrap();
+        assert_eq!(provider.get("a.bin").unwrap().len(), 6 + FILE_BUFFER_SIZE);
+        file.write_all(&[6]).unwrap();
+        drop(file);
+        assert_eq!(provider.get("a.bin").unwrap().len(), 7 + FILE_BUFFER_SIZE);
+    }
+
+    #[test]
+    fn test_read_only_files() {
+        let provider = MemoryFileProvider::default();
+        provider.insert("data.bin", vec![5, 6]);
+        let ops = vec![FOPEN_VAL, 0, FTRUNC_VAL, 0, FILEW_VAL_VAL, 0, 9];
+        let mut device = Device::new(ops, vec![], vec![], vec![String::from("data.bin")]);
+        device.set_file_provider(Box::new(provider.clone()));
+        device.set_read_only_files(vec![0]);
+
+        assert_eq!(device.step(true), RunResult::Pause);
+        assert_eq!(device.data_reg, [0, 0, 0, 2]);
+        assert_eq!(device.step(true), RunResult::ProgError);
+        device.pc = 4;
+        assert_eq!(device.step(true), RunResult::ProgError);
+        assert_eq!(provider.get("data.bin"), Some(vec![5, 6]));
+
+        let mut device = Device::new(
+            vec![FOPEN_VAL, 0],
+            vec![],
+            vec![],
+            vec![String::from("missing.bin")],
+        );
+        device.set_file_provider(Box::new(provider.clone()));
+        device.set_read_only_files(vec![0]);
+        assert_eq!(device.step(true), RunResult::ProgError);
+        assert_eq!(provider.get("missing.bin"), None);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_sandbox_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("inner")).unwrap();
+        let mut provider = SandboxFileProvider::new(root.join("inner"));
+
+        let mut file = provider.open(&root.join("inner/a.bin")).unwrap();
+        file.write_all(&[1, 2]).unwrap();
+        assert_eq!(std::fs::read(root.join("inner/a.bin")).unwrap(), vec![1, 2]);
+        assert!(provider.open(&root.join("inner/../b.bin")).is_err());
+        assert!(provider.open(&root.join("b.bin")).is_err());
+        assert!(provider.open(&root.join("missing/c.bin")).is_err());
+        assert!(!root.join("b.bin").exists());
+
+        std::fs::write(root.join("outside.bin"), [3]).unwrap();
+        assert!(provider.remove(&root.join("inner/../outside.bin")).is_err());
+        assert!(root.join("outside.bin").exists());
+        provider.remove(&root.join("inner/a.bin")).unwrap();
+        assert!(!root.join("inner/a.bin").exists());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("outside.bin"), root.join("inner/link.bin"))
+                .unwrap();
+            assert!(provider.open(&root.join("inner/link.bin")).is_err());
+
+            std::os::unix::fs::symlink(root.join("escaped.bin"), root.join("inner/dangle.bin"))
+                .unwrap();
+            assert!(provider.open(&root.join("inner/dangle.bin")).is_err());
+            assert!(!root.join("escaped.bin").exists());
+        }
+    }
+
+    #[test]
+    fn test_device_memory_files() {
+        let provider = MemoryFileProvider::default();
+        provider.insert("data.bin", vec![5, 6]);
+        let mut device = Device::new(
+            vec![FOPEN_VAL, 0, FILEW_VAL_VAL, 0, 9],
+            vec![],
+            vec![],
+            vec![String::from("data.bin")],
+        );
+        device.set_file_provider(Box::new(provider.clone()));
+
+        assert_eq!(device.step(true), RunResult::Pause);
+        assert_eq!(device.data_reg, [0, 0, 0, 2]);
+        assert_eq!(device.step(true), RunResult::Pause);
+        assert_eq!(provider.get("data.bin"), Some(vec![5, 6]));
+        assert_eq!(device.step(true), RunResult::EoF);
+        assert_eq!(provider.get("data.bin"), Some(vec![9, 6]));
+    }
+}
@@ -1,53 +1,200 @@
-use anyhow::Result;
-use crossterm::event::{Event, KeyCode, KeyModifiers};
+use crate::common::read_lines;
+#[cfg(feature = "terminal")]
+use crate::constants::hardware::{
+    KEY_DOWN, KEY_END, KEY_F1, KEY_HOME, KEY_INSERT, KEY_LEFT, KEY_PAGE_DOWN, KEY_PAGE_UP,
+    KEY_RIGHT, KEY_UP,
+};
+use anyhow::{Error, Result};
+#[cfg(feature = "terminal")]
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use std::collections::VecDeque;
+#[cfg(feature = "terminal")]
 use std::io::stdin;
 
+///Keyboard input read from a file instead of the terminal
+///
+///Each line is a chunk that's given to the program when it requests input (RCHR or RSTR),
+///lines starting with `@<cycle> ` are also given once the program has executed that many instructions (for IPOLL)
+///Blank lines and lines starting with `#` are ignored, `\n`, `\t`, `\e`, `\#` and `\\` can be used for special chars
+#[derive(Debug, Default, PartialEq)]
+pub struct InputScript {
+    chunks: VecDeque<ScriptChunk>,
+}
+
+#[derive(Debug, PartialEq)]
+struct ScriptChunk {
+    at_cycle: Option<u64>,
+    bytes: Vec<u8>,
+}
+
+impl InputScript {
+    pub fn read(path: &str) -> Result<InputScript> {
+        InputScript::parse(&read_lines(path)?)
+    }
+
+    pub fn parse(lines: &[String]) -> Result<InputScript> {
+        let mut chunks = VecDeque::new();
+        for (idx, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (at_cycle, text) = match line.strip_prefix('@') {
+                Some(timed) => {
+                    let (cycle, text) = timed.split_once(' ').unwrap_or((timed, ""));
+                    let cycle = cycle.parse::<u64>().map_err(|_| {
+                        Error::msg(format!(
+                            "Invalid cycle '{}' in input script on line {}",
+                            cycle,
+                            idx + 1
+                        ))
+                    })?;
+                    (Some(cycle), text)
+                }
+                None => (None, line.as_str()),
+            };
+            chunks.push_back(ScriptChunk {
+                at_cycle,
+                bytes: unescape(text).map_err(|err| {
+                    Error::msg(format!("{} in input script on line {}", err, idx + 1))
+                })?,
+            });
+        }
+        Ok(InputScript { chunks })
+    }
+
+    ///Next chunk if it's timed and `cycles` has reached it's time
+    pub fn due(&mut self, cycles: u64) -> Option<Vec<u8>> {
+        match self.chunks.front() {
+            Some(ScriptChunk {
+                at_cycle: Some(at_cycle),
+                ..
+            }) if *at_cycle <= cycles => self.chunks.pop_front().map(|chunk| chunk.bytes),
+            _ => None,
+        }
+    }
+
+    ///Next chunk, used when the program is waiting for input
+    pub fn next_chunk(&mut self) -> Option<Vec<u8>> {
+        self.chunks.pop_front().map(|chunk| chunk.bytes)
+    }
+}
+
+fn unescape(text: &str) -> Result<Vec<u8>> {
+    let mut output = String::new();
+    let mut chars = text.chars();
+    while let Some(chr) = chars.next() {
+        if chr == '\\' {
+            match chars.next() {
+                Some('n') => output.push('\n'),
+                Some('t') => output.push('\t'),
+                Some('e') => output.push(27 as char),
+                Some('#') => output.push('#'),
+                Some('\\') => output.push('\\'),
+                other => {
+                    return Err(Error::msg(format!(
+                        "Invalid escape '\\{}'",
+                        other.map(String::from).unwrap_or_default()
+                    )))
+                }
+            }
+        } else {
+            output.push(chr);
+        }
+    }
+    Ok(output.into_bytes())
+}
+
+#[cfg(feature = "terminal")]
 pub fn read_str() -> Vec<u8> {
     let mut chars = String::new();
     stdin().read_line(&mut chars).unwrap();
     chars.trim().as_bytes().to_vec()
 }
 
+#[cfg(feature = "terminal")]
 pub fn read_char() -> Result<u8> {
-    let mut char = [0_u8; 1];
     crossterm::terminal::enable_raw_mode()?;
-    let mut event = crossterm::event::read()?;
-    loop {
-        if let Event::Key(key) = event {
-            if key.modifiers == KeyModifiers::CONTROL && key.code == KeyCode::Char('c') {
+    let chr = loop {
+        if let Event::Key(key) = crossterm::event::read()? {
+            if is_quit(&key) {
                 crossterm::terminal::disable_raw_mode()?;
                 std::process::exit(1);
             }
-            match key.code {
-                KeyCode::Enter => {
-                    char[0] = 10;
-                    break;
-                }
-                KeyCode::Backspace => {
-                    char[0] = 8;
-                    break;
-                }
-                KeyCode::Tab => {
-                    char[0] = 9;
-                    break;
-                }
-                KeyCode::Char(chr) => {
-                    char[0] = chr as u8;
-                    break;
-                }
-                KeyCode::Esc => {
-                    char[0] = 27;
-                    break;
-                }
-                KeyCode::Delete => {
-                    char[0] = 127;
-                    break;
-                }
-                _ => {}
+            if let Some(chr) = key_to_byte(&key) {
+                break chr;
             }
         }
-        event = crossterm::event::read()?;
-    }
+    };
     crossterm::terminal::disable_raw_mode()?;
-    Ok(char[0])
+    Ok(chr)
+}
+
+///Ctrl+C
+#[cfg(feature = "terminal")]
+pub fn is_quit(key: &KeyEvent) -> bool {
+    key.modifiers == KeyModifiers::CONTROL && key.code == KeyCode::Char('c')
+}
+
+///Byte given to the program for a key press, `None` for keys the device doesn't support
+#[cfg(feature = "terminal")]
+pub fn key_to_byte(key: &KeyEvent) -> Option<u8> {
+    match key.code {
+        KeyCode::Enter => Some(10),
+        KeyCode::Backspace => Some(8),
+        KeyCode::Tab => Some(9),
+        KeyCode::Char(chr) => Some(chr as u8),
+        KeyCode::Esc => Some(27),
+        KeyCode::Delete => Some(127),
+        KeyCode::Up => Some(KEY_UP),
+        KeyCode::Down => Some(KEY_DOWN),
+        KeyCode::Left => Some(KEY_LEFT),
+        KeyCode::Right => Some(KEY_RIGHT),
+        KeyCode::Home => Some(KEY_HOME),
+        KeyCode::End => Some(KEY_END),
+        KeyCode::PageUp => Some(KEY_PAGE_UP),
+        KeyCode::PageDown => Some(KEY_PAGE_DOWN),
+        KeyCode::Insert => Some(KEY_INSERT),
+        KeyCode::F(num) if (1..=12).contains(&num) => Some(KEY_F1 + num - 1),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::lines;
+
+    #[test]
+    fn test_parse_script() {
+        let mut script =
+            InputScript::parse(&lines("# comment\nhello\n\n@100 y\\n\n\\#1\\t\\\\")).unwrap();
+        assert_eq!(script.due(0), None);
+        assert_eq!(script.next_chunk(), Some(b"hello".to_vec()));
+        assert_eq!(script.due(99), None);
+        assert_eq!(script.due(100), Some(b"y\n".to_vec()));
+        assert_eq!(script.due(200), None);
+        assert_eq!(script.next_chunk(), Some(b"#1\t\\".to_vec()));
+        assert_eq!(script.next_chunk(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "terminal")]
+    fn test_key_to_byte() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        assert_eq!(key_to_byte(&key(KeyCode::Char('a'))), Some(b'a'));
+        assert_eq!(key_to_byte(&key(KeyCode::Enter)), Some(10));
+        assert_eq!(key_to_byte(&key(KeyCode::Up)), Some(KEY_UP));
+        assert_eq!(key_to_byte(&key(KeyCode::Right)), Some(KEY_RIGHT));
+        assert_eq!(key_to_byte(&key(KeyCode::F(1))), Some(KEY_F1));
+        assert_eq!(key_to_byte(&key(KeyCode::F(12))), Some(0x9C));
+        assert_eq!(key_to_byte(&key(KeyCode::F(13))), None);
+        assert_eq!(key_to_byte(&key(KeyCode::BackTab)), None);
+    }
+
+    #[test]
+    fn test_parse_script_errors() {
+        assert!(InputScript::parse(&lines("@abc text")).is_err());
+        assert!(InputScript::parse(&lines("bad \\q")).is_err());
+        assert!(InputScript::parse(&lines("trailing \\")).is_err());
+    }
 }
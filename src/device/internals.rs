@@ -1,52 +1,418 @@
 use crate::constants::code::*;
 use crate::constants::hardware::*;
+use crate::constants::system::{ACC_COMPARE_PRG_VERSION, WIDE_DATA_PRG_VERSION};
 use crate::constants::{compare, get_byte_count, is_jump_op};
+use crate::device::clock::HostClock;
 use crate::device::comm::Output::*;
 use crate::device::comm::*;
-use crate::device::internals::RunResult::{Breakpoint, EoF, ProgError};
-use crate::device::Dump;
+#[cfg(not(feature = "fs"))]
+use crate::device::files::MemoryFileProvider;
+#[cfg(feature = "fs")]
+use crate::device::files::StdFileProvider;
+use crate::device::files::{BufferedFile, DeviceFile, FileProvider};
+use crate::device::internals::RunResult::{
+    Breakpoint, CycleLimit, EoF, Pause, ProgError, Watchpoint,
+};
+use crate::device::protocol::InputKind;
+use crate::device::storage::KvStore;
+use crate::device::{DeviceState, Dump, FileState, StackFrame};
 use anyhow::{Error, Result};
-use chrono::{Local, Timelike};
+use lazy_static::lazy_static;
 use random_fast_rng::{FastRng, Random};
 use std::cmp::Ordering;
-use std::fs::{File, OpenOptions};
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::io::{Read, Seek, SeekFrom, Write};
-use std::ops::{BitAnd, BitOr, BitXor, Not};
+use std::ops::{BitAnd, BitOr, BitXor, Not, Range};
+use std::path::{Path, PathBuf};
 
 //Fields are only public for testing
 pub struct Device {
     pub mem: [u8; RAM_SIZE],
     tape_ops: Vec<u8>,
+    ///Bytes of `tape_ops` that start an instruction, see [`find_op_starts`]
+    op_starts: Vec<bool>,
     pub tape_strings: Vec<u8>,
     pub tape_data: Vec<u8>,
     data_files: Vec<String>,
     flags: Flags,
+    ///CMP stores result in ACC instead of flags, for tapes built before the flags register
+    acc_compare: bool,
+    ///Data array lengths are 1 byte instead of 2, for tapes built before 16 bit lengths
+    narrow_data: bool,
     pub pc: u16,
     pub acc: u8,
     sp: u16,
     fp: u16,
+    ///Lowest address the stack may use
+    stack_limit: u16,
+    ///Calls that haven't returned yet, used to validate ARG
+    frames: Vec<StackFrame>,
+    ///Where PUSHes for the current frame start, i.e. FP minus any LOCAL bytes
+    stack_base: u16,
+    ///CALL writes canaries around the saved FP and return address, see [`Device::set_stack_check`]
+    stack_check: bool,
+    ///Address of each live canary that's been written to and the PC of the instruction that did it
+    canary_writes: Vec<(u16, u16)>,
     pub data_reg: [u8; DATA_REG_COUNT],
     pub addr_reg: [u16; ADDR_REG_COUNT],
-    files: Vec<Option<File>>,
+    files: Vec<Option<Box<dyn DeviceFile>>>,
+    file_provider: Box<dyn FileProvider>,
+    ///Paths of open files, needed for FDEL
+    file_paths: HashMap<usize, PathBuf>,
     pub breakpoints: Vec<u16>,
+    pub watchpoints: Vec<(u16, u16)>,
+    watchpoint_hit: Option<u16>,
+    ///Bytes of RAM the program has written, only tracked in strict mode, see [`Device::set_strict`]
+    initialized: Option<Vec<bool>>,
     rng: FastRng,
-    pub keyboard_buffer: Vec<u8>,
-    pub output: Vec<Output>,
+    ///Keys waiting to be read by RCHR, RSTR and IPOLL, added with [`Device::push_char`] and [`Device::push_string`]
+    keyboard_buffer: Vec<u8>,
+    ///Input the program stopped for in the last step
+    input_pending: Option<InputKind>,
+    ///Bytes from the host's stdin waiting to be read with `FILER 254`
+    pub stdin_buffer: Vec<u8>,
+    ///Host stdin has ended, `FILER 254` reads 0 bytes once the buffer is empty
+    stdin_closed: bool,
+    ///Bytes received by the serial port waiting to be read with RECV
+    pub serial_in: Vec<u8>,
+    ///Bytes sent with SEND, the frontend moves these to whatever is connected to the serial port
+    pub serial_out: Vec<u8>,
+    ///MEMR/MEMW of the [`MMIO_ADDR`] page use the device instead of RAM
+    mmio: bool,
+    ///Value of MILLIS latched when [`MMIO_MILLIS`] is read
+    mmio_millis: [u8; 4],
+    ///Output waiting for the frontend to take it with [`Device::take_output`] (which also resets
+    ///the count for [`ResourceLimits::max_output_bytes`]), unused if an output sink is set
+    output: Vec<Output>,
+    ///Text and binary bytes in [`Device::output`], kept as it's checked every step
+    output_bytes: usize,
+    output_sink: Option<Box<dyn OutputSink>>,
+    limits: ResourceLimits,
+    capabilities: Capabilities,
+    ///Directories FOPEN may open files by name from, any path if empty
+    allowed_paths: Vec<PathBuf>,
+    ///File numbers opened read only, FILEW, FTRUNC and FDEL fail for these
+    read_only_files: Vec<usize>,
+    ///Program arguments for ARGS
+    args: Vec<String>,
+    ///Records for SAVE and LOADKV, None if there's no save file
+    storage: Option<KvStore>,
+    ///Instructions executed so far
+    cycles: u64,
+    max_cycles: Option<u64>,
+    profile: Option<Profile>,
+    ///Seconds since midnight when the program started, TIME uses a virtual clock if set
+    start_time: Option<u32>,
+    ///Host time for TIME, MILLIS and SLEEP when not using the virtual clock
+    clock: HostClock,
+    ///Milliseconds the program has slept for when using a virtual clock
+    slept_millis: u64,
+    interrupts: Interrupts,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+///Interrupt state, see [`Device::check_interrupts`]
+#[derive(Debug, Default, Clone)]
+struct Interrupts {
+    ///Handler address for each interrupt, set with INTH
+    handlers: [Option<u16>; INTERRUPT_COUNT],
+    ///Bit set for each masked interrupt (bit 0 is [`INT_TIMER`])
+    mask: u8,
+    ///Bit set for each interrupt that has been raised but not handled
+    pending: u8,
+    ///Masks to restore with RETI, one per running handler
+    saved_masks: Vec<u8>,
+    ///Milliseconds between timer interrupts, 0 if the timer is stopped
+    timer_period: u8,
+    ///Time of the next timer interrupt, see [`Device::current_millis`]
+    next_tick: u64,
+}
+
+///Options to make RAND and TIME give the same results every run
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct Determinism {
+    ///Initial RNG seed
+    pub seed: Option<u64>,
+    ///Virtual clock start as seconds since midnight, the clock advances by one second every
+    ///[`VIRTUAL_CYCLES_PER_SECOND`] instructions and by the length of each SLEEP
+    pub start_time: Option<u32>,
+}
+
+pub const VIRTUAL_CYCLES_PER_SECOND: u64 = 1000;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[cfg(feature = "fs")]
+fn default_file_provider() -> Box<dyn FileProvider> {
+    Box::new(StdFileProvider)
+}
+
+#[cfg(not(feature = "fs"))]
+fn default_file_provider() -> Box<dyn FileProvider> {
+    Box::new(MemoryFileProvider::default())
+}
+
+///Whether each byte of `ops` starts an instruction, found by decoding every instruction from the
+///first byte, with an extra entry for the end of the program (jumping there ends the program)
+///
+///Bytes after an unknown opcode aren't instruction starts
+fn find_op_starts(ops: &[u8]) -> Vec<bool> {
+    let mut starts = vec![false; ops.len() + 1];
+    let mut pc = 0;
+    while pc < ops.len() {
+        match &DISPATCH[ops[pc] as usize] {
+            Some(dispatch) => {
+                starts[pc] = true;
+                pc += dispatch.len;
+            }
+            None => return starts,
+        }
+    }
+    starts[ops.len()] = true;
+    starts
+}
+
+fn no_save_file() -> Error {
+    Error::msg("No save file, SAVE and LOADKV can't be used")
+}
+
+impl Determinism {
+    ///Parse a time in the format `HH:MM:SS` into seconds since midnight
+    pub fn parse_time(text: &str) -> Result<u32> {
+        let parts = text
+            .split(':')
+            .map(|part| part.trim().parse::<u32>())
+            .collect::<std::result::Result<Vec<u32>, _>>();
+        match parts.as_deref() {
+            Ok([hour, minute, second]) if *hour < 24 && *minute < 60 && *second < 60 => {
+                Ok(hour * 3600 + minute * 60 + second)
+            }
+            _ => Err(Error::msg(format!(
+                "Invalid time '{}', must be HH:MM:SS",
+                text
+            ))),
+        }
+    }
+}
+
+///Execution counts collected while profiling
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Profile {
+    ///Executions per opcode
+    pub ops: HashMap<u8, u64>,
+    ///Executions per instruction address
+    pub addrs: HashMap<u16, u64>,
+}
+
+impl Profile {
+    fn record(&mut self, addr: u16, op: u8) {
+        *self.ops.entry(op).or_insert(0) += 1;
+        *self.addrs.entry(addr).or_insert(0) += 1;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.ops.values().sum()
+    }
+}
+
+///Host memory used by the device that can grow while a program runs
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct ResourceStats {
+    ///Number of undrained entries in `Device::output`
+    pub output_entries: usize,
+    ///Number of bytes of text in undrained entries in `Device::output`
+    pub output_bytes: usize,
+    pub keyboard_buffer_bytes: usize,
+    ///Host stdin waiting to be read with `FILER 254`
+    pub stdin_buffer_bytes: usize,
+    ///Bytes received but not yet read with RECV
+    pub serial_in_bytes: usize,
+    ///Bytes sent with SEND that the frontend hasn't taken from `Device::serial_out`
+    pub serial_out_bytes: usize,
+    ///CALLs that haven't returned, kept to check ARG offsets
+    pub call_frames: usize,
+    ///Addresses in the execution trace if profiling, at most one per instruction in the tape
+    pub profiled_addrs: usize,
+    pub breakpoints: usize,
+    pub watchpoints: usize,
+}
+
+///Caps on host memory growth, exceeding any of these is treated as a program error
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct ResourceLimits {
+    pub max_output_bytes: Option<usize>,
+    pub max_keyboard_buffer_bytes: Option<usize>,
+    pub max_stdin_buffer_bytes: Option<usize>,
+    pub max_serial_in_bytes: Option<usize>,
+    pub max_serial_out_bytes: Option<usize>,
+    pub max_call_frames: Option<usize>,
+}
+
+bitflags! {
+    ///Families of instructions a program is allowed to execute, running a denied instruction is a program error
+    pub struct Capabilities: u8 {
+        ///FOPEN, FSEEK, FSKIP, FILER, FILEW, FCHK, FTRUNC, FDEL, FLIST, SAVE, LOADKV
+        const FILE =  0b00000001;
+        ///IPOLL, RCHR, RSTR
+        const INPUT = 0b00000010;
+        ///TIME, MILLIS, SLEEP, ITIMER
+        const TIME =  0b00000100;
+        ///RAND, SEED
+        const RAND =  0b00001000;
+        ///DEBUG
+        const DEBUG = 0b00010000;
+        ///Jumps and calls to an address in a register
+        const EXEC =  0b00100000;
+        ///FOPEN with a file name
+        const PATH =  0b01000000;
+        ///SEND, RECV
+        const SERIAL = 0b10000000;
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities::all()
+    }
+}
+
+impl Capabilities {
+    pub const NAMES: [(&'static str, Capabilities); 8] = [
+        ("file", Capabilities::FILE),
+        ("input", Capabilities::INPUT),
+        ("time", Capabilities::TIME),
+        ("rand", Capabilities::RAND),
+        ("debug", Capabilities::DEBUG),
+        ("exec", Capabilities::EXEC),
+        ("path", Capabilities::PATH),
+        ("serial", Capabilities::SERIAL),
+    ];
+
+    ///Parse comma separated list of families to deny, e.g. `file,time`
+    pub fn from_deny_list(list: &str) -> Result<Capabilities> {
+        let mut capabilities = Capabilities::all();
+        for name in list.split(',').map(|name| name.trim().to_lowercase()) {
+            if name.is_empty() {
+                continue;
+            }
+            match Capabilities::NAMES.iter().find(|(key, _)| *key == name) {
+                Some((_, family)) => capabilities.remove(*family),
+                None => {
+                    return Err(Error::msg(format!(
+                        "Unknown capability '{}', must be one of: {}",
+                        name,
+                        Capabilities::NAMES
+                            .iter()
+                            .map(|(key, _)| *key)
+                            .collect::<Vec<&str>>()
+                            .join(", ")
+                    )))
+                }
+            }
+        }
+        Ok(capabilities)
+    }
+
+    ///Capabilities needed to execute `op`, empty if always allowed
+    pub fn required_for(op: Opcode) -> Capabilities {
+        match op {
+            Opcode::FopenRegStr
+            | Opcode::FopenValStr
+            | Opcode::FopenRegAreg
+            | Opcode::FopenValAreg => Capabilities::FILE | Capabilities::PATH,
+            Opcode::FopenReg
+            | Opcode::FopenVal
+            | Opcode::FseekReg
+            | Opcode::FseekVal
+            | Opcode::FtruncReg
+            | Opcode::FtruncVal
+            | Opcode::FdelReg
+            | Opcode::FdelVal
+            | Opcode::FlistAddr
+            | Opcode::FlistAreg
+            | Opcode::FskipRegReg
+            | Opcode::FskipRegVal
+            | Opcode::FskipValReg
+            | Opcode::FskipValVal
+            | Opcode::FilerRegAddr
+            | Opcode::FilerRegAreg
+            | Opcode::FilerValAddr
+            | Opcode::FilerValAreg
+            | Opcode::FilewRegAddr
+            | Opcode::FilewRegAreg
+            | Opcode::FilewValAddr
+            | Opcode::FilewValAreg
+            | Opcode::FilewRegReg
+            | Opcode::FilewRegVal
+            | Opcode::FilewValReg
+            | Opcode::FilewValVal
+            | Opcode::FilerRegAregAreg
+            | Opcode::FilerValAregAreg
+            | Opcode::FilewRegAregAreg
+            | Opcode::FilewValAregAreg
+            | Opcode::FchkRegAreg
+            | Opcode::FchkRegAddr
+            | Opcode::FchkValAreg
+            | Opcode::FchkValAddr
+            | Opcode::SaveStrAreg
+            | Opcode::LoadkvStrAreg => Capabilities::FILE,
+            Opcode::IpollAreg
+            | Opcode::IpollAddr
+            | Opcode::RchrReg
+            | Opcode::RstrAreg
+            | Opcode::RstrAddr => Capabilities::INPUT,
+            Opcode::Time
+            | Opcode::Millis
+            | Opcode::SleepReg
+            | Opcode::SleepVal
+            | Opcode::ItimerVal => Capabilities::TIME,
+            Opcode::RandReg | Opcode::SeedReg => Capabilities::RAND,
+            Opcode::SendReg | Opcode::SendVal | Opcode::RecvReg => Capabilities::SERIAL,
+            Opcode::Debug => Capabilities::DEBUG,
+            Opcode::JmpAreg
+            | Opcode::JeAreg
+            | Opcode::JneAreg
+            | Opcode::JlAreg
+            | Opcode::JgAreg
+            | Opcode::OverAreg
+            | Opcode::NoverAreg
+            | Opcode::CallAreg => Capabilities::EXEC,
+            _ => Capabilities::empty(),
+        }
+    }
+
+    ///Name of the first family in these flags
+    fn name(&self) -> &'static str {
+        Capabilities::NAMES
+            .iter()
+            .find(|(_, family)| self.contains(*family))
+            .map(|(name, _)| *name)
+            .unwrap_or("?")
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum RunResult {
     Pause,
     ///Breakpoint hit
     Breakpoint,
+    ///Watched memory was written to by the last instruction
+    Watchpoint,
     ///End of program
     EoF,
     ///Program error
     ProgError,
     //HALT instruction
     Halt,
+    ///EXIT instruction with the exit code
+    Exit(u8),
+    ///Executed the maximum number of instructions allowed
+    CycleLimit,
     CharInputRequested,
     StringInputRequested,
+    ///`FILER 254` executed with nothing in [`Device::stdin_buffer`]
+    StdinRequested,
+    ///RECV executed with nothing in [`Device::serial_in`]
+    SerialRequested,
 }
 
 impl Device {
@@ -58,53 +424,1483 @@ impl Device {
         Device {
             mem: [0; RAM_SIZE],
             flags: Flags::default(),
+            acc_compare: false,
+            narrow_data: false,
             acc: 0,
             data_reg: [0; DATA_REG_COUNT],
             addr_reg: [0; ADDR_REG_COUNT],
             pc: 0,
             sp: RAM_SIZE as u16,
             fp: RAM_SIZE as u16,
+            stack_limit: 0,
+            frames: vec![],
+            stack_base: RAM_SIZE as u16,
+            stack_check: false,
+            canary_writes: vec![],
             breakpoints: vec![],
+            watchpoints: vec![],
+            watchpoint_hit: None,
+            initialized: None,
+            op_starts: find_op_starts(&ops),
             tape_ops: ops,
             tape_strings: strings,
             tape_data: data,
             data_files,
             files,
-            rng: FastRng::new(),
+            file_paths: HashMap::new(),
+            file_provider: default_file_provider(),
+            rng: HostClock::rng(),
             keyboard_buffer: vec![],
+            stdin_buffer: vec![],
+            stdin_closed: false,
+            input_pending: None,
+            serial_in: vec![],
+            serial_out: vec![],
+            mmio: false,
+            mmio_millis: [0; 4],
             output: vec![],
+            output_bytes: 0,
+            output_sink: None,
+            limits: ResourceLimits::default(),
+            capabilities: Capabilities::default(),
+            allowed_paths: vec![],
+            read_only_files: vec![],
+            args: vec![],
+            storage: None,
+            cycles: 0,
+            max_cycles: None,
+            profile: None,
+            start_time: None,
+            clock: HostClock::new(),
+            slept_millis: 0,
+            interrupts: Interrupts::default(),
+        }
+    }
+
+    ///Replace the instruction at `addr` with `bytes`, any remaining bytes of the old instruction are replaced with NOPs
+    ///
+    ///Returns the number of NOPs added
+    pub fn patch_instruction(&mut self, addr: u16, bytes: &[u8]) -> Result<usize> {
+        let addr = addr as usize;
+        if addr >= self.tape_ops.len() {
+            return Err(Error::msg(format!(
+                "Can't patch {:04X}, outside of program",
+                addr
+            )));
+        }
+        let dispatch = match &DISPATCH[self.tape_ops[addr] as usize] {
+            Some(dispatch) if self.op_starts[addr] => dispatch,
+            _ => {
+                return Err(Error::msg(format!(
+                    "Can't patch {:04X}, not the start of an instruction",
+                    addr
+                )))
+            }
+        };
+        let existing = dispatch.len;
+        if addr + existing > self.tape_ops.len() {
+            return Err(Error::msg(format!(
+                "Can't patch {:04X}, instruction is cut short by the end of the program",
+                addr
+            )));
+        }
+        if bytes.is_empty() || bytes.len() > existing {
+            return Err(Error::msg(format!(
+                "Patch is {} bytes but instruction at {:04X} is {} bytes",
+                bytes.len(),
+                addr,
+                existing
+            )));
+        }
+        self.tape_ops[addr..addr + bytes.len()].copy_from_slice(bytes);
+        for byte in &mut self.tape_ops[addr + bytes.len()..addr + existing] {
+            *byte = NOP;
+        }
+        self.op_starts = find_op_starts(&self.tape_ops);
+        Ok(existing - bytes.len())
+    }
+
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    ///Map device I/O into memory from [`MMIO_ADDR`], see [`mmio_register_name`]
+    pub fn set_mmio(&mut self, enabled: bool) {
+        self.mmio = enabled;
+    }
+
+    pub fn is_mmio(&self, addr: u16) -> bool {
+        self.mmio && (MMIO_ADDR as usize..MMIO_ADDR as usize + MMIO_SIZE).contains(&(addr as usize))
+    }
+
+    ///Track which bytes of RAM have been written and emit [`Output::UninitializedRead`] when MEMR,
+    ///ARG, LVAR or MEMP read one that hasn't, each address is only reported once
+    pub fn set_strict(&mut self, enabled: bool) {
+        self.initialized = enabled.then(|| vec![false; RAM_SIZE]);
+    }
+
+    ///Surround the saved FP and return address pushed by CALL (and interrupts) with
+    ///[`STACK_CANARY`] bytes that RET checks, making frames 6 bytes instead of 4
+    ///
+    ///If a canary has changed RET fails, after emitting [`Output::StackCorrupted`] if the
+    ///instruction that overwrote it is known. This must be set before the program starts.
+    pub fn set_stack_check(&mut self, enabled: bool) {
+        self.stack_check = enabled;
+    }
+
+    ///Restrict FOPEN by name to files inside `paths`, which must already be canonical
+    pub fn set_allowed_paths(&mut self, paths: Vec<PathBuf>) {
+        self.allowed_paths = paths;
+    }
+
+    ///Open these file numbers read only so the program can't change them
+    pub fn set_read_only_files(&mut self, files: Vec<usize>) {
+        self.read_only_files = files;
+    }
+
+    ///Arguments the program reads with ARGS
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    ///File SAVE and LOADKV use, opened with the file provider, see [`crate::device::storage`]
+    pub fn set_save_file(&mut self, path: PathBuf) {
+        self.storage = Some(KvStore::new(path));
+    }
+
+    ///Replace where FOPEN opens files from, by default this is the filesystem (with the `fs` feature)
+    ///or in memory
+    pub fn set_file_provider(&mut self, provider: Box<dyn FileProvider>) {
+        self.file_provider = provider;
+    }
+
+    ///Send output to `sink` as it happens instead of keeping it in [`Device::output`]
+    pub fn set_output_sink(&mut self, sink: Box<dyn OutputSink>) {
+        self.output_sink = Some(sink);
+    }
+
+    ///Output since [`Device::take_output`] was last called, always empty if an output sink is set
+    pub fn output(&self) -> &[Output] {
+        &self.output
+    }
+
+    ///Remove and return the output since this was last called
+    pub fn take_output(&mut self) -> Vec<Output> {
+        self.output_bytes = 0;
+        std::mem::take(&mut self.output)
+    }
+
+    ///Add a key press for RCHR, RSTR or IPOLL
+    pub fn push_char(&mut self, chr: u8) {
+        self.keyboard_buffer.push(chr);
+    }
+
+    ///Add text for RCHR, RSTR or IPOLL
+    pub fn push_string(&mut self, text: &str) {
+        self.push_bytes(text.as_bytes());
+    }
+
+    ///Add raw bytes for RCHR, RSTR or IPOLL, e.g. from an input script
+    pub fn push_bytes(&mut self, bytes: &[u8]) {
+        self.keyboard_buffer.extend_from_slice(bytes);
+    }
+
+    ///Keys that haven't been read by the program yet
+    pub fn keyboard_buffer(&self) -> &[u8] {
+        &self.keyboard_buffer
+    }
+
+    ///Input the program is waiting for, if the last step stopped because it needs input
+    ///
+    ///Cleared when the device is stepped again
+    pub fn input_pending(&self) -> Option<InputKind> {
+        self.input_pending
+    }
+
+    ///Called when the host's stdin has ended
+    pub fn close_stdin(&mut self) {
+        self.stdin_closed = true;
+    }
+
+    pub fn set_resource_limits(&mut self, limits: ResourceLimits) {
+        self.limits = limits;
+    }
+
+    ///Match compare behaviour and data layout to the format the tape was built for
+    pub fn set_tape_version(&mut self, version: u8) {
+        self.acc_compare = version == ACC_COMPARE_PRG_VERSION;
+        self.narrow_data = version < WIDE_DATA_PRG_VERSION;
+    }
+
+    ///Start running at `addr` instead of the first op, for tapes using `.start`
+    pub fn set_entry(&mut self, addr: u16) {
+        self.pc = addr;
+    }
+
+    pub fn set_determinism(&mut self, determinism: Determinism) {
+        if let Some(seed) = determinism.seed {
+            self.rng = FastRng::seed(seed, seed.not());
+        }
+        self.start_time = determinism.start_time;
+    }
+
+    ///Stop the program with [`RunResult::CycleLimit`] after executing `max` instructions
+    pub fn set_max_cycles(&mut self, max: u64) {
+        self.max_cycles = Some(max);
+    }
+
+    ///Number of instructions executed
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    ///Start counting executions per opcode and address
+    pub fn enable_profiling(&mut self) {
+        self.profile = Some(Profile::default());
+    }
+
+    ///Counts collected since profiling was enabled
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    ///Remove every watchpoint that overlaps `start..=end`, so a single address clears any range
+    ///containing it
+    pub fn clear_watchpoints(&mut self, start: u16, end: u16) {
+        self.watchpoints
+            .retain(|(wp_start, wp_end)| *wp_end < start || *wp_start > end);
+    }
+
+    ///Set the lowest address the stack can grow down to, pushing past it is a program error
+    pub fn set_stack_limit(&mut self, limit: u16) {
+        self.stack_limit = limit;
+    }
+
+    pub fn resource_stats(&self) -> ResourceStats {
+        ResourceStats {
+            output_entries: self.output.len(),
+            output_bytes: self.output_bytes,
+            keyboard_buffer_bytes: self.keyboard_buffer.len(),
+            stdin_buffer_bytes: self.stdin_buffer.len(),
+            serial_in_bytes: self.serial_in.len(),
+            serial_out_bytes: self.serial_out.len(),
+            call_frames: self.frames.len(),
+            profiled_addrs: self
+                .profile
+                .as_ref()
+                .map_or(0, |profile| profile.addrs.len()),
+            breakpoints: self.breakpoints.len(),
+            watchpoints: self.watchpoints.len(),
         }
     }
+
+    fn check_limits(&self) -> Result<()> {
+        let buffers = [
+            (
+                "Keyboard buffer",
+                self.keyboard_buffer.len(),
+                self.limits.max_keyboard_buffer_bytes,
+            ),
+            (
+                "Stdin buffer",
+                self.stdin_buffer.len(),
+                self.limits.max_stdin_buffer_bytes,
+            ),
+            (
+                "Serial input",
+                self.serial_in.len(),
+                self.limits.max_serial_in_bytes,
+            ),
+            (
+                "Serial output",
+                self.serial_out.len(),
+                self.limits.max_serial_out_bytes,
+            ),
+        ];
+        for (name, bytes, max) in buffers {
+            if let Some(max) = max.filter(|max| bytes > *max) {
+                return Err(Error::msg(format!(
+                    "{} limit exceeded ({} bytes, max {})",
+                    name, bytes, max
+                )));
+            }
+        }
+        if let Some(max) = self.limits.max_call_frames {
+            if self.frames.len() > max {
+                return Err(Error::msg(format!(
+                    "Call frame limit exceeded ({} frames, max {})",
+                    self.frames.len(),
+                    max
+                )));
+            }
+        }
+        if let Some(max) = self.limits.max_output_bytes {
+            let bytes = self.output_bytes;
+            if bytes > max {
+                return Err(Error::msg(format!(
+                    "Output buffer limit exceeded ({} bytes, max {}), output must be drained",
+                    bytes, max
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct Flags {
+    ///Last compared values were equal
+    zero: bool,
+    ///Last compared lhs was less than rhs
+    carry: bool,
+    ///Top bit of last compared lhs - rhs
+    negative: bool,
+    ///Last ADD, SUB, INC or DEC wrapped
     overflow: bool,
 }
 
+impl Flags {
+    fn from_bits(bits: u8) -> Flags {
+        Flags {
+            zero: bits & FLAG_ZERO == FLAG_ZERO,
+            carry: bits & FLAG_CARRY == FLAG_CARRY,
+            negative: bits & FLAG_NEGATIVE == FLAG_NEGATIVE,
+            overflow: bits & FLAG_OVERFLOW == FLAG_OVERFLOW,
+        }
+    }
+
+    fn bits(&self) -> u8 {
+        let mut bits = 0;
+        if self.zero {
+            bits |= FLAG_ZERO;
+        }
+        if self.carry {
+            bits |= FLAG_CARRY;
+        }
+        if self.negative {
+            bits |= FLAG_NEGATIVE;
+        }
+        if self.overflow {
+            bits |= FLAG_OVERFLOW;
+        }
+        bits
+    }
+}
+
+///Function that runs an instruction, called with the bytes of the instruction (including the opcode)
+///
+///Returns [`RunResult::Pause`] if the program should continue, in which case the PC is moved to the
+///next instruction unless the op is a jump
+type Handler = fn(&mut Device, &Instruction) -> Result<RunResult>;
+
+///Bytes of the instruction at the PC, copied out of the tape once per step
+type Instruction = [u8; MAX_INSTRUCTION_BYTES];
+
+///Length of the longest instruction (LD)
+const MAX_INSTRUCTION_BYTES: usize = 6;
+
+///Everything needed to execute an opcode, precomputed so stepping doesn't have to match on the op
+#[derive(Clone, Copy)]
+struct Dispatch {
+    len: usize,
+    jump: bool,
+    required: Capabilities,
+    handler: Handler,
+}
+
+lazy_static! {
+    ///Indexed by opcode byte, `None` for unknown opcodes
+    static ref DISPATCH: [Option<Dispatch>; 256] = {
+        let mut table = [None; 256];
+        for op in Opcode::ALL {
+            table[*op as usize] = Some(Dispatch {
+                len: get_byte_count(*op as u8),
+                jump: is_jump_op(*op as u8),
+                required: Capabilities::required_for(*op),
+                handler: handler(*op),
+            });
+        }
+        table
+    };
+}
+
+///Handler for `op`, only called when building [`DISPATCH`]
+fn handler(op: Opcode) -> Handler {
+    match op {
+        Opcode::Nop => |_, _| Ok(Pause),
+        Opcode::AddRegReg => |dev, bytes| {
+            dev.add(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_reg_content(bytes[2])?,
+            );
+            Ok(Pause)
+        },
+        Opcode::AddRegVal => |dev, bytes| {
+            dev.add(dev.get_reg_content(bytes[1])?, bytes[2]);
+            Ok(Pause)
+        },
+        Opcode::AddRegAreg => |dev, bytes| {
+            dev.add(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_data_content(dev.get_addr_reg_content(bytes[2])?)?,
+            );
+            Ok(Pause)
+        },
+        Opcode::AddcRegReg => |dev, bytes| {
+            dev.add_carry(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_reg_content(bytes[2])?,
+            );
+            Ok(Pause)
+        },
+        Opcode::AddcRegVal => |dev, bytes| {
+            dev.add_carry(dev.get_reg_content(bytes[1])?, bytes[2]);
+            Ok(Pause)
+        },
+        Opcode::AddcRegAreg => |dev, bytes| {
+            dev.add_carry(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_data_content(dev.get_addr_reg_content(bytes[2])?)?,
+            );
+            Ok(Pause)
+        },
+        Opcode::SubcRegReg => |dev, bytes| {
+            dev.sub_carry(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_reg_content(bytes[2])?,
+            );
+            Ok(Pause)
+        },
+        Opcode::SubcRegVal => |dev, bytes| {
+            dev.sub_carry(dev.get_reg_content(bytes[1])?, bytes[2]);
+            Ok(Pause)
+        },
+        Opcode::SubcRegAreg => |dev, bytes| {
+            dev.sub_carry(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_data_content(dev.get_addr_reg_content(bytes[2])?)?,
+            );
+            Ok(Pause)
+        },
+        Opcode::SubRegReg => |dev, bytes| {
+            dev.sub(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_reg_content(bytes[2])?,
+            );
+            Ok(Pause)
+        },
+        Opcode::SubRegVal => |dev, bytes| {
+            dev.sub(dev.get_reg_content(bytes[1])?, bytes[2]);
+            Ok(Pause)
+        },
+        Opcode::SubRegAreg => |dev, bytes| {
+            dev.sub(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_data_content(dev.get_addr_reg_content(bytes[2])?)?,
+            );
+            Ok(Pause)
+        },
+        Opcode::MemrAddr => |dev, bytes| {
+            let value = dev.get_mem(addr(bytes[1], bytes[2]))?;
+            dev.set_data_reg(REG_ACC, value)?;
+            Ok(Pause)
+        },
+        Opcode::MemrAreg => |dev, bytes| {
+            let value = dev.get_mem(dev.get_addr_reg_content(bytes[1])?)?;
+            dev.set_data_reg(REG_ACC, value)?;
+            Ok(Pause)
+        },
+        Opcode::MemrAregReg => |dev, bytes| {
+            let value = dev.get_mem(dev.indexed_addr(bytes)?)?;
+            dev.set_data_reg(REG_ACC, value)?;
+            Ok(Pause)
+        },
+        Opcode::CpyRegVal => |dev, bytes| {
+            dev.set_data_reg(bytes[1], bytes[2])?;
+            Ok(Pause)
+        },
+        Opcode::CpyRegReg => |dev, bytes| {
+            dev.set_data_reg(bytes[1], dev.get_reg_content(bytes[2])?)?;
+            Ok(Pause)
+        },
+        Opcode::CpyAregRegReg => |dev, bytes| {
+            dev.copy_addr_reg(bytes[1], bytes[2], bytes[3])?;
+            Ok(Pause)
+        },
+        Opcode::CpyRegRegAreg => |dev, bytes| {
+            dev.load_addr_reg(bytes[3], bytes[1], bytes[2])?;
+            Ok(Pause)
+        },
+        Opcode::CpyAregAddr => |dev, bytes| {
+            dev.set_addr_reg(bytes[1], addr(bytes[2], bytes[3]))?;
+            Ok(Pause)
+        },
+        Opcode::LdiAregVal => |dev, bytes| {
+            dev.set_addr_reg(bytes[1], addr(bytes[2], bytes[3]))?;
+            Ok(Pause)
+        },
+        Opcode::CpyAregAreg => |dev, bytes| {
+            dev.set_addr_reg(bytes[1], dev.get_addr_reg_content(bytes[2])?)?;
+            Ok(Pause)
+        },
+        Opcode::MemwAddr => |dev, bytes| {
+            dev.store(addr(bytes[1], bytes[2]))?;
+            Ok(Pause)
+        },
+        Opcode::MemwAreg => |dev, bytes| {
+            dev.store(dev.get_addr_reg_content(bytes[1])?)?;
+            Ok(Pause)
+        },
+        Opcode::MemwAregReg => |dev, bytes| {
+            dev.store(dev.indexed_addr(bytes)?)?;
+            Ok(Pause)
+        },
+        Opcode::MemcpyAregAregReg => |dev, bytes| {
+            let dest = dev.get_addr_reg_content(bytes[1])?;
+            let src = dev.get_addr_reg_content(bytes[2])?;
+            let len = dev.get_reg_content(bytes[3])? as usize;
+            let src = dev.block_range(src, len)?;
+            let dest = dev.block_range(dest, len)?;
+            dev.mem.copy_within(src, dest.start);
+            dev.record_write(dest);
+            Ok(Pause)
+        },
+        Opcode::MemsetAregRegReg => |dev, bytes| {
+            let value = dev.get_reg_content(bytes[2])?;
+            dev.mem_set(bytes, value)?;
+            Ok(Pause)
+        },
+        Opcode::MemsetAregValReg => |dev, bytes| {
+            dev.mem_set(bytes, bytes[2])?;
+            Ok(Pause)
+        },
+        Opcode::JmpAreg => |dev, bytes| {
+            dev.jump(dev.get_addr_reg_content(bytes[1])?);
+            Ok(Pause)
+        },
+        Opcode::JeAreg => |dev, bytes| {
+            dev.cond_jump(dev.is_equal(), dev.get_addr_reg_content(bytes[1])?, JE_AREG);
+            Ok(Pause)
+        },
+        Opcode::JlAreg => |dev, bytes| {
+            dev.cond_jump(
+                dev.is_lesser(),
+                dev.get_addr_reg_content(bytes[1])?,
+                JL_AREG,
+            );
+            Ok(Pause)
+        },
+        Opcode::JgAreg => |dev, bytes| {
+            dev.cond_jump(
+                dev.is_greater(),
+                dev.get_addr_reg_content(bytes[1])?,
+                JG_AREG,
+            );
+            Ok(Pause)
+        },
+        Opcode::JneAreg => |dev, bytes| {
+            dev.cond_jump(
+                !dev.is_equal(),
+                dev.get_addr_reg_content(bytes[1])?,
+                JNE_AREG,
+            );
+            Ok(Pause)
+        },
+        Opcode::OverAreg => |dev, bytes| {
+            dev.cond_jump(
+                dev.flags.overflow,
+                dev.get_addr_reg_content(bytes[1])?,
+                OVER_AREG,
+            );
+            Ok(Pause)
+        },
+        Opcode::NoverAreg => |dev, bytes| {
+            dev.cond_jump(
+                !dev.flags.overflow,
+                dev.get_addr_reg_content(bytes[1])?,
+                NOVER_AREG,
+            );
+            Ok(Pause)
+        },
+        Opcode::JmpAddr => |dev, bytes| {
+            dev.jump(addr(bytes[1], bytes[2]));
+            Ok(Pause)
+        },
+        Opcode::JeAddr => |dev, bytes| {
+            dev.cond_jump(dev.is_equal(), addr(bytes[1], bytes[2]), JE_ADDR);
+            Ok(Pause)
+        },
+        Opcode::JlAddr => |dev, bytes| {
+            dev.cond_jump(dev.is_lesser(), addr(bytes[1], bytes[2]), JL_ADDR);
+            Ok(Pause)
+        },
+        Opcode::JgAddr => |dev, bytes| {
+            dev.cond_jump(dev.is_greater(), addr(bytes[1], bytes[2]), JG_ADDR);
+            Ok(Pause)
+        },
+        Opcode::JneAddr => |dev, bytes| {
+            dev.cond_jump(!dev.is_equal(), addr(bytes[1], bytes[2]), JNE_ADDR);
+            Ok(Pause)
+        },
+        Opcode::OverAddr => |dev, bytes| {
+            dev.cond_jump(dev.flags.overflow, addr(bytes[1], bytes[2]), OVER_ADDR);
+            Ok(Pause)
+        },
+        Opcode::NoverAddr => |dev, bytes| {
+            dev.cond_jump(!dev.flags.overflow, addr(bytes[1], bytes[2]), NOVER_ADDR);
+            Ok(Pause)
+        },
+        Opcode::IncReg => |dev, bytes| {
+            dev.change(bytes[1], 1)?;
+            Ok(Pause)
+        },
+        Opcode::DecReg => |dev, bytes| {
+            dev.change(bytes[1], -1)?;
+            Ok(Pause)
+        },
+        Opcode::CmpRegReg => |dev, bytes| {
+            dev.compare(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_reg_content(bytes[2])?,
+            );
+            Ok(Pause)
+        },
+        Opcode::CmpRegVal => |dev, bytes| {
+            dev.compare(dev.get_reg_content(bytes[1])?, bytes[2]);
+            Ok(Pause)
+        },
+        Opcode::CmpAregAddr => |dev, bytes| {
+            dev.compare_16(
+                dev.get_addr_reg_content(bytes[1])?,
+                addr(bytes[2], bytes[3]),
+            );
+            Ok(Pause)
+        },
+        Opcode::CmpAregAreg => |dev, bytes| {
+            dev.compare_16(
+                dev.get_addr_reg_content(bytes[1])?,
+                dev.get_addr_reg_content(bytes[2])?,
+            );
+            Ok(Pause)
+        },
+        Opcode::CmpAregRegReg => |dev, bytes| {
+            dev.compare_16(
+                dev.get_addr_reg_content(bytes[1])?,
+                addr(
+                    dev.get_reg_content(bytes[2])?,
+                    dev.get_reg_content(bytes[3])?,
+                ),
+            );
+            Ok(Pause)
+        },
+        Opcode::CmpRegRegAreg => |dev, bytes| {
+            dev.compare_16(
+                addr(
+                    dev.get_reg_content(bytes[1])?,
+                    dev.get_reg_content(bytes[2])?,
+                ),
+                dev.get_addr_reg_content(bytes[3])?,
+            );
+            Ok(Pause)
+        },
+        Opcode::PrtReg => |dev, bytes| {
+            dev.print(dev.get_reg_content(bytes[1])?);
+            Ok(Pause)
+        },
+        Opcode::PrtVal => |dev, bytes| {
+            dev.print(bytes[1]);
+            Ok(Pause)
+        },
+        Opcode::PrtcReg => |dev, bytes| {
+            dev.printc(dev.get_reg_content(bytes[1])?);
+            Ok(Pause)
+        },
+        Opcode::PrtcVal => |dev, bytes| {
+            dev.printc(bytes[1]);
+            Ok(Pause)
+        },
+        Opcode::PrtAreg => |dev, bytes| {
+            dev.print(dev.get_data_content(dev.get_addr_reg_content(bytes[1])?)?);
+            Ok(Pause)
+        },
+        Opcode::PrtxReg => |dev, bytes| {
+            dev.printx(dev.get_reg_content(bytes[1])?);
+            Ok(Pause)
+        },
+        Opcode::PrtxVal => |dev, bytes| {
+            dev.printx(bytes[1]);
+            Ok(Pause)
+        },
+        Opcode::PrtxAreg => |dev, bytes| {
+            dev.printx(dev.get_data_content(dev.get_addr_reg_content(bytes[1])?)?);
+            Ok(Pause)
+        },
+        Opcode::PrtcAreg => |dev, bytes| {
+            dev.printc(dev.get_data_content(dev.get_addr_reg_content(bytes[1])?)?);
+            Ok(Pause)
+        },
+        Opcode::Prtln => |dev, _| {
+            dev.emit(OutputStd(String::from("\n")));
+            Ok(Pause)
+        },
+        Opcode::PrtsStr => |dev, bytes| {
+            dev.print_tape_string(addr(bytes[1], bytes[2]))?;
+            Ok(Pause)
+        },
+        Opcode::PrtsAreg => |dev, bytes| {
+            dev.print_tape_string(dev.get_addr_reg_content(bytes[1])?)?;
+            Ok(Pause)
+        },
+        Opcode::ItoaAregReg => |dev, bytes| {
+            let value = dev.get_any_reg_content(bytes[2])?;
+            dev.itoa(bytes[1], value)?;
+            Ok(Pause)
+        },
+        Opcode::ItoaAregVal => |dev, bytes| {
+            dev.itoa(bytes[1], bytes[2] as u16)?;
+            Ok(Pause)
+        },
+        Opcode::AtoiAregReg => |dev, bytes| {
+            dev.atoi(bytes[1], bytes[2])?;
+            Ok(Pause)
+        },
+        Opcode::StrldAregStr => |dev, bytes| {
+            let range = dev.tape_string_range(addr(bytes[2], bytes[3]))?;
+            dev.load_tape_bytes(bytes[1], range, false)?;
+            Ok(Pause)
+        },
+        Opcode::StrldAregAreg => |dev, bytes| {
+            let range = dev.tape_string_range(dev.get_addr_reg_content(bytes[2])?)?;
+            dev.load_tape_bytes(bytes[1], range, false)?;
+            Ok(Pause)
+        },
+        Opcode::StrldAregDataVal => |dev, bytes| {
+            let range = dev.data_array_range(addr(bytes[2], bytes[3]), bytes[4])?;
+            dev.load_tape_bytes(bytes[1], range, true)?;
+            Ok(Pause)
+        },
+        Opcode::StrldAregDataReg => |dev, bytes| {
+            let array = dev.get_reg_content(bytes[4])?;
+            let range = dev.data_array_range(addr(bytes[2], bytes[3]), array)?;
+            dev.load_tape_bytes(bytes[1], range, true)?;
+            Ok(Pause)
+        },
+        Opcode::FopenReg => |dev, bytes| {
+            dev.open_file(dev.get_reg_content(bytes[1])? as usize)?;
+            Ok(Pause)
+        },
+        Opcode::FilerRegAddr => |dev, bytes| {
+            if !dev.read_file(
+                dev.get_reg_content(bytes[1])? as usize,
+                addr(bytes[2], bytes[3]),
+            )? {
+                return Ok(RunResult::StdinRequested);
+            }
+            Ok(Pause)
+        },
+        Opcode::FilerRegAreg => |dev, bytes| {
+            if !dev.read_file(
+                dev.get_reg_content(bytes[1])? as usize,
+                dev.get_addr_reg_content(bytes[2])?,
+            )? {
+                return Ok(RunResult::StdinRequested);
+            }
+            Ok(Pause)
+        },
+        Opcode::FilewRegAreg => |dev, bytes| {
+            dev.write_file(
+                dev.get_reg_content(bytes[1])? as usize,
+                dev.get_addr_reg_content(bytes[2])?,
+            )?;
+            Ok(Pause)
+        },
+        Opcode::FilewRegAddr => |dev, bytes| {
+            dev.write_file(
+                dev.get_reg_content(bytes[1])? as usize,
+                addr(bytes[2], bytes[3]),
+            )?;
+            Ok(Pause)
+        },
+        Opcode::FseekReg => |dev, bytes| {
+            dev.seek_file_stack(dev.get_reg_content(bytes[1])? as usize)?;
+            Ok(Pause)
+        },
+        Opcode::FskipRegReg => |dev, bytes| {
+            dev.skip_file(
+                dev.get_reg_content(bytes[1])? as usize,
+                dev.get_reg_content(bytes[2])?,
+            )?;
+            Ok(Pause)
+        },
+        Opcode::FskipRegVal => |dev, bytes| {
+            dev.skip_file(dev.get_reg_content(bytes[1])? as usize, bytes[2])?;
+            Ok(Pause)
+        },
+        Opcode::FopenVal => |dev, bytes| {
+            dev.open_file(bytes[1] as usize)?;
+            Ok(Pause)
+        },
+        Opcode::FopenRegStr => |dev, bytes| {
+            let path = dev.tape_string(addr(bytes[2], bytes[3]))?;
+            dev.open_named_file(dev.get_reg_content(bytes[1])? as usize, path)?;
+            Ok(Pause)
+        },
+        Opcode::FopenValStr => |dev, bytes| {
+            let path = dev.tape_string(addr(bytes[2], bytes[3]))?;
+            dev.open_named_file(bytes[1] as usize, path)?;
+            Ok(Pause)
+        },
+        Opcode::FopenRegAreg => |dev, bytes| {
+            let path = dev.mem_string(dev.get_addr_reg_content(bytes[2])?)?;
+            dev.open_named_file(dev.get_reg_content(bytes[1])? as usize, path)?;
+            Ok(Pause)
+        },
+        Opcode::FopenValAreg => |dev, bytes| {
+            let path = dev.mem_string(dev.get_addr_reg_content(bytes[2])?)?;
+            dev.open_named_file(bytes[1] as usize, path)?;
+            Ok(Pause)
+        },
+        Opcode::FilerValAddr => |dev, bytes| {
+            if !dev.read_file(bytes[1] as usize, addr(bytes[2], bytes[3]))? {
+                return Ok(RunResult::StdinRequested);
+            }
+            Ok(Pause)
+        },
+        Opcode::FilerValAreg => |dev, bytes| {
+            if !dev.read_file(bytes[1] as usize, dev.get_addr_reg_content(bytes[2])?)? {
+                return Ok(RunResult::StdinRequested);
+            }
+            Ok(Pause)
+        },
+        Opcode::FilewValAreg => |dev, bytes| {
+            dev.write_file(bytes[1] as usize, dev.get_addr_reg_content(bytes[2])?)?;
+            Ok(Pause)
+        },
+        Opcode::FilewValAddr => |dev, bytes| {
+            dev.write_file(bytes[1] as usize, addr(bytes[2], bytes[3]))?;
+            Ok(Pause)
+        },
+        Opcode::FilewRegReg => |dev, bytes| {
+            dev.write_file_value(
+                dev.get_reg_content(bytes[1])? as usize,
+                dev.get_reg_content(bytes[2])?,
+            )?;
+            Ok(Pause)
+        },
+        Opcode::FilewRegVal => |dev, bytes| {
+            dev.write_file_value(dev.get_reg_content(bytes[1])? as usize, bytes[2])?;
+            Ok(Pause)
+        },
+        Opcode::FilewValReg => |dev, bytes| {
+            dev.write_file_value(bytes[1] as usize, dev.get_reg_content(bytes[2])?)?;
+            Ok(Pause)
+        },
+        Opcode::FilewValVal => |dev, bytes| {
+            dev.write_file_value(bytes[1] as usize, bytes[2])?;
+            Ok(Pause)
+        },
+        Opcode::FilerRegAregAreg => |dev, bytes| {
+            if !dev.read_file_block(
+                dev.get_reg_content(bytes[1])? as usize,
+                dev.get_addr_reg_content(bytes[2])?,
+                dev.get_addr_reg_content(bytes[3])?,
+            )? {
+                return Ok(RunResult::StdinRequested);
+            }
+            Ok(Pause)
+        },
+        Opcode::FilerValAregAreg => |dev, bytes| {
+            if !dev.read_file_block(
+                bytes[1] as usize,
+                dev.get_addr_reg_content(bytes[2])?,
+                dev.get_addr_reg_content(bytes[3])?,
+            )? {
+                return Ok(RunResult::StdinRequested);
+            }
+            Ok(Pause)
+        },
+        Opcode::FilewRegAregAreg => |dev, bytes| {
+            dev.write_file_block(
+                dev.get_reg_content(bytes[1])? as usize,
+                dev.get_addr_reg_content(bytes[2])?,
+                dev.get_addr_reg_content(bytes[3])?,
+            )?;
+            Ok(Pause)
+        },
+        Opcode::FilewValAregAreg => |dev, bytes| {
+            dev.write_file_block(
+                bytes[1] as usize,
+                dev.get_addr_reg_content(bytes[2])?,
+                dev.get_addr_reg_content(bytes[3])?,
+            )?;
+            Ok(Pause)
+        },
+        Opcode::FseekVal => |dev, bytes| {
+            dev.seek_file(bytes[1] as usize)?;
+            Ok(Pause)
+        },
+        Opcode::FtruncReg => |dev, bytes| {
+            dev.truncate_file(dev.get_reg_content(bytes[1])? as usize)?;
+            Ok(Pause)
+        },
+        Opcode::FtruncVal => |dev, bytes| {
+            dev.truncate_file(bytes[1] as usize)?;
+            Ok(Pause)
+        },
+        Opcode::FdelReg => |dev, bytes| {
+            dev.delete_file(dev.get_reg_content(bytes[1])? as usize)?;
+            Ok(Pause)
+        },
+        Opcode::FdelVal => |dev, bytes| {
+            dev.delete_file(bytes[1] as usize)?;
+            Ok(Pause)
+        },
+        Opcode::FlistAddr => |dev, bytes| {
+            dev.list_files(addr(bytes[1], bytes[2]))?;
+            Ok(Pause)
+        },
+        Opcode::FlistAreg => |dev, bytes| {
+            dev.list_files(dev.get_addr_reg_content(bytes[1])?)?;
+            Ok(Pause)
+        },
+        Opcode::FskipValReg => |dev, bytes| {
+            dev.skip_file(bytes[1] as usize, dev.get_reg_content(bytes[2])?)?;
+            Ok(Pause)
+        },
+        Opcode::FskipValVal => |dev, bytes| {
+            dev.skip_file(bytes[1] as usize, bytes[2])?;
+            Ok(Pause)
+        },
+        Opcode::Halt => |_, _| Ok(RunResult::Halt),
+        Opcode::ExitReg => |dev, bytes| Ok(RunResult::Exit(dev.get_reg_content(bytes[1])?)),
+        Opcode::ExitVal => |_, bytes| Ok(RunResult::Exit(bytes[1])),
+        Opcode::PushVal => |dev, bytes| {
+            dev.stack_push(bytes[1])?;
+            Ok(Pause)
+        },
+        Opcode::PushReg => |dev, bytes| {
+            dev.stack_push_reg(bytes[1])?;
+            Ok(Pause)
+        },
+        Opcode::PopReg => |dev, bytes| {
+            dev.stack_pop(bytes[1])?;
+            Ok(Pause)
+        },
+        Opcode::PopVal => |dev, bytes| {
+            dev.stack_drop(bytes[1])?;
+            Ok(Pause)
+        },
+        Opcode::LocalVal => |dev, bytes| {
+            dev.stack_reserve(bytes[1])?;
+            Ok(Pause)
+        },
+        Opcode::LvarRegVal => |dev, bytes| {
+            dev.local_read(bytes[1], bytes[2])?;
+            Ok(Pause)
+        },
+        Opcode::LvarValReg => |dev, bytes| {
+            dev.local_write(bytes[1], bytes[2])?;
+            Ok(Pause)
+        },
+        Opcode::ArgRegVal => |dev, bytes| {
+            dev.stack_arg(bytes[1], bytes[2])?;
+            Ok(Pause)
+        },
+        Opcode::ArgRegReg => |dev, bytes| {
+            dev.stack_arg(bytes[1], dev.get_reg_content(bytes[2])?)?;
+            Ok(Pause)
+        },
+        Opcode::Ret => |dev, _| {
+            dev.stack_return()?;
+            Ok(Pause)
+        },
+        Opcode::Reti => |dev, _| {
+            dev.interrupt_return()?;
+            Ok(Pause)
+        },
+        Opcode::InthValAddr => |dev, bytes| {
+            dev.set_interrupt_handler(bytes[1], addr(bytes[2], bytes[3]))?;
+            Ok(Pause)
+        },
+        Opcode::ImaskVal => |dev, bytes| {
+            dev.interrupts.mask = bytes[1];
+            Ok(Pause)
+        },
+        Opcode::ItimerVal => |dev, bytes| {
+            dev.set_timer(bytes[1]);
+            Ok(Pause)
+        },
+        Opcode::CallAddr => |dev, bytes| {
+            dev.stack_call(addr(bytes[1], bytes[2]), false)?;
+            Ok(Pause)
+        },
+        Opcode::CallAreg => |dev, bytes| {
+            dev.stack_call(dev.get_addr_reg_content(bytes[1])?, true)?;
+            Ok(Pause)
+        },
+        Opcode::SwpRegReg | Opcode::SwpAregAreg => |dev, bytes| {
+            dev.swap(bytes[1], bytes[2])?;
+            Ok(Pause)
+        },
+        Opcode::IpollAddr => |dev, bytes| {
+            dev.poll_input(addr(bytes[1], bytes[2]), false)?;
+            Ok(Pause)
+        },
+        Opcode::IpollAreg => |dev, bytes| {
+            dev.poll_input(dev.get_addr_reg_content(bytes[1])?, true)?;
+            Ok(Pause)
+        },
+        Opcode::RchrReg => |dev, bytes| {
+            if !dev.read_char(bytes[1])? {
+                return Ok(RunResult::CharInputRequested);
+            }
+            Ok(Pause)
+        },
+        Opcode::SendReg => |dev, bytes| {
+            let value = dev.get_reg_content(bytes[1])?;
+            dev.serial_out.push(value);
+            Ok(Pause)
+        },
+        Opcode::SendVal => |dev, bytes| {
+            dev.serial_out.push(bytes[1]);
+            Ok(Pause)
+        },
+        Opcode::RecvReg => |dev, bytes| {
+            if dev.serial_in.is_empty() {
+                return Ok(RunResult::SerialRequested);
+            }
+            let value = dev.serial_in.remove(0);
+            dev.set_data_reg(bytes[1], value)?;
+            Ok(Pause)
+        },
+        Opcode::RstrAddr => |dev, bytes| {
+            if !dev.read_string(addr(bytes[1], bytes[2]))? {
+                return Ok(RunResult::StringInputRequested);
+            }
+            Ok(Pause)
+        },
+        Opcode::RstrAreg => |dev, bytes| {
+            if !dev.read_string(dev.get_addr_reg_content(bytes[1])?)? {
+                return Ok(RunResult::StringInputRequested);
+            }
+            Ok(Pause)
+        },
+        Opcode::ArgsAddr => |dev, bytes| {
+            dev.load_args(addr(bytes[1], bytes[2]))?;
+            Ok(Pause)
+        },
+        Opcode::ArgsAreg => |dev, bytes| {
+            dev.load_args(dev.get_addr_reg_content(bytes[1])?)?;
+            Ok(Pause)
+        },
+        Opcode::SaveStrAreg => |dev, bytes| {
+            let key = dev.tape_string(addr(bytes[1], bytes[2]))?;
+            dev.save_value(key, dev.get_addr_reg_content(bytes[3])?)?;
+            Ok(Pause)
+        },
+        Opcode::LoadkvStrAreg => |dev, bytes| {
+            let key = dev.tape_string(addr(bytes[1], bytes[2]))?;
+            dev.load_value(&key, dev.get_addr_reg_content(bytes[3])?)?;
+            Ok(Pause)
+        },
+        Opcode::MempAddr => |dev, bytes| {
+            dev.print_string(addr(bytes[1], bytes[2]))?;
+            Ok(Pause)
+        },
+        Opcode::MempAreg => |dev, bytes| {
+            dev.print_string(dev.get_addr_reg_content(bytes[1])?)?;
+            Ok(Pause)
+        },
+        Opcode::FchkRegAddr => |dev, bytes| {
+            dev.cond_jump(
+                dev.file_available(dev.get_reg_content(bytes[1])? as usize),
+                addr(bytes[2], bytes[3]),
+                FCHK_REG_ADDR,
+            );
+            Ok(Pause)
+        },
+        Opcode::FchkRegAreg => |dev, bytes| {
+            dev.cond_jump(
+                dev.file_available(dev.get_reg_content(bytes[1])? as usize),
+                dev.get_addr_reg_content(bytes[2])?,
+                FCHK_REG_AREG,
+            );
+            Ok(Pause)
+        },
+        Opcode::FchkValAddr => |dev, bytes| {
+            dev.cond_jump(
+                dev.file_available(bytes[1] as usize),
+                addr(bytes[2], bytes[3]),
+                FCHK_VAL_ADDR,
+            );
+            Ok(Pause)
+        },
+        Opcode::FchkValAreg => |dev, bytes| {
+            dev.cond_jump(
+                dev.file_available(bytes[1] as usize),
+                dev.get_addr_reg_content(bytes[2])?,
+                FCHK_VAL_AREG,
+            );
+            Ok(Pause)
+        },
+        Opcode::Time => |dev, _| {
+            dev.set_time();
+            Ok(Pause)
+        },
+        Opcode::Millis => |dev, _| {
+            dev.set_millis();
+            Ok(Pause)
+        },
+        Opcode::Cls => |dev, _| {
+            dev.emit(ClearScreen);
+            Ok(Pause)
+        },
+        Opcode::Vsync => |dev, _| {
+            dev.emit(Vsync);
+            Ok(Pause)
+        },
+        Opcode::CursRegReg => |dev, bytes| {
+            dev.emit(MoveCursor(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_reg_content(bytes[2])?,
+            ));
+            Ok(Pause)
+        },
+        Opcode::CursRegVal => |dev, bytes| {
+            dev.emit(MoveCursor(dev.get_reg_content(bytes[1])?, bytes[2]));
+            Ok(Pause)
+        },
+        Opcode::CursValReg => |dev, bytes| {
+            dev.emit(MoveCursor(bytes[1], dev.get_reg_content(bytes[2])?));
+            Ok(Pause)
+        },
+        Opcode::CursValVal => |dev, bytes| {
+            dev.emit(MoveCursor(bytes[1], bytes[2]));
+            Ok(Pause)
+        },
+        Opcode::ColorReg => |dev, bytes| {
+            dev.set_color(dev.get_reg_content(bytes[1])?);
+            Ok(Pause)
+        },
+        Opcode::ColorVal => |dev, bytes| {
+            dev.set_color(bytes[1]);
+            Ok(Pause)
+        },
+        Opcode::SleepReg => |dev, bytes| {
+            dev.sleep(dev.get_reg_content(bytes[1])?);
+            Ok(Pause)
+        },
+        Opcode::SleepVal => |dev, bytes| {
+            dev.sleep(bytes[1]);
+            Ok(Pause)
+        },
+        Opcode::RandReg => |dev, bytes| {
+            dev.rand(bytes[1])?;
+            Ok(Pause)
+        },
+        Opcode::SeedReg => |dev, bytes| {
+            dev.seed(dev.get_reg_content(bytes[1])?)?;
+            Ok(Pause)
+        },
+        Opcode::AndRegReg => |dev, bytes| {
+            dev.bit_and(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_reg_content(bytes[2])?,
+            );
+            Ok(Pause)
+        },
+        Opcode::AndRegVal => |dev, bytes| {
+            dev.bit_and(dev.get_reg_content(bytes[1])?, bytes[2]);
+            Ok(Pause)
+        },
+        Opcode::AndRegAreg => |dev, bytes| {
+            dev.bit_and(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_data_content(dev.get_addr_reg_content(bytes[2])?)?,
+            );
+            Ok(Pause)
+        },
+        Opcode::OrRegReg => |dev, bytes| {
+            dev.bit_or(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_reg_content(bytes[2])?,
+            );
+            Ok(Pause)
+        },
+        Opcode::OrRegVal => |dev, bytes| {
+            dev.bit_or(dev.get_reg_content(bytes[1])?, bytes[2]);
+            Ok(Pause)
+        },
+        Opcode::OrRegAreg => |dev, bytes| {
+            dev.bit_or(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_data_content(dev.get_addr_reg_content(bytes[2])?)?,
+            );
+            Ok(Pause)
+        },
+        Opcode::XorRegReg => |dev, bytes| {
+            dev.bit_xor(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_reg_content(bytes[2])?,
+            );
+            Ok(Pause)
+        },
+        Opcode::XorRegVal => |dev, bytes| {
+            dev.bit_xor(dev.get_reg_content(bytes[1])?, bytes[2]);
+            Ok(Pause)
+        },
+        Opcode::XorRegAreg => |dev, bytes| {
+            dev.bit_xor(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_data_content(dev.get_addr_reg_content(bytes[2])?)?,
+            );
+            Ok(Pause)
+        },
+        Opcode::NotReg => |dev, bytes| {
+            dev.bit_not(dev.get_reg_content(bytes[1])?);
+            Ok(Pause)
+        },
+        Opcode::LdAregDataValVal => |dev, bytes| {
+            dev.load_data_addr(bytes[1], addr(bytes[2], bytes[3]), bytes[4], bytes[5])?;
+            Ok(Pause)
+        },
+        Opcode::LdAregDataValReg => |dev, bytes| {
+            dev.load_data_addr(
+                bytes[1],
+                addr(bytes[2], bytes[3]),
+                bytes[4],
+                dev.get_reg_content(bytes[5])?,
+            )?;
+            Ok(Pause)
+        },
+        Opcode::LdAregDataRegVal => |dev, bytes| {
+            dev.load_data_addr(
+                bytes[1],
+                addr(bytes[2], bytes[3]),
+                dev.get_reg_content(bytes[4])?,
+                bytes[5],
+            )?;
+            Ok(Pause)
+        },
+        Opcode::LdAregDataRegReg => |dev, bytes| {
+            dev.load_data_addr(
+                bytes[1],
+                addr(bytes[2], bytes[3]),
+                dev.get_reg_content(bytes[4])?,
+                dev.get_reg_content(bytes[5])?,
+            )?;
+            Ok(Pause)
+        },
+        Opcode::CpyRegAreg => |dev, bytes| {
+            dev.load_data(bytes[1], bytes[2])?;
+            Ok(Pause)
+        },
+        Opcode::CmpRegAreg => |dev, bytes| {
+            dev.compare_data(
+                dev.get_reg_content(bytes[1])?,
+                dev.get_addr_reg_content(bytes[2])?,
+            )?;
+            Ok(Pause)
+        },
+        Opcode::PrtdAreg => |dev, bytes| {
+            dev.print_data(bytes[1])?;
+            Ok(Pause)
+        },
+        Opcode::Debug => {
+            |dev, _| {
+                let dump = dev.dump();
+                dev.log(format!(
+                    "ACC: {:02X}  D0: {:02X}  D1: {:02X}  D2: {:02X}  D3: {:02X} A0: {:04X} A1: {:04X}",
+                    dump.acc, dump.data_reg[0], dump.data_reg[1], dump.data_reg[2], dump.data_reg[3], dump.addr_reg[0], dump.addr_reg[1]
+                ));
+                dev.log(format!(
+                    "PC: {:4} SP: {:4X} FP: {:4X} Flags: {}",
+                    dump.pc,
+                    dump.sp,
+                    dump.fp,
+                    dump.flags_text()
+                ));
+                dev.log(format!(
+                    "Stack ({:4X}..FFFF): {:?}",
+                    dump.sp,
+                    &dev.mem[dump.sp as usize..0xFFFF]
+                ));
+                Ok(Pause)
+            }
+        }
+    }
+}
+
 impl Device {
     ///Execute next instruction
     pub fn step(&mut self, ignore_breakpoints: bool) -> RunResult {
+        let result = self.step_op(ignore_breakpoints);
+        self.input_pending = match result {
+            RunResult::CharInputRequested => Some(InputKind::Char),
+            RunResult::StringInputRequested => Some(InputKind::String),
+            RunResult::StdinRequested => Some(InputKind::Stdin),
+            RunResult::SerialRequested => Some(InputKind::Serial),
+            _ => None,
+        };
+        if result != Pause {
+            self.flush_files();
+        }
+        result
+    }
+
+    ///Write anything buffered by FILEW to the files, this is done whenever the program stops
+    pub fn flush_files(&mut self) {
+        let errors: Vec<String> = self
+            .files
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(num, file)| {
+                let err = file.as_mut()?.flush().err()?;
+                Some(format!("Unable to write file {}: {}", num, err))
+            })
+            .collect();
+        for err in errors {
+            self.elog(err);
+        }
+    }
+
+    fn step_op(&mut self, ignore_breakpoints: bool) -> RunResult {
         if self.pc as usize >= self.tape_ops.len() {
             return EoF;
         }
         if !ignore_breakpoints && self.breakpoints.contains(&self.pc) {
-            self.output.push(Output::BreakpointHit(self.pc));
+            self.emit(Output::BreakpointHit(self.pc));
             return Breakpoint;
         }
-        self.execute()
+        if let Err(err) = self.check_limits() {
+            self.elog(format!("\nFatal error at byte {}:", self.pc));
+            self.elog(format!("{}", err));
+            return ProgError;
+        }
+        if let Some(max) = self.max_cycles {
+            if self.cycles >= max {
+                self.elog(format!(
+                    "\nCycle limit of {} reached at byte {}",
+                    max, self.pc
+                ));
+                return CycleLimit;
+            }
+        }
+        if let Err(err) = self.check_interrupts() {
+            self.elog(format!("\nFatal error at byte {}:", self.pc));
+            self.elog(format!("{}", err));
+            return ProgError;
+        }
+        if self.pc as usize >= self.tape_ops.len() {
+            return EoF;
+        }
+        self.watchpoint_hit = None;
+        self.cycles += 1;
+        if let Some(profile) = &mut self.profile {
+            profile.record(self.pc, self.tape_ops[self.pc as usize]);
+        }
+        let result = self.execute();
+        if let (Some(addr), Pause, false) = (self.watchpoint_hit, &result, ignore_breakpoints) {
+            self.emit(Output::WatchpointHit(addr));
+            return Watchpoint;
+        }
+        result
+    }
+
+    ///Raise timer and input interrupts and call the handler of the lowest unmasked pending interrupt
+    ///
+    ///Handlers are called like CALL with every interrupt masked until RETI
+    fn check_interrupts(&mut self) -> Result<()> {
+        let interrupts = &self.interrupts;
+        if interrupts.timer_period > 0 && interrupts.handlers[INT_TIMER as usize].is_some() {
+            let now = self.current_millis();
+            if now >= self.interrupts.next_tick {
+                self.interrupts.pending |= 1 << INT_TIMER;
+                self.interrupts.next_tick = now + self.interrupts.timer_period as u64;
+            }
+        }
+        if !self.keyboard_buffer.is_empty()
+            && self.interrupts.handlers[INT_INPUT as usize].is_some()
+        {
+            self.interrupts.pending |= 1 << INT_INPUT;
+        }
+        let ready = self.interrupts.pending & !self.interrupts.mask;
+        if ready == 0 {
+            return Ok(());
+        }
+        let interrupt = ready.trailing_zeros() as usize;
+        self.interrupts.pending &= !(1 << interrupt);
+        if let Some(addr) = self.interrupts.handlers[interrupt] {
+            //interrupted code didn't push arguments for the handler
+            self.push_frame(self.pc, addr, 0)?;
+            self.interrupts.saved_masks.push(self.interrupts.mask);
+            self.interrupts.mask = INT_MASK_ALL;
+        }
+        Ok(())
+    }
+
+    fn set_interrupt_handler(&mut self, interrupt: u8, addr: u16) -> Result<()> {
+        self.check_jump_target(addr)?;
+        match self.interrupts.handlers.get_mut(interrupt as usize) {
+            Some(handler) => {
+                *handler = Some(addr);
+                Ok(())
+            }
+            None => Err(Error::msg(format!(
+                "Invalid interrupt {}, max {}",
+                interrupt,
+                INTERRUPT_COUNT - 1
+            ))),
+        }
+    }
+
+    fn set_timer(&mut self, millis: u8) {
+        self.interrupts.timer_period = millis;
+        self.interrupts.next_tick = self.current_millis() + millis as u64;
+    }
+
+    fn interrupt_return(&mut self) -> Result<()> {
+        match self.interrupts.saved_masks.pop() {
+            Some(mask) => {
+                self.stack_return()?;
+                self.interrupts.mask = mask;
+                Ok(())
+            }
+            None => Err(Error::msg("RETI used outside of an interrupt handler")),
+        }
+    }
+
+    fn emit(&mut self, output: Output) {
+        match &mut self.output_sink {
+            Some(sink) => sink.push(output),
+            None => {
+                self.output_bytes += match &output {
+                    OutputStd(text) | OutputErr(text) => text.len(),
+                    OutputBinary(bytes) => bytes.len(),
+                    BreakpointHit(_)
+                    | WatchpointHit(_)
+                    | UninitializedRead(..)
+                    | StackCorrupted(..)
+                    | ClearScreen
+                    | MoveCursor(..)
+                    | SetColor(_)
+                    | Vsync => 0,
+                };
+                self.output.push(output)
+            }
+        }
     }
 
     fn log(&mut self, msg: String) {
-        self.output.push(OutputStd(msg));
+        self.emit(OutputStd(msg));
     }
 
     fn elog(&mut self, msg: String) {
-        self.output.push(OutputErr(msg));
+        self.emit(OutputErr(msg));
     }
 
     fn execute(&mut self) -> RunResult {
-        return match self.try_execute() {
+        match self.try_execute() {
             Ok(output) => output,
             Err(err) => {
                 self.elog(format!("\nFatal error at byte {}:", self.pc));
@@ -129,8 +1925,12 @@ impl Device {
                     dump.acc, dump.data_reg[0], dump.data_reg[1], dump.data_reg[2], dump.data_reg[3], dump.addr_reg[0], dump.addr_reg[1]
                 ));
                 self.elog(format!(
-                    "PC: {:4} SP: {:4X} FP: {:4X} Overflowed: {}",
-                    dump.pc, dump.sp, dump.fp, dump.overflow
+                    "PC: {:4} SP: {:4X} FP: {:4X} Stack limit: {:4X} Flags: {}",
+                    dump.pc,
+                    dump.sp,
+                    dump.fp,
+                    dump.stack_limit,
+                    dump.flags_text()
                 ));
                 self.elog(format!(
                     "Stack ({:4X}..FFFF): {:?}",
@@ -139,7 +1939,7 @@ impl Device {
                 ));
                 ProgError
             }
-        };
+        }
     }
 
     fn cond_jump(&mut self, should_jump: bool, addr: u16, opcode: u8) {
@@ -152,401 +1952,59 @@ impl Device {
 
     fn try_execute(&mut self) -> Result<RunResult> {
         let idx = self.pc as usize;
-        let op = self.tape_ops[idx];
-        match op {
-            NOP => {}
-            ADD_REG_REG => self.add(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.get_reg_content(self.tape_ops[idx + 2])?,
-            ),
-            ADD_REG_VAL => self.add(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.tape_ops[idx + 2],
-            ),
-            ADD_REG_AREG => self.add(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.get_data_content(self.get_addr_reg_content(self.tape_ops[idx + 2])?)?,
-            ),
-            SUB_REG_REG => self.sub(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.get_reg_content(self.tape_ops[idx + 2])?,
-            ),
-            SUB_REG_VAL => self.sub(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.tape_ops[idx + 2],
-            ),
-            SUB_REG_AREG => self.sub(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.get_data_content(self.get_addr_reg_content(self.tape_ops[idx + 2])?)?,
-            ),
-            MEMR_ADDR => self.set_data_reg(
-                REG_ACC,
-                self.get_mem(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2])),
-            )?,
-            MEMR_AREG => self.set_data_reg(
-                REG_ACC,
-                self.get_mem(self.get_addr_reg_content(self.tape_ops[idx + 1])?),
-            )?,
-            CPY_REG_VAL => self.set_data_reg(self.tape_ops[idx + 1], self.tape_ops[idx + 2])?,
-            CPY_REG_REG => self.set_data_reg(
-                self.tape_ops[idx + 1],
-                self.get_reg_content(self.tape_ops[idx + 2])?,
-            )?,
-            CPY_AREG_REG_REG => self.copy_addr_reg(
-                self.tape_ops[idx + 1],
-                self.tape_ops[idx + 2],
-                self.tape_ops[idx + 3],
-            )?,
-            CPY_REG_REG_AREG => self.load_addr_reg(
-                self.tape_ops[idx + 3],
-                self.tape_ops[idx + 1],
-                self.tape_ops[idx + 2],
-            )?,
-            CPY_AREG_ADDR => self.set_addr_reg(
-                self.tape_ops[idx + 1],
-                addr(self.tape_ops[idx + 2], self.tape_ops[idx + 3]),
-            )?,
-            CPY_AREG_AREG => self.set_addr_reg(
-                self.tape_ops[idx + 1],
-                self.get_addr_reg_content(self.tape_ops[idx + 2])?,
-            )?,
-            MEMW_ADDR => self.store(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2])),
-            MEMW_AREG => self.store(self.get_addr_reg_content(self.tape_ops[idx + 1])?),
-            JMP_AREG => self.jump(self.get_addr_reg_content(self.tape_ops[idx + 1])?),
-            JE_AREG => self.cond_jump(
-                self.acc == compare::EQUAL,
-                self.get_addr_reg_content(self.tape_ops[idx + 1])?,
-                JE_AREG,
-            ),
-            JL_AREG => self.cond_jump(
-                self.acc == compare::LESSER,
-                self.get_addr_reg_content(self.tape_ops[idx + 1])?,
-                JL_AREG,
-            ),
-            JG_AREG => self.cond_jump(
-                self.acc == compare::GREATER,
-                self.get_addr_reg_content(self.tape_ops[idx + 1])?,
-                JG_AREG,
-            ),
-            JNE_AREG => self.cond_jump(
-                self.acc != compare::EQUAL,
-                self.get_addr_reg_content(self.tape_ops[idx + 1])?,
-                JNE_AREG,
-            ),
-            OVER_AREG => self.cond_jump(
-                self.flags.overflow,
-                self.get_addr_reg_content(self.tape_ops[idx + 1])?,
-                OVER_AREG,
-            ),
-            NOVER_AREG => self.cond_jump(
-                !self.flags.overflow,
-                self.get_addr_reg_content(self.tape_ops[idx + 1])?,
-                NOVER_AREG,
-            ),
-            JMP_ADDR => self.jump(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2])),
-            JE_ADDR => self.cond_jump(
-                self.acc == compare::EQUAL,
-                addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]),
-                JE_ADDR,
-            ),
-            JL_ADDR => self.cond_jump(
-                self.acc == compare::LESSER,
-                addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]),
-                JL_ADDR,
-            ),
-            JG_ADDR => self.cond_jump(
-                self.acc == compare::GREATER,
-                addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]),
-                JG_ADDR,
-            ),
-            JNE_ADDR => self.cond_jump(
-                self.acc != compare::EQUAL,
-                addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]),
-                JNE_ADDR,
-            ),
-            OVER_ADDR => self.cond_jump(
-                self.flags.overflow,
-                addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]),
-                OVER_ADDR,
-            ),
-            NOVER_ADDR => self.cond_jump(
-                !self.flags.overflow,
-                addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]),
-                NOVER_ADDR,
-            ),
-            INC_REG => self.change(self.tape_ops[idx + 1], 1)?,
-            DEC_REG => self.change(self.tape_ops[idx + 1], -1)?,
-            CMP_REG_REG => self.compare(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.get_reg_content(self.tape_ops[idx + 2])?,
-            ),
-            CMP_REG_VAL => self.compare(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.tape_ops[idx + 2],
-            ),
-            CMP_AREG_ADDR => self.compare_16(
-                self.get_addr_reg_content(self.tape_ops[idx + 1])?,
-                addr(self.tape_ops[idx + 2], self.tape_ops[idx + 3]),
-            ),
-            CMP_AREG_AREG => self.compare_16(
-                self.get_addr_reg_content(self.tape_ops[idx + 1])?,
-                self.get_addr_reg_content(self.tape_ops[idx + 2])?,
-            ),
-            CMP_AREG_REG_REG => self.compare_16(
-                self.get_addr_reg_content(self.tape_ops[idx + 1])?,
-                addr(
-                    self.get_reg_content(self.tape_ops[idx + 2])?,
-                    self.get_reg_content(self.tape_ops[idx + 3])?,
-                ),
-            ),
-            CMP_REG_REG_AREG => self.compare_16(
-                addr(
-                    self.get_reg_content(self.tape_ops[idx + 1])?,
-                    self.get_reg_content(self.tape_ops[idx + 2])?,
-                ),
-                self.get_addr_reg_content(self.tape_ops[idx + 3])?,
-            ),
-            PRT_REG => self.print(self.get_reg_content(self.tape_ops[idx + 1])?),
-            PRT_VAL => self.print(self.tape_ops[idx + 1]),
-            PRTC_REG => self.printc(self.get_reg_content(self.tape_ops[idx + 1])?),
-            PRTC_VAL => self.printc(self.tape_ops[idx + 1]),
-            PRT_AREG => self
-                .print(self.get_data_content(self.get_addr_reg_content(self.tape_ops[idx + 1])?)?),
-            PRTC_AREG => self
-                .printc(self.get_data_content(self.get_addr_reg_content(self.tape_ops[idx + 1])?)?),
-            PRTLN => {
-                self.output.push(OutputStd(String::from("\n")));
-            }
-            PRTS_STR => {
-                self.print_tape_string(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]))?
-            }
-            FOPEN_REG => self.open_file(self.get_reg_content(self.tape_ops[idx + 1])? as usize)?,
-            FILER_REG_ADDR => self.read_file(
-                self.get_reg_content(self.tape_ops[idx + 1])? as usize,
-                addr(self.tape_ops[idx + 2], self.tape_ops[idx + 3]),
-            )?,
-            FILER_REG_AREG => self.read_file(
-                self.get_reg_content(self.tape_ops[idx + 1])? as usize,
-                self.get_addr_reg_content(self.tape_ops[idx + 2])?,
-            )?,
-            FILEW_REG_AREG => self.write_file(
-                self.get_reg_content(self.tape_ops[idx + 1])? as usize,
-                self.get_addr_reg_content(self.tape_ops[idx + 2])?,
-            )?,
-            FILEW_REG_ADDR => self.write_file(
-                self.get_reg_content(self.tape_ops[idx + 1])? as usize,
-                addr(self.tape_ops[idx + 2], self.tape_ops[idx + 3]),
-            )?,
-
-            FSEEK_REG => {
-                self.seek_file_stack(self.get_reg_content(self.tape_ops[idx + 1])? as usize)?
-            }
-            FSKIP_REG_REG => self.skip_file(
-                self.get_reg_content(self.tape_ops[idx + 1])? as usize,
-                self.get_reg_content(self.tape_ops[idx + 2])?,
-            )?,
-            FSKIP_REG_VAL => self.skip_file(
-                self.get_reg_content(self.tape_ops[idx + 1])? as usize,
-                self.tape_ops[idx + 2],
-            )?,
-            FOPEN_VAL => self.open_file(self.tape_ops[idx + 1] as usize)?,
-            FILER_VAL_ADDR => self.read_file(
-                self.tape_ops[idx + 1] as usize,
-                addr(self.tape_ops[idx + 2], self.tape_ops[idx + 3]),
-            )?,
-            FILER_VAL_AREG => self.read_file(
-                self.tape_ops[idx + 1] as usize,
-                self.get_addr_reg_content(self.tape_ops[idx + 2])?,
-            )?,
-            FILEW_VAL_AREG => self.write_file(
-                self.tape_ops[idx + 1] as usize,
-                self.get_addr_reg_content(self.tape_ops[idx + 2])?,
-            )?,
-            FILEW_VAL_ADDR => self.write_file(
-                self.tape_ops[idx + 1] as usize,
-                addr(self.tape_ops[idx + 2], self.tape_ops[idx + 3]),
-            )?,
-            FILEW_REG_REG => self.write_file_value(
-                self.get_reg_content(self.tape_ops[idx + 1])? as usize,
-                self.get_reg_content(self.tape_ops[idx + 2])?,
-            )?,
-            FILEW_REG_VAL => self.write_file_value(
-                self.get_reg_content(self.tape_ops[idx + 1])? as usize,
-                self.tape_ops[idx + 2],
-            )?,
-            FILEW_VAL_REG => self.write_file_value(
-                self.tape_ops[idx + 1] as usize,
-                self.get_reg_content(self.tape_ops[idx + 2])?,
-            )?,
-            FILEW_VAL_VAL => {
-                self.write_file_value(self.tape_ops[idx + 1] as usize, self.tape_ops[idx + 2])?
-            }
-            FSEEK_VAL => self.seek_file(self.tape_ops[idx + 1] as usize)?,
-            FSKIP_VAL_REG => self.skip_file(
-                self.tape_ops[idx + 1] as usize,
-                self.get_reg_content(self.tape_ops[idx + 2])?,
-            )?,
-            FSKIP_VAL_VAL => {
-                self.skip_file(self.tape_ops[idx + 1] as usize, self.tape_ops[idx + 2])?
-            }
-            HALT => return Ok(RunResult::Halt),
-            PUSH_VAL => self.stack_push(self.tape_ops[idx + 1]),
-            PUSH_REG => self.stack_push_reg(self.tape_ops[idx + 1])?,
-            POP_REG => self.stack_pop(self.tape_ops[idx + 1])?,
-            ARG_REG_VAL => self.stack_arg(self.tape_ops[idx + 1], self.tape_ops[idx + 2])?,
-            ARG_REG_REG => self.stack_arg(
-                self.tape_ops[idx + 1],
-                self.get_reg_content(self.tape_ops[idx + 2])?,
-            )?,
-            RET => self.stack_return()?,
-            CALL_ADDR => {
-                self.stack_call(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]), false)
-            }
-            CALL_AREG => self.stack_call(self.get_addr_reg_content(self.tape_ops[idx + 1])?, true),
-            SWP_REG_REG | SWP_AREG_AREG => {
-                self.swap(self.tape_ops[idx + 1], self.tape_ops[idx + 2])?
-            }
-            IPOLL_ADDR => {
-                self.poll_input(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]), false)?
-            }
-            IPOLL_AREG => {
-                self.poll_input(self.get_addr_reg_content(self.tape_ops[idx + 1])?, true)?
-            }
-            RCHR_REG => {
-                if !self.read_char(self.tape_ops[idx + 1])? {
-                    return Ok(RunResult::CharInputRequested);
-                }
-            }
-            RSTR_ADDR => {
-                if !self.read_string(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]))? {
-                    return Ok(RunResult::StringInputRequested);
-                }
-            }
-            RSTR_AREG => {
-                if !self.read_string(self.get_addr_reg_content(self.tape_ops[idx + 1])?)? {
-                    return Ok(RunResult::StringInputRequested);
-                }
-            }
-            MEMP_ADDR => self.print_string(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]))?,
-            MEMP_AREG => self.print_string(self.get_addr_reg_content(self.tape_ops[idx + 1])?)?,
-            FCHK_REG_ADDR => self.cond_jump(
-                self.files.len() > self.get_reg_content(self.tape_ops[idx + 1])? as usize,
-                addr(self.tape_ops[idx + 2], self.tape_ops[idx + 3]),
-                FCHK_REG_ADDR,
-            ),
-            FCHK_REG_AREG => self.cond_jump(
-                self.files.len() > self.get_reg_content(self.tape_ops[idx + 1])? as usize,
-                self.get_addr_reg_content(self.tape_ops[idx + 2])?,
-                FCHK_REG_AREG,
-            ),
-            FCHK_VAL_ADDR => self.cond_jump(
-                self.files.len() > self.tape_ops[idx + 1] as usize,
-                addr(self.tape_ops[idx + 2], self.tape_ops[idx + 3]),
-                FCHK_VAL_ADDR,
-            ),
-            FCHK_VAL_AREG => self.cond_jump(
-                self.files.len() > self.tape_ops[idx + 1] as usize,
-                self.get_addr_reg_content(self.tape_ops[idx + 2])?,
-                FCHK_VAL_AREG,
-            ),
-            TIME => self.set_time(),
-            RAND_REG => self.rand(self.tape_ops[idx + 1])?,
-            SEED_REG => self.seed(self.get_reg_content(self.tape_ops[idx + 1])?)?,
-            AND_REG_REG => self.bit_and(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.get_reg_content(self.tape_ops[idx + 2])?,
-            ),
-            AND_REG_VAL => self.bit_and(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.tape_ops[idx + 2],
-            ),
-            AND_REG_AREG => self.bit_and(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.get_data_content(self.get_addr_reg_content(self.tape_ops[idx + 2])?)?,
-            ),
-            OR_REG_REG => self.bit_or(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.get_reg_content(self.tape_ops[idx + 2])?,
-            ),
-            OR_REG_VAL => self.bit_or(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.tape_ops[idx + 2],
-            ),
-            OR_REG_AREG => self.bit_or(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.get_data_content(self.get_addr_reg_content(self.tape_ops[idx + 2])?)?,
-            ),
-            XOR_REG_REG => self.bit_xor(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.get_reg_content(self.tape_ops[idx + 2])?,
-            ),
-            XOR_REG_VAL => self.bit_xor(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.tape_ops[idx + 2],
-            ),
-            XOR_REG_AREG => self.bit_xor(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.get_data_content(self.get_addr_reg_content(self.tape_ops[idx + 2])?)?,
-            ),
-            NOT_REG => self.bit_not(self.get_reg_content(self.tape_ops[idx + 1])?),
-            LD_AREG_DATA_VAL_VAL => self.load_data_addr(
-                self.tape_ops[idx + 1],
-                addr(self.tape_ops[idx + 2], self.tape_ops[idx + 3]),
-                self.tape_ops[idx + 4],
-                self.tape_ops[idx + 5],
-            )?,
-            LD_AREG_DATA_VAL_REG => self.load_data_addr(
-                self.tape_ops[idx + 1],
-                addr(self.tape_ops[idx + 2], self.tape_ops[idx + 3]),
-                self.tape_ops[idx + 4],
-                self.get_reg_content(self.tape_ops[idx + 5])?,
-            )?,
-            LD_AREG_DATA_REG_VAL => self.load_data_addr(
-                self.tape_ops[idx + 1],
-                addr(self.tape_ops[idx + 2], self.tape_ops[idx + 3]),
-                self.get_reg_content(self.tape_ops[idx + 4])?,
-                self.tape_ops[idx + 5],
-            )?,
-            LD_AREG_DATA_REG_REG => self.load_data_addr(
-                self.tape_ops[idx + 1],
-                addr(self.tape_ops[idx + 2], self.tape_ops[idx + 3]),
-                self.get_reg_content(self.tape_ops[idx + 4])?,
-                self.get_reg_content(self.tape_ops[idx + 5])?,
-            )?,
-            CPY_REG_AREG => self.load_data(self.tape_ops[idx + 1], self.tape_ops[idx + 2])?,
-            CMP_REG_AREG => self.compare_data(
-                self.get_reg_content(self.tape_ops[idx + 1])?,
-                self.get_addr_reg_content(self.tape_ops[idx + 2])?,
-            )?,
-            PRTD_AREG => self.print_data(self.tape_ops[idx + 1])?,
-            DEBUG => {
-                let dump = self.dump();
-                self.log(format!(
-                    "ACC: {:02X}  D0: {:02X}  D1: {:02X}  D2: {:02X}  D3: {:02X} A0: {:04X} A1: {:04X}",
-                    dump.acc, dump.data_reg[0], dump.data_reg[1], dump.data_reg[2], dump.data_reg[3], dump.addr_reg[0], dump.addr_reg[1]
-                ));
-                self.log(format!(
-                    "PC: {:4} SP: {:4X} FP: {:4X} Overflowed: {}",
-                    dump.pc, dump.sp, dump.fp, dump.overflow
-                ));
-                self.log(format!(
-                    "Stack ({:4X}..FFFF): {:?}",
-                    dump.sp,
-                    &self.mem[dump.sp as usize..0xFFFF]
-                ));
-            }
-            _ => {
-                return Err(Error::msg(format!(
-                    "Unknown instruction: {:02X}",
-                    self.tape_ops[idx]
-                )));
+        let opcode = self.tape_ops[idx];
+        let dispatch = match &DISPATCH[opcode as usize] {
+            Some(dispatch) => *dispatch,
+            None => return Err(Error::msg(format!("Unknown instruction: {:02X}", opcode))),
+        };
+        let len = dispatch.len;
+        if idx + len > self.tape_ops.len() {
+            return Err(Error::msg(format!(
+                "Instruction {:02X} is truncated, it needs {} bytes but only {} remain",
+                opcode,
+                len,
+                self.tape_ops.len() - idx
+            )));
+        }
+        if !self.capabilities.contains(dispatch.required) {
+            return Err(Error::msg(format!(
+                "Instruction {:02X} requires the '{}' capability, which has been denied",
+                opcode,
+                (dispatch.required - self.capabilities).name()
+            )));
+        }
+        let mut instruction = [0; MAX_INSTRUCTION_BYTES];
+        instruction[..len].copy_from_slice(&self.tape_ops[idx..idx + len]);
+        let result = (dispatch.handler)(self, &instruction)?;
+        if result == Pause {
+            if !dispatch.jump {
+                self.pc += len as u16;
+            } else if let Err(err) = self.check_jump_target(self.pc) {
+                //report the error at the jump rather than where it went
+                self.pc = idx as u16;
+                return Err(err);
             }
         }
-        if !is_jump_op(self.tape_ops[idx]) {
-            let op_size = get_byte_count(self.tape_ops[idx]) as u16;
-            self.pc += op_size;
+        Ok(result)
+    }
+
+    ///Jumps must go to the start of an instruction or the end of the program
+    fn check_jump_target(&self, addr: u16) -> Result<()> {
+        if self.op_starts.get(addr as usize).copied().unwrap_or(false) {
+            return Ok(());
+        }
+        if addr as usize > self.tape_ops.len() {
+            Err(Error::msg(format!(
+                "Invalid jump to {:04X}, it's outside of the program ({} bytes)",
+                addr,
+                self.tape_ops.len()
+            )))
+        } else {
+            Err(Error::msg(format!(
+                "Invalid jump to {:04X}, it's in the middle of an instruction",
+                addr
+            )))
         }
-        Ok(RunResult::Pause)
     }
 
     pub fn dump(&self) -> Dump {
@@ -555,53 +2013,215 @@ impl Device {
             acc: self.acc,
             sp: self.sp,
             fp: self.fp,
+            stack_limit: self.stack_limit,
             data_reg: self.data_reg,
             addr_reg: self.addr_reg,
-            overflow: self.flags.overflow,
+            flags: self.flags.bits(),
+        }
+    }
+
+    ///Copy of memory, registers, flags and file cursors
+    pub fn snapshot(&mut self) -> Result<DeviceState> {
+        let mut files = vec![];
+        for (num, file) in self.files.iter_mut().enumerate() {
+            files.push(match (file, self.file_paths.get(&num)) {
+                (Some(file), Some(path)) => Some(FileState {
+                    path: path.clone(),
+                    cursor: file.stream_position()?,
+                }),
+                _ => None,
+            });
         }
+        Ok(DeviceState {
+            registers: self.dump(),
+            mem: self.mem.to_vec(),
+            keyboard_buffer: self.keyboard_buffer.clone(),
+            cycles: self.cycles,
+            files,
+            frames: self.frames.clone(),
+        })
+    }
+
+    ///Replace memory, registers, flags and open files with those from `state`
+    ///
+    ///The tape is not included in the snapshot so it must be restored to a device running the same tape
+    pub fn restore(&mut self, state: DeviceState) -> Result<()> {
+        if state.mem.len() != RAM_SIZE {
+            return Err(Error::msg(format!(
+                "Snapshot memory is {} bytes, expected {}",
+                state.mem.len(),
+                RAM_SIZE
+            )));
+        }
+        self.files.iter_mut().for_each(|file| *file = None);
+        self.file_paths.clear();
+        for (num, file) in state.files.into_iter().enumerate() {
+            if let Some(file) = file {
+                self.open_path(num, &file.path)?;
+                if let Some(Some(opened)) = self.files.get_mut(num) {
+                    opened.seek(SeekFrom::Start(file.cursor))?;
+                }
+            }
+        }
+        self.mem.copy_from_slice(&state.mem);
+        //which bytes were written isn't in the snapshot
+        self.mark_initialized(0..RAM_SIZE);
+        self.set_registers(state.registers);
+        self.keyboard_buffer = state.keyboard_buffer;
+        self.cycles = state.cycles;
+        //LOCALs aren't in the snapshot so any after FP count as pushes
+        self.stack_base = self.fp;
+        self.frames = state.frames;
+        Ok(())
+    }
+
+    ///Replace every register and the flags, e.g. with a modified [`Device::dump`]
+    pub fn set_registers(&mut self, registers: Dump) {
+        self.pc = registers.pc;
+        self.acc = registers.acc;
+        self.sp = registers.sp;
+        self.fp = registers.fp;
+        self.stack_limit = registers.stack_limit;
+        self.data_reg = registers.data_reg;
+        self.addr_reg = registers.addr_reg;
+        self.flags = Flags::from_bits(registers.flags);
     }
 
     //Accessors
 
     fn get_reg_content(&self, id: u8) -> Result<u8> {
-        return match id {
-            REG_ACC => Ok(self.acc),
-            REG_D0 => Ok(self.data_reg[0]),
-            REG_D1 => Ok(self.data_reg[1]),
-            REG_D2 => Ok(self.data_reg[2]),
-            REG_D3 => Ok(self.data_reg[3]),
+        match Register::try_from(id) {
+            Ok(Register::Acc) => Ok(self.acc),
+            Ok(reg) if !reg.is_addr_reg() => Ok(self.data_reg[reg.index().unwrap()]),
             _ => Err(Error::msg(format!("Invalid data register: {:02X}", id))),
-        };
+        }
+    }
+
+    ///Address register plus data register offset for `instruction`, fails if it's past the end of memory
+    fn indexed_addr(&self, instruction: &Instruction) -> Result<u16> {
+        let base = self.get_addr_reg_content(instruction[1])?;
+        let offset = self.get_reg_content(instruction[2])?;
+        base.checked_add(offset as u16).ok_or_else(|| {
+            Error::msg(format!(
+                "Memory access out of bounds, {:04X}+{:02X} is past the end of memory",
+                base, offset
+            ))
+        })
+    }
+
+    ///Content of a data or address register
+    fn get_any_reg_content(&self, id: u8) -> Result<u16> {
+        match Register::try_from(id) {
+            Ok(reg) if reg.is_addr_reg() => self.get_addr_reg_content(id),
+            _ => Ok(self.get_reg_content(id)? as u16),
+        }
     }
 
     fn get_addr_reg_content(&self, id: u8) -> Result<u16> {
-        return match id {
-            REG_A0 => Ok(self.addr_reg[0]),
-            REG_A1 => Ok(self.addr_reg[1]),
+        match Register::try_from(id) {
+            Ok(reg) if reg.is_addr_reg() => Ok(self.addr_reg[reg.index().unwrap()]),
             _ => Err(Error::msg(format!("Invalid address register: {:02X}", id))),
-        };
+        }
     }
 
-    fn get_mem(&self, addr: u16) -> u8 {
-        self.mem[addr as usize]
+    fn get_mem(&mut self, addr: u16) -> Result<u8> {
+        if self.is_mmio(addr) {
+            return self.mmio_read(addr);
+        }
+        let range = self.mem_range(addr, 1)?;
+        self.check_initialized(range);
+        Ok(self.mem[addr as usize])
+    }
+
+    fn mmio_read(&mut self, addr: u16) -> Result<u8> {
+        match addr {
+            MMIO_INPUT_STATUS => {
+                self.mmio_require(addr, Capabilities::INPUT)?;
+                Ok(self.keyboard_buffer.len().min(u8::MAX as usize) as u8)
+            }
+            MMIO_INPUT_CHAR => {
+                self.mmio_require(addr, Capabilities::INPUT)?;
+                if self.keyboard_buffer.is_empty() {
+                    Ok(0)
+                } else {
+                    Ok(self.keyboard_buffer.remove(0))
+                }
+            }
+            MMIO_RAND => {
+                self.mmio_require(addr, Capabilities::RAND)?;
+                Ok(self.rng.get_u8())
+            }
+            _ if (MMIO_MILLIS..MMIO_MILLIS + 4).contains(&addr) => {
+                self.mmio_require(addr, Capabilities::TIME)?;
+                if addr == MMIO_MILLIS {
+                    self.mmio_millis = (self.current_millis() as u32).to_be_bytes();
+                }
+                Ok(self.mmio_millis[(addr - MMIO_MILLIS) as usize])
+            }
+            _ => Err(Error::msg(format!(
+                "Memory mapped I/O at {:04X} can't be read",
+                addr
+            ))),
+        }
+    }
+
+    fn mmio_write(&mut self, addr: u16, value: u8) -> Result<()> {
+        match addr {
+            MMIO_CHAR_OUT => self.printc(value),
+            MMIO_NUM_OUT => self.print(value),
+            _ => {
+                return Err(Error::msg(format!(
+                    "Memory mapped I/O at {:04X} can't be written",
+                    addr
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn mmio_require(&self, addr: u16, required: Capabilities) -> Result<()> {
+        if self.capabilities.contains(required) {
+            Ok(())
+        } else {
+            Err(Error::msg(format!(
+                "Memory mapped I/O at {:04X} requires the '{}' capability, which has been denied",
+                addr,
+                required.name()
+            )))
+        }
+    }
+
+    ///Indexes of `len` bytes of RAM starting at `addr`, fails if any are past the end of memory
+    fn mem_range(&self, addr: u16, len: usize) -> Result<Range<usize>> {
+        let start = addr as usize;
+        let end = start + len;
+        if end > RAM_SIZE {
+            return Err(Error::msg(format!(
+                "Memory access out of bounds, {} bytes at {:04X} but max address is {:04X}",
+                len,
+                addr,
+                RAM_SIZE - 1
+            )));
+        }
+        Ok(start..end)
     }
 
     fn set_data_reg(&mut self, reg: u8, value: u8) -> Result<()> {
-        match reg {
-            REG_ACC => self.acc = value,
-            REG_D0 => self.data_reg[0] = value,
-            REG_D1 => self.data_reg[1] = value,
-            REG_D2 => self.data_reg[2] = value,
-            REG_D3 => self.data_reg[3] = value,
+        match Register::try_from(reg) {
+            Ok(Register::Acc) => self.acc = value,
+            Ok(register) if !register.is_addr_reg() => {
+                self.data_reg[register.index().unwrap()] = value
+            }
             _ => return Err(Error::msg(format!("Invalid data register: {:02X}", reg))),
         }
         Ok(())
     }
 
     fn set_addr_reg(&mut self, reg: u8, value: u16) -> Result<()> {
-        match reg {
-            REG_A0 => self.addr_reg[0] = value,
-            REG_A1 => self.addr_reg[1] = value,
+        match Register::try_from(reg) {
+            Ok(register) if register.is_addr_reg() => {
+                self.addr_reg[register.index().unwrap()] = value
+            }
             _ => return Err(Error::msg(format!("Invalid address register: {:02X}", reg))),
         }
         Ok(())
@@ -610,17 +2230,7 @@ impl Device {
     //Operations
 
     fn load_addr_reg(&mut self, addr_reg: u8, reg1: u8, reg2: u8) -> Result<()> {
-        let bytes = match addr_reg {
-            REG_A0 => self.addr_reg[0],
-            REG_A1 => self.addr_reg[1],
-            _ => {
-                return Err(Error::msg(format!(
-                    "Invalid addr register: {:02X}",
-                    addr_reg
-                )));
-            }
-        }
-        .to_be_bytes();
+        let bytes = self.get_addr_reg_content(addr_reg)?.to_be_bytes();
         self.set_data_reg(reg1, bytes[0])?;
         self.set_data_reg(reg2, bytes[1])?;
 
@@ -631,18 +2241,7 @@ impl Device {
         let byte0 = self.get_reg_content(reg1)?;
         let byte1 = self.get_reg_content(reg2)?;
         let addr = u16::from_be_bytes([byte0, byte1]);
-        match addr_reg {
-            REG_A0 => self.addr_reg[0] = addr,
-            REG_A1 => self.addr_reg[1] = addr,
-            _ => {
-                return Err(Error::msg(format!(
-                    "Invalid addr register: {:02X}",
-                    addr_reg
-                )));
-            }
-        }
-
-        Ok(())
+        self.set_addr_reg(addr_reg, addr)
     }
 
     fn poll_input(&mut self, addr: u16, from_areg: bool) -> Result<()> {
@@ -667,17 +2266,70 @@ impl Device {
         } else {
             self.keyboard_buffer.len()
         };
+        self.mem_range(addr, len)?;
         for i in 0..len {
-            self.mem[i + addr as usize] = self.keyboard_buffer.remove(0);
+            let chr = self.keyboard_buffer.remove(0);
+            self.write_mem(addr + i as u16, chr)?;
         }
         self.acc = len as u8;
         Ok(true)
     }
 
+    ///Write each argument as its length then its bytes starting at `addr` and set ACC to the count
+    fn load_args(&mut self, addr: u16) -> Result<()> {
+        if self.args.len() > u8::MAX as usize {
+            return Err(Error::msg(format!(
+                "{} program arguments, max {}",
+                self.args.len(),
+                u8::MAX
+            )));
+        }
+        let mut bytes = vec![];
+        for arg in &self.args {
+            if arg.len() > MAX_STRING_LEN {
+                return Err(Error::msg(format!(
+                    "Program argument '{}' is {} bytes, max {}",
+                    arg,
+                    arg.len(),
+                    MAX_STRING_LEN
+                )));
+            }
+            bytes.push(arg.len() as u8);
+            bytes.extend_from_slice(arg.as_bytes());
+        }
+        let range = self.block_range(addr, bytes.len())?;
+        self.mem[range.clone()].copy_from_slice(&bytes);
+        self.record_write(range);
+        self.acc = self.args.len() as u8;
+        Ok(())
+    }
+
+    ///Store ACC bytes from `addr` under `key`
+    fn save_value(&mut self, key: String, addr: u16) -> Result<()> {
+        let range = self.block_range(addr, self.acc as usize)?;
+        let value = self.mem[range].to_vec();
+        let storage = self.storage.as_mut().ok_or_else(no_save_file)?;
+        storage.set(self.file_provider.as_mut(), key, value)
+    }
+
+    ///Copy the value of `key` to `addr` and set ACC to its length, 0 if it hasn't been saved
+    fn load_value(&mut self, key: &str, addr: u16) -> Result<()> {
+        let storage = self.storage.as_mut().ok_or_else(no_save_file)?;
+        let value = storage
+            .get(self.file_provider.as_mut(), key)?
+            .map(<[u8]>::to_vec)
+            .unwrap_or_default();
+        let range = self.block_range(addr, value.len())?;
+        self.mem[range.clone()].copy_from_slice(&value);
+        self.record_write(range);
+        self.acc = value.len() as u8;
+        Ok(())
+    }
+
     fn print_string(&mut self, addr: u16) -> Result<()> {
-        let start = addr as usize;
-        let end = (addr + self.acc as u16) as usize;
-        self.log(String::from_utf8_lossy(&self.mem[start..end]).to_string());
+        let range = self.mem_range(addr, self.acc as usize)?;
+        self.check_initialized(range.clone());
+        self.log(String::from_utf8_lossy(&self.mem[range]).to_string());
         Ok(())
     }
 
@@ -692,17 +2344,50 @@ impl Device {
     }
 
     fn open_file(&mut self, file_num: usize) -> Result<()> {
-        if self.files[file_num].is_some() {
-            return Err(Error::msg(format!("File {} already open", file_num)));
-        }
         if self.data_files.len() <= file_num {
             return Err(Error::msg(format!("File {} not provided", file_num)));
         }
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&self.data_files[file_num])?;
+        let path = PathBuf::from(&self.data_files[file_num]);
+        self.open_path(file_num, &path)
+    }
+
+    fn open_named_file(&mut self, file_num: usize, name: String) -> Result<()> {
+        let path = PathBuf::from(&name);
+        if !self.allowed_paths.is_empty() {
+            let allowed = self
+                .file_provider
+                .canonicalize(&path)
+                .map(|path| self.allowed_paths.iter().any(|dir| path.starts_with(dir)))
+                .unwrap_or(false);
+            if !allowed {
+                return Err(Error::msg(format!(
+                    "File {} is not in an allowed path",
+                    name
+                )));
+            }
+        }
+        self.open_path(file_num, &path)
+    }
+
+    fn open_path(&mut self, file_num: usize, path: &Path) -> Result<()> {
+        if file_num == STDIN_FILE as usize || file_num == STDOUT_FILE as usize {
+            return Err(Error::msg(format!(
+                "File {} is reserved for stdin/stdout and is always open",
+                file_num
+            )));
+        }
+        if self.files.len() <= file_num {
+            self.files.resize_with(file_num + 1, || None);
+        }
+        if self.files[file_num].is_some() {
+            return Err(Error::msg(format!("File {} already open", file_num)));
+        }
+        let file = if self.read_only_files.contains(&file_num) {
+            self.file_provider.open_read_only(path)?
+        } else {
+            self.file_provider.open(path)?
+        };
+        let mut file: Box<dyn DeviceFile> = Box::new(BufferedFile::new(file));
         let pos = file
             .seek(SeekFrom::End(0))
             .expect("Unable to get file length");
@@ -712,7 +2397,8 @@ impl Device {
         self.data_reg[0] = (pos.rotate_right(24) & 0xFF) as u8;
         file.seek(SeekFrom::Start(0))
             .expect("Unable to reset file cursor");
-        self.files.insert(file_num, Some(file));
+        self.files[file_num] = Some(file);
+        self.file_paths.insert(file_num, path.to_path_buf());
 
         Ok(())
     }
@@ -727,118 +2413,216 @@ impl Device {
         bytes[6] = self.acc;
         self.stack_pop(REG_ACC)?;
         bytes[7] = self.acc;
-        match &mut self.files[file_num] {
-            None => Err(Error::msg(format!("File {} not open", file_num))),
+        let addr = u64::from_be_bytes(bytes);
+        self.file_mut(file_num)?.seek(SeekFrom::Start(addr))?;
+        Ok(())
+    }
+
+    fn seek_file(&mut self, file_num: usize) -> Result<()> {
+        let addr = u64::from_be_bytes([
+            0,
+            0,
+            0,
+            0,
+            self.data_reg[0],
+            self.data_reg[1],
+            self.data_reg[2],
+            self.data_reg[3],
+        ]);
+        self.file_mut(file_num)?.seek(SeekFrom::Start(addr))?;
+        Ok(())
+    }
+
+    ///Open file `file_num`, an error if the number is out of range or the file isn't open
+    fn file_mut(&mut self, file_num: usize) -> Result<&mut Box<dyn DeviceFile>> {
+        match self.files.get_mut(file_num) {
+            Some(Some(file)) => Ok(file),
+            _ => Err(Error::msg(format!("File {} not open", file_num))),
+        }
+    }
+
+    ///Writes the number of input files then each file name as a length prefixed string, populates `ACC` with the number of files
+    fn list_files(&mut self, addr: u16) -> Result<()> {
+        if self.data_files.len() > u8::MAX as usize {
+            return Err(Error::msg(format!(
+                "{} input files provided, FLIST supports at most 255",
+                self.data_files.len()
+            )));
+        }
+        let mut bytes = vec![self.data_files.len() as u8];
+        for name in &self.data_files {
+            if name.len() > u8::MAX as usize {
+                return Err(Error::msg(format!(
+                    "File name {} is longer than 255 bytes",
+                    name
+                )));
+            }
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(name.as_bytes());
+        }
+        if addr as usize + bytes.len() > RAM_SIZE {
+            return Err(Error::msg(format!(
+                "File list is {} bytes but only {} bytes remain at {:04X}",
+                bytes.len(),
+                RAM_SIZE - addr as usize,
+                addr
+            )));
+        }
+        for (i, byte) in bytes.iter().enumerate() {
+            self.write_mem(addr + i as u16, *byte)?;
+        }
+        self.acc = self.data_files.len() as u8;
+        Ok(())
+    }
+
+    ///Stdin and stdout are always available
+    fn file_available(&self, file_num: usize) -> bool {
+        file_num == STDIN_FILE as usize
+            || file_num == STDOUT_FILE as usize
+            || self.files.len() > file_num
+    }
+
+    fn check_writable(&self, file_num: usize) -> Result<()> {
+        if self.read_only_files.contains(&file_num) {
+            return Err(Error::msg(format!("File {} is read only", file_num)));
+        }
+        Ok(())
+    }
+
+    fn truncate_file(&mut self, file_num: usize) -> Result<()> {
+        self.check_writable(file_num)?;
+        let file = self.file_mut(file_num)?;
+        let pos = file.stream_position()?;
+        file.set_len(pos)?;
+        Ok(())
+    }
+
+    ///Closes the file before removing it, the file can be opened again to recreate it
+    fn delete_file(&mut self, file_num: usize) -> Result<()> {
+        self.check_writable(file_num)?;
+        match self.files.get_mut(file_num).and_then(Option::take) {
             Some(file) => {
-                let addr = u64::from_be_bytes(bytes);
-                match file.seek(SeekFrom::Start(addr)) {
-                    Ok(_) => Ok(()),
-                    Err(err) => Err(Error::from(err)),
+                drop(file);
+                match self.file_paths.remove(&file_num) {
+                    Some(path) => self.file_provider.remove(&path).map_err(|err| {
+                        Error::msg(format!("Unable to delete file {}: {}", file_num, err))
+                    }),
+                    None => Err(Error::msg(format!("File {} has no path", file_num))),
                 }
             }
+            None => Err(Error::msg(format!("File {} not open", file_num))),
+        }
+    }
+
+    ///Returns false if reading from stdin and no bytes are available yet
+    fn read_file(&mut self, file_num: usize, addr: u16) -> Result<bool> {
+        match self.read_bytes(file_num, addr, self.acc as usize)? {
+            Some(count) => {
+                self.acc = count as u8;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    ///Reads up to `len` bytes and sets `[D2][D3]` to the number read, returns false if reading
+    ///from stdin and no bytes are available yet
+    fn read_file_block(&mut self, file_num: usize, addr: u16, len: u16) -> Result<bool> {
+        match self.read_bytes(file_num, addr, len as usize)? {
+            Some(count) => {
+                self.set_block_count(count);
+                Ok(true)
+            }
+            None => Ok(false),
         }
     }
 
-    fn seek_file(&mut self, file_num: usize) -> Result<()> {
-        match &mut self.files[file_num] {
-            None => Err(Error::msg(format!("File {} not open", file_num))),
-            Some(file) => {
-                let addr = u64::from_be_bytes([
-                    0,
-                    0,
-                    0,
-                    0,
-                    self.data_reg[0],
-                    self.data_reg[1],
-                    self.data_reg[2],
-                    self.data_reg[3],
-                ]);
-                match file.seek(SeekFrom::Start(addr)) {
-                    Ok(_) => Ok(()),
-                    Err(err) => Err(Error::from(err)),
-                }
-            }
+    ///Copy up to `len` bytes from the file into memory at `addr` and return how many were copied,
+    ///None if reading from stdin and no bytes are available yet
+    fn read_bytes(&mut self, file_num: usize, addr: u16, len: usize) -> Result<Option<usize>> {
+        if file_num == STDIN_FILE as usize {
+            return self.read_stdin(addr, len);
+        }
+        self.mem_range(addr, len)?;
+        let mut buffer = vec![0_u8; len];
+        let count = self.file_mut(file_num)?.read(&mut buffer)?;
+        #[allow(clippy::needless_range_loop)] //looks better this way
+        for i in 0..count {
+            self.write_mem(addr + i as u16, buffer[i])?;
         }
+
+        Ok(Some(count))
     }
 
-    fn read_file(&mut self, file_num: usize, addr: u16) -> Result<()> {
-        match &mut self.files[file_num] {
-            None => Err(Error::msg(format!("File {} not open", file_num))),
-            Some(file) => {
-                let mut buffer = vec![0_u8; self.acc as usize];
-                match file.read(&mut buffer) {
-                    Ok(count) => {
-                        #[allow(clippy::needless_range_loop)] //looks better this way
-                        for i in 0..count {
-                            let mem_addr = addr as usize + i;
-                            self.mem[mem_addr] = buffer[i];
-                        }
-                        self.acc = count as u8;
-
-                        Ok(())
-                    }
-                    Err(err) => Err(Error::from(err)),
-                }
-            }
+    fn read_stdin(&mut self, addr: u16, len: usize) -> Result<Option<usize>> {
+        if self.stdin_buffer.is_empty() && !self.stdin_closed && len > 0 {
+            return Ok(None);
         }
+        let count = self.stdin_buffer.len().min(len);
+        self.mem_range(addr, count)?;
+        let bytes: Vec<u8> = self.stdin_buffer.drain(..count).collect();
+        for (i, byte) in bytes.into_iter().enumerate() {
+            self.write_mem(addr + i as u16, byte)?;
+        }
+        Ok(Some(count))
     }
 
     fn write_file(&mut self, file_num: usize, addr: u16) -> Result<()> {
-        match &mut self.files[file_num] {
-            None => Err(Error::msg(format!("File {} not open", file_num))),
-            Some(file) => {
-                let buffer = &self.mem[(addr as usize)..((addr + self.acc as u16) as usize)];
-                match file.write(buffer) {
-                    Ok(count) => {
-                        self.acc = count as u8;
+        self.acc = self.write_bytes(file_num, addr, self.acc as usize)? as u8;
+        Ok(())
+    }
 
-                        file.flush()?;
-                        Ok(())
-                    }
-                    Err(err) => Err(Error::from(err)),
-                }
-            }
+    ///Writes up to `len` bytes and sets `[D2][D3]` to the number written
+    fn write_file_block(&mut self, file_num: usize, addr: u16, len: u16) -> Result<()> {
+        let count = self.write_bytes(file_num, addr, len as usize)?;
+        self.set_block_count(count);
+        Ok(())
+    }
+
+    ///Write `len` bytes from memory at `addr` to the file and return how many were written
+    fn write_bytes(&mut self, file_num: usize, addr: u16, len: usize) -> Result<usize> {
+        if file_num == STDOUT_FILE as usize {
+            let end = (addr as usize + len).min(RAM_SIZE);
+            let bytes = self.mem[addr as usize..end].to_vec();
+            let count = bytes.len();
+            self.emit(OutputBinary(bytes));
+            return Ok(count);
         }
+        self.check_writable(file_num)?;
+        let range = self.mem_range(addr, len)?;
+        let bytes = self.mem[range].to_vec();
+        Ok(self.file_mut(file_num)?.write(&bytes)?)
     }
 
-    fn write_file_value(&mut self, file_num: usize, value: u8) -> Result<()> {
-        match &mut self.files[file_num] {
-            None => Err(Error::msg(format!("File {} not open", file_num))),
-            Some(file) => match file.write(&[value]) {
-                Ok(count) => {
-                    self.acc = count as u8;
+    fn set_block_count(&mut self, count: usize) {
+        let [high, low] = (count as u16).to_be_bytes();
+        self.data_reg[2] = high;
+        self.data_reg[3] = low;
+    }
 
-                    file.flush()?;
-                    Ok(())
-                }
-                Err(err) => Err(Error::from(err)),
-            },
+    fn write_file_value(&mut self, file_num: usize, value: u8) -> Result<()> {
+        if file_num == STDOUT_FILE as usize {
+            self.acc = 1;
+            self.emit(OutputBinary(vec![value]));
+            return Ok(());
         }
+        self.check_writable(file_num)?;
+        self.acc = self.file_mut(file_num)?.write(&[value])? as u8;
+        Ok(())
     }
 
     fn skip_file(&mut self, file_num: usize, val: u8) -> Result<()> {
-        match &mut self.files[file_num] {
-            None => Err(Error::msg(format!("File {} not open", file_num))),
-            Some(file) => {
-                let mut buffer = vec![0_u8; val as usize];
-                match file.read(&mut buffer) {
-                    Ok(count) => {
-                        self.acc = count as u8;
-
-                        Ok(())
-                    }
-                    Err(err) => Err(Error::from(err)),
-                }
-            }
-        }
+        let mut buffer = vec![0_u8; val as usize];
+        self.acc = self.file_mut(file_num)?.read(&mut buffer)? as u8;
+        Ok(())
     }
 
     fn load_data_addr(&mut self, areg: u8, addr: u16, offset1: u8, offset2: u8) -> Result<()> {
         if (addr as usize + offset1 as usize) >= self.tape_data.len() {
             return Err(Error::msg(format!(
                 "Data access out of bounds {}, max {}",
-                addr + offset1 as u16,
-                self.tape_data[addr as usize]
+                addr as usize + offset1 as usize,
+                self.tape_data.len()
             )));
         }
         let subarray_count = self.tape_data[addr as usize];
@@ -848,31 +2632,31 @@ impl Device {
                 offset1, subarray_count
             )));
         }
-        let mut subarray_addr = 0;
-        if offset1 > 0 {
-            subarray_addr += 1;
-            for offset in 0..offset1 {
-                subarray_addr += self.tape_data[addr as usize + offset as usize] as u16;
-            }
-        }
-        let data_addr = addr + subarray_addr + offset2 as u16;
-        if data_addr as usize >= self.tape_data.len() {
+        let data_addr = if offset1 == 0 {
+            //0 is the count, after that the (low byte of the) length of each array
+            addr as usize + offset2 as usize * self.data_len_size()
+        } else {
+            self.data_array_range(addr, offset1)?.start + offset2 as usize
+        };
+        if data_addr >= self.tape_data.len() {
             return Err(Error::msg(format!(
                 "Data byte access out of bounds {}, max {}",
                 data_addr,
                 self.tape_data.len()
             )));
         }
-        match areg {
-            REG_A0 => self.addr_reg[0] = data_addr,
-            REG_A1 => self.addr_reg[1] = data_addr,
-            _ => return Err(Error::msg(format!("Invalid addr register: {:02X}", areg))),
-        }
-        Ok(())
+        self.set_addr_reg(areg, data_addr as u16)
     }
 
     fn print_data(&mut self, areg: u8) -> Result<()> {
         let addr = self.get_addr_reg_content(areg)? as usize;
+        if addr + self.acc as usize > self.tape_data.len() {
+            return Err(Error::msg(format!(
+                "Data access out of bounds {}, max {}",
+                addr + self.acc as usize,
+                self.tape_data.len()
+            )));
+        }
         for i in 0..self.acc as usize {
             self.log(format!("{}", self.tape_data[addr + i] as char));
         }
@@ -900,27 +2684,166 @@ impl Device {
     }
 
     fn print_tape_string(&mut self, data_addr: u16) -> Result<()> {
-        let length = self.tape_strings[data_addr as usize] as u16;
-        let start = (data_addr + 1) as usize;
-        let end = (data_addr + 1 + length) as usize;
-        let bytes = &self.tape_strings[start..end];
-        let msg = String::from_utf8(bytes.to_vec())?;
+        let msg = self.tape_string(data_addr)?;
         self.log(msg);
         Ok(())
     }
 
+    fn tape_string(&self, data_addr: u16) -> Result<String> {
+        let range = self.tape_string_range(data_addr)?;
+        Ok(String::from_utf8(self.tape_strings[range].to_vec())?)
+    }
+
+    ///Copy `range` of tape strings (or data if `is_data`) into memory at the address in `areg`, ACC is set to the length
+    fn load_tape_bytes(&mut self, areg: u8, range: Range<usize>, is_data: bool) -> Result<()> {
+        let dest = self.block_range(self.get_addr_reg_content(areg)?, range.len())?;
+        let source = if is_data {
+            &self.tape_data
+        } else {
+            &self.tape_strings
+        };
+        self.acc = range.len() as u8;
+        self.mem[dest.clone()].copy_from_slice(&source[range]);
+        self.record_write(dest);
+        Ok(())
+    }
+
+    ///Bytes of array `array` (1 is the first, as with LD) of the data at `data_addr`
+    fn data_array_range(&self, data_addr: u16, array: u8) -> Result<Range<usize>> {
+        let data_addr = data_addr as usize;
+        let count = *self.tape_data.get(data_addr).ok_or_else(|| {
+            Error::msg(format!(
+                "Data access out of bounds {}, max {}",
+                data_addr,
+                self.tape_data.len()
+            ))
+        })?;
+        if array == 0 || array > count {
+            return Err(Error::msg(format!(
+                "Data array {} out of bounds, must be 1 to {}",
+                array, count
+            )));
+        }
+        let len_size = self.data_len_size();
+        let lengths = (0..array as usize)
+            .map(|idx| {
+                let pos = data_addr + 1 + idx * len_size;
+                let bytes = self.tape_data.get(pos..pos + len_size)?;
+                Some(
+                    bytes
+                        .iter()
+                        .fold(0, |len, byte| (len << 8) + *byte as usize),
+                )
+            })
+            .collect::<Option<Vec<usize>>>()
+            .ok_or_else(|| Error::msg(format!("Data at {} is truncated", data_addr)))?;
+        let len = *lengths.last().unwrap();
+        let start = data_addr
+            + 1
+            + count as usize * len_size
+            + lengths[..lengths.len() - 1].iter().sum::<usize>();
+        if start + len > self.tape_data.len() {
+            return Err(Error::msg(format!(
+                "Data byte access out of bounds {}, max {}",
+                start + len,
+                self.tape_data.len()
+            )));
+        }
+        Ok(start..start + len)
+    }
+
+    ///Bytes used for each data array length
+    fn data_len_size(&self) -> usize {
+        if self.narrow_data {
+            1
+        } else {
+            2
+        }
+    }
+
+    ///Bytes of the tape string at `data_addr`, not including the length
+    fn tape_string_range(&self, data_addr: u16) -> Result<Range<usize>> {
+        if data_addr as usize >= self.tape_strings.len() {
+            return Err(Error::msg(format!(
+                "String access out of bounds {}, max {}",
+                data_addr,
+                self.tape_strings.len()
+            )));
+        }
+        let length = self.tape_strings[data_addr as usize] as usize;
+        let start = data_addr as usize + 1;
+        let end = start + length;
+        if end > self.tape_strings.len() {
+            return Err(Error::msg(format!(
+                "String at {} is {} bytes but only {} bytes remain",
+                data_addr,
+                length,
+                self.tape_strings.len() - start
+            )));
+        }
+        Ok(start..end)
+    }
+
+    ///ACC bytes of memory starting at `addr`
+    fn mem_string(&self, addr: u16) -> Result<String> {
+        let start = addr as usize;
+        let end = start + self.acc as usize;
+        if end > RAM_SIZE {
+            return Err(Error::msg(format!(
+                "String at {:04X} is {} bytes but only {} bytes remain",
+                addr,
+                self.acc,
+                RAM_SIZE - start
+            )));
+        }
+        Ok(String::from_utf8(self.mem[start..end].to_vec())?)
+    }
+
+    fn printx(&mut self, val: u8) {
+        self.log(format!("{:02X}", val));
+    }
+
     fn printc(&mut self, val: u8) {
         self.log(format!("{}", val as char));
     }
 
     fn set_time(&mut self) {
-        let time = Local::now();
-        let hour = time.hour() as u8;
-        let minute = time.minute() as u8;
-        let second = time.second() as u8;
-        self.data_reg[0] = second;
-        self.data_reg[1] = minute;
-        self.data_reg[2] = hour;
+        let seconds = match (self.start_time, self.clock.seconds_since_midnight()) {
+            (None, Some(seconds)) => seconds,
+            (start, _) => {
+                (start.unwrap_or(0) as u64 + self.virtual_millis() / 1000) % SECONDS_PER_DAY
+            }
+        };
+        self.data_reg[0] = (seconds % 60) as u8;
+        self.data_reg[1] = ((seconds / 60) % 60) as u8;
+        self.data_reg[2] = (seconds / 3600) as u8;
+    }
+
+    ///Milliseconds since the program started on the virtual clock
+    fn virtual_millis(&self) -> u64 {
+        self.cycles * 1000 / VIRTUAL_CYCLES_PER_SECOND + self.slept_millis
+    }
+
+    fn set_color(&mut self, color: u8) {
+        self.emit(SetColor(if color < 16 { Some(color) } else { None }));
+    }
+
+    ///Milliseconds since the program started, from the host clock unless using the virtual clock
+    fn current_millis(&self) -> u64 {
+        match (self.start_time, self.clock.millis()) {
+            (None, Some(millis)) => millis,
+            _ => self.virtual_millis(),
+        }
+    }
+
+    fn set_millis(&mut self) {
+        self.data_reg = (self.current_millis() as u32).to_be_bytes();
+    }
+
+    fn sleep(&mut self, millis: u8) {
+        if self.start_time.is_some() || !self.clock.sleep(millis as u64) {
+            self.slept_millis += millis as u64;
+        }
     }
 
     fn seed(&mut self, value: u8) -> Result<()> {
@@ -935,33 +2858,21 @@ impl Device {
     }
 
     fn swap(&mut self, reg1: u8, reg2: u8) -> Result<()> {
-        let is_addr_reg = |reg: u8| reg == REG_A0 || reg == REG_A1;
-        let data_reg_idx = |reg: u8| match reg {
-            REG_D0 => 0,
-            REG_D1 => 1,
-            REG_D2 => 2,
-            REG_D3 => 3,
-            _ => panic!("Impossible state, {}", reg),
-        };
-
-        if is_addr_reg(reg1) && is_addr_reg(reg2) {
-            self.addr_reg.swap(0, 1);
-        } else if !is_addr_reg(reg1) && !is_addr_reg(reg2) {
-            if reg1 != REG_ACC && reg2 != REG_ACC {
-                self.data_reg.swap(data_reg_idx(reg1), data_reg_idx(reg2))
-            } else if reg1 == REG_ACC && reg2 == REG_ACC {
-                //do nothing
-            } else if reg1 == REG_ACC {
-                let tmp = self.acc;
-                self.acc = self.data_reg[data_reg_idx(reg2)];
-                self.data_reg[data_reg_idx(reg2)] = tmp;
-            } else if reg2 == REG_ACC {
-                let tmp = self.acc;
-                self.acc = self.data_reg[data_reg_idx(reg1)];
-                self.data_reg[data_reg_idx(reg1)] = tmp;
-            }
-        } else {
-            return Err(Error::msg("Invalid registers, mix of data and address"));
+        let reg1 = Register::try_from(reg1)?;
+        let reg2 = Register::try_from(reg2)?;
+
+        match (reg1.is_addr_reg(), reg2.is_addr_reg()) {
+            (true, true) => self.addr_reg.swap(0, 1),
+            (false, false) => match (reg1.index(), reg2.index()) {
+                (Some(idx1), Some(idx2)) => self.data_reg.swap(idx1, idx2),
+                (None, None) => {
+                    //do nothing
+                }
+                (None, Some(idx)) | (Some(idx), None) => {
+                    std::mem::swap(&mut self.acc, &mut self.data_reg[idx])
+                }
+            },
+            _ => return Err(Error::msg("Invalid registers, mix of data and address")),
         }
 
         Ok(())
@@ -982,43 +2893,72 @@ impl Device {
                 value.overflowing_add(1)
             }
         };
-        match id {
-            REG_ACC => (self.acc, self.flags.overflow) = update(self.acc),
-            REG_D0 => (self.data_reg[0], self.flags.overflow) = update(self.data_reg[0]),
-            REG_D1 => (self.data_reg[1], self.flags.overflow) = update(self.data_reg[1]),
-            REG_D2 => (self.data_reg[2], self.flags.overflow) = update(self.data_reg[2]),
-            REG_D3 => (self.data_reg[3], self.flags.overflow) = update(self.data_reg[3]),
-            REG_A0 => (self.addr_reg[0], self.flags.overflow) = update16(self.addr_reg[0]),
-            REG_A1 => (self.addr_reg[1], self.flags.overflow) = update16(self.addr_reg[1]),
-            _ => return Err(Error::msg(format!("Invalid register: {:02X}", id))),
+        match Register::try_from(id)? {
+            Register::Acc => (self.acc, self.flags.overflow) = update(self.acc),
+            reg @ (Register::D0 | Register::D1 | Register::D2 | Register::D3) => {
+                let idx = reg.index().unwrap();
+                (self.data_reg[idx], self.flags.overflow) = update(self.data_reg[idx])
+            }
+            reg @ (Register::A0 | Register::A1) => {
+                let idx = reg.index().unwrap();
+                (self.addr_reg[idx], self.flags.overflow) = update16(self.addr_reg[idx])
+            }
         }
 
         Ok(())
     }
 
     fn compare(&mut self, lhs: u8, rhs: u8) {
-        match lhs.cmp(&rhs) {
-            Ordering::Less => self.acc = compare::LESSER,
-            Ordering::Equal => self.acc = compare::EQUAL,
-            Ordering::Greater => self.acc = compare::GREATER,
-        }
+        let (value, borrowed) = lhs.overflowing_sub(rhs);
+        self.set_compare_result(lhs.cmp(&rhs), borrowed, value & 0x80 != 0);
     }
 
     fn compare_data(&mut self, lhs: u8, addr: u16) -> Result<()> {
         let rhs = self.get_data_content(addr)?;
-        match lhs.cmp(&rhs) {
-            Ordering::Less => self.acc = compare::LESSER,
-            Ordering::Equal => self.acc = compare::EQUAL,
-            Ordering::Greater => self.acc = compare::GREATER,
-        }
+        self.compare(lhs, rhs);
         Ok(())
     }
 
     fn compare_16(&mut self, lhs: u16, rhs: u16) {
-        match lhs.cmp(&rhs) {
-            Ordering::Less => self.acc = compare::LESSER,
-            Ordering::Equal => self.acc = compare::EQUAL,
-            Ordering::Greater => self.acc = compare::GREATER,
+        let (value, borrowed) = lhs.overflowing_sub(rhs);
+        self.set_compare_result(lhs.cmp(&rhs), borrowed, value & 0x8000 != 0);
+    }
+
+    fn set_compare_result(&mut self, ordering: Ordering, borrowed: bool, negative: bool) {
+        if self.acc_compare {
+            self.acc = match ordering {
+                Ordering::Less => compare::LESSER,
+                Ordering::Equal => compare::EQUAL,
+                Ordering::Greater => compare::GREATER,
+            };
+        } else {
+            self.flags.zero = ordering == Ordering::Equal;
+            self.flags.carry = borrowed;
+            self.flags.negative = negative;
+        }
+    }
+
+    fn is_equal(&self) -> bool {
+        if self.acc_compare {
+            self.acc == compare::EQUAL
+        } else {
+            self.flags.zero
+        }
+    }
+
+    fn is_lesser(&self) -> bool {
+        if self.acc_compare {
+            self.acc == compare::LESSER
+        } else {
+            self.flags.carry
+        }
+    }
+
+    fn is_greater(&self) -> bool {
+        if self.acc_compare {
+            self.acc == compare::GREATER
+        } else {
+            !self.flags.carry && !self.flags.zero
         }
     }
 
@@ -1034,12 +2974,24 @@ impl Device {
         self.acc = value;
     }
 
+    ///ADD that also adds the overflow from the previous ADD/ADDC
+    fn add_carry(&mut self, lhs: u8, rhs: u8) {
+        let (value, overflowed) = lhs.overflowing_add(rhs);
+        let (value, carried) = value.overflowing_add(self.flags.overflow as u8);
+        self.flags.overflow = overflowed || carried;
+        self.acc = value;
+    }
+
+    ///SUB that also subtracts the borrow from the previous SUB/SUBC
+    fn sub_carry(&mut self, lhs: u8, rhs: u8) {
+        let (value, overflowed) = lhs.overflowing_sub(rhs);
+        let (value, borrowed) = value.overflowing_sub(self.flags.overflow as u8);
+        self.flags.overflow = overflowed || borrowed;
+        self.acc = value;
+    }
+
     fn load_data(&mut self, dest: u8, areg: u8) -> Result<()> {
-        let data_addr = match areg {
-            REG_A0 => self.addr_reg[0],
-            REG_A1 => self.addr_reg[1],
-            _ => return Err(Error::msg(format!("Invalid addr register: {:02X}", areg))),
-        };
+        let data_addr = self.get_addr_reg_content(areg)?;
         let data = self.get_data_content(data_addr)?;
         self.set_data_reg(dest, data)?;
         Ok(())
@@ -1056,93 +3008,292 @@ impl Device {
         Ok(self.tape_data[addr as usize])
     }
 
-    fn store(&mut self, addr: u16) {
-        self.mem[addr as usize] = self.acc;
+    fn store(&mut self, addr: u16) -> Result<()> {
+        if self.is_mmio(addr) {
+            return self.mmio_write(addr, self.acc);
+        }
+        self.write_mem(addr, self.acc)
+    }
+
+    ///Write a byte to RAM from outside the program (i.e. the debugger), bounds and watchpoints
+    ///are checked the same as for STORE
+    pub fn poke(&mut self, addr: u16, value: u8) -> Result<()> {
+        self.write_mem(addr, value)
+    }
+
+    ///Write a byte to RAM, recording the address if it's inside a watchpoint range
+    fn write_mem(&mut self, addr: u16, value: u8) -> Result<()> {
+        let range = self.mem_range(addr, 1)?;
+        self.mem[addr as usize] = value;
+        self.record_write(range);
+        Ok(())
+    }
+
+    ///Record a write to `range` for strict mode and record the first address in `range` that's
+    ///inside a watchpoint range, if one hasn't already been hit
+    fn record_write(&mut self, range: Range<usize>) {
+        self.mark_initialized(range.clone());
+        self.record_canary_write(range.clone());
+        if self.watchpoint_hit.is_none() {
+            self.watchpoint_hit = self
+                .watchpoints
+                .iter()
+                .map(|(start, end)| (range.start.max(*start as usize), *end as usize))
+                .filter(|(first, end)| *first < range.end && first <= end)
+                .map(|(first, _)| first as u16)
+                .min();
+        }
+    }
+
+    ///Set the bytes for the MEMSET `instruction` to `value`
+    fn itoa(&mut self, areg: u8, value: u16) -> Result<()> {
+        let digits = value.to_string();
+        let dest = self.block_range(self.get_addr_reg_content(areg)?, digits.len())?;
+        self.mem[dest.clone()].copy_from_slice(digits.as_bytes());
+        self.acc = digits.len() as u8;
+        self.record_write(dest);
+        Ok(())
+    }
+
+    ///Parse the leading digits of the ACC bytes at `areg`, sets overflow (leaving `reg` unchanged) if the value doesn't fit
+    fn atoi(&mut self, areg: u8, reg: u8) -> Result<()> {
+        let range = self.block_range(self.get_addr_reg_content(areg)?, self.acc as usize)?;
+        let max = match Register::try_from(reg) {
+            Ok(register) if register.is_addr_reg() => u16::MAX as u32,
+            _ => u8::MAX as u32,
+        };
+        let mut count = 0;
+        let mut value: u32 = 0;
+        for byte in self.mem[range]
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit())
+        {
+            count += 1;
+            value = (value * 10 + (byte - b'0') as u32).min(max + 1);
+        }
+        self.acc = count;
+        self.flags.overflow = value > max;
+        if value <= max {
+            if max > u8::MAX as u32 {
+                self.set_addr_reg(reg, value as u16)?;
+            } else {
+                self.set_data_reg(reg, value as u8)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn mem_set(&mut self, instruction: &Instruction, value: u8) -> Result<()> {
+        let dest = self.get_addr_reg_content(instruction[1])?;
+        let len = self.get_reg_content(instruction[3])? as usize;
+        let dest = self.block_range(dest, len)?;
+        self.mem[dest.clone()].fill(value);
+        self.record_write(dest);
+        Ok(())
+    }
+
+    ///RAM used by MEMCPY and MEMSET, fails if it's past the end of memory or includes the memory mapped I/O page
+    fn block_range(&self, addr: u16, len: usize) -> Result<Range<usize>> {
+        let range = self.mem_range(addr, len)?;
+        let mmio = MMIO_ADDR as usize..MMIO_ADDR as usize + MMIO_SIZE;
+        if self.mmio && range.start < mmio.end && mmio.start < range.end {
+            return Err(Error::msg(format!(
+                "{} bytes at {:04X} includes memory mapped I/O, use MEMR/MEMW instead",
+                len, addr
+            )));
+        }
+        Ok(range)
+    }
+
+    ///When checking the stack remember which instruction first wrote to each canary in `range`
+    fn record_canary_write(&mut self, range: Range<usize>) {
+        if !self.stack_check {
+            return;
+        }
+        for frame in &self.frames {
+            for addr in [frame.fp, frame.fp.saturating_add(5)] {
+                if range.contains(&(addr as usize))
+                    && !self.canary_writes.iter().any(|(canary, _)| *canary == addr)
+                {
+                    self.canary_writes.push((addr, self.pc));
+                }
+            }
+        }
+    }
+
+    fn mark_initialized(&mut self, range: Range<usize>) {
+        if let Some(initialized) = &mut self.initialized {
+            initialized[range].fill(true);
+        }
+    }
+
+    ///In strict mode report the first byte of `range` that hasn't been written
+    fn check_initialized(&mut self, range: Range<usize>) {
+        let uninitialized = match &mut self.initialized {
+            Some(initialized) => {
+                let addr = initialized[range.clone()]
+                    .iter()
+                    .position(|written| !written)
+                    .map(|offset| range.start + offset);
+                if let Some(addr) = addr {
+                    initialized[addr] = true;
+                }
+                addr
+            }
+            None => None,
+        };
+        if let Some(addr) = uninitialized {
+            self.emit(Output::UninitializedRead(self.pc, addr as u16));
+        }
     }
 
     fn jump(&mut self, addr: u16) {
         self.pc = addr;
     }
 
-    fn sp_add(&mut self, value: u8) {
-        self.sp = self.sp.saturating_sub(1);
+    fn sp_add(&mut self, value: u8) -> Result<()> {
+        if self.sp <= self.stack_limit {
+            return Err(Error::msg(format!(
+                "Stack overflow, SP {:04X} would pass stack limit {:04X}",
+                self.sp, self.stack_limit
+            )));
+        }
+        self.sp -= 1;
         self.mem[self.sp as usize] = value;
+        self.mark_initialized(self.sp as usize..self.sp as usize + 1);
+        self.record_canary_write(self.sp as usize..self.sp as usize + 1);
+        Ok(())
     }
 
     fn sp_remove(&mut self) -> Result<u8> {
         if self.sp as usize >= self.mem.len() {
-            return Err(Error::msg("Attempted to pop beyond memory"));
+            return Err(Error::msg(
+                "Stack underflow, attempted to pop beyond memory",
+            ));
         }
         let value = self.mem[self.sp as usize];
         self.sp = self.sp.saturating_add(1).min(RAM_SIZE as u16);
         Ok(value)
     }
 
-    fn stack_push(&mut self, value: u8) {
-        self.sp_add(value);
+    fn stack_push(&mut self, value: u8) -> Result<()> {
+        self.sp_add(value)
     }
 
     fn stack_push_reg(&mut self, reg: u8) -> Result<()> {
-        if matches!(reg, REG_ACC | REG_D0 | REG_D1 | REG_D2 | REG_D3) {
-            self.stack_push(self.get_reg_content(reg)?);
-        } else if reg == REG_A0 {
-            let bytes = self.addr_reg[0].to_le_bytes();
-            self.sp_add(bytes[0]);
-            self.sp_add(bytes[1]);
-        } else if reg == REG_A1 {
-            let bytes = self.addr_reg[1].to_le_bytes();
-            self.sp_add(bytes[0]);
-            self.sp_add(bytes[1]);
+        if Register::try_from(reg)?.is_addr_reg() {
+            let bytes = self.get_addr_reg_content(reg)?.to_le_bytes();
+            self.sp_add(bytes[0])?;
+            self.sp_add(bytes[1])?;
         } else {
-            return Err(Error::msg(format!("Invalid register: {:02X}", reg)));
+            self.stack_push(self.get_reg_content(reg)?)?;
         }
 
         Ok(())
     }
 
     fn stack_pop(&mut self, reg: u8) -> Result<()> {
-        match reg {
-            REG_ACC => self.acc = self.sp_remove()?,
-            REG_D0 => self.data_reg[0] = self.sp_remove()?,
-            REG_D1 => self.data_reg[1] = self.sp_remove()?,
-            REG_D2 => self.data_reg[2] = self.sp_remove()?,
-            REG_D3 => self.data_reg[3] = self.sp_remove()?,
-            REG_A0 => self.addr_reg[0] = u16::from_be_bytes([self.sp_remove()?, self.sp_remove()?]),
-            REG_A1 => self.addr_reg[1] = u16::from_be_bytes([self.sp_remove()?, self.sp_remove()?]),
-            _ => return Err(Error::msg(format!("Invalid register: {:02X}", reg))),
+        if Register::try_from(reg)?.is_addr_reg() {
+            let value = u16::from_be_bytes([self.sp_remove()?, self.sp_remove()?]);
+            self.set_addr_reg(reg, value)
+        } else {
+            let value = self.sp_remove()?;
+            self.set_data_reg(reg, value)
+        }
+    }
+
+    fn stack_drop(&mut self, count: u8) -> Result<()> {
+        for _ in 0..count {
+            self.sp_remove()?;
         }
+        Ok(())
+    }
 
+    fn stack_reserve(&mut self, count: u8) -> Result<()> {
+        for _ in 0..count {
+            self.sp_add(0)?;
+        }
+        self.stack_base = self.sp;
         Ok(())
     }
 
-    fn stack_arg(&mut self, reg: u8, offset: u8) -> Result<()> {
-        let addr = self.fp.saturating_add(offset.saturating_add(3) as u16) as usize;
-        let addr_second = self.fp.saturating_add((offset.saturating_add(4)) as u16) as usize;
-        if addr >= RAM_SIZE || ((reg == REG_A0 || reg == REG_A1) && addr_second >= RAM_SIZE) {
+    ///Address of the first byte of the local at `offset` (starting at 1) below FP
+    fn local_addr(&self, offset: u8, len: u16) -> Result<usize> {
+        let end = offset as u16 + len - 1;
+        if offset == 0 || end > self.fp || self.fp - end < self.sp {
             return Err(Error::msg(format!(
-                "Attempted to access argument beyond memory {}, max {}",
-                addr,
-                RAM_SIZE - 1
+                "Local {} is outside the reserved locals (FP {:04X}, SP {:04X})",
+                offset, self.fp, self.sp
             )));
         }
-        match reg {
-            REG_ACC => self.acc = self.mem[addr],
-            REG_D0 => self.data_reg[0] = self.mem[addr],
-            REG_D1 => self.data_reg[1] = self.mem[addr],
-            REG_D2 => self.data_reg[2] = self.mem[addr],
-            REG_D3 => self.data_reg[3] = self.mem[addr],
-            REG_A0 => {
-                self.addr_reg[0] = u16::from_be_bytes([self.mem[addr_second], self.mem[addr]])
-            }
-            REG_A1 => {
-                self.addr_reg[1] = u16::from_be_bytes([self.mem[addr_second], self.mem[addr]])
-            }
-            _ => return Err(Error::msg(format!("Invalid register: {:02X}", reg))),
+        Ok((self.fp - end) as usize)
+    }
+
+    fn local_read(&mut self, reg: u8, offset: u8) -> Result<()> {
+        if Register::try_from(reg)?.is_addr_reg() {
+            let addr = self.local_addr(offset, 2)?;
+            self.check_initialized(addr..addr + 2);
+            let value = u16::from_be_bytes([self.mem[addr], self.mem[addr + 1]]);
+            self.set_addr_reg(reg, value)
+        } else {
+            let addr = self.local_addr(offset, 1)?;
+            self.check_initialized(addr..addr + 1);
+            self.set_data_reg(reg, self.mem[addr])
+        }
+    }
+
+    fn local_write(&mut self, offset: u8, reg: u8) -> Result<()> {
+        if Register::try_from(reg)?.is_addr_reg() {
+            let addr = self.local_addr(offset, 2)? as u16;
+            let bytes = self.get_addr_reg_content(reg)?.to_be_bytes();
+            self.write_mem(addr, bytes[0])?;
+            self.write_mem(addr + 1, bytes[1])?;
+        } else {
+            let addr = self.local_addr(offset, 1)? as u16;
+            self.write_mem(addr, self.get_reg_content(reg)?)?;
         }
         Ok(())
     }
 
-    ///Stack explanation
+    fn stack_arg(&mut self, reg: u8, offset: u8) -> Result<()> {
+        let register = Register::try_from(reg)?;
+        let frame = self
+            .frames
+            .last()
+            .filter(|frame| frame.fp == self.fp)
+            .ok_or_else(|| {
+                Error::msg(format!(
+                    "ARG used outside of a CALL, there's no frame for FP {:04X}",
+                    self.fp
+                ))
+            })?;
+        let last = if register.is_addr_reg() {
+            offset as u16 + 1
+        } else {
+            offset as u16
+        };
+        if offset == 0 || last > frame.arg_len {
+            return Err(Error::msg(format!(
+                "Argument {} is outside the {} bytes pushed before CALL (FP {:04X})",
+                offset, frame.arg_len, self.fp
+            )));
+        }
+        let addr = self.fp as usize + self.frame_header_len() as usize + offset as usize - 1;
+        let addr_second = addr + 1;
+        if register.is_addr_reg() {
+            self.check_initialized(addr..addr + 1);
+            self.check_initialized(addr_second..addr_second + 1);
+            self.set_addr_reg(
+                reg,
+                u16::from_be_bytes([self.mem[addr_second], self.mem[addr]]),
+            )
+        } else {
+            self.check_initialized(addr..addr + 1);
+            self.set_data_reg(reg, self.mem[addr])
+        }
+    }
+
+    //Stack explanation
     //Stack is written downwards from top of memory, so @FFFF is 0
     //FP points to the last byte of the previous frame (starts at FFFF)
     //SP points to the last byte of the current frame, which is also the end of the stack (starts at FFFF)
@@ -1153,6 +3304,10 @@ impl Device {
     //FP is set to SP
     //Arguments/parameters are stored relative to the FP and must be manually PUSHed and POPed by caller
     //and are access via ARG reg pos e.g. arg d0 1 gets FP+1 and sets in D0
+    //The bytes pushed since the caller's frame (or LOCAL) started are recorded as a StackFrame so
+    //ARG can't read past them
+    //With stack checking a STACK_CANARY byte is pushed before the FP and after the PC, so FP points
+    //at a canary and the frame is 6 bytes
     //So if CALLing calc with 5 the code would be
     //
     //push 5
@@ -1165,21 +3320,70 @@ impl Device {
     //Return:  SP=1 FP=1 PC=5 Stack=05 [param]
     //Pop:     SP=0 FP=0 PC=7 Stack=
 
-    fn stack_call(&mut self, addr: u16, from_reg: bool) {
-        let bytes = self.fp.to_be_bytes();
-        self.sp_add(bytes[0]);
-        self.sp_add(bytes[1]);
-
+    fn stack_call(&mut self, addr: u16, from_reg: bool) -> Result<()> {
         let offset = if from_reg { 2 } else { 3 };
-        let bytes = (self.pc.wrapping_add(offset)).to_be_bytes();
-        self.sp_add(bytes[0]);
-        self.sp_add(bytes[1]);
+        let arg_len = self.stack_base.saturating_sub(self.sp);
+        self.push_frame(self.pc.wrapping_add(offset), addr, arg_len)
+    }
+
+    ///Bytes pushed by CALL, FP and the return address plus canaries when checking the stack
+    fn frame_header_len(&self) -> u16 {
+        if self.stack_check {
+            6
+        } else {
+            4
+        }
+    }
+
+    ///Push FP and `return_addr` then jump to `addr`, `arg_len` bytes above SP are the new frame's arguments
+    fn push_frame(&mut self, return_addr: u16, addr: u16, arg_len: u16) -> Result<()> {
+        let header_len = self.frame_header_len();
+        if self.sp < self.stack_limit.saturating_add(header_len) {
+            return Err(Error::msg(format!(
+                "Stack overflow, CALL needs {} bytes but SP {:04X} is too close to stack limit {:04X}",
+                header_len, self.sp, self.stack_limit
+            )));
+        }
+        if self.stack_check {
+            self.sp_add(STACK_CANARY)?;
+        }
+        let bytes = self.fp.to_be_bytes();
+        self.sp_add(bytes[0])?;
+        self.sp_add(bytes[1])?;
+
+        let bytes = return_addr.to_be_bytes();
+        self.sp_add(bytes[0])?;
+        self.sp_add(bytes[1])?;
+        if self.stack_check {
+            self.sp_add(STACK_CANARY)?;
+        }
 
         self.pc = addr;
         self.fp = self.sp;
+        self.frames.push(StackFrame {
+            fp: self.fp,
+            arg_len,
+            caller_base: self.stack_base,
+        });
+        self.stack_base = self.fp;
+        Ok(())
     }
 
     fn stack_return(&mut self) -> Result<()> {
+        if self.stack_check {
+            self.check_canaries()?;
+        }
+        let frame = match self.frames.last() {
+            Some(frame) if frame.fp == self.fp => self.frames.pop(),
+            _ => None,
+        };
+        //discard locals
+        if (self.fp as usize) < RAM_SIZE {
+            self.sp = self.fp;
+        }
+        if self.stack_check {
+            self.sp_remove()?;
+        }
         let mut bytes = [0; 2];
         bytes[1] = self.sp_remove()?;
         bytes[0] = self.sp_remove()?;
@@ -1187,7 +3391,21 @@ impl Device {
 
         bytes[1] = self.sp_remove()?;
         bytes[0] = self.sp_remove()?;
-        self.fp = u16::from_be_bytes(bytes);
+        let fp = u16::from_be_bytes(bytes);
+        if self.stack_check {
+            self.sp_remove()?;
+            let canaries = [self.fp, self.fp.saturating_add(5)];
+            self.canary_writes
+                .retain(|(canary, _)| !canaries.contains(canary));
+        }
+        if fp < self.sp || fp as usize > RAM_SIZE {
+            return Err(Error::msg(format!(
+                "Stack corrupted, RET would restore FP to {:04X} but SP is {:04X}",
+                fp, self.sp
+            )));
+        }
+        self.fp = fp;
+        self.stack_base = frame.map(|frame| frame.caller_base).unwrap_or(fp);
 
         while self.fp < self.sp {
             self.sp_remove()?;
@@ -1195,6 +3413,46 @@ impl Device {
 
         Ok(())
     }
+
+    ///Fail if either canary of the current frame has changed, see [`Device::set_stack_check`]
+    fn check_canaries(&mut self) -> Result<()> {
+        for addr in [self.fp, self.fp.saturating_add(5)] {
+            let value = match self.mem.get(addr as usize) {
+                Some(value) => *value,
+                //RET without a frame, this is reported as a stack underflow
+                None => continue,
+            };
+            if value == STACK_CANARY {
+                continue;
+            }
+            let writer = self
+                .canary_writes
+                .iter()
+                .find(|(canary, _)| *canary == addr)
+                .map(|(_, pc)| *pc);
+            if let Some(pc) = writer {
+                self.emit(Output::StackCorrupted(pc, addr));
+            }
+            return Err(Error::msg(format!(
+                "Stack corrupted, canary at {:04X} for the frame at FP {:04X} is {:02X} instead of {:02X}",
+                addr, self.fp, value, STACK_CANARY
+            )));
+        }
+        Ok(())
+    }
+}
+
+///Name of the memory mapped I/O register at `addr`, if there is one
+pub fn mmio_register_name(addr: u16) -> Option<&'static str> {
+    match addr {
+        MMIO_CHAR_OUT => Some("char out"),
+        MMIO_NUM_OUT => Some("num out"),
+        MMIO_INPUT_STATUS => Some("input status"),
+        MMIO_INPUT_CHAR => Some("input char"),
+        MMIO_RAND => Some("rand"),
+        _ if (MMIO_MILLIS..MMIO_MILLIS + 4).contains(&addr) => Some("millis"),
+        _ => None,
+    }
 }
 
 fn addr(byte1: u8, byte2: u8) -> u16 {
@@ -1,50 +1,376 @@
+pub mod client;
+mod clock;
+#[cfg(feature = "terminal")]
 mod debug_device;
+#[cfg(feature = "terminal")]
+mod debug_diff;
+#[cfg(feature = "terminal")]
+mod debug_expr;
+#[cfg(feature = "terminal")]
+mod debug_script;
+#[cfg(feature = "terminal")]
+mod display_device;
+pub mod files;
 mod input;
 pub mod internals;
+#[cfg(feature = "terminal")]
+mod output_log;
 mod piped_device;
+#[cfg(feature = "terminal")]
+mod profiler;
+pub mod protocol;
+pub mod runner;
+pub mod scheduler;
+#[cfg(feature = "terminal")]
 mod std_device;
+pub mod storage;
+#[cfg(feature = "terminal")]
 mod util;
 
-use crate::constants::hardware::{ADDR_REG_COUNT, DATA_REG_COUNT, RAM_SIZE};
+use crate::assembler::debug_model::DebugModel;
+use crate::constants::hardware::{
+    ADDR_REG_COUNT, DATA_REG_COUNT, FLAG_CARRY, FLAG_NEGATIVE, FLAG_OVERFLOW, FLAG_ZERO,
+    MAX_DISPLAY_CELLS, RAM_SIZE,
+};
+#[cfg(feature = "terminal")]
+use crate::coverage;
+#[cfg(feature = "terminal")]
 use crate::device::debug_device::{setup_terminal, shutdown_terminal, DebugDevice};
+#[cfg(feature = "terminal")]
+use crate::device::debug_script::parse_script;
+#[cfg(feature = "terminal")]
+use crate::device::display_device::DisplayDevice;
+use crate::device::files::MemoryFileProvider;
+#[cfg(feature = "fs")]
+use crate::device::files::SandboxFileProvider;
+#[cfg(feature = "terminal")]
+use crate::device::input::{read_char, read_str, InputScript};
+#[cfg(feature = "terminal")]
+use crate::device::internals::RunResult;
+use crate::device::internals::{Capabilities, Determinism, Device};
 use crate::device::piped_device::PipedDevice;
+#[cfg(feature = "terminal")]
+use crate::device::profiler::format_report;
+use crate::device::protocol::PipedFormat;
+#[cfg(feature = "terminal")]
+use crate::device::runner::{run_parsed_tape, ExitReason};
+#[cfg(feature = "terminal")]
+use crate::device::scheduler::{Scheduled, Scheduler};
+#[cfg(feature = "terminal")]
 use crate::device::std_device::StdDevice;
-use crate::tape_reader::read_tape;
-use anyhow::Result;
+#[cfg(feature = "terminal")]
+use crate::device::storage::save_path;
+#[cfg(feature = "terminal")]
+use crate::diff::check_expected;
+use crate::tape_reader::{link_data_tapes, read_tape_strict};
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
 use std::fs::read_to_string;
+use std::path::Path;
+use std::path::PathBuf;
 
-pub fn start(path: &str, input_paths: Vec<&str>) -> Result<()> {
-    let tape = read_tape(path)?;
+///Settings for running a tape outside of the debugger
+#[derive(Debug, Default, Clone)]
+pub struct RunOptions {
+    pub capabilities: Capabilities,
+    pub max_cycles: Option<u64>,
+    ///Stop [`runner::run_tape`] after this many bytes of output
+    pub max_output: Option<usize>,
+    pub determinism: Determinism,
+    ///Print execution counts when the program ends
+    pub profile: bool,
+    ///Add the executed instructions to this coverage file when the program ends, see [`crate::coverage`]
+    pub coverage: Option<String>,
+    ///File to read keyboard input from instead of the terminal, see [`InputScript`]
+    pub input_script: Option<String>,
+    ///Keyboard input in the input script format, used by [`runner::run_tape`] if `input_script` isn't set
+    pub input_lines: Vec<String>,
+    ///Data tapes to load external data from, see [`link_data_tapes`]
+    pub data_tapes: Vec<String>,
+    ///Directories FOPEN can open files by name from, any path if empty
+    pub allowed_paths: Vec<PathBuf>,
+    ///Input file numbers that are opened read only, see [`Device::set_read_only_files`]
+    pub read_only_files: Vec<usize>,
+    ///Arguments for the program, read with ARGS
+    pub args: Vec<String>,
+    ///File for SAVE and LOADKV, see [`storage::save_path`]
+    pub save_file: Option<PathBuf>,
+    ///Run as a fantasy console with a character framebuffer of this size
+    pub display: Option<DisplaySize>,
+    ///Files for FOPEN to use instead of the filesystem
+    pub files: Option<MemoryFileProvider>,
+    ///Directory all files must be in, must already be canonical, see [`SandboxFileProvider`]
+    pub sandbox: Option<PathBuf>,
+    ///Map device I/O into memory, see [`Device::set_mmio`]
+    pub mmio: bool,
+    ///Warn when memory is read before it's written, see [`Device::set_strict`]
+    pub strict: bool,
+    ///Put canaries around each CALL's saved FP and return address, see [`Device::set_stack_check`]
+    pub check_stack: bool,
+}
+
+///Size of the framebuffer in characters for `--display`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct DisplaySize {
+    pub width: u8,
+    pub height: u8,
+}
+
+impl DisplaySize {
+    ///Parse `WxH`, e.g. `40x20`
+    pub fn parse(text: &str) -> Result<DisplaySize> {
+        let invalid = || {
+            Error::msg(format!(
+                "Invalid display size '{}', must be WxH (i.e. 40x20)",
+                text
+            ))
+        };
+        let (width, height) = text.split_once('x').ok_or_else(invalid)?;
+        let width = width.trim().parse::<u8>().map_err(|_| invalid())?;
+        let height = height.trim().parse::<u8>().map_err(|_| invalid())?;
+        let size = DisplaySize { width, height };
+        if width == 0 || height == 0 {
+            return Err(invalid());
+        }
+        if size.cells() > MAX_DISPLAY_CELLS {
+            return Err(Error::msg(format!(
+                "Display {} is too large, max {} characters",
+                text, MAX_DISPLAY_CELLS
+            )));
+        }
+        Ok(size)
+    }
+
+    pub fn cells(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+}
+
+impl RunOptions {
+    fn configure(&self, device: &mut Device) {
+        device.set_capabilities(self.capabilities);
+        device.set_determinism(self.determinism);
+        device.set_allowed_paths(self.allowed_paths.clone());
+        device.set_read_only_files(self.read_only_files.clone());
+        device.set_args(self.args.clone());
+        if let Some(path) = &self.save_file {
+            device.set_save_file(path.clone());
+        }
+        device.set_mmio(self.mmio);
+        device.set_strict(self.strict);
+        device.set_stack_check(self.check_stack);
+        #[cfg(feature = "fs")]
+        if let Some(root) = &self.sandbox {
+            device.set_file_provider(Box::new(SandboxFileProvider::new(root.clone())));
+        }
+        if let Some(files) = &self.files {
+            device.set_file_provider(Box::new(files.clone()));
+        }
+        if let Some(max) = self.max_cycles {
+            device.set_max_cycles(max);
+        }
+        if self.profile || self.coverage.is_some() {
+            device.enable_profiling();
+        }
+    }
+}
+
+///Returns the exit code if the program ended with EXIT
+#[cfg(feature = "terminal")]
+pub fn start(path: &str, input_paths: Vec<&str>, options: RunOptions) -> Result<Option<u8>> {
+    let mut tape = read_tape_strict(path)?;
+    link_data_tapes(&mut tape, &options.data_tapes)?;
+    let script = match &options.input_script {
+        Some(script_path) => Some(InputScript::read(script_path)?),
+        None => None,
+    };
+
+    if let Some(size) = options.display {
+        let mut device = DisplayDevice::new(
+            tape.ops,
+            tape.strings,
+            tape.data,
+            input_paths.iter().map(|str| str.to_string()).collect(),
+            &options,
+            size,
+        );
+        device.set_tape_version(tape.tape_version);
+        device.set_entry(tape.entry);
+        return device.run();
+    }
 
     println!("Running {} v{}", tape.name, tape.version);
 
+    let ops = tape.ops.clone();
     let mut device = StdDevice::new(
         tape.ops,
         tape.strings,
         tape.data,
         input_paths.iter().map(|str| str.to_string()).collect(),
+        &options,
     );
-    device.run();
+    device.set_tape_version(tape.tape_version);
+    device.set_entry(tape.entry);
+    if let Some(script) = script {
+        device.set_input_script(script);
+    }
+    if options.strict || options.check_stack {
+        if let Some(debug) = read_sibling_debug_file(path) {
+            device.set_debug(debug);
+        }
+    }
+    let exit_code = device.run();
 
-    Ok(())
+    if let Some(profile) = device.profile().filter(|_| options.profile) {
+        let debug = read_sibling_debug_file(path);
+        println!();
+        for line in format_report(profile, &ops, debug.as_ref()) {
+            println!("{}", line);
+        }
+    }
+    if let (Some(coverage_path), Some(profile)) = (&options.coverage, device.profile()) {
+        coverage::record(coverage_path, path, profile)?;
+    }
+
+    Ok(exit_code)
 }
 
-pub fn start_piped(path: &str, input_paths: Vec<&str>) -> Result<()> {
-    let tape = read_tape(path)?;
+///Run headlessly and compare the printed text against `expected_path`, see [`check_expected`]
+#[cfg(feature = "terminal")]
+pub fn start_expect_output(
+    path: &str,
+    input_paths: Vec<&str>,
+    options: RunOptions,
+    expected_path: &str,
+) -> Result<()> {
+    let tape = read_tape_strict(path)?;
+    let report = run_parsed_tape(
+        tape,
+        input_paths.iter().map(|str| str.to_string()).collect(),
+        options,
+    )?;
+    if !report.stderr.is_empty() {
+        eprintln!("{}", report.stderr.trim_end());
+    }
+    if !matches!(report.exit, ExitReason::Halt | ExitReason::EoF) {
+        eprintln!("Program stopped: {:?}", report.exit);
+    }
+    let lines: Vec<String> = report.stdout.lines().map(String::from).collect();
+    check_expected("Output", expected_path, &lines)
+}
+
+///Run several tapes at once with [`Scheduler`], returns the first non zero EXIT code
+#[cfg(feature = "terminal")]
+pub fn start_multi(paths: Vec<&str>, options: RunOptions) -> Result<Option<u8>> {
+    let mut devices = vec![];
+    for path in paths {
+        let mut tape = read_tape_strict(path)?;
+        link_data_tapes(&mut tape, &options.data_tapes)?;
+        println!("Running {} v{}", tape.name, tape.version);
+        let mut device = Device::new(tape.ops, tape.strings, tape.data, vec![]);
+        options.configure(&mut device);
+        //each tape has its own save file
+        if options.save_file.is_some() {
+            device.set_save_file(save_path(Path::new(path), options.sandbox.as_deref()));
+        }
+        device.set_tape_version(tape.tape_version);
+        device.set_entry(tape.entry);
+        device.close_stdin();
+        let name = Path::new(path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_string());
+        devices.push((name, device));
+    }
+
+    let mut scheduler = Scheduler::new(devices);
+    loop {
+        let scheduled = scheduler.step();
+        for line in scheduler.take_lines() {
+            println!("{}", line);
+        }
+        match scheduled {
+            Scheduled::Done => break,
+            Scheduled::Stepped(idx, RunResult::CharInputRequested) => {
+                let chr = read_char()?;
+                scheduler.task_mut(idx).device.push_char(chr);
+            }
+            Scheduled::Stepped(idx, RunResult::StringInputRequested) => {
+                let input = read_str();
+                scheduler.task_mut(idx).device.push_bytes(&input);
+            }
+            Scheduled::Stepped(..) => {}
+        }
+    }
+    scheduler.flush();
+    for line in scheduler.take_lines() {
+        println!("{}", line);
+    }
+    for task in scheduler.tasks() {
+        if task.finished == Some(RunResult::SerialRequested) {
+            eprintln!("[{}] Stopped waiting for serial input", task.name);
+        }
+    }
+    Ok(scheduler
+        .tasks()
+        .iter()
+        .find_map(|task| match task.finished {
+            Some(RunResult::Exit(code)) if code != 0 => Some(code),
+            _ => None,
+        }))
+}
+
+///Debug file written by the assembler next to the tape, i.e. program.debug for program.tape
+pub(crate) fn read_sibling_debug_file(tape_path: &str) -> Option<DebugModel> {
+    let debug_path = Path::new(tape_path).with_extension("debug");
+    let text = read_to_string(debug_path).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+///Lines of the .basm file next to the tape, i.e. program.basm for program.tape, empty if missing
+#[cfg(feature = "terminal")]
+pub(crate) fn read_sibling_source(tape_path: &str) -> Vec<String> {
+    read_to_string(Path::new(tape_path).with_extension("basm"))
+        .map(|text| text.lines().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+pub fn start_piped(
+    path: &str,
+    input_paths: Vec<&str>,
+    options: RunOptions,
+    format: PipedFormat,
+) -> Result<()> {
+    let mut tape = read_tape_strict(path)?;
+    link_data_tapes(&mut tape, &options.data_tapes)?;
 
     let mut device = PipedDevice::new(
         tape.ops,
         tape.strings,
         tape.data,
         input_paths.iter().map(|str| str.to_string()).collect(),
+        &options,
     );
-    device.run();
-
-    Ok(())
+    device.set_tape_version(tape.tape_version);
+    device.set_entry(tape.entry);
+    device.run(format)
 }
 
-pub fn start_debug(path: &str, debug_path: &str, input_paths: Vec<&str>) -> Result<()> {
-    let tape = read_tape(path)?;
+#[cfg(feature = "terminal")]
+#[allow(clippy::too_many_arguments)]
+pub fn start_debug(
+    path: &str,
+    debug_path: &str,
+    input_paths: Vec<&str>,
+    read_only_files: Vec<usize>,
+    capabilities: Capabilities,
+    mmio: bool,
+    strict: bool,
+    check_stack: bool,
+    script_path: Option<&str>,
+) -> Result<()> {
+    let mut tape = read_tape_strict(path)?;
+    link_data_tapes(&mut tape, &[])?;
     let debug_info_text = read_to_string(debug_path).expect("Unable to read debug info file");
     let debug_info = serde_json::from_str(&debug_info_text).expect("Unable to parse debug info");
 
@@ -54,7 +380,24 @@ pub fn start_debug(path: &str, debug_path: &str, input_paths: Vec<&str>) -> Resu
         tape.data,
         debug_info,
         input_paths.iter().map(|str| str.to_string()).collect(),
+        capabilities,
     );
+    device.set_tape_version(tape.tape_version);
+    device.set_entry(tape.entry);
+    device.set_mmio(mmio);
+    device.set_strict(strict);
+    device.set_stack_check(check_stack);
+    device.set_read_only_files(read_only_files);
+    device.set_save_file(save_path(Path::new(path), None));
+    device.set_reload_paths(path, debug_path);
+    device.set_source(read_sibling_source(path));
+
+    if let Some(script_path) = script_path {
+        let text = read_to_string(script_path)
+            .map_err(|err| Error::msg(format!("Unable to read {}: {}", script_path, err)))?;
+        let commands = parse_script(&text)?;
+        return device.run_script(&commands, &mut std::io::stdout());
+    }
 
     setup_terminal()?;
     device.run()?;
@@ -63,23 +406,141 @@ pub fn start_debug(path: &str, debug_path: &str, input_paths: Vec<&str>) -> Resu
     Ok(())
 }
 
+///Everything needed to resume a program later, see [`Device::snapshot`]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct DeviceState {
+    pub registers: Dump,
+    pub mem: Vec<u8>,
+    pub keyboard_buffer: Vec<u8>,
+    pub cycles: u64,
+    ///Indexed by file number, `None` if the file isn't open
+    pub files: Vec<Option<FileState>>,
+    ///Frames of the functions that have been called but haven't returned, innermost last
+    #[serde(default)]
+    pub frames: Vec<StackFrame>,
+}
+
+///Recorded by CALL so ARG can check it only reads the arguments for its frame
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub struct StackFrame {
+    ///FP of the called function
+    pub fp: u16,
+    ///Number of bytes the caller pushed before CALL
+    pub arg_len: u16,
+    ///Where the caller's pushes started (below its locals), restored by RET
+    pub caller_base: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct FileState {
+    pub path: PathBuf,
+    pub cursor: u64,
+}
+
 pub mod comm {
+    use std::sync::mpsc::{Sender, SyncSender};
+
+    #[derive(Debug, Eq, PartialEq)]
     pub enum Output {
         OutputStd(String),
         OutputErr(String),
+        ///Bytes written to `FILEW 255`
+        OutputBinary(Vec<u8>),
+        ClearScreen,
+        ///Column, row
+        MoveCursor(u8, u8),
+        ///0-15 for the standard terminal colors, `None` to reset
+        SetColor(Option<u8>),
+        ///Frame finished, see [`crate::device::DisplaySize`]
+        Vsync,
         BreakpointHit(u16),
+        WatchpointHit(u16),
+        ///PC and address of a read of memory that has never been written, only in strict mode
+        UninitializedRead(u16, u16),
+        ///PC of the instruction that overwrote a stack canary and the canary's address, only
+        ///when checking the stack, sent just before RET fails
+        StackCorrupted(u16, u16),
+    }
+
+    ///Text for [`Output::UninitializedRead`], `line` is the source line of the instruction if known
+    pub fn uninitialized_read_text(pc: u16, addr: u16, line: Option<usize>) -> String {
+        match line {
+            Some(line) => format!(
+                "Warning at byte {} (line {}): read uninitialized memory at {:04X}",
+                pc, line, addr
+            ),
+            None => format!(
+                "Warning at byte {}: read uninitialized memory at {:04X}",
+                pc, addr
+            ),
+        }
+    }
+
+    ///Text for [`Output::StackCorrupted`], `line` is the source line of the instruction if known
+    pub fn stack_corrupted_text(pc: u16, addr: u16, line: Option<usize>) -> String {
+        match line {
+            Some(line) => format!(
+                "Byte {} (line {}) overwrote the stack canary at {:04X}",
+                pc, line, addr
+            ),
+            None => format!("Byte {} overwrote the stack canary at {:04X}", pc, addr),
+        }
+    }
+
+    ///Receives output from a [`crate::device::internals::Device`] as it's produced, see
+    ///[`crate::device::internals::Device::set_output_sink`]
+    pub trait OutputSink {
+        fn push(&mut self, output: Output);
+    }
+
+    impl OutputSink for Vec<Output> {
+        fn push(&mut self, output: Output) {
+            Vec::push(self, output);
+        }
+    }
+
+    ///Output is dropped once the receiver has been dropped
+    impl OutputSink for Sender<Output> {
+        fn push(&mut self, output: Output) {
+            let _ = self.send(output);
+        }
+    }
+
+    ///Blocks the device while the channel is full, so memory stays flat if the receiver falls behind
+    impl OutputSink for SyncSender<Output> {
+        fn push(&mut self, output: Output) {
+            let _ = self.send(output);
+        }
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub struct Dump {
     pub pc: u16,
     pub acc: u8,
     pub sp: u16,
     pub fp: u16,
+    ///Lowest address the stack can use
+    pub stack_limit: u16,
     pub data_reg: [u8; DATA_REG_COUNT],
     pub addr_reg: [u16; ADDR_REG_COUNT],
-    pub overflow: bool,
+    ///See `FLAG_` constants in [`crate::constants::hardware`]
+    pub flags: u8,
+}
+
+impl Dump {
+    ///Flags as letters, i.e. `Z-N-` when zero and negative are set
+    pub fn flags_text(&self) -> String {
+        [
+            (FLAG_ZERO, 'Z'),
+            (FLAG_CARRY, 'C'),
+            (FLAG_NEGATIVE, 'N'),
+            (FLAG_OVERFLOW, 'V'),
+        ]
+        .iter()
+        .map(|(flag, chr)| if self.flags & flag != 0 { *chr } else { '-' })
+        .collect()
+    }
 }
 
 impl Default for Dump {
@@ -89,9 +550,30 @@ impl Default for Dump {
             acc: 0,
             sp: RAM_SIZE as u16,
             fp: RAM_SIZE as u16,
+            stack_limit: 0,
             data_reg: [0, 0, 0, 0],
             addr_reg: [0, 0],
-            overflow: false,
+            flags: 0,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_display_size() {
+        assert_eq!(
+            DisplaySize::parse("40x20").unwrap(),
+            DisplaySize {
+                width: 40,
+                height: 20
+            }
+        );
+        assert!(DisplaySize::parse("40").is_err());
+        assert!(DisplaySize::parse("0x20").is_err());
+        assert!(DisplaySize::parse("300x20").is_err());
+        assert!(DisplaySize::parse("255x255").is_err());
+    }
+}
@@ -0,0 +1,99 @@
+//! Everything a program has printed while being debugged, split into lines for the output pane
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LogLine {
+    pub text: String,
+    ///Printed as an error (OutputErr)
+    pub is_err: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct OutputLog {
+    lines: Vec<LogLine>,
+    ///The last line hasn't ended with a newline yet so more text is added to it
+    open: bool,
+}
+
+impl OutputLog {
+    pub fn lines(&self) -> &[LogLine] {
+        &self.lines
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.open = false;
+    }
+
+    ///Add printed text, continuing the last line if it didn't end with a newline
+    pub fn push_std(&mut self, text: &str) {
+        let parts: Vec<&str> = text.split('\n').collect();
+        for (idx, part) in parts.iter().enumerate() {
+            //every part but the last was followed by a newline
+            let terminated = idx < parts.len() - 1;
+            match self.lines.last_mut() {
+                Some(line) if idx == 0 && self.open => line.text.push_str(part),
+                _ if terminated || !part.is_empty() => self.lines.push(LogLine {
+                    text: part.to_string(),
+                    is_err: false,
+                }),
+                _ => {}
+            }
+            self.open = !terminated && (self.open || !part.is_empty());
+        }
+    }
+
+    ///Add an error, always on its own line
+    pub fn push_err(&mut self, text: &str) {
+        self.lines.push(LogLine {
+            text: text.trim_end().to_string(),
+            is_err: true,
+        });
+        self.open = false;
+    }
+
+    ///Index of the closest line before `before` containing `query` (ignoring case)
+    pub fn search_back(&self, query: &str, before: usize) -> Option<usize> {
+        let query = query.to_lowercase();
+        self.lines[..before.min(self.lines.len())]
+            .iter()
+            .rposition(|line| line.text.to_lowercase().contains(&query))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn texts(log: &OutputLog) -> Vec<&str> {
+        log.lines().iter().map(|line| line.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_push() {
+        let mut log = OutputLog::default();
+        log.push_std("Hello");
+        log.push_std(" world\nSecond");
+        log.push_std(" line\n");
+        log.push_err("Bad thing\n");
+        log.push_std("\n\nLast");
+        assert_eq!(
+            texts(&log),
+            vec!["Hello world", "Second line", "Bad thing", "", "", "Last"]
+        );
+        assert!(log.lines()[2].is_err);
+    }
+
+    #[test]
+    fn test_search_back() {
+        let mut log = OutputLog::default();
+        log.push_std("score 1\nlives 3\nSCORE 2\nlives 2\n");
+        assert_eq!(log.search_back("score", log.len()), Some(2));
+        assert_eq!(log.search_back("score", 2), Some(0));
+        assert_eq!(log.search_back("score", 0), None);
+        assert_eq!(log.search_back("level", log.len()), None);
+    }
+}
@@ -1,199 +1,361 @@
-use crate::device::comm::Output;
+use crate::constants::hardware::RAM_SIZE;
+use crate::device::comm::{stack_corrupted_text, uninitialized_read_text, Output, OutputSink};
 use crate::device::internals::{Device, RunResult};
-use crate::device::piped_device::prefix::*;
-use std::io::{stdin, stdout, Read, Write};
+use crate::device::protocol::{
+    read_line_message, read_message, write_line_message, write_message, Event, InputKind,
+    PipedFormat, Request, Response, PROTOCOL_VERSION,
+};
+use crate::device::{Dump, RunOptions};
+use anyhow::Result;
+use std::cell::RefCell;
+use std::io::{stdin, stdout, BufRead, Read, Write};
 use std::mem::swap;
-use std::thread::sleep;
-use std::time::Duration;
+use std::rc::Rc;
 
 /// PipedDevice
 /// Can be used by external programs to host the Device so that it can used inside other programs such as debuggers
 ///
-/// The Device can be communicated with over stdin and stdout using bytes
+/// The Device can be communicated with over stdin and stdout using the messages in [`crate::device::protocol`]
 /// Instructions and user input are sent over stdin
 /// Program output and diagnostics are sent over stdout
-///
-/// The format for both channels is <cmd><content len><content>
-/// Program output: o,11,H,e,l,l,o, ,w,o,r,l,d
-/// Error output:   e,12,C,r,a,s,h,e,d,\n,P,C,:, ,1
-/// Set breakpoint: b,2,30
-
-mod prefix {
-    pub const OUTPUT_STR: u8 = b'o';
-    pub const OUTPUT_ERR: u8 = b'e';
-    pub const OUTPUT_BP_HIT: u8 = b'h';
-    pub const OUTPUT_REQ_STR: u8 = b't';
-    pub const OUTPUT_REQ_KEY: u8 = b'k';
-    pub const OUTPUT_END: u8 = b'f';
-    pub const OUTPUT_CRASH: u8 = b'c';
-    pub const OUTPUT_DUMP: u8 = b'd';
-    pub const OUTPUT_MEMORY: u8 = b'm';
-
-    pub const INPUT_STEP: u8 = b'e';
-    pub const INPUT_STEP_FORCE: u8 = b'f';
-    pub const INPUT_DUMP: u8 = b'd';
-    pub const INPUT_BP_SET: u8 = b'b';
-    pub const INPUT_BP_CLEAR: u8 = b'c';
-    pub const INPUT_MEMORY: u8 = b'm';
-    pub const INPUT_CHAR: u8 = b'k';
-    pub const INPUT_STRING: u8 = b't';
-}
-
 pub struct PipedDevice {
     device: Device,
 }
 
 impl PipedDevice {
-    pub fn new(ops: Vec<u8>, strings: Vec<u8>, data: Vec<u8>, data_files: Vec<String>) -> Self {
-        PipedDevice {
-            device: Device::new(ops, strings, data, data_files),
-        }
+    pub fn new(
+        ops: Vec<u8>,
+        strings: Vec<u8>,
+        data: Vec<u8>,
+        data_files: Vec<String>,
+        options: &RunOptions,
+    ) -> Self {
+        let mut device = Device::new(ops, strings, data, data_files);
+        options.configure(&mut device);
+        PipedDevice { device }
+    }
+
+    pub fn set_tape_version(&mut self, version: u8) {
+        self.device.set_tape_version(version);
+    }
+
+    pub fn set_entry(&mut self, addr: u16) {
+        self.device.set_entry(addr);
     }
 }
 
 impl PipedDevice {
-    pub fn run(&mut self) {
-        loop {
-            self.process_input();
-
-            let mut msgs = vec![];
-            swap(&mut self.device.output, &mut msgs);
-            for output in msgs {
-                match output {
-                    Output::OutputStd(text) => self.send_text(OUTPUT_STR, text),
-                    Output::OutputErr(text) => self.send_text(OUTPUT_ERR, text),
-                    Output::BreakpointHit(byte) => {
-                        stdout()
-                            .write_all(&[OUTPUT_BP_HIT])
-                            .expect("Writing to stdout");
-                        stdout()
-                            .write_all(&byte.to_be_bytes())
-                            .expect("Writing to stdout");
-                    }
-                }
-            }
-
-            sleep(Duration::from_millis(10));
+    pub fn run(&mut self, format: PipedFormat) -> Result<()> {
+        match format {
+            PipedFormat::Framed => self.serve(&mut stdin().lock(), stdout().lock()),
+            PipedFormat::Json => self.serve_json(&mut stdin().lock(), stdout().lock()),
         }
     }
 
-    fn step(&mut self, ignore_breakpoints: bool) {
-        match self.device.step(ignore_breakpoints) {
-            RunResult::Pause => { /* do nothing*/ }
-            RunResult::Breakpoint => { /*handled below*/ }
-            RunResult::Halt | RunResult::EoF => {
-                stdout()
-                    .write_all(&[OUTPUT_END])
-                    .expect("Writing to stdout");
-            }
-            RunResult::ProgError => {
-                stdout()
-                    .write_all(&[OUTPUT_CRASH])
-                    .expect("Writing to stdout");
-            }
-            RunResult::CharInputRequested => {
-                stdout()
-                    .write_all(&[OUTPUT_REQ_KEY])
-                    .expect("Writing to stdout");
-            }
-            RunResult::StringInputRequested => {
-                stdout()
-                    .write_all(&[OUTPUT_REQ_STR])
-                    .expect("Writing to stdout");
+    ///Handle requests until `input` is closed, program output is written as it's produced
+    pub fn serve<R: Read, W: Write + 'static>(&mut self, input: &mut R, output: W) -> Result<()> {
+        let output = self.stream_output(output, PipedFormat::Framed);
+        write_message(
+            &mut *output.borrow_mut(),
+            &Response::Hello {
+                version: PROTOCOL_VERSION,
+            },
+        )?;
+        while let Some(request) = read_message(input)? {
+            let mut responses = self.handle(request);
+            responses.extend(self.drain_serial());
+            let mut output = output.borrow_mut();
+            for response in responses {
+                write_message(&mut *output, &response)?;
             }
+            write_message(&mut *output, &Response::Ready)?;
         }
+        Ok(())
     }
 
-    fn send_text(&self, cmd: u8, text: String) {
-        for chunk in text.into_bytes().chunks(255) {
-            stdout()
-                .write_all(&[cmd, chunk.len() as u8])
-                .expect("Writing to stdout");
-            stdout().write_all(chunk).expect("Writing to stdout");
-            stdout().flush().expect("Flushing stdout");
+    ///Handle requests, one per line, until `input` is closed, sending [`Event`]s instead of responses
+    pub fn serve_json<R: BufRead, W: Write + 'static>(
+        &mut self,
+        input: &mut R,
+        output: W,
+    ) -> Result<()> {
+        let output = self.stream_output(output, PipedFormat::Json);
+        write_line_message(
+            &mut *output.borrow_mut(),
+            &Event::Hello {
+                version: PROTOCOL_VERSION,
+            },
+        )?;
+        while let Some(request) = read_line_message(input)? {
+            let mut responses = self.handle(request);
+            responses.extend(self.drain_serial());
+            let dump = self.device.dump();
+            let mut output = output.borrow_mut();
+            for response in responses {
+                write_line_message(&mut *output, &Event::from_response(response, &dump))?;
+            }
+            write_line_message(&mut *output, &Event::Ready)?;
         }
+        Ok(())
     }
 
-    fn process_input(&mut self) {
-        match read_u8() {
-            INPUT_STEP => self.step(false),
-            INPUT_STEP_FORCE => self.step(true),
-            INPUT_DUMP => {
-                let dump = self.device.dump();
-                stdout()
-                    .write_all(&[OUTPUT_DUMP])
-                    .expect("Writing to stdout");
-                write_u16(dump.pc);
-                write_u16(dump.addr_reg[0]);
-                write_u16(dump.addr_reg[1]);
-                write_u16(dump.sp);
-                write_u16(dump.fp);
-                let overflow_byte = if dump.overflow { 1 } else { 0 };
-                stdout()
-                    .write_all(&[
-                        dump.acc,
-                        dump.data_reg[0],
-                        dump.data_reg[1],
-                        dump.data_reg[2],
-                        dump.data_reg[3],
-                        overflow_byte,
-                    ])
-                    .expect("Writing to stdout");
-                stdout().flush().expect("Writing to stdout");
-            }
-            INPUT_BP_SET => {
-                let addr = read_u16();
+    ///Write the device's output to `output` as it's produced, the device waits for each write so
+    ///output doesn't build up if the client is slow to read it
+    fn stream_output<W: Write + 'static>(
+        &mut self,
+        output: W,
+        format: PipedFormat,
+    ) -> Rc<RefCell<W>> {
+        let output = Rc::new(RefCell::new(output));
+        self.device.set_output_sink(Box::new(ResponseSink {
+            output: output.clone(),
+            format,
+        }));
+        output
+    }
+
+    fn handle(&mut self, request: Request) -> Vec<Response> {
+        match request {
+            Request::Step => self.step(false),
+            Request::StepForce => self.step(true),
+            Request::Dump => vec![Response::Dump(self.device.dump())],
+            Request::SetBreakpoint(addr) => {
                 self.device.breakpoints.push(addr);
+                vec![]
             }
-            INPUT_BP_CLEAR => {
-                let addr = read_u16();
+            Request::ClearBreakpoint(addr) => {
                 self.device.breakpoints.retain(|value| value != &addr);
+                vec![]
             }
-            INPUT_MEMORY => {
-                let start = read_u16();
-                let end = read_u16();
-                let mem = &self.device.mem[start as usize..end as usize];
-                stdout()
-                    .write_all(&[OUTPUT_MEMORY])
-                    .expect("Writing to stdout");
-                write_u16(start);
-                write_u16(end);
-                write_u16(mem.len() as u16);
-                stdout().write_all(mem).expect("Writing to stdout");
-                stdout().flush().expect("Writing to stdout");
+            Request::SetWatchpoint(start, end) => {
+                self.device.watchpoints.push((start, end));
+                vec![]
             }
-            INPUT_CHAR => {
-                let chr = read_u8();
-                self.device.keyboard_buffer.push(chr);
+            Request::ClearWatchpoint(start, end) => {
+                self.device.clear_watchpoints(start, end);
+                vec![]
             }
-            INPUT_STRING => {
-                let len = read_u8() as usize;
-                let mut bytes = vec![];
-                for _ in 0..len {
-                    bytes.push(read_u8())
+            Request::Memory(start, end) => {
+                if start > end || end as usize > RAM_SIZE {
+                    vec![Response::Error(format!(
+                        "Invalid memory range {:04X}..{:04X}",
+                        start, end
+                    ))]
+                } else {
+                    vec![Response::Memory {
+                        start,
+                        bytes: self.device.mem[start as usize..end as usize].to_vec(),
+                    }]
                 }
-                self.device.keyboard_buffer.extend_from_slice(&bytes);
             }
-            _ => {}
+            Request::Key(chr) => {
+                self.device.push_char(chr);
+                vec![]
+            }
+            Request::Text(bytes) => {
+                self.device.push_bytes(&bytes);
+                vec![]
+            }
+            Request::Stdin(bytes) => {
+                if bytes.is_empty() {
+                    self.device.close_stdin();
+                } else {
+                    self.device.stdin_buffer.extend_from_slice(&bytes);
+                }
+                vec![]
+            }
+            Request::Serial(bytes) => {
+                self.device.serial_in.extend_from_slice(&bytes);
+                vec![]
+            }
         }
     }
+
+    fn step(&mut self, ignore_breakpoints: bool) -> Vec<Response> {
+        match self.device.step(ignore_breakpoints) {
+            RunResult::Pause => vec![],
+            //sent from device output
+            RunResult::Breakpoint | RunResult::Watchpoint => vec![],
+            RunResult::Halt | RunResult::EoF => vec![Response::End],
+            RunResult::Exit(code) => vec![Response::Exit(code)],
+            RunResult::ProgError => vec![Response::Crash],
+            RunResult::CycleLimit => vec![Response::CycleLimit],
+            RunResult::CharInputRequested => vec![Response::InputRequest(InputKind::Char)],
+            RunResult::StringInputRequested => vec![Response::InputRequest(InputKind::String)],
+            RunResult::StdinRequested => vec![Response::InputRequest(InputKind::Stdin)],
+            RunResult::SerialRequested => vec![Response::InputRequest(InputKind::Serial)],
+        }
+    }
+
+    ///Serial bytes written since the last request
+    fn drain_serial(&mut self) -> Option<Response> {
+        if self.device.serial_out.is_empty() {
+            return None;
+        }
+        let mut bytes = vec![];
+        swap(&mut self.device.serial_out, &mut bytes);
+        Some(Response::Serial(bytes))
+    }
 }
 
-fn read_u8() -> u8 {
-    let mut addr = [0_u8; 1];
-    stdin().read_exact(&mut addr).expect("Reading from stdin");
-    addr[0]
+///Sends device output to the client, shared with [`PipedDevice`] which sends the other responses
+struct ResponseSink<W: Write> {
+    output: Rc<RefCell<W>>,
+    format: PipedFormat,
 }
 
-fn read_u16() -> u16 {
-    let mut addr = [0_u8; 2];
-    stdin().read_exact(&mut addr).expect("Reading from stdin");
-    u16::from_be_bytes(addr)
+impl<W: Write> OutputSink for ResponseSink<W> {
+    fn push(&mut self, output: Output) {
+        let response = match output {
+            Output::OutputStd(text) => Response::Output(text),
+            Output::OutputErr(text) => Response::Error(text),
+            Output::OutputBinary(bytes) => Response::Stdout(bytes),
+            Output::ClearScreen => Response::ClearScreen,
+            Output::MoveCursor(column, row) => Response::MoveCursor(column, row),
+            Output::SetColor(color) => Response::SetColor(color),
+            Output::Vsync => Response::Vsync,
+            Output::BreakpointHit(addr) => Response::BreakpointHit(addr),
+            Output::WatchpointHit(addr) => Response::WatchpointHit(addr),
+            Output::UninitializedRead(pc, addr) => {
+                Response::Error(uninitialized_read_text(pc, addr, None))
+            }
+            Output::StackCorrupted(pc, addr) => {
+                Response::Error(stack_corrupted_text(pc, addr, None))
+            }
+        };
+        let output = &mut *self.output.borrow_mut();
+        //write errors are found by the next response PipedDevice sends
+        let _ = match self.format {
+            PipedFormat::Framed => write_message(output, &response),
+            //none of the output responses include the dump
+            PipedFormat::Json => {
+                write_line_message(output, &Event::from_response(response, &Dump::default()))
+            }
+        };
+    }
 }
 
-fn write_u16(value: u16) {
-    stdout()
-        .write_all(&value.to_be_bytes())
-        .expect("Writing to stdout");
-    stdout().flush().expect("Writing to stdout");
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constants::code::{HALT, PRTC_VAL, RCHR_REG};
+    use crate::constants::hardware::REG_D0;
+    use crate::device::client::PipedClient;
+    use std::io::Cursor;
+
+    ///Written to by the device, read by the test once the device is done
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn serve(ops: Vec<u8>, requests: &[Request]) -> Vec<u8> {
+        let mut input = vec![];
+        for request in requests {
+            write_message(&mut input, request).unwrap();
+        }
+        let output = SharedBuffer::default();
+        let mut device = PipedDevice::new(ops, vec![], vec![], vec![], &RunOptions::default());
+        device
+            .serve(&mut Cursor::new(input), output.clone())
+            .unwrap();
+        output.0.take()
+    }
+
+    #[test]
+    fn test_serve() {
+        let output = serve(
+            vec![PRTC_VAL, b'a', RCHR_REG, REG_D0, HALT],
+            &[
+                Request::SetBreakpoint(4),
+                Request::Step,
+                Request::Step,
+                Request::Key(b'x'),
+                Request::Step,
+                Request::Step,
+                Request::StepForce,
+                Request::Memory(0, 2),
+            ],
+        );
+
+        let mut client = PipedClient::new(Cursor::new(output), vec![]).unwrap();
+        let mut responses = vec![];
+        while let Ok(response) = client.receive() {
+            responses.push(response);
+        }
+        assert_eq!(
+            responses,
+            vec![
+                Response::Ready,
+                Response::Output(String::from("a")),
+                Response::Ready,
+                Response::InputRequest(InputKind::Char),
+                Response::Ready,
+                Response::Ready,
+                Response::Ready,
+                Response::BreakpointHit(4),
+                Response::Ready,
+                Response::End,
+                Response::Ready,
+                Response::Memory {
+                    start: 0,
+                    bytes: vec![0, 0]
+                },
+                Response::Ready,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_serve_json() {
+        let mut device = PipedDevice::new(
+            vec![PRTC_VAL, b'a', RCHR_REG, REG_D0, HALT],
+            vec![],
+            vec![],
+            vec![],
+            &RunOptions::default(),
+        );
+        let input = "\"Step\"\n\"Step\"\n{\"Key\":120}\n\"Step\"\n\"Step\"\n";
+        let output = SharedBuffer::default();
+        device
+            .serve_json(&mut Cursor::new(input), output.clone())
+            .unwrap();
+
+        let events: Vec<Event> = String::from_utf8(output.0.take())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let dump = device.device.dump();
+        assert_eq!(dump.data_reg[0], b'x');
+        assert_eq!(
+            events,
+            vec![
+                Event::Hello {
+                    version: PROTOCOL_VERSION
+                },
+                Event::Stdout {
+                    data: String::from("a")
+                },
+                Event::Ready,
+                Event::InputRequest {
+                    kind: String::from("char")
+                },
+                Event::Ready,
+                Event::Ready,
+                Event::Ready,
+                Event::Halt { dump },
+                Event::Ready,
+            ]
+        );
+    }
 }
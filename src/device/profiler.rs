@@ -0,0 +1,105 @@
+use crate::assembler::debug_model::DebugModel;
+use crate::constants::code::Opcode;
+use crate::constants::get_byte_count;
+use crate::decompiler::decode;
+use crate::device::internals::Profile;
+use std::convert::TryFrom;
+
+///Number of addresses shown in the hot instructions table
+const HOT_INSTRUCTIONS: usize = 10;
+
+///Summary of a profile, instructions are shown as source lines if `debug` is available
+///otherwise they are decompiled from `ops`
+pub fn format_report(profile: &Profile, ops: &[u8], debug: Option<&DebugModel>) -> Vec<String> {
+    let total = profile.total();
+    let percent = |count: u64| {
+        if total == 0 {
+            0.0
+        } else {
+            count as f64 / total as f64 * 100.0
+        }
+    };
+
+    let mut lines = vec![
+        format!("Profile: {} instructions executed", total),
+        String::new(),
+        String::from("Hot instructions"),
+        String::from("     Count       %  Addr  Line  Source"),
+    ];
+    let mut addrs: Vec<(&u16, &u64)> = profile.addrs.iter().collect();
+    addrs.sort_by(|lhs, rhs| rhs.1.cmp(lhs.1).then(lhs.0.cmp(rhs.0)));
+    for (addr, count) in addrs.into_iter().take(HOT_INSTRUCTIONS) {
+        let (line_num, source) = describe(*addr, ops, debug);
+        lines.push(format!(
+            "{: >10} {: >6.2}%  {:04X}  {: >4}  {}",
+            count,
+            percent(*count),
+            addr,
+            line_num,
+            source
+        ));
+    }
+
+    lines.push(String::new());
+    lines.push(String::from("Opcodes"));
+    lines.push(String::from("     Count       %  Opcode"));
+    let mut ops_counts: Vec<(&u8, &u64)> = profile.ops.iter().collect();
+    ops_counts.sort_by(|lhs, rhs| rhs.1.cmp(lhs.1).then(lhs.0.cmp(rhs.0)));
+    for (op, count) in ops_counts {
+        let name = Opcode::try_from(*op)
+            .map(|opcode| format!("{:?}", opcode))
+            .unwrap_or_else(|_| String::from("???"));
+        lines.push(format!(
+            "{: >10} {: >6.2}%  {:02X} {}",
+            count,
+            percent(*count),
+            op,
+            name
+        ));
+    }
+    lines
+}
+
+///Line number (if known) and text for the instruction at `addr`
+fn describe(addr: u16, ops: &[u8], debug: Option<&DebugModel>) -> (String, String) {
+    if let Some(op) = debug.and_then(|debug| debug.op_for_byte(addr)) {
+        return (op.line_num.to_string(), op.processed_line.clone());
+    }
+    let start = addr as usize;
+    match ops.get(start) {
+        Some(op) if start + get_byte_count(*op) <= ops.len() => {
+            let mut bytes = ops[start..start + get_byte_count(*op)].to_vec();
+            let decoded = decode(&mut bytes, &[], start, false);
+            (String::from("?"), decoded.strings.join(" "))
+        }
+        _ => (String::from("?"), String::from("???")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constants::code::{CPY_REG_VAL, INC_REG, JMP_ADDR};
+    use crate::constants::hardware::REG_D0;
+    use crate::device::internals::{Device, RunResult};
+
+    #[test]
+    fn test_report() {
+        let ops = vec![CPY_REG_VAL, REG_D0, 1, INC_REG, REG_D0, JMP_ADDR, 0, 3];
+        let mut device = Device::new(ops.clone(), vec![], vec![], vec![]);
+        device.enable_profiling();
+        for _ in 0..7 {
+            assert_eq!(device.step(true), RunResult::Pause);
+        }
+        let profile = device.profile().unwrap();
+        assert_eq!(profile.total(), 7);
+        assert_eq!(profile.addrs[&3], 3);
+        assert_eq!(profile.ops[&JMP_ADDR], 3);
+
+        let report = format_report(profile, &ops, None);
+        assert_eq!(report[0], "Profile: 7 instructions executed");
+        assert_eq!(report[4], "         3  42.86%  0003     ?  INC D0");
+        assert_eq!(report[5], "         3  42.86%  0005     ?  JMP @x0003");
+        assert_eq!(report[6], "         1  14.29%  0000     ?  CPY D0 1");
+    }
+}
@@ -0,0 +1,356 @@
+//! Messages exchanged with a piped device (`tape_device program.tape --piped`)
+//!
+//! Each message is sent as `<version><len><json>`, where version is one byte ([`PROTOCOL_VERSION`]),
+//! len is the length of the json in bytes as a big endian u32 and json is the message serialized with serde
+//!
+//! The device sends [`Response::Hello`] when it starts and [`Response::Ready`] after it's finished handling each [`Request`]
+//!
+//! With `--piped-format json` messages are newline delimited JSON instead, requests are the same but the
+//! device sends [`Event`]s, which are tagged with `type` and include the registers when the program ends
+
+use crate::device::Dump;
+use anyhow::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::io::{BufRead, ErrorKind, Read, Write};
+
+pub const PROTOCOL_VERSION: u8 = 1;
+///Messages larger than this are rejected
+const MAX_MESSAGE_LEN: usize = 1024 * 1024;
+
+///Sent to the device
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub enum Request {
+    ///Execute the next instruction, unless there's a breakpoint
+    Step,
+    ///Execute the next instruction, ignoring breakpoints
+    StepForce,
+    Dump,
+    SetBreakpoint(u16),
+    ClearBreakpoint(u16),
+    ///Pause when memory between the addresses (inclusive) is written
+    SetWatchpoint(u16, u16),
+    ///Range must match the one used to set the watchpoint
+    ClearWatchpoint(u16, u16),
+    ///Memory from start to end (exclusive)
+    Memory(u16, u16),
+    ///Add one key press to the keyboard buffer
+    Key(u8),
+    ///Add text to the keyboard buffer
+    Text(Vec<u8>),
+    ///Add bytes to stdin (file 254), empty to signal the end of stdin
+    Stdin(Vec<u8>),
+    ///Bytes received by the serial port, for RECV
+    Serial(Vec<u8>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum InputKind {
+    Char,
+    String,
+    ///Bytes for stdin (file 254)
+    Stdin,
+    ///Bytes for the serial port, see [`Request::Serial`]
+    Serial,
+}
+
+impl InputKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputKind::Char => "char",
+            InputKind::String => "string",
+            InputKind::Stdin => "stdin",
+            InputKind::Serial => "serial",
+        }
+    }
+}
+
+///How messages are sent to and from a piped device
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PipedFormat {
+    ///Framed [`Request`]s and [`Response`]s, see [`write_message`]
+    Framed,
+    ///Newline delimited [`Request`]s and [`Event`]s, see [`write_line_message`]
+    Json,
+}
+
+impl PipedFormat {
+    pub fn parse(text: &str) -> Result<PipedFormat> {
+        match text {
+            "framed" => Ok(PipedFormat::Framed),
+            "json" => Ok(PipedFormat::Json),
+            _ => Err(Error::msg(format!(
+                "Invalid piped format '{}', must be framed or json",
+                text
+            ))),
+        }
+    }
+}
+
+///Sent by the device
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub enum Response {
+    ///Sent once when the device starts
+    Hello {
+        version: u8,
+    },
+    ///Request has been handled, all other responses for it have already been sent
+    Ready,
+    ///Standard output from the tape program
+    Output(String),
+    ///Error output from the device
+    Error(String),
+    ///Bytes written to stdout (file 255) by the tape program
+    Stdout(Vec<u8>),
+    ///Bytes sent to the serial port with SEND
+    Serial(Vec<u8>),
+    ///Tape program cleared the screen
+    ClearScreen,
+    ///Tape program moved the cursor to column, row
+    MoveCursor(u8, u8),
+    ///Tape program set the text color, 0-15 or `None` to reset
+    SetColor(Option<u8>),
+    ///Tape program finished drawing a frame, the framebuffer can be read with [`Request::Memory`]
+    Vsync,
+    BreakpointHit(u16),
+    WatchpointHit(u16),
+    ///Tape program is waiting for input
+    InputRequest(InputKind),
+    ///Tape program has finished (EoF or HALT)
+    End,
+    ///Tape program finished with EXIT and this exit code
+    Exit(u8),
+    Crash,
+    ///Tape program executed the number of instructions set by `--max-cycles`
+    CycleLimit,
+    Dump(Dump),
+    Memory {
+        start: u16,
+        bytes: Vec<u8>,
+    },
+}
+
+///Sent by the device instead of [`Response`] with [`PipedFormat::Json`]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Hello {
+        version: u8,
+    },
+    Ready,
+    ///Standard output from the tape program
+    Stdout {
+        data: String,
+    },
+    ///Error output from the device
+    Stderr {
+        data: String,
+    },
+    ///Bytes written to stdout (file 255) by the tape program
+    Binary {
+        data: Vec<u8>,
+    },
+    ///Bytes sent to the serial port with SEND
+    Serial {
+        data: Vec<u8>,
+    },
+    ClearScreen,
+    MoveCursor {
+        column: u8,
+        row: u8,
+    },
+    SetColor {
+        color: Option<u8>,
+    },
+    Vsync,
+    BreakpointHit {
+        addr: u16,
+    },
+    WatchpointHit {
+        addr: u16,
+    },
+    ///Tape program is waiting for input, `kind` is [`InputKind::name`]
+    InputRequest {
+        kind: String,
+    },
+    ///Tape program has finished (EoF or HALT)
+    Halt {
+        dump: Dump,
+    },
+    Exit {
+        code: u8,
+        dump: Dump,
+    },
+    Crash {
+        dump: Dump,
+    },
+    CycleLimit {
+        dump: Dump,
+    },
+    Dump {
+        dump: Dump,
+    },
+    Memory {
+        start: u16,
+        bytes: Vec<u8>,
+    },
+}
+
+impl Event {
+    ///Event for `response`, `dump` is included if the program has ended
+    pub fn from_response(response: Response, dump: &Dump) -> Event {
+        match response {
+            Response::Hello { version } => Event::Hello { version },
+            Response::Ready => Event::Ready,
+            Response::Output(data) => Event::Stdout { data },
+            Response::Error(data) => Event::Stderr { data },
+            Response::Stdout(data) => Event::Binary { data },
+            Response::Serial(data) => Event::Serial { data },
+            Response::ClearScreen => Event::ClearScreen,
+            Response::MoveCursor(column, row) => Event::MoveCursor { column, row },
+            Response::SetColor(color) => Event::SetColor { color },
+            Response::Vsync => Event::Vsync,
+            Response::BreakpointHit(addr) => Event::BreakpointHit { addr },
+            Response::WatchpointHit(addr) => Event::WatchpointHit { addr },
+            Response::InputRequest(kind) => Event::InputRequest {
+                kind: kind.name().to_string(),
+            },
+            Response::End => Event::Halt { dump: dump.clone() },
+            Response::Exit(code) => Event::Exit {
+                code,
+                dump: dump.clone(),
+            },
+            Response::Crash => Event::Crash { dump: dump.clone() },
+            Response::CycleLimit => Event::CycleLimit { dump: dump.clone() },
+            Response::Dump(dump) => Event::Dump { dump },
+            Response::Memory { start, bytes } => Event::Memory { start, bytes },
+        }
+    }
+}
+
+pub fn write_message<W: Write, T: Serialize>(writer: &mut W, message: &T) -> Result<()> {
+    let json = serde_json::to_vec(message)?;
+    let len = u32::try_from(json.len())
+        .map_err(|_| Error::msg(format!("Message too large ({} bytes)", json.len())))?;
+    writer.write_all(&[PROTOCOL_VERSION])?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(&json)?;
+    writer.flush()?;
+    Ok(())
+}
+
+///Read the next message, returns `None` if the stream was closed before the message started
+pub fn read_message<R: Read, T: DeserializeOwned>(reader: &mut R) -> Result<Option<T>> {
+    let mut version = [0_u8; 1];
+    match reader.read_exact(&mut version) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(Error::from(err)),
+    }
+    if version[0] != PROTOCOL_VERSION {
+        return Err(Error::msg(format!(
+            "Unsupported protocol version {}, expected {}",
+            version[0], PROTOCOL_VERSION
+        )));
+    }
+    let mut len = [0_u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(Error::msg(format!(
+            "Message too large ({} bytes, max {})",
+            len, MAX_MESSAGE_LEN
+        )));
+    }
+    let mut json = vec![0_u8; len];
+    reader.read_exact(&mut json)?;
+    Ok(Some(serde_json::from_slice(&json)?))
+}
+
+///Write `message` as JSON on one line, for [`PipedFormat::Json`]
+pub fn write_line_message<W: Write, T: Serialize>(writer: &mut W, message: &T) -> Result<()> {
+    serde_json::to_writer(&mut *writer, message)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+///Read the next line as a message, blank lines are skipped, returns `None` if the stream was closed
+pub fn read_line_message<R: BufRead, T: DeserializeOwned>(reader: &mut R) -> Result<Option<T>> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if line.len() > MAX_MESSAGE_LEN {
+            return Err(Error::msg(format!(
+                "Message too large ({} bytes, max {})",
+                line.len(),
+                MAX_MESSAGE_LEN
+            )));
+        }
+        if !line.trim().is_empty() {
+            return Ok(Some(serde_json::from_str(&line)?));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_round_trip() {
+        let mut bytes = vec![];
+        write_message(&mut bytes, &Request::SetWatchpoint(1, 20)).unwrap();
+        write_message(&mut bytes, &Request::Text(b"hi".to_vec())).unwrap();
+        assert_eq!(bytes[0], PROTOCOL_VERSION);
+
+        let mut reader = Cursor::new(bytes);
+        let first: Option<Request> = read_message(&mut reader).unwrap();
+        let second: Option<Request> = read_message(&mut reader).unwrap();
+        let end: Option<Request> = read_message(&mut reader).unwrap();
+        assert_eq!(first, Some(Request::SetWatchpoint(1, 20)));
+        assert_eq!(second, Some(Request::Text(b"hi".to_vec())));
+        assert_eq!(end, None);
+    }
+
+    #[test]
+    fn test_line_messages() {
+        let mut bytes = vec![];
+        write_line_message(
+            &mut bytes,
+            &Event::InputRequest {
+                kind: String::from("char"),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "{\"type\":\"input_request\",\"kind\":\"char\"}\n"
+        );
+
+        let mut reader = Cursor::new("\"Step\"\n\n{\"Key\":65}\n");
+        let first: Option<Request> = read_line_message(&mut reader).unwrap();
+        let second: Option<Request> = read_line_message(&mut reader).unwrap();
+        let end: Option<Request> = read_line_message(&mut reader).unwrap();
+        assert_eq!(first, Some(Request::Step));
+        assert_eq!(second, Some(Request::Key(65)));
+        assert_eq!(end, None);
+
+        assert_eq!(PipedFormat::parse("json").unwrap(), PipedFormat::Json);
+        assert!(PipedFormat::parse("xml").is_err());
+    }
+
+    #[test]
+    fn test_wrong_version() {
+        let mut bytes = vec![];
+        write_message(&mut bytes, &Request::Step).unwrap();
+        bytes[0] = PROTOCOL_VERSION + 1;
+        let result: Result<Option<Request>> = read_message(&mut Cursor::new(bytes));
+        assert!(result.is_err());
+    }
+}
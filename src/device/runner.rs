@@ -0,0 +1,212 @@
+use crate::device::comm::{stack_corrupted_text, uninitialized_read_text, Output};
+use crate::device::input::InputScript;
+use crate::device::internals::{Device, Profile, RunResult};
+use crate::device::{Dump, RunOptions};
+use crate::tape_reader::{link_data_tapes, parse_tape_strict, Tape};
+use anyhow::Result;
+
+///Why [`run_tape`] stopped
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ExitReason {
+    ///HALT instruction
+    Halt,
+    ///EXIT instruction with the exit code
+    Exit(u8),
+    ///Ran past the last op
+    EoF,
+    ///Program error, the message is in [`RunReport::stderr`]
+    ProgError,
+    ///Executed [`RunOptions::max_cycles`] instructions
+    CycleLimit,
+    ///Printed more than [`RunOptions::max_output`] bytes
+    OutputLimit,
+    ///Program requested keyboard input and there was none (or the input script was empty)
+    InputRequested,
+}
+
+///Result of running a tape with [`run_tape`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RunReport {
+    pub exit: ExitReason,
+    ///Printed text (PRT, PRTS, etc)
+    pub stdout: String,
+    ///Error messages
+    pub stderr: String,
+    ///Bytes written to [`crate::constants::hardware::STDOUT_FILE`]
+    pub binary: Vec<u8>,
+    pub dump: Dump,
+    pub cycles: u64,
+    ///Instruction counts if [`RunOptions::profile`] or [`RunOptions::coverage`] was set
+    pub profile: Option<Profile>,
+}
+
+///Run tape `bytes` to completion without a terminal
+///
+///Keyboard input is read from [`RunOptions::input_script`] if set, stdin (file 254) is always empty
+pub fn run_tape(bytes: Vec<u8>, options: RunOptions) -> Result<RunReport> {
+    run_parsed_tape(parse_tape_strict(bytes)?, vec![], options)
+}
+
+///Same as [`run_tape`] for a tape that's already been read, `input_paths` are the files for FOPEN by number
+pub fn run_parsed_tape(
+    mut tape: Tape,
+    input_paths: Vec<String>,
+    options: RunOptions,
+) -> Result<RunReport> {
+    link_data_tapes(&mut tape, &options.data_tapes)?;
+    let mut script = match &options.input_script {
+        Some(path) => Some(InputScript::read(path)?),
+        None if !options.input_lines.is_empty() => Some(InputScript::parse(&options.input_lines)?),
+        None => None,
+    };
+
+    let mut device = Device::new(tape.ops, tape.strings, tape.data, input_paths);
+    options.configure(&mut device);
+    device.set_tape_version(tape.tape_version);
+    device.set_entry(tape.entry);
+    device.close_stdin();
+
+    let mut report = RunReport {
+        exit: ExitReason::EoF,
+        stdout: String::new(),
+        stderr: String::new(),
+        binary: vec![],
+        dump: device.dump(),
+        cycles: 0,
+        profile: None,
+    };
+
+    let exit = loop {
+        if let Some(script) = &mut script {
+            if let Some(bytes) = script.due(device.cycles()) {
+                device.push_bytes(&bytes);
+            }
+        }
+        let result = device.step(true);
+        if capture_output(&mut device, &mut report, options.max_output) {
+            break ExitReason::OutputLimit;
+        }
+        match result {
+            RunResult::Pause | RunResult::Breakpoint | RunResult::Watchpoint => {}
+            //stdin is closed so this shouldn't happen
+            RunResult::StdinRequested => {}
+            RunResult::SerialRequested => break ExitReason::InputRequested,
+            RunResult::Halt => break ExitReason::Halt,
+            RunResult::Exit(code) => break ExitReason::Exit(code),
+            RunResult::EoF => break ExitReason::EoF,
+            RunResult::ProgError => break ExitReason::ProgError,
+            RunResult::CycleLimit => break ExitReason::CycleLimit,
+            RunResult::CharInputRequested | RunResult::StringInputRequested => {
+                match script.as_mut().and_then(|script| script.next_chunk()) {
+                    Some(bytes) => device.push_bytes(&bytes),
+                    None => break ExitReason::InputRequested,
+                }
+            }
+        }
+    };
+
+    report.exit = exit;
+    report.dump = device.dump();
+    report.cycles = device.cycles();
+    report.profile = device.profile().cloned();
+    Ok(report)
+}
+
+///Move device output into the report, returns true if `max_output` was exceeded
+fn capture_output(device: &mut Device, report: &mut RunReport, max_output: Option<usize>) -> bool {
+    let msgs = device.take_output();
+    for output in msgs {
+        match output {
+            Output::OutputStd(text) => report.stdout.push_str(&text),
+            Output::OutputErr(text) => report.stderr.push_str(&text),
+            Output::OutputBinary(bytes) => report.binary.extend_from_slice(&bytes),
+            Output::UninitializedRead(pc, addr) => {
+                report
+                    .stderr
+                    .push_str(&uninitialized_read_text(pc, addr, None));
+                report.stderr.push('\n');
+            }
+            Output::StackCorrupted(pc, addr) => {
+                report
+                    .stderr
+                    .push_str(&stack_corrupted_text(pc, addr, None));
+                report.stderr.push('\n');
+            }
+            Output::ClearScreen
+            | Output::MoveCursor(..)
+            | Output::SetColor(_)
+            | Output::Vsync
+            | Output::BreakpointHit(_)
+            | Output::WatchpointHit(_) => {}
+        }
+    }
+    match max_output {
+        Some(max) => report.stdout.len() + report.binary.len() > max,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble_source;
+
+    fn run(source: &str, options: RunOptions) -> RunReport {
+        let tape = assemble_source(source).unwrap();
+        run_tape(tape.bytes, options).unwrap()
+    }
+
+    #[test]
+    fn test_run_tape() {
+        let report = run(
+            "Test\n1\n.strings\nhi=Hello\n.ops\nprts hi\ncpy d0 5\nhalt\n",
+            RunOptions::default(),
+        );
+        assert_eq!(report.exit, ExitReason::Halt);
+        assert_eq!(report.stdout, "Hello");
+        assert_eq!(report.stderr, "");
+        assert_eq!(report.dump.data_reg, [5, 0, 0, 0]);
+        assert_eq!(report.cycles, 3);
+    }
+
+    #[test]
+    fn test_start() {
+        let report = run(
+            "Test\n1\n.start main\n.ops\nsetup:\nprtc 'a'\nret\nmain:\ncall setup\nprtc 'b'\n",
+            RunOptions::default(),
+        );
+        assert_eq!(report.stdout, "ab");
+        assert_eq!(report.cycles, 4);
+    }
+
+    #[test]
+    fn test_limits() {
+        let program = "Test\n1\n.ops\nloop:\nprtc 'a'\njmp loop\n";
+        let report = run(
+            program,
+            RunOptions {
+                max_cycles: Some(10),
+                ..RunOptions::default()
+            },
+        );
+        assert_eq!(report.exit, ExitReason::CycleLimit);
+        assert_eq!(report.stdout, "aaaaa");
+
+        let report = run(
+            program,
+            RunOptions {
+                max_output: Some(3),
+                ..RunOptions::default()
+            },
+        );
+        assert_eq!(report.exit, ExitReason::OutputLimit);
+        assert_eq!(report.stdout, "aaaa");
+    }
+
+    #[test]
+    fn test_input_requested() {
+        let report = run("Test\n1\n.ops\nrchr d0\n", RunOptions::default());
+        assert_eq!(report.exit, ExitReason::InputRequested);
+        assert_eq!(report.dump.pc, 0);
+    }
+}
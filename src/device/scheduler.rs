@@ -0,0 +1,287 @@
+//! Runs several devices in one session (`--multi`), stepping each in turn
+//!
+//! Memory from [`MAILBOX_ADDR`] for [`MAILBOX_SIZE`] bytes is shared by every device so
+//! programs can talk to each other, bytes sent with SEND are received by every other device.
+//! Printed text is split into lines prefixed with the task name
+
+use crate::constants::hardware::{MAILBOX_ADDR, MAILBOX_SIZE};
+use crate::device::comm::{stack_corrupted_text, uninitialized_read_text, Output};
+use crate::device::internals::{Device, RunResult};
+use std::mem::swap;
+use std::ops::Range;
+
+pub struct Task {
+    pub name: String,
+    pub device: Device,
+    ///Set once the device has stopped (HALT, EXIT, error, etc), [`RunResult::SerialRequested`]
+    ///if every task was waiting for serial input so none could ever arrive
+    pub finished: Option<RunResult>,
+    ///Last step was RECV with nothing to read
+    waiting: bool,
+    ///Printed text since the last newline
+    line: String,
+}
+
+///Result of [`Scheduler::step`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Scheduled {
+    ///Task was stepped, the result is from [`Device::step`]
+    Stepped(usize, RunResult),
+    ///Every task has finished
+    Done,
+}
+
+///Round robin scheduler, each call to [`Scheduler::step`] executes one instruction of the next unfinished task
+pub struct Scheduler {
+    tasks: Vec<Task>,
+    next: usize,
+    mailbox: [u8; MAILBOX_SIZE],
+    ///Complete lines printed by the tasks, with prefixes
+    lines: Vec<String>,
+}
+
+fn mailbox_range() -> Range<usize> {
+    MAILBOX_ADDR as usize..MAILBOX_ADDR as usize + MAILBOX_SIZE
+}
+
+impl Scheduler {
+    pub fn new(devices: Vec<(String, Device)>) -> Self {
+        Scheduler {
+            tasks: devices
+                .into_iter()
+                .map(|(name, device)| Task {
+                    name,
+                    device,
+                    finished: None,
+                    waiting: false,
+                    line: String::new(),
+                })
+                .collect(),
+            next: 0,
+            mailbox: [0; MAILBOX_SIZE],
+            lines: vec![],
+        }
+    }
+
+    pub fn tasks(&self) -> &[Task] {
+        &self.tasks
+    }
+
+    pub fn task_mut(&mut self, idx: usize) -> &mut Task {
+        &mut self.tasks[idx]
+    }
+
+    ///Step the next unfinished task, the mailbox is copied in before and out after so every task sees the latest writes
+    ///
+    ///Input requests are returned without finishing the task, it will retry the instruction on its next turn
+    pub fn step(&mut self) -> Scheduled {
+        self.check_deadlock();
+        let count = self.tasks.len();
+        let idx = match (0..count)
+            .map(|offset| (self.next + offset) % count)
+            .find(|idx| self.tasks[*idx].finished.is_none())
+        {
+            Some(idx) => idx,
+            None => return Scheduled::Done,
+        };
+        self.next = (idx + 1) % count;
+
+        let task = &mut self.tasks[idx];
+        task.device.mem[mailbox_range()].copy_from_slice(&self.mailbox);
+        let result = task.device.step(true);
+        self.mailbox
+            .copy_from_slice(&task.device.mem[mailbox_range()]);
+        task.waiting = result == RunResult::SerialRequested;
+        match result {
+            RunResult::Halt
+            | RunResult::Exit(_)
+            | RunResult::EoF
+            | RunResult::ProgError
+            | RunResult::CycleLimit => task.finished = Some(result),
+            _ => {}
+        }
+        let mut sent = vec![];
+        swap(&mut task.device.serial_out, &mut sent);
+        if !sent.is_empty() {
+            for (other_idx, other) in self.tasks.iter_mut().enumerate() {
+                if other_idx != idx && other.finished.is_none() {
+                    other.device.serial_in.extend_from_slice(&sent);
+                }
+            }
+        }
+        self.collect_output(idx);
+        Scheduled::Stepped(idx, result)
+    }
+
+    ///Stop the tasks if every one is waiting for serial input and none has been sent
+    fn check_deadlock(&mut self) {
+        let mut running = self
+            .tasks
+            .iter()
+            .filter(|task| task.finished.is_none())
+            .peekable();
+        if running.peek().is_some()
+            && running.all(|task| task.waiting && task.device.serial_in.is_empty())
+        {
+            for task in &mut self.tasks {
+                if task.finished.is_none() {
+                    task.finished = Some(RunResult::SerialRequested);
+                }
+            }
+        }
+    }
+
+    ///Complete lines printed since the last call, each prefixed with `[name] `
+    pub fn take_lines(&mut self) -> Vec<String> {
+        let mut lines = vec![];
+        swap(&mut self.lines, &mut lines);
+        lines
+    }
+
+    ///Move any unfinished lines into [`Scheduler::take_lines`], call once all tasks have finished
+    pub fn flush(&mut self) {
+        for task in &mut self.tasks {
+            if !task.line.is_empty() {
+                self.lines.push(format!("[{}] {}", task.name, task.line));
+                task.line.clear();
+            }
+        }
+    }
+
+    fn collect_output(&mut self, idx: usize) {
+        let task = &mut self.tasks[idx];
+        let msgs = task.device.take_output();
+        for output in msgs {
+            let text = match output {
+                Output::OutputStd(text) | Output::OutputErr(text) => text,
+                Output::OutputBinary(bytes) => String::from_utf8_lossy(&bytes).to_string(),
+                Output::UninitializedRead(pc, addr) => {
+                    format!("{}\n", uninitialized_read_text(pc, addr, None))
+                }
+                Output::StackCorrupted(pc, addr) => {
+                    format!("{}\n", stack_corrupted_text(pc, addr, None))
+                }
+                //screen control doesn't make sense when tasks share the terminal
+                Output::ClearScreen
+                | Output::MoveCursor(..)
+                | Output::SetColor(_)
+                | Output::Vsync
+                | Output::BreakpointHit(_)
+                | Output::WatchpointHit(_) => continue,
+            };
+            for chr in text.chars() {
+                if chr == '\n' {
+                    self.lines.push(format!("[{}] {}", task.name, task.line));
+                    task.line.clear();
+                } else {
+                    task.line.push(chr);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble_source;
+    use crate::tape_reader::parse_tape_strict;
+
+    fn device(source: &str) -> Device {
+        let tape = parse_tape_strict(assemble_source(source).unwrap().bytes).unwrap();
+        let mut device = Device::new(tape.ops, tape.strings, tape.data, vec![]);
+        device.close_stdin();
+        device
+    }
+
+    fn run(scheduler: &mut Scheduler) {
+        for _ in 0..10_000 {
+            if scheduler.step() == Scheduled::Done {
+                scheduler.flush();
+                return;
+            }
+        }
+        panic!("Scheduler didn't finish");
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let mut scheduler = Scheduler::new(vec![
+            (
+                String::from("a"),
+                device("Test\n1\n.ops\nprtc 'a'\nprtln\nhalt\n"),
+            ),
+            (
+                String::from("b"),
+                device("Test\n1\n.ops\nprtc 'b'\nexit 2\n"),
+            ),
+        ]);
+        assert_eq!(scheduler.step(), Scheduled::Stepped(0, RunResult::Pause));
+        assert_eq!(scheduler.step(), Scheduled::Stepped(1, RunResult::Pause));
+        assert_eq!(scheduler.step(), Scheduled::Stepped(0, RunResult::Pause));
+        assert_eq!(scheduler.step(), Scheduled::Stepped(1, RunResult::Exit(2)));
+        assert_eq!(scheduler.step(), Scheduled::Stepped(0, RunResult::Halt));
+        assert_eq!(scheduler.step(), Scheduled::Done);
+        scheduler.flush();
+        assert_eq!(scheduler.take_lines(), vec!["[a] a", "[b] b"]);
+        assert_eq!(scheduler.tasks()[1].finished, Some(RunResult::Exit(2)));
+    }
+
+    #[test]
+    fn test_mailbox() {
+        //sender writes 42 to the mailbox, receiver waits for it and prints it
+        let mut scheduler = Scheduler::new(vec![
+            (
+                String::from("recv"),
+                device(
+                    "Test\n1\n.ops\ncpy a0 @xB000\nwait: memr a0\ncmp acc 0\nje wait\nprt acc\n",
+                ),
+            ),
+            (
+                String::from("send"),
+                device("Test\n1\n.ops\ncpy a0 @xB000\ncpy acc 42\nmemw a0\n"),
+            ),
+        ]);
+        run(&mut scheduler);
+        assert_eq!(scheduler.take_lines(), vec!["[recv] 42"]);
+    }
+
+    #[test]
+    fn test_serial() {
+        //client sends a number, server replies with it plus one
+        let mut scheduler = Scheduler::new(vec![
+            (
+                String::from("server"),
+                device(
+                    "Test
+1
+.ops
+recv d0
+inc d0
+send d0
+",
+                ),
+            ),
+            (
+                String::from("client"),
+                device(
+                    "Test
+1
+.ops
+send 4
+recv d1
+prt d1
+recv d1
+",
+                ),
+            ),
+        ]);
+        run(&mut scheduler);
+        assert_eq!(scheduler.take_lines(), vec!["[client] 5"]);
+        assert_eq!(scheduler.tasks()[0].finished, Some(RunResult::EoF));
+        assert_eq!(
+            scheduler.tasks()[1].finished,
+            Some(RunResult::SerialRequested)
+        );
+    }
+}
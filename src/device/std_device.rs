@@ -1,69 +1,209 @@
-use crate::device::comm::Output;
-use crate::device::input::{read_char, read_str};
-use crate::device::internals::{Device, RunResult};
+use crate::assembler::debug_model::DebugModel;
+use crate::device::comm::{stack_corrupted_text, uninitialized_read_text, Output, OutputSink};
+use crate::device::input::{read_char, read_str, InputScript};
+use crate::device::internals::{Device, Profile, RunResult};
+use crate::device::util::terminal_color;
+use crate::device::RunOptions;
+use crossterm::cursor::MoveTo;
 use crossterm::style::{Color, Print, ResetColor, SetForegroundColor};
+use crossterm::terminal::{Clear, ClearType};
 use crossterm::ExecutableCommand;
-use std::io::stdout;
-use std::mem::swap;
+use std::io::{stdin, stdout, Read, Write};
 
 pub struct StdDevice {
     device: Device,
     last_run_result: RunResult,
+    ///Used for keyboard input instead of the terminal if set
+    input_script: Option<InputScript>,
+    ///Used to add source lines to warnings if set, moved to the output sink by [`StdDevice::run`]
+    debug: Option<DebugModel>,
 }
 
 impl StdDevice {
-    pub fn new(ops: Vec<u8>, strings: Vec<u8>, data: Vec<u8>, data_files: Vec<String>) -> Self {
+    pub fn new(
+        ops: Vec<u8>,
+        strings: Vec<u8>,
+        data: Vec<u8>,
+        data_files: Vec<String>,
+        options: &RunOptions,
+    ) -> Self {
+        let mut device = Device::new(ops, strings, data, data_files);
+        options.configure(&mut device);
         StdDevice {
-            device: Device::new(ops, strings, data, data_files),
+            device,
             last_run_result: RunResult::Pause,
+            input_script: None,
+            debug: None,
         }
     }
+
+    pub fn set_input_script(&mut self, script: InputScript) {
+        self.input_script = Some(script);
+    }
+
+    pub fn set_debug(&mut self, debug: DebugModel) {
+        self.debug = Some(debug);
+    }
+
+    pub fn set_tape_version(&mut self, version: u8) {
+        self.device.set_tape_version(version);
+    }
+
+    pub fn set_entry(&mut self, addr: u16) {
+        self.device.set_entry(addr);
+    }
 }
 
 impl StdDevice {
-    pub fn run(&mut self) {
+    pub fn profile(&self) -> Option<&Profile> {
+        self.device.profile()
+    }
+
+    ///Returns the exit code if the program ended with EXIT
+    pub fn run(&mut self) -> Option<u8> {
+        self.device.set_output_sink(Box::new(StdOutput {
+            color: None,
+            debug: self.debug.take(),
+        }));
         loop {
             match self.last_run_result {
-                RunResult::Pause => self.last_run_result = self.device.step(true),
+                RunResult::Pause => {
+                    if let Some(script) = &mut self.input_script {
+                        if let Some(bytes) = script.due(self.device.cycles()) {
+                            self.device.push_bytes(&bytes);
+                        }
+                    }
+                    self.last_run_result = self.device.step(true)
+                }
                 RunResult::Breakpoint => panic!("Encountered and stopped for breakpoint"),
-                RunResult::EoF => return,
-                RunResult::ProgError => return,
-                RunResult::Halt => return,
+                RunResult::Watchpoint => panic!("Encountered and stopped for watchpoint"),
+                RunResult::EoF => return None,
+                RunResult::ProgError => return None,
+                RunResult::Halt => return None,
+                RunResult::Exit(code) => return Some(code),
+                RunResult::CycleLimit => return None,
+                RunResult::SerialRequested => {
+                    eprintln!("\nProgram is waiting for serial input but nothing is connected");
+                    return None;
+                }
+                RunResult::CharInputRequested | RunResult::StringInputRequested
+                    if self.input_script.is_some() =>
+                {
+                    match self
+                        .input_script
+                        .as_mut()
+                        .and_then(|script| script.next_chunk())
+                    {
+                        Some(bytes) => {
+                            self.device.push_bytes(&bytes);
+                            self.last_run_result = RunResult::Pause;
+                        }
+                        None => {
+                            eprintln!("\nProgram requested input but the input script is empty");
+                            return None;
+                        }
+                    }
+                }
                 RunResult::CharInputRequested => {
                     let chr = read_char().expect("Error reading input (char)");
-                    self.device.keyboard_buffer.push(chr);
+                    self.device.push_char(chr);
                     self.last_run_result = RunResult::Pause;
                 }
                 RunResult::StringInputRequested => {
                     let input = read_str();
-                    self.device.keyboard_buffer.extend_from_slice(&input);
+                    self.device.push_bytes(&input);
                     self.last_run_result = RunResult::Pause;
                 }
-            }
-
-            let mut msgs = vec![];
-            swap(&mut self.device.output, &mut msgs);
-            for output in msgs {
-                match output {
-                    Output::OutputStd(text) => {
-                        stdout()
-                            .execute(ResetColor)
-                            .expect("Error setting foreground color")
-                            .execute(Print(text))
-                            .expect("Error printing output");
-                    }
-                    Output::OutputErr(text) => {
-                        stdout()
-                            .execute(SetForegroundColor(Color::Red))
-                            .expect("Error setting foreground color")
-                            .execute(Print(text))
-                            .expect("Error printing error output")
-                            .execute(ResetColor)
-                            .expect("Error setting foreground color");
+                RunResult::StdinRequested => {
+                    let mut buffer = [0_u8; 256];
+                    match stdin().read(&mut buffer) {
+                        Ok(0) | Err(_) => self.device.close_stdin(),
+                        Ok(count) => self.device.stdin_buffer.extend_from_slice(&buffer[..count]),
                     }
-                    Output::BreakpointHit(_) => panic!("Encountered and stopped for breakpoint"),
+                    self.last_run_result = RunResult::Pause;
                 }
             }
         }
     }
 }
+
+///Prints output to the terminal as the device produces it
+struct StdOutput {
+    ///Set by COLOR, reapplied after error output
+    color: Option<u8>,
+    ///Used to add source lines to warnings if set
+    debug: Option<DebugModel>,
+}
+
+impl OutputSink for StdOutput {
+    fn push(&mut self, output: Output) {
+        match output {
+            Output::OutputStd(text) => {
+                stdout()
+                    .execute(SetForegroundColor(terminal_color(self.color)))
+                    .expect("Error setting foreground color")
+                    .execute(Print(text))
+                    .expect("Error printing output");
+            }
+            Output::OutputErr(text) => {
+                stdout()
+                    .execute(SetForegroundColor(Color::Red))
+                    .expect("Error setting foreground color")
+                    .execute(Print(text))
+                    .expect("Error printing error output")
+                    .execute(ResetColor)
+                    .expect("Error setting foreground color");
+            }
+            Output::UninitializedRead(pc, addr) => {
+                let line = self
+                    .debug
+                    .as_ref()
+                    .and_then(|debug| debug.line_for_byte(pc));
+                stdout()
+                    .execute(SetForegroundColor(Color::Yellow))
+                    .expect("Error setting foreground color")
+                    .execute(Print(format!(
+                        "{}\n",
+                        uninitialized_read_text(pc, addr, line)
+                    )))
+                    .expect("Error printing warning")
+                    .execute(ResetColor)
+                    .expect("Error setting foreground color");
+            }
+            Output::StackCorrupted(pc, addr) => {
+                let line = self
+                    .debug
+                    .as_ref()
+                    .and_then(|debug| debug.line_for_byte(pc));
+                stdout()
+                    .execute(SetForegroundColor(Color::Red))
+                    .expect("Error setting foreground color")
+                    .execute(Print(format!("{}\n", stack_corrupted_text(pc, addr, line))))
+                    .expect("Error printing error output")
+                    .execute(ResetColor)
+                    .expect("Error setting foreground color");
+            }
+            Output::ClearScreen => {
+                stdout()
+                    .execute(Clear(ClearType::All))
+                    .expect("Error clearing screen")
+                    .execute(MoveTo(0, 0))
+                    .expect("Error moving cursor");
+            }
+            Output::MoveCursor(column, row) => {
+                stdout()
+                    .execute(MoveTo(column as u16, row as u16))
+                    .expect("Error moving cursor");
+            }
+            Output::SetColor(color) => self.color = color,
+            Output::Vsync => {}
+            Output::OutputBinary(bytes) => {
+                let mut out = stdout();
+                out.write_all(&bytes).expect("Error writing stdout");
+                out.flush().expect("Error writing stdout");
+            }
+            Output::BreakpointHit(_) => panic!("Encountered and stopped for breakpoint"),
+            Output::WatchpointHit(_) => panic!("Encountered and stopped for watchpoint"),
+        }
+    }
+}
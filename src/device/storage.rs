@@ -0,0 +1,238 @@
+//! Key value storage for SAVE and LOADKV, kept in a `.sav` file next to the tape
+//!
+//! The file is a list of records, each is the key length (1 byte), the key, the value length
+//! (1 byte) and then the value. Records are sorted by key.
+//!
+//! The file is read and written through the device's [`FileProvider`] so it follows the same
+//! rules as FOPEN (sandbox, in memory files, etc).
+
+use crate::device::files::FileProvider;
+use anyhow::{Error, Result};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+pub const SAVE_EXTENSION: &str = "sav";
+///Most keys a save file can have, SAVE fails for new keys once this is reached
+pub const MAX_RECORDS: usize = 256;
+
+///Save file for the tape at `tape_path`, i.e. game.sav for game.tape
+///
+///If `sandbox` is set then the file is put in it instead of next to the tape, as the program
+///wouldn't be able to use it otherwise
+pub fn save_path(tape_path: &Path, sandbox: Option<&Path>) -> PathBuf {
+    let path = tape_path.with_extension(SAVE_EXTENSION);
+    match (sandbox, path.file_name()) {
+        (Some(dir), Some(name)) => dir.join(name),
+        _ => path,
+    }
+}
+
+pub fn parse_records(bytes: &[u8]) -> Result<BTreeMap<String, Vec<u8>>> {
+    let mut records = BTreeMap::new();
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let key = read_field(bytes, &mut idx)?;
+        let value = read_field(bytes, &mut idx)?;
+        let key = String::from_utf8(key.to_vec())
+            .map_err(|_| Error::msg(format!("Save file key at {} isn't valid text", idx)))?;
+        records.insert(key, value.to_vec());
+    }
+    if records.len() > MAX_RECORDS {
+        return Err(Error::msg(format!(
+            "Save file has {} records, max {}",
+            records.len(),
+            MAX_RECORDS
+        )));
+    }
+    Ok(records)
+}
+
+///Length prefixed bytes at `idx`, `idx` is moved past them
+fn read_field<'a>(bytes: &'a [u8], idx: &mut usize) -> Result<&'a [u8]> {
+    let len = bytes
+        .get(*idx)
+        .ok_or_else(|| Error::msg(format!("Save file record at {} is missing its value", idx)))?;
+    let start = *idx + 1;
+    let end = start + *len as usize;
+    if end > bytes.len() {
+        return Err(Error::msg(format!(
+            "Save file record at {} is {} bytes but only {} bytes remain",
+            idx,
+            end - start,
+            bytes.len().saturating_sub(start)
+        )));
+    }
+    *idx = end;
+    Ok(&bytes[start..end])
+}
+
+///Keys and values must be at most 255 bytes
+pub fn write_records(records: &BTreeMap<String, Vec<u8>>) -> Vec<u8> {
+    let mut bytes = vec![];
+    for (key, value) in records {
+        bytes.push(key.len() as u8);
+        bytes.extend_from_slice(key.as_bytes());
+        bytes.push(value.len() as u8);
+        bytes.extend_from_slice(value);
+    }
+    bytes
+}
+
+///Records of a save file, read the first time they're used
+#[derive(Debug, Clone)]
+pub struct KvStore {
+    path: PathBuf,
+    records: Option<BTreeMap<String, Vec<u8>>>,
+}
+
+impl KvStore {
+    pub fn new(path: PathBuf) -> Self {
+        KvStore {
+            path,
+            records: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    ///None if `key` hasn't been saved
+    pub fn get(&mut self, provider: &mut dyn FileProvider, key: &str) -> Result<Option<&[u8]>> {
+        Ok(self.records(provider)?.get(key).map(Vec::as_slice))
+    }
+
+    ///Set `key` to `value` and rewrite the file
+    pub fn set(
+        &mut self,
+        provider: &mut dyn FileProvider,
+        key: String,
+        value: Vec<u8>,
+    ) -> Result<()> {
+        if key.len() > u8::MAX as usize || value.len() > u8::MAX as usize {
+            return Err(Error::msg(format!(
+                "Save key '{}' and value must be at most {} bytes",
+                key,
+                u8::MAX
+            )));
+        }
+        let records = self.records(provider)?;
+        if records.len() >= MAX_RECORDS && !records.contains_key(&key) {
+            return Err(Error::msg(format!(
+                "Save file already has {} keys, can't add '{}'",
+                MAX_RECORDS, key
+            )));
+        }
+        records.insert(key, value);
+        let bytes = write_records(records);
+        let mut file = provider.open(&self.path).map_err(|err| self.error(err))?;
+        file.set_len(0)
+            .and_then(|_| file.write_all(&bytes))
+            .and_then(|_| file.flush())
+            .map_err(|err| self.error(Error::from(err)))
+    }
+
+    fn records(
+        &mut self,
+        provider: &mut dyn FileProvider,
+    ) -> Result<&mut BTreeMap<String, Vec<u8>>> {
+        if self.records.is_none() {
+            if provider.canonicalize(&self.path).is_none() {
+                return Err(self.error(Error::msg("path not allowed")));
+            }
+            //a missing file is the same as an empty one, it's created by the first SAVE
+            let records = match provider.open_read_only(&self.path) {
+                Ok(mut file) => {
+                    let mut bytes = vec![];
+                    file.read_to_end(&mut bytes)
+                        .map_err(|err| self.error(Error::from(err)))?;
+                    parse_records(&bytes).map_err(|err| self.error(err))?
+                }
+                Err(_) => BTreeMap::new(),
+            };
+            self.records = Some(records);
+        }
+        Ok(self.records.get_or_insert_with(BTreeMap::new))
+    }
+
+    fn error(&self, err: Error) -> Error {
+        Error::msg(format!(
+            "Unable to use save file {}: {}",
+            self.path.display(),
+            err
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::device::files::MemoryFileProvider;
+
+    #[test]
+    fn test_records() {
+        let mut records = BTreeMap::new();
+        records.insert(String::from("score"), vec![1, 2]);
+        records.insert(String::from("name"), b"ab".to_vec());
+        let bytes = write_records(&records);
+        assert_eq!(
+            bytes,
+            vec![
+                4, b'n', b'a', b'm', b'e', 2, b'a', b'b', 5, b's', b'c', b'o', b'r', b'e', 2, 1, 2
+            ]
+        );
+        assert_eq!(parse_records(&bytes).unwrap(), records);
+        assert!(parse_records(&[]).unwrap().is_empty());
+        assert!(parse_records(&[1, b'a']).is_err());
+        assert!(parse_records(&[1, b'a', 3, 1]).is_err());
+    }
+
+    #[test]
+    fn test_kv_store() {
+        let mut provider = MemoryFileProvider::default();
+        let mut store = KvStore::new(PathBuf::from("game.sav"));
+        assert_eq!(store.get(&mut provider, "score").unwrap(), None);
+        assert_eq!(provider.get("game.sav"), None);
+
+        store
+            .set(&mut provider, String::from("score"), vec![9])
+            .unwrap();
+        assert_eq!(
+            provider.get("game.sav"),
+            Some(vec![5, b's', b'c', b'o', b'r', b'e', 1, 9])
+        );
+        store
+            .set(&mut provider, String::from("score"), vec![])
+            .unwrap();
+        assert_eq!(
+            store.get(&mut provider, "score").unwrap(),
+            Some([].as_slice())
+        );
+
+        let mut store = KvStore::new(PathBuf::from("game.sav"));
+        assert_eq!(
+            store.get(&mut provider, "score").unwrap(),
+            Some([].as_slice())
+        );
+        assert!(store
+            .set(&mut provider, String::from("big"), vec![0; 256])
+            .is_err());
+
+        provider.insert("bad.sav", vec![10]);
+        let mut store = KvStore::new(PathBuf::from("bad.sav"));
+        assert!(store.get(&mut provider, "score").is_err());
+    }
+
+    #[test]
+    fn test_save_path() {
+        assert_eq!(
+            save_path(Path::new("games/snake.tape"), None),
+            PathBuf::from("games/snake.sav")
+        );
+        assert_eq!(
+            save_path(Path::new("games/snake.tape"), Some(Path::new("/sandbox"))),
+            PathBuf::from("/sandbox/snake.sav")
+        );
+    }
+}
@@ -1,3 +1,13 @@
+use crossterm::style::Color;
+
+///Terminal color for [`crate::device::comm::Output::SetColor`]
+pub fn terminal_color(color: Option<u8>) -> Color {
+    match color {
+        Some(value) => Color::AnsiValue(value),
+        None => Color::Reset,
+    }
+}
+
 pub fn convert_and_fit(parts: Vec<&str>, max_width: usize, padding: &str) -> Vec<String> {
     fit_in_lines(
         parts
@@ -33,8 +43,19 @@ pub fn fit_in_lines(parts: Vec<String>, max_width: usize) -> Vec<String> {
     output
 }
 
+///Cut line to fit in max_width chars, marking the cut with '…'
+pub fn truncate_line(line: String, max_width: usize) -> String {
+    if line.chars().count() <= max_width {
+        line
+    } else {
+        let mut output: String = line.chars().take(max_width.saturating_sub(1)).collect();
+        output.push('…');
+        output
+    }
+}
+
 pub fn breaklines(mut line: String, max_width: usize) -> Vec<String> {
-    return if line.chars().count() < max_width {
+    if line.chars().count() < max_width {
         vec![line]
     } else {
         let mut output = vec![];
@@ -59,7 +80,7 @@ pub fn breaklines(mut line: String, max_width: usize) -> Vec<String> {
             }
         }
         output
-    };
+    }
 }
 
 fn index_of_first_non_alphanumeric_before(str: &str, start: usize) -> Option<usize> {
@@ -156,6 +177,22 @@ mod test {
             );
         }
 
+        #[test]
+        fn test_truncate_line() {
+            assert_eq!(
+                truncate_line(String::from("short"), 10),
+                String::from("short")
+            );
+            assert_eq!(
+                truncate_line(String::from("exactly"), 7),
+                String::from("exactly")
+            );
+            assert_eq!(
+                truncate_line(String::from("much too long"), 8),
+                String::from("much to…")
+            );
+        }
+
         #[test]
         fn test_breaklines() {
             let str = String::from(
@@ -271,14 +308,14 @@ mod test {
 
         #[test]
         fn test_index_of_first_before() {
-            let list = vec![1, 2, 3];
+            let list = [1, 2, 3];
             let result1 = index_of_first_before(&mut list.iter(), |num| num == &2, 0);
             let result2 = index_of_first_before(&mut list.iter(), |num| num == &2, 2);
 
             assert_eq!(result1, None);
             assert_eq!(result2, Some(1));
 
-            let list = vec!['a', 'k', 'p', 'n'];
+            let list = ['a', 'k', 'p', 'n'];
             let result1 = index_of_first_before(&mut list.iter(), |chr| chr == &'n', 0);
             let result2 = index_of_first_before(&mut list.iter(), |chr| chr == &'p', 2);
             let result3 = index_of_first_before(&mut list.iter(), |chr| chr == &'p', 3);
@@ -0,0 +1,154 @@
+use crate::common::read_lines;
+use anyhow::{Error, Result};
+#[cfg(feature = "terminal")]
+use crossterm::style::Colorize;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DiffLine {
+    Same(String),
+    ///Line is in the expected listing but not the generated one
+    Missing(String),
+    ///Line is in the generated listing but not the expected one
+    Extra(String),
+}
+
+///Line based diff (using longest common subsequence)
+pub fn diff_lines(expected: &[String], actual: &[String]) -> Vec<DiffLine> {
+    let mut lcs = vec![vec![0_usize; actual.len() + 1]; expected.len() + 1];
+    for e in (0..expected.len()).rev() {
+        for a in (0..actual.len()).rev() {
+            lcs[e][a] = if expected[e] == actual[a] {
+                lcs[e + 1][a + 1] + 1
+            } else {
+                lcs[e + 1][a].max(lcs[e][a + 1])
+            };
+        }
+    }
+
+    let mut output = vec![];
+    let (mut e, mut a) = (0, 0);
+    while e < expected.len() && a < actual.len() {
+        if expected[e] == actual[a] {
+            output.push(DiffLine::Same(expected[e].clone()));
+            e += 1;
+            a += 1;
+        } else if lcs[e + 1][a] >= lcs[e][a + 1] {
+            output.push(DiffLine::Missing(expected[e].clone()));
+            e += 1;
+        } else {
+            output.push(DiffLine::Extra(actual[a].clone()));
+            a += 1;
+        }
+    }
+    output.extend(expected[e..].iter().cloned().map(DiffLine::Missing));
+    output.extend(actual[a..].iter().cloned().map(DiffLine::Extra));
+    output
+}
+
+///Compare `actual` against golden file at `expected_path`, printing a coloured diff if they differ
+///
+///`kind` names what's being compared for messages (i.e. `Listing`). Returns an error if they
+///don't match so the process exits with a non zero code
+pub fn check_expected(kind: &str, expected_path: &str, actual: &[String]) -> Result<()> {
+    let expected: Vec<String> = read_lines(expected_path)?
+        .iter()
+        .map(|line| line.trim_end().to_string())
+        .collect();
+    let actual: Vec<String> = actual
+        .iter()
+        .map(|line| line.trim_end().to_string())
+        .collect();
+
+    let (changes, output) = format_diff(&expected, &actual);
+    if changes == 0 {
+        println!("{} matches {}", kind, expected_path);
+        return Ok(());
+    }
+
+    for line in output {
+        println!("{}", line);
+    }
+    Err(Error::msg(format!(
+        "{} does not match {} ({} lines differ)",
+        kind, expected_path, changes
+    )))
+}
+
+///Number of lines that differ and the coloured diff (`-` expected, `+` actual)
+pub fn format_diff(expected: &[String], actual: &[String]) -> (usize, Vec<String>) {
+    let diff = diff_lines(expected, actual);
+    let changes = diff
+        .iter()
+        .filter(|line| !matches!(line, DiffLine::Same(_)))
+        .count();
+    let output = diff
+        .into_iter()
+        .map(|line| match line {
+            DiffLine::Same(line) => format!("  {}", line),
+            DiffLine::Missing(line) => red(format!("- {}", line)),
+            DiffLine::Extra(line) => green(format!("+ {}", line)),
+        })
+        .collect();
+    (changes, output)
+}
+
+#[cfg(feature = "terminal")]
+fn red(text: String) -> String {
+    text.red().to_string()
+}
+
+#[cfg(feature = "terminal")]
+fn green(text: String) -> String {
+    text.green().to_string()
+}
+
+#[cfg(not(feature = "terminal"))]
+fn red(text: String) -> String {
+    text
+}
+
+#[cfg(not(feature = "terminal"))]
+fn green(text: String) -> String {
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::lines;
+
+    #[test]
+    fn test_identical() {
+        let listing = lines("CPY D0 1\nPRT D0\nHALT");
+        let diff = diff_lines(&listing, &listing);
+        assert!(diff.iter().all(|line| matches!(line, DiffLine::Same(_))));
+        assert_eq!(diff.len(), 3);
+    }
+
+    #[test]
+    fn test_changed_line() {
+        let diff = diff_lines(
+            &lines("CPY D0 1\nPRT D0\nHALT"),
+            &lines("CPY D0 2\nPRT D0\nHALT\nNOP"),
+        );
+        assert_eq!(
+            diff,
+            vec![
+                DiffLine::Missing(String::from("CPY D0 1")),
+                DiffLine::Extra(String::from("CPY D0 2")),
+                DiffLine::Same(String::from("PRT D0")),
+                DiffLine::Same(String::from("HALT")),
+                DiffLine::Extra(String::from("NOP")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(
+            diff_lines(&lines("HALT"), &[]),
+            vec![DiffLine::Missing(String::from("HALT"))]
+        );
+        assert_eq!(diff_lines(&[], &[]), vec![]);
+    }
+}
@@ -0,0 +1,401 @@
+//! C API for embedding the device in other languages
+//!
+//! Devices are created with [`td_device_new`] and must be freed with [`td_device_free`].
+//! Functions return [`TD_OK`] or a negative `TD_ERR_` code, [`td_device_step`] returns a `TD_STEP_` code.
+
+use crate::constants::hardware::{ADDR_REG_COUNT, DATA_REG_COUNT, RAM_SIZE};
+use crate::device::comm::{stack_corrupted_text, uninitialized_read_text, Output};
+use crate::device::internals::{Device, RunResult};
+use crate::tape_reader::{link_data, parse_tape_strict};
+use std::slice;
+
+pub const TD_OK: i32 = 0;
+///A required pointer was null
+pub const TD_ERR_NULL: i32 = -1;
+///Memory range is outside of RAM
+pub const TD_ERR_OUT_OF_RANGE: i32 = -2;
+///Program hasn't run EXIT, see [`td_device_exit_code`]
+pub const TD_ERR_NOT_EXITED: i32 = -3;
+
+pub const TD_STEP_PAUSE: i32 = 0;
+pub const TD_STEP_HALT: i32 = 1;
+pub const TD_STEP_EOF: i32 = 2;
+///Program error, the message can be read with [`td_device_take_errors`]
+pub const TD_STEP_ERROR: i32 = 3;
+pub const TD_STEP_CYCLE_LIMIT: i32 = 4;
+///Program is waiting for a key, see [`td_device_push_input`]
+pub const TD_STEP_CHAR_INPUT: i32 = 5;
+///Program is waiting for a line of text, see [`td_device_push_input`]
+pub const TD_STEP_STRING_INPUT: i32 = 6;
+///Program is waiting for stdin, see [`td_device_push_stdin`]
+pub const TD_STEP_STDIN: i32 = 7;
+pub const TD_STEP_BREAKPOINT: i32 = 8;
+pub const TD_STEP_WATCHPOINT: i32 = 9;
+///Program ended with EXIT, the code can be read with [`td_device_exit_code`]
+pub const TD_STEP_EXIT: i32 = 10;
+///Program is waiting for a serial byte, see [`td_device_push_serial`]
+pub const TD_STEP_SERIAL_INPUT: i32 = 11;
+
+///Opaque handle to a device
+pub struct TdDevice {
+    device: Device,
+    ///Printed text and bytes written to stdout not yet taken by the host
+    output: Vec<u8>,
+    ///Error messages not yet taken by the host
+    errors: Vec<u8>,
+    ///Set when the program runs EXIT
+    exit_code: Option<u8>,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct TdRegisters {
+    pub pc: u16,
+    pub acc: u8,
+    pub sp: u16,
+    pub fp: u16,
+    pub data_reg: [u8; DATA_REG_COUNT],
+    pub addr_reg: [u16; ADDR_REG_COUNT],
+    pub flags: u8,
+}
+
+impl TdDevice {
+    fn collect_output(&mut self) {
+        let msgs = self.device.take_output();
+        for output in msgs {
+            match output {
+                Output::OutputStd(text) => self.output.extend_from_slice(text.as_bytes()),
+                Output::OutputBinary(bytes) => self.output.extend_from_slice(&bytes),
+                Output::OutputErr(text) => self.errors.extend_from_slice(text.as_bytes()),
+                Output::UninitializedRead(pc, addr) => self.errors.extend_from_slice(
+                    format!("{}\n", uninitialized_read_text(pc, addr, None)).as_bytes(),
+                ),
+                Output::StackCorrupted(pc, addr) => self.errors.extend_from_slice(
+                    format!("{}\n", stack_corrupted_text(pc, addr, None)).as_bytes(),
+                ),
+                Output::ClearScreen
+                | Output::MoveCursor(..)
+                | Output::SetColor(_)
+                | Output::Vsync
+                | Output::BreakpointHit(_)
+                | Output::WatchpointHit(_) => {}
+            }
+        }
+    }
+}
+
+///Create a device running the tape in `bytes`, returns null if the tape is invalid or uses data tapes
+///
+/// # Safety
+/// `bytes` must point to `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn td_device_new(bytes: *const u8, len: usize) -> *mut TdDevice {
+    if bytes.is_null() {
+        return std::ptr::null_mut();
+    }
+    let bytes = slice::from_raw_parts(bytes, len).to_vec();
+    let mut tape = match parse_tape_strict(bytes) {
+        Ok(tape) => tape,
+        Err(_) => return std::ptr::null_mut(),
+    };
+    if link_data(&mut tape, vec![]).is_err() {
+        return std::ptr::null_mut();
+    }
+    let mut device = Device::new(tape.ops, tape.strings, tape.data, vec![]);
+    device.set_tape_version(tape.tape_version);
+    device.set_entry(tape.entry);
+    Box::into_raw(Box::new(TdDevice {
+        device,
+        output: vec![],
+        errors: vec![],
+        exit_code: None,
+    }))
+}
+
+///Free a device created by [`td_device_new`], does nothing if `device` is null
+///
+/// # Safety
+/// `device` must have been returned by [`td_device_new`] and not already freed
+#[no_mangle]
+pub unsafe extern "C" fn td_device_free(device: *mut TdDevice) {
+    if !device.is_null() {
+        drop(Box::from_raw(device));
+    }
+}
+
+///Execute one instruction, returns a `TD_STEP_` code
+///
+/// # Safety
+/// `device` must be a valid handle from [`td_device_new`]
+#[no_mangle]
+pub unsafe extern "C" fn td_device_step(device: *mut TdDevice) -> i32 {
+    let device = match device.as_mut() {
+        Some(device) => device,
+        None => return TD_ERR_NULL,
+    };
+    let result = device.device.step(false);
+    device.collect_output();
+    match result {
+        RunResult::Pause => TD_STEP_PAUSE,
+        RunResult::Halt => TD_STEP_HALT,
+        RunResult::Exit(code) => {
+            device.exit_code = Some(code);
+            TD_STEP_EXIT
+        }
+        RunResult::EoF => TD_STEP_EOF,
+        RunResult::ProgError => TD_STEP_ERROR,
+        RunResult::CycleLimit => TD_STEP_CYCLE_LIMIT,
+        RunResult::CharInputRequested => TD_STEP_CHAR_INPUT,
+        RunResult::StringInputRequested => TD_STEP_STRING_INPUT,
+        RunResult::StdinRequested => TD_STEP_STDIN,
+        RunResult::Breakpoint => TD_STEP_BREAKPOINT,
+        RunResult::Watchpoint => TD_STEP_WATCHPOINT,
+        RunResult::SerialRequested => TD_STEP_SERIAL_INPUT,
+    }
+}
+
+///Exit code of the program if it ended with EXIT, otherwise [`TD_ERR_NOT_EXITED`]
+///
+/// # Safety
+/// `device` must be a valid handle from [`td_device_new`]
+#[no_mangle]
+pub unsafe extern "C" fn td_device_exit_code(device: *const TdDevice) -> i32 {
+    match device.as_ref() {
+        Some(device) => device
+            .exit_code
+            .map(|code| code as i32)
+            .unwrap_or(TD_ERR_NOT_EXITED),
+        None => TD_ERR_NULL,
+    }
+}
+
+///Copy the registers into `registers`
+///
+/// # Safety
+/// `device` must be a valid handle from [`td_device_new`] and `registers` must be writable
+#[no_mangle]
+pub unsafe extern "C" fn td_device_registers(
+    device: *const TdDevice,
+    registers: *mut TdRegisters,
+) -> i32 {
+    let (device, registers) = match (device.as_ref(), registers.as_mut()) {
+        (Some(device), Some(registers)) => (device, registers),
+        _ => return TD_ERR_NULL,
+    };
+    let dump = device.device.dump();
+    *registers = TdRegisters {
+        pc: dump.pc,
+        acc: dump.acc,
+        sp: dump.sp,
+        fp: dump.fp,
+        data_reg: dump.data_reg,
+        addr_reg: dump.addr_reg,
+        flags: dump.flags,
+    };
+    TD_OK
+}
+
+///Copy `len` bytes of memory starting at `start` into `buffer`
+///
+/// # Safety
+/// `device` must be a valid handle from [`td_device_new`] and `buffer` must have `len` writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn td_device_read_memory(
+    device: *const TdDevice,
+    start: u16,
+    buffer: *mut u8,
+    len: usize,
+) -> i32 {
+    let device = match device.as_ref() {
+        Some(device) if !buffer.is_null() => device,
+        _ => return TD_ERR_NULL,
+    };
+    let start = start as usize;
+    if start.checked_add(len).is_none_or(|end| end > RAM_SIZE) {
+        return TD_ERR_OUT_OF_RANGE;
+    }
+    slice::from_raw_parts_mut(buffer, len).copy_from_slice(&device.device.mem[start..start + len]);
+    TD_OK
+}
+
+///Add `len` bytes to the keyboard buffer, for RCHR, RSTR and IPOLL
+///
+/// # Safety
+/// `device` must be a valid handle from [`td_device_new`] and `bytes` must have `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn td_device_push_input(
+    device: *mut TdDevice,
+    bytes: *const u8,
+    len: usize,
+) -> i32 {
+    match device.as_mut() {
+        Some(device) if !bytes.is_null() => {
+            device.device.push_bytes(slice::from_raw_parts(bytes, len));
+            TD_OK
+        }
+        _ => TD_ERR_NULL,
+    }
+}
+
+///Add `len` bytes to stdin (file 254), a `len` of 0 closes stdin
+///
+/// # Safety
+/// `device` must be a valid handle from [`td_device_new`] and `bytes` must have `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn td_device_push_stdin(
+    device: *mut TdDevice,
+    bytes: *const u8,
+    len: usize,
+) -> i32 {
+    match device.as_mut() {
+        Some(device) if len == 0 => {
+            device.device.close_stdin();
+            TD_OK
+        }
+        Some(device) if !bytes.is_null() => {
+            device
+                .device
+                .stdin_buffer
+                .extend_from_slice(slice::from_raw_parts(bytes, len));
+            TD_OK
+        }
+        _ => TD_ERR_NULL,
+    }
+}
+
+///Add `len` bytes to the serial port, for RECV
+///
+/// # Safety
+/// `device` must be a valid handle from [`td_device_new`] and `bytes` must have `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn td_device_push_serial(
+    device: *mut TdDevice,
+    bytes: *const u8,
+    len: usize,
+) -> i32 {
+    match device.as_mut() {
+        Some(device) if !bytes.is_null() => {
+            device
+                .device
+                .serial_in
+                .extend_from_slice(slice::from_raw_parts(bytes, len));
+            TD_OK
+        }
+        _ => TD_ERR_NULL,
+    }
+}
+
+///Move up to `len` bytes sent with SEND into `buffer`, returns the number of bytes copied
+///
+/// # Safety
+/// `device` must be a valid handle from [`td_device_new`] and `buffer` must have `len` writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn td_device_take_serial(
+    device: *mut TdDevice,
+    buffer: *mut u8,
+    len: usize,
+) -> isize {
+    match device.as_mut() {
+        Some(device) if !buffer.is_null() => take(&mut device.device.serial_out, buffer, len),
+        _ => TD_ERR_NULL as isize,
+    }
+}
+
+///Move up to `len` bytes of program output into `buffer`, returns the number of bytes copied
+///
+/// # Safety
+/// `device` must be a valid handle from [`td_device_new`] and `buffer` must have `len` writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn td_device_take_output(
+    device: *mut TdDevice,
+    buffer: *mut u8,
+    len: usize,
+) -> isize {
+    match device.as_mut() {
+        Some(device) if !buffer.is_null() => take(&mut device.output, buffer, len),
+        _ => TD_ERR_NULL as isize,
+    }
+}
+
+///Move up to `len` bytes of error messages into `buffer`, returns the number of bytes copied
+///
+/// # Safety
+/// `device` must be a valid handle from [`td_device_new`] and `buffer` must have `len` writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn td_device_take_errors(
+    device: *mut TdDevice,
+    buffer: *mut u8,
+    len: usize,
+) -> isize {
+    match device.as_mut() {
+        Some(device) if !buffer.is_null() => take(&mut device.errors, buffer, len),
+        _ => TD_ERR_NULL as isize,
+    }
+}
+
+unsafe fn take(source: &mut Vec<u8>, buffer: *mut u8, len: usize) -> isize {
+    let count = source.len().min(len);
+    slice::from_raw_parts_mut(buffer, count).copy_from_slice(&source[..count]);
+    source.drain(..count);
+    count as isize
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble_source;
+
+    #[test]
+    fn test_ffi() {
+        let tape = assemble_source("Test\n1\n.ops\nrchr d0\nprt d0\nhalt\n").unwrap();
+        unsafe {
+            let device = td_device_new(tape.bytes.as_ptr(), tape.bytes.len());
+            assert!(!device.is_null());
+
+            assert_eq!(td_device_step(device), TD_STEP_CHAR_INPUT);
+            assert_eq!(td_device_push_input(device, b"A".as_ptr(), 1), TD_OK);
+            assert_eq!(td_device_step(device), TD_STEP_PAUSE);
+            assert_eq!(td_device_step(device), TD_STEP_PAUSE);
+            assert_eq!(td_device_step(device), TD_STEP_HALT);
+
+            let mut registers = TdRegisters::default();
+            assert_eq!(td_device_registers(device, &mut registers), TD_OK);
+            assert_eq!(registers.data_reg, [65, 0, 0, 0]);
+            assert_eq!(registers.pc, 4);
+
+            let mut buffer = [0_u8; 8];
+            assert_eq!(td_device_take_output(device, buffer.as_mut_ptr(), 1), 1);
+            assert_eq!(td_device_take_output(device, buffer.as_mut_ptr(), 8), 1);
+            assert_eq!(td_device_take_output(device, buffer.as_mut_ptr(), 8), 0);
+            assert_eq!(&buffer[..1], b"5");
+
+            assert_eq!(
+                td_device_read_memory(device, 0, buffer.as_mut_ptr(), 8),
+                TD_OK
+            );
+            assert_eq!(
+                td_device_read_memory(device, u16::MAX, buffer.as_mut_ptr(), 8),
+                TD_ERR_OUT_OF_RANGE
+            );
+            assert_eq!(
+                td_device_read_memory(device, 1, buffer.as_mut_ptr(), usize::MAX),
+                TD_ERR_OUT_OF_RANGE
+            );
+
+            td_device_free(device);
+        }
+    }
+
+    #[test]
+    fn test_ffi_errors() {
+        unsafe {
+            assert!(td_device_new([1_u8, 2].as_ptr(), 2).is_null());
+            assert!(td_device_new(std::ptr::null(), 0).is_null());
+            assert_eq!(td_device_step(std::ptr::null_mut()), TD_ERR_NULL);
+            assert_eq!(
+                td_device_registers(std::ptr::null(), std::ptr::null_mut()),
+                TD_ERR_NULL
+            );
+        }
+    }
+}
@@ -1,8 +1,11 @@
+use crate::constants::get_byte_count;
 use crate::language::ops::OPS;
 use crate::language::parser::params::Param;
 use anyhow::{Error, Result};
 use lazy_static::lazy_static;
 use regex::Regex;
+use std::fmt;
+use std::fmt::{Display, Formatter};
 
 mod ops;
 pub mod parser;
@@ -10,16 +13,114 @@ pub mod parser;
 lazy_static! {
     //finds groups of non whitespace or chars
     //eg prtc @xAF 10 label 'a' ' '
-    static ref LINE_REGEX: Regex = Regex::new("'.'|(?:\\S)+").unwrap();
+    static ref LINE_REGEX: Regex = Regex::new("'\\\\.'|'.'|(?:\\S)+").unwrap();
+}
+
+///Error caused by a specific token in a line, so diagnostics can point at it
+///
+///Displays as just the message
+#[derive(Debug)]
+pub(crate) struct TokenError {
+    pub token: String,
+    ///Index of the token in the instruction, see [`split_line`]
+    pub index: Option<usize>,
+    ///Start and end (exclusive) columns of the token in the source line, starting at 1
+    pub columns: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl TokenError {
+    pub fn error(token: &str, message: String) -> Error {
+        Error::new(TokenError {
+            token: token.to_owned(),
+            index: None,
+            columns: None,
+            message,
+        })
+    }
+
+    ///Error for the token at `index` of an instruction
+    pub fn at(index: usize, token: &str, message: String) -> Error {
+        Error::new(TokenError {
+            token: token.to_owned(),
+            index: Some(index),
+            columns: None,
+            message,
+        })
+    }
+}
+
+impl Display for TokenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+///Split instruction into mnemonic and params, char literals such as `' '` are kept together
+pub fn split_line(input: &str) -> Vec<&str> {
+    split_line_spans(input)
+        .into_iter()
+        .map(|(_, token)| token)
+        .collect()
+}
+
+///Same as [`split_line`] but with the byte offset of each token in `input`
+pub fn split_line_spans(input: &str) -> Vec<(usize, &str)> {
+    LINE_REGEX
+        .find_iter(input)
+        .map(|cap| (cap.start(), cap.as_str()))
+        .collect()
+}
+
+///Description, forms (with opcode and size), flags and an example of the instruction `mnemonic` (case insensitive)
+pub fn op_help(mnemonic: &str) -> Option<String> {
+    let op = OPS.iter().find(|op| op.matches(mnemonic))?;
+    let doc = op.doc();
+    let forms: Vec<(String, u8)> = op
+        .forms()
+        .into_iter()
+        .map(|(params, opcode)| (format!("{} {}", op.mnemonic(), params), opcode))
+        .collect();
+    let width = forms
+        .iter()
+        .map(|(form, _)| form.trim_end().len())
+        .max()
+        .unwrap_or_default()
+        .max(4);
+    let mut output = format!(
+        "{} - {}\n\n{:<width$}  Opcode  Bytes",
+        op.mnemonic(),
+        doc.description,
+        "Form",
+        width = width
+    );
+    for (form, opcode) in forms {
+        output.push_str(&format!(
+            "\n{:<width$}  x{:02X}     {}",
+            form.trim_end(),
+            opcode,
+            get_byte_count(opcode),
+            width = width
+        ));
+    }
+    output.push_str(&format!(
+        "\n\nFlags: {}\nExample: {}",
+        if doc.flags.is_empty() {
+            "none"
+        } else {
+            doc.flags
+        },
+        doc.example
+    ));
+    Some(output)
 }
 
 ///This method converts a BASM instruction into usable parts for the assembler
 ///The line can not contain any comments or a label
 pub fn parse_line(input: &str) -> Result<(u8, Vec<Param>)> {
-    let parts = LINE_REGEX
-        .find_iter(input)
-        .map(|cap| cap.as_str())
-        .collect::<Vec<&str>>();
+    let parts = split_line(input);
 
     for op in OPS.iter() {
         if op.matches(parts[0]) {
@@ -29,42 +130,73 @@ pub fn parse_line(input: &str) -> Result<(u8, Vec<Param>)> {
                 op.parse(&[])
             };
             return match result {
-                None => Err(Error::msg(format!(
-                    "parsing line '{}'\n{}",
-                    input,
-                    op.error_text()
-                ))),
+                None => {
+                    let idx = op
+                        .invalid_param(&parts[1..])
+                        .map(|idx| idx + 1)
+                        .unwrap_or(0);
+                    Err(TokenError::at(
+                        idx,
+                        parts[idx],
+                        format!("parsing line '{}'\n{}", input, op.error_text()),
+                    ))
+                }
                 Some(params) => Ok(params),
             };
         }
     }
 
-    Err(Error::msg(format!(
-        "Unable to parse {}, instruction not recognised",
-        input
-    )))
+    Err(TokenError::at(
+        0,
+        parts[0],
+        format!("Unable to parse {}, instruction not recognised", input),
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::constants::code::*;
-    use crate::constants::hardware::{REG_A1, REG_ACC, REG_D3};
+    use crate::constants::hardware::Register;
 
     #[test]
     #[rustfmt::skip]
     fn basic_test() {
         assert_eq!(
             parse_line("cpy acc 5").unwrap(),
-            (CPY_REG_VAL, vec![Param::DataReg(REG_ACC), Param::Number(5)])
+            (CPY_REG_VAL, vec![Param::DataReg(Register::Acc), Param::Number(5)])
         );
         assert_eq!(
             parse_line("ld a1 test d3 xF1").unwrap(),
             (
                 LD_AREG_DATA_REG_VAL,
-                vec![Param::AddrReg(REG_A1), Param::DataKey(String::from("test")), Param::DataReg(REG_D3), Param::Number(241)]
+                vec![Param::AddrReg(Register::A1), Param::DataKey(String::from("test")), Param::DataReg(Register::D3), Param::Number(241)]
             )
         );
         assert_eq!(parse_line("halt").unwrap(), (HALT, vec![]));
     }
+
+    #[test]
+    fn test_op_help() {
+        assert_eq!(
+            op_help("inc").unwrap(),
+            "INC - Increment 1st param by 1\n\nForm                     Opcode  Bytes\nINC (data_reg|addr_reg)  x05     2\n\nFlags: Overflow\nExample: inc d0"
+        );
+        assert!(op_help("foo").is_none());
+    }
+
+    #[test]
+    fn test_token_errors() {
+        let token = |line: &str| {
+            let err = parse_line(line).unwrap_err();
+            let token = err.downcast_ref::<TokenError>().unwrap();
+            (token.index, token.token.clone())
+        };
+        assert_eq!(token("cpy d0 300"), (Some(2), String::from("300")));
+        assert_eq!(token("add d0"), (Some(0), String::from("add")));
+        assert_eq!(token("halt 1"), (Some(1), String::from("1")));
+        assert_eq!(token("foo d0"), (Some(0), String::from("foo")));
+        assert_eq!(split_line_spans("prtc  ' '"), vec![(0, "prtc"), (6, "' '")]);
+        assert_eq!(split_line("cpy d0 '\\''"), vec!["cpy", "d0", "'\\''"]);
+    }
 }
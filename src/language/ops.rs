@@ -3,155 +3,253 @@ use crate::language::parser::ops::Op;
 use lazy_static::lazy_static;
 
 //To add new operation the following files must be updated:
-//language/ops.rs
+//language/ops.rs (including the doc for help-op)
 //constants.rs
 //decompiler/mod.rs
 //device/internal.rs
 //language.md
 
 lazy_static! {
-    pub static ref OPS: [Op; 47] = [
+    pub static ref OPS: [Op; 77] = [
         //CPY reg reg, reg val, areg areg, areg label|addr, areg reg reg, reg reg areg, reg areg
-        //Copy value from 2nd param to 1st
-        Op::new_reg_complex("CPY", CPY_REG_REG, CPY_REG_VAL, CPY_AREG_AREG, CPY_AREG_ADDR, CPY_AREG_REG_REG, CPY_REG_REG_AREG, CPY_REG_AREG),
+        Op::new_reg_complex("CPY", CPY_REG_REG, CPY_REG_VAL, CPY_AREG_AREG, CPY_AREG_ADDR, CPY_AREG_REG_REG, CPY_REG_REG_AREG, CPY_REG_AREG)
+            .with_doc("Copy value from 2nd param to 1st", "", "cpy d0 10"),
+        //LDI addr_reg word
+        Op::new_areg_word("LDI", LDI_AREG_VAL)
+            .with_doc("Load a 16 bit value (0-65535 or x0-xFFFF) into 1st param", "", "ldi a0 1000"),
         //ADD reg reg|val|addr_reg
-        //Add 1st and 2nd params and store in ACC (addr_reg must point to data)
-        Op::new_reg_val("ADD", ADD_REG_REG, ADD_REG_VAL, ADD_REG_AREG),
+        Op::new_reg_val("ADD", ADD_REG_REG, ADD_REG_VAL, ADD_REG_AREG)
+            .with_doc("Add 1st and 2nd params and store in ACC (addr_reg must point to data)", "Overflow", "add d0 d1"),
         //ADD reg reg|val|addr_reg
-        //Subtract 2nd param from 1st and store in ACC (addr_reg must point to data)
-        Op::new_reg_val("SUB", SUB_REG_REG, SUB_REG_VAL, SUB_REG_AREG),
+        Op::new_reg_val("SUB", SUB_REG_REG, SUB_REG_VAL, SUB_REG_AREG)
+            .with_doc("Subtract 2nd param from 1st and store in ACC (addr_reg must point to data)", "Overflow", "sub d0 1"),
+        //ADDC reg reg|val|addr_reg
+        Op::new_reg_val("ADDC", ADDC_REG_REG, ADDC_REG_VAL, ADDC_REG_AREG)
+            .with_doc("Add 1st and 2nd params and 1 if overflow is set and store in ACC (addr_reg must point to data)", "Overflow", "addc d0 d2"),
+        //SUBC reg reg|val|addr_reg
+        Op::new_reg_val("SUBC", SUBC_REG_REG, SUBC_REG_VAL, SUBC_REG_AREG)
+            .with_doc("Subtract 2nd param and 1 if overflow is set from 1st and store in ACC (addr_reg must point to data)", "Overflow", "subc d0 d2"),
         //CMP reg reg, reg val, areg areg, areg label|addr, areg reg reg, reg reg areg, reg areg
-        //Compare values in 1st and 2nd params, store result in ACC (0 = Equal, 1 = Lesser, 2 = Greater)
-        Op::new_reg_complex("CMP", CMP_REG_REG, CMP_REG_VAL, CMP_AREG_AREG, CMP_AREG_ADDR, CMP_AREG_REG_REG, CMP_REG_REG_AREG, CMP_REG_AREG),
+        Op::new_reg_complex("CMP", CMP_REG_REG, CMP_REG_VAL, CMP_AREG_AREG, CMP_AREG_ADDR, CMP_AREG_REG_REG, CMP_REG_REG_AREG, CMP_REG_AREG)
+            .with_doc("Compare values in 1st and 2nd params, store result in flags (Zero = Equal, Carry = Lesser)", "Zero, Carry, Negative", "cmp d0 10"),
         //LD areg data_key (reg reg, reg val, val reg, val val)
-        //Load address of indexed data (params 2 to 4) into 1st param
-        Op::new_data("LD", LD_AREG_DATA_REG_REG, LD_AREG_DATA_REG_VAL, LD_AREG_DATA_VAL_REG, LD_AREG_DATA_VAL_VAL),
+        Op::new_data("LD", LD_AREG_DATA_REG_REG, LD_AREG_DATA_REG_VAL, LD_AREG_DATA_VAL_REG, LD_AREG_DATA_VAL_VAL)
+            .with_doc("Load address of indexed data (params 2 to 4) into 1st param", "", "ld a0 levels d0 0"),
         //JMP addr|lbl|addr_reg
-        //Jump to instruction at 1st param
-        Op::new_jmp("JMP", JMP_ADDR, JMP_AREG),
+        Op::new_jmp("JMP", JMP_ADDR, JMP_AREG)
+            .with_doc("Jump to instruction at 1st param", "", "jmp loop"),
         //JE addr|lbl|addr_reg
-        //Jump to instruction at 1st param if ACC == 0 (Equal)
-        Op::new_jmp("JE", JE_ADDR, JE_AREG),
+        Op::new_jmp("JE", JE_ADDR, JE_AREG)
+            .with_doc("Jump to instruction at 1st param if Zero is set (Equal)", "", "je done"),
         //JNE addr|lbl|addr_reg
-        //Jump to instruction at 1st param if ACC != 0 (Equal)
-        Op::new_jmp("JNE", JNE_ADDR, JNE_AREG),
+        Op::new_jmp("JNE", JNE_ADDR, JNE_AREG)
+            .with_doc("Jump to instruction at 1st param if Zero is not set (Not equal)", "", "jne loop"),
         //JG addr|lbl|addr_reg
-        //Jump to instruction at 1st param if ACC == 2 (Greater)
-        Op::new_jmp("JG", JG_ADDR, JG_AREG),
+        Op::new_jmp("JG", JG_ADDR, JG_AREG)
+            .with_doc("Jump to instruction at 1st param if Carry and Zero are not set (Greater)", "", "jg too_big"),
         //JL addr|lbl|addr_reg
-        //Jump to instruction at 1st param if ACC == 1 (Lesser)
-        Op::new_jmp("JL", JL_ADDR, JL_AREG),
+        Op::new_jmp("JL", JL_ADDR, JL_AREG)
+            .with_doc("Jump to instruction at 1st param if Carry is set (Lesser)", "", "jl too_small"),
         //OVER addr|lbl|addr_reg
-        //Jump to instruction at 1st param if overflow flag is set
-        Op::new_jmp("OVER", OVER_ADDR, OVER_AREG),
+        Op::new_jmp("OVER", OVER_ADDR, OVER_AREG)
+            .with_doc("Jump to instruction at 1st param if overflow flag is set", "", "over overflowed"),
         //NOVER addr|lbl|addr_reg
-        //Jump to instruction at 1st param if overflow flag is not set
-        Op::new_jmp("NOVER", NOVER_ADDR, NOVER_AREG),
+        Op::new_jmp("NOVER", NOVER_ADDR, NOVER_AREG)
+            .with_doc("Jump to instruction at 1st param if overflow flag is not set", "", "nover fits"),
         //HALT
-        //Stop program execution
-        Op::new_none("HALT", HALT),
-        //FOPEN reg|val
-        //Opens input/data file <num> or crashes, saves length to [D0][D1][D2][D3]
-        Op::new_regval("FOPEN", FOPEN_REG, FOPEN_VAL),
+        Op::new_none("HALT", HALT)
+            .with_doc("Stop program execution", "", "halt"),
+        //EXIT reg|val
+        Op::new_regval("EXIT", EXIT_REG, EXIT_VAL)
+            .with_doc("Terminates the program with <1st param> as the exit code", "", "exit 1"),
+        //INTH num addr
+        Op::new_val_addr("INTH", INTH_VAL_ADDR)
+            .with_doc("Call <2nd param> when interrupt <1st param> (0 timer, 1 input) is raised", "", "inth 0 on_timer"),
+        //IMASK num
+        Op::new_num("IMASK", IMASK_VAL)
+            .with_doc("Mask interrupts, bit 0 masks the timer and bit 1 masks input", "", "imask 1"),
+        //ITIMER num
+        Op::new_num("ITIMER", ITIMER_VAL)
+            .with_doc("Raise the timer interrupt every <num> milliseconds, 0 to stop", "", "itimer 100"),
+        //RETI
+        Op::new_none("RETI", RETI)
+            .with_doc("Return from an interrupt handler and restore the interrupt mask", "", "reti"),
+        //FOPEN reg|val [addr_reg|str_key]
+        Op::new_file_open(
+            "FOPEN",
+            FOPEN_REG,
+            FOPEN_VAL,
+            FOPEN_REG_AREG,
+            FOPEN_VAL_AREG,
+            FOPEN_REG_STR,
+            FOPEN_VAL_STR,
+        )
+        .with_doc("Opens input/data file <num> or crashes, saves length to [D0][D1][D2][D3]. If 2nd param is set then the file named by the string (or ACC bytes at addr_reg) is opened as <num>", "", "fopen 0 save_name"),
         //NOP
-        //Does nothing
-        Op::new_none("NOP", NOP),
+        Op::new_none("NOP", NOP)
+            .with_doc("Does nothing", "", "nop"),
         //RET
-        //Return from subroutine
-        Op::new_none("RET", RET),
+        Op::new_none("RET", RET)
+            .with_doc("Return from subroutine", "", "ret"),
         //FSEEK reg|val
-        //Move file <num> cursor to [D0][D1][D2][D3]
-        Op::new_regval("FSEEK", FSEEK_REG, FSEEK_VAL),
+        Op::new_regval("FSEEK", FSEEK_REG, FSEEK_VAL)
+            .with_doc("Move file <num> cursor to [D0][D1][D2][D3]", "", "fseek 0"),
+        //FTRUNC reg|val
+        Op::new_regval("FTRUNC", FTRUNC_REG, FTRUNC_VAL)
+            .with_doc("Cuts off file <num> at the cursor", "", "ftrunc 0"),
+        //FDEL reg|val
+        Op::new_regval("FDEL", FDEL_REG, FDEL_VAL)
+            .with_doc("Closes and deletes file <num>", "", "fdel 0"),
+        //FLIST addr_reg|addr
+        Op::new_mem("FLIST", FLIST_ADDR, FLIST_AREG)
+            .with_doc("Writes input file names to memory, [count][len][name][len][name]...", "", "flist a0"),
         //SWP reg reg, areg areg
-        //Swaps contents of 1st param and 2nd param
-        Op::new_either_reg_reg("SWP", SWP_REG_REG, SWP_AREG_AREG),
+        Op::new_either_reg_reg("SWP", SWP_REG_REG, SWP_AREG_AREG)
+            .with_doc("Swaps contents of 1st param and 2nd param", "", "swp d0 d1"),
         //INC reg|addr_reg
-        //Increment 1st param by 1
-        Op::new_single_reg("INC", INC_REG),
+        Op::new_single_reg("INC", INC_REG)
+            .with_doc("Increment 1st param by 1", "Overflow", "inc d0"),
         //DEC reg|addr_reg
-        //Decrement 1st param by 1
-        Op::new_single_reg("DEC", DEC_REG),
-        //MEMR addr|addr_reg
-        //Read byte at 1st param in memory and store in ACC
-        Op::new_mem("MEMR", MEMR_ADDR, MEMR_AREG),
-        //MEMW addr|addr_reg
-        //Write byte in ACC and write to byte at 1st param in memory
-        Op::new_mem("MEMW", MEMW_ADDR, MEMW_AREG),
-        //FILER reg|val addr|addr_reg
-        //Read ACC bytes from file <num> cursor and write to 1st param in memory, sets read byte count in ACC
-        Op::new_file_mem("FILER", FILER_REG_ADDR, FILER_REG_AREG, FILER_VAL_ADDR, FILER_VAL_AREG),
-        //FILEW reg|val addr|addr_reg
-        //Write ACC bytes starting at 1st param in memory to file <num> cursor, sets written byte count in ACC
-        Op::new_file_mem_value("FILEW", FILEW_REG_ADDR, FILEW_REG_AREG, FILEW_VAL_ADDR, FILEW_VAL_AREG, FILEW_REG_REG, FILEW_REG_VAL, FILEW_VAL_REG, FILEW_VAL_VAL),
+        Op::new_single_reg("DEC", DEC_REG)
+            .with_doc("Decrement 1st param by 1", "Overflow", "dec a0"),
+        //MEMR addr|addr_reg|addr_reg+data_reg
+        Op::new_mem_indexed("MEMR", MEMR_ADDR, MEMR_AREG, MEMR_AREG_REG)
+            .with_doc("Read byte at 1st param in memory and store in ACC", "", "memr a0+d1"),
+        //MEMW addr|addr_reg|addr_reg+data_reg
+        Op::new_mem_indexed("MEMW", MEMW_ADDR, MEMW_AREG, MEMW_AREG_REG)
+            .with_doc("Write byte in ACC and write to byte at 1st param in memory", "", "memw @x100"),
+        //MEMCPY addr_reg addr_reg data_reg
+        Op::new_areg_areg_reg("MEMCPY", MEMCPY_AREG_AREG_REG)
+            .with_doc("Copy 3rd param bytes from 2nd param in memory to 1st param, the ranges can overlap", "", "memcpy a0 a1 d0"),
+        //MEMSET addr_reg data_reg|num data_reg
+        Op::new_areg_regval_reg("MEMSET", MEMSET_AREG_REG_REG, MEMSET_AREG_VAL_REG)
+            .with_doc("Set 3rd param bytes from 1st param in memory to 2nd param", "", "memset a0 0 d1"),
+        //FILER reg|val addr|addr_reg [addr_reg]
+        Op::new_file_mem("FILER", FILER_REG_ADDR, FILER_REG_AREG, FILER_VAL_ADDR, FILER_VAL_AREG, FILER_REG_AREG_AREG, FILER_VAL_AREG_AREG)
+            .with_doc("Read ACC bytes from file <num> cursor and write to 1st param in memory, sets read byte count in ACC. If 3rd param is set it's the number of bytes and the count is set in [D2][D3]", "", "filer 0 a0"),
+        //FILEW reg|val addr|addr_reg|reg|val [addr_reg]
+        Op::new_file_mem_value("FILEW", FILEW_REG_ADDR, FILEW_REG_AREG, FILEW_VAL_ADDR, FILEW_VAL_AREG, FILEW_REG_REG, FILEW_REG_VAL, FILEW_VAL_REG, FILEW_VAL_VAL, FILEW_REG_AREG_AREG, FILEW_VAL_AREG_AREG)
+            .with_doc("Write ACC bytes starting at 1st param in memory to file <num> cursor, sets written byte count in ACC. If 3rd param is set it's the number of bytes and the count is set in [D2][D3]", "", "filew 0 a0"),
         //PRT reg|val|addr_reg
-        //Prints value in 1st param (addr_reg must point to data)
-        Op::new_regvaldata("PRT", PRT_REG, PRT_VAL, PRT_AREG),
+        Op::new_regvaldata("PRT", PRT_REG, PRT_VAL, PRT_AREG)
+            .with_doc("Prints value in 1st param (addr_reg must point to data)", "", "prt d0"),
         //PRTLN
-        //Prints new line
-        Op::new_none("PRTLN", PRTLN),
+        Op::new_none("PRTLN", PRTLN)
+            .with_doc("Prints new line", "", "prtln"),
         //PRTC reg|val|addr_reg
-        //Prints value in 1st param as ASCII (addr_reg must point to data)
-        Op::new_regvaldata("PRTC", PRTC_REG, PRTC_VAL, PRTC_AREG),
+        Op::new_regvaldata("PRTC", PRTC_REG, PRTC_VAL, PRTC_AREG)
+            .with_doc("Prints value in 1st param as ASCII (addr_reg must point to data)", "", "prtc 'a'"),
+        //PRTX reg|val|addr_reg
+        Op::new_regvaldata("PRTX", PRTX_REG, PRTX_VAL, PRTX_AREG)
+            .with_doc("Prints value in 1st param as two digit hex (addr_reg must point to data)", "", "prtx acc"),
         //FSKIP reg|val reg|val
-        //Move file <num> cursor forward by number of bytes set by 1st param
-        Op::new_regval_regval("FSKIP", FSKIP_REG_REG, FSKIP_REG_VAL, FSKIP_VAL_REG, FSKIP_VAL_VAL),
-        //PRTS key
-        //Prints string named by 1st param
-        Op::new_string("PRTS", PRTS_STR),
+        Op::new_regval_regval("FSKIP", FSKIP_REG_REG, FSKIP_REG_VAL, FSKIP_VAL_REG, FSKIP_VAL_VAL)
+            .with_doc("Move file <num> cursor forward by number of bytes set by 1st param", "", "fskip 0 10"),
+        //PRTS key|addr_reg
+        Op::new_string("PRTS", PRTS_STR, PRTS_AREG)
+            .with_doc("Prints string named by 1st param (addr_reg must point to a string)", "", "prts greeting"),
+        //STRLD addr_reg string|addr_reg, STRLD addr_reg data_key data_reg|num
+        Op::new_string_load("STRLD", STRLD_AREG_STR, STRLD_AREG_AREG, STRLD_AREG_DATA_VAL, STRLD_AREG_DATA_REG)
+            .with_doc("Copy string (2nd param) or data array (3rd param of 2nd param) into memory at 1st param and set ACC to the length", "", "strld a0 greeting"),
+        //ITOA addr_reg data_reg|addr_reg|num
+        Op::new_areg_regval("ITOA", ITOA_AREG_REG, ITOA_AREG_VAL)
+            .with_doc("Write 2nd param as decimal digits into memory at 1st param and set ACC to the number of digits", "", "itoa a0 d0"),
+        //ATOI addr_reg data_reg|addr_reg
+        Op::new_areg_reg("ATOI", ATOI_AREG_REG)
+            .with_doc("Parse up to ACC decimal digits from memory at 1st param into 2nd param and set ACC to the number of digits read", "Overflow", "atoi a0 d0"),
         //PRTD addr_reg
-        //Prints ACC bytes from data starting at by 1st param
-        Op::new_areg("PRTD", PRTD_AREG),
+        Op::new_areg("PRTD", PRTD_AREG)
+            .with_doc("Prints ACC bytes from data starting at by 1st param", "", "prtd a0"),
         //CALL addr|lbl|addr_reg
-        //Jump to 1st param, setup stack to allow RET
-        Op::new_jmp("CALL", CALL_ADDR, CALL_AREG),
+        Op::new_jmp("CALL", CALL_ADDR, CALL_AREG)
+            .with_doc("Jump to 1st param, setup stack to allow RET", "", "call print_score"),
         //PUSH addr_reg|reg|val
-        //Push 1st param in to stack
-        Op::new_addrregval("PUSH", PUSH_REG, PUSH_VAL),
-        //POP addr_reg|reg
-        //Pop value from stack to 1st param
-        Op::new_single_reg("POP", POP_REG),
+        Op::new_addrregval("PUSH", PUSH_REG, PUSH_VAL)
+            .with_doc("Push 1st param in to stack", "", "push d0"),
+        //POP addr_reg|reg|val
+        Op::new_addrregval("POP", POP_REG, POP_VAL)
+            .with_doc("Pop value from stack to 1st param, or discard 1st param bytes", "", "pop d0"),
         //ARG addr_reg|reg reg|val
-        //Read from value from stack 2nd param bytes before the FP and save to 1st param
-        Op::new_addrreg_regval("ARG", ARG_REG_REG, ARG_REG_VAL),
+        Op::new_addrreg_regval("ARG", ARG_REG_REG, ARG_REG_VAL)
+            .with_doc("Read from value from stack 2nd param bytes before the FP and save to 1st param", "", "arg d0 1"),
+        //LOCAL val
+        Op::new_num("LOCAL", LOCAL_VAL)
+            .with_doc("Reserve 1st param bytes on the stack for locals, they're discarded by RET", "", "local 2"),
+        //LVAR addr_reg|reg val, val addr_reg|reg
+        Op::new_addrreg_val_either("LVAR", LVAR_REG_VAL, LVAR_VAL_REG)
+            .with_doc("Read local at val into reg, or write reg into local at val", "", "lvar d0 0"),
         //IPOLL addr_reg|addr
-        //Jump to 1st param if at least one char can be read from keyboard
-        Op::new_jmp("IPOLL", IPOLL_ADDR, IPOLL_AREG),
+        Op::new_jmp("IPOLL", IPOLL_ADDR, IPOLL_AREG)
+            .with_doc("Jump to 1st param if at least one char can be read from keyboard", "", "ipoll key_pressed"),
         //FCHK reg|val addr_reg|addr
-        //Jump to 1st param if input file <num> is available
-        Op::new_regval_jmp("FCHK", FCHK_REG_ADDR, FCHK_REG_AREG, FCHK_VAL_ADDR, FCHK_VAL_AREG),
+        Op::new_regval_jmp("FCHK", FCHK_REG_ADDR, FCHK_REG_AREG, FCHK_VAL_ADDR, FCHK_VAL_AREG)
+            .with_doc("Jump to 1st param if input file <num> is available", "", "fchk 0 has_save"),
         //MEMP addr_reg|addr
-        //Print ACC chars from 1st param in memory or data
-        Op::new_mem("MEMP", MEMP_ADDR, MEMP_AREG),
+        Op::new_mem("MEMP", MEMP_ADDR, MEMP_AREG)
+            .with_doc("Print ACC chars from 1st param in memory or data", "", "memp a0"),
         //RSTR addr_reg|addr
-        //Read up to chars keyboard (until return is pressed or 255 entered) starting at 1st param in memory
-        Op::new_mem("RSTR", RSTR_ADDR, RSTR_AREG),
+        Op::new_mem("RSTR", RSTR_ADDR, RSTR_AREG)
+            .with_doc("Read up to chars keyboard (until return is pressed or 255 entered) starting at 1st param in memory", "", "rstr a0"),
+        //ARGS addr_reg|addr
+        Op::new_mem("ARGS", ARGS_ADDR, ARGS_AREG)
+            .with_doc("Write the program arguments starting at 1st param in memory, each as its length then its chars, and set ACC to the number of arguments", "", "args a0"),
+        //SAVE str_key addr_reg
+        Op::new_string_areg("SAVE", SAVE_STR_AREG)
+            .with_doc("Store ACC bytes from memory starting at 2nd param in the save file with 1st param as the key", "", "save high_score a0"),
+        //LOADKV str_key addr_reg
+        Op::new_string_areg("LOADKV", LOADKV_STR_AREG)
+            .with_doc("Copy the value saved with 1st param as the key into memory starting at 2nd param and set ACC to its length, ACC is 0 if the key hasn't been saved", "", "loadkv high_score a0"),
         //RCHR reg
-        //Read one char from keyboard into 1st param
-        Op::new_single_reg("RCHR", RCHR_REG),
+        Op::new_single_reg("RCHR", RCHR_REG)
+            .with_doc("Read one char from keyboard into 1st param", "", "rchr d0"),
+        //SEND reg|val
+        Op::new_regval("SEND", SEND_REG, SEND_VAL)
+            .with_doc("Write 1st param to the serial port", "", "send d0"),
+        //RECV reg
+        Op::new_single_reg("RECV", RECV_REG)
+            .with_doc("Read one byte from the serial port into 1st param, waits until a byte is received", "", "recv d0"),
         //RAND reg
-        //Generate a pseudorandom number and put in 1st param
-        Op::new_single_reg("RAND", RAND_REG),
+        Op::new_single_reg("RAND", RAND_REG)
+            .with_doc("Generate a pseudorandom number and put in 1st param", "", "rand d0"),
         //SEED reg
-        //Set the seed for the rng
-        Op::new_single_reg("SEED", SEED_REG),
+        Op::new_single_reg("SEED", SEED_REG)
+            .with_doc("Set the seed for the rng", "", "seed d0"),
         //TIME
-        //Populates D0 with seconds, D1 with minutes, D2 with hours
-        Op::new_none("TIME", TIME),
+        Op::new_none("TIME", TIME)
+            .with_doc("Populates D0 with seconds, D1 with minutes, D2 with hours", "", "time"),
+        //CLS
+        Op::new_none("CLS", CLS)
+            .with_doc("Clear the screen and move the cursor to the top left", "", "cls"),
+        //CURS reg|val reg|val
+        Op::new_regval_regval("CURS", CURS_REG_REG, CURS_REG_VAL, CURS_VAL_REG, CURS_VAL_VAL)
+            .with_doc("Move the cursor to column <1st param>, row <2nd param>", "", "curs 0 10"),
+        //COLOR reg|val
+        Op::new_regval("COLOR", COLOR_REG, COLOR_VAL)
+            .with_doc("Set the text color for following output, 0-15 or 16+ to reset", "", "color 2"),
+        //VSYNC
+        Op::new_none("VSYNC", VSYNC)
+            .with_doc("Draw the framebuffer and wait for the next frame when running with --display", "", "vsync"),
+        //MILLIS
+        Op::new_none("MILLIS", MILLIS)
+            .with_doc("Populates [D0][D1][D2][D3] with milliseconds since the program started", "", "millis"),
+        //SLEEP reg|val
+        Op::new_regval("SLEEP", SLEEP_REG, SLEEP_VAL)
+            .with_doc("Pause for <num> milliseconds", "", "sleep 100"),
         //AND reg reg|val|addr_reg
-        //and bits of 1st and 2nd params and store in ACC (addr_reg must point to data)
-        Op::new_reg_val("AND", AND_REG_REG, AND_REG_VAL, AND_REG_AREG),
+        Op::new_reg_val("AND", AND_REG_REG, AND_REG_VAL, AND_REG_AREG)
+            .with_doc("And bits of 1st and 2nd params and store in ACC (addr_reg must point to data)", "", "and d0 x0F"),
         //OR reg reg|val|addr_reg
-        //or bits of 1st and 2nd params and store in ACC (addr_reg must point to data)
-        Op::new_reg_val("OR", OR_REG_REG, OR_REG_VAL, OR_REG_AREG),
+        Op::new_reg_val("OR", OR_REG_REG, OR_REG_VAL, OR_REG_AREG)
+            .with_doc("Or bits of 1st and 2nd params and store in ACC (addr_reg must point to data)", "", "or d0 x80"),
         //XOR reg reg|val|addr_reg
-        //xor bits of 1st and 2nd params and store in ACC (addr_reg must point to data)
-        Op::new_reg_val("XOR", XOR_REG_REG, XOR_REG_VAL, XOR_REG_AREG),
+        Op::new_reg_val("XOR", XOR_REG_REG, XOR_REG_VAL, XOR_REG_AREG)
+            .with_doc("Xor bits of 1st and 2nd params and store in ACC (addr_reg must point to data)", "", "xor d0 d1"),
         //NOT reg|addr_reg
-        //not bits of 1st param (addr_reg must point to data)
-        Op::new_single_reg("NOT", NOT_REG),
+        Op::new_single_reg("NOT", NOT_REG)
+            .with_doc("Not bits of 1st param (addr_reg must point to data)", "", "not d0"),
         //DEBUG
-        //Prints dump from system
-        Op::new_none("DEBUG", DEBUG),
+        Op::new_none("DEBUG", DEBUG)
+            .with_doc("Prints dump from system", "", "debug"),
     ];
 }
 
@@ -163,4 +261,19 @@ mod test {
     fn check_for_missing_ops() {
         assert_eq!(MNEMONICS.len(), OPS.len())
     }
+
+    #[test]
+    fn check_docs() {
+        for op in OPS.iter() {
+            let doc = op.doc();
+            assert!(!doc.description.is_empty(), "{}", op.mnemonic());
+            let (opcode, _) = crate::language::parse_line(doc.example)
+                .unwrap_or_else(|err| panic!("{}: {}", op.mnemonic(), err));
+            assert!(
+                op.forms().iter().any(|(_, form)| *form == opcode),
+                "{}",
+                op.mnemonic()
+            );
+        }
+    }
 }
@@ -6,6 +6,16 @@ use std::fmt::{Display, Formatter};
 pub struct Op {
     mnemonic: &'static str,
     variants: Vec<OpVariant>,
+    doc: OpDoc,
+}
+
+///What an instruction does, shown by `help-op`, editor hovers and parse errors
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OpDoc {
+    pub description: &'static str,
+    ///Flags changed by the instruction, empty if none
+    pub flags: &'static str,
+    pub example: &'static str,
 }
 
 impl Op {
@@ -18,9 +28,64 @@ impl Op {
         for variant in self.variants.iter() {
             output.push_str(&format!("\n{} {}", self.mnemonic, variant))
         }
+        if !self.doc.example.is_empty() {
+            output.push_str(&format!("\ne.g. {}", self.doc.example));
+        }
         output
     }
 
+    pub fn with_doc(
+        mut self,
+        description: &'static str,
+        flags: &'static str,
+        example: &'static str,
+    ) -> Self {
+        self.doc = OpDoc {
+            description,
+            flags,
+            example,
+        };
+        self
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        self.mnemonic
+    }
+
+    pub fn doc(&self) -> OpDoc {
+        self.doc
+    }
+
+    ///Params (as text) and opcode of each form of the instruction
+    pub fn forms(&self) -> Vec<(String, u8)> {
+        self.variants
+            .iter()
+            .map(|variant| (variant.to_string(), variant.opcode))
+            .collect()
+    }
+
+    ///Index of the first param after the longest run any variant accepts, None if only the number of params is wrong
+    pub fn invalid_param(&self, parts: &[&str]) -> Option<usize> {
+        let accepted = self
+            .variants
+            .iter()
+            .map(|variant| {
+                variant
+                    .params
+                    .iter()
+                    .zip(parts)
+                    .take_while(|(param, part)| param.parse(part).is_ok())
+                    .count()
+            })
+            .max()
+            .unwrap_or_default();
+        if accepted < parts.len() {
+            Some(accepted)
+        } else {
+            None
+        }
+    }
+
     pub fn parse(&self, parts: &[&str]) -> Option<(u8, Vec<Param>)> {
         for variant in self.variants.iter() {
             if let Ok(params) = variant.parse(parts) {
@@ -35,20 +100,37 @@ impl Op {
     pub fn new_none(mnemonic: &'static str, opcode: u8) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![OpVariant::new(opcode, vec![])],
         }
     }
 
-    pub fn new_string(mnemonic: &'static str, opcode: u8) -> Self {
+    pub fn new_string(mnemonic: &'static str, opcode_str: u8, opcode_areg: u8) -> Self {
+        Op {
+            mnemonic,
+            doc: OpDoc::default(),
+            variants: vec![
+                OpVariant::new(opcode_areg, vec![Parameters::ADDR_REG]),
+                OpVariant::new(opcode_str, vec![Parameters::STRING_KEY]),
+            ],
+        }
+    }
+
+    pub fn new_string_areg(mnemonic: &'static str, opcode: u8) -> Self {
         Op {
             mnemonic,
-            variants: vec![OpVariant::new(opcode, vec![Parameters::STRING_KEY])],
+            doc: OpDoc::default(),
+            variants: vec![OpVariant::new(
+                opcode,
+                vec![Parameters::STRING_KEY, Parameters::ADDR_REG],
+            )],
         }
     }
 
     pub fn new_areg(mnemonic: &'static str, opcode: u8) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![OpVariant::new(opcode, vec![Parameters::ADDR_REG])],
         }
     }
@@ -61,6 +143,7 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![
                 OpVariant::new(opcode_reg, vec![Parameters::DATA_REG]),
                 OpVariant::new(opcode_val, vec![Parameters::NUMBER]),
@@ -72,9 +155,45 @@ impl Op {
     pub fn new_regval(mnemonic: &'static str, opcode_reg: u8, opcode_val: u8) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
+            variants: vec![
+                OpVariant::new(opcode_reg, vec![Parameters::DATA_REG]),
+                OpVariant::new(opcode_val, vec![Parameters::NUMBER]),
+            ],
+        }
+    }
+
+    pub fn new_file_open(
+        mnemonic: &'static str,
+        opcode_reg: u8,
+        opcode_val: u8,
+        opcode_reg_areg: u8,
+        opcode_val_areg: u8,
+        opcode_reg_str: u8,
+        opcode_val_str: u8,
+    ) -> Self {
+        Op {
+            mnemonic,
+            doc: OpDoc::default(),
             variants: vec![
                 OpVariant::new(opcode_reg, vec![Parameters::DATA_REG]),
                 OpVariant::new(opcode_val, vec![Parameters::NUMBER]),
+                OpVariant::new(
+                    opcode_reg_areg,
+                    vec![Parameters::DATA_REG, Parameters::ADDR_REG],
+                ),
+                OpVariant::new(
+                    opcode_val_areg,
+                    vec![Parameters::NUMBER, Parameters::ADDR_REG],
+                ),
+                OpVariant::new(
+                    opcode_reg_str,
+                    vec![Parameters::DATA_REG, Parameters::STRING_KEY],
+                ),
+                OpVariant::new(
+                    opcode_val_str,
+                    vec![Parameters::NUMBER, Parameters::STRING_KEY],
+                ),
             ],
         }
     }
@@ -82,6 +201,7 @@ impl Op {
     pub fn new_addrregval(mnemonic: &'static str, opcode_reg: u8, opcode_val: u8) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![
                 OpVariant::new(opcode_reg, vec![Parameters::REGISTERS]),
                 OpVariant::new(opcode_val, vec![Parameters::NUMBER]),
@@ -96,6 +216,7 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![
                 OpVariant::new(
                     opcode_regreg,
@@ -109,9 +230,154 @@ impl Op {
         }
     }
 
+    pub fn new_num(mnemonic: &'static str, opcode: u8) -> Self {
+        Op {
+            mnemonic,
+            doc: OpDoc::default(),
+            variants: vec![OpVariant::new(opcode, vec![Parameters::NUMBER])],
+        }
+    }
+
+    pub fn new_addrreg_val_either(
+        mnemonic: &'static str,
+        opcode_reg_val: u8,
+        opcode_val_reg: u8,
+    ) -> Self {
+        Op {
+            mnemonic,
+            doc: OpDoc::default(),
+            variants: vec![
+                OpVariant::new(
+                    opcode_reg_val,
+                    vec![Parameters::REGISTERS, Parameters::NUMBER],
+                ),
+                OpVariant::new(
+                    opcode_val_reg,
+                    vec![Parameters::NUMBER, Parameters::REGISTERS],
+                ),
+            ],
+        }
+    }
+
+    pub fn new_areg_areg_reg(mnemonic: &'static str, opcode: u8) -> Self {
+        Op {
+            mnemonic,
+            doc: OpDoc::default(),
+            variants: vec![OpVariant::new(
+                opcode,
+                vec![
+                    Parameters::ADDR_REG,
+                    Parameters::ADDR_REG,
+                    Parameters::DATA_REG,
+                ],
+            )],
+        }
+    }
+
+    pub fn new_areg_regval_reg(mnemonic: &'static str, opcode_reg: u8, opcode_val: u8) -> Self {
+        Op {
+            mnemonic,
+            doc: OpDoc::default(),
+            variants: vec![
+                OpVariant::new(
+                    opcode_reg,
+                    vec![
+                        Parameters::ADDR_REG,
+                        Parameters::DATA_REG,
+                        Parameters::DATA_REG,
+                    ],
+                ),
+                OpVariant::new(
+                    opcode_val,
+                    vec![
+                        Parameters::ADDR_REG,
+                        Parameters::NUMBER,
+                        Parameters::DATA_REG,
+                    ],
+                ),
+            ],
+        }
+    }
+
+    pub fn new_areg_regval(mnemonic: &'static str, opcode_reg: u8, opcode_val: u8) -> Self {
+        Op {
+            mnemonic,
+            doc: OpDoc::default(),
+            variants: vec![
+                OpVariant::new(
+                    opcode_reg,
+                    vec![Parameters::ADDR_REG, Parameters::REGISTERS],
+                ),
+                OpVariant::new(opcode_val, vec![Parameters::ADDR_REG, Parameters::NUMBER]),
+            ],
+        }
+    }
+
+    pub fn new_areg_reg(mnemonic: &'static str, opcode: u8) -> Self {
+        Op {
+            mnemonic,
+            doc: OpDoc::default(),
+            variants: vec![OpVariant::new(
+                opcode,
+                vec![Parameters::ADDR_REG, Parameters::REGISTERS],
+            )],
+        }
+    }
+
+    pub fn new_string_load(
+        mnemonic: &'static str,
+        opcode_str: u8,
+        opcode_areg: u8,
+        opcode_data_val: u8,
+        opcode_data_reg: u8,
+    ) -> Self {
+        Op {
+            mnemonic,
+            doc: OpDoc::default(),
+            variants: vec![
+                OpVariant::new(
+                    opcode_areg,
+                    vec![Parameters::ADDR_REG, Parameters::ADDR_REG],
+                ),
+                OpVariant::new(
+                    opcode_str,
+                    vec![Parameters::ADDR_REG, Parameters::STRING_KEY],
+                ),
+                OpVariant::new(
+                    opcode_data_reg,
+                    vec![
+                        Parameters::ADDR_REG,
+                        Parameters::DATA_KEY,
+                        Parameters::DATA_REG,
+                    ],
+                ),
+                OpVariant::new(
+                    opcode_data_val,
+                    vec![
+                        Parameters::ADDR_REG,
+                        Parameters::DATA_KEY,
+                        Parameters::NUMBER,
+                    ],
+                ),
+            ],
+        }
+    }
+
+    pub fn new_areg_word(mnemonic: &'static str, opcode: u8) -> Self {
+        Op {
+            mnemonic,
+            doc: OpDoc::default(),
+            variants: vec![OpVariant::new(
+                opcode,
+                vec![Parameters::ADDR_REG, Parameters::WORD],
+            )],
+        }
+    }
+
     pub fn new_single_reg(mnemonic: &'static str, opcode: u8) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![OpVariant::new(opcode, vec![Parameters::REGISTERS])],
         }
     }
@@ -122,9 +388,12 @@ impl Op {
         opcode_reg_addr_reg: u8,
         opcode_val_addr: u8,
         opcode_val_addr_reg: u8,
+        opcode_reg_block: u8,
+        opcode_val_block: u8,
     ) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![
                 OpVariant::new(
                     opcode_reg_addr,
@@ -142,6 +411,22 @@ impl Op {
                     opcode_val_addr_reg,
                     vec![Parameters::NUMBER, Parameters::ADDR_REG],
                 ),
+                OpVariant::new(
+                    opcode_reg_block,
+                    vec![
+                        Parameters::DATA_REG,
+                        Parameters::ADDR_REG,
+                        Parameters::ADDR_REG,
+                    ],
+                ),
+                OpVariant::new(
+                    opcode_val_block,
+                    vec![
+                        Parameters::NUMBER,
+                        Parameters::ADDR_REG,
+                        Parameters::ADDR_REG,
+                    ],
+                ),
             ],
         }
     }
@@ -157,9 +442,12 @@ impl Op {
         opcode_reg_val: u8,
         opcode_val_reg: u8,
         opcode_val_val: u8,
+        opcode_reg_block: u8,
+        opcode_val_block: u8,
     ) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![
                 OpVariant::new(
                     opcode_reg_addr,
@@ -190,6 +478,22 @@ impl Op {
                     vec![Parameters::NUMBER, Parameters::DATA_REG],
                 ),
                 OpVariant::new(opcode_val_val, vec![Parameters::NUMBER, Parameters::NUMBER]),
+                OpVariant::new(
+                    opcode_reg_block,
+                    vec![
+                        Parameters::DATA_REG,
+                        Parameters::ADDR_REG,
+                        Parameters::ADDR_REG,
+                    ],
+                ),
+                OpVariant::new(
+                    opcode_val_block,
+                    vec![
+                        Parameters::NUMBER,
+                        Parameters::ADDR_REG,
+                        Parameters::ADDR_REG,
+                    ],
+                ),
             ],
         }
     }
@@ -197,6 +501,7 @@ impl Op {
     pub fn new_mem(mnemonic: &'static str, opcode_addr: u8, opcode_addr_reg: u8) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![
                 OpVariant::new(opcode_addr, vec![Parameters::ADDRESS]),
                 OpVariant::new(opcode_addr_reg, vec![Parameters::ADDR_REG]),
@@ -204,6 +509,23 @@ impl Op {
         }
     }
 
+    pub fn new_mem_indexed(
+        mnemonic: &'static str,
+        opcode_addr: u8,
+        opcode_addr_reg: u8,
+        opcode_indexed: u8,
+    ) -> Self {
+        Op {
+            mnemonic,
+            doc: OpDoc::default(),
+            variants: vec![
+                OpVariant::new(opcode_addr, vec![Parameters::ADDRESS]),
+                OpVariant::new(opcode_addr_reg, vec![Parameters::ADDR_REG]),
+                OpVariant::new(opcode_indexed, vec![Parameters::INDEXED]),
+            ],
+        }
+    }
+
     pub fn new_regval_regval(
         mnemonic: &'static str,
         opcode_reg_reg: u8,
@@ -213,6 +535,7 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![
                 OpVariant::new(
                     opcode_reg_reg,
@@ -239,6 +562,7 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![
                 OpVariant::new(
                     opcode_reg_reg,
@@ -263,6 +587,7 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![
                 OpVariant::new(
                     opcode_reg_reg,
@@ -289,6 +614,7 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![
                 OpVariant::new(
                     opcode_dreg_dreg,
@@ -339,6 +665,7 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![
                 OpVariant::new(
                     opcode_areg_data_reg_reg,
@@ -380,9 +707,21 @@ impl Op {
         }
     }
 
+    pub fn new_val_addr(mnemonic: &'static str, opcode: u8) -> Self {
+        Op {
+            mnemonic,
+            doc: OpDoc::default(),
+            variants: vec![OpVariant::new(
+                opcode,
+                vec![Parameters::NUMBER, Parameters::ADDRESSES],
+            )],
+        }
+    }
+
     pub fn new_jmp(mnemonic: &'static str, opcode_addr: u8, opcode_addr_reg: u8) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![
                 OpVariant::new(opcode_addr_reg, vec![Parameters::ADDR_REG]),
                 OpVariant::new(opcode_addr, vec![Parameters::ADDRESSES]),
@@ -399,6 +738,7 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            doc: OpDoc::default(),
             variants: vec![
                 OpVariant::new(
                     opcode_reg_addr_reg,
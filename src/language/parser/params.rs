@@ -1,14 +1,18 @@
-use crate::constants::hardware::*;
+use crate::constants::hardware::Register;
 use anyhow::{Error, Result};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::fmt::{Display, Formatter};
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Param {
     Number(u8),
-    DataReg(u8),
-    AddrReg(u8),
+    ///16 bit value, only used by LDI
+    Word(u16),
+    ///Address register plus data register offset, written `a0+d1`
+    Indexed(Register, Register),
+    DataReg(Register),
+    AddrReg(Register),
     Addr(u16),
     Label(String),
     StrKey(String),
@@ -24,6 +28,8 @@ bitflags! {
         const LABEL =     0b00010000;
         const STRING_KEY =0b00100000;
         const DATA_KEY =  0b01000000;
+        const WORD =      0b10000000;
+        const INDEXED =   0b100000000;
         const ADDRESSES = Self::LABEL.bits | Self::ADDRESS.bits;
         const REGISTERS = Self::DATA_REG.bits | Self::ADDR_REG.bits;
     }
@@ -33,6 +39,8 @@ impl Display for Parameters {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match *self {
             Parameters::NUMBER => write!(f, "byte"),
+            Parameters::WORD => write!(f, "word"),
+            Parameters::INDEXED => write!(f, "addr_reg+data_reg"),
             Parameters::ADDRESS => write!(f, "address"),
             Parameters::DATA_REG => write!(f, "data_reg"),
             Parameters::DATA_KEY => write!(f, "data_key"),
@@ -51,6 +59,8 @@ impl Parameters {
         let input = strip_trailing_comment(input);
         match *self {
             Parameters::NUMBER => parse_number(input),
+            Parameters::WORD => parse_word(input),
+            Parameters::INDEXED => parse_indexed(input),
             Parameters::DATA_REG => parse_data_reg(input),
             Parameters::ADDR_REG => parse_addr_reg(input),
             Parameters::ADDRESS => parse_addr(input),
@@ -90,19 +100,19 @@ fn strip_trailing_comment(input: &str) -> &str {
 
 fn parse_data_reg(input: &str) -> Result<Param> {
     match input.to_ascii_lowercase().as_str() {
-        "d0" => Ok(Param::DataReg(REG_D0)),
-        "d1" => Ok(Param::DataReg(REG_D1)),
-        "d2" => Ok(Param::DataReg(REG_D2)),
-        "d3" => Ok(Param::DataReg(REG_D3)),
-        "acc" => Ok(Param::DataReg(REG_ACC)),
+        "d0" => Ok(Param::DataReg(Register::D0)),
+        "d1" => Ok(Param::DataReg(Register::D1)),
+        "d2" => Ok(Param::DataReg(Register::D2)),
+        "d3" => Ok(Param::DataReg(Register::D3)),
+        "acc" => Ok(Param::DataReg(Register::Acc)),
         _ => Err(Error::msg(format!("Not a valid data register: {}", input))),
     }
 }
 
 fn parse_addr_reg(input: &str) -> Result<Param> {
     match input.to_ascii_lowercase().as_str() {
-        "a0" => Ok(Param::AddrReg(REG_A0)),
-        "a1" => Ok(Param::AddrReg(REG_A1)),
+        "a0" => Ok(Param::AddrReg(Register::A0)),
+        "a1" => Ok(Param::AddrReg(Register::A1)),
         _ => Err(Error::msg(format!(
             "Not a valid address register: {}",
             input
@@ -111,34 +121,120 @@ fn parse_addr_reg(input: &str) -> Result<Param> {
 }
 
 fn parse_number(input: &str) -> Result<Param> {
-    let num = if input.starts_with('x') {
-        let hex = input.chars().skip(1).collect::<String>();
-        u8::from_str_radix(&hex, 16)
-    } else if input.starts_with('b') {
-        if input.len() == 9 {
-            let bin = input.chars().skip(1).collect::<String>();
-            u8::from_str_radix(&bin, 2)
-        } else {
-            return Err(Error::msg(format!(
-                "Error parsing number {}: must be b followed all 8 bits",
-                input
-            )));
-        }
-    } else if input.len() == 3 && input.starts_with('\'') && input.ends_with('\'') {
-        let chr = input.chars().nth(1).unwrap();
-        if chr.is_ascii() {
-            Ok(chr as u8)
-        } else {
-            return Err(Error::msg(format!("Invalid char: {}", input)));
-        }
-    } else {
-        input.parse::<u8>()
+    parse_byte_literal(input).map(Param::Number)
+}
+
+fn parse_indexed(input: &str) -> Result<Param> {
+    let err = || {
+        Error::msg(format!(
+            "Expected addr reg+data reg (e.g. a0+d1), found {}",
+            input
+        ))
     };
-    match num {
-        Ok(num) => Ok(Param::Number(num)),
-        Err(err) => Err(Error::msg(format!(
-            "Error parsing number {}: {}",
-            input, err
+    let (areg, reg) = input.split_once('+').ok_or_else(err)?;
+    match (parse_addr_reg(areg), parse_data_reg(reg)) {
+        (Ok(Param::AddrReg(areg)), Ok(Param::DataReg(reg))) => Ok(Param::Indexed(areg, reg)),
+        _ => Err(err()),
+    }
+}
+
+fn parse_word(input: &str) -> Result<Param> {
+    parse_word_literal(input).map(Param::Word)
+}
+
+///Parse a 16 bit value written the same way as bytes (see [`parse_byte_literal`]),
+///binary and octal can be up to 16 bits and negative decimals down to -32768 wrap
+pub fn parse_word_literal(input: &str) -> Result<u16> {
+    if input.starts_with('\'') {
+        return parse_char_literal(input).map(u16::from);
+    }
+    let (digits, radix) = match input.chars().next() {
+        Some('x') => (&input[1..], 16),
+        Some('b') => (&input[1..], 2),
+        Some('o') => (&input[1..], 8),
+        _ => (input, 10),
+    };
+    let num = match digits.strip_prefix('-') {
+        Some(negative) if radix == 10 => match negative.parse::<u32>() {
+            Ok(num) if num <= 32768 => Ok((num as u16).wrapping_neg()),
+            Ok(_) => {
+                return Err(Error::msg(format!(
+                    "Error parsing number {}: must be between -32768 and 65535",
+                    input
+                )))
+            }
+            Err(err) => Err(err),
+        },
+        _ => u16::from_str_radix(digits, radix),
+    };
+    num.map_err(|err| Error::msg(format!("Error parsing number {}: {}", input, err)))
+}
+
+///Parse a byte written as decimal, hex (`xFF`), binary (`b1010`), octal (`o17`) or a char (`'a'`, `'\n'`)
+///
+///Negative decimals down to -128 wrap, so `-1` is 255 (see [`is_wrapped_literal`])
+pub fn parse_byte_literal(input: &str) -> Result<u8> {
+    if input.len() >= 2 && input.starts_with('\'') && input.ends_with('\'') {
+        return parse_char_literal(input);
+    }
+    let (digits, radix) = match input.chars().next() {
+        Some('x') => (&input[1..], 16),
+        Some('b') => (&input[1..], 2),
+        Some('o') => (&input[1..], 8),
+        _ => (input, 10),
+    };
+    if radix == 2 && digits.len() > 8 {
+        return Err(Error::msg(format!(
+            "Error parsing number {}: must be b followed by up to 8 bits",
+            input
+        )));
+    }
+    let num = match digits.strip_prefix('-') {
+        Some(negative) if radix == 10 => match negative.parse::<u16>() {
+            Ok(num) if num <= 128 => Ok((num as u8).wrapping_neg()),
+            Ok(_) => {
+                return Err(Error::msg(format!(
+                    "Error parsing number {}: must be between -128 and 255",
+                    input
+                )))
+            }
+            Err(err) => Err(err),
+        },
+        _ => u8::from_str_radix(digits, radix),
+    };
+    num.map_err(|err| Error::msg(format!("Error parsing number {}: {}", input, err)))
+}
+
+///True if `input` is a negative decimal that [`parse_byte_literal`] wraps, i.e. `-1`
+pub fn is_wrapped_literal(input: &str) -> bool {
+    input.starts_with('-') && matches!(parse_byte_literal(input), Ok(num) if num != 0)
+}
+
+fn parse_char_literal(input: &str) -> Result<u8> {
+    let content = &input[1..input.len() - 1];
+    let mut chars = content.chars();
+    let chr = match (chars.next(), chars.next(), chars.next()) {
+        (Some('\\'), Some(escaped), None) => match escaped {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            'e' => Some('\x1b'),
+            '\\' | '\'' | '"' => Some(escaped),
+            _ => None,
+        },
+        (Some(chr), None, None) => Some(chr),
+        _ => None,
+    };
+    match chr {
+        Some(chr) if chr.is_ascii() => Ok(chr as u8),
+        Some(_) => Err(Error::msg(format!(
+            "Invalid char {}, only ASCII characters can be stored in a single byte",
+            input
+        ))),
+        None => Err(Error::msg(format!(
+            "Invalid char {}, must be a single character or an escape (\\n, \\t, \\r, \\0, \\e, \\\\, \\', \\\")",
+            input
         ))),
     }
 }
@@ -201,7 +297,14 @@ mod tests {
         assert_eq!(parse_number("255").unwrap(), Param::Number(255));
         assert!(parse_number("256").is_err());
         assert!(parse_number("1000").is_err());
-        assert!(parse_number("-1").is_err());
+        assert_eq!(parse_number("-1").unwrap(), Param::Number(255));
+        assert_eq!(parse_number("-128").unwrap(), Param::Number(128));
+        assert!(parse_number("-129").is_err());
+        assert!(parse_number("-x1").is_err());
+        assert_eq!(parse_number("o17").unwrap(), Param::Number(15));
+        assert_eq!(parse_number("o377").unwrap(), Param::Number(255));
+        assert!(parse_number("o400").is_err());
+        assert!(parse_number("o8").is_err());
         assert_eq!(parse_number("xA").unwrap(), Param::Number(10));
         assert_eq!(parse_number("x0").unwrap(), Param::Number(0));
         assert_eq!(parse_number("x64").unwrap(), Param::Number(100));
@@ -214,23 +317,75 @@ mod tests {
         assert_eq!(parse_number("'}'").unwrap(), Param::Number(125));
         assert!(parse_number("'s '").is_err());
         assert!(parse_number("''").is_err());
+        assert_eq!(parse_number("'\\n'").unwrap(), Param::Number(10));
+        assert_eq!(parse_number("'\\''").unwrap(), Param::Number(39));
+        assert_eq!(parse_number("'\\\\'").unwrap(), Param::Number(92));
+        assert_eq!(parse_number("'\\0'").unwrap(), Param::Number(0));
+        assert!(parse_number("'\\q'").is_err());
+        assert_eq!(
+            parse_number("'é'").unwrap_err().to_string(),
+            "Invalid char 'é', only ASCII characters can be stored in a single byte"
+        );
         assert_eq!(parse_number("b00000000").unwrap(), Param::Number(0));
         assert_eq!(parse_number("b11111111").unwrap(), Param::Number(255));
         assert_eq!(parse_number("b00001111").unwrap(), Param::Number(15));
-        assert!(parse_number("b0").is_err());
-        assert!(parse_number("b1").is_err());
+        assert_eq!(parse_number("b0").unwrap(), Param::Number(0));
+        assert_eq!(parse_number("b1010").unwrap(), Param::Number(10));
+        assert!(parse_number("b").is_err());
+        assert!(parse_number("b2").is_err());
+        assert!(is_wrapped_literal("-1"));
+        assert!(!is_wrapped_literal("-0"));
+        assert!(!is_wrapped_literal("255"));
+        assert!(!is_wrapped_literal("'-'"));
         assert!(parse_number("b101010100111").is_err());
     }
 
+    #[test]
+    fn test_indexed_parsing() {
+        assert_eq!(
+            parse_indexed("a0+d1").unwrap(),
+            Param::Indexed(Register::A0, Register::D1)
+        );
+        assert_eq!(
+            parse_indexed("A1+ACC").unwrap(),
+            Param::Indexed(Register::A1, Register::Acc)
+        );
+        assert!(parse_indexed("a0").is_err());
+        assert!(parse_indexed("d0+a1").is_err());
+        assert!(parse_indexed("a0+a1").is_err());
+        assert!(parse_indexed("a0+1").is_err());
+        assert!(parse_indexed("a0+d1+d2").is_err());
+    }
+
+    #[test]
+    fn test_word_parsing() {
+        assert_eq!(parse_word("0").unwrap(), Param::Word(0));
+        assert_eq!(parse_word("1000").unwrap(), Param::Word(1000));
+        assert_eq!(parse_word("65535").unwrap(), Param::Word(65535));
+        assert_eq!(parse_word("xBEEF").unwrap(), Param::Word(0xBEEF));
+        assert_eq!(parse_word("b1000000000000001").unwrap(), Param::Word(32769));
+        assert_eq!(parse_word("o177777").unwrap(), Param::Word(65535));
+        assert_eq!(parse_word("-1").unwrap(), Param::Word(65535));
+        assert_eq!(parse_word("'a'").unwrap(), Param::Word(97));
+        assert!(parse_word("65536").is_err());
+        assert!(parse_word("x10000").is_err());
+        assert!(parse_word("-32769").is_err());
+        assert!(parse_word("@10").is_err());
+        assert!(parse_word("label").is_err());
+    }
+
     #[test]
     fn test_reg_parsing() {
-        assert_eq!(parse_data_reg("d0").unwrap(), Param::DataReg(REG_D0));
-        assert_eq!(parse_data_reg("d1").unwrap(), Param::DataReg(REG_D1));
-        assert_eq!(parse_data_reg("d2").unwrap(), Param::DataReg(REG_D2));
-        assert_eq!(parse_data_reg("d3").unwrap(), Param::DataReg(REG_D3));
-        assert_eq!(parse_data_reg("acc").unwrap(), Param::DataReg(REG_ACC));
-        assert_eq!(parse_addr_reg("a0").unwrap(), Param::AddrReg(REG_A0));
-        assert_eq!(parse_addr_reg("a1").unwrap(), Param::AddrReg(REG_A1));
+        assert_eq!(parse_data_reg("d0").unwrap(), Param::DataReg(Register::D0));
+        assert_eq!(parse_data_reg("d1").unwrap(), Param::DataReg(Register::D1));
+        assert_eq!(parse_data_reg("d2").unwrap(), Param::DataReg(Register::D2));
+        assert_eq!(parse_data_reg("d3").unwrap(), Param::DataReg(Register::D3));
+        assert_eq!(
+            parse_data_reg("acc").unwrap(),
+            Param::DataReg(Register::Acc)
+        );
+        assert_eq!(parse_addr_reg("a0").unwrap(), Param::AddrReg(Register::A0));
+        assert_eq!(parse_addr_reg("a1").unwrap(), Param::AddrReg(Register::A1));
         assert!(parse_data_reg("d5").is_err());
         assert!(parse_data_reg("a0").is_err());
         assert!(parse_data_reg("").is_err());
@@ -249,7 +404,7 @@ mod tests {
     fn test_addr_reg_parameter_parsing() {
         assert_eq!(
             Parameters::ADDR_REG.parse("a0").unwrap(),
-            Param::AddrReg(REG_A0)
+            Param::AddrReg(Register::A0)
         );
     }
 
@@ -267,7 +422,7 @@ mod tests {
     fn test_data_reg_parameter_parsing() {
         assert_eq!(
             Parameters::DATA_REG.parse("D0").unwrap(),
-            Param::DataReg(REG_D0)
+            Param::DataReg(Register::D0)
         );
     }
 
@@ -299,7 +454,7 @@ mod tests {
     fn test_registers_parameter_parsing() {
         assert_eq!(
             Parameters::REGISTERS.parse("aCc").unwrap(),
-            Param::DataReg(REG_ACC)
+            Param::DataReg(Register::Acc)
         );
     }
 }
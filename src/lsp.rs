@@ -0,0 +1,382 @@
+//! Minimal Language Server Protocol server for BASM, started with `lsp` and spoken over stdin/stdout
+//!
+//! Supports diagnostics when a file is opened or changed (full sync), go to definition for labels,
+//! strings, data, constants and reserved memory and hover for instructions and keys.
+//! Positions are treated as char offsets, which only differs from UTF-16 for text outside the BMP
+
+use crate::assembler::check_source;
+use crate::assembler::diagnostic::{token_span, Diagnostic, Severity};
+use crate::assembler::parser::generate_program_model;
+use crate::assembler::program_model::ProgramModel;
+use crate::language::op_help;
+use anyhow::{Error, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+
+///Run the server on stdin/stdout until the client sends `exit`
+pub fn start() -> Result<()> {
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    run(&mut stdin.lock(), &mut stdout.lock())
+}
+
+///Read messages from `input` and write responses and notifications to `output` until `exit`
+pub fn run<R: BufRead, W: Write>(input: &mut R, output: &mut W) -> Result<()> {
+    let mut server = Server::default();
+    while let Some(message) = read_message(input)? {
+        if message["method"] == "exit" {
+            break;
+        }
+        for reply in server.handle(&message) {
+            write_message(output, &reply)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_message<R: BufRead>(input: &mut R) -> Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = Some(value.trim().parse::<usize>()?);
+            }
+        }
+    }
+    let length = length.ok_or_else(|| Error::msg("LSP message has no Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Value) -> Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()?;
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct Server {
+    ///Text of open documents by URI
+    documents: HashMap<String, String>,
+}
+
+impl Server {
+    ///Responses and notifications for `message`
+    fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let result = match message["method"].as_str().unwrap_or_default() {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "hoverProvider": true
+                },
+                "serverInfo": { "name": "tape_device" }
+            }),
+            "shutdown" => Value::Null,
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_owned(), text.to_owned());
+                return vec![self.publish_diagnostics(uri)];
+            }
+            "textDocument/didChange" => {
+                //full sync so the last change is the whole document
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    self.documents.insert(uri.to_owned(), text.to_owned());
+                }
+                return vec![self.publish_diagnostics(uri)];
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                )];
+            }
+            "textDocument/definition" => self.definition(uri, &params["position"]),
+            "textDocument/hover" => self.hover(uri, &params["position"]),
+            _ if message.get("id").is_some() => {
+                return vec![json!({
+                    "jsonrpc": "2.0",
+                    "id": message["id"],
+                    "error": {
+                        "code": METHOD_NOT_FOUND,
+                        "message": format!("Unsupported method {}", message["method"])
+                    }
+                })]
+            }
+            //other notifications are ignored
+            _ => return vec![],
+        };
+        vec![json!({ "jsonrpc": "2.0", "id": message["id"], "result": result })]
+    }
+
+    fn lines(&self, uri: &str) -> Vec<String> {
+        self.documents
+            .get(uri)
+            .map(|text| text.lines().map(String::from).collect())
+            .unwrap_or_default()
+    }
+
+    fn publish_diagnostics(&self, uri: &str) -> Value {
+        let diagnostics: Vec<Value> = check_source(self.lines(uri))
+            .iter()
+            .map(to_lsp_diagnostic)
+            .collect();
+        notification(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+
+    ///Program model and the word at `position`, programs with errors can't be used
+    fn model_and_word(&self, uri: &str, position: &Value) -> Option<(ProgramModel, String)> {
+        let lines = self.lines(uri);
+        let line = lines.get(position["line"].as_u64()? as usize)?;
+        let word = word_at(line, position["character"].as_u64()? as usize)?;
+        Some((generate_program_model(lines).ok()?, word))
+    }
+
+    fn definition(&self, uri: &str, position: &Value) -> Value {
+        self.model_and_word(uri, position)
+            .and_then(|(model, word)| {
+                let key = word.trim_start_matches('@');
+                let definition = model.definition_of(key)?;
+                let line = definition.line_num - 1;
+                let (start, end) = token_span(&definition.original_line, key);
+                Some(json!({
+                    "uri": uri,
+                    "range": range(line, start - 1, line, end - 1)
+                }))
+            })
+            .unwrap_or(Value::Null)
+    }
+
+    fn hover(&self, uri: &str, position: &Value) -> Value {
+        let lines = self.lines(uri);
+        let word = position["line"]
+            .as_u64()
+            .and_then(|line| lines.get(line as usize))
+            .zip(position["character"].as_u64())
+            .and_then(|(line, character)| word_at(line, character as usize));
+        let word = match word {
+            Some(word) => word,
+            None => return Value::Null,
+        };
+        let text = match op_help(&word) {
+            Some(text) => Some(format!("```\n{}\n```", text)),
+            None => generate_program_model(lines)
+                .ok()
+                .and_then(|model| describe_key(&model, word.trim_start_matches('@'))),
+        };
+        match text {
+            Some(text) => json!({ "contents": { "kind": "markdown", "value": text } }),
+            None => Value::Null,
+        }
+    }
+}
+
+///Definition and usage of a key, see [`ProgramModel::definition_of`]
+fn describe_key(model: &ProgramModel, key: &str) -> Option<String> {
+    let definition = model.definition_of(key)?;
+    let usage = model
+        .labels
+        .get(key)
+        .map(|model| &model.usage)
+        .or_else(|| model.strings.get(key).map(|model| &model.usage))
+        .or_else(|| model.data.get(key).map(|model| &model.usage))
+        .or_else(|| model.constants.get(key).map(|model| &model.usage))
+        .or_else(|| model.reserved.get(key).map(|model| &model.usage));
+    let mut lines: Vec<String> = usage
+        .map(|usage| {
+            usage
+                .iter()
+                .map(|usage| usage.line_num.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    lines.dedup();
+    let used = if lines.is_empty() {
+        String::from("Never used")
+    } else {
+        format!("Used on line {}", lines.join(", "))
+    };
+    Some(format!(
+        "```\n{}\n```\nDefined on line {}, {}",
+        definition.original_line.trim(),
+        definition.line_num,
+        used
+    ))
+}
+
+///Key, mnemonic or register at char `character` of `line`
+fn word_at(line: &str, character: usize) -> Option<String> {
+    let chars: Vec<char> = line.chars().collect();
+    let is_word = |chr: &char| chr.is_ascii_alphanumeric() || *chr == '_' || *chr == '@';
+    if !chars.get(character).map(is_word).unwrap_or(false) {
+        return None;
+    }
+    let start = chars[..character]
+        .iter()
+        .rposition(|chr| !is_word(chr))
+        .map(|idx| idx + 1)
+        .unwrap_or(0);
+    let end = chars[character..]
+        .iter()
+        .position(|chr| !is_word(chr))
+        .map(|len| character + len)
+        .unwrap_or(chars.len());
+    Some(chars[start..end].iter().collect())
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+fn range(start_line: usize, start: usize, end_line: usize, end: usize) -> Value {
+    json!({
+        "start": { "line": start_line, "character": start },
+        "end": { "line": end_line, "character": end }
+    })
+}
+
+///Diagnostics without a line are shown at the start of the file
+fn to_lsp_diagnostic(diagnostic: &Diagnostic) -> Value {
+    let line = diagnostic.line.saturating_sub(1);
+    json!({
+        "range": range(
+            line,
+            diagnostic.column.saturating_sub(1),
+            line,
+            diagnostic.end_column.saturating_sub(1)
+        ),
+        "severity": match diagnostic.severity {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+        },
+        "code": diagnostic.code,
+        "source": "tape_device",
+        "message": diagnostic.message
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    const URI: &str = "file:///prog.basm";
+
+    fn open(server: &mut Server, text: &str) -> Value {
+        server
+            .handle(&json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didOpen",
+                "params": { "textDocument": { "uri": URI, "text": text } }
+            }))
+            .remove(0)
+    }
+
+    fn request(server: &mut Server, method: &str, line: usize, character: usize) -> Value {
+        server
+            .handle(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": {
+                    "textDocument": { "uri": URI },
+                    "position": { "line": line, "character": character }
+                }
+            }))
+            .remove(0)["result"]
+            .clone()
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut server = Server::default();
+        let notification = open(&mut server, "Prog\n1\n.ops\n  foo d0\n");
+        assert_eq!(
+            notification["params"]["diagnostics"][0]["range"],
+            range(3, 2, 3, 5)
+        );
+        assert_eq!(
+            notification["params"]["diagnostics"][0]["code"],
+            "invalid_op"
+        );
+        let notification = open(&mut server, "Prog\n1\n.ops\nhalt\n");
+        assert_eq!(notification["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn test_definition_and_hover() {
+        let mut server = Server::default();
+        open(
+            &mut server,
+            "Prog\n1\n.strings\nhi=Hello\n.ops\ncpy a0 hi\nloop:\nprts a0\njmp loop\n",
+        );
+        assert_eq!(
+            request(&mut server, "textDocument/definition", 8, 5),
+            json!({ "uri": URI, "range": range(6, 0, 6, 4) })
+        );
+        assert_eq!(
+            request(&mut server, "textDocument/definition", 5, 7),
+            json!({ "uri": URI, "range": range(3, 0, 3, 2) })
+        );
+        assert_eq!(
+            request(&mut server, "textDocument/definition", 5, 0),
+            Value::Null
+        );
+        let hover = request(&mut server, "textDocument/hover", 7, 1);
+        assert!(hover["contents"]["value"]
+            .as_str()
+            .unwrap()
+            .starts_with("```\nPRTS - Prints string named by 1st param"));
+        assert_eq!(
+            request(&mut server, "textDocument/hover", 5, 8)["contents"]["value"],
+            "```\nhi=Hello\n```\nDefined on line 4, Used on line 6"
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let messages = [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "unknown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+        ];
+        let input: String = messages
+            .iter()
+            .map(|message| {
+                let body = message.to_string();
+                format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+            })
+            .collect();
+        let mut output = vec![];
+        run(&mut Cursor::new(input), &mut output).unwrap();
+        let mut output = Cursor::new(output);
+        let initialize = read_message(&mut output).unwrap().unwrap();
+        assert_eq!(initialize["result"]["capabilities"]["hoverProvider"], true);
+        let unknown = read_message(&mut output).unwrap().unwrap();
+        assert_eq!(unknown["error"]["code"], METHOD_NOT_FOUND);
+        assert!(read_message(&mut output).unwrap().is_none());
+    }
+}
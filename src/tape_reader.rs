@@ -1,28 +1,268 @@
-use crate::common::read_bytes;
+use crate::common::{crc32, read_bytes};
+use crate::constants::code::{
+    Opcode, FOPEN_REG_STR, FOPEN_VAL_STR, INTH_VAL_ADDR, LD_AREG_DATA_REG_REG,
+    LD_AREG_DATA_REG_VAL, LD_AREG_DATA_VAL_REG, LD_AREG_DATA_VAL_VAL, LOADKV_STR_AREG, PRTS_STR,
+    SAVE_STR_AREG,
+};
+use crate::constants::hardware::MAX_DATA_BYTES;
 use crate::constants::system::*;
+use crate::constants::{get_addr_byte_offset, get_byte_count, is_jump_op};
 use anyhow::{Context, Error, Result};
+use chrono::{TimeZone, Utc};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 
 pub struct Tape {
+    ///Format version, see [`PRG_VERSION`]
+    pub tape_version: u8,
     pub name: String,
     pub version: String,
+    pub description: Option<String>,
+    ///Seconds since the UNIX epoch, only for tapes with [`METADATA_PRG_VERSION`] or later
+    pub build_time: Option<u64>,
+    ///CRC32 of the ops, strings and data, only for tapes with [`METADATA_PRG_VERSION`] or later
+    pub checksum: Option<u32>,
+    ///Address of the first op to run, always 0 for tapes before [`ENTRY_PRG_VERSION`]
+    pub entry: u16,
     pub ops: Vec<u8>,
     pub strings: Vec<u8>,
     pub data: Vec<u8>,
+    ///Data keys that must be loaded from data tapes, see [`link_data_tapes`]
+    pub relocations: Vec<Relocation>,
+}
+
+///Ops that use data from a data tape
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    pub key: String,
+    ///Index in ops of each data address to replace
+    pub offsets: Vec<u16>,
+}
+
+///Named data entries that are appended to a tape's data when it's loaded
+///
+///Format: `0xFD DA 02 <entry count u16> [<key len> <key> <content len u16> <content>]`
+///
+///Entries from version 1 data tapes are converted to 2 byte array lengths when read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataTape {
+    pub entries: Vec<(String, Vec<u8>)>,
 }
 
 pub fn read_tape(path: &str) -> Result<Tape> {
+    parse_tape(read_bytes(path)?)
+}
+
+///Read tape and fail if [`validate_tape`] finds any problems, for running tapes that may not come from the assembler
+pub fn read_tape_strict(path: &str) -> Result<Tape> {
+    parse_tape_strict(read_bytes(path)?)
+}
+
+pub fn parse_tape_strict(bytes: Vec<u8>) -> Result<Tape> {
+    let tape = parse_tape(bytes)?;
+    let problems = validate_tape(&tape);
+    if problems.is_empty() {
+        Ok(tape)
+    } else {
+        Err(Error::msg(format!(
+            "Invalid tape, {} problems found:\n{}",
+            problems.len(),
+            problems.join("\n")
+        )))
+    }
+}
+
+///Check the ops are valid instructions and that jumps, strings and data they use are inside the tape
+///
+///Returns a message for each problem found. Header and section lengths are checked by [`parse_tape`].
+///Ops that use data from data tapes aren't checked as the data isn't known until linking.
+pub fn validate_tape(tape: &Tape) -> Vec<String> {
+    let mut problems = vec![];
+    if tape.data.len() > MAX_DATA_BYTES {
+        problems.push(format!(
+            "Data is {} bytes, max {} bytes",
+            tape.data.len(),
+            MAX_DATA_BYTES
+        ));
+    }
+
+    let mut string_starts = HashSet::new();
+    let mut idx = 0;
+    while idx < tape.strings.len() {
+        string_starts.insert(idx);
+        let end = idx + 1 + tape.strings[idx] as usize;
+        if end > tape.strings.len() {
+            problems.push(format!(
+                "String at {:04X} is {} bytes but only {} bytes remain",
+                idx,
+                tape.strings[idx],
+                tape.strings.len() - idx - 1
+            ));
+        } else if std::str::from_utf8(&tape.strings[idx + 1..end]).is_err() {
+            problems.push(format!("String at {:04X} is not valid UTF-8", idx));
+        }
+        idx = end;
+    }
+
+    let relocated: HashSet<usize> = tape
+        .relocations
+        .iter()
+        .flat_map(|relocation| relocation.offsets.iter().map(|offset| *offset as usize))
+        .collect();
+    let mut op_starts = HashSet::new();
+    let mut addresses = vec![];
+    let mut pc = 0;
+    while pc < tape.ops.len() {
+        let opcode = tape.ops[pc];
+        if Opcode::try_from(opcode).is_err() {
+            problems.push(format!(
+                "Unknown instruction {:02X} at {:04X}, the remaining {} bytes can't be checked",
+                opcode,
+                pc,
+                tape.ops.len() - pc
+            ));
+            break;
+        }
+        op_starts.insert(pc);
+        let len = get_byte_count(opcode);
+        if pc + len > tape.ops.len() {
+            problems.push(format!(
+                "Instruction {:02X} at {:04X} is truncated, it needs {} bytes but only {} remain",
+                opcode,
+                pc,
+                len,
+                tape.ops.len() - pc
+            ));
+            break;
+        }
+        if let Some(offset) = get_addr_byte_offset(opcode) {
+            let addr = u16::from_be_bytes([tape.ops[pc + offset], tape.ops[pc + offset + 1]]);
+            addresses.push((
+                pc,
+                opcode,
+                addr as usize,
+                relocated.contains(&(pc + offset)),
+            ));
+        }
+        pc += len;
+    }
+
+    if tape.entry != 0 && !op_starts.contains(&(tape.entry as usize)) {
+        problems.push(format!(
+            "Entry point {:04X} is not the start of an instruction ({} bytes of ops)",
+            tape.entry,
+            tape.ops.len()
+        ));
+    }
+
+    for (pc, opcode, addr, relocated) in addresses {
+        match opcode {
+            _ if is_jump_op(opcode) || opcode == INTH_VAL_ADDR => {
+                if addr >= tape.ops.len() {
+                    problems.push(format!(
+                        "Instruction {:02X} at {:04X} jumps to {:04X} which is outside of ops ({} bytes)",
+                        opcode,
+                        pc,
+                        addr,
+                        tape.ops.len()
+                    ));
+                } else if !op_starts.contains(&addr) {
+                    problems.push(format!(
+                        "Instruction {:02X} at {:04X} jumps to {:04X} which is in the middle of an instruction",
+                        opcode, pc, addr
+                    ));
+                }
+            }
+            PRTS_STR | FOPEN_REG_STR | FOPEN_VAL_STR | SAVE_STR_AREG | LOADKV_STR_AREG
+                if !string_starts.contains(&addr) =>
+            {
+                problems.push(format!(
+                    "Instruction {:02X} at {:04X} uses string {:04X} which isn't the start of a string ({} bytes of strings)",
+                    opcode,
+                    pc,
+                    addr,
+                    tape.strings.len()
+                ));
+            }
+            LD_AREG_DATA_REG_REG | LD_AREG_DATA_REG_VAL | LD_AREG_DATA_VAL_REG
+            | LD_AREG_DATA_VAL_VAL
+                if !relocated && addr >= tape.data.len() =>
+            {
+                problems.push(format!(
+                    "Instruction {:02X} at {:04X} uses data {:04X} which is outside of data ({} bytes)",
+                    opcode,
+                    pc,
+                    addr,
+                    tape.data.len()
+                ));
+            }
+            _ => {}
+        }
+    }
+    problems
+}
+
+///Print every problem [`validate_tape`] finds, fails if the tape can't be parsed or has problems
+pub fn validate(path: &str) -> Result<()> {
+    let tape = read_tape(path)?;
+    let problems = validate_tape(&tape);
+    if problems.is_empty() {
+        println!("{} is valid", path);
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    Err(Error::msg(format!(
+        "{} problems found in {}",
+        problems.len(),
+        path
+    )))
+}
+
+pub fn parse_tape(mut bytes: Vec<u8>) -> Result<Tape> {
     let mut idx = 0;
-    let mut bytes = read_bytes(path)?;
     if get_byte(&mut bytes, &mut idx, "header")? != TAPE_HEADER_1
         || get_byte(&mut bytes, &mut idx, "header")? != TAPE_HEADER_2
     {
         return Err(Error::msg("Not a TD tape file"));
     }
-    if get_byte(&mut bytes, &mut idx, "tape version")? != PRG_VERSION {
+    let tape_version = get_byte(&mut bytes, &mut idx, "tape version")?;
+    if tape_version == 0 || tape_version > PRG_VERSION {
         return Err(Error::msg("Incompatible TD version"));
     }
     let name = read_string(&mut bytes, &mut idx, "program name")?;
     let version = read_string(&mut bytes, &mut idx, "program version")?;
+    let mut entry = 0;
+    let (description, build_time, checksum) = if tape_version >= METADATA_PRG_VERSION {
+        let description = read_string(&mut bytes, &mut idx, "program description")?;
+        let mut time_bytes = [0; 8];
+        for byte in time_bytes.iter_mut() {
+            *byte = get_byte(&mut bytes, &mut idx, "build time")?;
+        }
+        if tape_version >= ENTRY_PRG_VERSION {
+            entry = read_u16(&mut bytes, &mut idx, "entry point")?;
+        }
+        let mut checksum_bytes = [0; 4];
+        for byte in checksum_bytes.iter_mut() {
+            *byte = get_byte(&mut bytes, &mut idx, "checksum")?;
+        }
+        let checksum = u32::from_be_bytes(checksum_bytes);
+        let actual = crc32(&bytes);
+        if checksum != actual {
+            return Err(Error::msg(format!(
+                "Tape checksum mismatch, expected {:08X} but was {:08X}, the tape may be corrupted",
+                checksum, actual
+            )));
+        }
+        (
+            Some(description).filter(|text| !text.is_empty()),
+            Some(u64::from_be_bytes(time_bytes)),
+            Some(checksum),
+        )
+    } else {
+        (None, None, None)
+    };
     let pc_byte_count = u16::from_be_bytes([
         get_byte(&mut bytes, &mut idx, "program op count")?,
         get_byte(&mut bytes, &mut idx, "program op count")?,
@@ -39,16 +279,242 @@ pub fn read_tape(path: &str) -> Result<Tape> {
     for _ in 0..strings_byte_count {
         strings.push(get_byte(&mut bytes, &mut idx, "strings")?);
     }
+    let mut relocations = vec![];
+    if tape_version >= RELOCATION_PRG_VERSION {
+        let count = read_u16(&mut bytes, &mut idx, "relocation count")?;
+        for _ in 0..count {
+            let key = read_string(&mut bytes, &mut idx, "relocation key")?;
+            let offset_count = read_u16(&mut bytes, &mut idx, "relocation")?;
+            let mut offsets = vec![];
+            for _ in 0..offset_count {
+                let offset = read_u16(&mut bytes, &mut idx, "relocation")?;
+                if offset as usize + 1 >= ops.len() {
+                    return Err(Error::msg(format!(
+                        "Relocation for {} at {:04X} is outside of ops",
+                        key, offset
+                    )));
+                }
+                offsets.push(offset);
+            }
+            relocations.push(Relocation { key, offsets });
+        }
+    }
 
     Ok(Tape {
+        tape_version,
         name,
         version,
+        description,
+        build_time,
+        checksum,
+        entry,
         ops,
         strings,
         data: bytes,
+        relocations,
     })
 }
 
+pub fn read_data_tape(path: &str) -> Result<DataTape> {
+    parse_data_tape(read_bytes(path)?).context(path.to_string())
+}
+
+pub fn parse_data_tape(mut bytes: Vec<u8>) -> Result<DataTape> {
+    let mut idx = 0;
+    if get_byte(&mut bytes, &mut idx, "header")? != TAPE_HEADER_1
+        || get_byte(&mut bytes, &mut idx, "header")? != DATA_TAPE_HEADER_2
+    {
+        return Err(Error::msg("Not a TD data tape file"));
+    }
+    let data_tape_version = get_byte(&mut bytes, &mut idx, "data tape version")?;
+    if data_tape_version == 0 || data_tape_version > DATA_TAPE_VERSION {
+        return Err(Error::msg("Incompatible TD data tape version"));
+    }
+    let count = read_u16(&mut bytes, &mut idx, "entry count")?;
+    let mut entries = vec![];
+    for _ in 0..count {
+        let key = read_string(&mut bytes, &mut idx, "data key")?;
+        let len = read_u16(&mut bytes, &mut idx, "data length")?;
+        let mut content = vec![];
+        for _ in 0..len {
+            content.push(get_byte(&mut bytes, &mut idx, "data")?);
+        }
+        if data_tape_version < WIDE_DATA_TAPE_VERSION {
+            content = resize_data_lengths(&content, 1, 2).context(format!("Data {}", key))?;
+        }
+        entries.push((key, content));
+    }
+    Ok(DataTape { entries })
+}
+
+///Read data tapes at `paths` and [`link_data`] them
+pub fn link_data_tapes(tape: &mut Tape, paths: &[String]) -> Result<()> {
+    let mut data_tapes = vec![];
+    for path in paths {
+        data_tapes.push(read_data_tape(path)?);
+    }
+    link_data(tape, data_tapes)
+}
+
+///Append the entries from each data tape to the tape data and update ops that use them
+pub fn link_data(tape: &mut Tape, data_tapes: Vec<DataTape>) -> Result<()> {
+    let mut addresses = HashMap::new();
+    for (key, content) in data_tapes
+        .into_iter()
+        .flat_map(|data_tape| data_tape.entries)
+    {
+        if addresses.contains_key(&key) {
+            return Err(Error::msg(format!(
+                "Data {} is in more than one data tape",
+                key
+            )));
+        }
+        let content = if tape.tape_version < WIDE_DATA_PRG_VERSION {
+            resize_data_lengths(&content, 2, 1).context(format!("Data {}", key))?
+        } else {
+            content
+        };
+        if tape.data.len() + content.len() > MAX_DATA_BYTES {
+            return Err(Error::msg(format!(
+                "Too much data when loading {}, max {} bytes",
+                key, MAX_DATA_BYTES
+            )));
+        }
+        addresses.insert(key, tape.data.len() as u16);
+        tape.data.extend_from_slice(&content);
+    }
+    for relocation in &tape.relocations {
+        let addr = addresses.get(&relocation.key).ok_or_else(|| {
+            Error::msg(format!(
+                "Data {} is not in any data tape, pass the data tape with --data",
+                relocation.key
+            ))
+        })?;
+        for offset in &relocation.offsets {
+            let offset = *offset as usize;
+            tape.ops[offset..offset + 2].copy_from_slice(&addr.to_be_bytes());
+        }
+    }
+    Ok(())
+}
+
+///Re-encode a data entry so each array length uses `to` bytes instead of `from` bytes
+fn resize_data_lengths(content: &[u8], from: usize, to: usize) -> Result<Vec<u8>> {
+    let truncated = || Error::msg("Data entry is truncated");
+    let count = *content.first().ok_or_else(truncated)? as usize;
+    let lengths = content.get(1..1 + count * from).ok_or_else(truncated)?;
+    let mut output = vec![count as u8];
+    for len in lengths.chunks(from) {
+        let len = len.iter().fold(0, |len, byte| (len << 8) + *byte as usize);
+        if len >= 1 << (to * 8) {
+            return Err(Error::msg(format!(
+                "Data array is {} bytes, tapes before version {} only support up to 255",
+                len, WIDE_DATA_PRG_VERSION
+            )));
+        }
+        output.extend_from_slice(&(len as u16).to_be_bytes()[2 - to..]);
+    }
+    output.extend_from_slice(&content[1 + count * from..]);
+    Ok(output)
+}
+
+///Print tape header details and section sizes
+pub fn print_info(path: &str) -> Result<()> {
+    let tape = read_tape(path)?;
+    print_header(path, &tape);
+    Ok(())
+}
+
+///Print tape header details, entry point and a hexdump of each section
+///
+///If the tape can't be parsed then the error and a hexdump of the whole file are printed instead
+pub fn inspect(path: &str) -> Result<()> {
+    let bytes = read_bytes(path)?;
+    match parse_tape(bytes.clone()) {
+        Ok(tape) => {
+            print_header(path, &tape);
+            println!("Entry:       {:04X}", tape.entry);
+            for (name, section) in [
+                ("Ops", &tape.ops),
+                ("Strings", &tape.strings),
+                ("Data", &tape.data),
+            ]
+            .iter()
+            {
+                println!();
+                println!("{} ({}b)", name, section.len());
+                for line in hexdump(section) {
+                    println!("{}", line);
+                }
+            }
+            Ok(())
+        }
+        Err(err) => {
+            println!("Unable to parse tape: {}", err);
+            println!();
+            println!("File ({}b)", bytes.len());
+            for line in hexdump(&bytes) {
+                println!("{}", line);
+            }
+            Err(err)
+        }
+    }
+}
+
+///Format `bytes` as lines of 16 with the offset, hex values and printable ASCII characters
+pub fn hexdump(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(idx, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let text: String = chunk
+                .iter()
+                .map(|byte| {
+                    if byte.is_ascii_graphic() || *byte == b' ' {
+                        *byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:04X}  {:<47}  |{}|", idx * 16, hex.join(" "), text)
+        })
+        .collect()
+}
+
+fn print_header(path: &str, tape: &Tape) {
+    println!("File:        {}", path);
+    println!("Name:        {}", tape.name);
+    println!("Version:     {}", tape.version);
+    if let Some(description) = &tape.description {
+        println!("Description: {}", description);
+    }
+    println!("Format:      {}", tape.tape_version);
+    match tape.build_time {
+        Some(time) => match Utc.timestamp_opt(time as i64, 0).single() {
+            Some(date) => println!("Built:       {}", date.format("%Y-%m-%d %H:%M:%S UTC")),
+            None => println!("Built:       {} (invalid)", time),
+        },
+        None => println!("Built:       unknown"),
+    }
+    match tape.checksum {
+        Some(checksum) => println!("Checksum:    {:08X} (valid)", checksum),
+        None => println!("Checksum:    none"),
+    }
+    println!("Ops:         {}b", tape.ops.len());
+    println!("Strings:     {}b", tape.strings.len());
+    println!("Data:        {}b", tape.data.len());
+    if !tape.relocations.is_empty() {
+        let keys: Vec<&str> = tape
+            .relocations
+            .iter()
+            .map(|relocation| relocation.key.as_str())
+            .collect();
+        println!("Data tapes:  {}", keys.join(", "));
+    }
+}
+
 fn read_string(bytes: &mut Vec<u8>, idx: &mut usize, name: &str) -> Result<String> {
     let length = get_byte(bytes, idx, name)?;
     let mut str_bytes = vec![];
@@ -58,6 +524,13 @@ fn read_string(bytes: &mut Vec<u8>, idx: &mut usize, name: &str) -> Result<Strin
     String::from_utf8(str_bytes).context(format!("parsing {}", name))
 }
 
+fn read_u16(bytes: &mut Vec<u8>, idx: &mut usize, name: &str) -> Result<u16> {
+    Ok(u16::from_be_bytes([
+        get_byte(bytes, idx, name)?,
+        get_byte(bytes, idx, name)?,
+    ]))
+}
+
 fn get_byte(bytes: &mut Vec<u8>, idx: &mut usize, area: &str) -> Result<u8> {
     *idx += 1;
     if !bytes.is_empty() {
@@ -69,3 +542,208 @@ fn get_byte(bytes: &mut Vec<u8>, idx: &mut usize, area: &str) -> Result<u8> {
         )))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constants::code::{CALL_ADDR, HALT, JMP_ADDR, LD_AREG_DATA_VAL_VAL, PRTS_STR};
+    use crate::constants::hardware::REG_A0;
+
+    #[rustfmt::skip]
+    fn tape_v3() -> Vec<u8> {
+        vec![
+            TAPE_HEADER_1, TAPE_HEADER_2, METADATA_PRG_VERSION,
+            1, 97,
+            1, 98,
+            2, 72, 105,
+            0, 0, 0, 0, 0, 0, 0, 10,
+            0xC0, 0x06, 0xA4, 0x95,
+            0, 1, HALT,
+            0, 0,
+        ]
+    }
+
+    #[test]
+    fn test_hexdump() {
+        assert_eq!(hexdump(&[]), Vec::<String>::new());
+        let mut bytes = b"Hello World!".to_vec();
+        bytes.extend_from_slice(&[0, 1, 0xFF, 0x7F, 65]);
+        assert_eq!(
+            hexdump(&bytes),
+            vec![
+                String::from(
+                    "0000  48 65 6C 6C 6F 20 57 6F 72 6C 64 21 00 01 FF 7F  |Hello World!....|"
+                ),
+                String::from("0010  41                                               |A|"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_metadata() {
+        let tape = parse_tape(tape_v3()).unwrap();
+        assert_eq!(tape.name, String::from("a"));
+        assert_eq!(tape.description, Some(String::from("Hi")));
+        assert_eq!(tape.build_time, Some(10));
+        assert_eq!(tape.checksum, Some(crc32(&[0, 1, HALT, 0, 0])));
+        assert_eq!(tape.ops, vec![HALT]);
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let mut bytes = tape_v3();
+        let last = bytes.len() - 1;
+        bytes[last] = 1;
+        let err = parse_tape(bytes).err().unwrap();
+        assert!(err.to_string().contains("checksum mismatch"));
+    }
+
+    #[rustfmt::skip]
+    fn tape_with_relocation() -> Tape {
+        let body = vec![
+            0, 7, LD_AREG_DATA_VAL_VAL, REG_A0, 0, 0, 0, 0, HALT,
+            0, 0,
+            0, 1, 3, 102, 111, 111, 0, 1, 0, 2,
+            1, 0, 1, 9,
+        ];
+        let mut bytes = vec![TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION, 1, 97, 1, 98, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        bytes.extend_from_slice(&crc32(&body).to_be_bytes());
+        bytes.extend_from_slice(&body);
+        parse_tape(bytes).unwrap()
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_link_data() {
+        let mut tape = tape_with_relocation();
+        assert_eq!(
+            tape.relocations,
+            vec![Relocation {
+                key: String::from("foo"),
+                offsets: vec![2]
+            }]
+        );
+        assert_eq!(tape.data, vec![1, 0, 1, 9]);
+
+        let data_tape = parse_data_tape(vec![
+            TAPE_HEADER_1, DATA_TAPE_HEADER_2, DATA_TAPE_VERSION,
+            0, 2,
+            3, 98, 97, 114, 0, 3, 1, 0, 0,
+            3, 102, 111, 111, 0, 4, 1, 0, 1, 5,
+        ])
+        .unwrap();
+        assert_eq!(data_tape.entries.len(), 2);
+
+        link_data(&mut tape, vec![data_tape]).unwrap();
+        assert_eq!(tape.data, vec![1, 0, 1, 9, 1, 0, 0, 1, 0, 1, 5]);
+        assert_eq!(tape.ops[2..4], [0, 7]);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_link_narrow_data() {
+        let data_tape = parse_data_tape(vec![
+            TAPE_HEADER_1, DATA_TAPE_HEADER_2, 1,
+            0, 1,
+            3, 102, 111, 111, 0, 4, 2, 1, 1, 5,
+        ])
+        .unwrap();
+        assert_eq!(data_tape.entries[0].1, vec![2, 0, 1, 0, 1, 5]);
+
+        let mut tape = tape_with_relocation();
+        tape.tape_version = WIDE_DATA_PRG_VERSION - 1;
+        tape.data = vec![1, 1, 9];
+        link_data(&mut tape, vec![data_tape]).unwrap();
+        assert_eq!(tape.data, vec![1, 1, 9, 2, 1, 1, 5]);
+        assert_eq!(tape.ops[2..4], [0, 3]);
+    }
+
+    #[test]
+    fn test_resize_data_lengths() {
+        assert_eq!(
+            resize_data_lengths(&[2, 1, 3, 4, 5, 6, 7], 1, 2).unwrap(),
+            vec![2, 0, 1, 0, 3, 4, 5, 6, 7]
+        );
+        assert_eq!(
+            resize_data_lengths(&[1, 0, 2, 8, 9], 2, 1).unwrap(),
+            vec![1, 2, 8, 9]
+        );
+        assert!(resize_data_lengths(&[1, 1, 0], 2, 1).is_err());
+        assert!(resize_data_lengths(&[2, 1], 1, 2).is_err());
+    }
+
+    #[test]
+    fn test_link_data_missing() {
+        let mut tape = tape_with_relocation();
+        let err = link_data(&mut tape, vec![]).err().unwrap();
+        assert!(err.to_string().contains("Data foo is not in any data tape"));
+
+        let mut tape = tape_with_relocation();
+        let data_tape = DataTape {
+            entries: vec![(String::from("foo"), vec![0])],
+        };
+        assert!(link_data(&mut tape, vec![data_tape.clone(), data_tape]).is_err());
+    }
+
+    fn tape(ops: Vec<u8>, strings: Vec<u8>, data: Vec<u8>) -> Tape {
+        Tape {
+            tape_version: PRG_VERSION,
+            name: String::from("a"),
+            version: String::from("b"),
+            description: None,
+            build_time: None,
+            checksum: None,
+            entry: 0,
+            ops,
+            strings,
+            data,
+            relocations: vec![],
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_validate_tape() {
+        let valid = tape(vec![JMP_ADDR, 0, 3, PRTS_STR, 0, 2, LD_AREG_DATA_VAL_VAL, REG_A0, 0, 0, 0, 0, HALT], vec![1, 65, 2, 66, 67], vec![1, 1, 5]);
+        assert_eq!(validate_tape(&valid), Vec::<String>::new());
+
+        let invalid = tape(vec![JMP_ADDR, 0, 4, PRTS_STR, 0, 1, CALL_ADDR, 0, 50, LD_AREG_DATA_VAL_VAL, REG_A0, 0, 9, 0, 0, HALT, 0], vec![1, 65, 5, 66], vec![]);
+        assert_eq!(validate_tape(&invalid), vec![
+            String::from("String at 0002 is 5 bytes but only 1 bytes remain"),
+            String::from("Unknown instruction 00 at 0010, the remaining 1 bytes can't be checked"),
+            String::from("Instruction 20 at 0000 jumps to 0004 which is in the middle of an instruction"),
+            String::from("Instruction 93 at 0003 uses string 0001 which isn't the start of a string (4 bytes of strings)"),
+            String::from("Instruction 70 at 0006 jumps to 0032 which is outside of ops (17 bytes)"),
+            String::from("Instruction 47 at 0009 uses data 0009 which is outside of data (0 bytes)"),
+        ]);
+
+        let truncated = tape(vec![HALT, JMP_ADDR, 0], vec![], vec![]);
+        assert_eq!(validate_tape(&truncated), vec![String::from("Instruction 20 at 0001 is truncated, it needs 3 bytes but only 2 remain")]);
+
+        let mut entry = tape(vec![HALT, JMP_ADDR, 0, 0], vec![], vec![]);
+        entry.entry = 1;
+        assert!(validate_tape(&entry).is_empty());
+        entry.entry = 2;
+        assert_eq!(validate_tape(&entry), vec![String::from("Entry point 0002 is not the start of an instruction (4 bytes of ops)")]);
+
+        let mut relocated = tape(vec![LD_AREG_DATA_VAL_VAL, REG_A0, 0, 0, 0, 0], vec![], vec![]);
+        relocated.relocations.push(Relocation { key: String::from("foo"), offsets: vec![2] });
+        assert!(validate_tape(&relocated).is_empty());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_old_versions() {
+        for version in [ACC_COMPARE_PRG_VERSION, 2].iter() {
+            let tape = parse_tape(vec![TAPE_HEADER_1, TAPE_HEADER_2, *version, 1, 97, 1, 98, 0, 1, HALT, 0, 0]).unwrap();
+            assert_eq!(tape.tape_version, *version);
+            assert_eq!(tape.description, None);
+            assert_eq!(tape.build_time, None);
+            assert_eq!(tape.checksum, None);
+            assert_eq!(tape.ops, vec![HALT]);
+        }
+        let tape = parse_tape(tape_v3()).unwrap();
+        assert_eq!(tape.entry, 0);
+        assert!(parse_tape(vec![TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION + 1, 1, 97, 1, 98, 0, 0, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,316 @@
+//! `test` subcommand, assembles and runs `*.test.basm` files and checks the expectations in their comments
+//!
+//! - `#> text` a line of expected output, the output is only checked if there is at least one
+//! - `#< text` a line of keyboard input, in the input script format
+//! - `#= target value` an assertion on the device when the program ends, the target is a register
+//!   (`acc`, `d0`-`d3`, `a0`, `a1`, `sp`, `fp`, `pc`) or `exit` with one of `halt`, `eof`, `error`,
+//!   `cycle_limit`, `output_limit`, `input_requested` or the code passed to EXIT.
+//!   Numbers can be decimal or hex (`x1F`).
+//!
+//! Programs must end with HALT, `EXIT 0` or by running out of ops unless `#= exit` is used.
+//! Files opened by the program are kept in memory and start empty.
+
+use crate::assembler::assemble_source;
+use crate::coverage::Coverage;
+use crate::device::files::MemoryFileProvider;
+use crate::device::internals::Profile;
+use crate::device::runner::{run_tape, ExitReason, RunReport};
+use crate::device::RunOptions;
+use crate::diff::format_diff;
+use anyhow::{Error, Result};
+use std::path::{Path, PathBuf};
+
+pub const TEST_SUFFIX: &str = ".test.basm";
+///Tests are stopped after this many instructions so infinite loops fail instead of hanging
+pub const TEST_MAX_CYCLES: u64 = 10_000_000;
+
+///Expectations read from the comments of a test file
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Expectations {
+    pub output: Vec<String>,
+    pub input: Vec<String>,
+    ///Target and expected value of each `#=` line
+    pub asserts: Vec<(String, String)>,
+}
+
+impl Expectations {
+    pub fn parse(lines: &[String]) -> Result<Self> {
+        let mut expectations = Expectations::default();
+        for (idx, line) in lines.iter().enumerate() {
+            let line = line.trim_start();
+            if let Some(text) = line.strip_prefix("#>") {
+                expectations.output.push(strip_space(text).to_string());
+            } else if let Some(text) = line.strip_prefix("#<") {
+                expectations.input.push(strip_space(text).to_string());
+            } else if let Some(text) = line.strip_prefix("#=") {
+                let parts: Vec<&str> = text.split_whitespace().collect();
+                if parts.len() != 2 {
+                    return Err(Error::msg(format!(
+                        "Invalid assertion on line {}, must be #= <target> <value>, e.g. #= d0 5",
+                        idx + 1
+                    )));
+                }
+                expectations
+                    .asserts
+                    .push((parts[0].to_ascii_lowercase(), parts[1].to_ascii_lowercase()));
+            }
+        }
+        Ok(expectations)
+    }
+
+    ///Messages for each expectation `report` doesn't meet
+    pub fn check(&self, report: &RunReport) -> Result<Vec<String>> {
+        let mut failures = vec![];
+        let expects_exit = self.asserts.iter().any(|(target, _)| target == "exit");
+        if !expects_exit
+            && !matches!(
+                report.exit,
+                ExitReason::Halt | ExitReason::EoF | ExitReason::Exit(0)
+            )
+        {
+            failures.push(format!(
+                "Program stopped with {}: {}",
+                exit_name(report.exit),
+                report.stderr.trim_end()
+            ));
+        }
+        for (target, expected) in &self.asserts {
+            let actual = match target.as_str() {
+                "exit" => exit_name(report.exit),
+                _ => register_value(report, target)?.to_string(),
+            };
+            let expected = match target.as_str() {
+                "exit" => expected.clone(),
+                _ => parse_value(expected)?.to_string(),
+            };
+            if actual != expected {
+                failures.push(format!(
+                    "{} is {} but expected {}",
+                    target, actual, expected
+                ));
+            }
+        }
+        if !self.output.is_empty() {
+            let actual: Vec<String> = report.stdout.lines().map(String::from).collect();
+            let (changes, diff) = format_diff(&self.output, &actual);
+            if changes > 0 {
+                failures.push(format!(
+                    "Output does not match ({} lines differ)\n{}",
+                    changes,
+                    diff.join("\n")
+                ));
+            }
+        }
+        Ok(failures)
+    }
+}
+
+///Text after `#>`/`#<` without the separating space
+fn strip_space(text: &str) -> &str {
+    text.strip_prefix(' ').unwrap_or(text)
+}
+
+pub(crate) fn exit_name(exit: ExitReason) -> String {
+    match exit {
+        ExitReason::Halt => String::from("halt"),
+        ExitReason::EoF => String::from("eof"),
+        ExitReason::Exit(code) => code.to_string(),
+        ExitReason::ProgError => String::from("error"),
+        ExitReason::CycleLimit => String::from("cycle_limit"),
+        ExitReason::OutputLimit => String::from("output_limit"),
+        ExitReason::InputRequested => String::from("input_requested"),
+    }
+}
+
+fn register_value(report: &RunReport, register: &str) -> Result<u16> {
+    let dump = &report.dump;
+    Ok(match register {
+        "acc" => dump.acc as u16,
+        "d0" => dump.data_reg[0] as u16,
+        "d1" => dump.data_reg[1] as u16,
+        "d2" => dump.data_reg[2] as u16,
+        "d3" => dump.data_reg[3] as u16,
+        "a0" => dump.addr_reg[0],
+        "a1" => dump.addr_reg[1],
+        "sp" => dump.sp,
+        "fp" => dump.fp,
+        "pc" => dump.pc,
+        _ => {
+            return Err(Error::msg(format!(
+                "Unknown assertion target '{}', must be a register or exit",
+                register
+            )))
+        }
+    })
+}
+
+fn parse_value(text: &str) -> Result<u16> {
+    let value = match text.strip_prefix('x') {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse::<u16>(),
+    };
+    value.map_err(|_| {
+        Error::msg(format!(
+            "Invalid value '{}', must be a number or x followed by hex",
+            text
+        ))
+    })
+}
+
+///Assemble and run `source`, returning the failed expectations (empty if the test passed)
+pub fn run_test(source: &str) -> Result<Vec<String>> {
+    run_test_profiled(source, false).map(|(failures, _)| failures)
+}
+
+///Same as [`run_test`], also returning the instruction counts if `profile` is true and the test assembled
+fn run_test_profiled(source: &str, profile: bool) -> Result<(Vec<String>, Option<Profile>)> {
+    let lines: Vec<String> = source.lines().map(String::from).collect();
+    let expectations = Expectations::parse(&lines)?;
+    let tape = match assemble_source(source) {
+        Ok(tape) => tape,
+        Err(diagnostics) => {
+            let failures = diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.is_error())
+                .map(|diagnostic| {
+                    let line = diagnostic
+                        .line
+                        .checked_sub(1)
+                        .and_then(|idx| lines.get(idx));
+                    diagnostic.render(line.map(|line| line.as_str()).unwrap_or_default())
+                })
+                .collect();
+            return Ok((failures, None));
+        }
+    };
+    let report = run_tape(
+        tape.bytes,
+        RunOptions {
+            max_cycles: Some(TEST_MAX_CYCLES),
+            input_lines: expectations.input.clone(),
+            profile,
+            files: Some(MemoryFileProvider::default()),
+            ..RunOptions::default()
+        },
+    )?;
+    Ok((expectations.check(&report)?, report.profile))
+}
+
+///Test files in `paths`, directories are searched recursively for files ending in [`TEST_SUFFIX`]
+pub fn find_tests(paths: &[&str]) -> Result<Vec<PathBuf>> {
+    let mut output = vec![];
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            search_dir(path, &mut output)?;
+        } else if path.is_file() {
+            output.push(path.to_path_buf());
+        } else {
+            return Err(Error::msg(format!(
+                "File does not exist: {}",
+                path.display()
+            )));
+        }
+    }
+    output.sort();
+    output.dedup();
+    Ok(output)
+}
+
+fn search_dir(dir: &Path, output: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            search_dir(&path, output)?;
+        } else if path.to_string_lossy().ends_with(TEST_SUFFIX) {
+            output.push(path);
+        }
+    }
+    Ok(())
+}
+
+///Run every test in `paths`, adding the executed instructions of each to `coverage_path` if set
+pub fn start(paths: Vec<&str>, coverage_path: Option<&str>) -> Result<()> {
+    let paths = if paths.is_empty() { vec!["."] } else { paths };
+    let tests = find_tests(&paths)?;
+    println!("Running {} tests", tests.len());
+    let mut failed = vec![];
+    let mut coverage = match coverage_path {
+        Some(path) => Some(Coverage::read(path)?),
+        None => None,
+    };
+    for test in &tests {
+        let failures = match std::fs::read_to_string(test) {
+            Ok(source) => match run_test_profiled(&source, coverage.is_some()) {
+                Ok((failures, profile)) => {
+                    if let (Some(coverage), Some(profile)) = (&mut coverage, profile) {
+                        coverage.add(&test.to_string_lossy(), &profile);
+                    }
+                    failures
+                }
+                Err(err) => vec![err.to_string()],
+            },
+            Err(err) => vec![err.to_string()],
+        };
+        if failures.is_empty() {
+            println!("test {} ... ok", test.display());
+        } else {
+            println!("test {} ... FAILED", test.display());
+            failed.push((test, failures));
+        }
+    }
+    for (test, failures) in &failed {
+        println!("\n---- {} ----", test.display());
+        for failure in failures {
+            println!("{}", failure);
+        }
+    }
+    if let (Some(coverage), Some(path)) = (&coverage, coverage_path) {
+        coverage.save(path)?;
+    }
+    println!(
+        "\n{} passed; {} failed",
+        tests.len() - failed.len(),
+        failed.len()
+    );
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::msg(format!("{} tests failed", failed.len())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_passing() {
+        let source = "Test\n1\n.strings\nhi=Hi\n.ops\n#> Hi\n#> 5\n#= d0 5\n#= a0 x10\nprts hi\nprtln\ncpy d0 5\ncpy a0 @x10\nprt d0\n";
+        assert_eq!(run_test(source).unwrap(), Vec::<String>::new());
+
+        let source = "Test\n1\n.ops\n#< A\n#= exit halt\n#= d0 65\nrchr d0\nhalt\n";
+        assert_eq!(run_test(source).unwrap(), Vec::<String>::new());
+
+        let source = "Test\n1\n.ops\n#= exit 3\nexit 3\n";
+        assert_eq!(run_test(source).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_failing() {
+        let failures = run_test("Test\n1\n.ops\n#= d0 4\n#> 1\ncpy d0 5\nprt 2\n").unwrap();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0], "d0 is 5 but expected 4");
+        assert!(failures[1].starts_with("Output does not match (2 lines differ)"));
+
+        let failures = run_test("Test\n1\n.ops\nrchr d0\n").unwrap();
+        assert_eq!(failures, vec!["Program stopped with input_requested: "]);
+
+        let failures = run_test("Test\n1\n.ops\nexit 1\n").unwrap();
+        assert_eq!(failures, vec!["Program stopped with 1: "]);
+
+        assert_eq!(run_test("Test\n1\n.ops\nfoo\n").unwrap().len(), 1);
+        assert!(run_test("Test\n1\n.ops\n#= d9 1\nnop\n").is_err());
+        assert!(run_test("Test\n1\n.ops\n#= d0\nnop\n").is_err());
+    }
+}
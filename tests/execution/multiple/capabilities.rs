@@ -0,0 +1,91 @@
+use crate::setup;
+use tape_device::constants::code::{
+    CALL_AREG, DEBUG, FOPEN_VAL, FOPEN_VAL_STR, JMP_ADDR, JMP_AREG, PRTC_VAL, RAND_REG, RCHR_REG,
+    SAVE_STR_AREG, SEED_REG, TIME,
+};
+use tape_device::constants::hardware::{REG_A0, REG_ACC};
+use tape_device::device::comm::Output;
+use tape_device::device::internals::{Capabilities, RunResult};
+
+fn assert_denied(ops: Vec<u8>, deny: &str, family: &str) {
+    let mut device = setup(ops);
+    device.set_capabilities(Capabilities::from_deny_list(deny).unwrap());
+
+    assert_eq!(device.step(true), RunResult::ProgError, "{}", deny);
+    let denied = device.output().iter().any(|output| match output {
+        Output::OutputErr(msg) => msg.contains(&format!("'{}' capability", family)),
+        _ => false,
+    });
+    assert!(denied, "No capability error for {}", deny);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_deny_file() {
+    assert_denied(vec![FOPEN_VAL, 0], "file", "file");
+    assert_denied(vec![SAVE_STR_AREG, 0, 0, REG_A0], "file", "file");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_deny_path() {
+    assert_denied(vec![FOPEN_VAL_STR, 0, 0, 0], "path", "path");
+    assert_denied(vec![FOPEN_VAL_STR, 0, 0, 0], "file", "file");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_deny_input() {
+    assert_denied(vec![RCHR_REG, REG_ACC], "input", "input");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_deny_time() {
+    assert_denied(vec![TIME], "time", "time");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_deny_rand() {
+    assert_denied(vec![RAND_REG, REG_ACC], "rand", "rand");
+    assert_denied(vec![SEED_REG, REG_ACC], "rand", "rand");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_deny_debug() {
+    assert_denied(vec![DEBUG], "debug", "debug");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_deny_exec() {
+    assert_denied(vec![JMP_AREG, REG_A0], "exec", "exec");
+    assert_denied(vec![CALL_AREG, REG_A0], "file, exec", "exec");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_allowed_when_other_families_denied() {
+    let ops = vec![
+        PRTC_VAL, b'a',
+        JMP_ADDR, 0, 5,
+        TIME,
+    ];
+    let mut device = setup(ops);
+    device.set_capabilities(Capabilities::from_deny_list("file,input,rand,debug,exec").unwrap());
+
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.step(true), RunResult::Pause);
+}
+
+#[test]
+fn test_unknown_capability() {
+    assert!(Capabilities::from_deny_list("file,network").is_err());
+    assert_eq!(
+        Capabilities::from_deny_list("").unwrap(),
+        Capabilities::all()
+    );
+}
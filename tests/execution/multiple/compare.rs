@@ -1,15 +1,18 @@
 use crate::{assert_no_output, assert_step_device, setup};
 use tape_device::constants::code::{
     CMP_AREG_ADDR, CMP_AREG_AREG, CMP_AREG_REG_REG, CMP_REG_AREG, CMP_REG_REG, CMP_REG_REG_AREG,
-    CMP_REG_VAL,
+    CMP_REG_VAL, HALT, JE_ADDR, JG_ADDR, JL_ADDR, JNE_ADDR,
 };
 use tape_device::constants::compare::{EQUAL, GREATER, LESSER};
-use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1, REG_D2, REG_D3};
+use tape_device::constants::hardware::{
+    FLAG_CARRY, FLAG_NEGATIVE, FLAG_ZERO, REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1, REG_D2, REG_D3,
+};
+use tape_device::constants::system::ACC_COMPARE_PRG_VERSION;
 use tape_device::device::Dump;
 
 #[test]
 #[rustfmt::skip]
-fn test_multiple_compare_ops() {
+fn test_multiple_compare_ops_acc() {
     let ops = vec![
         CMP_REG_VAL, REG_D0, 10,
         CMP_REG_VAL, REG_D1, 10,
@@ -30,6 +33,7 @@ fn test_multiple_compare_ops() {
         CMP_REG_VAL, REG_ACC, 10,
     ];
     let mut device = setup(ops);
+    device.set_tape_version(ACC_COMPARE_PRG_VERSION);
     device.tape_data = vec![1, 1, 50];
     device.data_reg[1] = 20;
     device.data_reg[2] = 10;
@@ -56,3 +60,68 @@ fn test_multiple_compare_ops() {
 
     assert_no_output(device);
 }
+
+#[test]
+#[rustfmt::skip]
+fn test_multiple_compare_ops_flags() {
+    let ops = vec![
+        CMP_REG_VAL, REG_D0, 10,
+        CMP_REG_VAL, REG_D1, 10,
+        CMP_REG_VAL, REG_D2, 10,
+        CMP_REG_VAL, REG_D1, 200,
+        CMP_AREG_ADDR, REG_A0, 0, 200,
+        CMP_AREG_ADDR, REG_A0, 1, 244,
+        CMP_AREG_ADDR, REG_A0, 4, 0,
+        CMP_REG_AREG, REG_D1, REG_A1,
+    ];
+    let mut device = setup(ops);
+    device.tape_data = vec![1, 1, 50];
+    device.acc = 7;
+    device.data_reg[1] = 20;
+    device.data_reg[2] = 10;
+    device.addr_reg[0] = 500;
+    device.addr_reg[1] = 2;
+
+    assert_step_device("CMP D0 10", &mut device, Dump { pc: 3, acc: 7, data_reg: [0, 20, 10, 0], addr_reg: [500, 2], flags: FLAG_CARRY | FLAG_NEGATIVE, ..Default::default() });
+    assert_step_device("CMP D1 10", &mut device, Dump { pc: 6, acc: 7, data_reg: [0, 20, 10, 0], addr_reg: [500, 2], flags: 0, ..Default::default() });
+    assert_step_device("CMP D2 10", &mut device, Dump { pc: 9, acc: 7, data_reg: [0, 20, 10, 0], addr_reg: [500, 2], flags: FLAG_ZERO, ..Default::default() });
+    assert_step_device("CMP D1 200", &mut device, Dump { pc: 12, acc: 7, data_reg: [0, 20, 10, 0], addr_reg: [500, 2], flags: FLAG_CARRY, ..Default::default() });
+    assert_step_device("CMP A0 @200", &mut device, Dump { pc: 16, acc: 7, data_reg: [0, 20, 10, 0], addr_reg: [500, 2], flags: 0, ..Default::default() });
+    assert_step_device("CMP A0 @500", &mut device, Dump { pc: 20, acc: 7, data_reg: [0, 20, 10, 0], addr_reg: [500, 2], flags: FLAG_ZERO, ..Default::default() });
+    assert_step_device("CMP A0 @1024", &mut device, Dump { pc: 24, acc: 7, data_reg: [0, 20, 10, 0], addr_reg: [500, 2], flags: FLAG_CARRY | FLAG_NEGATIVE, ..Default::default() });
+    assert_step_device("CMP D1 A1", &mut device, Dump { pc: 27, acc: 7, data_reg: [0, 20, 10, 0], addr_reg: [500, 2], flags: FLAG_CARRY | FLAG_NEGATIVE, ..Default::default() });
+
+    assert_no_output(device);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_conditional_jumps_read_flags() {
+    let ops = vec![
+        CMP_REG_VAL, REG_D0, 5,
+        JE_ADDR, 0, 0,
+        JG_ADDR, 0, 0,
+        JL_ADDR, 0, 13,
+        HALT,
+        CMP_REG_VAL, REG_D0, 3,
+        JNE_ADDR, 0, 0,
+        JL_ADDR, 0, 0,
+        JG_ADDR, 0, 0,
+        JE_ADDR, 0, 29,
+        HALT,
+    ];
+    let mut device = setup(ops);
+    device.data_reg[0] = 3;
+
+    assert_step_device("CMP D0 5", &mut device, Dump { pc: 3, data_reg: [3, 0, 0, 0], flags: FLAG_CARRY | FLAG_NEGATIVE, ..Default::default() });
+    assert_step_device("JE", &mut device, Dump { pc: 6, data_reg: [3, 0, 0, 0], flags: FLAG_CARRY | FLAG_NEGATIVE, ..Default::default() });
+    assert_step_device("JG", &mut device, Dump { pc: 9, data_reg: [3, 0, 0, 0], flags: FLAG_CARRY | FLAG_NEGATIVE, ..Default::default() });
+    assert_step_device("JL", &mut device, Dump { pc: 13, data_reg: [3, 0, 0, 0], flags: FLAG_CARRY | FLAG_NEGATIVE, ..Default::default() });
+    assert_step_device("CMP D0 3", &mut device, Dump { pc: 16, data_reg: [3, 0, 0, 0], flags: FLAG_ZERO, ..Default::default() });
+    assert_step_device("JNE", &mut device, Dump { pc: 19, data_reg: [3, 0, 0, 0], flags: FLAG_ZERO, ..Default::default() });
+    assert_step_device("JL", &mut device, Dump { pc: 22, data_reg: [3, 0, 0, 0], flags: FLAG_ZERO, ..Default::default() });
+    assert_step_device("JG", &mut device, Dump { pc: 25, data_reg: [3, 0, 0, 0], flags: FLAG_ZERO, ..Default::default() });
+    assert_step_device("JE", &mut device, Dump { pc: 29, data_reg: [3, 0, 0, 0], flags: FLAG_ZERO, ..Default::default() });
+
+    assert_no_output(device);
+}
@@ -1,7 +1,7 @@
 use crate::{assert_no_output, assert_step_device, setup};
 use tape_device::constants::code::{
     CPY_AREG_ADDR, CPY_AREG_AREG, CPY_AREG_REG_REG, CPY_REG_AREG, CPY_REG_REG, CPY_REG_REG_AREG,
-    CPY_REG_VAL,
+    CPY_REG_VAL, LDI_AREG_VAL,
 };
 use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1, REG_D2, REG_D3};
 use tape_device::device::Dump;
@@ -35,3 +35,18 @@ fn test_multiple_copy_ops() {
 
     assert_no_output(device);
 }
+
+#[test]
+#[rustfmt::skip]
+fn test_ldi() {
+    let ops = vec![
+        LDI_AREG_VAL, REG_A0, 0x12, 0x34,
+        LDI_AREG_VAL, REG_A1, 0xFF, 0xFF,
+    ];
+    let mut device = setup(ops);
+
+    assert_step_device("LDI A0 x1234", &mut device, Dump { pc: 4, addr_reg: [0x1234, 0], ..Default::default() });
+    assert_step_device("LDI A1 65535", &mut device, Dump { pc: 8, addr_reg: [0x1234, 65535], ..Default::default() });
+
+    assert_no_output(device);
+}
@@ -2,12 +2,18 @@ use crate::{assert_memory, assert_no_output, assert_step_device};
 use std::fs::{remove_file, File};
 use std::io::Write;
 use tape_device::constants::code::{
-    FCHK_REG_ADDR, FCHK_REG_AREG, FCHK_VAL_ADDR, FCHK_VAL_AREG, FILER_REG_ADDR, FILER_REG_AREG,
-    FILER_VAL_ADDR, FILEW_REG_REG, FILEW_REG_VAL, FILEW_VAL_ADDR, FILEW_VAL_REG, FILEW_VAL_VAL,
-    FOPEN_REG, FOPEN_VAL, FSEEK_REG, FSEEK_VAL, FSKIP_VAL_VAL, HALT, PUSH_VAL,
+    FCHK_REG_ADDR, FCHK_REG_AREG, FCHK_VAL_ADDR, FCHK_VAL_AREG, FDEL_REG, FDEL_VAL, FILER_REG_ADDR,
+    FILER_REG_AREG, FILER_REG_AREG_AREG, FILER_VAL_ADDR, FILEW_REG_REG, FILEW_REG_VAL,
+    FILEW_VAL_ADDR, FILEW_VAL_AREG_AREG, FILEW_VAL_REG, FILEW_VAL_VAL, FLIST_ADDR, FLIST_AREG,
+    FOPEN_REG, FOPEN_REG_AREG, FOPEN_VAL, FOPEN_VAL_STR, FSEEK_REG, FSEEK_VAL, FSKIP_VAL_VAL,
+    FTRUNC_REG, FTRUNC_VAL, HALT, LOADKV_STR_AREG, PUSH_VAL, SAVE_STR_AREG,
 };
-use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1, REG_D3};
-use tape_device::device::internals::Device;
+use tape_device::constants::hardware::{
+    REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1, REG_D3, STDIN_FILE, STDOUT_FILE,
+};
+use tape_device::device::comm::Output;
+use tape_device::device::files::MemoryFileProvider;
+use tape_device::device::internals::{Device, RunResult};
 use tape_device::device::Dump;
 use tempfile::tempdir;
 
@@ -114,8 +120,250 @@ fn test_multiple_test_ops() {
     assert_no_output(device);
 }
 
+#[test]
+#[rustfmt::skip]
+fn test_open_by_name() {
+    let path = setup_test_file("-named");
+    let mut strings = vec![path.len() as u8];
+    strings.extend_from_slice(path.as_bytes());
+
+    let ops = vec![
+        FOPEN_VAL_STR, 0, 0, 0,
+        FOPEN_REG_AREG, REG_ACC, REG_A0,
+    ];
+
+    let mut device = Device::new(ops, strings, vec![], vec![]);
+    device.mem[..path.len()].copy_from_slice(path.as_bytes());
+
+    assert_step_device("FOPEN 0 path", &mut device, Dump { pc: 4, data_reg: [0, 0, 0, 6], ..Default::default() });
+    device.data_reg = [0; 4];
+    device.acc = path.len() as u8;
+    assert_step_device("FOPEN ACC A0", &mut device, Dump { pc: 7, acc: path.len() as u8, data_reg: [0, 0, 0, 6], ..Default::default() });
+
+    assert_no_output(device);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_open_by_name_allowed_paths() {
+    let path = setup_test_file("-allowed");
+    let mut strings = vec![path.len() as u8];
+    strings.extend_from_slice(path.as_bytes());
+    let dir = std::path::Path::new(&path).parent().unwrap().canonicalize().unwrap();
+    let other_dir = tempdir().unwrap().keep().canonicalize().unwrap();
+
+    let mut device = Device::new(vec![FOPEN_VAL_STR, 0, 0, 0], strings.clone(), vec![], vec![]);
+    device.set_allowed_paths(vec![other_dir]);
+    assert_eq!(device.step(true), RunResult::ProgError);
+    let rejected = device.output().iter().any(|output| match output {
+        Output::OutputErr(msg) => msg.contains("is not in an allowed path"),
+        _ => false,
+    });
+    assert!(rejected, "No allowed path error");
+
+    let mut device = Device::new(vec![FOPEN_VAL_STR, 0, 0, 0], strings, vec![], vec![]);
+    device.set_allowed_paths(vec![dir]);
+    assert_step_device("FOPEN 0 path", &mut device, Dump { pc: 4, data_reg: [0, 0, 0, 6], ..Default::default() });
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_save_and_load() {
+    let strings = vec![5, b's', b'c', b'o', b'r', b'e', 4, b'n', b'a', b'm', b'e'];
+    let ops = vec![
+        LOADKV_STR_AREG, 0, 0, REG_A0,
+        SAVE_STR_AREG, 0, 0, REG_A0,
+        LOADKV_STR_AREG, 0, 0, REG_A1,
+    ];
+    let provider = MemoryFileProvider::default();
+    let mut device = Device::new(ops.clone(), strings.clone(), vec![], vec![]);
+    device.set_file_provider(Box::new(provider.clone()));
+    device.set_save_file("game.sav".into());
+    device.mem[..3].copy_from_slice(&[7, 8, 9]);
+    device.addr_reg = [0, 10];
+    device.acc = 5;
+
+    assert_step_device("LOADKV score A0", &mut device, Dump { pc: 4, addr_reg: [0, 10], ..Default::default() });
+    device.acc = 3;
+    assert_step_device("SAVE score A0", &mut device, Dump { pc: 8, acc: 3, addr_reg: [0, 10], ..Default::default() });
+    assert_eq!(provider.get("game.sav"), Some(vec![5, b's', b'c', b'o', b'r', b'e', 3, 7, 8, 9]));
+    device.acc = 0;
+    assert_step_device("LOADKV score A1", &mut device, Dump { pc: 12, acc: 3, addr_reg: [0, 10], ..Default::default() });
+    assert_memory(&device, 10, &[7, 8, 9]);
+
+    //values are kept between runs
+    let mut device = Device::new(vec![LOADKV_STR_AREG, 0, 0, REG_A0, LOADKV_STR_AREG, 0, 6, REG_A0], strings.clone(), vec![], vec![]);
+    device.set_file_provider(Box::new(provider.clone()));
+    device.set_save_file("game.sav".into());
+    assert_step_device("LOADKV score A0", &mut device, Dump { pc: 4, acc: 3, ..Default::default() });
+    assert_memory(&device, 0, &[7, 8, 9]);
+    assert_step_device("LOADKV name A0", &mut device, Dump { pc: 8, ..Default::default() });
+
+    let mut device = Device::new(ops, strings, vec![], vec![]);
+    device.set_file_provider(Box::new(provider));
+    assert_eq!(device.step(true), RunResult::ProgError);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_truncate_and_delete() {
+    let path = setup_test_file("-trunc");
+
+    let ops = vec![
+        FOPEN_VAL, 0,
+        FSKIP_VAL_VAL, 0, 2,
+        FTRUNC_VAL, 0,
+        FTRUNC_REG, REG_D0,
+        FDEL_REG, REG_D0,
+        FOPEN_VAL, 0,
+        FDEL_VAL, 0,
+        FDEL_VAL, 0,
+    ];
+
+    let mut device = Device::new(ops, vec![], vec![], vec![path.clone()]);
+
+    assert_step_device("FOPEN 0", &mut device, Dump { pc: 2, data_reg: [0, 0, 0, 6], ..Default::default() });
+    assert_step_device("FSKIP 0 2", &mut device, Dump { pc: 5, acc: 2, data_reg: [0, 0, 0, 6], ..Default::default() });
+    assert_step_device("FTRUNC 0", &mut device, Dump { pc: 7, acc: 2, data_reg: [0, 0, 0, 6], ..Default::default() });
+    assert_eq!(std::fs::read(&path).unwrap(), vec![5, 6]);
+    device.data_reg = [0; 4];
+    assert_step_device("FTRUNC D0", &mut device, Dump { pc: 9, acc: 2, ..Default::default() });
+    assert_eq!(std::fs::read(&path).unwrap(), vec![5, 6]);
+    assert_step_device("FDEL D0", &mut device, Dump { pc: 11, acc: 2, ..Default::default() });
+    assert!(!std::path::Path::new(&path).exists());
+    assert_step_device("FOPEN 0", &mut device, Dump { pc: 13, acc: 2, ..Default::default() });
+    assert!(std::path::Path::new(&path).exists());
+    assert_step_device("FDEL 0", &mut device, Dump { pc: 15, acc: 2, ..Default::default() });
+    assert!(!std::path::Path::new(&path).exists());
+    assert_eq!(device.step(true), RunResult::ProgError);
+    let not_open = device.output().iter().any(|output| match output {
+        Output::OutputErr(msg) => msg.contains("File 0 not open"),
+        _ => false,
+    });
+    assert!(not_open, "No file not open error");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_list_files() {
+    let ops = vec![
+        FLIST_ADDR, 0, 0,
+        FLIST_AREG, REG_A0,
+        FLIST_ADDR, 255, 250,
+    ];
+
+    let mut device = Device::new(ops, vec![], vec![], vec![String::from("a.bin"), String::from("save")]);
+    device.addr_reg = [20, 0];
+
+    assert_step_device("FLIST @0", &mut device, Dump { pc: 3, acc: 2, addr_reg: [20, 0], ..Default::default() });
+    assert_memory(&device, 0, &[2, 5, b'a', b'.', b'b', b'i', b'n', 4, b's', b'a', b'v', b'e', 0]);
+    assert_step_device("FLIST A0", &mut device, Dump { pc: 5, acc: 2, addr_reg: [20, 0], ..Default::default() });
+    assert_memory(&device, 20, &[2, 5, b'a', b'.', b'b', b'i', b'n', 4, b's', b'a', b'v', b'e', 0]);
+    assert_eq!(device.step(true), RunResult::ProgError);
+
+    let mut device = Device::new(vec![FLIST_ADDR, 0, 0], vec![], vec![], vec![]);
+    device.mem[1] = 9;
+    device.acc = 3;
+    assert_step_device("FLIST @0", &mut device, Dump { pc: 3, ..Default::default() });
+    assert_memory(&device, 0, &[0, 9]);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_stdin_stdout() {
+    let ops = vec![
+        FILER_VAL_ADDR, STDIN_FILE, 0, 0,
+        FILEW_VAL_ADDR, STDOUT_FILE, 0, 0,
+        FILEW_VAL_VAL, STDOUT_FILE, b'!',
+        FCHK_VAL_ADDR, STDIN_FILE, 0, 15,
+        FILER_VAL_ADDR, STDIN_FILE, 0, 0,
+    ];
+
+    let mut device = Device::new(ops, vec![], vec![], vec![]);
+    device.acc = 4;
+
+    assert_eq!(device.step(true), RunResult::StdinRequested);
+    assert_eq!(device.pc, 0);
+    device.stdin_buffer.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+    assert_step_device("FILER 254 @0", &mut device, Dump { pc: 4, acc: 4, ..Default::default() });
+    assert_memory(&device, 0, &[1, 2, 3, 4, 0]);
+    assert_eq!(device.stdin_buffer, vec![5, 6]);
+    device.acc = 3;
+    assert_step_device("FILEW 255 @0", &mut device, Dump { pc: 8, acc: 3, ..Default::default() });
+    assert_step_device("FILEW 255 '!'", &mut device, Dump { pc: 11, acc: 1, ..Default::default() });
+    assert_step_device("FCHK 254 @15", &mut device, Dump { pc: 15, acc: 1, ..Default::default() });
+    device.acc = 10;
+    device.close_stdin();
+    assert_step_device("FILER 254 @0", &mut device, Dump { pc: 19, acc: 2, ..Default::default() });
+    assert_memory(&device, 0, &[5, 6, 3, 4]);
+
+    let written: Vec<u8> = device.output().iter().flat_map(|output| match output {
+        Output::OutputBinary(bytes) => bytes.clone(),
+        _ => vec![],
+    }).collect();
+    assert_eq!(written, vec![1, 2, 3, b'!']);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_block_read_write() {
+    let path = setup_test_file("-block");
+
+    let ops = vec![
+        FOPEN_VAL, 0,
+        FILEW_VAL_AREG_AREG, 0, REG_A0, REG_A1,
+        FSEEK_VAL, 0,
+        FILER_REG_AREG_AREG, REG_D0, REG_A0, REG_A1,
+    ];
+
+    let mut device = Device::new(ops, vec![], vec![], vec![path.clone()]);
+    let content: Vec<u8> = (0..300).map(|i| i as u8).collect();
+    device.mem[0x100..0x100 + 300].copy_from_slice(&content);
+    device.addr_reg = [0x100, 300];
+
+    assert_step_device("FOPEN 0", &mut device, Dump { pc: 2, data_reg: [0, 0, 0, 6], addr_reg: [0x100, 300], ..Default::default() });
+    assert_step_device("FILEW 0 A0 A1", &mut device, Dump { pc: 6, data_reg: [0, 0, 1, 44], addr_reg: [0x100, 300], ..Default::default() });
+    device.data_reg = [0; 4];
+    assert_step_device("FSEEK 0", &mut device, Dump { pc: 8, addr_reg: [0x100, 300], ..Default::default() });
+    assert_eq!(std::fs::read(&path).unwrap(), content);
+    device.addr_reg = [0x1000, 1000];
+    assert_step_device("FILER D0 A0 A1", &mut device, Dump { pc: 12, data_reg: [0, 0, 1, 44], addr_reg: [0x1000, 1000], ..Default::default() });
+    assert_memory(&device, 0x1000, &content);
+    assert_memory(&device, 0x1000 + 300, &[0]);
+
+    assert_no_output(device);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_snapshot_file_cursor() {
+    let path = setup_test_file("-snapshot");
+
+    let ops = vec![
+        FOPEN_VAL, 0,
+        FSKIP_VAL_VAL, 0, 2,
+        FSEEK_VAL, 0,
+    ];
+
+    let mut device = Device::new(ops, vec![], vec![], vec![path]);
+
+    assert_step_device("FOPEN 0", &mut device, Dump { pc: 2, data_reg: [0, 0, 0, 6], ..Default::default() });
+    assert_step_device("FSKIP 0 2", &mut device, Dump { pc: 5, acc: 2, data_reg: [0, 0, 0, 6], ..Default::default() });
+    let state = device.snapshot().unwrap();
+    assert_eq!(state.files[0].as_ref().unwrap().cursor, 2);
+
+    assert_step_device("FSEEK 0", &mut device, Dump { pc: 7, acc: 2, data_reg: [0, 0, 0, 6], ..Default::default() });
+    assert_eq!(device.snapshot().unwrap().files[0].as_ref().unwrap().cursor, 6);
+
+    device.restore(state).unwrap();
+    assert_eq!(device.dump(), Dump { pc: 5, acc: 2, data_reg: [0, 0, 0, 6], ..Default::default() });
+    assert_eq!(device.snapshot().unwrap().files[0].as_ref().unwrap().cursor, 2);
+
+    assert_no_output(device);
+}
+
 fn setup_test_file(suffix: &str) -> String {
-    let mut path = tempdir().unwrap().into_path();
+    let mut path = tempdir().unwrap().keep();
     path.push(format!("tape-device-test-file{}.test.bin", suffix));
 
     if path.exists() {
@@ -127,3 +375,25 @@ fn setup_test_file(suffix: &str) -> String {
 
     path.to_str().unwrap().to_owned()
 }
+
+#[test]
+#[rustfmt::skip]
+fn test_unopened_file_numbers() {
+    let ops = [
+        vec![FSEEK_VAL, 5],
+        vec![FILER_VAL_ADDR, 5, 0, 0],
+        vec![FILEW_VAL_ADDR, 5, 0, 0],
+        vec![FILEW_VAL_VAL, 5, 1],
+        vec![FSKIP_VAL_VAL, 5, 1],
+    ];
+    for ops in ops {
+        let mut device = Device::new(ops.clone(), vec![], vec![], vec![]);
+        device.acc = 1;
+        assert_eq!(device.step(true), RunResult::ProgError, "{:?}", ops);
+        let reported = device.output().iter().any(|output| match output {
+            Output::OutputErr(text) => text.contains("File 5"),
+            _ => false,
+        });
+        assert!(reported, "No file error for {:?}", ops);
+    }
+}
@@ -1,7 +1,11 @@
 use crate::{assert_memory, assert_no_output, assert_step_device, setup};
-use tape_device::constants::code::{HALT, IPOLL_ADDR, IPOLL_AREG, RCHR_REG, RSTR_ADDR, RSTR_AREG};
-use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC};
+use tape_device::constants::code::{
+    ARGS_ADDR, ARGS_AREG, HALT, IPOLL_ADDR, IPOLL_AREG, RCHR_REG, RECV_REG, RSTR_ADDR, RSTR_AREG,
+    SEND_REG, SEND_VAL,
+};
+use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0};
 use tape_device::device::internals::RunResult;
+use tape_device::device::protocol::InputKind;
 use tape_device::device::Dump;
 
 #[test]
@@ -22,37 +26,89 @@ fn test_input() {
 
     assert_step_device("IPOLL @xFFFF", &mut device, Dump { pc: 3, ..Default::default() });
 
+    assert_eq!(device.input_pending(), None);
     assert_eq!(device.step(true), RunResult::CharInputRequested, "RCHR ACC");
     assert_eq!(device.dump(), Dump { pc: 3, ..Default::default() });
-    device.keyboard_buffer = vec![b'a'];
+    assert_eq!(device.input_pending(), Some(InputKind::Char));
+    device.push_char(b'a');
     assert_step_device("RCHR ACC", &mut device, Dump { pc: 5, acc: 97, ..Default::default() });
-    assert_eq!(device.keyboard_buffer, Vec::<u8>::new());
+    assert!(device.keyboard_buffer().is_empty());
+    assert_eq!(device.input_pending(), None);
 
     assert_eq!(device.step(true), RunResult::StringInputRequested, "RSTR A0");
     assert_eq!(device.dump(), Dump { pc: 5, acc: 97, ..Default::default() });
-    assert_eq!(device.keyboard_buffer, Vec::<u8>::new());
+    assert!(device.keyboard_buffer().is_empty());
+    assert_eq!(device.input_pending(), Some(InputKind::String));
 
-    device.keyboard_buffer = vec![b'H', b'i'];
+    device.push_string("Hi");
     assert_step_device("RSTR A0", &mut device, Dump { pc: 7, acc: 2, ..Default::default() });
-    assert_eq!(device.keyboard_buffer, Vec::<u8>::new());
-    assert_memory(&device, 0, &[b'H', b'i']);
+    assert!(device.keyboard_buffer().is_empty());
+    assert_memory(&device, 0, b"Hi");
 
     device.addr_reg[1] = 16;
-    device.keyboard_buffer = vec![b'T', b'e', b's', b't'];
+    device.push_string("Test");
     assert_step_device("IPOLL @11", &mut device, Dump { pc: 11, acc: 2, addr_reg: [0, 16], ..Default::default() });
     assert_step_device("RSTR A1", &mut device, Dump { pc: 13, acc: 4, addr_reg: [0, 16], ..Default::default() });
-    assert_eq!(device.keyboard_buffer, Vec::<u8>::new());
-    assert_memory(&device, 0, &[b'H', b'i']);
-    assert_memory(&device, 16, &[b'T', b'e', b's', b't']);
+    assert!(device.keyboard_buffer().is_empty());
+    assert_memory(&device, 0, b"Hi");
+    assert_memory(&device, 16, b"Test");
 
-    device.keyboard_buffer = vec![b'F', b'i', b'n'];
+    device.push_string("Fin");
     assert_step_device("IPOLL A1", &mut device, Dump { pc: 16, acc: 4, addr_reg: [0, 16], ..Default::default() });
     assert_step_device("RSTR @100", &mut device, Dump { pc: 19, acc: 3, addr_reg: [0, 16], ..Default::default() });
-    assert_eq!(device.keyboard_buffer, Vec::<u8>::new());
-    assert_memory(&device, 0, &[b'H', b'i']);
-    assert_memory(&device, 16, &[b'T', b'e', b's', b't']);
-    assert_memory(&device, 100, &[b'F', b'i', b'n']);
+    assert!(device.keyboard_buffer().is_empty());
+    assert_memory(&device, 0, b"Hi");
+    assert_memory(&device, 16, b"Test");
+    assert_memory(&device, 100, b"Fin");
 
 
     assert_no_output(device);
 }
+
+#[test]
+#[rustfmt::skip]
+fn test_args() {
+    let ops = vec![
+        ARGS_ADDR, 0, 10,
+        ARGS_AREG, REG_A0,
+    ];
+    let mut device = setup(ops);
+    device.set_args(vec![String::from("ab"), String::new(), String::from("xyz")]);
+    device.addr_reg[0] = 100;
+
+    assert_step_device("ARGS @10", &mut device, Dump { pc: 3, acc: 3, addr_reg: [100, 0], ..Default::default() });
+    assert_memory(&device, 10, &[2, b'a', b'b', 0, 3, b'x', b'y', b'z']);
+    assert_step_device("ARGS A0", &mut device, Dump { pc: 5, acc: 3, addr_reg: [100, 0], ..Default::default() });
+    assert_memory(&device, 100, &[2, b'a', b'b', 0, 3, b'x', b'y', b'z']);
+
+    let mut device = setup(vec![ARGS_ADDR, 0, 10]);
+    assert_step_device("ARGS @10 (none)", &mut device, Dump { pc: 3, ..Default::default() });
+
+    let mut device = setup(vec![ARGS_ADDR, 0, 10]);
+    device.set_args(vec!["a".repeat(256)]);
+    assert_eq!(device.step(true), RunResult::ProgError);
+
+    let mut device = setup(vec![ARGS_ADDR, 0xFF, 0xFD]);
+    device.set_args(vec![String::from("abc")]);
+    assert_eq!(device.step(true), RunResult::ProgError);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_serial() {
+    let ops = vec![
+        SEND_VAL, 7,
+        SEND_REG, REG_ACC,
+        RECV_REG, REG_D0,
+    ];
+    let mut device = setup(ops);
+    device.acc = 3;
+
+    assert_step_device("SEND 7", &mut device, Dump { pc: 2, acc: 3, ..Default::default() });
+    assert_step_device("SEND ACC", &mut device, Dump { pc: 4, acc: 3, ..Default::default() });
+    assert_eq!(device.serial_out, vec![7, 3]);
+    assert_eq!(device.step(true), RunResult::SerialRequested);
+    assert_eq!(device.dump().pc, 4);
+    device.serial_in.push(9);
+    assert_step_device("RECV D0", &mut device, Dump { pc: 6, acc: 3, data_reg: [9, 0, 0, 0], ..Default::default() });
+}
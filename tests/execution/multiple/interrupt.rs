@@ -0,0 +1,60 @@
+use crate::assert_step_device;
+use crate::setup;
+use tape_device::constants::code::{
+    IMASK_VAL, INTH_VAL_ADDR, ITIMER_VAL, JMP_ADDR, NOP, RCHR_REG, RETI,
+};
+use tape_device::constants::hardware::{INT_INPUT, INT_MASK_ALL, INT_TIMER, REG_D0};
+use tape_device::device::internals::{Determinism, RunResult};
+use tape_device::device::Dump;
+
+#[test]
+#[rustfmt::skip]
+fn test_input_interrupt() {
+    let ops = vec![
+        INTH_VAL_ADDR, INT_INPUT, 0, 11,
+        IMASK_VAL, INT_MASK_ALL,
+        IMASK_VAL, 0,
+        JMP_ADDR, 0, 8,
+        RCHR_REG, REG_D0,
+        RETI,
+    ];
+    let mut device = setup(ops);
+
+    assert_step_device("INTH 1 handler", &mut device, Dump { pc: 4, ..Default::default() });
+    assert_step_device("IMASK 3", &mut device, Dump { pc: 6, ..Default::default() });
+    device.push_char(65);
+    assert_step_device("IMASK 0", &mut device, Dump { pc: 8, ..Default::default() });
+    assert_step_device("(interrupt) RCHR D0", &mut device, Dump { pc: 13, data_reg: [65, 0, 0, 0], sp: 65531, fp: 65531, ..Default::default() });
+    assert_step_device("RETI", &mut device, Dump { pc: 8, data_reg: [65, 0, 0, 0], ..Default::default() });
+    assert_step_device("JMP loop", &mut device, Dump { pc: 8, data_reg: [65, 0, 0, 0], ..Default::default() });
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_timer_interrupt() {
+    let ops = vec![
+        INTH_VAL_ADDR, INT_TIMER, 0, 10,
+        ITIMER_VAL, 3,
+        JMP_ADDR, 0, 6,
+        NOP,
+        RETI,
+    ];
+    let mut device = setup(ops);
+    device.set_determinism(Determinism { start_time: Some(0), ..Determinism::default() });
+
+    assert_step_device("INTH 0 tick", &mut device, Dump { pc: 4, ..Default::default() });
+    assert_step_device("ITIMER 3", &mut device, Dump { pc: 6, ..Default::default() });
+    assert_step_device("JMP loop", &mut device, Dump { pc: 6, ..Default::default() });
+    assert_step_device("JMP loop", &mut device, Dump { pc: 6, ..Default::default() });
+    assert_step_device("JMP loop", &mut device, Dump { pc: 6, ..Default::default() });
+    assert_step_device("(interrupt) RETI", &mut device, Dump { pc: 6, ..Default::default() });
+}
+
+#[test]
+fn test_reti_outside_handler() {
+    let mut device = setup(vec![RETI]);
+    assert_eq!(device.step(true), RunResult::ProgError);
+
+    let mut device = setup(vec![INTH_VAL_ADDR, 2, 0, 0]);
+    assert_eq!(device.step(true), RunResult::ProgError);
+}
@@ -1,7 +1,11 @@
 use crate::{assert_no_output, assert_step_device, setup};
-use tape_device::constants::code::{HALT, JE_ADDR, JMP_ADDR, JMP_AREG};
+use tape_device::constants::code::{
+    CPY_REG_VAL, HALT, INTH_VAL_ADDR, JE_ADDR, JMP_ADDR, JMP_AREG, NOP, RET,
+};
 use tape_device::constants::compare::EQUAL;
-use tape_device::constants::hardware::REG_A0;
+use tape_device::constants::hardware::{REG_A0, REG_D0};
+use tape_device::device::comm::Output;
+use tape_device::device::internals::{Device, RunResult};
 use tape_device::device::Dump;
 
 #[test]
@@ -24,3 +28,49 @@ fn test_multiple_jump_ops() {
 
     assert_no_output(device);
 }
+
+fn assert_invalid_jump(mut device: Device, pc: u16, msg: &str) {
+    assert_eq!(device.step(true), RunResult::ProgError);
+    assert_eq!(device.dump().pc, pc);
+    let reported = device.output().iter().any(|output| match output {
+        Output::OutputErr(text) => text.contains(msg),
+        _ => false,
+    });
+    assert!(reported, "No '{}' error", msg);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_invalid_jumps() {
+    let ops = vec![
+        JMP_ADDR, 0, 4,
+        CPY_REG_VAL, REG_D0, 1,
+    ];
+    assert_invalid_jump(setup(ops), 0, "Invalid jump to 0004, it's in the middle of an instruction");
+
+    let ops = vec![
+        NOP,
+        JMP_AREG, REG_A0,
+    ];
+    let mut device = setup(ops);
+    device.addr_reg = [0x100, 0];
+    assert_step_device("NOP", &mut device, Dump { pc: 1, addr_reg: [0x100, 0], ..Default::default() });
+    assert_invalid_jump(device, 1, "Invalid jump to 0100, it's outside of the program (3 bytes)");
+
+    let mut device = setup(vec![RET, HALT]);
+    device.mem[0xFFFB..].copy_from_slice(&[3, 0, 0xFF, 0xFF]);
+    device.set_registers(Dump { sp: 0xFFFB, fp: 0xFFFB, ..Default::default() });
+    assert_invalid_jump(device, 0, "Invalid jump to 0003");
+
+    assert_invalid_jump(setup(vec![INTH_VAL_ADDR, 0, 0, 1, HALT]), 0, "Invalid jump to 0001");
+
+    //patching in a shorter instruction makes the NOPs after it valid targets
+    let mut device = setup(vec![JMP_ADDR, 0, 4, CPY_REG_VAL, REG_D0, 1]);
+    device.patch_instruction(3, &[NOP]).unwrap();
+    assert_step_device("JMP @4", &mut device, Dump { pc: 4, ..Default::default() });
+
+    //the end of the program is a valid target, it stops the program
+    let mut device = setup(vec![JMP_ADDR, 0, 3]);
+    assert_step_device("JMP @3", &mut device, Dump { pc: 3, ..Default::default() });
+    assert_eq!(device.step(true), RunResult::EoF);
+}
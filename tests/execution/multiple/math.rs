@@ -1,8 +1,12 @@
 use crate::{assert_no_output, assert_step_device, setup};
 use tape_device::constants::code::{
-    ADD_REG_AREG, ADD_REG_REG, ADD_REG_VAL, DEC_REG, INC_REG, SUB_REG_AREG, SUB_REG_REG,
+    ADDC_REG_AREG, ADDC_REG_REG, ADDC_REG_VAL, ADD_REG_AREG, ADD_REG_REG, ADD_REG_VAL, CPY_REG_REG,
+    DEC_REG, INC_REG, SUBC_REG_AREG, SUBC_REG_REG, SUBC_REG_VAL, SUB_REG_AREG, SUB_REG_REG,
+    SUB_REG_VAL,
+};
+use tape_device::constants::hardware::{
+    FLAG_OVERFLOW, REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1, REG_D2, REG_D3,
 };
-use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1, REG_D3};
 use tape_device::device::Dump;
 
 #[test]
@@ -33,14 +37,57 @@ fn test_multiple_math_ops() {
     assert_step_device("ADD D3 40", &mut device, Dump { pc: 10, acc: 40, data_reg: [1, 1, 0, 0], ..Default::default() });
     assert_step_device("DEC ACC", &mut device, Dump { pc: 12, acc: 39, data_reg: [1, 1, 0, 0], ..Default::default() });
     assert_step_device("DEC ACC", &mut device, Dump { pc: 14, acc: 38, data_reg: [1, 1, 0, 0], ..Default::default() });
-    assert_step_device("DEC D3", &mut device, Dump { pc: 16, acc: 38, data_reg: [1, 1, 0, 255],  overflow: true, ..Default::default() });
+    assert_step_device("DEC D3", &mut device, Dump { pc: 16, acc: 38, data_reg: [1, 1, 0, 255],  flags: FLAG_OVERFLOW, ..Default::default() });
     assert_step_device("SUB D3 ACC", &mut device, Dump { pc: 19, acc: 217, data_reg: [1, 1, 0, 255], ..Default::default() });
     assert_step_device("INC A0", &mut device, Dump { pc: 21, acc: 217, data_reg: [1, 1, 0, 255], addr_reg: [1,0], ..Default::default() });
     assert_step_device("INC A0", &mut device, Dump { pc: 23, acc: 217, data_reg: [1, 1, 0, 255], addr_reg: [2,0], ..Default::default() });
-    assert_step_device("DEC A1", &mut device, Dump { pc: 25, acc: 217, data_reg: [1, 1, 0, 255], addr_reg: [2,65535], overflow: true, ..Default::default() });
+    assert_step_device("DEC A1", &mut device, Dump { pc: 25, acc: 217, data_reg: [1, 1, 0, 255], addr_reg: [2,65535], flags: FLAG_OVERFLOW, ..Default::default() });
     assert_step_device("SUB ACC ACC", &mut device, Dump { pc: 28, acc: 0, data_reg: [1, 1, 0, 255], addr_reg: [2,65535], ..Default::default() });
     assert_step_device("ADD ACC A0", &mut device, Dump { pc: 31, acc: 4, data_reg: [1, 1, 0, 255], addr_reg: [2,65535], ..Default::default() });
     assert_step_device("SUB D3 A0", &mut device, Dump { pc: 34, acc: 251, data_reg: [1, 1, 0, 255], addr_reg: [2,65535], ..Default::default() });
 
     assert_no_output(device);
 }
+
+#[test]
+#[rustfmt::skip]
+fn test_carry_ops() {
+    //0x01FF + 0x0001, then 0x0200 - 0x0001
+    let ops = vec![
+        ADD_REG_REG, REG_D1, REG_D3,
+        CPY_REG_REG, REG_D1, REG_ACC,
+        ADDC_REG_REG, REG_D0, REG_D2,
+        CPY_REG_REG, REG_D0, REG_ACC,
+        SUB_REG_REG, REG_D1, REG_D3,
+        CPY_REG_REG, REG_D1, REG_ACC,
+        SUBC_REG_REG, REG_D0, REG_D2,
+        CPY_REG_REG, REG_D0, REG_ACC,
+        ADDC_REG_VAL, REG_D1, 255,
+        ADDC_REG_AREG, REG_D0, REG_A0,
+        SUBC_REG_AREG, REG_D0, REG_A0,
+    ];
+    let mut device = setup(ops);
+    device.data_reg = [1, 255, 0, 1];
+    device.tape_data = vec![2];
+
+    assert_step_device("ADD D1 D3", &mut device, Dump { pc: 3, acc: 0, data_reg: [1, 255, 0, 1], flags: FLAG_OVERFLOW, ..Default::default() });
+    assert_step_device("CPY D1 ACC", &mut device, Dump { pc: 6, acc: 0, data_reg: [1, 0, 0, 1], flags: FLAG_OVERFLOW, ..Default::default() });
+    assert_step_device("ADDC D0 D2", &mut device, Dump { pc: 9, acc: 2, data_reg: [1, 0, 0, 1], ..Default::default() });
+    assert_step_device("CPY D0 ACC", &mut device, Dump { pc: 12, acc: 2, data_reg: [2, 0, 0, 1], ..Default::default() });
+    assert_step_device("SUB D1 D3", &mut device, Dump { pc: 15, acc: 255, data_reg: [2, 0, 0, 1], flags: FLAG_OVERFLOW, ..Default::default() });
+    assert_step_device("CPY D1 ACC", &mut device, Dump { pc: 18, acc: 255, data_reg: [2, 255, 0, 1], flags: FLAG_OVERFLOW, ..Default::default() });
+    assert_step_device("SUBC D0 D2", &mut device, Dump { pc: 21, acc: 1, data_reg: [2, 255, 0, 1], ..Default::default() });
+    assert_step_device("CPY D0 ACC", &mut device, Dump { pc: 24, acc: 1, data_reg: [1, 255, 0, 1], ..Default::default() });
+    assert_step_device("ADDC D1 255", &mut device, Dump { pc: 27, acc: 254, data_reg: [1, 255, 0, 1], flags: FLAG_OVERFLOW, ..Default::default() });
+    assert_step_device("ADDC D0 A0", &mut device, Dump { pc: 30, acc: 4, data_reg: [1, 255, 0, 1], ..Default::default() });
+    assert_step_device("SUBC D0 A0", &mut device, Dump { pc: 33, acc: 255, data_reg: [1, 255, 0, 1], flags: FLAG_OVERFLOW, ..Default::default() });
+
+    //overflow from the carry alone
+    let mut device = setup(vec![SUB_REG_VAL, REG_D1, 1, ADDC_REG_VAL, REG_D0, 0, SUBC_REG_VAL, REG_D1, 0]);
+    device.data_reg = [255, 0, 0, 0];
+    assert_step_device("SUB D1 1", &mut device, Dump { pc: 3, acc: 255, data_reg: [255, 0, 0, 0], flags: FLAG_OVERFLOW, ..Default::default() });
+    assert_step_device("ADDC D0 0", &mut device, Dump { pc: 6, acc: 0, data_reg: [255, 0, 0, 0], flags: FLAG_OVERFLOW, ..Default::default() });
+    assert_step_device("SUBC D1 0", &mut device, Dump { pc: 9, acc: 255, data_reg: [255, 0, 0, 0], flags: FLAG_OVERFLOW, ..Default::default() });
+
+    assert_no_output(device);
+}
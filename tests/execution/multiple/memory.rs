@@ -1,9 +1,41 @@
 use crate::{assert_memory, assert_specific_output, assert_step_device, setup};
 use tape_device::constants::code::{
-    MEMP_ADDR, MEMP_AREG, MEMR_ADDR, MEMR_AREG, MEMW_ADDR, MEMW_AREG,
+    ATOI_AREG_REG, ITOA_AREG_REG, ITOA_AREG_VAL, MEMCPY_AREG_AREG_REG, MEMP_ADDR, MEMP_AREG,
+    MEMR_ADDR, MEMR_AREG, MEMR_AREG_REG, MEMSET_AREG_REG_REG, MEMSET_AREG_VAL_REG, MEMW_ADDR,
+    MEMW_AREG, MEMW_AREG_REG,
 };
-use tape_device::constants::hardware::{REG_A0, REG_A1};
-use tape_device::device::Dump;
+use tape_device::constants::hardware::{FLAG_OVERFLOW, REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1};
+use tape_device::device::comm::Output;
+use tape_device::device::internals::RunResult;
+use tape_device::device::{DeviceState, Dump};
+
+#[test]
+#[rustfmt::skip]
+fn test_snapshot_restore() {
+    let ops = vec![
+        MEMW_ADDR, 0, 0,
+        MEMR_ADDR, 0, 10,
+    ];
+    let mut device = setup(ops);
+
+    device.acc = 3;
+    device.mem[0..3].copy_from_slice(&[1, 2, 3]);
+    assert_step_device("MEMW @0", &mut device, Dump { pc: 3, acc: 3, ..Default::default() });
+    let state = device.snapshot().unwrap();
+    let json = serde_json::to_string(&state).unwrap();
+
+    device.mem[0..3].copy_from_slice(&[9, 9, 9]);
+    device.acc = 0;
+    assert_step_device("MEMR @0", &mut device, Dump { pc: 6, ..Default::default() });
+
+    device.restore(serde_json::from_str::<DeviceState>(&json).unwrap()).unwrap();
+    assert_eq!(device.dump(), Dump { pc: 3, acc: 3, ..Default::default() });
+    assert_memory(&device, 0, &[3, 2, 3]);
+
+    let mut state = device.snapshot().unwrap();
+    state.mem.truncate(10);
+    assert!(device.restore(state).is_err());
+}
 
 #[test]
 #[rustfmt::skip]
@@ -41,3 +73,176 @@ fn test_multiple_memory_ops() {
 
     assert_specific_output(device, "Hellollo\u{0}\u{0}");
 }
+
+#[test]
+#[rustfmt::skip]
+fn test_indexed() {
+    let ops = vec![
+        MEMW_AREG_REG, REG_A0, REG_D1,
+        MEMR_AREG_REG, REG_A1, REG_ACC,
+        MEMR_AREG_REG, REG_A1, REG_D1,
+    ];
+    let mut device = setup(ops);
+    device.acc = 7;
+    device.data_reg = [0, 3, 0, 0];
+    device.addr_reg = [100, 90];
+
+    assert_step_device("MEMW A0+D1", &mut device, Dump { pc: 3, acc: 7, data_reg: [0, 3, 0, 0], addr_reg: [100, 90], ..Default::default() });
+    assert_memory(&device, 100, &[0, 0, 0, 7]);
+    device.mem[97] = 42;
+    assert_step_device("MEMR A1+ACC", &mut device, Dump { pc: 6, acc: 42, data_reg: [0, 3, 0, 0], addr_reg: [100, 90], ..Default::default() });
+    assert_step_device("MEMR A1+D1", &mut device, Dump { pc: 9, acc: 0, data_reg: [0, 3, 0, 0], addr_reg: [100, 90], ..Default::default() });
+
+    let mut device = setup(vec![MEMR_AREG_REG, REG_A0, REG_D1]);
+    device.addr_reg[0] = 0xFFFE;
+    device.data_reg[1] = 2;
+    assert_eq!(device.step(true), RunResult::ProgError);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_block_ops() {
+    let ops = vec![
+        MEMSET_AREG_VAL_REG, REG_A0, 7, REG_D0,
+        MEMSET_AREG_REG_REG, REG_A1, REG_D1, REG_D1,
+        MEMCPY_AREG_AREG_REG, REG_A1, REG_A0, REG_D0,
+        MEMCPY_AREG_AREG_REG, REG_A0, REG_A1, REG_D1,
+    ];
+    let mut device = setup(ops);
+    device.data_reg = [4, 2, 0, 0];
+    device.addr_reg = [10, 12];
+
+    assert_step_device("MEMSET A0 7 D0", &mut device, Dump { pc: 4, data_reg: [4, 2, 0, 0], addr_reg: [10, 12], ..Default::default() });
+    assert_memory(&device, 9, &[0, 7, 7, 7, 7, 0]);
+    assert_step_device("MEMSET A1 D1 D1", &mut device, Dump { pc: 8, data_reg: [4, 2, 0, 0], addr_reg: [10, 12], ..Default::default() });
+    assert_memory(&device, 9, &[0, 7, 7, 2, 2, 0]);
+    assert_step_device("MEMCPY A1 A0 D0", &mut device, Dump { pc: 12, data_reg: [4, 2, 0, 0], addr_reg: [10, 12], ..Default::default() });
+    assert_memory(&device, 9, &[0, 7, 7, 7, 7, 2, 2, 0]);
+    assert_step_device("MEMCPY A0 A1 D1", &mut device, Dump { pc: 16, data_reg: [4, 2, 0, 0], addr_reg: [10, 12], ..Default::default() });
+    assert_memory(&device, 9, &[0, 7, 7, 7, 7, 2, 2, 0]);
+
+    let mut device = setup(vec![MEMSET_AREG_VAL_REG, REG_A0, 1, REG_D0, MEMCPY_AREG_AREG_REG, REG_A1, REG_A0, REG_D0]);
+    device.data_reg[0] = 10;
+    device.addr_reg = [0xFFFA, 0];
+    assert_eq!(device.step(true), RunResult::ProgError);
+    device.pc = 4;
+    assert_eq!(device.step(true), RunResult::ProgError);
+    assert_memory(&device, 0, &[0; 10]);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_decimal_conversion() {
+    let ops = vec![
+        ITOA_AREG_REG, REG_A0, REG_D0,
+        ITOA_AREG_REG, REG_A0, REG_A1,
+        ATOI_AREG_REG, REG_A0, REG_D1,
+        ITOA_AREG_VAL, REG_A0, 7,
+        ATOI_AREG_REG, REG_A0, REG_D1,
+        ATOI_AREG_REG, REG_A0, REG_A1,
+    ];
+    let mut device = setup(ops);
+    device.data_reg = [42, 0, 0, 0];
+    device.addr_reg = [10, 300];
+
+    assert_step_device("ITOA A0 D0", &mut device, Dump { pc: 3, acc: 2, data_reg: [42, 0, 0, 0], addr_reg: [10, 300], ..Default::default() });
+    assert_memory(&device, 10, b"42");
+    assert_step_device("ITOA A0 A1", &mut device, Dump { pc: 6, acc: 3, data_reg: [42, 0, 0, 0], addr_reg: [10, 300], ..Default::default() });
+    assert_memory(&device, 10, b"300");
+    assert_step_device("ATOI A0 D1", &mut device, Dump { pc: 9, acc: 3, data_reg: [42, 0, 0, 0], addr_reg: [10, 300], flags: FLAG_OVERFLOW, ..Default::default() });
+    assert_step_device("ITOA A0 7", &mut device, Dump { pc: 12, acc: 1, data_reg: [42, 0, 0, 0], addr_reg: [10, 300], flags: FLAG_OVERFLOW, ..Default::default() });
+    assert_memory(&device, 10, b"700");
+    assert_step_device("ATOI A0 D1", &mut device, Dump { pc: 15, acc: 1, data_reg: [42, 7, 0, 0], addr_reg: [10, 300], ..Default::default() });
+    device.mem[12] = b'x';
+    device.acc = 3;
+    assert_step_device("ATOI A0 A1", &mut device, Dump { pc: 18, acc: 2, data_reg: [42, 7, 0, 0], addr_reg: [10, 70], ..Default::default() });
+
+    let mut device = setup(vec![ITOA_AREG_VAL, REG_A0, 100]);
+    device.addr_reg[0] = 0xFFFE;
+    assert_eq!(device.step(true), RunResult::ProgError);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_out_of_bounds() {
+    let mut device = setup(vec![MEMR_ADDR, 0]);
+    assert_eq!(device.step(true), RunResult::ProgError);
+
+    let mut device = setup(vec![MEMW_ADDR, 0xFF, 0xFF, MEMR_AREG, REG_A0, MEMP_AREG, REG_A1]);
+    assert_eq!(device.step(true), RunResult::ProgError);
+    device.pc = 3;
+    device.addr_reg[0] = 0xFFFF;
+    assert_eq!(device.step(true), RunResult::ProgError);
+    device.pc = 5;
+    device.acc = 10;
+    device.addr_reg[1] = 0xFFFA;
+    assert_eq!(device.step(true), RunResult::ProgError);
+    let errors: Vec<&Output> = device.output().iter().filter(|output| matches!(output, Output::OutputErr(_))).collect();
+    assert!(errors.contains(&&Output::OutputErr(String::from("Memory access out of bounds, 10 bytes at FFFA but max address is FFFE"))));
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_mmio() {
+    let ops = vec![
+        MEMW_ADDR, 0xBF, 0x00,
+        MEMW_ADDR, 0xBF, 0x01,
+        MEMR_ADDR, 0xBF, 0x02,
+        MEMR_ADDR, 0xBF, 0x03,
+        MEMR_ADDR, 0xBF, 0x03,
+        MEMW_ADDR, 0xBF, 0x02,
+    ];
+    let mut device = setup(ops.clone());
+    device.set_mmio(true);
+    device.acc = 65;
+    device.push_char(66);
+
+    assert_step_device("MEMW xBF00", &mut device, Dump { pc: 3, acc: 65, ..Default::default() });
+    assert_step_device("MEMW xBF01", &mut device, Dump { pc: 6, acc: 65, ..Default::default() });
+    assert_step_device("MEMR xBF02", &mut device, Dump { pc: 9, acc: 1, ..Default::default() });
+    assert_step_device("MEMR xBF03", &mut device, Dump { pc: 12, acc: 66, ..Default::default() });
+    assert_step_device("MEMR xBF03", &mut device, Dump { pc: 15, acc: 0, ..Default::default() });
+    assert_eq!(device.step(true), RunResult::ProgError);
+    assert_memory(&device, 0xBF00, &[0, 0, 0, 0]);
+    assert_eq!(device.output()[0], Output::OutputStd(String::from("A")));
+    assert_eq!(device.output()[1], Output::OutputStd(String::from("65")));
+
+    //without --mmio the page is normal memory
+    let mut device = setup(ops);
+    device.acc = 65;
+    assert_step_device("MEMW xBF00", &mut device, Dump { pc: 3, acc: 65, ..Default::default() });
+    assert_memory(&device, 0xBF00, &[65]);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_strict_uninitialized_reads() {
+    let ops = vec![
+        MEMR_ADDR, 0, 10,
+        MEMR_ADDR, 0, 10,
+        MEMW_ADDR, 0, 20,
+        MEMR_ADDR, 0, 20,
+        MEMP_ADDR, 0, 20,
+    ];
+    let mut device = setup(ops);
+    device.set_strict(true);
+
+    assert_step_device("MEMR @10", &mut device, Dump { pc: 3, ..Default::default() });
+    assert_step_device("MEMR @10", &mut device, Dump { pc: 6, ..Default::default() });
+    assert_eq!(device.take_output(), vec![Output::UninitializedRead(0, 10)]);
+
+    device.acc = 2;
+    assert_step_device("MEMW @20", &mut device, Dump { pc: 9, acc: 2, ..Default::default() });
+    assert_step_device("MEMR @20", &mut device, Dump { pc: 12, acc: 2, ..Default::default() });
+    assert!(device.take_output().is_empty());
+
+    assert_step_device("MEMP @20", &mut device, Dump { pc: 15, acc: 2, ..Default::default() });
+    assert_eq!(device.take_output(), vec![
+        Output::UninitializedRead(12, 21),
+        Output::OutputStd(String::from("\u{2}\u{0}")),
+    ]);
+
+    let mut device = setup(vec![MEMR_ADDR, 0, 10]);
+    assert_step_device("MEMR @10", &mut device, Dump { pc: 3, ..Default::default() });
+    assert!(device.take_output().is_empty());
+}
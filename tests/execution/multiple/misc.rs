@@ -1,9 +1,11 @@
 use crate::{assert_specific_output, assert_step_device, setup};
 use tape_device::constants::code::{
-    DEBUG, HALT, NOP, RAND_REG, SEED_REG, SWP_AREG_AREG, SWP_REG_REG, TIME,
+    DEBUG, EXIT_REG, EXIT_VAL, HALT, JMP_ADDR, MILLIS, NOP, RAND_REG, SEED_REG, SLEEP_REG,
+    SLEEP_VAL, SWP_AREG_AREG, SWP_REG_REG, TIME, VSYNC,
 };
-use tape_device::constants::hardware::{REG_A0, REG_A1, REG_D0, REG_D1};
-use tape_device::device::internals::{Device, RunResult};
+use tape_device::constants::hardware::{REG_A0, REG_A1, REG_D0, REG_D1, REG_D3};
+use tape_device::device::comm::Output;
+use tape_device::device::internals::{Determinism, Device, RunResult, VIRTUAL_CYCLES_PER_SECOND};
 use tape_device::device::Dump;
 
 #[test]
@@ -39,13 +41,13 @@ fn test_multiple_misc_ops() {
     assert_eq!(device.dump().pc, 13);
     validate(&mut device);
 
-    assert_specific_output(device, "ACC: 00  D0: 00  D1: 6E  D2: 00  D3: 00 A0: 0000 A1: 0130PC:   11 SP: FFFF FP: FFFF Overflowed: falseStack (FFFF..FFFF): []");
+    assert_specific_output(device, "ACC: 00  D0: 00  D1: 6E  D2: 00  D3: 00 A0: 0000 A1: 0130PC:   11 SP: FFFF FP: FFFF Flags: ----Stack (FFFF..FFFF): []");
 }
 
 fn validate(device: &mut Device) {
     let dump = device.dump();
     assert_eq!(dump.addr_reg, [0, 304]);
-    assert!(!dump.overflow);
+    assert_eq!(dump.flags, 0);
     assert_eq!(dump.sp, 65535);
     assert_eq!(dump.fp, 65535);
     assert_eq!(dump.acc, 0);
@@ -54,3 +56,137 @@ fn validate(device: &mut Device) {
     assert!(dump.data_reg[2] < 24, "{}", dump.data_reg[2]);
     assert_eq!(dump.data_reg[3], 0);
 }
+
+#[test]
+#[rustfmt::skip]
+fn test_patch_instruction() {
+    let ops = vec![
+        SWP_REG_REG, REG_D0, REG_D1,
+        HALT,
+    ];
+    let mut device = setup(ops);
+    device.data_reg[0] = 5;
+
+    assert!(device.patch_instruction(0, &[SWP_REG_REG, REG_D0, REG_D1, HALT]).is_err());
+    assert!(device.patch_instruction(4, &[NOP]).is_err());
+    assert_eq!(device.patch_instruction(0, &[DEBUG]).unwrap(), 2);
+
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.pc, 3);
+    assert_eq!(device.data_reg[0], 5);
+    assert_eq!(device.step(true), RunResult::Halt);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_patch_instruction_errors() {
+    let ops = vec![
+        SWP_REG_REG, REG_D0, REG_D1,
+        SWP_REG_REG, REG_D0,
+    ];
+    let mut device = setup(ops);
+
+    //operand byte, not an instruction
+    assert!(device.patch_instruction(1, &[NOP]).is_err());
+    //last instruction is missing a byte
+    assert!(device.patch_instruction(3, &[NOP]).is_err());
+    assert_eq!(device.patch_instruction(0, &[NOP]).unwrap(), 2);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_deterministic_rand() {
+    let ops = vec![
+        RAND_REG, REG_D0,
+        RAND_REG, REG_D1,
+    ];
+    let run = || {
+        let mut device = setup(ops.clone());
+        device.set_determinism(Determinism { seed: Some(41), ..Default::default() });
+        assert_eq!(device.step(true), RunResult::Pause);
+        assert_eq!(device.step(true), RunResult::Pause);
+        device.data_reg
+    };
+
+    assert_eq!(run(), run());
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_fixed_time() {
+    let ops = vec![
+        TIME,
+        JMP_ADDR, 0, 0,
+    ];
+    let mut device = setup(ops);
+    device.set_determinism(Determinism { start_time: Some(Determinism::parse_time("23:59:59").unwrap()), ..Default::default() });
+
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.data_reg, [59, 59, 23, 0]);
+    while device.cycles() < VIRTUAL_CYCLES_PER_SECOND {
+        assert_eq!(device.step(true), RunResult::Pause);
+    }
+    assert_eq!(device.pc, 0);
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.data_reg, [0, 0, 0, 0]);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_virtual_millis() {
+    let ops = vec![
+        MILLIS,
+        SLEEP_VAL, 250,
+        SLEEP_REG, REG_D3,
+        MILLIS,
+        TIME,
+    ];
+    let mut device = setup(ops);
+    device.set_determinism(Determinism { start_time: Some(Determinism::parse_time("10:00:00").unwrap()), ..Default::default() });
+
+    assert_step_device("MILLIS", &mut device, Dump { pc: 1, data_reg: [0, 0, 0, 1], ..Default::default() });
+    assert_step_device("SLEEP 250", &mut device, Dump { pc: 3, data_reg: [0, 0, 0, 1], ..Default::default() });
+    device.data_reg = [0, 0, 0, 255];
+    assert_step_device("SLEEP D3", &mut device, Dump { pc: 5, data_reg: [0, 0, 0, 255], ..Default::default() });
+    assert_step_device("MILLIS", &mut device, Dump { pc: 6, data_reg: [0, 0, 1, 253], ..Default::default() });
+    assert_step_device("TIME", &mut device, Dump { pc: 7, data_reg: [0, 0, 10, 253], ..Default::default() });
+}
+
+#[test]
+fn test_sleep() {
+    let mut device = setup(vec![SLEEP_VAL, 20, MILLIS]);
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert!(u32::from_be_bytes(device.data_reg) >= 20);
+}
+
+#[test]
+fn test_vsync() {
+    let mut device = setup(vec![VSYNC]);
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.output(), vec![Output::Vsync]);
+}
+
+#[test]
+fn test_parse_time() {
+    assert_eq!(Determinism::parse_time("00:00:00").unwrap(), 0);
+    assert_eq!(Determinism::parse_time("01:02:03").unwrap(), 3723);
+    assert!(Determinism::parse_time("24:00:00").is_err());
+    assert!(Determinism::parse_time("12:00").is_err());
+    assert!(Determinism::parse_time("noon").is_err());
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_exit() {
+    let mut device = setup(vec![EXIT_VAL, 3, HALT]);
+    assert_eq!(device.step(true), RunResult::Exit(3));
+    assert_eq!(device.pc, 0);
+
+    let mut device = setup(vec![NOP, EXIT_REG, REG_D1]);
+    device.data_reg[1] = 200;
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.step(true), RunResult::Exit(200));
+}
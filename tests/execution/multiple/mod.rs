@@ -1,12 +1,17 @@
+mod capabilities;
 mod compare;
 mod copy;
 mod data;
+#[cfg(feature = "fs")]
 mod file;
 mod input;
+mod interrupt;
 mod jump;
 mod logic;
 mod math;
 mod memory;
 mod misc;
 mod print;
+mod resources;
 mod stack;
+mod watchpoint;
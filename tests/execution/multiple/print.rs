@@ -1,8 +1,14 @@
 use crate::{assert_specific_output, assert_step_device, setup};
+use std::sync::mpsc::{channel, sync_channel};
+use std::thread;
 use tape_device::constants::code::{
-    PRTC_AREG, PRTC_REG, PRTC_VAL, PRTD_AREG, PRTLN, PRTS_STR, PRT_AREG, PRT_REG, PRT_VAL,
+    CLS, COLOR_REG, COLOR_VAL, CURS_REG_VAL, CURS_VAL_REG, CURS_VAL_VAL, PRTC_AREG, PRTC_REG,
+    PRTC_VAL, PRTD_AREG, PRTLN, PRTS_AREG, PRTS_STR, PRTX_AREG, PRTX_REG, PRTX_VAL, PRT_AREG,
+    PRT_REG, PRT_VAL,
 };
-use tape_device::constants::hardware::{REG_A0, REG_A1, REG_D0, REG_D2};
+use tape_device::constants::hardware::{REG_A0, REG_A1, REG_D0, REG_D1, REG_D2};
+use tape_device::device::comm::Output;
+use tape_device::device::internals::RunResult;
 use tape_device::device::Dump;
 
 #[test]
@@ -45,3 +51,128 @@ fn test_multiple_print_ops() {
 
     assert_specific_output(device, "97a68F\nWorld50bbc");
 }
+
+#[test]
+#[rustfmt::skip]
+fn test_print_string_areg() {
+    let ops = vec![
+        PRTS_AREG, REG_A0,
+        PRTS_AREG, REG_A1,
+        PRTS_AREG, REG_A1,
+    ];
+    let mut device = setup(ops);
+
+    device.tape_strings = vec![2, 72, 105, 5, 87, 111, 114, 108, 100];
+    device.addr_reg = [3, 0];
+
+    assert_step_device("PRTS A0", &mut device, Dump { pc: 2, addr_reg: [3, 0], ..Default::default() });
+    assert_step_device("PRTS A1", &mut device, Dump { pc: 4, addr_reg: [3, 0], ..Default::default() });
+    let printed: String = device.output().iter().map(|output| match output {
+        Output::OutputStd(msg) => msg.as_str(),
+        _ => "",
+    }).collect();
+    assert_eq!(printed, "WorldHi");
+
+    device.addr_reg = [3, 9];
+
+    assert_eq!(device.step(true), RunResult::ProgError);
+    let out_of_bounds = device.output().iter().any(|output| match output {
+        Output::OutputErr(msg) => msg.contains("String access out of bounds 9"),
+        _ => false,
+    });
+    assert!(out_of_bounds);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_print_hex() {
+    let ops = vec![
+        PRTX_VAL, 10,
+        PRTX_REG, REG_D0,
+        PRTX_AREG, REG_A0,
+    ];
+    let mut device = setup(ops);
+
+    device.tape_data = vec![0, 255];
+    device.data_reg = [171, 0, 0, 0];
+    device.addr_reg = [1, 0];
+
+    assert_step_device("PRTX 10", &mut device, Dump { pc: 2, data_reg: [171, 0, 0, 0], addr_reg: [1, 0], ..Default::default() });
+    assert_step_device("PRTX D0", &mut device, Dump { pc: 4, data_reg: [171, 0, 0, 0], addr_reg: [1, 0], ..Default::default() });
+    assert_step_device("PRTX A0", &mut device, Dump { pc: 6, data_reg: [171, 0, 0, 0], addr_reg: [1, 0], ..Default::default() });
+
+    assert_specific_output(device, "0AABFF");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_terminal_control() {
+    let ops = vec![
+        CLS,
+        CURS_VAL_VAL, 4, 2,
+        CURS_REG_VAL, REG_D0, 1,
+        CURS_VAL_REG, 3, REG_D1,
+        COLOR_VAL, 9,
+        COLOR_REG, REG_D1,
+        PRTC_VAL, b'a',
+    ];
+    let mut device = setup(ops);
+    device.data_reg = [10, 200, 0, 0];
+
+    assert_step_device("CLS", &mut device, Dump { pc: 1, data_reg: [10, 200, 0, 0], ..Default::default() });
+    assert_step_device("CURS 4 2", &mut device, Dump { pc: 4, data_reg: [10, 200, 0, 0], ..Default::default() });
+    assert_step_device("CURS D0 1", &mut device, Dump { pc: 7, data_reg: [10, 200, 0, 0], ..Default::default() });
+    assert_step_device("CURS 3 D1", &mut device, Dump { pc: 10, data_reg: [10, 200, 0, 0], ..Default::default() });
+    assert_step_device("COLOR 9", &mut device, Dump { pc: 12, data_reg: [10, 200, 0, 0], ..Default::default() });
+    assert_step_device("COLOR D1", &mut device, Dump { pc: 14, data_reg: [10, 200, 0, 0], ..Default::default() });
+    assert_step_device("PRTC 'a'", &mut device, Dump { pc: 16, data_reg: [10, 200, 0, 0], ..Default::default() });
+
+    assert_eq!(device.output(), vec![
+        Output::ClearScreen,
+        Output::MoveCursor(4, 2),
+        Output::MoveCursor(10, 1),
+        Output::MoveCursor(3, 200),
+        Output::SetColor(Some(9)),
+        Output::SetColor(None),
+        Output::OutputStd(String::from("a")),
+    ]);
+}
+
+#[test]
+fn test_output_sink() {
+    let mut device = setup(vec![PRTC_VAL, b'x', PRTC_VAL, b'a', CLS, PRTC_VAL, b'b']);
+    assert_eq!(device.step(true), RunResult::Pause);
+    let (sender, receiver) = channel();
+    device.set_output_sink(Box::new(sender));
+
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(
+        receiver.try_recv(),
+        Ok(Output::OutputStd(String::from("a")))
+    );
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.step(true), RunResult::Pause);
+    let received: Vec<Output> = receiver.try_iter().collect();
+    assert_eq!(
+        received,
+        vec![Output::ClearScreen, Output::OutputStd(String::from("b"))]
+    );
+    assert_eq!(
+        device.take_output(),
+        vec![Output::OutputStd(String::from("x"))]
+    );
+    assert!(device.output().is_empty());
+}
+
+#[test]
+fn test_bounded_output_sink() {
+    let ops = [PRTC_VAL, b'x'].repeat(100);
+    let mut device = setup(ops);
+    let (sender, receiver) = sync_channel(1);
+    device.set_output_sink(Box::new(sender));
+
+    let reader = thread::spawn(move || receiver.iter().count());
+    while device.step(true) == RunResult::Pause {}
+    drop(device);
+    assert_eq!(reader.join().unwrap(), 100);
+}
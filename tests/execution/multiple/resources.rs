@@ -0,0 +1,116 @@
+use crate::setup;
+use tape_device::constants::code::{CALL_ADDR, JMP_ADDR, NOP, PRTC_VAL, RCHR_REG, SEND_VAL};
+use tape_device::constants::hardware::REG_ACC;
+use tape_device::device::internals::{ResourceLimits, ResourceStats, RunResult};
+
+#[test]
+#[rustfmt::skip]
+fn test_resource_stats() {
+    let ops = vec![
+        PRTC_VAL, b'a',
+        PRTC_VAL, b'b',
+    ];
+    let mut device = setup(ops);
+    device.push_bytes(&[1, 2, 3]);
+    device.breakpoints.push(10);
+
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.resource_stats(), ResourceStats {
+        output_entries: 2,
+        output_bytes: 2,
+        keyboard_buffer_bytes: 3,
+        breakpoints: 1,
+        ..Default::default()
+    });
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_serial_out_limit() {
+    let ops = vec![
+        SEND_VAL, 1,
+        JMP_ADDR, 0, 0,
+    ];
+    let mut device = setup(ops);
+    device.set_resource_limits(ResourceLimits { max_serial_out_bytes: Some(2), ..Default::default() });
+
+    for _ in 0..4 {
+        assert_eq!(device.step(true), RunResult::Pause);
+    }
+    assert_eq!(device.resource_stats().serial_out_bytes, 2);
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.step(true), RunResult::ProgError);
+    assert_eq!(device.resource_stats().serial_out_bytes, 3);
+
+    device.serial_out.clear();
+    assert_eq!(device.step(true), RunResult::Pause);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_call_frame_limit() {
+    let ops = vec![
+        CALL_ADDR, 0, 0,
+    ];
+    let mut device = setup(ops);
+    device.set_resource_limits(ResourceLimits { max_call_frames: Some(3), ..Default::default() });
+
+    for _ in 0..4 {
+        assert_eq!(device.step(true), RunResult::Pause);
+    }
+    assert_eq!(device.resource_stats().call_frames, 4);
+    assert_eq!(device.step(true), RunResult::ProgError);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_output_limit() {
+    let ops = vec![
+        PRTC_VAL, b'a',
+        PRTC_VAL, b'b',
+        PRTC_VAL, b'c',
+    ];
+    let mut device = setup(ops);
+    device.set_resource_limits(ResourceLimits { max_output_bytes: Some(1), ..Default::default() });
+
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.step(true), RunResult::ProgError);
+
+    device.take_output();
+    assert_eq!(device.step(true), RunResult::Pause);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_keyboard_limit() {
+    let ops = vec![
+        RCHR_REG, REG_ACC,
+    ];
+    let mut device = setup(ops);
+    device.set_resource_limits(ResourceLimits { max_keyboard_buffer_bytes: Some(2), ..Default::default() });
+    device.push_bytes(&[1, 2, 3]);
+
+    assert_eq!(device.step(true), RunResult::ProgError);
+    device.set_resource_limits(ResourceLimits { max_keyboard_buffer_bytes: Some(3), ..Default::default() });
+    assert_eq!(device.step(true), RunResult::Pause);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_max_cycles() {
+    let ops = vec![
+        NOP,
+        JMP_ADDR, 0, 0,
+    ];
+    let mut device = setup(ops);
+    device.set_max_cycles(5);
+
+    for _ in 0..5 {
+        assert_eq!(device.step(true), RunResult::Pause);
+    }
+    assert_eq!(device.cycles(), 5);
+    assert_eq!(device.step(true), RunResult::CycleLimit);
+    assert_eq!(device.cycles(), 5);
+}
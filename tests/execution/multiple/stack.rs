@@ -1,6 +1,11 @@
 use crate::{assert_memory, assert_no_output, assert_step_device, setup};
-use tape_device::constants::code::{CALL_ADDR, CALL_AREG, HALT, POP_REG, PUSH_REG, PUSH_VAL, RET};
+use tape_device::constants::code::{
+    ARG_REG_VAL, CALL_ADDR, CALL_AREG, HALT, LOCAL_VAL, LVAR_REG_VAL, LVAR_VAL_REG, MEMW_ADDR,
+    POP_REG, POP_VAL, PUSH_REG, PUSH_VAL, RET,
+};
+use tape_device::constants::hardware::STACK_CANARY;
 use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1};
+use tape_device::device::comm::Output;
 use tape_device::device::internals::RunResult;
 use tape_device::device::Dump;
 
@@ -60,3 +65,239 @@ fn test_multiple_addr_stack_ops() {
 
     assert_no_output(device);
 }
+
+fn assert_stack_error(device: &mut tape_device::device::internals::Device, msg: &str) {
+    assert_eq!(device.step(true), RunResult::ProgError);
+    let found = device.output().iter().any(|output| match output {
+        Output::OutputErr(text) => text.contains(msg),
+        _ => false,
+    });
+    assert!(found, "No error containing '{}'", msg);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_stack_limit() {
+    let ops = vec![
+        PUSH_VAL, 1,
+        PUSH_VAL, 2,
+        PUSH_VAL, 3,
+    ];
+    let mut device = setup(ops);
+    device.set_stack_limit(65533);
+
+    assert_step_device("PUSH 1", &mut device, Dump { pc: 2, sp: 65534, stack_limit: 65533, ..Default::default() });
+    assert_step_device("PUSH 2", &mut device, Dump { pc: 4, sp: 65533, stack_limit: 65533, ..Default::default() });
+    assert_stack_error(&mut device, "Stack overflow");
+    assert_eq!(device.dump().sp, 65533);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_stack_limit_call() {
+    let ops = vec![
+        PUSH_VAL, 1,
+        CALL_ADDR, 0, 0,
+    ];
+    let mut device = setup(ops);
+    device.set_stack_limit(65531);
+
+    assert_step_device("PUSH 1", &mut device, Dump { pc: 2, sp: 65534, stack_limit: 65531, ..Default::default() });
+    assert_stack_error(&mut device, "CALL needs 4 bytes");
+    assert_eq!(device.dump().sp, 65534);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_stack_underflow() {
+    let ops = vec![
+        POP_REG, REG_ACC,
+    ];
+    let mut device = setup(ops);
+
+    assert_stack_error(&mut device, "Stack underflow");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_stack_corrupted_return() {
+    let ops = vec![
+        PUSH_VAL, 0,
+        PUSH_VAL, 0,
+        PUSH_VAL, 0,
+        PUSH_VAL, 0,
+        RET,
+    ];
+    let mut device = setup(ops);
+
+    for pc in [2, 4, 6, 8].iter() {
+        assert_step_device("PUSH 0", &mut device, Dump { pc: *pc, sp: 65535 - (*pc / 2), ..Default::default() });
+    }
+    assert_stack_error(&mut device, "Stack corrupted");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_pop_discard() {
+    let ops = vec![
+        PUSH_VAL, 73,
+        PUSH_REG, REG_A1,
+        POP_VAL, 3,
+        POP_VAL, 1,
+    ];
+    let mut device = setup(ops);
+    device.acc = 5;
+    device.addr_reg = [0, 259];
+
+    assert_step_device("PUSH 73", &mut device, Dump { pc: 2, acc: 5, addr_reg: [0, 259], sp: 65534, ..Default::default() });
+    assert_step_device("PUSH A1", &mut device, Dump { pc: 4, acc: 5, addr_reg: [0, 259], sp: 65532, ..Default::default() });
+    assert_step_device("POP 3", &mut device, Dump { pc: 6, acc: 5, addr_reg: [0, 259], sp: 65535, ..Default::default() });
+    assert_eq!(device.step(true), RunResult::ProgError);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_locals() {
+    let ops = vec![
+        CALL_ADDR, 0, 4,
+        HALT,
+        LOCAL_VAL, 3,
+        LVAR_VAL_REG, 1, REG_D0,
+        LVAR_VAL_REG, 2, REG_A0,
+        LVAR_REG_VAL, REG_D1, 1,
+        LVAR_REG_VAL, REG_A1, 2,
+        RET,
+    ];
+    let mut device = setup(ops);
+    device.data_reg = [7, 0, 0, 0];
+    device.addr_reg = [258, 0];
+
+    assert_step_device("CALL lbl", &mut device, Dump { pc: 4, data_reg: [7, 0, 0, 0], addr_reg: [258, 0], sp: 65531, fp: 65531, ..Default::default() });
+    assert_step_device("LOCAL 3", &mut device, Dump { pc: 6, data_reg: [7, 0, 0, 0], addr_reg: [258, 0], sp: 65528, fp: 65531, ..Default::default() });
+    assert_step_device("LVAR 1 D0", &mut device, Dump { pc: 9, data_reg: [7, 0, 0, 0], addr_reg: [258, 0], sp: 65528, fp: 65531, ..Default::default() });
+    assert_memory(&device, 65530, &[7]);
+    assert_step_device("LVAR 2 A0", &mut device, Dump { pc: 12, data_reg: [7, 0, 0, 0], addr_reg: [258, 0], sp: 65528, fp: 65531, ..Default::default() });
+    assert_memory(&device, 65528, &[1, 2, 7]);
+    assert_step_device("LVAR D1 1", &mut device, Dump { pc: 15, data_reg: [7, 7, 0, 0], addr_reg: [258, 0], sp: 65528, fp: 65531, ..Default::default() });
+    assert_step_device("LVAR A1 2", &mut device, Dump { pc: 18, data_reg: [7, 7, 0, 0], addr_reg: [258, 258], sp: 65528, fp: 65531, ..Default::default() });
+    assert_step_device("RET", &mut device, Dump { pc: 3, data_reg: [7, 7, 0, 0], addr_reg: [258, 258], ..Default::default() });
+    assert_eq!(device.step(true), RunResult::Halt);
+
+    assert_no_output(device);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_locals_out_of_range() {
+    let ops = vec![
+        CALL_ADDR, 0, 4,
+        HALT,
+        LOCAL_VAL, 1,
+        LVAR_REG_VAL, REG_A0, 1,
+    ];
+    let mut device = setup(ops);
+
+    assert_step_device("CALL lbl", &mut device, Dump { pc: 4, sp: 65531, fp: 65531, ..Default::default() });
+    assert_step_device("LOCAL 1", &mut device, Dump { pc: 6, sp: 65530, fp: 65531, ..Default::default() });
+    assert_stack_error(&mut device, "Local 1 is outside the reserved locals");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_args() {
+    let ops = vec![
+        LOCAL_VAL, 1,
+        PUSH_VAL, 5,
+        PUSH_VAL, 9,
+        CALL_ADDR, 0, 10,
+        HALT,
+        ARG_REG_VAL, REG_D0, 1,
+        ARG_REG_VAL, REG_D1, 2,
+        ARG_REG_VAL, REG_A0, 1,
+        ARG_REG_VAL, REG_A0, 2,
+    ];
+    let mut device = setup(ops);
+
+    assert_step_device("LOCAL 1", &mut device, Dump { pc: 2, sp: 65534, ..Default::default() });
+    assert_step_device("PUSH 5", &mut device, Dump { pc: 4, sp: 65533, ..Default::default() });
+    assert_step_device("PUSH 9", &mut device, Dump { pc: 6, sp: 65532, ..Default::default() });
+    assert_step_device("CALL lbl", &mut device, Dump { pc: 10, sp: 65528, fp: 65528, ..Default::default() });
+    assert_step_device("ARG D0 1", &mut device, Dump { pc: 13, data_reg: [9, 0, 0, 0], sp: 65528, fp: 65528, ..Default::default() });
+    assert_step_device("ARG D1 2", &mut device, Dump { pc: 16, data_reg: [9, 5, 0, 0], sp: 65528, fp: 65528, ..Default::default() });
+    assert_step_device("ARG A0 1", &mut device, Dump { pc: 19, data_reg: [9, 5, 0, 0], addr_reg: [1289, 0], sp: 65528, fp: 65528, ..Default::default() });
+    //the local reserved before the pushes isn't an argument
+    assert_stack_error(&mut device, "Argument 2 is outside the 2 bytes pushed before CALL (FP FFF8)");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_args_out_of_frame() {
+    let mut device = setup(vec![ARG_REG_VAL, REG_D0, 1]);
+    assert_stack_error(&mut device, "ARG used outside of a CALL, there's no frame for FP FFFF");
+
+    let ops = vec![
+        PUSH_VAL, 5,
+        CALL_ADDR, 0, 6,
+        HALT,
+        CALL_ADDR, 0, 10,
+        HALT,
+        ARG_REG_VAL, REG_D0, 1,
+    ];
+    let mut device = setup(ops);
+
+    assert_step_device("PUSH 5", &mut device, Dump { pc: 2, sp: 65534, ..Default::default() });
+    assert_step_device("CALL lbl", &mut device, Dump { pc: 6, sp: 65530, fp: 65530, ..Default::default() });
+    assert_step_device("CALL lbl", &mut device, Dump { pc: 10, sp: 65526, fp: 65526, ..Default::default() });
+    //the outer call's argument belongs to the outer frame
+    assert_stack_error(&mut device, "Argument 1 is outside the 0 bytes pushed before CALL (FP FFF6)");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_stack_canaries() {
+    let ops = vec![
+        PUSH_VAL, 7,
+        CALL_ADDR, 0, 6,
+        HALT,
+        ARG_REG_VAL, REG_D0, 1,
+        RET,
+    ];
+    let mut device = setup(ops);
+    device.set_stack_check(true);
+
+    assert_step_device("PUSH 7", &mut device, Dump { pc: 2, sp: 65534, ..Default::default() });
+    assert_step_device("CALL lbl", &mut device, Dump { pc: 6, sp: 65528, fp: 65528, ..Default::default() });
+    assert_memory(&device, 65528, &[STACK_CANARY, 5, 0, 255, 255, STACK_CANARY, 7]);
+    assert_step_device("ARG D0 1", &mut device, Dump { pc: 9, data_reg: [7, 0, 0, 0], sp: 65528, fp: 65528, ..Default::default() });
+    assert_step_device("RET", &mut device, Dump { pc: 5, data_reg: [7, 0, 0, 0], sp: 65534, ..Default::default() });
+    assert_eq!(device.step(true), RunResult::Halt);
+
+    assert_no_output(device);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_stack_canary_overwritten() {
+    let ops = vec![
+        CALL_ADDR, 0, 4,
+        HALT,
+        MEMW_ADDR, 255, 254,
+        RET,
+    ];
+    let mut device = setup(ops.clone());
+    device.set_stack_check(true);
+
+    assert_step_device("CALL lbl", &mut device, Dump { pc: 4, sp: 65529, fp: 65529, ..Default::default() });
+    assert_step_device("MEMW @FFFE", &mut device, Dump { pc: 7, sp: 65529, fp: 65529, ..Default::default() });
+    assert_stack_error(&mut device, "Stack corrupted, canary at FFFE for the frame at FP FFF9 is 00 instead of A5");
+    assert_eq!(device.output()[0], Output::StackCorrupted(4, 0xFFFE));
+
+    //the writer isn't known if memory is changed from outside the program
+    let mut device = setup(ops);
+    device.set_stack_check(true);
+    assert_step_device("CALL lbl", &mut device, Dump { pc: 4, sp: 65529, fp: 65529, ..Default::default() });
+    device.mem[65529] = 0;
+    device.pc = 7;
+    assert_stack_error(&mut device, "Stack corrupted, canary at FFF9");
+    assert!(!device.output().iter().any(|output| matches!(output, Output::StackCorrupted(..))));
+}
@@ -0,0 +1,78 @@
+use crate::{assert_memory, setup};
+use tape_device::constants::code::{
+    MEMCPY_AREG_AREG_REG, MEMSET_AREG_VAL_REG, MEMW_ADDR, MEMW_AREG, RSTR_ADDR,
+};
+use tape_device::constants::hardware::{REG_A0, REG_A1, REG_D0};
+use tape_device::device::comm::Output;
+use tape_device::device::internals::RunResult;
+use tape_device::device::Dump;
+
+#[test]
+#[rustfmt::skip]
+fn test_watchpoints() {
+    let ops = vec![
+        MEMW_ADDR, 0, 5,
+        MEMW_ADDR, 0, 20,
+        MEMW_AREG, REG_A0,
+        RSTR_ADDR, 0, 30,
+    ];
+    let mut device = setup(ops);
+    device.watchpoints.push((10, 20));
+    device.watchpoints.push((32, 32));
+    device.acc = 9;
+    device.addr_reg[0] = 15;
+
+    assert_eq!(device.step(false), RunResult::Pause, "MEMW @5");
+    assert_eq!(device.step(false), RunResult::Watchpoint, "MEMW @20");
+    assert_eq!(device.dump(), Dump { pc: 6, acc: 9, addr_reg: [15, 0], ..Default::default() });
+    assert_memory(&device, 20, &[9]);
+
+    assert_eq!(device.step(true), RunResult::Pause, "MEMW A0 ignoring watchpoints");
+    assert_memory(&device, 15, &[9]);
+
+    device.push_string("abcd");
+    assert_eq!(device.step(false), RunResult::Watchpoint, "RSTR @30");
+    assert_memory(&device, 30, b"abcd");
+
+    let hits: Vec<u16> = device.output().iter().filter_map(|output| match output {
+        Output::WatchpointHit(addr) => Some(*addr),
+        _ => None,
+    }).collect();
+    assert_eq!(hits, vec![20, 32]);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_block_watchpoints() {
+    let ops = vec![
+        MEMSET_AREG_VAL_REG, REG_A0, 1, REG_D0,
+        MEMCPY_AREG_AREG_REG, REG_A1, REG_A0, REG_D0,
+    ];
+    let mut device = setup(ops);
+    device.watchpoints.push((12, 14));
+    device.data_reg[0] = 4;
+    device.addr_reg = [0, 10];
+
+    assert_eq!(device.step(false), RunResult::Pause, "MEMSET A0 1 D0");
+    assert_eq!(device.step(false), RunResult::Watchpoint, "MEMCPY A1 A0 D0");
+    assert_memory(&device, 10, &[1, 1, 1, 1]);
+
+    let hits: Vec<u16> = device.output().iter().filter_map(|output| match output {
+        Output::WatchpointHit(addr) => Some(*addr),
+        _ => None,
+    }).collect();
+    assert_eq!(hits, vec![12]);
+}
+
+#[test]
+fn test_clear_watchpoints() {
+    let mut device = setup(vec![]);
+    device.watchpoints = vec![(10, 20), (30, 30), (40, 50)];
+
+    device.clear_watchpoints(15, 15);
+    assert_eq!(device.watchpoints, vec![(30, 30), (40, 50)]);
+    device.clear_watchpoints(31, 39);
+    assert_eq!(device.watchpoints, vec![(30, 30), (40, 50)]);
+    device.clear_watchpoints(25, 45);
+    assert!(device.watchpoints.is_empty());
+}
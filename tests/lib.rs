@@ -16,8 +16,8 @@ fn setup(ops: Vec<u8>) -> Device {
     device
 }
 
-fn assert_no_output(device: Device) {
-    for output in device.output {
+fn assert_no_output(mut device: Device) {
+    for output in device.take_output() {
         match output {
             Output::OutputStd(msg) => panic!("Expected no output but got: {}", msg),
             Output::OutputErr(msg) => panic!("Expected no error output but got: {}", msg),
@@ -26,9 +26,9 @@ fn assert_no_output(device: Device) {
     }
 }
 
-fn assert_specific_output(device: Device, msg: &str) {
+fn assert_specific_output(mut device: Device, msg: &str) {
     let mut output_text = String::new();
-    for output in device.output {
+    for output in device.take_output() {
         match output {
             Output::OutputStd(msg) => output_text.push_str(&msg),
             Output::OutputErr(msg) => panic!("Expected no error output but got: {}", msg),
@@ -41,7 +41,7 @@ fn assert_specific_output(device: Device, msg: &str) {
 fn assert_step_device(name: &str, device: &mut Device, dump: Dump) {
     let result = device.step(true);
     if result == RunResult::ProgError {
-        for output in &device.output {
+        for output in device.output() {
             if let OutputErr(text) = output {
                 eprintln!("{}", text);
             }
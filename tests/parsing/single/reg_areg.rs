@@ -7,7 +7,7 @@ use crate::parsing::{
 use tape_device::constants::code::{
     ADD_REG_AREG, AND_REG_AREG, OR_REG_AREG, SUB_REG_AREG, XOR_REG_AREG,
 };
-use tape_device::constants::hardware::{REG_A0, REG_A1, REG_D0, REG_D1};
+use tape_device::constants::hardware::Register;
 use tape_device::language::parser::params::Param::{AddrReg, DataReg};
 
 #[test]
@@ -30,8 +30,8 @@ fn test_reg_areg() {
 
 #[rustfmt::skip]
 fn test_valid_reg(op: &str, opcode: u8) {
-    test_single_instruction(&format!("{} D0 A0", op), opcode, vec![DataReg(REG_D0), AddrReg(REG_A0)]);
-    test_single_instruction(&format!("{} D1 A1", op), opcode, vec![DataReg(REG_D1), AddrReg(REG_A1)]);
+    test_single_instruction(&format!("{} D0 A0", op), opcode, vec![DataReg(Register::D0), AddrReg(Register::A0)]);
+    test_single_instruction(&format!("{} D1 A1", op), opcode, vec![DataReg(Register::D1), AddrReg(Register::A1)]);
 }
 
 #[rustfmt::skip]
@@ -7,7 +7,7 @@ use crate::parsing::{
 use tape_device::constants::code::{
     ADD_REG_REG, AND_REG_REG, OR_REG_REG, SUB_REG_REG, XOR_REG_REG,
 };
-use tape_device::constants::hardware::{REG_ACC, REG_D0, REG_D1, REG_D2, REG_D3};
+use tape_device::constants::hardware::Register;
 use tape_device::language::parser::params::Param::DataReg;
 
 #[test]
@@ -30,10 +30,10 @@ fn test_reg_reg() {
 
 #[rustfmt::skip]
 fn test_valid_reg(op: &str, opcode: u8) {
-    test_single_instruction(&format!("{} D0 D1", op), opcode, vec![DataReg(REG_D0), DataReg(REG_D1)]);
-    test_single_instruction(&format!("{} D1 D3", op), opcode, vec![DataReg(REG_D1), DataReg(REG_D3)]);
-    test_single_instruction(&format!("{} D2 ACC", op), opcode, vec![DataReg(REG_D2), DataReg(REG_ACC)]);
-    test_single_instruction(&format!("{} D3 D1", op), opcode, vec![DataReg(REG_D3), DataReg(REG_D1)]);
+    test_single_instruction(&format!("{} D0 D1", op), opcode, vec![DataReg(Register::D0), DataReg(Register::D1)]);
+    test_single_instruction(&format!("{} D1 D3", op), opcode, vec![DataReg(Register::D1), DataReg(Register::D3)]);
+    test_single_instruction(&format!("{} D2 ACC", op), opcode, vec![DataReg(Register::D2), DataReg(Register::Acc)]);
+    test_single_instruction(&format!("{} D3 D1", op), opcode, vec![DataReg(Register::D3), DataReg(Register::D1)]);
 }
 
 #[rustfmt::skip]
@@ -7,7 +7,7 @@ use crate::parsing::{
 use tape_device::constants::code::{
     ADD_REG_VAL, AND_REG_VAL, OR_REG_VAL, SUB_REG_VAL, XOR_REG_VAL,
 };
-use tape_device::constants::hardware::{REG_ACC, REG_D0, REG_D1, REG_D2, REG_D3};
+use tape_device::constants::hardware::Register;
 use tape_device::language::parser::params::Param::{DataReg, Number};
 
 #[test]
@@ -33,38 +33,39 @@ fn test_reg_val() {
 
 #[rustfmt::skip]
 fn test_valid_int(op: &str, opcode: u8) {
-    test_single_instruction(&format!("{} D0 0", op), opcode, vec![DataReg(REG_D0), Number(0)]);
-    test_single_instruction(&format!("{} D1 1", op), opcode, vec![DataReg(REG_D1), Number(1)]);
-    test_single_instruction(&format!("{} D2 100", op), opcode, vec![DataReg(REG_D2), Number(100)]);
-    test_single_instruction(&format!("{} D3 255", op), opcode, vec![DataReg(REG_D3), Number(255)]);
+    test_single_instruction(&format!("{} D0 0", op), opcode, vec![DataReg(Register::D0), Number(0)]);
+    test_single_instruction(&format!("{} D1 1", op), opcode, vec![DataReg(Register::D1), Number(1)]);
+    test_single_instruction(&format!("{} D2 100", op), opcode, vec![DataReg(Register::D2), Number(100)]);
+    test_single_instruction(&format!("{} D3 255", op), opcode, vec![DataReg(Register::D3), Number(255)]);
 }
 
 #[rustfmt::skip]
 fn test_valid_hex(op: &str, opcode: u8) {
-    test_single_instruction(&format!("{} ACC x0", op), opcode, vec![DataReg(REG_ACC), Number(0)]);
-    test_single_instruction(&format!("{} D0 x1", op), opcode, vec![DataReg(REG_D0), Number(1)]);
-    test_single_instruction(&format!("{} D1 xF", op), opcode, vec![DataReg(REG_D1), Number(15)]);
-    test_single_instruction(&format!("{} D2 x4F", op), opcode, vec![DataReg(REG_D2), Number(79)]);
+    test_single_instruction(&format!("{} ACC x0", op), opcode, vec![DataReg(Register::Acc), Number(0)]);
+    test_single_instruction(&format!("{} D0 x1", op), opcode, vec![DataReg(Register::D0), Number(1)]);
+    test_single_instruction(&format!("{} D1 xF", op), opcode, vec![DataReg(Register::D1), Number(15)]);
+    test_single_instruction(&format!("{} D2 x4F", op), opcode, vec![DataReg(Register::D2), Number(79)]);
 }
 
 #[rustfmt::skip]
 fn test_valid_chr(op: &str, opcode: u8) {
-    test_single_instruction(&format!("{} D3 '0'", op), opcode, vec![DataReg(REG_D3), Number(48)]);
-    test_single_instruction(&format!("{} ACC 'a'", op), opcode, vec![DataReg(REG_ACC), Number(97)]);
-    test_single_instruction(&format!("{} D0 '.'", op), opcode, vec![DataReg(REG_D0), Number(46)]);
-    test_single_instruction(&format!("{} D1 'A'", op), opcode, vec![DataReg(REG_D1), Number(65)]);
+    test_single_instruction(&format!("{} D3 '0'", op), opcode, vec![DataReg(Register::D3), Number(48)]);
+    test_single_instruction(&format!("{} ACC 'a'", op), opcode, vec![DataReg(Register::Acc), Number(97)]);
+    test_single_instruction(&format!("{} D0 '.'", op), opcode, vec![DataReg(Register::D0), Number(46)]);
+    test_single_instruction(&format!("{} D1 'A'", op), opcode, vec![DataReg(Register::D1), Number(65)]);
 }
 
 #[rustfmt::skip]
 fn test_valid_bin(op: &str, opcode: u8) {
-    test_single_instruction(&format!("{} D2 b00000000", op), opcode, vec![DataReg(REG_D2), Number(0)]);
-    test_single_instruction(&format!("{} D3 b10001000", op), opcode, vec![DataReg(REG_D3), Number(136)]);
-    test_single_instruction(&format!("{} ACC b11111111", op), opcode, vec![DataReg(REG_ACC), Number(255)]);
+    test_single_instruction(&format!("{} D2 b00000000", op), opcode, vec![DataReg(Register::D2), Number(0)]);
+    test_single_instruction(&format!("{} D3 b10001000", op), opcode, vec![DataReg(Register::D3), Number(136)]);
+    test_single_instruction(&format!("{} D1 b1010", op), opcode, vec![DataReg(Register::D1), Number(10)]);
+    test_single_instruction(&format!("{} ACC b11111111", op), opcode, vec![DataReg(Register::Acc), Number(255)]);
 }
 
 #[rustfmt::skip]
 fn test_invalid_int(op: &str, opcode: u8) {
-    test_single_invalid_instruction(&format!("{} D0 -1", op), opcode, &format!("{} supports", op));
+    test_single_invalid_instruction(&format!("{} D0 -129", op), opcode, &format!("{} supports", op));
     test_single_invalid_instruction(&format!("{} D1 256", op), opcode, &format!("{} supports", op));
     test_single_invalid_instruction(&format!("{} D2 600", op), opcode, &format!("{} supports", op));
 }
@@ -79,7 +80,7 @@ fn test_invalid_hex(op: &str, opcode: u8) {
 
 #[rustfmt::skip]
 fn test_invalid_bin(op: &str, opcode: u8) {
-    test_single_invalid_instruction(&format!("{} D0 b0", op), opcode, &format!("{} supports", op));
+    test_single_invalid_instruction(&format!("{} D0 b", op), opcode, &format!("{} supports", op));
     test_single_invalid_instruction(&format!("{} D1 10101010", op), opcode, &format!("{} supports", op));
     test_single_invalid_instruction(&format!("{} D2 b101101101", op), opcode, &format!("{} supports", op));
     test_single_invalid_instruction(&format!("{} D3 b10000002", op), opcode, &format!("{} supports", op));
@@ -5,7 +5,7 @@ use crate::parsing::{
     new_program_model_with_data, new_program_model_with_label, new_program_model_with_string,
 };
 use tape_device::constants::code::ARG_REG_VAL;
-use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1, REG_D2, REG_D3};
+use tape_device::constants::hardware::Register;
 use tape_device::language::parser::params::Param::{AddrReg, DataReg, Number};
 
 #[test]
@@ -31,38 +31,38 @@ fn test_regs_val() {
 
 #[rustfmt::skip]
 fn test_valid_int(op: &str, opcode: u8) {
-    test_single_instruction(&format!("{} D0 0", op), opcode, vec![DataReg(REG_D0), Number(0)]);
-    test_single_instruction(&format!("{} D1 1", op), opcode, vec![DataReg(REG_D1), Number(1)]);
-    test_single_instruction(&format!("{} D2 100", op), opcode, vec![DataReg(REG_D2), Number(100)]);
-    test_single_instruction(&format!("{} D3 255", op), opcode, vec![DataReg(REG_D3), Number(255)]);
+    test_single_instruction(&format!("{} D0 0", op), opcode, vec![DataReg(Register::D0), Number(0)]);
+    test_single_instruction(&format!("{} D1 1", op), opcode, vec![DataReg(Register::D1), Number(1)]);
+    test_single_instruction(&format!("{} D2 100", op), opcode, vec![DataReg(Register::D2), Number(100)]);
+    test_single_instruction(&format!("{} D3 255", op), opcode, vec![DataReg(Register::D3), Number(255)]);
 }
 
 #[rustfmt::skip]
 fn test_valid_hex(op: &str, opcode: u8) {
-    test_single_instruction(&format!("{} ACC x0", op), opcode, vec![DataReg(REG_ACC), Number(0)]);
-    test_single_instruction(&format!("{} A0 x1", op), opcode, vec![AddrReg(REG_A0), Number(1)]);
-    test_single_instruction(&format!("{} A1 xF", op), opcode, vec![AddrReg(REG_A1), Number(15)]);
-    test_single_instruction(&format!("{} D0 x4F", op), opcode, vec![DataReg(REG_D0), Number(79)]);
+    test_single_instruction(&format!("{} ACC x0", op), opcode, vec![DataReg(Register::Acc), Number(0)]);
+    test_single_instruction(&format!("{} A0 x1", op), opcode, vec![AddrReg(Register::A0), Number(1)]);
+    test_single_instruction(&format!("{} A1 xF", op), opcode, vec![AddrReg(Register::A1), Number(15)]);
+    test_single_instruction(&format!("{} D0 x4F", op), opcode, vec![DataReg(Register::D0), Number(79)]);
 }
 
 #[rustfmt::skip]
 fn test_valid_chr(op: &str, opcode: u8) {
-    test_single_instruction(&format!("{} D1 '0'", op), opcode, vec![DataReg(REG_D1), Number(48)]);
-    test_single_instruction(&format!("{} D2 'a'", op), opcode, vec![DataReg(REG_D2), Number(97)]);
-    test_single_instruction(&format!("{} D3 '.'", op), opcode, vec![DataReg(REG_D3), Number(46)]);
-    test_single_instruction(&format!("{} ACC 'A'", op), opcode, vec![DataReg(REG_ACC), Number(65)]);
+    test_single_instruction(&format!("{} D1 '0'", op), opcode, vec![DataReg(Register::D1), Number(48)]);
+    test_single_instruction(&format!("{} D2 'a'", op), opcode, vec![DataReg(Register::D2), Number(97)]);
+    test_single_instruction(&format!("{} D3 '.'", op), opcode, vec![DataReg(Register::D3), Number(46)]);
+    test_single_instruction(&format!("{} ACC 'A'", op), opcode, vec![DataReg(Register::Acc), Number(65)]);
 }
 
 #[rustfmt::skip]
 fn test_valid_bin(op: &str, opcode: u8) {
-    test_single_instruction(&format!("{} A0 b00000000", op), opcode, vec![AddrReg(REG_A0), Number(0)]);
-    test_single_instruction(&format!("{} A1 b10001000", op), opcode, vec![AddrReg(REG_A1), Number(136)]);
-    test_single_instruction(&format!("{} D0 b11111111", op), opcode, vec![DataReg(REG_D0), Number(255)]);
+    test_single_instruction(&format!("{} A0 b00000000", op), opcode, vec![AddrReg(Register::A0), Number(0)]);
+    test_single_instruction(&format!("{} A1 b10001000", op), opcode, vec![AddrReg(Register::A1), Number(136)]);
+    test_single_instruction(&format!("{} D0 b11111111", op), opcode, vec![DataReg(Register::D0), Number(255)]);
 }
 
 #[rustfmt::skip]
 fn test_invalid_int(op: &str, opcode: u8) {
-    test_single_invalid_instruction(&format!("{} D0 -1", op), opcode, &format!("{} supports", op));
+    test_single_invalid_instruction(&format!("{} D0 -129", op), opcode, &format!("{} supports", op));
     test_single_invalid_instruction(&format!("{} D1 256", op), opcode, &format!("{} supports", op));
     test_single_invalid_instruction(&format!("{} D2 600", op), opcode, &format!("{} supports", op));
 }
@@ -77,7 +77,7 @@ fn test_invalid_hex(op: &str, opcode: u8) {
 
 #[rustfmt::skip]
 fn test_invalid_bin(op: &str, opcode: u8) {
-    test_single_invalid_instruction(&format!("{} A0 b0", op), opcode, &format!("{} supports", op));
+    test_single_invalid_instruction(&format!("{} A0 b", op), opcode, &format!("{} supports", op));
     test_single_invalid_instruction(&format!("{} A1 10101010", op), opcode, &format!("{} supports", op));
     test_single_invalid_instruction(&format!("{} D2 b101101101", op), opcode, &format!("{} supports", op));
     test_single_invalid_instruction(&format!("{} D3 b10000002", op), opcode, &format!("{} supports", op));
@@ -28,7 +28,7 @@ fn test_valid_int(op: &str, opcode: u8) {
 
 #[rustfmt::skip]
 fn test_invalid_int(op: &str, opcode: u8) {
-    test_single_invalid_instruction(&format!("{} -1", op), opcode, &format!("{} supports", op));
+    test_single_invalid_instruction(&format!("{} -129", op), opcode, &format!("{} supports", op));
     test_single_invalid_instruction(&format!("{} 256", op), opcode, &format!("{} supports", op));
 }
 